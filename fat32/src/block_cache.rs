@@ -1,8 +1,8 @@
-use super::{BlockDevice, BLOCK_SZ};
+use super::{BlockDevice, BlockError, FatError, FatResult, BLOCK_SZ};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use lazy_static::*;
-use spin::RwLock;
+use crate::lock::RwLock;
 #[allow(unused)]
 
 
@@ -11,24 +11,26 @@ pub struct BlockCache {
     block_id: usize,  // 块号
     block_device: Arc<dyn BlockDevice>,  // 块设备
     modified: bool,   // 是否被修改
+    poisoned: bool,   // 多次写回失败，淘汰时跳过，只在 sync_all 时重试
     #[allow(unused)]
     time_stamp: usize,   // 时间戳
 }
 
 // BlockCache的实现
 impl BlockCache {
-    /// 新建一个BlockCache
-    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+    /// 新建一个BlockCache，从设备读入块的内容
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Result<Self, BlockError> {
         let mut cache = [0u8; BLOCK_SZ];
-        block_device.read_block(block_id, &mut cache);
+        block_device.read_block(block_id, &mut cache)?;
         let time_stamp = 0;
-        Self {
+        Ok(Self {
             cache,
             block_id,
             block_device,
             modified: false,
+            poisoned: false,
             time_stamp,
-        }
+        })
     }
 
     // 获取偏移量的地址
@@ -69,85 +71,130 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
-    // 写入
-    pub fn sync(&mut self) {
-        if self.modified {
-            self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache);
+    // 写入，失败时重试 SYNC_RETRIES 次，仍然失败的块被标记为 poisoned 并保持修改状态。
+    // 设备只读时不写入，块同样保持修改状态，设备恢复可写后再写回
+    pub fn sync(&mut self) -> Result<(), BlockError> {
+        if !self.modified {
+            return Ok(());
+        }
+        if self.block_device.is_read_only() {
+            self.poisoned = true;
+            return Err(BlockError::ReadOnly);
+        }
+        let mut result = Ok(());
+        for _ in 0..SYNC_RETRIES {
+            result = self.block_device.write_block(self.block_id, &self.cache);
+            if result.is_ok() {
+                break;
+            }
         }
+        self.modified = result.is_err();
+        self.poisoned = result.is_err();
+        result
     }
 }
 
 impl Drop for BlockCache {
     fn drop(&mut self) {
-        self.sync()
+        // manager 只丢弃写回成功的块，这里失败的只有已经不在 manager 中的块
+        let _ = self.sync();
     }
 }
+
+// 写回失败时的重试次数
+const SYNC_RETRIES: usize = 3;
 // cache块数
 const BLOCK_CACHE_SIZE: usize = 10;
+
+// cache块的键：(设备, 块号)，多个设备上的文件系统共用同一个cache
+type CacheKey = (usize, usize);
+
+// 设备的标识，即它的地址
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const u8 as usize
+}
+
 // BlockCacheManager的实现
 pub struct BlockCacheManager {
-    start_sec: usize,  
-    queue: VecDeque<(usize, Arc<RwLock<BlockCache>>)>,  // cache块队列
+    queue: VecDeque<(CacheKey, Arc<RwLock<BlockCache>>)>,  // cache块队列
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
         Self {
-            start_sec: 0,
             queue: VecDeque::new(),
         }
     }
 
-    pub fn set_start_sec(&mut self, new_start_sec: usize) {
-        self.start_sec = new_start_sec;
-    }
-
-    pub fn get_start_sec(&self) -> usize {
-        self.start_sec
-    }
-
     // 读取cache块
-    pub fn read_block_cache(&self, block_id: usize) -> Option<Arc<RwLock<BlockCache>>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
+    pub fn read_block_cache(
+        &self,
+        block_id: usize,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Option<Arc<RwLock<BlockCache>>> {
+        let key = (device_id(block_device), block_id);
+        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == key) {
             Some(Arc::clone(&pair.1))
         } else {
             None
         }
     }
 
-    // 获取cache块
-    pub fn get_block_cache(
-        &mut self,
-        block_id: usize,
-        block_device: Arc<dyn BlockDevice>,
-    ) -> Arc<RwLock<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
+    // 把从设备读入的块加入cache，淘汰旧块时写回失败返回错误。
+    // 其他任务可能已经先读入了同一个块，这时丢弃新读入的内容，使用cache中的块
+    fn insert(&mut self, block_cache: BlockCache) -> FatResult<Arc<RwLock<BlockCache>>> {
+        let key = (device_id(&block_cache.block_device), block_cache.block_id);
+        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == key) {
+            return Ok(Arc::clone(&pair.1));
+        }
+        if self.queue.len() == BLOCK_CACHE_SIZE {
+            self.evict()?;
+        }
+        let block_cache = Arc::new(RwLock::new(block_cache));
+        self.queue.push_back((key, Arc::clone(&block_cache)));
+        Ok(block_cache)
+    }
+
+    // 淘汰一个没有被引用的块。写回失败的块留在cache中，以免丢失修改；
+    // 没有能淘汰的块时返回最后一次写回的错误
+    fn evict(&mut self) -> FatResult<()> {
+        let mut error = None;
+        for idx in 0..self.queue.len() {
+            let cache = &self.queue[idx].1;
+            if Arc::strong_count(cache) != 1 {
+                continue;
+            }
+            let mut cache = cache.write();
+            if cache.poisoned {
+                error = Some(BlockError::Device);
+                continue;
+            }
+            match cache.sync() {
+                Ok(()) => {
+                    drop(cache);
+                    self.queue.remove(idx);
+                    return Ok(());
                 }
+                Err(err) => error = Some(err),
             }
-            let block_cache = Arc::new(RwLock::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+        }
+        match error {
+            Some(err) => Err(FatError::IoError(err)),
+            None => panic!("Run out of BlockCache!"),
         }
     }
 
-    pub fn drop_all(&mut self) {
-        self.queue.clear();
+    // 写回并丢弃所有块，写回失败的块留在cache中
+    pub fn drop_all(&mut self) -> FatResult<()> {
+        let mut result = Ok(());
+        self.queue.retain(|(_, cache)| match cache.write().sync() {
+            Ok(()) => false,
+            Err(err) => {
+                result = Err(FatError::IoError(err));
+                true
+            }
+        });
+        result
     }
 }
 
@@ -167,60 +214,65 @@ pub enum CacheMode {
     WRITE,
 }
 
-// 获取数据块cache
-pub fn get_block_cache(
+// 从 manager 中获取cache块，块不在cache中时先从设备读入，再加入 manager。
+// 读设备时任务可能让出处理器等待磁盘中断，所以读入期间不持有 manager 的锁，
+// 否则所有访问cache的任务都要等待它，不能让出处理器的任务（例如在缺页处理中）会一直自旋，持有者再也得不到运行
+fn get_cache(
+    manager: &RwLock<BlockCacheManager>,
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
-    rw_mode: CacheMode,
-) -> Arc<RwLock<BlockCache>> {
-    let phy_blk_id = DATA_BLOCK_CACHE_MANAGER.read().get_start_sec() + block_id;
-    if rw_mode == CacheMode::READ {
-        // make sure the blk is in cache
-        DATA_BLOCK_CACHE_MANAGER
-            .write()
-            .get_block_cache(phy_blk_id, block_device);
-        DATA_BLOCK_CACHE_MANAGER
-            .read()
-            .read_block_cache(phy_blk_id)
-            .unwrap()
-    } else {
-        DATA_BLOCK_CACHE_MANAGER
-            .write()
-            .get_block_cache(phy_blk_id, block_device)
+) -> FatResult<Arc<RwLock<BlockCache>>> {
+    if let Some(block_cache) = manager.read().read_block_cache(block_id, &block_device) {
+        return Ok(block_cache);
     }
+    let block_cache = BlockCache::new(block_id, block_device)?;
+    manager.write().insert(block_cache)
 }
 
-// 获取信息块cache
+// 获取数据块cache，两种模式的获取方式相同
+pub fn get_block_cache(
+    block_id: usize,
+    block_device: Arc<dyn BlockDevice>,
+    _rw_mode: CacheMode,
+) -> FatResult<Arc<RwLock<BlockCache>>> {
+    get_cache(&DATA_BLOCK_CACHE_MANAGER, block_id, block_device)
+}
+
+// 获取信息块cache，两种模式的获取方式相同
 pub fn get_info_cache(
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
-    rw_mode: CacheMode,
-) -> Arc<RwLock<BlockCache>> {
-    let phy_blk_id = INFO_CACHE_MANAGER.read().get_start_sec() + block_id;
-    if rw_mode == CacheMode::READ {
-        // make sure the blk is in cache
-        INFO_CACHE_MANAGER
-            .write()
-            .get_block_cache(phy_blk_id, block_device);
-        INFO_CACHE_MANAGER
-            .read()
-            .read_block_cache(phy_blk_id)
-            .unwrap()
-    } else {
-        INFO_CACHE_MANAGER
-            .write()
-            .get_block_cache(phy_blk_id, block_device)
-    }
+    _rw_mode: CacheMode,
+) -> FatResult<Arc<RwLock<BlockCache>>> {
+    get_cache(&INFO_CACHE_MANAGER, block_id, block_device)
 }
 
-// 设置起始扇区
-pub fn set_start_sec(start_sec: usize) {
-    INFO_CACHE_MANAGER.write().set_start_sec(start_sec);
-    DATA_BLOCK_CACHE_MANAGER.write().set_start_sec(start_sec);
+// 数据块是否在cache中
+pub fn is_block_cached(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> bool {
+    DATA_BLOCK_CACHE_MANAGER.read().read_block_cache(block_id, block_device).is_some()
+}
+
+// 绕过cache，从设备一次读入连续的多个数据块，调用者保证这些块都不在cache中
+pub fn read_blocks_uncached(block_id: usize, buf: &mut [u8], block_device: &Arc<dyn BlockDevice>) -> FatResult<()> {
+    Ok(block_device.read_blocks(block_id, buf)?)
+}
+
+// 将所有被修改的cache块（包括之前写回失败的块）写回设备，cache块仍保留在内存中。
+// 有块写回失败时返回最后一个错误
+pub fn sync_all() -> FatResult<()> {
+    let mut result = Ok(());
+    for manager in [&*INFO_CACHE_MANAGER, &*DATA_BLOCK_CACHE_MANAGER] {
+        for (_, cache) in manager.read().queue.iter() {
+            if let Err(err) = cache.write().sync() {
+                result = Err(FatError::IoError(err));
+            }
+        }
+    }
+    result
 }
 
 // 写入设备
-pub fn write_to_dev() {
-    INFO_CACHE_MANAGER.write().drop_all();
-    DATA_BLOCK_CACHE_MANAGER.write().drop_all();
+pub fn write_to_dev() -> FatResult<()> {
+    let info = INFO_CACHE_MANAGER.write().drop_all();
+    DATA_BLOCK_CACHE_MANAGER.write().drop_all().and(info)
 }
@@ -1,9 +1,60 @@
+use super::BLOCK_SZ;
 use core::any::Any;
+///Errors reported by a block device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockError {
+    ///The device did not complete the request in time
+    Timeout,
+    ///The device reported a failed request
+    Device,
+    ///The request reaches past the end of the device
+    OutOfRange,
+    ///The device refuses writes
+    ReadOnly,
+}
 /// Trait for block devices
 /// which reads and writes data in the unit of blocks
 pub trait BlockDevice: Send + Sync + Any {
     ///Read data form block to buffer
-    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError>;
     ///Write data from buffer to block
-    fn write_block(&self, block_id: usize, buf: &[u8]);
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError>;
+    ///Read contiguous blocks starting at `start_block`; `buf` holds a whole
+    ///number of blocks. Devices that can batch requests should override it
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        for (i, block) in buf.chunks_mut(BLOCK_SZ).enumerate() {
+            self.read_block(start_block + i, block)?;
+        }
+        Ok(())
+    }
+    ///Write contiguous blocks starting at `start_block`; `buf` holds a whole
+    ///number of blocks. Devices that can batch requests should override it
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        for (i, block) in buf.chunks(BLOCK_SZ).enumerate() {
+            self.write_block(start_block + i, block)?;
+        }
+        Ok(())
+    }
+    ///Make every completed write durable, e.g. by flushing a volatile write
+    ///cache in the device; devices without such a cache do not need it
+    fn flush(&self) -> Result<(), BlockError> {
+        Ok(())
+    }
+    ///Whether the device refuses writes
+    fn is_read_only(&self) -> bool {
+        false
+    }
+    ///Start or stop refusing writes; returns whether the device is now in the
+    ///requested mode, which fails e.g. for writes to a write-protected disk
+    fn set_read_only(&self, read_only: bool) -> bool {
+        read_only == self.is_read_only()
+    }
+    ///Whether requests submitted to the device are still outstanding; devices
+    ///that complete requests synchronously are never busy
+    fn is_busy(&self) -> bool {
+        false
+    }
+    ///Handle a completion interrupt of the device; devices that complete
+    ///requests synchronously do not need it
+    fn handle_irq(&self) {}
 }
@@ -0,0 +1,23 @@
+//! 文件系统操作的错误
+
+use super::BlockError;
+
+/// 文件系统操作失败的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatError {
+    /// 读写块设备失败，内核把它转换为 EIO
+    IoError(BlockError),
+    /// 没有足够的空闲簇，内核把它转换为 ENOSPC
+    NoSpace,
+    /// 设备上不是 FAT32 文件系统，内核把它转换为 EINVAL
+    InvalidFs,
+}
+
+impl From<BlockError> for FatError {
+    fn from(err: BlockError) -> Self {
+        FatError::IoError(err)
+    }
+}
+
+/// 文件系统操作的结果
+pub type FatResult<T> = Result<T, FatError>;
@@ -1,12 +1,12 @@
 use super::{
-    get_block_cache, get_info_cache, set_start_sec, write_to_dev, BlockDevice, CacheMode, FSInfo,
-    FatBS, FatExtBS, FAT,
+    get_block_cache, get_info_cache, write_to_dev, BlockDevice, CacheMode, FSInfo,
+    FatBS, FatError, FatExtBS, FatResult, BLOCK_SZ, FAT,
 };
 use crate::{layout::*, VFile};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use spin::RwLock;
+use crate::lock::RwLock;
 
 // FAT32文件系统管理器
 pub struct FAT32Manager {
@@ -17,13 +17,12 @@ pub struct FAT32Manager {
     bytes_per_cluster: u32,   // 每簇字节数
     fat: Arc<RwLock<FAT>>,   // FAT表
     root_sec: u32,          // 根目录扇区
-    #[allow(unused)] 
     total_sectors: u32,    // 总扇区数
     vroot_dirent: Arc<RwLock<ShortDirEntry>>,  // 根目录短目录项
 }
 
-pub fn create_fat(block_id: usize, device: Arc<dyn BlockDevice>) {
-    let cache = get_info_cache(block_id, device, CacheMode::WRITE);
+pub fn create_fat(block_id: usize, device: Arc<dyn BlockDevice>) -> FatResult<()> {
+    let cache = get_info_cache(block_id, device, CacheMode::WRITE)?;
     let mut guard = cache.write();
     guard.modify(0, |fat: &mut u64| {
         *fat = 0xFFFFFFFFFFFFFFFF;
@@ -32,11 +31,12 @@ pub fn create_fat(block_id: usize, device: Arc<dyn BlockDevice>) {
         *fat = 0x0FFFFFFF;
     });
     drop(guard);
+    Ok(())
 }
 
 impl FAT32Manager {
 
-    pub fn create(block_device: Arc<dyn BlockDevice>) -> Arc<RwLock<Self>> {
+    pub fn create(block_device: Arc<dyn BlockDevice>) -> FatResult<Arc<RwLock<Self>>> {
         Self::open(Arc::clone(&block_device))
     }
 
@@ -56,27 +56,95 @@ impl FAT32Manager {
         self.root_sec
     }
 
+    /// 数据区的簇数
+    pub fn total_clusters(&self) -> u32 {
+        (self.total_sectors - self.root_sec) / self.sectors_per_cluster
+    }
+
     pub fn first_sector_of_cluster(&self, cluster: u32) -> usize {
         (cluster as usize - 2) * self.sectors_per_cluster as usize + self.root_sec as usize
     }
 
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<RwLock<Self>> {
-        let start_sector = 0;
-        set_start_sec(start_sector as usize);
+    /// 把 `total_sectors` 个扇区的块设备格式化为每簇一个扇区的 FAT32，根目录为空。
+    /// 直接写设备、不经过块缓存，设备上不能有已经打开的文件系统
+    pub fn format(block_device: Arc<dyn BlockDevice>, total_sectors: u32) -> FatResult<()> {
+        const RESERVED_SECTORS: u32 = 32;
+        const FSINFO_SECTOR: u32 = 1;
+        const BACKUP_BOOT_SECTOR: u32 = 6;
+        // 每个 FAT 要能容纳数据区所有簇以及 0、1 两个保留项
+        let entries_per_sector = (BLOCK_SZ / 4) as u32;
+        let mut fat_size = 1;
+        while fat_size * entries_per_sector < total_sectors - RESERVED_SECTORS - 2 * fat_size + 2 {
+            fat_size += 1;
+        }
+        let data_sector = RESERVED_SECTORS + 2 * fat_size;
+        let total_clusters = total_sectors - data_sector;
+
+        let mut boot = [0u8; BLOCK_SZ];
+        boot[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[3..11].copy_from_slice(b"BITOS   ");
+        boot[11..13].copy_from_slice(&(BLOCK_SZ as u16).to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        boot[16] = 2;
+        boot[21] = 0xF8;
+        boot[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+        boot[36..40].copy_from_slice(&fat_size.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48..50].copy_from_slice(&(FSINFO_SECTOR as u16).to_le_bytes());
+        boot[50..52].copy_from_slice(&(BACKUP_BOOT_SECTOR as u16).to_le_bytes());
+        boot[64] = 0x80;
+        boot[66] = 0x29;
+        boot[71..82].copy_from_slice(b"NO NAME    ");
+        boot[82..90].copy_from_slice(b"FAT32   ");
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let mut fsinfo = [0u8; BLOCK_SZ];
+        fsinfo[0..4].copy_from_slice(&LEAD_SIGNATURE.to_le_bytes());
+        fsinfo[484..488].copy_from_slice(&SECOND_SIGNATURE.to_le_bytes());
+        // 根目录占用了簇 2，分配从它之后开始查找
+        fsinfo[488..492].copy_from_slice(&(total_clusters - 1).to_le_bytes());
+        fsinfo[492..496].copy_from_slice(&2u32.to_le_bytes());
+        fsinfo[508..512].copy_from_slice(&[0x00, 0x00, 0x55, 0xAA]);
+
+        let zero = [0u8; BLOCK_SZ];
+        for sector in 0..data_sector + 1 {
+            block_device.write_block(sector as usize, &zero)?;
+        }
+        for base in [0, BACKUP_BOOT_SECTOR] {
+            block_device.write_block(base as usize, &boot)?;
+            block_device.write_block((base + FSINFO_SECTOR) as usize, &fsinfo)?;
+        }
+        // 两个保留项和根目录的簇链结尾
+        let mut first_fat_sector = [0u8; BLOCK_SZ];
+        first_fat_sector[0..4].copy_from_slice(&0x0FFFFFF8u32.to_le_bytes());
+        first_fat_sector[4..8].copy_from_slice(&0x0FFFFFFFu32.to_le_bytes());
+        first_fat_sector[8..12].copy_from_slice(&0x0FFFFFFFu32.to_le_bytes());
+        for fat in 0..2 {
+            block_device.write_block((RESERVED_SECTORS + fat * fat_size) as usize, &first_fat_sector)?;
+        }
+        Ok(())
+    }
 
-        let boot_sec: FatBS = get_info_cache(0, Arc::clone(&block_device), CacheMode::READ)
+    /// 打开块设备上的文件系统，读取引导扇区或 FSInfo 失败时返回错误，设备上不是 FAT32 文件系统时返回 [`FatError::InvalidFs`]
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> FatResult<Arc<RwLock<Self>>> {
+        let boot_sec: FatBS = get_info_cache(0, Arc::clone(&block_device), CacheMode::READ)?
             .read()
             .read(0, |bs: &FatBS| *bs);
-        let ext_boot_sec: FatExtBS = get_info_cache(0, Arc::clone(&block_device), CacheMode::READ)
+        let ext_boot_sec: FatExtBS = get_info_cache(0, Arc::clone(&block_device), CacheMode::READ)?
             .read()
             .read(36, |ebs: &FatExtBS| {
                 *ebs 
             });
         let fsinfo = FSInfo::new(ext_boot_sec.fat_info_sec());
-        assert!(
-            fsinfo.check_signature(Arc::clone(&block_device)),
-            "Error loading fat32! Illegal signature"
-        );
+        // 引导扇区的参数不合理或 FSInfo 的签名不对，说明设备上不是 FAT32 文件系统
+        if boot_sec.bytes_per_sector as usize != BLOCK_SZ
+            || boot_sec.sectors_per_cluster == 0
+            || ext_boot_sec.fat_size() == 0
+            || !fsinfo.check_signature(Arc::clone(&block_device))?
+        {
+            return Err(FatError::InvalidFs);
+        }
 
         let sectors_per_cluster = boot_sec.sectors_per_cluster as u32;
         let bytes_per_sector = boot_sec.bytes_per_sector as u32;
@@ -106,7 +174,27 @@ impl FAT32Manager {
             total_sectors: boot_sec.total_sectors(),
             vroot_dirent: Arc::new(RwLock::new(root_dirent)),
         };
-        Arc::new(RwLock::new(fat32_manager))
+        fat32_manager.check_fsinfo()?;
+        Ok(Arc::new(RwLock::new(fat32_manager)))
+    }
+
+    /// FSInfo 中的空闲簇数和下一个空闲簇可以是表示未知的 0xFFFFFFFF（有些格式化工具就这样写），
+    /// 超出数据区时按未知处理：空闲簇数通过扫描 FAT 重新统计，下一个空闲簇从数据区开头找起
+    fn check_fsinfo(&self) -> FatResult<()> {
+        let total_clusters = self.total_clusters();
+        if self.fsinfo.read_free_clusters(self.block_device.clone())? > total_clusters {
+            let free = self
+                .fat
+                .read()
+                .count_free_clusters(total_clusters, self.block_device.clone())?;
+            self.fsinfo.write_free_clusters(free, self.block_device.clone())?;
+        }
+        let first_free = self.fsinfo.first_free_cluster(self.block_device.clone())?;
+        if first_free < 2 || first_free >= total_clusters + 2 {
+            // 分配时从记录的簇之后开始查找
+            self.fsinfo.write_first_free_cluster(1, self.block_device.clone())?;
+        }
+        Ok(())
     }
 
     // 获取根目录的虚拟文件
@@ -122,6 +210,7 @@ impl FAT32Manager {
             0,
             Arc::clone(fs_manager),
             block_device.clone(),
+            (0, 0),
         )
     }
 
@@ -131,69 +220,72 @@ impl FAT32Manager {
     }
 
     // 为文件分配簇
-    pub fn alloc_cluster(&self, num: u32) -> Option<u32> {
-        let free_clusters = self.free_clusters();
+    // 为文件分配簇，空闲簇不够时返回 None
+    pub fn alloc_cluster(&self, num: u32) -> FatResult<Option<u32>> {
+        let free_clusters = self.free_clusters()?;
         if num > free_clusters {
-            return None;
+            return Ok(None);
         }
 
         let fat_writer = self.fat.write();
-        let prev_cluster = self.fsinfo.first_free_cluster(self.block_device.clone());
+        let prev_cluster = self.fsinfo.first_free_cluster(self.block_device.clone())?;
+        let end_cluster = self.total_clusters() + 2;
 
         let first_cluster: u32 =
-            fat_writer.next_free_cluster(prev_cluster, self.block_device.clone());
+            fat_writer.next_free_cluster(prev_cluster, end_cluster, self.block_device.clone())?;
         let mut current_cluster = first_cluster;
 
         #[allow(unused)]
         for i in 1..num {
-            self.clear_cluster(current_cluster);
+            self.clear_cluster(current_cluster)?;
             let next_cluster =
-                fat_writer.next_free_cluster(current_cluster, self.block_device.clone());
+                fat_writer.next_free_cluster(current_cluster, end_cluster, self.block_device.clone())?;
             assert_ne!(next_cluster, 0);
-            fat_writer.set_next_cluster(current_cluster, next_cluster, self.block_device.clone());
+            fat_writer.set_next_cluster(current_cluster, next_cluster, self.block_device.clone())?;
 
             current_cluster = next_cluster;
         }
-        self.clear_cluster(current_cluster);
+        self.clear_cluster(current_cluster)?;
 
-        fat_writer.set_end(current_cluster, self.block_device.clone());
+        fat_writer.set_end(current_cluster, self.block_device.clone())?;
         self.fsinfo
-            .write_free_clusters(free_clusters - num, self.block_device.clone());
+            .write_free_clusters(free_clusters - num, self.block_device.clone())?;
         self.fsinfo
-            .write_first_free_cluster(current_cluster, self.block_device.clone());
-        self.cache_write_back();
-        Some(first_cluster)
+            .write_first_free_cluster(current_cluster, self.block_device.clone())?;
+        self.cache_write_back()?;
+        Ok(Some(first_cluster))
     }
 
     // 释放簇
-    pub fn dealloc_cluster(&self, clusters: Vec<u32>) {
+    pub fn dealloc_cluster(&self, clusters: Vec<u32>) -> FatResult<()> {
         let fat_writer = self.fat.write();
-        let free_clusters = self.free_clusters();
+        let free_clusters = self.free_clusters()?;
         let num = clusters.len();
         for i in 0..num {
-            fat_writer.set_next_cluster(clusters[i], FREE_CLUSTER, self.block_device.clone())
+            fat_writer.set_next_cluster(clusters[i], FREE_CLUSTER, self.block_device.clone())?;
         }
         if num > 0 {
             self.fsinfo
-                .write_free_clusters(free_clusters + num as u32, self.block_device.clone());
+                .write_free_clusters(free_clusters + num as u32, self.block_device.clone())?;
             if clusters[0] > 2
-                && clusters[0] < self.fsinfo.first_free_cluster(self.block_device.clone())
+                && clusters[0] < self.fsinfo.first_free_cluster(self.block_device.clone())?
             {
                 self.fsinfo
-                    .write_first_free_cluster(clusters[0] - 1, self.block_device.clone());
+                    .write_first_free_cluster(clusters[0] - 1, self.block_device.clone())?;
             }
         }
+        Ok(())
     }
 
     // 清空簇
-    pub fn clear_cluster(&self, cluster_id: u32) {
+    pub fn clear_cluster(&self, cluster_id: u32) -> FatResult<()> {
         let start_sec = self.first_sector_of_cluster(cluster_id);
         for i in 0..self.sectors_per_cluster {
             get_block_cache(
                 start_sec + i as usize,
                 self.block_device.clone(),
                 CacheMode::WRITE,
-            )
+            )?
             .write()
             .modify(0, |blk: &mut [u8; 512]| {
                 for j in 0..512 {
@@ -201,6 +293,7 @@ impl FAT32Manager {
                 }
             });
         }
+        Ok(())
     }
 
     // 获取FAT
@@ -215,18 +308,18 @@ impl FAT32Manager {
         new_size: u32,
         is_dir: bool,
         first_cluster: u32,
-    ) -> u32 {
+    ) -> FatResult<u32> {
         if old_size >= new_size {
-            0
+            Ok(0)
         } else {
             if is_dir {
                 let old_clusters = self
                     .fat
                     .read()
-                    .count_claster_num(first_cluster, self.block_device.clone());
-                self.size_to_clusters(new_size) - old_clusters
+                    .count_claster_num(first_cluster, self.block_device.clone())?;
+                Ok(self.size_to_clusters(new_size) - old_clusters)
             } else {
-                self.size_to_clusters(new_size) - self.size_to_clusters(old_size)
+                Ok(self.size_to_clusters(new_size) - self.size_to_clusters(old_size))
             }
         }
     }
@@ -242,7 +335,7 @@ impl FAT32Manager {
     }
 
     // 读取空闲簇
-    pub fn free_clusters(&self) -> u32 {
+    pub fn free_clusters(&self) -> FatResult<u32> {
         self.fsinfo.read_free_clusters(self.block_device.clone())
     }
 
@@ -332,7 +425,7 @@ impl FAT32Manager {
     }
 
     // 缓存写回
-    pub fn cache_write_back(&self) {
-        write_to_dev();
+    pub fn cache_write_back(&self) -> FatResult<()> {
+        write_to_dev()
     }
 }
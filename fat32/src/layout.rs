@@ -1,11 +1,12 @@
 use super::{
-    clone_into_array, fat::FAT32Manager, get_block_cache, get_info_cache, BlockDevice,
-    CacheMode, BLOCK_SZ, FAT_SIZE, SECTOR_SIZE,
+    clone_into_array, fat::FAT32Manager, get_block_cache, get_info_cache, is_block_cached, read_blocks_uncached,
+    BlockDevice, CacheMode, FatResult, BLOCK_SZ, FAT_SIZE, SECTOR_SIZE,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use spin::RwLock;
+use crate::lock::RwLock;
+use crate::time;
 
 // 签名常量
 pub const LEAD_SIGNATURE: u32 = 0x41615252;
@@ -38,7 +39,8 @@ pub const SHORT_EXT_LEN: u32 = 3;
 pub const LONG_NAME_LEN: u32 = 13;
 
 pub const ALL_UPPER_CASE: u8 = 0x00;
-pub const ALL_LOWER_CASE: u8 = 0x08;
+// 0x08 表示主名小写，0x10 表示扩展名小写
+pub const ALL_LOWER_CASE: u8 = 0x18;
 
 type DataBlock = [u8; BLOCK_SZ];
 
@@ -63,8 +65,8 @@ pub struct FatBS {
 
 impl FatBS {
     // 初始化引导扇区
-    pub fn init_boot_sector(block_device: Arc<dyn BlockDevice>) {
-        let cache = get_info_cache(0, block_device, CacheMode::WRITE);
+    pub fn init_boot_sector(block_device: Arc<dyn BlockDevice>) -> FatResult<()> {
+        let cache = get_info_cache(0, block_device, CacheMode::WRITE)?;
         let mut guard = cache.write();
         guard.modify(0, |fat_bs: &mut FatBS| {
             *fat_bs = FatBS {
@@ -85,6 +87,7 @@ impl FatBS {
         });
 
         drop(guard);
+        Ok(())
     }
 
     // 总扇区数
@@ -146,54 +149,56 @@ impl FSInfo {
     }
 
 
-    fn check_lead_signature(&self, block_device: Arc<dyn BlockDevice>) -> bool {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)
+    fn check_lead_signature(&self, block_device: Arc<dyn BlockDevice>) -> FatResult<bool> {
+        Ok(get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)?
             .read()
-            .read(0, |&lead_sig: &u32| lead_sig == LEAD_SIGNATURE)
+            .read(0, |&lead_sig: &u32| lead_sig == LEAD_SIGNATURE))
     }
 
-    fn check_another_signature(&self, block_device: Arc<dyn BlockDevice>) -> bool {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)
+    fn check_another_signature(&self, block_device: Arc<dyn BlockDevice>) -> FatResult<bool> {
+        Ok(get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)?
             .read()
-            .read(484, |&sec_sig: &u32| sec_sig == SECOND_SIGNATURE)
+            .read(484, |&sec_sig: &u32| sec_sig == SECOND_SIGNATURE))
     }
 
     /// 对签名进行校验
-    pub fn check_signature(&self, block_device: Arc<dyn BlockDevice>) -> bool {
-        return self.check_lead_signature(block_device.clone())
-            && self.check_another_signature(block_device.clone());
+    pub fn check_signature(&self, block_device: Arc<dyn BlockDevice>) -> FatResult<bool> {
+        Ok(self.check_lead_signature(block_device.clone())?
+            && self.check_another_signature(block_device.clone())?)
     }
 
     /// 读取空闲簇数
-    pub fn read_free_clusters(&self, block_device: Arc<dyn BlockDevice>) -> u32 {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)
+    pub fn read_free_clusters(&self, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
+        Ok(get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)?
             .read()
-            .read(488, |&free_cluster_count: &u32| free_cluster_count)
+            .read(488, |&free_cluster_count: &u32| free_cluster_count))
     }
 
     /// 写空闲块数
-    pub fn write_free_clusters(&self, free_clusters: u32, block_device: Arc<dyn BlockDevice>) {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::WRITE)
+    pub fn write_free_clusters(&self, free_clusters: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<()> {
+        get_info_cache(self.sector_num as usize, block_device, CacheMode::WRITE)?
             .write()
             .modify(488, |free_cluster_count: &mut u32| {
                 *free_cluster_count = free_clusters;
             });
+        Ok(())
     }
 
     /// 读起始空闲块
-    pub fn first_free_cluster(&self, block_device: Arc<dyn BlockDevice>) -> u32 {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)
+    pub fn first_free_cluster(&self, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
+        Ok(get_info_cache(self.sector_num as usize, block_device, CacheMode::READ)?
             .read()
-            .read(492, |&start_cluster: &u32| start_cluster)
+            .read(492, |&start_cluster: &u32| start_cluster))
     }
 
     /// 写起始空闲块
-    pub fn write_first_free_cluster(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) {
-        get_info_cache(self.sector_num as usize, block_device, CacheMode::WRITE)
+    pub fn write_first_free_cluster(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<()> {
+        get_info_cache(self.sector_num as usize, block_device, CacheMode::WRITE)?
             .write()
             .modify(492, |start_clu: &mut u32| {
                 *start_clu = start_cluster;
             });
+        Ok(())
     }
 }
 
@@ -238,18 +243,19 @@ impl ShortDirEntry {
     pub fn new(name_: &[u8], extension_: &[u8], attribute: u8) -> Self {
         let name: [u8; 8] = clone_into_array(&name_[0..8]);
         let extension: [u8; 3] = clone_into_array(&extension_[0..3]);
+        let (date, time) = time::now();
         Self {
             name,
             extension,
             attribute,
             winnt_reserved: 0,
             creation_tenths: 0,
-            creation_time: 0,
-            creation_date: 0x529c,
-            last_acc_date: 0,
+            creation_time: time,
+            creation_date: date,
+            last_acc_date: date,
             cluster_high: 0,
-            modification_time: 0,
-            modification_date: 0,
+            modification_time: time,
+            modification_date: date,
             cluster_low: 0,
             size: 0,
         }
@@ -259,18 +265,19 @@ impl ShortDirEntry {
     pub fn initialize(&mut self, name_: &[u8], extension_: &[u8], attribute: u8) {
         let name: [u8; 8] = clone_into_array(&name_[0..8]);
         let extension: [u8; 3] = clone_into_array(&extension_[0..3]);
+        let (date, time) = time::now();
         *self = Self {
             name,
             extension,
             attribute,
             winnt_reserved: 0,
             creation_tenths: 0,
-            creation_time: 0,
-            creation_date: 0x529c,
-            last_acc_date: 0,
+            creation_time: time,
+            creation_date: date,
+            last_acc_date: date,
             cluster_high: 0,
-            modification_time: 0,
-            modification_date: 0,
+            modification_time: time,
+            modification_date: date,
             cluster_low: 0,
             size: 0,
         };
@@ -340,8 +347,7 @@ impl ShortDirEntry {
         let hour: u32 = ((self.creation_time & 0xF800) >> 11) as u32;
         let min: u32 = ((self.creation_time & 0x07E0) >> 5) as u32;
         let sec: u32 = ((self.creation_time & 0x001F) << 1) as u32; 
-        let long_sec: u64 =
-            ((((year - 1970) * 365 + month * 30 + day) * 24 + hour) * 3600 + min * 60 + sec) as u64;
+        let long_sec = time::to_unix(year, month, day, hour, min, sec);
         (year, month, day, hour, min, sec, long_sec)
     }
 
@@ -353,8 +359,7 @@ impl ShortDirEntry {
         let hour: u32 = ((self.modification_time & 0xF800) >> 11) as u32;
         let min: u32 = ((self.modification_time & 0x07E0) >> 5) as u32;
         let sec: u32 = ((self.modification_time & 0x001F) << 1) as u32; 
-        let long_sec: u64 =
-            ((((year - 1970) * 365 + month * 30 + day) * 24 + hour) * 3600 + min * 60 + sec) as u64;
+        let long_sec = time::to_unix(year, month, day, hour, min, sec);
         (year, month, day, hour, min, sec, long_sec)
     }
 
@@ -366,11 +371,18 @@ impl ShortDirEntry {
         let hour: u32 = 0;
         let min: u32 = 0;
         let sec: u32 = 0; 
-        let long_sec: u64 =
-            ((((year - 1970) * 365 + month * 30 + day) * 24 + hour) * 3600 + min * 60 + sec) as u64;
+        let long_sec = time::to_unix(year, month, day, hour, min, sec);
         (year, month, day, hour, min, sec, long_sec)
     }
 
+    /// 把修改时间和访问日期更新为当前时间
+    pub fn touch(&mut self) {
+        let (date, time) = time::now();
+        self.modification_date = date;
+        self.modification_time = time;
+        self.last_acc_date = date;
+    }
+
     /// 获取文件起始簇号
     pub fn first_cluster(&self) -> u32 {
         ((self.cluster_high as u32) << 16) + (self.cluster_low as u32)
@@ -436,10 +448,11 @@ impl ShortDirEntry {
             name_buff[i + 8] = self.extension[i];
         }
         for i in 0..11 {
+            // 按规范取模 256 累加
             if (sum & 1) != 0 {
-                sum = 0x80 + (sum >> 1) + name_buff[i];
+                sum = (0x80 + (sum >> 1)).wrapping_add(name_buff[i]);
             } else {
-                sum = (sum >> 1) + name_buff[i];
+                sum = (sum >> 1).wrapping_add(name_buff[i]);
             }
         }
         sum
@@ -459,6 +472,15 @@ impl ShortDirEntry {
         self.winnt_reserved = case;
     }
 
+    /// 设置或清除只读属性
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.attribute |= ATTRIBUTE_READ_ONLY;
+        } else {
+            self.attribute &= !ATTRIBUTE_READ_ONLY;
+        }
+    }
+
     /// 设置文件起始簇
     pub fn set_first_cluster(&mut self, cluster: u32) {
         self.cluster_high = ((cluster & 0xFFFF0000) >> 16) as u16;
@@ -485,7 +507,7 @@ impl ShortDirEntry {
         manager: &Arc<RwLock<FAT32Manager>>,
         fat: &Arc<RwLock<FAT>>,
         block_device: &Arc<dyn BlockDevice>,
-    ) -> (u32, usize, usize) {
+    ) -> FatResult<(u32, usize, usize)> {
         let manager_reader = manager.read();
         let fat_reader = fat.read();
         let bytes_per_sector = manager_reader.bytes_per_sector() as usize;
@@ -495,10 +517,10 @@ impl ShortDirEntry {
             self.first_cluster(),
             cluster_index,
             Arc::clone(block_device),
-        );
+        )?;
         let current_sector = manager_reader.first_sector_of_cluster(current_cluster)
             + (offset - cluster_index as usize * bytes_per_cluster) / bytes_per_sector;
-        (current_cluster, current_sector, offset % bytes_per_sector)
+        Ok((current_cluster, current_sector, offset % bytes_per_sector))
     }
 
     /// 以偏移量读取文件，这里会对fat和manager加读锁
@@ -509,7 +531,7 @@ impl ShortDirEntry {
         manager: &Arc<RwLock<FAT32Manager>>,
         fat: &Arc<RwLock<FAT>>,
         block_device: &Arc<dyn BlockDevice>,
-    ) -> usize {
+    ) -> FatResult<usize> {
         // 获取共享锁
         let manager_reader = manager.read();
         let fat_reader = fat.read();
@@ -519,7 +541,7 @@ impl ShortDirEntry {
         let end: usize;
         if self.is_dir() {
             let size = bytes_per_cluster
-                * fat_reader.count_claster_num(self.first_cluster() as u32, block_device.clone())
+                * fat_reader.count_claster_num(self.first_cluster() as u32, block_device.clone())?
                     as usize;
             end = offset + buf.len().min(size); // DEBUG:约束上界
         } else {
@@ -527,12 +549,12 @@ impl ShortDirEntry {
         }
     
         if current_off >= end {
-            return 0;
+            return Ok(0);
         }
         let (c_clu, c_sec, _) =
-            self.get_pos(offset, manager, &manager_reader.get_fat(), block_device);
+            self.get_pos(offset, manager, &manager_reader.get_fat(), block_device)?;
         if c_clu >= END_CLUSTER {
-            return 0;
+            return Ok(0);
         };
         let mut current_cluster = c_clu;
         let mut current_sector = c_sec;
@@ -545,13 +567,48 @@ impl ShortDirEntry {
             // 读
             let block_read_size = end_current_block - current_off;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            if self.is_dir() {
+            if !self.is_dir()
+                && block_read_size == bytes_per_sector
+                && end - current_off > bytes_per_sector
+                && !is_block_cached(current_sector, block_device)
+            {
+                // 连续的整扇区都不在cache中时，沿簇链找出物理上连续的一段，向设备一次读入
+                let mut run_sectors = 1;
+                let mut next_off = end_current_block;
+                while next_off + bytes_per_sector <= end {
+                    let (next_cluster, next_sector) = if next_off % bytes_per_cluster == 0 {
+                        let cluster = fat_reader.get_next_cluster(current_cluster, Arc::clone(block_device))?;
+                        if cluster >= END_CLUSTER {
+                            break;
+                        }
+                        (cluster, manager_reader.first_sector_of_cluster(cluster))
+                    } else {
+                        (current_cluster, current_sector + 1)
+                    };
+                    if next_sector != current_sector + 1 || is_block_cached(next_sector, block_device) {
+                        break;
+                    }
+                    current_cluster = next_cluster;
+                    current_sector = next_sector;
+                    run_sectors += 1;
+                    next_off += bytes_per_sector;
+                }
+                let run_size = run_sectors * bytes_per_sector;
+                read_blocks_uncached(
+                    current_sector + 1 - run_sectors,
+                    &mut buf[read_size..read_size + run_size],
+                    block_device,
+                )?;
+                // 之后按这一段的最后一个扇区更新索引
+                end_current_block = next_off;
+                read_size += run_size - block_read_size;
+            } else if self.is_dir() {
                 get_info_cache(
                     // 目录项通过Infocache访问
                     current_sector,
                     Arc::clone(block_device),
                     CacheMode::READ,
-                )
+                )?
                 .read()
                 .read(0, |data_block: &DataBlock| {
                     let src = &data_block
@@ -559,7 +616,7 @@ impl ShortDirEntry {
                     dst.copy_from_slice(src);
                 });
             } else {
-                get_block_cache(current_sector, Arc::clone(block_device), CacheMode::READ)
+                get_block_cache(current_sector, Arc::clone(block_device), CacheMode::READ)?
                     .read()
                     .read(0, |data_block: &DataBlock| {
                         let src = &data_block
@@ -578,7 +635,7 @@ impl ShortDirEntry {
             if current_off % bytes_per_cluster == 0 {
                 // 读完一个簇
                 current_cluster =
-                    fat_reader.get_next_cluster(current_cluster, Arc::clone(block_device));
+                    fat_reader.get_next_cluster(current_cluster, Arc::clone(block_device))?;
                 if current_cluster >= END_CLUSTER {
                     break;
                 }
@@ -587,7 +644,7 @@ impl ShortDirEntry {
                 current_sector += 1; //没读完一个簇，直接进入下一扇区
             }
         }
-        read_size
+        Ok(read_size)
     }
 
     /// 以偏移量写文件，这里会对fat和manager加读锁
@@ -598,7 +655,7 @@ impl ShortDirEntry {
         manager: &Arc<RwLock<FAT32Manager>>,
         fat: &Arc<RwLock<FAT>>,
         block_device: &Arc<dyn BlockDevice>,
-    ) -> usize {
+    ) -> FatResult<usize> {
         // 获取共享锁
         let manager_reader = manager.read();
         let fat_reader = fat.read();
@@ -608,7 +665,7 @@ impl ShortDirEntry {
         let end: usize;
         if self.is_dir() {
             let size = bytes_per_cluster
-                * fat_reader.count_claster_num(self.first_cluster() as u32, block_device.clone())
+                * fat_reader.count_claster_num(self.first_cluster() as u32, block_device.clone())?
                     as usize;
             end = offset + buf.len().min(size); // DEBUG:约束上界
         } else {
@@ -616,7 +673,7 @@ impl ShortDirEntry {
             end = (offset + buf.len()).min(self.size as usize);
         }
         let (c_clu, c_sec, _) =
-            self.get_pos(offset, manager, &manager_reader.get_fat(), block_device);
+            self.get_pos(offset, manager, &manager_reader.get_fat(), block_device)?;
         // 找到当前的cluster和sector，我们这里应该是一样的
         let mut current_cluster = c_clu;
         let mut current_sector = c_sec;
@@ -634,7 +691,7 @@ impl ShortDirEntry {
                     current_sector,
                     Arc::clone(block_device),
                     CacheMode::READ,
-                )
+                )?
                 .write()
                 .modify(0, |data_block: &mut DataBlock| {
                     let src = &buf[write_size..write_size + block_write_size];
@@ -643,7 +700,7 @@ impl ShortDirEntry {
                     dst.copy_from_slice(src);
                 });
             } else {
-                get_block_cache(current_sector, Arc::clone(block_device), CacheMode::READ)
+                get_block_cache(current_sector, Arc::clone(block_device), CacheMode::READ)?
                     .write()
                     .modify(0, |data_block: &mut DataBlock| {
                         let src = &buf[write_size..write_size + block_write_size];
@@ -661,7 +718,7 @@ impl ShortDirEntry {
             current_off = end_current_block;
             if current_off % bytes_per_cluster == 0 {
                 current_cluster =
-                    fat_reader.get_next_cluster(current_cluster, Arc::clone(block_device));
+                    fat_reader.get_next_cluster(current_cluster, Arc::clone(block_device))?;
                 if current_cluster >= END_CLUSTER {
                     panic!("END_CLUSTER");
                 } 
@@ -670,7 +727,7 @@ impl ShortDirEntry {
                 current_sector += 1;
             }
         }
-        write_size
+        Ok(write_size)
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -907,20 +964,24 @@ impl FAT {
     }
 
     /* 搜索下一个可用簇 */
-    // caller需要确定有足够的空闲簇，这里不作越界检查
+    // caller需要确定有足够的空闲簇；搜索到数据区末尾 `end_cluster` 时从簇 2 重新开始
     pub fn next_free_cluster(
         &self,
         current_cluster: u32,
+        end_cluster: u32,
         block_device: Arc<dyn BlockDevice>,
-    ) -> u32 {
+    ) -> FatResult<u32> {
         // DEBUG
         let mut curr_cluster = current_cluster + 1;
         loop {
+            if curr_cluster < 2 || curr_cluster >= end_cluster {
+                curr_cluster = 2;
+            }
             #[allow(unused)]
             let (fat1_sec, fat2_sec, offset) = self.calculate_pos(curr_cluster);
             // 查看当前cluster的表项
             let entry_val =
-                get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::READ)
+                get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::READ)?
                     .read()
                     .read(offset as usize, |&entry_val: &u32| entry_val);
             if entry_val == FREE_CLUSTER {
@@ -929,34 +990,56 @@ impl FAT {
                 curr_cluster += 1;
             }
         }
-        curr_cluster & 0x0FFFFFFF
+        Ok(curr_cluster & 0x0FFFFFFF)
+    }
+
+    /// 统计簇号 2 到 `n_clusters + 1` 中空闲簇的个数
+    pub fn count_free_clusters(&self, n_clusters: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
+        let mut free = 0;
+        let end = n_clusters + 2;
+        let mut cluster = 2;
+        while cluster < end {
+            let (fat1_sec, _, _) = self.calculate_pos(cluster);
+            let first = cluster % FATENTRY_PER_SEC;
+            let last = (end - cluster + first).min(FATENTRY_PER_SEC);
+            free += get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::READ)?
+                .read()
+                .read(0, |entries: &[u32; FATENTRY_PER_SEC as usize]| {
+                    entries[first as usize..last as usize]
+                        .iter()
+                        .filter(|&&entry| entry & 0x0FFFFFFF == FREE_CLUSTER)
+                        .count() as u32
+                });
+            cluster += last - first;
+        }
+        Ok(free)
     }
 
     /// 查询当前簇的下一个簇
-    pub fn get_next_cluster(&self, cluster: u32, block_device: Arc<dyn BlockDevice>) -> u32 {
+    pub fn get_next_cluster(&self, cluster: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
         // 需要对损坏簇作出判断
         // 及时使用备用表
         // 无效或未使用返回0
         let (fat1_sec, fat2_sec, offset) = self.calculate_pos(cluster);
-        let fat1_rs = get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::READ)
+        let fat1_rs = get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::READ)?
             .read()
             .read(offset as usize, |&next_cluster: &u32| next_cluster);
-        let fat2_rs = get_info_cache(fat2_sec as usize, block_device.clone(), CacheMode::READ)
+        let fat2_rs = get_info_cache(fat2_sec as usize, block_device.clone(), CacheMode::READ)?
             .read()
             .read(offset as usize, |&next_cluster: &u32| next_cluster);
         if fat1_rs == BAD_CLUSTER {
             if fat2_rs == BAD_CLUSTER {
-                0
+                Ok(0)
             } else {
-                fat2_rs & 0x0FFFFFFF
+                Ok(fat2_rs & 0x0FFFFFFF)
             }
         } else {
-            fat1_rs & 0x0FFFFFFF
+            Ok(fat1_rs & 0x0FFFFFFF)
         }
     }
 
-    pub fn set_end(&self, cluster: u32, block_device: Arc<dyn BlockDevice>) {
-        self.set_next_cluster(cluster, END_CLUSTER, block_device);
+    pub fn set_end(&self, cluster: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<()> {
+        self.set_next_cluster(cluster, END_CLUSTER, block_device)
     }
 
     /* 设置当前簇的下一个簇 */
@@ -965,18 +1048,19 @@ impl FAT {
         cluster: u32,
         next_cluster: u32,
         block_device: Arc<dyn BlockDevice>,
-    ) {
+    ) -> FatResult<()> {
         let (fat1_sec, fat2_sec, offset) = self.calculate_pos(cluster);
-        get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::WRITE)
+        get_info_cache(fat1_sec as usize, block_device.clone(), CacheMode::WRITE)?
             .write()
             .modify(offset as usize, |old_clu: &mut u32| {
                 *old_clu = next_cluster;
             });
-        get_info_cache(fat2_sec as usize, block_device.clone(), CacheMode::WRITE)
+        get_info_cache(fat2_sec as usize, block_device.clone(), CacheMode::WRITE)?
             .write()
             .modify(offset as usize, |old_clu: &mut u32| {
                 *old_clu = next_cluster;
             });
+        Ok(())
     }
 
     /* 获取某个文件的指定cluster */
@@ -985,25 +1069,25 @@ impl FAT {
         start_cluster: u32,
         index: u32,
         block_device: Arc<dyn BlockDevice>,
-    ) -> u32 {
+    ) -> FatResult<u32> {
         let mut cluster = start_cluster;
         #[allow(unused)]
         for i in 0..index {
-            cluster = self.get_next_cluster(cluster, block_device.clone());
+            cluster = self.get_next_cluster(cluster, block_device.clone())?;
             if cluster == 0 {
                 break;
             }
         }
-        cluster & 0x0FFFFFFF
+        Ok(cluster & 0x0FFFFFFF)
     }
 
-    pub fn final_cluster(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) -> u32 {
+    pub fn final_cluster(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
         let mut curr_cluster = start_cluster;
         assert_ne!(start_cluster, 0);
         loop {
-            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone());
+            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone())?;
             if next_cluster >= END_CLUSTER || next_cluster == 0 {
-                return curr_cluster & 0x0FFFFFFF;
+                return Ok(curr_cluster & 0x0FFFFFFF);
             } else {
                 curr_cluster = next_cluster;
             }
@@ -1014,31 +1098,31 @@ impl FAT {
         &self,
         start_cluster: u32,
         block_device: Arc<dyn BlockDevice>,
-    ) -> Vec<u32> {
+    ) -> FatResult<Vec<u32>> {
         let mut curr_cluster = start_cluster;
         let mut v_cluster: Vec<u32> = Vec::new();
         loop {
             v_cluster.push(curr_cluster & 0x0FFFFFFF);
-            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone());
+            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone())?;
             if next_cluster >= END_CLUSTER || next_cluster == 0 {
-                return v_cluster;
+                return Ok(v_cluster);
             } else {
                 curr_cluster = next_cluster;
             }
         }
     }
 
-    pub fn count_claster_num(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) -> u32 {
+    pub fn count_claster_num(&self, start_cluster: u32, block_device: Arc<dyn BlockDevice>) -> FatResult<u32> {
         if start_cluster == 0 {
-            return 0;
+            return Ok(0);
         }
         let mut curr_cluster = start_cluster;
         let mut count: u32 = 0;
         loop {
             count += 1;
-            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone());
+            let next_cluster = self.get_next_cluster(curr_cluster, block_device.clone())?;
             if next_cluster >= END_CLUSTER || next_cluster > 0xF000000 {
-                return count;
+                return Ok(count);
             } else {
                 curr_cluster = next_cluster;
             }
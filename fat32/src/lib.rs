@@ -12,8 +12,11 @@ extern crate alloc;
 
 mod block_cache;
 mod block_dev;
+mod error;
 mod fat;
 mod layout;
+mod lock;
+mod time;
 mod vfs;
 
 // fat32 文件系统的一些常量
@@ -25,12 +28,18 @@ pub const DATA_SIZE: usize = 7390;
 pub const FIRST_FAT_SEC: usize = 2;
 extern crate lazy_static;
 extern crate spin;
-use block_cache::{get_block_cache, get_info_cache, set_start_sec, write_to_dev, CacheMode};
-pub use block_dev::BlockDevice;
+use block_cache::{
+    get_block_cache, get_info_cache, is_block_cached, read_blocks_uncached, write_to_dev, CacheMode,
+};
+pub use block_cache::sync_all;
+pub use block_dev::{BlockDevice, BlockError};
+pub use error::{FatError, FatResult};
 pub use fat::FAT32Manager;
 pub use layout::ShortDirEntry;
 pub use layout::*;
-pub use vfs::VFile;
+pub use lock::{set_relax_hook, RelaxHook, RwLock};
+pub use time::{civil_from_days, days_from_civil, set_time_hook, TimeHook};
+pub use vfs::{set_dir_hook, DirEvent, DirHook, VFile};
 
 pub fn clone_into_array<A, T>(slice: &[T]) -> A
 where
@@ -0,0 +1,61 @@
+//! 文件系统使用的读写锁
+//!
+//! 内核读写块设备时可能让出处理器去等待磁盘中断，这时任务仍然持有文件系统的锁。
+//! 单核上其他任务如果一直自旋等待这把锁，持有者就再也没有机会运行，
+//! 因此锁被占用时调用内核通过 [`set_relax_hook`] 注册的回调（例如切换到其他任务），没有注册时才自旋。
+
+pub use spin::{RwLockReadGuard, RwLockWriteGuard};
+
+/// 锁被占用时调用的回调
+pub type RelaxHook = fn();
+
+static RELAX_HOOK: spin::RwLock<Option<RelaxHook>> = spin::RwLock::new(None);
+
+/// 注册锁被占用时调用的回调
+pub fn set_relax_hook(hook: RelaxHook) {
+    *RELAX_HOOK.write() = Some(hook);
+}
+
+fn relax() {
+    // 复制出回调再调用，回调切换任务时不持有 RELAX_HOOK
+    let hook = *RELAX_HOOK.read();
+    match hook {
+        Some(hook) => hook(),
+        None => core::hint::spin_loop(),
+    }
+}
+
+/// 与 `spin::RwLock` 相同的读写锁，但被占用时调用 [`RelaxHook`]
+pub struct RwLock<T: ?Sized> {
+    inner: spin::RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::RwLock::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    // 获取读锁
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.inner.try_read() {
+                return guard;
+            }
+            relax();
+        }
+    }
+
+    // 获取写锁
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.inner.try_write() {
+                return guard;
+            }
+            relax();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! 目录项中的时间戳
+//!
+//! FAT32 以本地日期和时间（精度 2 秒，年份从 1980 开始）记录文件的创建、修改和访问时间。
+//! 文件系统本身没有时钟，内核通过 [`set_time_hook`] 注册返回当前 Unix 时间（秒）的回调；
+//! 没有注册时新目录项的时间戳为 FAT32 能表示的最早时间 1980-01-01 00:00:00。
+//! 这里不处理时区，按 UTC 换算。
+
+use crate::lock::RwLock;
+
+/// 返回当前 Unix 时间（自 1970-01-01 00:00:00 UTC 以来的秒数）的回调
+pub type TimeHook = fn() -> u64;
+
+static TIME_HOOK: RwLock<Option<TimeHook>> = RwLock::new(None);
+
+/// 注册提供当前时间的回调，替换之前注册的回调
+pub fn set_time_hook(hook: TimeHook) {
+    *TIME_HOOK.write() = Some(hook);
+}
+
+/// FAT32 能表示的最早时间 1980-01-01 00:00:00 对应的 Unix 时间
+const FAT_EPOCH: u64 = 315_532_800;
+/// FAT32 能表示的最晚时间 2107-12-31 23:59:58 对应的 Unix 时间
+const FAT_MAX: u64 = 4_354_819_198;
+const SECS_PER_DAY: u64 = 86400;
+
+/// 当前时间对应的 FAT32 日期和时间，超出可表示范围时取最近的边界
+pub(crate) fn now() -> (u16, u16) {
+    let hook = *TIME_HOOK.read();
+    let secs = hook.map_or(FAT_EPOCH, |hook| hook());
+    to_fat(secs.clamp(FAT_EPOCH, FAT_MAX))
+}
+
+/// 把 Unix 时间转换为 FAT32 的 (日期, 时间)，`secs` 必须在 FAT32 能表示的范围内
+fn to_fat(secs: u64) -> (u16, u16) {
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let rem = secs % SECS_PER_DAY;
+    let (hour, min, sec) = (rem / 3600, rem % 3600 / 60, rem % 60);
+    let date = ((year - 1980) as u16) << 9 | (month as u16) << 5 | day as u16;
+    let time = (hour as u16) << 11 | (min as u16) << 5 | (sec / 2) as u16;
+    (date, time)
+}
+
+/// 把 FAT32 的日期各字段转换为 Unix 时间。日期为 0（没有记录）时返回 0
+pub(crate) fn to_unix(year: u32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> u64 {
+    if month == 0 || day == 0 {
+        return 0;
+    }
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    days as u64 * SECS_PER_DAY + (hour * 3600 + min * 60 + sec) as u64
+}
+
+/// 公历日期距 1970-01-01 的天数
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // 把一年的开始移到 3 月 1 日，闰日成为一年的最后一天
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// 距 1970-01-01 `days` 天的公历日期 (年, 月, 日)
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
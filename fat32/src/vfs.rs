@@ -9,30 +9,32 @@ use super::{
     layout::*,
     BlockDevice,
     CacheMode,
+    FatError,
+    FatResult,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use spin::RwLock;
+use crate::lock::RwLock;
 pub struct kstat {
-    st_dev: u64,   // 文件所在设备的ID
-    st_ino: u64,   // 文件的inode节点号
-    st_mode: u32,  // 文件的类型和存取的权限
-    st_nlink: u32, // 连到该文件的硬连接数目，刚建立的文件值为1
-    st_uid: u32,   // 文件所有者的用户ID
-    st_gid: u32,   // 文件所有者的组ID
-    st_rdev: u64,  // 若此文件为设备文件，则为其设备编号
+    pub st_dev: u64,   // 文件所在设备的ID
+    pub st_ino: u64,   // 文件的inode节点号
+    pub st_mode: u32,  // 文件的类型和存取的权限
+    pub st_nlink: u32, // 连到该文件的硬连接数目，刚建立的文件值为1
+    pub st_uid: u32,   // 文件所有者的用户ID
+    pub st_gid: u32,   // 文件所有者的组ID
+    pub st_rdev: u64,  // 若此文件为设备文件，则为其设备编号
     __pad: u64,    // 未使用
-    st_size: i64,  // 文件字节数
-    st_blksize: u32,   // 块大小
+    pub st_size: i64,  // 文件字节数
+    pub st_blksize: u32,   // 块大小
     __pad2: i32,       // 未使用
-    st_blocks: u64,    // 文件所占块数
-    st_atime_sec: i64,    // 最近一次访问时间秒
-    st_atime_nsec: i64,   // 最近一次访问时间纳秒
-    st_mtime_sec: i64,    // 最近一次修改时间秒
-    st_mtime_nsec: i64,   // 最近一次修改时间纳秒
-    st_ctime_sec: i64,    // 最近一次改变时间秒
-    st_ctime_nsec: i64,   // 最近一次改变时间纳秒
+    pub st_blocks: u64,    // 文件所占块数
+    pub st_atime_sec: i64,    // 最近一次访问时间秒
+    pub st_atime_nsec: i64,   // 最近一次访问时间纳秒
+    pub st_mtime_sec: i64,    // 最近一次修改时间秒
+    pub st_mtime_nsec: i64,   // 最近一次修改时间纳秒
+    pub st_ctime_sec: i64,    // 最近一次改变时间秒
+    pub st_ctime_nsec: i64,   // 最近一次改变时间纳秒
     __unused: [u32; 2],   // 未使用
 }
 
@@ -89,6 +91,34 @@ impl dirent{
     }
 }
 
+/// 目录内容变化的种类
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirEvent {
+    /// 在目录中创建了文件或子目录
+    Create,
+    /// 从目录中删除了文件或子目录
+    Delete,
+    /// 目录中的文件被写入
+    Modify,
+}
+
+/// 目录内容变化时的回调，参数是目录的标识（见 [`VFile::dirent_pos`]）、文件名和变化的种类
+pub type DirHook = fn((usize, usize), &str, DirEvent);
+
+static DIR_HOOK: RwLock<Option<DirHook>> = RwLock::new(None);
+
+/// 注册目录内容变化的回调，替换之前注册的回调
+pub fn set_dir_hook(hook: DirHook) {
+    *DIR_HOOK.write() = Some(hook);
+}
+
+fn notify_dir(dir: (usize, usize), name: &str, event: DirEvent) {
+    let hook = *DIR_HOOK.read();
+    if let Some(hook) = hook {
+        hook(dir, name, event);
+    }
+}
+
 // 文件系统的文件
 #[derive(Clone)]
 pub struct VFile {
@@ -97,6 +127,7 @@ pub struct VFile {
     pub short_offset: usize,               //文件短目录项所在扇区和偏移
     pub long_pos_vec: Vec<(usize, usize)>, // 长目录项的位置<sector, offset>
     pub attribute: u8,                     // 文件属性
+    parent: (usize, usize),                // 所在目录的短目录项位置，根目录为 (0, 0)
     fs: Arc<RwLock<FAT32Manager>>,         // 文件系统
     block_device: Arc<dyn BlockDevice>,    // 块设备
 }
@@ -112,6 +143,7 @@ impl VFile {
         size: u32,
         fs: Arc<RwLock<FAT32Manager>>,
         block_device: Arc<dyn BlockDevice>,
+        parent: (usize, usize),
     ) -> Self {
         Self {
             name,
@@ -121,6 +153,7 @@ impl VFile {
             //first_cluster,
             attribute,
             //size,
+            parent,
             fs,
             block_device,
         }
@@ -134,7 +167,7 @@ impl VFile {
         self.attribute
     }
 
-    pub fn get_size(&self) -> u32 {
+    pub fn get_size(&self) -> FatResult<u32> {
         self.read_short_dirent(|se: &ShortDirEntry| se.get_size())
     }
 
@@ -150,6 +183,22 @@ impl VFile {
         }
     }
 
+    /// 短目录项所在的扇区和扇区内的偏移，同一个文件的所有 VFile 都相同，可以作为文件的标识。
+    /// 根目录没有目录项，返回 (0, 0)
+    pub fn dirent_pos(&self) -> (usize, usize) {
+        (self.short_sector, self.short_offset)
+    }
+
+    /// 短目录项中是否设置了只读属性。属性可能被 chmod 修改，所以每次从目录项中读取
+    pub fn is_readonly(&self) -> FatResult<bool> {
+        self.read_short_dirent(|se: &ShortDirEntry| se.attribute() & ATTRIBUTE_READ_ONLY != 0)
+    }
+
+    /// 设置或清除短目录项中的只读属性
+    pub fn set_readonly(&self, readonly: bool) -> FatResult<()> {
+        self.modify_short_dirent(|se: &mut ShortDirEntry| se.set_readonly(readonly))
+    }
+
     pub fn is_short(&self) -> bool {
         if self.long_pos_vec.len() == 0 {
             true
@@ -158,50 +207,50 @@ impl VFile {
         }
     }
 
-    // 读取目录项
-    pub fn read_short_dirent<V>(&self, f: impl FnOnce(&ShortDirEntry) -> V) -> V {
+    // 读取目录项，读入目录项所在的扇区失败时返回错误
+    pub fn read_short_dirent<V>(&self, f: impl FnOnce(&ShortDirEntry) -> V) -> FatResult<V> {
         if self.short_sector == 0 {
             let root_dirent = self.fs.read().get_root_dirent();
             let rr = root_dirent.read();
-            f(&rr)
+            Ok(f(&rr))
         } else {
-            get_info_cache(
+            Ok(get_info_cache(
                 self.short_sector,
                 self.block_device.clone(),
                 CacheMode::READ,
-            )
+            )?
             .read()
-            .read(self.short_offset, f)
+            .read(self.short_offset, f))
         }
     }
 
     // 修改长目录项
-    fn modify_long_dirent<V>(&self, index: usize, f: impl FnOnce(&mut LongDirEntry) -> V) -> V {
+    fn modify_long_dirent<V>(&self, index: usize, f: impl FnOnce(&mut LongDirEntry) -> V) -> FatResult<V> {
         let (sector, offset) = self.long_pos_vec[index];
-        get_info_cache(sector, self.block_device.clone(), CacheMode::READ)
+        Ok(get_info_cache(sector, self.block_device.clone(), CacheMode::READ)?
             .write()
-            .modify(offset, f)
+            .modify(offset, f))
     }
 
     // 修改短目录项
-    pub fn modify_short_dirent<V>(&self, f: impl FnOnce(&mut ShortDirEntry) -> V) -> V {
+    pub fn modify_short_dirent<V>(&self, f: impl FnOnce(&mut ShortDirEntry) -> V) -> FatResult<V> {
         if self.short_sector == 0 {
             let root_dirent = self.fs.read().get_root_dirent();
             let mut rw = root_dirent.write();
-            f(&mut rw)
+            Ok(f(&mut rw))
         } else {
-            get_info_cache(
+            Ok(get_info_cache(
                 self.short_sector,
                 self.block_device.clone(),
                 CacheMode::READ,
-            )
+            )?
             .write()
-            .modify(self.short_offset, f)
+            .modify(self.short_offset, f))
         }
     }
 
     /* 返回sector和offset */
-    pub fn get_pos(&self, offset: usize) -> (usize, usize) {
+    pub fn get_pos(&self, offset: usize) -> FatResult<(usize, usize)> {
         let (_, sec, off) = self.read_short_dirent(|s_ent: &ShortDirEntry| {
             s_ent.get_pos(
                 offset,
@@ -209,26 +258,19 @@ impl VFile {
                 &self.fs.read().get_fat(),
                 &self.block_device,
             )
-        });
-        (sec, off)
+        })??;
+        Ok((sec, off))
     }
 
     // 长目录名来寻找目录
-    fn find_long_name(&self, name: &str, dir_ent: &ShortDirEntry) -> Option<Arc<VFile>> {
+    fn find_long_name(&self, name: &str, dir_ent: &ShortDirEntry) -> FatResult<Option<Arc<VFile>>> {
         let name_vec = self.fs.read().long_name_split(name);
         let mut offset: usize = 0;
         let mut long_ent = LongDirEntry::empty();
         let long_ent_num = name_vec.len();
         let mut long_pos_vec: Vec<(usize, usize)> = Vec::new();
         let name_last = name_vec[long_ent_num - 1].clone();
-        let mut step: usize = long_ent_num;
-        for i in (long_ent_num - 2)..0 {
-            if name_last == name_vec[i] {
-                step = step - i - 1;
-                break;
-            }
-        }
-        step = 1;
+        let step: usize = 1;
         loop {
             long_pos_vec.clear();
             // 读取offset处的目录项
@@ -238,9 +280,9 @@ impl VFile {
                 &self.fs,
                 &self.fs.read().get_fat(),
                 &self.block_device,
-            );
+            )?;
             if read_sz != DIRENT_SZ || long_ent.is_empty() {
-                return None;
+                return Ok(None);
             }
             if long_ent.get_name_raw() == name_last && long_ent.attribute() == ATTRIBUTE_LFN {
                 // 匹配：如果名一致，且第一字段为0x4*，获取该order，以及校验和
@@ -264,9 +306,9 @@ impl VFile {
                         &self.fs,
                         &self.fs.read().get_fat(),
                         &self.block_device,
-                    );
+                    )?;
                     if read_sz != DIRENT_SZ {
-                        return None;
+                        return Ok(None);
                     }
                     if long_ent.get_name_raw() != name_vec[long_ent_num - 1 - i]
                         || long_ent.attribute() != ATTRIBUTE_LFN
@@ -285,18 +327,18 @@ impl VFile {
                         &self.fs,
                         &self.fs.read().get_fat(),
                         &self.block_device,
-                    );
+                    )?;
                     if read_sz != DIRENT_SZ {
-                        return None;
+                        return Ok(None);
                     }
                     if short_ent.is_valid() && l_checksum == short_ent.checksum() {
-                        let (short_sector, short_offset) = self.get_pos(s_off);
+                        let (short_sector, short_offset) = self.get_pos(s_off)?;
                         for i in 0..order as usize {
                             // 存入长名目录项位置了，第一个在栈顶
-                            let pos = self.get_pos(offset + i);
+                            let pos = self.get_pos(offset + i * DIRENT_SZ)?;
                             long_pos_vec.push(pos);
                         }
-                        return Some(Arc::new(VFile::new(
+                        return Ok(Some(Arc::new(VFile::new(
                             String::from(name),
                             short_sector,
                             short_offset,
@@ -305,9 +347,10 @@ impl VFile {
                             short_ent.get_size(),
                             self.fs.clone(),
                             self.block_device.clone(),
-                        )));
+                            self.dirent_pos(),
+                        ))));
                     } else {
-                        return None; // QUES
+                        return Ok(None); // QUES
                     }
                 } else {
                     offset += step * DIRENT_SZ;
@@ -319,27 +362,43 @@ impl VFile {
         }
     }
 
-    /// 查找短文件名目录
-    fn find_short_name(&self, name: &str, dir_ent: &ShortDirEntry) -> Option<Arc<VFile>> {
+    /// 查找短文件名目录。其他实现会给小写的短文件名也写上长目录项，
+    /// 紧挨在短目录项前面、校验和一致的长目录项一并记下，删除文件时一起删除
+    fn find_short_name(&self, name: &str, dir_ent: &ShortDirEntry) -> FatResult<Option<Arc<VFile>>> {
         let name_upper = name.to_ascii_uppercase();
-        let mut short_ent = ShortDirEntry::empty();
+        let mut long_ent = LongDirEntry::empty();
+        // 前面连续的长目录项的偏移和校验和
+        let mut long_offsets: Vec<(usize, u8)> = Vec::new();
         let mut offset = 0;
-        let mut read_sz: usize;
         loop {
-            read_sz = dir_ent.read_at(
+            let read_sz = dir_ent.read_at(
                 offset,
-                short_ent.as_bytes_mut(),
+                long_ent.as_bytes_mut(),
                 &self.fs,
                 &self.fs.read().get_fat(),
                 &self.block_device,
-            );
-            if read_sz != DIRENT_SZ || short_ent.is_empty() {
-                return None;
+            )?;
+            if read_sz != DIRENT_SZ || long_ent.is_empty() {
+                return Ok(None);
+            }
+            if !long_ent.is_valid() {
+                long_offsets.clear();
+            } else if long_ent.attribute() == ATTRIBUTE_LFN {
+                long_offsets.push((offset, long_ent.get_checksum()));
             } else {
-                if short_ent.is_valid() && name_upper == short_ent.get_name_uppercase() {
-                    let (short_sector, short_offset) = self.get_pos(offset);
-                    let long_pos_vec: Vec<(usize, usize)> = Vec::new();
-                    return Some(Arc::new(VFile::new(
+                let (_, se_array, _) =
+                    unsafe { long_ent.as_bytes_mut().align_to_mut::<ShortDirEntry>() };
+                let short_ent = se_array[0];
+                if name_upper == short_ent.get_name_uppercase() {
+                    let (short_sector, short_offset) = self.get_pos(offset)?;
+                    let checksum = short_ent.checksum();
+                    let mut long_pos_vec: Vec<(usize, usize)> = Vec::new();
+                    for &(long_offset, long_checksum) in long_offsets.iter() {
+                        if long_checksum == checksum {
+                            long_pos_vec.push(self.get_pos(long_offset)?);
+                        }
+                    }
+                    return Ok(Some(Arc::new(VFile::new(
                         String::from(name),
                         short_sector,
                         short_offset,
@@ -348,17 +407,17 @@ impl VFile {
                         short_ent.get_size(),
                         self.fs.clone(),
                         self.block_device.clone(),
-                    )));
-                } else {
-                    offset += DIRENT_SZ;
-                    continue;
+                        self.dirent_pos(),
+                    ))));
                 }
+                long_offsets.clear();
             }
+            offset += DIRENT_SZ;
         }
     }
 
-    /// 根据名称搜索当前目录下的文件
-    pub fn find_vfile_byname(&self, name: &str) -> Option<Arc<VFile>> {
+    /// 根据名称搜索当前目录下的文件，不存在时返回 None
+    pub fn find_vfile_byname(&self, name: &str) -> FatResult<Option<Arc<VFile>>> {
         assert!(self.is_dir());
         // 将文件名和扩展分开
         let mut name_and_ext: Vec<&str> = name.split(".").collect();
@@ -377,16 +436,16 @@ impl VFile {
                 // 短文件名
                 return self.find_short_name(name, short_ent);
             }
-        })
+        })?
     }
 
     /// 根据路径递归搜索文件
-    pub fn find_vfile_bypath(&self, path: Vec<&str>) -> Option<Arc<VFile>> {
+    pub fn find_vfile_bypath(&self, path: Vec<&str>) -> FatResult<Option<Arc<VFile>>> {
         let _ = self.fs.read(); // 获取读锁
         let len = path.len();
         if len == 0 {
             // 如果长度为0，则返回自己
-            return Some(Arc::new(self.clone()));
+            return Ok(Some(Arc::new(self.clone())));
         }
         let mut current_vfile = Arc::new(self.clone());
         for i in 0..len {
@@ -394,63 +453,63 @@ impl VFile {
                 // 跳过，表示仍然为当前目录
                 continue;
             }
-            if let Some(vfile) = current_vfile.find_vfile_byname(path[i]) {
+            if let Some(vfile) = current_vfile.find_vfile_byname(path[i])? {
                 current_vfile = vfile;
             } else {
-                return None;
+                return Ok(None);
             }
         }
-        Some(current_vfile)
+        Ok(Some(current_vfile))
     }
 
-    fn increase_size(&self, new_size: u32) {
-        let first_cluster = self.first_cluster();
-        let old_size = self.get_size();
+    fn increase_size(&self, new_size: u32) -> FatResult<()> {
+        let first_cluster = self.first_cluster()?;
+        let old_size = self.get_size()?;
         let manager_writer = self.fs.write();
         if new_size <= old_size {
-            return;
+            return Ok(());
         }
         // 获取现在需要多少cluster去增长size
         let needed =
-            manager_writer.cluster_num_needed(old_size, new_size, self.is_dir(), first_cluster);
+            manager_writer.cluster_num_needed(old_size, new_size, self.is_dir(), first_cluster)?;
         if needed == 0 {
             if !self.is_dir() {
                 self.modify_short_dirent(|se: &mut ShortDirEntry| {
                     se.set_size(new_size);
-                });
+                })?;
             }
-            return;
+            return Ok(());
         }
         
         
-        if let Some(cluster) = manager_writer.alloc_cluster(needed) {
+        if let Some(cluster) = manager_writer.alloc_cluster(needed)? {
             if first_cluster == 0 {
                 //未分配簇
                 drop(manager_writer);
                 self.modify_short_dirent(|se: &mut ShortDirEntry| {
                     se.set_first_cluster(cluster);
-                });
+                })?;
             } else {
                 let fat = manager_writer.get_fat();
                 let fat_writer = fat.write();
                 let final_cluster =
-                    fat_writer.final_cluster(first_cluster, self.block_device.clone());
+                    fat_writer.final_cluster(first_cluster, self.block_device.clone())?;
                 assert_ne!(cluster, 0);
-                fat_writer.set_next_cluster(final_cluster, cluster, self.block_device.clone());
+                fat_writer.set_next_cluster(final_cluster, cluster, self.block_device.clone())?;
                 drop(manager_writer);
             }
             //self.size = new_size;
             self.modify_short_dirent(|se: &mut ShortDirEntry| {
                 se.set_size(new_size);
-            });
+            })
         } else {
-            panic!("SD Card no space!!!");
+            Err(FatError::NoSpace)
         }
     }
 
 
     /// 在当前目录下创建文件
-    pub fn create(&self, name: &str, attribute: u8) -> Option<Arc<VFile>> {
+    pub fn create(&self, name: &str, attribute: u8) -> FatResult<Option<Arc<VFile>>> {
         // 检测同名文件, 此时应在根目录下
         assert!(self.is_dir());
         let manager_reader = self.fs.read();
@@ -458,10 +517,10 @@ impl VFile {
         // 搜索空处
         // 此时若不是目录文件，则返回为None
         let mut dirent_offset: usize;
-        if let Some(offset) = self.find_free_dirent() {
+        if let Some(offset) = self.find_free_dirent()? {
             dirent_offset = offset;
         } else {
-            return None;
+            return Ok(None);
         }
         let mut short_ent = ShortDirEntry::empty();
         if name_.len() > 8 || ext_.len() > 3 {
@@ -484,7 +543,7 @@ impl VFile {
                 long_ent.initialize(v_long_name.pop().unwrap().as_bytes(), order, check_sum);
                 assert_eq!(
                     // 写长目录项
-                    self.write_at(dirent_offset, long_ent.as_bytes_mut()),
+                    self.write_at(dirent_offset, long_ent.as_bytes_mut())?,
                     DIRENT_SZ
                 );
                 dirent_offset += DIRENT_SZ;
@@ -498,11 +557,11 @@ impl VFile {
         }
         // 写短目录项
         assert_eq!(
-            self.write_at(dirent_offset, short_ent.as_bytes_mut()),
+            self.write_at(dirent_offset, short_ent.as_bytes_mut())?,
             DIRENT_SZ
         );
         // 如果是目录类型，需要创建.和..
-        if let Some(vfile) = self.find_vfile_byname(name) {
+        if let Some(vfile) = self.find_vfile_byname(name)? {
             if attribute & ATTRIBUTE_DIRECTORY != 0 {
                 let manager_reader = self.fs.read();
                 let (name_bytes, ext_bytes) = manager_reader.short_name_format(".");
@@ -510,30 +569,31 @@ impl VFile {
                 let (name_bytes, ext_bytes) = manager_reader.short_name_format("..");
                 let mut par_dir = ShortDirEntry::new(&name_bytes, &ext_bytes, ATTRIBUTE_DIRECTORY);
                 drop(manager_reader);
-                par_dir.set_first_cluster(self.first_cluster());
+                par_dir.set_first_cluster(self.first_cluster()?);
 
-                vfile.write_at(0, self_dir.as_bytes_mut());
-                vfile.write_at(DIRENT_SZ, par_dir.as_bytes_mut());
+                vfile.write_at(0, self_dir.as_bytes_mut())?;
+                vfile.write_at(DIRENT_SZ, par_dir.as_bytes_mut())?;
                 let first_cluster =
-                    vfile.read_short_dirent(|se: &ShortDirEntry| se.first_cluster());
+                    vfile.read_short_dirent(|se: &ShortDirEntry| se.first_cluster())?;
                 self_dir.set_first_cluster(first_cluster);
-                vfile.write_at(0, self_dir.as_bytes_mut());
+                vfile.write_at(0, self_dir.as_bytes_mut())?;
             }
-            return Some(vfile);
+            notify_dir(self.dirent_pos(), name, DirEvent::Create);
+            return Ok(Some(vfile));
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn first_cluster(&self) -> u32 {
+    pub fn first_cluster(&self) -> FatResult<u32> {
         self.read_short_dirent(|se: &ShortDirEntry| se.first_cluster())
     }
 
     /* 获取当前目录下的所有文件名以及属性，以Vector形式返回 */
     // 如果出现错误，返回None
-    pub fn ls(&self) -> Option<Vec<(String, u8)>> {
+    pub fn ls(&self) -> FatResult<Option<Vec<(String, u8)>>> {
         if !self.is_dir() {
-            return None;
+            return Ok(None);
         }
         let mut list: Vec<(String, u8)> = Vec::new();
         // DEBUG
@@ -548,10 +608,10 @@ impl VFile {
                     &self.fs.read().get_fat(),
                     &self.block_device,
                 )
-            });
+            })??;
             // 检测是否结束或被删除
             if read_sz != DIRENT_SZ || short_ent.is_empty() {
-                return Some(list);
+                return Ok(Some(list));
             }
             if short_ent.is_deleted() {
                 offset += DIRENT_SZ;
@@ -583,9 +643,9 @@ impl VFile {
                             &self.fs.read().get_fat(),
                             &self.block_device,
                         )
-                    });
+                    })??;
                     if read_sz != DIRENT_SZ || long_ent.is_empty() || long_ent.is_deleted() {
-                        return Some(list);
+                        return Ok(Some(list));
                     }
                     
                     // 若无误，把该段名字放在name最前
@@ -601,9 +661,9 @@ impl VFile {
                         &self.fs.read().get_fat(),
                         &self.block_device,
                     )
-                });
+                })??;
                 if read_sz != DIRENT_SZ || long_ent.is_empty() || long_ent.is_deleted() {
-                    return Some(list);
+                    return Ok(Some(list));
                 }
                 
                 list.push((name, long_ent.attribute()));
@@ -620,7 +680,7 @@ impl VFile {
     }
 
 
-    pub fn dirent_info(&self) -> Option<dirent> {
+    pub fn dirent_info(&self) -> FatResult<Option<dirent>> {
         self.read_short_dirent(|sde: &ShortDirEntry| {
             let first_clu = sde.first_cluster();
             let mut bytes:[u8;512] = [0;512];
@@ -638,7 +698,7 @@ impl VFile {
     /* 获取目录中offset处目录项的信息 TODO:之后考虑和stat复用
      * 返回<size, atime, mtime, ctime>
      */
-    pub fn stat(&self) -> kstat {
+    pub fn stat(&self) -> FatResult<kstat> {
         self.read_short_dirent(|sde: &ShortDirEntry| {
             let (_, _, _, _, _, _, ctime) = sde.get_creation_time();
             let (_, _, _, _, _, _, atime) = sde.get_accessed_time();
@@ -650,10 +710,10 @@ impl VFile {
                 let fat = fs_reader.get_fat();
                 let fat_reader = fat.read();
                 let cluster_num =
-                    fat_reader.count_claster_num(first_clu, self.block_device.clone());
+                    fat_reader.count_claster_num(first_clu, self.block_device.clone())?;
                 size = cluster_num * fs_reader.bytes_per_cluster();
             }
-            kstat{
+            Ok(kstat{
                 st_dev: 0,
                 st_ino: first_clu as u64,
                 st_mode: self.attribute as u32,
@@ -673,15 +733,23 @@ impl VFile {
                 st_ctime_sec: ctime as i64,
                 st_ctime_nsec: 0,
                 __unused: [0;2],
-            }
-        })
+            })
+        })?
+    }
+
+    pub fn ls_lite(&self) -> FatResult<Option<Vec<(String, u8)>>> {
+        let Some(entries) = self.ls_entries()? else {
+            return Ok(None);
+        };
+        Ok(Some(entries.into_iter().map(|(name, short_ent)| (name, short_ent.attribute())).collect()))
     }
 
-    pub fn ls_lite(&self) -> Option<Vec<(String, u8)>> {
+    /// 目录中所有文件的名字和短目录项，短目录项中有属性、首簇号和大小，不需要再按名字查找
+    pub fn ls_entries(&self) -> FatResult<Option<Vec<(String, ShortDirEntry)>>> {
         if !self.is_dir() {
-            return None;
+            return Ok(None);
         }
-        let mut list: Vec<(String, u8)> = Vec::new();
+        let mut list: Vec<(String, ShortDirEntry)> = Vec::new();
         let mut long_ent = LongDirEntry::empty();
         let mut offset = 0;
         let mut name = String::new();
@@ -695,13 +763,14 @@ impl VFile {
                     &self.fs.read().get_fat(),
                     &self.block_device,
                 )
-            });
+            })??;
             if read_sz != DIRENT_SZ || long_ent.is_empty() {
-                return Some(list);
+                return Ok(Some(list));
             }
             if long_ent.is_deleted() {
                 offset += DIRENT_SZ;
                 is_long = false;
+                name.clear();
                 continue;
             }
             // 名称拼接
@@ -712,9 +781,9 @@ impl VFile {
                 let short_ent = se_array[0];
                 if is_long {
                     is_long = false;
-                    list.push((name.clone(), short_ent.attribute()));
+                    list.push((name.clone(), short_ent));
                 } else {
-                    list.push((short_ent.get_name_lowercase(), short_ent.attribute()))
+                    list.push((short_ent.get_name_lowercase(), short_ent))
                 }
                 name.clear();
             } else {
@@ -726,7 +795,8 @@ impl VFile {
         }
     }
 
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    /// 读取文件的内容，读取块设备失败时返回错误
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> FatResult<usize> {
         self.read_short_dirent(|short_ent: &ShortDirEntry| {
             short_ent.read_at(
                 offset,
@@ -735,55 +805,73 @@ impl VFile {
                 &self.fs.read().get_fat(),
                 &self.block_device,
             )
-        })
+        })?
     }
 
     /// 写入文件的具体内容
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        self.increase_size((offset + buf.len()) as u32);
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> FatResult<usize> {
+        self.increase_size((offset + buf.len()) as u32)?;
+        let is_dir = self.is_dir();
         // 写入短目录
-        self.modify_short_dirent(|short_ent: &mut ShortDirEntry| {
+        let written = self.modify_short_dirent(|short_ent: &mut ShortDirEntry| -> FatResult<usize> {
             // 写入短目录的数据
-            short_ent.write_at(
+            let written = short_ent.write_at(
                 offset,
                 buf,
                 &self.fs,
                 &self.fs.read().get_fat(),
                 &self.block_device,
-            )
-        })
+            )?;
+            // 文件内容变化时更新修改时间
+            if written > 0 && !is_dir {
+                short_ent.touch();
+            }
+            Ok(written)
+        })??;
+        // 目录自身的写入是在修改目录项，不算文件内容的变化
+        if written > 0 && !self.is_dir() {
+            notify_dir(self.parent, &self.name, DirEvent::Modify);
+        }
+        Ok(written)
+    }
+
+    /// 写回所有被修改的缓存块，再刷新文件所在设备的写缓存，返回后文件的数据不会因为掉电丢失。
+    /// 块缓存由所有文件共享，无法只写回属于这个文件的块
+    pub fn sync(&self) -> FatResult<()> {
+        crate::sync_all()?;
+        Ok(self.block_device.flush()?)
     }
 
-    pub fn clear(&self) {
+    pub fn clear(&self) -> FatResult<()> {
         // 难点:长名目录项也要修改
-        let first_cluster: u32 = self.first_cluster();
+        let first_cluster: u32 = self.first_cluster()?;
         if self.is_dir() || first_cluster == 0 {
-            return;
+            return Ok(());
         }
         for i in 0..self.long_pos_vec.len() {
             self.modify_long_dirent(i, |long_ent: &mut LongDirEntry| {
                 long_ent.clear();
-            });
+            })?;
         }
         self.modify_short_dirent(|short_ent: &mut ShortDirEntry| {
             short_ent.clear();
-        });
+        })?;
         let all_clusters = self
             .fs
             .read()
             .get_fat()
             .read()
-            .get_all_cluster_of(first_cluster, self.block_device.clone());
+            .get_all_cluster_of(first_cluster, self.block_device.clone())?;
         let fs_reader = self.fs.read();
-        fs_reader.dealloc_cluster(all_clusters);
-        fs_reader.cache_write_back();
+        fs_reader.dealloc_cluster(all_clusters)?;
+        fs_reader.cache_write_back()
     }
 
     /// 查找可用目录项，返回offset，簇不够也会返回相应的offset，caller需要及时分配
-    fn find_free_dirent(&self) -> Option<usize> {
+    fn find_free_dirent(&self) -> FatResult<Option<usize>> {
         // 不是目录项，返回空
         if !self.is_dir() {
-            return None;
+            return Ok(None);
         }
         let mut offset = 0;
         loop {
@@ -797,46 +885,47 @@ impl VFile {
                     &self.fs.read().get_fat(),
                     &self.block_device,
                 )
-            });
+            })??;
             // 判断短目录项是否为空
             if tmp_dirent.is_empty() || read_sz == 0 {
-                return Some(offset);
+                return Ok(Some(offset));
             }
             offset += DIRENT_SZ;
         }
     }
 
-    pub fn creation_time(&self) -> (u32, u32, u32, u32, u32, u32, u64) {
+    pub fn creation_time(&self) -> FatResult<(u32, u32, u32, u32, u32, u32, u64)> {
         self.read_short_dirent(|sde: &ShortDirEntry| sde.get_creation_time())
     }
 
-    pub fn accessed_time(&self) -> (u32, u32, u32, u32, u32, u32, u64) {
+    pub fn accessed_time(&self) -> FatResult<(u32, u32, u32, u32, u32, u32, u64)> {
         self.read_short_dirent(|sde: &ShortDirEntry| sde.get_accessed_time())
     }
 
-    pub fn modification_time(&self) -> (u32, u32, u32, u32, u32, u32, u64) {
+    pub fn modification_time(&self) -> FatResult<(u32, u32, u32, u32, u32, u32, u64)> {
         self.read_short_dirent(|sde: &ShortDirEntry| sde.get_modification_time())
     }
 
     /*删除自己*/
-    pub fn remove(&self) -> usize {
-        let first_cluster: u32 = self.first_cluster();
+    pub fn remove(&self) -> FatResult<usize> {
+        let first_cluster: u32 = self.first_cluster()?;
         for i in 0..self.long_pos_vec.len() {
             self.modify_long_dirent(i, |long_ent: &mut LongDirEntry| {
                 long_ent.delete();
-            });
+            })?;
         }
         self.modify_short_dirent(|short_ent: &mut ShortDirEntry| {
             short_ent.delete();
-        });
+        })?;
         let all_clusters = self
             .fs
             .read()
             .get_fat()
             .read()
-            .get_all_cluster_of(first_cluster, self.block_device.clone());
-        self.fs.write().dealloc_cluster(all_clusters.clone());
-        return all_clusters.len();
+            .get_all_cluster_of(first_cluster, self.block_device.clone())?;
+        self.fs.write().dealloc_cluster(all_clusters.clone())?;
+        notify_dir(self.parent, &self.name, DirEvent::Delete);
+        return Ok(all_clusters.len());
     }
 }
 
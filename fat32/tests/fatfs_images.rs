@@ -0,0 +1,249 @@
+//! 在主机上用 fatfs 格式化和修改镜像文件，再通过文件上的 BlockDevice 用 fat32 访问同一个镜像，
+//! 检查 fat32 能正确处理其他实现写出的目录项和 FSInfo
+
+extern crate fat32;
+extern crate fatfs;
+
+use fat32::{BlockDevice, BlockError, FAT32Manager, FatError, RwLock, VFile, BLOCK_SZ};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// 镜像大小，保证 512 字节的簇也有足够的簇数成为 FAT32
+const IMAGE_SIZE: u64 = 40 * 1024 * 1024;
+
+/// 镜像文件上的块设备
+struct FileDisk(Mutex<File>);
+
+impl BlockDevice for FileDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64)).map_err(|_| BlockError::Device)?;
+        file.read_exact(buf).map_err(|_| BlockError::OutOfRange)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64)).map_err(|_| BlockError::Device)?;
+        file.write_all(buf).map_err(|_| BlockError::Device)
+    }
+}
+
+/// 临时的镜像文件，离开作用域时删除
+struct Image(PathBuf);
+
+impl Image {
+    /// 创建一个用 fatfs 格式化为 FAT32、簇大小 512 字节的镜像
+    fn new(name: &str) -> Image {
+        let image = Image::blank(name, IMAGE_SIZE);
+        let options = fatfs::FormatVolumeOptions::new().fat_type(fatfs::FatType::Fat32).bytes_per_cluster(512);
+        fatfs::format_volume(image.file(), options).unwrap();
+        image
+    }
+
+    /// 创建一个 `size` 字节、内容全为 0 的镜像
+    fn blank(name: &str, size: u64) -> Image {
+        let path = std::env::temp_dir().join(format!("fat32-test-{}-{}.img", std::process::id(), name));
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+        file.set_len(size).unwrap();
+        Image(path)
+    }
+
+    fn disk(&self) -> Arc<dyn BlockDevice> {
+        Arc::new(FileDisk(Mutex::new(self.file())))
+    }
+
+    fn file(&self) -> File {
+        OpenOptions::new().read(true).write(true).open(&self.0).unwrap()
+    }
+
+    /// 用 fatfs 打开镜像，`f` 返回后写回
+    fn with_fatfs<T, F: FnOnce(&fatfs::FileSystem<File>) -> T>(&self, f: F) -> T {
+        let fs = fatfs::FileSystem::new(self.file(), fatfs::FsOptions::new()).unwrap();
+        let result = f(&fs);
+        fs.unmount().unwrap();
+        result
+    }
+
+    /// 用 fatfs 在根目录创建文件
+    fn create_with_fatfs(&self, name: &str, data: &[u8]) {
+        self.with_fatfs(|fs| fs.root_dir().create_file(name).unwrap().write_all(data).unwrap());
+    }
+
+    /// fatfs 看到的根目录中的名称
+    fn fatfs_names(&self) -> Vec<String> {
+        self.with_fatfs(|fs| fs.root_dir().iter().map(|entry| entry.unwrap().file_name()).collect())
+    }
+
+    /// 用 fat32 打开镜像，`f` 返回后把缓存写回镜像文件
+    fn with_fat32<T, F: FnOnce(&Arc<RwLock<FAT32Manager>>, &VFile) -> T>(&self, f: F) -> T {
+        let fs = FAT32Manager::open(self.disk()).unwrap();
+        let root = FAT32Manager::get_root_vfile(&fs);
+        let result = f(&fs, &root);
+        fat32::sync_all().unwrap();
+        result
+    }
+
+    /// 根目录中短目录项名为 `short_name`（8+3 个字符，以空格补齐）的目录项在镜像文件中的位置
+    fn short_entry_offset(&self, short_name: &[u8; 11]) -> u64 {
+        let mut data = Vec::new();
+        self.file().read_to_end(&mut data).unwrap();
+        (0..data.len() / 32)
+            .map(|i| i * 32)
+            .find(|&offset| &data[offset..offset + 11] == short_name)
+            .expect("short entry not found") as u64
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.0);
+    }
+}
+
+/// fat32 列出的根目录中的名称
+fn fat32_names(root: &VFile) -> Vec<String> {
+    root.ls_entries().unwrap().unwrap_or_default().into_iter().map(|(name, _)| name).collect()
+}
+
+#[test]
+fn unknown_fsinfo_hints_are_recomputed() {
+    let image = Image::new("fsinfo");
+    let free = image.with_fatfs(|fs| fs.stats().unwrap().free_clusters());
+    // 把 FSInfo 的空闲簇数和下一个空闲簇都改为表示未知的 0xFFFFFFFF
+    let mut file = image.file();
+    let mut boot = [0u8; 512];
+    file.read_exact(&mut boot).unwrap();
+    let fsinfo_sector = u16::from_le_bytes([boot[48], boot[49]]) as u64;
+    file.seek(SeekFrom::Start(fsinfo_sector * 512 + 488)).unwrap();
+    file.write_all(&[0xFF; 8]).unwrap();
+    drop(file);
+
+    image.with_fat32(|fs, root| {
+        assert_eq!(fs.read().free_clusters().unwrap(), free);
+        let file = root.create("data.bin", 0).unwrap().unwrap();
+        assert_eq!(file.write_at(0, &[0x5A; 10000]).unwrap(), 10000);
+    });
+    let data = image.with_fatfs(|fs| {
+        let mut data = Vec::new();
+        fs.root_dir().open_file("data.bin").unwrap().read_to_end(&mut data).unwrap();
+        data
+    });
+    assert_eq!(data, vec![0x5A; 10000]);
+}
+
+#[test]
+fn removing_long_name_removes_its_lfn_entries() {
+    let image = Image::new("long-remove");
+    image.create_with_fatfs("a rather long file name.txt", b"hello");
+    image.with_fat32(|_, root| {
+        let file = root.find_vfile_byname("a rather long file name.txt").unwrap().unwrap();
+        file.remove().unwrap();
+        assert!(fat32_names(root).is_empty());
+    });
+    assert!(image.fatfs_names().is_empty());
+    // 留下的长目录项会被接到下一个文件的名称上
+    image.create_with_fatfs("NEXT.TXT", b"");
+    image.with_fat32(|_, root| assert_eq!(fat32_names(root), ["NEXT.TXT"]));
+}
+
+#[test]
+fn short_name_lookup_removes_matching_lfn_entries() {
+    // fatfs 给小写的短文件名也写一个长目录项
+    let image = Image::new("short-remove");
+    image.create_with_fatfs("a.txt", b"hello");
+    image.with_fat32(|_, root| {
+        let file = root.find_vfile_byname("A.TXT").unwrap().unwrap();
+        file.remove().unwrap();
+    });
+    assert!(image.fatfs_names().is_empty());
+    image.create_with_fatfs("B.TXT", b"");
+    image.with_fat32(|_, root| assert_eq!(fat32_names(root), ["B.TXT"]));
+}
+
+#[test]
+fn deleted_entry_drops_pending_long_name() {
+    let image = Image::new("orphan-lfn");
+    image.create_with_fatfs("a rather long file name.txt", b"");
+    image.create_with_fatfs("SHORT.TXT", b"");
+    // 只删除长文件名的短目录项，它前面的长目录项成为孤立的项
+    let offset = image.short_entry_offset(b"ARATHE~1TXT");
+    let mut file = image.file();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(&[0xE5]).unwrap();
+    drop(file);
+    image.with_fat32(|_, root| assert_eq!(fat32_names(root), ["SHORT.TXT"]));
+}
+
+#[test]
+fn lowercase_short_names_keep_their_case() {
+    let image = Image::new("lowercase");
+    image.with_fat32(|_, root| {
+        root.create("a.txt", 0).unwrap().unwrap();
+    });
+    assert_eq!(image.fatfs_names(), ["a.txt"]);
+}
+
+#[test]
+fn lfn_checksum_wraps() {
+    // 这个短文件名的校验和计算会超过 255，调试构建中没有按模 256 累加时会溢出
+    let image = Image::new("checksum");
+    image.create_with_fatfs("~~~~~~~~ long name.~~~", b"x");
+    image.with_fat32(|_, root| {
+        assert_eq!(fat32_names(root), ["~~~~~~~~ long name.~~~"]);
+        let file = root.find_vfile_byname("~~~~~~~~ long name.~~~").unwrap().unwrap();
+        assert_eq!(file.get_size().unwrap(), 1);
+    });
+}
+
+#[test]
+fn formatted_image_opens_in_fatfs() {
+    let image = Image::blank("format", IMAGE_SIZE);
+    FAT32Manager::format(image.disk(), (IMAGE_SIZE / 512) as u32).unwrap();
+    let (fat_type, free) = image.with_fatfs(|fs| {
+        assert!(fs.root_dir().iter().next().is_none());
+        let stats = fs.stats().unwrap();
+        (fs.fat_type(), stats.free_clusters())
+    });
+    assert_eq!(fat_type, fatfs::FatType::Fat32);
+    image.with_fat32(|fs, root| {
+        assert_eq!(fs.read().free_clusters().unwrap(), free);
+        let file = root.create("data.bin", 0).unwrap().unwrap();
+        assert_eq!(file.write_at(0, &[0x5A; 10000]).unwrap(), 10000);
+    });
+    let data = image.with_fatfs(|fs| {
+        assert_eq!(fs.stats().unwrap().free_clusters(), free - 20);
+        let mut data = Vec::new();
+        fs.root_dir().open_file("data.bin").unwrap().read_to_end(&mut data).unwrap();
+        data
+    });
+    assert_eq!(data, vec![0x5A; 10000]);
+}
+
+#[test]
+fn small_formatted_image_is_usable() {
+    // 内核中测试用的内存磁盘只有几 MB，簇数达不到 FAT32 的下限，只要求 fat32 自己能使用
+    let image = Image::blank("small-format", 2 * 1024 * 1024);
+    FAT32Manager::format(image.disk(), 4096).unwrap();
+    image.with_fat32(|fs, root| {
+        let free = fs.read().free_clusters().unwrap();
+        assert_eq!(free, fs.read().total_clusters() - 1);
+        let dir = root.create("dir", fat32::ATTRIBUTE_DIRECTORY).unwrap().unwrap();
+        let file = dir.create("file.txt", 0).unwrap().unwrap();
+        assert_eq!(file.write_at(0, b"hello").unwrap(), 5);
+    });
+    image.with_fat32(|_, root| {
+        let file = root.find_vfile_bypath(vec!["dir", "file.txt"]).unwrap().unwrap();
+        let mut buf = [0u8; 5];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    });
+}
+
+#[test]
+fn non_fat_device_is_rejected() {
+    // 全零的设备没有引导扇区和 FSInfo 签名，打开时返回错误而不是 panic
+    let image = Image::blank("not-fat", 2 * 1024 * 1024);
+    assert_eq!(FAT32Manager::open(image.disk()).err(), Some(FatError::InvalidFs));
+}
@@ -0,0 +1,185 @@
+//! 镜像中目录的抽象
+//!
+//! 写入、取回、列出和删除都只通过 [`ImageDir`] 访问镜像，既可以用外部的 fatfs 实现，
+//! 也可以用本仓库内核使用的 fat32 实现（见 `native` 模块），比较两者在同一个镜像上的行为。
+
+use super::native::NativeImage;
+use super::time::{from_fat, to_fat, unix_secs};
+use fatfs::FileAttributes;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+/// 写入文件时每次从主机文件读取的字节数
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 镜像数据区的空间，都以字节计
+pub struct Space {
+    pub total: u64,
+    pub free: u64,
+    pub cluster_size: u64,
+}
+
+/// 目录中的一项
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    /// 文件大小，目录为 0
+    pub size: u64,
+    pub read_only: bool,
+    /// 修改时间（Unix 时间，秒）
+    pub modified: i64,
+}
+
+/// 镜像中的一个目录。名称都是目录中一项的名称，不含 `/`
+pub trait ImageDir: Sized {
+    /// 目录中除 `.` 和 `..` 以外的所有项，按目录中的顺序
+    fn entries(&self) -> io::Result<Vec<Entry>>;
+    /// 打开子目录，没有这个子目录（包括同名的项是文件）时返回 [`io::ErrorKind::NotFound`]
+    fn open_dir(&self, name: &str) -> io::Result<Self>;
+    /// 创建子目录，已经存在时打开它
+    fn create_dir(&self, name: &str) -> io::Result<Self>;
+    /// 打开文件，按块读取它的内容
+    fn file_reader(&self, name: &str) -> io::Result<Box<dyn Read + '_>>;
+    /// 创建文件或替换已有文件的内容，内容从 `src` 按块读取，每写入一块都用写入的字节数调用 `written`。
+    /// 最后设置创建和修改时间（Unix 时间，秒）以及只读属性
+    fn write_file(
+        &self,
+        name: &str,
+        src: &mut dyn Read,
+        modified: i64,
+        read_only: bool,
+        written: &mut dyn FnMut(usize),
+    ) -> io::Result<()>;
+    /// 删除文件或空目录
+    fn remove(&self, name: &str) -> io::Result<()>;
+}
+
+/// 从 `root` 开始逐级打开以 `/` 分隔的目录路径 `path`
+pub fn open_path<D: ImageDir>(root: &D, path: &str) -> io::Result<D> {
+    let mut components = path.split('/').filter(|c| !c.is_empty());
+    let mut dir = root.open_dir(components.next().unwrap_or(""))?;
+    for name in components {
+        dir = dir.open_dir(name)?;
+    }
+    Ok(dir)
+}
+
+/// 把 `src` 按块交给 `sink` 写入，直到读完
+pub fn copy_chunks<F>(src: &mut dyn Read, mut sink: F, written: &mut dyn FnMut(usize)) -> io::Result<()>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let len = match src.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        sink(&buf[..len])?;
+        written(len);
+    }
+}
+
+/// 用 fatfs 打开的目录。
+///
+/// fatfs 0.3.6 不能修改目录项的属性，写入文件时只读属性先记录在 `attributes` 中，
+/// 卸载 fatfs 后再由 [`apply_attributes`] 用 fat32 写入镜像
+pub struct FatfsDir<'a> {
+    dir: fatfs::Dir<'a, File>,
+    /// 目录在镜像中的路径，根目录为空，其余以 `/` 开头
+    path: String,
+    attributes: &'a RefCell<Vec<(String, bool)>>,
+}
+
+impl<'a> FatfsDir<'a> {
+    /// 镜像的根目录，写入的文件的只读属性记录到 `attributes` 中
+    pub fn root(fs: &'a fatfs::FileSystem<File>, attributes: &'a RefCell<Vec<(String, bool)>>) -> Self {
+        FatfsDir { dir: fs.root_dir(), path: String::new(), attributes }
+    }
+
+    fn child(&self, dir: fatfs::Dir<'a, File>, name: &str) -> Self {
+        FatfsDir { dir, path: format!("{}/{}", self.path, name), attributes: self.attributes }
+    }
+}
+
+/// 用 fat32 把 fatfs 写入的文件的只读属性 `attributes`（路径和是否只读）写入镜像文件 `img`
+pub fn apply_attributes(img: File, attributes: &[(String, bool)]) -> io::Result<()> {
+    if attributes.is_empty() {
+        return Ok(());
+    }
+    let image = NativeImage::open(img)?;
+    image.set_read_only(attributes)?;
+    image.sync()
+}
+
+impl<'a> ImageDir for FatfsDir<'a> {
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for entry in self.dir.iter() {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            entries.push(Entry {
+                name,
+                is_dir: entry.is_dir(),
+                size: if entry.is_dir() { 0 } else { entry.len() },
+                read_only: entry.attributes().contains(FileAttributes::READ_ONLY),
+                modified: unix_secs(from_fat(entry.modified())),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Self> {
+        match self.dir.open_dir(name) {
+            // fatfs 对同名的文件报告 ErrorKind::Other，和 fat32 的实现一样当作没有这个目录
+            Err(err) if err.kind() == io::ErrorKind::Other && self.dir.open_file(name).is_ok() => {
+                Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", name)))
+            }
+            result => Ok(self.child(result?, name)),
+        }
+    }
+
+    fn create_dir(&self, name: &str) -> io::Result<Self> {
+        Ok(self.child(self.dir.create_dir(name)?, name))
+    }
+
+    fn file_reader(&self, name: &str) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(self.dir.open_file(name)?))
+    }
+
+    fn write_file(
+        &self,
+        name: &str,
+        src: &mut dyn Read,
+        modified: i64,
+        read_only: bool,
+        written: &mut dyn FnMut(usize),
+    ) -> io::Result<()> {
+        // 覆盖的文件可能是只读的，写入后要按 `read_only` 重新设置
+        let existed = self.dir.open_file(name).is_ok();
+        // create a file in the image, dropping the old content if it exists
+        let mut file = self.dir.create_file(name)?;
+        file.truncate()?;
+        copy_chunks(src, |chunk| file.write_all(chunk), written)?;
+        // 时间戳要在写入之后设置，写入会把修改时间更新为当前时间
+        #[allow(deprecated)]
+        {
+            file.set_created(to_fat(modified));
+            file.set_modified(to_fat(modified));
+        }
+        if read_only || existed {
+            self.attributes.borrow_mut().push((format!("{}/{}", self.path, name), read_only));
+        }
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        self.dir.remove(name)
+    }
+}
@@ -0,0 +1,85 @@
+//! 创建并格式化新的镜像
+
+use fatfs::{FatType, FormatVolumeOptions};
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+
+/// FAT32 至少要有的簇数，簇更少的卷会被识别为 FAT12/FAT16
+const FAT32_MIN_CLUSTERS: u64 = 65525;
+
+/// 新镜像的参数
+pub struct ImageOptions {
+    /// 镜像大小（字节）
+    pub size: u64,
+    /// 簇大小（字节），`None` 时由 fatfs 按镜像大小选择
+    pub cluster_size: Option<u32>,
+    /// 卷标
+    pub label: Option<String>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// 解析 `64M`、`1G`、`512K` 或字节数形式的大小
+pub fn parse_size(text: &str) -> io::Result<u64> {
+    let (digits, unit) = match text.char_indices().last() {
+        Some((pos, c)) if c.is_ascii_alphabetic() => (&text[..pos], c.to_ascii_uppercase()),
+        _ => (text, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return Err(invalid(format!("invalid size {:?}", text))),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| invalid(format!("invalid size {:?}", text)))
+}
+
+/// 卷标：最多 11 个 ASCII 字符，转换为大写并以空格补齐
+fn volume_label(label: &str) -> io::Result<[u8; 11]> {
+    if label.len() > 11 || !label.is_ascii() {
+        return Err(invalid(format!("volume label {:?} is not at most 11 ASCII characters", label)));
+    }
+    let mut bytes = [b' '; 11];
+    bytes[..label.len()].copy_from_slice(label.to_ascii_uppercase().as_bytes());
+    Ok(bytes)
+}
+
+/// 在 `path` 创建 `options.size` 字节的镜像（主机支持时是稀疏文件）并格式化为 FAT32。
+/// `path` 已经存在且不为空时只有 `force` 才重新格式化，否则报错
+pub fn create_image(path: &Path, options: &ImageOptions, force: bool) -> io::Result<()> {
+    let mut format = FormatVolumeOptions::new().fat_type(FatType::Fat32);
+    if let Some(cluster_size) = options.cluster_size {
+        if cluster_size < 512 || !cluster_size.is_power_of_two() {
+            return Err(invalid(format!("cluster size {} is not a power of two of at least 512", cluster_size)));
+        }
+        format = format.bytes_per_cluster(cluster_size);
+    }
+    let min_size = FAT32_MIN_CLUSTERS * options.cluster_size.unwrap_or(512) as u64;
+    if options.size <= min_size {
+        return Err(invalid(format!("{} bytes is too small for FAT32, need more than {}", options.size, min_size)));
+    }
+    if let Some(ref label) = options.label {
+        format = format.volume_label(volume_label(label)?);
+    }
+    let non_empty = path.metadata().map(|metadata| metadata.len() > 0).unwrap_or(false);
+    if non_empty && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists, use --force to format it again", path.display()),
+        ));
+    }
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+    // 只改变文件长度而不写入数据，主机文件系统支持时不占用磁盘空间
+    file.set_len(options.size)?;
+    fatfs::format_volume(file, format)?;
+    println!("已将 {} 格式化为 FAT32，{} 字节", path.display(), options.size);
+    Ok(())
+}
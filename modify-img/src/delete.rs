@@ -0,0 +1,88 @@
+//! 删除镜像中的文件和目录
+
+use super::backend::{open_path, ImageDir};
+use super::glob::glob_match;
+use std::io;
+
+/// 目录 `dir` 中除 `.` 和 `..` 以外的项的名称和是否是目录
+fn children<D: ImageDir>(dir: &D) -> io::Result<Vec<(String, bool)>> {
+    Ok(dir.entries()?.into_iter().map(|entry| (entry.name, entry.is_dir)).collect())
+}
+
+/// 在镜像目录 `dir` 下逐级匹配路径分量 `components`，把匹配的路径（相对根目录、不带开头的 `/`）和是否是目录放进 `out`
+fn expand<D: ImageDir>(dir: &D, rel: &str, components: &[&str], out: &mut Vec<(String, bool)>) -> io::Result<()> {
+    let (first, rest) = components.split_first().unwrap();
+    for (name, is_dir) in children(dir)? {
+        if !glob_match(first, &name) {
+            continue;
+        }
+        let path = if rel.is_empty() { name.clone() } else { format!("{}/{}", rel, name) };
+        if rest.is_empty() {
+            out.push((path, is_dir));
+        } else if is_dir {
+            expand(&dir.open_dir(&name)?, &path, rest, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// 删除镜像目录 `dir` 中的 `name` 及其下的所有内容，`rel` 是 `dir` 在镜像中的路径（根目录为空串）
+pub fn remove_tree<D: ImageDir>(dir: &D, rel: &str, name: &str, is_dir: bool) -> io::Result<()> {
+    let path = format!("{}/{}", rel, name);
+    if is_dir {
+        let sub_dir = dir.open_dir(name)?;
+        for (child, child_is_dir) in children(&sub_dir)? {
+            remove_tree(&sub_dir, &path, &child, child_is_dir)?;
+        }
+    }
+    dir.remove(name)?;
+    println!("deleted {}", path);
+    Ok(())
+}
+
+/// 删除镜像中匹配 `patterns` 的文件和目录。每个模式是以 `/` 分隔的路径，分量中可以使用 `*` 和 `?` 通配符，
+/// 通配符不跨越 `/`。非空目录只有 `recursive` 时才删除。
+/// 先找出所有要删除的项，有模式没有匹配任何项或者要删除非空目录却没有 `recursive` 时什么都不删除
+pub fn delete<D: ImageDir>(root: &D, patterns: &[&str], recursive: bool) -> io::Result<()> {
+    let mut matched = Vec::new();
+    for pattern in patterns {
+        let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+        let count = matched.len();
+        if !components.is_empty() {
+            expand(root, "", &components, &mut matched)?;
+        }
+        if matched.len() == count {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not match anything in the image", pattern),
+            ));
+        }
+    }
+    matched.sort();
+    matched.dedup();
+    // 祖先目录也被删除时不必单独删除
+    let mut targets: Vec<(String, bool)> = Vec::new();
+    for (path, is_dir) in matched {
+        let covered = targets.iter().any(|(dir, _)| path.starts_with(&format!("{}/", dir)));
+        if !covered {
+            targets.push((path, is_dir));
+        }
+    }
+    if !recursive {
+        for (path, is_dir) in &targets {
+            if *is_dir && !children(&open_path(root, path)?)?.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("/{} is not empty, use --recursive to delete it", path),
+                ));
+            }
+        }
+    }
+    for (path, is_dir) in &targets {
+        match path.rfind('/') {
+            Some(i) => remove_tree(&open_path(root, &path[..i])?, &format!("/{}", &path[..i]), &path[i + 1..], *is_dir)?,
+            None => remove_tree(root, "", path, *is_dir)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! 把镜像中的文件取回主机
+//!
+//! 取回的文件的修改时间和只读属性与镜像中的一致。
+
+use super::backend::{copy_chunks, open_path, Entry, ImageDir};
+use super::time::{from_fat, to_fat};
+use super::Stats;
+use fatfs::{Date, DateTime, Time};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// 取回哪些文件
+pub struct Filter {
+    /// 只取回修改时间晚于这个时间的文件
+    pub newer_than: Option<DateTime>,
+    /// 覆盖主机上已经存在的文件
+    pub force: bool,
+}
+
+impl Filter {
+    fn wants(&self, modified: i64) -> bool {
+        self.newer_than.map_or(true, |since| sort_key(to_fat(modified)) > sort_key(since))
+    }
+}
+
+/// 按时间先后比较 `DateTime` 用的键
+fn sort_key(t: DateTime) -> (u16, u16, u16, u16, u16, u16, u16) {
+    (t.date.year, t.date.month, t.date.day, t.time.hour, t.time.min, t.time.sec, t.time.millis)
+}
+
+/// 解析 `YYYY-MM-DD` 或 `YYYY-MM-DD HH:MM:SS`（日期和时间之间也可以是 `T`）形式的时间。
+/// 镜像中的时间按 UTC 记录，这里同样按 UTC 理解
+pub fn parse_time(text: &str) -> Option<DateTime> {
+    let (date, time) = match text.find(|c| c == ' ' || c == 'T') {
+        Some(pos) => (&text[..pos], Some(&text[pos + 1..])),
+        None => (text, None),
+    };
+    let date = parse_fields(date, '-')?;
+    let time = match time {
+        Some(time) => parse_fields(time, ':')?,
+        None => [0; 3],
+    };
+    let valid = (1980..=2107).contains(&date[0])
+        && (1..=12).contains(&date[1])
+        && (1..=31).contains(&date[2])
+        && time[0] < 24
+        && time[1] < 60
+        && time[2] < 60;
+    if !valid {
+        return None;
+    }
+    Some(DateTime {
+        date: Date { year: date[0], month: date[1], day: date[2] },
+        time: Time { hour: time[0], min: time[1], sec: time[2], millis: 0 },
+    })
+}
+
+/// 解析以 `sep` 分隔的三个数
+fn parse_fields(text: &str, sep: char) -> Option<[u16; 3]> {
+    let mut fields = text.split(sep).map(|field| field.parse().ok());
+    let parsed = [fields.next()??, fields.next()??, fields.next()??];
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(parsed)
+}
+
+/// 把镜像目录 `dir` 下的内容递归地写到主机目录 `out`，`rel` 是 `dir` 在镜像中的路径（根目录为空串）
+pub fn extract_dir<D: ImageDir>(dir: &D, out: &Path, rel: &str, filter: &Filter, stats: &mut Stats) -> io::Result<()> {
+    create_dir_all(out)?;
+    for entry in dir.entries()? {
+        let path = format!("{}/{}", rel, entry.name);
+        if entry.is_dir {
+            println!("{:>10}  {}/", "<dir>", path);
+            stats.dirs += 1;
+            extract_dir(&dir.open_dir(&entry.name)?, &out.join(&entry.name), &path, filter, stats)?;
+        } else if filter.wants(entry.modified) {
+            extract_file(dir, &entry, &out.join(&entry.name), &path, filter.force, stats)?;
+        }
+    }
+    Ok(())
+}
+
+/// 取回镜像中路径为 `path`（相对根目录，不带开头的 `/`）的文件，放在主机目录 `out` 下
+pub fn extract_one<D: ImageDir>(root: &D, path: &str, out: &Path, filter: &Filter, stats: &mut Stats) -> io::Result<()> {
+    let (parent, name) = match path.rfind('/') {
+        Some(pos) => (Some(open_path(root, &path[..pos])?), &path[pos + 1..]),
+        None => (None, path),
+    };
+    let parent = parent.as_ref().unwrap_or(root);
+    for entry in parent.entries()? {
+        if !entry.is_dir && entry.name.eq_ignore_ascii_case(name) {
+            create_dir_all(out)?;
+            if filter.wants(entry.modified) {
+                extract_file(parent, &entry, &out.join(&entry.name), &format!("/{}", path), filter.force, stats)?;
+            }
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("/{} is not in the image", path)))
+}
+
+/// 把镜像目录 `dir` 中的文件 `entry` 写到主机路径 `host_path`，文件已经存在时只有 `force` 才覆盖。`path` 是文件在镜像中的路径
+fn extract_file<D: ImageDir>(
+    dir: &D,
+    entry: &Entry,
+    host_path: &Path,
+    path: &str,
+    force: bool,
+    stats: &mut Stats,
+) -> io::Result<()> {
+    let mut reader = dir.file_reader(&entry.name)?;
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+        // 之前取回的只读文件也要能覆盖
+        if let Ok(metadata) = host_path.metadata() {
+            let mut permissions = metadata.permissions();
+            if permissions.readonly() {
+                // 只给属主加上写权限，不放开其他用户
+                permissions.set_mode(permissions.mode() | 0o200);
+                std::fs::set_permissions(host_path, permissions)?;
+            }
+        }
+    } else {
+        options.create_new(true);
+    }
+    let mut file: File = options.open(host_path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists, use --force to overwrite it", host_path.display()),
+        ),
+        _ => err,
+    })?;
+    // 按块复制，不把整个文件读入内存
+    let mut size = 0;
+    copy_chunks(&mut *reader, |chunk| file.write_all(chunk), &mut |len| size += len)?;
+    file.set_modified(from_fat(to_fat(entry.modified)))?;
+    if entry.read_only {
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(true);
+        file.set_permissions(permissions)?;
+    }
+    println!("{:>10}  {}", size, path);
+    stats.files += 1;
+    stats.bytes += size as u64;
+    Ok(())
+}
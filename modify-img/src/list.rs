@@ -0,0 +1,110 @@
+//! 列出镜像中的目录树
+
+use super::backend::{Entry, ImageDir, Space};
+use super::time::to_fat;
+use std::io;
+
+/// 镜像中的一个文件或目录
+struct Listed {
+    /// 在镜像中的绝对路径
+    path: String,
+    /// 树形显示时名称前面的连线
+    prefix: String,
+    entry: Entry,
+}
+
+/// 递归地收集镜像目录 `dir` 下的内容，`rel` 是 `dir` 在镜像中的路径（根目录为空串），
+/// `indent` 是 `dir` 的子项在树形显示中的缩进
+fn collect<D: ImageDir>(dir: &D, rel: &str, indent: &str, out: &mut Vec<Listed>) -> io::Result<()> {
+    let entries = dir.entries()?;
+    let count = entries.len();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let path = format!("{}/{}", rel, entry.name);
+        let sub_dir = if entry.is_dir { Some(dir.open_dir(&entry.name)?) } else { None };
+        out.push(Listed {
+            path: path.clone(),
+            prefix: format!("{}{}", indent, if last { "└── " } else { "├── " }),
+            entry,
+        });
+        if let Some(sub_dir) = sub_dir {
+            let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            collect(&sub_dir, &path, &indent, out)?;
+        }
+    }
+    Ok(())
+}
+
+fn format_time(secs: i64) -> String {
+    let t = to_fat(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        t.date.year, t.date.month, t.date.day, t.time.hour, t.time.min, t.time.sec
+    )
+}
+
+/// JSON 字符串字面量
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// 打印镜像的目录树和空间使用情况，`json` 时以 JSON 输出，便于脚本检查
+pub fn list<D: ImageDir>(root: &D, space: &Space, json: bool) -> io::Result<()> {
+    let (total, free) = (space.total, space.free);
+    let mut listed = Vec::new();
+    collect(root, "", "", &mut listed)?;
+    if json {
+        println!("{{");
+        println!("  \"entries\": [");
+        for (i, item) in listed.iter().enumerate() {
+            println!(
+                "    {{\"path\": {}, \"type\": \"{}\", \"size\": {}, \"read_only\": {}, \"modified\": \"{}\"}}{}",
+                json_string(&item.path),
+                if item.entry.is_dir { "dir" } else { "file" },
+                item.entry.size,
+                item.entry.read_only,
+                format_time(item.entry.modified),
+                if i + 1 == listed.len() { "" } else { "," }
+            );
+        }
+        println!("  ],");
+        println!("  \"total_bytes\": {},", total);
+        println!("  \"used_bytes\": {},", total - free);
+        println!("  \"free_bytes\": {}", free);
+        println!("}}");
+    } else {
+        println!("/");
+        for item in &listed {
+            println!(
+                "{}{}  {}  {:>10}  {}{}{}",
+                if item.entry.is_dir { 'd' } else { '-' },
+                if item.entry.read_only { 'r' } else { '-' },
+                format_time(item.entry.modified),
+                if item.entry.is_dir { String::from("-") } else { item.entry.size.to_string() },
+                item.prefix,
+                item.entry.name,
+                if item.entry.is_dir { "/" } else { "" }
+            );
+        }
+        let files = listed.iter().filter(|item| !item.entry.is_dir).count();
+        println!(
+            "{} 个文件、{} 个目录；已用 {} 字节，空闲 {} 字节，共 {} 字节",
+            files,
+            listed.len() - files,
+            total - free,
+            free,
+            total
+        );
+    }
+    Ok(())
+}
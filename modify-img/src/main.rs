@@ -1,9 +1,40 @@
-// 本文件是为了在本地测试时向文件镜像中写入文件
+// 本文件是为了在本地测试时创建文件镜像、向镜像中写入或删除文件、查看和校验镜像中的文件，以及把操作系统写入镜像的文件取回主机
 extern crate fatfs;
+extern crate fat32;
 extern crate clap;
-use clap::{App, Arg};
-use std::fs::{read_dir, File};
-use std::io::{Read, Write};
+
+mod backend;
+mod create;
+mod delete;
+mod extract;
+mod glob;
+mod list;
+mod native;
+mod pack;
+mod progress;
+mod time;
+mod verify;
+
+use backend::{FatfsDir, ImageDir, Space};
+use clap::{App, Arg, ArgMatches};
+use std::cell::RefCell;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+/// 写入或取回的文件和目录的统计
+#[derive(Default)]
+struct Stats {
+    /// 写入或取回的文件数
+    files: usize,
+    dirs: usize,
+    bytes: u64,
+    /// 写入的文件中覆盖镜像中已有文件的个数
+    updated: usize,
+    /// 没有改变因而没有写入的文件数
+    skipped: usize,
+}
+
 fn main() -> std::io::Result<()>{
     // 解析命令行参数
     let matches = App::new("EasyFileSystem packer")
@@ -12,6 +43,7 @@ fn main() -> std::io::Result<()>{
                 .short("s")
                 .long("source")
                 .takes_value(true)
+                .required_unless_one(&["extract", "list", "create", "verify", "delete"])
                 .help("Executable source dir(with backslash)"),
         )
         .arg(
@@ -21,36 +53,288 @@ fn main() -> std::io::Result<()>{
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Overwrite files that already exist in the image, or on the host when extracting"),
+        )
+        .arg(
+            Arg::with_name("create")
+                .long("create")
+                .requires("size")
+                .conflicts_with_all(&["extract", "list"])
+                .help("Create the image and format it as FAT32 before packing"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .takes_value(true)
+                .requires("create")
+                .help("Size of the new image, e.g. 64M"),
+        )
+        .arg(
+            Arg::with_name("cluster-size")
+                .long("cluster-size")
+                .takes_value(true)
+                .requires("create")
+                .help("Cluster size of the new image in bytes, chosen from the size by default"),
+        )
+        .arg(
+            Arg::with_name("label")
+                .long("label")
+                .takes_value(true)
+                .requires("create")
+                .help("Volume label of the new image"),
+        )
+        .arg(
+            Arg::with_name("touch")
+                .long("touch")
+                .takes_value(true)
+                .value_name("RFC3339")
+                .requires("source")
+                .help("Give every packed file this timestamp instead of its host mtime, e.g. 2024-01-01T00:00:00Z"),
+        )
+        .arg(
+            Arg::with_name("only-changed")
+                .long("only-changed")
+                .requires("source")
+                .help("Skip files whose size and mtime match the image and overwrite the ones that changed"),
+        )
+        .arg(
+            Arg::with_name("checksum")
+                .long("checksum")
+                .requires("only-changed")
+                .help("Compare file contents instead of size and mtime"),
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .requires("source")
+                .help("Delete files and directories that are not in the source dir from the image"),
+        )
+        .arg(
+            Arg::with_name("extract")
+                .short("x")
+                .long("extract")
+                .takes_value(true)
+                .value_name("OUT_DIR")
+                .conflicts_with("source")
+                .help("Copy the files in the image out to OUT_DIR instead of packing"),
+        )
+        .arg(
+            Arg::with_name("list")
+                .short("l")
+                .long("list")
+                .conflicts_with_all(&["source", "extract"])
+                .help("Print the directory tree of the image with sizes and timestamps"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .requires("list")
+                .help("Print the listing as JSON"),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .takes_value(true)
+                .value_name("DIR")
+                .conflicts_with_all(&["source", "extract", "list", "create"])
+                .help("Compare the files in the image with DIR and exit with 1 on any difference"),
+        )
+        .arg(
+            Arg::with_name("ignore")
+                .long("ignore")
+                .takes_value(true)
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("verify")
+                .help("Leave out matching files when verifying, e.g. '*.log' or '/results/*'"),
+        )
+        .arg(
+            Arg::with_name("delete")
+                .long("delete")
+                .takes_value(true)
+                .value_name("PATH")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["extract", "list", "verify"])
+                .help("Delete matching files and empty directories before packing, e.g. '/riscv64/old_*'"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .requires("delete")
+                .help("Let --delete remove directories that are not empty"),
+        )
+        .arg(
+            Arg::with_name("path")
+                .long("path")
+                .takes_value(true)
+                .requires("extract")
+                .help("Only extract this file or directory of the image, e.g. /results"),
+        )
+        .arg(
+            Arg::with_name("newer-than")
+                .long("newer-than")
+                .takes_value(true)
+                .value_name("TIME")
+                .requires("extract")
+                .help("Only extract files modified after TIME (YYYY-MM-DD[ HH:MM:SS], UTC)"),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .takes_value(true)
+                .possible_values(&["fatfs", "native"])
+                .default_value("fatfs")
+                .help("FAT32 implementation used to access the image; native is the kernel's fat32 crate"),
+        )
         .get_matches();
-    let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
-    println!("src_path = {}\ntarget_path = {}", src_path, target_path);
-    let img = std::fs::OpenOptions::new().read(true).write(true)
-        .open(format!("{}{}", target_path, "sdcard.img"));
+    let force = matches.is_present("force");
+    let img_path = format!("{}{}", target_path, "sdcard.img");
+    if matches.is_present("create") {
+        let cluster_size = match matches.value_of("cluster-size") {
+            Some(text) => Some(create::parse_size(text)? as u32),
+            None => None,
+        };
+        let options = create::ImageOptions {
+            size: create::parse_size(matches.value_of("size").unwrap())?,
+            cluster_size,
+            label: matches.value_of("label").map(String::from),
+        };
+        create::create_image(Path::new(&img_path), &options, force)?;
+    }
+    let img = std::fs::OpenOptions::new().read(true).write(true).open(&img_path);
     let img_file = img?;
-    let fs = fatfs::FileSystem::new(img_file, fatfs::FsOptions::new())?;
-    // 获取根目录
-    let root_dir = fs.root_dir();
-    let apps: Vec<_> = read_dir(src_path)
-    .unwrap()
-    .into_iter()
-    .map(|dir_entry| {
-        let name_with_ext = dir_entry.unwrap().file_name().into_string().unwrap();           
-        name_with_ext
-    })
-    .collect();
-    // 遍历文件夹下的所有文件
-    for app in apps {
-        // load app data from host file system
-        println!("{:?}", app);
-        let mut host_file = File::open(format!("{}{}", src_path, app)).unwrap();
-        let mut all_data: Vec<u8> = Vec::new();
-        host_file.read_to_end(&mut all_data).unwrap();
-        // create a file in easy-fs
-        let mut file = root_dir.create_file(app.as_str()).expect("Failed to create file");
-        // write data to easy-fs
-        file.write_all(all_data.as_slice()).expect("Failed to write to file");
+    if matches.value_of("backend") == Some("native") {
+        if matches.is_present("create") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--create is not supported with --backend native"));
+        }
+        let image = native::NativeImage::open(img_file)?;
+        run(&matches, &image.root_dir(), &|| image.space())?;
+        return image.sync();
+    }
+    // fatfs 写入的只读属性在卸载后用 fat32 写入镜像
+    let attributes = RefCell::new(Vec::new());
+    {
+        let fs = fatfs::FileSystem::new(img_file.try_clone()?, fatfs::FsOptions::new())?;
+        // 获取根目录
+        let root_dir = FatfsDir::root(&fs, &attributes);
+        run(&matches, &root_dir, &|| {
+            let stats = fs.stats()?;
+            let cluster_size = stats.cluster_size() as u64;
+            Ok(Space {
+                total: stats.total_clusters() as u64 * cluster_size,
+                free: stats.free_clusters() as u64 * cluster_size,
+                cluster_size,
+            })
+        })?;
+        drop(root_dir);
+        fs.unmount()?;
+    }
+    backend::apply_attributes(img_file, &attributes.into_inner())
+}
+
+/// 在镜像根目录 `root_dir` 上执行校验、删除、列出、取回或写入，`space` 返回镜像当前的空间
+fn run<D: ImageDir>(matches: &ArgMatches, root_dir: &D, space: &dyn Fn() -> io::Result<Space>) -> io::Result<()> {
+    let target_path = matches.value_of("target").unwrap();
+    let force = matches.is_present("force");
+    let mut stats = Stats::default();
+    // --verify 和 --delete 互斥，校验不需要先删除
+    if let Some(host_path) = matches.value_of("verify") {
+        let ignore = verify::Ignore(matches.values_of("ignore").map_or(Vec::new(), |globs| globs.map(String::from).collect()));
+        if !verify::verify(root_dir, Path::new(host_path), &ignore)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(patterns) = matches.values_of("delete") {
+        delete::delete(root_dir, &patterns.collect::<Vec<_>>(), matches.is_present("recursive"))?;
+    }
+    if matches.is_present("list") {
+        list::list(root_dir, &space()?, matches.is_present("json"))?;
+    } else if let Some(out_path) = matches.value_of("extract") {
+        println!("target_path = {}\nout_path = {}", target_path, out_path);
+        let newer_than = match matches.value_of("newer-than") {
+            Some(text) => Some(extract::parse_time(text).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("invalid time {:?}", text))
+            })?),
+            None => None,
+        };
+        let filter = extract::Filter { newer_than, force };
+        let path = matches.value_of("path").unwrap_or("/").trim_matches('/');
+        let out = Path::new(out_path);
+        if path.is_empty() {
+            extract::extract_dir(root_dir, out, "", &filter, &mut stats)?;
+        } else {
+            match backend::open_path(root_dir, path) {
+                Ok(dir) => extract::extract_dir(&dir, out, &format!("/{}", path), &filter, &mut stats)?,
+                // 不是目录时按单个文件取回，路径不存在时由 extract_one 报告
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    extract::extract_one(root_dir, path, out, &filter, &mut stats)?
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        println!(
+            "文件取回成功！共 {} 个文件、{} 个目录，{} 字节",
+            stats.files, stats.dirs, stats.bytes
+        );
+    } else if let Some(src_path) = matches.value_of("source") {
+        println!("src_path = {}\ntarget_path = {}", src_path, target_path);
+        let touch = match matches.value_of("touch") {
+            Some(text) => Some(time::parse_rfc3339(text).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("invalid RFC 3339 time {:?}", text))
+            })?),
+            None => None,
+        };
+        let options = pack::PackOptions {
+            force,
+            touch,
+            only_changed: matches.is_present("only-changed"),
+            checksum: matches.is_present("checksum"),
+            prune: matches.is_present("prune"),
+        };
+        // 先估算需要的空间，空间不够时在写入任何文件之前就报错
+        let space = space()?;
+        let mut plan = pack::Plan::default();
+        pack::estimate(Path::new(src_path), Some(root_dir), space.cluster_size, &mut plan)?;
+        let needed = plan.clusters.max(0) as u64 * space.cluster_size;
+        if needed > space.free {
+            let suggested = (space.total - space.free + needed) * 5 / 4;
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} needs about {} bytes but the image only has {} bytes free; \
+                     create a bigger image with --create --size {}M --force",
+                    src_path,
+                    needed,
+                    space.free,
+                    (suggested + (1 << 20) - 1) >> 20
+                ),
+            ));
+        }
+        let mut progress = progress::Progress::new(plan.files, plan.bytes);
+        let start = Instant::now();
+        pack::pack_dir(Path::new(src_path), root_dir, "", &options, &mut stats, &mut progress)?;
+        progress.clear();
+        let elapsed = start.elapsed();
+        println!(
+            "文件写入成功！共 {} 个目录，新建 {} 个文件、更新 {} 个、跳过 {} 个未改变的文件，写入 {} 字节，用时 {}.{:03} 秒（{:.1} MB/s）",
+            stats.dirs,
+            stats.files - stats.updated,
+            stats.updated,
+            stats.skipped,
+            stats.bytes,
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            stats.bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64().max(0.001)
+        );
     }
-    println!("文件写入成功！");
     Ok(())
 }
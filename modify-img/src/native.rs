@@ -0,0 +1,195 @@
+//! 用本仓库的 fat32 实现访问镜像
+//!
+//! 镜像文件作为 `fat32::BlockDevice` 交给 `FAT32Manager`，目录操作都通过 `VFile` 完成，
+//! 这样在主机上就能用真实的镜像检查内核使用的文件系统实现。
+//! fat32 在创建和写入文件时通过时间回调取得时间戳，写入文件时把回调返回的时间固定为主机文件的修改时间。
+
+use super::backend::{copy_chunks, Entry, ImageDir, Space};
+use super::time::unix_secs;
+use fat32::{BlockDevice, BlockError, FAT32Manager, FatError, VFile, ATTRIBUTE_DIRECTORY, BLOCK_SZ};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// 时间回调返回的时间（Unix 时间，秒）
+static PINNED_TIME: AtomicI64 = AtomicI64::new(0);
+
+fn pinned_time() -> u64 {
+    PINNED_TIME.load(Ordering::Relaxed).max(0) as u64
+}
+
+/// 镜像文件上的块设备
+struct FileDisk(Mutex<File>);
+
+impl BlockDevice for FileDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64)).map_err(|_| BlockError::Device)?;
+        file.read_exact(buf).map_err(|_| BlockError::OutOfRange)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64)).map_err(|_| BlockError::Device)?;
+        file.write_all(buf).map_err(|_| BlockError::Device)
+    }
+}
+
+fn fat_error(err: FatError) -> io::Error {
+    match err {
+        FatError::NoSpace => io::Error::new(io::ErrorKind::Other, "fat32: no space left in the image"),
+        err => io::Error::new(io::ErrorKind::Other, format!("fat32: {:?}", err)),
+    }
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} is not in the image", name))
+}
+
+/// 从头顺序读取镜像中的文件
+struct VFileReader {
+    vfile: Arc<VFile>,
+    offset: usize,
+}
+
+impl Read for VFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.vfile.read_at(self.offset, buf).map_err(fat_error)?;
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+/// 用 fat32 打开的镜像
+pub struct NativeImage {
+    fs: Arc<fat32::RwLock<FAT32Manager>>,
+}
+
+impl NativeImage {
+    /// 打开镜像文件上的文件系统
+    pub fn open(file: File) -> io::Result<Self> {
+        PINNED_TIME.store(unix_secs(SystemTime::now()), Ordering::Relaxed);
+        fat32::set_time_hook(pinned_time);
+        let fs = FAT32Manager::open(Arc::new(FileDisk(Mutex::new(file)))).map_err(fat_error)?;
+        Ok(NativeImage { fs })
+    }
+
+    pub fn root_dir(&self) -> NativeDir {
+        NativeDir(Arc::new(FAT32Manager::get_root_vfile(&self.fs)))
+    }
+
+    /// 数据区的空间
+    pub fn space(&self) -> io::Result<Space> {
+        let fs = self.fs.read();
+        let cluster_size = fs.bytes_per_cluster() as u64;
+        let free = fs.free_clusters().map_err(fat_error)? as u64;
+        Ok(Space { total: fs.total_clusters() as u64 * cluster_size, free: free * cluster_size, cluster_size })
+    }
+
+    /// 设置镜像中文件的只读属性，`attributes` 中是以 `/` 开头的路径和是否只读
+    pub fn set_read_only(&self, attributes: &[(String, bool)]) -> io::Result<()> {
+        let root = FAT32Manager::get_root_vfile(&self.fs);
+        for (path, read_only) in attributes {
+            let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+            let vfile = root.find_vfile_bypath(components).map_err(fat_error)?.ok_or_else(|| not_found(path))?;
+            vfile.set_readonly(*read_only).map_err(fat_error)?;
+        }
+        Ok(())
+    }
+
+    /// 把缓存中的修改写回镜像文件
+    pub fn sync(&self) -> io::Result<()> {
+        fat32::sync_all().map_err(fat_error)
+    }
+}
+
+/// 用 fat32 打开的目录
+pub struct NativeDir(Arc<VFile>);
+
+impl NativeDir {
+    fn find(&self, name: &str) -> io::Result<Option<Arc<VFile>>> {
+        self.0.find_vfile_byname(name).map_err(fat_error)
+    }
+}
+
+impl ImageDir for NativeDir {
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        let entries = self.0.ls_entries().map_err(fat_error)?.unwrap_or_default();
+        Ok(entries
+            .into_iter()
+            .filter(|(name, _)| name != "." && name != "..")
+            .map(|(name, short_ent)| Entry {
+                is_dir: short_ent.is_dir(),
+                size: if short_ent.is_dir() { 0 } else { short_ent.get_size() as u64 },
+                read_only: short_ent.attribute() & fat32::ATTRIBUTE_READ_ONLY != 0,
+                modified: short_ent.get_modification_time().6 as i64,
+                name,
+            })
+            .collect())
+    }
+
+    fn open_dir(&self, name: &str) -> io::Result<Self> {
+        match self.find(name)? {
+            Some(vfile) if vfile.is_dir() => Ok(NativeDir(vfile)),
+            _ => Err(not_found(name)),
+        }
+    }
+
+    fn create_dir(&self, name: &str) -> io::Result<Self> {
+        match self.find(name)? {
+            Some(vfile) if vfile.is_dir() => Ok(NativeDir(vfile)),
+            Some(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a file", name))),
+            None => match self.0.create(name, ATTRIBUTE_DIRECTORY).map_err(fat_error)? {
+                Some(vfile) => Ok(NativeDir(vfile)),
+                None => Err(io::Error::new(io::ErrorKind::Other, format!("cannot create directory {}", name))),
+            },
+        }
+    }
+
+    fn file_reader(&self, name: &str) -> io::Result<Box<dyn Read + '_>> {
+        let vfile = self.find(name)?.filter(|vfile| !vfile.is_dir()).ok_or_else(|| not_found(name))?;
+        Ok(Box::new(VFileReader { vfile, offset: 0 }))
+    }
+
+    fn write_file(
+        &self,
+        name: &str,
+        src: &mut dyn Read,
+        modified: i64,
+        read_only: bool,
+        written: &mut dyn FnMut(usize),
+    ) -> io::Result<()> {
+        if let Some(old) = self.find(name)? {
+            if old.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is a directory", name)));
+            }
+            old.remove().map_err(fat_error)?;
+        }
+        // 新目录项的创建时间和写入后的修改时间都取回调返回的时间
+        let now = PINNED_TIME.swap(modified, Ordering::Relaxed);
+        let result = self.0.create(name, 0).map_err(fat_error).and_then(|vfile| {
+            let vfile = vfile.ok_or_else(|| io::Error::new(io::ErrorKind::Other, format!("cannot create {}", name)))?;
+            let mut offset = 0;
+            copy_chunks(
+                src,
+                |chunk| {
+                    vfile.write_at(offset, chunk).map_err(fat_error)?;
+                    offset += chunk.len();
+                    Ok(())
+                },
+                written,
+            )?;
+            vfile.set_readonly(read_only).map_err(fat_error)
+        });
+        PINNED_TIME.store(now, Ordering::Relaxed);
+        result
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        let vfile = self.find(name)?.ok_or_else(|| not_found(name))?;
+        vfile.remove().map_err(fat_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! 把主机上的目录树写入镜像
+//!
+//! 镜像中文件的创建和修改时间取主机文件的修改时间，主机上只读的文件在镜像中带有只读属性。
+
+use super::backend::{Entry, ImageDir, CHUNK_SIZE};
+use super::delete::remove_tree;
+use super::progress::Progress;
+use super::time::unix_secs;
+use super::Stats;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{read_dir, File, Metadata};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// 写入镜像的方式
+pub struct PackOptions {
+    /// 覆盖镜像中已经存在的文件
+    pub force: bool,
+    /// 所有文件都使用这个时间（Unix 时间，秒）而不是主机文件的修改时间，用于生成可复现的镜像
+    pub touch: Option<i64>,
+    /// 跳过镜像中已有且没有改变的文件，改变了的文件直接覆盖
+    pub only_changed: bool,
+    /// 按内容而不是大小和修改时间判断文件是否改变
+    pub checksum: bool,
+    /// 删除镜像中有而主机目录中没有的文件和目录
+    pub prune: bool,
+}
+
+/// 镜像中的文件 `old` 是否和主机文件一致：大小、只读属性相同，并且修改时间相同（`checksum` 时内容相同）
+fn unchanged<D: ImageDir>(
+    dir: &D,
+    old: &Entry,
+    host_path: &Path,
+    metadata: &Metadata,
+    secs: i64,
+    checksum: bool,
+) -> io::Result<bool> {
+    if old.size != metadata.len() || old.read_only != metadata.permissions().readonly() {
+        return Ok(false);
+    }
+    if !checksum {
+        // FAT 的修改时间精度是 2 秒
+        return Ok(old.modified == secs - secs.rem_euclid(2));
+    }
+    // 两边按块比较，不把整个文件读入内存
+    let mut old_file = dir.file_reader(&old.name)?;
+    let mut host_file = File::open(host_path)?;
+    let mut old_buf = vec![0; CHUNK_SIZE];
+    let mut host_buf = vec![0; CHUNK_SIZE];
+    loop {
+        let len = read_full(&mut host_file, &mut host_buf)?;
+        if read_full(&mut *old_file, &mut old_buf[..len.max(1)])? != len || old_buf[..len] != host_buf[..len] {
+            return Ok(false);
+        }
+        if len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// 从 `src` 读满 `buf`，返回读到的字节数，只有读到末尾时才少于 `buf` 的长度
+pub(crate) fn read_full(src: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// 写入前对主机目录树的估算
+#[derive(Default)]
+pub struct Plan {
+    /// 要写入的文件数和字节数
+    pub files: usize,
+    pub bytes: u64,
+    /// 还需要的簇数：新文件和新目录占用的簇减去被覆盖的文件释放的簇
+    pub clusters: i64,
+}
+
+/// 镜像目录 `dir` 中的各项，以小写的名称为键（FAT 的文件名不区分大小写）
+fn existing_entries<D: ImageDir>(dir: &D) -> io::Result<BTreeMap<String, Entry>> {
+    let mut existing = BTreeMap::new();
+    for entry in dir.entries()? {
+        existing.insert(entry.name.to_lowercase(), entry);
+    }
+    Ok(existing)
+}
+
+/// 主机目录 `src` 下按名称排序的项，保证每次生成的镜像相同
+fn sorted_entries(src: &Path) -> io::Result<Vec<std::fs::DirEntry>> {
+    let mut entries = read_dir(src)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// 估算把主机目录 `src` 写入镜像目录 `dir`（镜像中还没有这个目录时为 `None`）需要的文件数、字节数和簇数。
+/// 不考虑 `--only-changed` 跳过的文件和 `--prune` 删除的文件，也不计长文件名占用的目录项，所以只是大致的估算
+pub fn estimate<D: ImageDir>(src: &Path, dir: Option<&D>, cluster_size: u64, plan: &mut Plan) -> io::Result<()> {
+    let existing = match dir {
+        Some(dir) => existing_entries(dir)?,
+        None => BTreeMap::new(),
+    };
+    let clusters = |size: u64| ((size + cluster_size - 1) / cluster_size) as i64;
+    for entry in sorted_entries(src)? {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        let old = existing.get(&name);
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            continue;
+        } else if file_type.is_dir() {
+            match (dir, old) {
+                (Some(dir), Some(old)) if old.is_dir => {
+                    let sub_dir = dir.open_dir(&old.name)?;
+                    estimate(&entry.path(), Some(&sub_dir), cluster_size, plan)?;
+                }
+                _ => {
+                    // 新目录至少占一个簇
+                    plan.clusters += 1;
+                    estimate::<D>(&entry.path(), None, cluster_size, plan)?;
+                }
+            }
+        } else {
+            let size = entry.metadata()?.len();
+            plan.files += 1;
+            plan.bytes += size;
+            plan.clusters += clusters(size);
+            if let Some(old) = old.filter(|old| !old.is_dir) {
+                plan.clusters -= clusters(old.size);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 把主机目录 `src` 下的内容递归地写入镜像目录 `dir`，`rel` 是 `dir` 在镜像中的路径（根目录为空串）。
+/// 符号链接只警告并跳过；镜像中已经存在的文件只有 `options.force` 或 `options.only_changed` 时才覆盖，否则报错。
+/// 写入文件失败时报告是哪个文件、写入了多少字节
+pub fn pack_dir<D: ImageDir>(
+    src: &Path,
+    dir: &D,
+    rel: &str,
+    options: &PackOptions,
+    stats: &mut Stats,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    // 镜像目录中已有的项，FAT 的文件名不区分大小写
+    let existing = existing_entries(dir)?;
+    let mut seen = HashSet::new();
+    for entry in sorted_entries(src)? {
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("non UTF-8 file name {:?}", name))
+        })?;
+        seen.insert(name.to_lowercase());
+        let path = format!("{}/{}", rel, name);
+        // 不跟随符号链接
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            progress.clear();
+            eprintln!("warning: skipping symlink {}", entry.path().display());
+        } else if file_type.is_dir() {
+            let sub_dir = dir
+                .create_dir(&name)
+                .map_err(|err| io::Error::new(err.kind(), format!("failed to create {}/: {}", path, err)))?;
+            progress.clear();
+            println!("{:>10}  {}/", "<dir>", path);
+            stats.dirs += 1;
+            pack_dir(&entry.path(), &sub_dir, &path, options, stats, progress)?;
+        } else {
+            let metadata = entry.metadata()?;
+            let secs = match options.touch {
+                Some(secs) => secs,
+                None => unix_secs(metadata.modified()?),
+            };
+            let old = existing.get(&name.to_lowercase());
+            if let Some(old) = old {
+                if options.only_changed && unchanged(dir, old, &entry.path(), &metadata, secs, options.checksum)? {
+                    stats.skipped += 1;
+                    progress.file_done(metadata.len());
+                    continue;
+                }
+                if !options.force && !options.only_changed {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} already exists in the image, use --force to overwrite it", path),
+                    ));
+                }
+            }
+            // stream app data from the host file to the image, dropping the old content if it exists
+            let mut host_file = File::open(entry.path())?;
+            let mut written = 0;
+            dir.write_file(&name, &mut host_file, secs, metadata.permissions().readonly(), &mut |len| {
+                written += len as u64;
+                progress.add_bytes(len as u64);
+            })
+            .map_err(|err| {
+                progress.clear();
+                io::Error::new(
+                    err.kind(),
+                    format!(
+                        "failed to write {} after {} of {} bytes: {}; the image now holds a partial copy",
+                        path,
+                        written,
+                        metadata.len(),
+                        err
+                    ),
+                )
+            })?;
+            progress.clear();
+            println!("{:>10}  {}", written, path);
+            progress.file_done(0);
+            stats.files += 1;
+            if old.is_some() {
+                stats.updated += 1;
+            }
+            stats.bytes += written;
+        }
+    }
+    if options.prune {
+        for (key, old) in &existing {
+            if !seen.contains(key) {
+                remove_tree(dir, rel, &old.name, old.is_dir)?;
+            }
+        }
+    }
+    Ok(())
+}
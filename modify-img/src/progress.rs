@@ -0,0 +1,86 @@
+//! 写入镜像时的进度行
+//!
+//! 标准错误是终端时在同一行刷新已完成的文件数、字节数和速度，输出到文件或管道时不显示。
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// 两次刷新进度行的最短间隔
+const REFRESH: Duration = Duration::from_millis(100);
+
+const MB: f64 = 1024.0 * 1024.0;
+
+pub struct Progress {
+    total_files: usize,
+    total_bytes: u64,
+    files: usize,
+    bytes: u64,
+    start: Instant,
+    last_draw: Option<Instant>,
+    enabled: bool,
+}
+
+impl Progress {
+    /// 一共要处理 `total_files` 个文件、`total_bytes` 字节
+    pub fn new(total_files: usize, total_bytes: u64) -> Self {
+        Progress {
+            total_files,
+            total_bytes,
+            files: 0,
+            bytes: 0,
+            start: Instant::now(),
+            last_draw: None,
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    /// 又处理了 `bytes` 字节
+    pub fn add_bytes(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.draw(false);
+    }
+
+    /// 处理完一个文件，`skipped_bytes` 是没有写入而直接跳过的字节数
+    pub fn file_done(&mut self, skipped_bytes: u64) {
+        self.files += 1;
+        self.bytes += skipped_bytes;
+        self.draw(true);
+    }
+
+    /// 每秒处理的 MB 数
+    fn rate(&self) -> f64 {
+        let secs = self.start.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / MB / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// 擦掉进度行，之后可以正常输出
+    pub fn clear(&self) {
+        if self.enabled && self.last_draw.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    fn draw(&mut self, force: bool) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if !force && self.last_draw.map_or(false, |last| now - last < REFRESH) {
+            return;
+        }
+        self.last_draw = Some(now);
+        eprint!(
+            "\r\x1b[K[{}/{}] {:.1}/{:.1} MB, {:.1} MB/s",
+            self.files,
+            self.total_files,
+            self.bytes as f64 / MB,
+            self.total_bytes as f64 / MB,
+            self.rate()
+        );
+        let _ = io::stderr().flush();
+    }
+}
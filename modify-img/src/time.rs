@@ -0,0 +1,79 @@
+//! 主机时间和镜像中时间戳的换算
+//!
+//! FAT32 以日期和时间（精度 2 秒，年份 1980 到 2107）记录时间戳。
+//! 内核的 fat32 按 UTC 解释这些时间，这里同样按 UTC 换算，不处理时区。
+
+use fat32::{civil_from_days, days_from_civil};
+use fatfs::{Date, DateTime, Time};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// FAT32 能表示的最早时间 1980-01-01 00:00:00 对应的 Unix 时间
+const FAT_EPOCH: i64 = 315_532_800;
+/// FAT32 能表示的最晚时间 2107-12-31 23:59:58 对应的 Unix 时间
+const FAT_MAX: i64 = 4_354_819_198;
+const SECS_PER_DAY: i64 = 86400;
+
+/// 主机时间对应的 Unix 时间（秒），早于 1970 年时为负
+pub fn unix_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+/// Unix 时间 `secs` 对应的 FAT32 时间戳，超出可表示范围时取最近的边界
+pub fn to_fat(secs: i64) -> DateTime {
+    let secs = secs.clamp(FAT_EPOCH, FAT_MAX);
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    let rem = secs.rem_euclid(SECS_PER_DAY);
+    DateTime {
+        date: Date { year: year as u16, month: month as u16, day: day as u16 },
+        time: Time { hour: (rem / 3600) as u16, min: (rem % 3600 / 60) as u16, sec: (rem % 60) as u16, millis: 0 },
+    }
+}
+
+/// FAT32 时间戳对应的主机时间
+pub fn from_fat(t: DateTime) -> SystemTime {
+    let days = days_from_civil(t.date.year as i64, t.date.month as i64, t.date.day as i64);
+    let secs = days * SECS_PER_DAY + t.time.hour as i64 * 3600 + t.time.min as i64 * 60 + t.time.sec as i64;
+    UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64) + Duration::from_millis(t.time.millis as u64)
+}
+
+/// 解析 RFC 3339 时间，如 `2024-01-01T00:00:00Z` 或 `2024-01-01T08:00:00.5+08:00`，返回 Unix 时间（秒）
+pub fn parse_rfc3339(text: &str) -> Option<i64> {
+    let bytes = text.as_bytes();
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':' || bytes[16] != b':'
+    {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, min, sec) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    // 忽略秒的小数部分
+    let mut rest = &text[19..];
+    if rest.starts_with('.') {
+        rest = rest[1..].trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let rest = rest.as_bytes();
+            if rest.len() != 6 || rest[3] != b':' {
+                return None;
+            }
+            let sign = match rest[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours = std::str::from_utf8(&rest[1..3]).ok()?.parse::<i64>().ok()?;
+            let mins = std::str::from_utf8(&rest[4..6]).ok()?.parse::<i64>().ok()?;
+            sign * (hours * 3600 + mins * 60)
+        }
+    };
+    Some(days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + min * 60 + sec - offset)
+}
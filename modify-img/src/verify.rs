@@ -0,0 +1,146 @@
+//! 比较镜像和主机目录的内容
+
+use super::backend::{open_path, ImageDir, CHUNK_SIZE};
+use super::glob::glob_match;
+use super::pack::read_full;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// 目录树中的一项
+enum Kind {
+    Dir,
+    /// 普通文件及其大小
+    File(u64),
+}
+
+/// 要忽略的路径，每个模式是只支持 `*` 和 `?` 的通配符：
+/// 含有 `/` 的模式和镜像中的完整路径（如 `/results/*.log`）匹配，否则只和文件名匹配
+pub struct Ignore(pub Vec<String>);
+
+impl Ignore {
+    fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap();
+        self.0.iter().any(|pattern| {
+            let text = if pattern.contains('/') { path } else { name };
+            glob_match(pattern, text)
+        })
+    }
+}
+
+/// 收集主机目录 `dir` 下的内容，路径以 `rel` 为前缀，符号链接只警告并跳过
+fn collect_host(dir: &Path, rel: &str, ignore: &Ignore, out: &mut BTreeMap<String, Kind>) -> io::Result<()> {
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = format!("{}/{}", rel, entry.file_name().to_string_lossy());
+        if ignore.matches(&path) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            eprintln!("warning: skipping symlink {}", entry.path().display());
+        } else if file_type.is_dir() {
+            collect_host(&entry.path(), &path, ignore, out)?;
+            out.insert(path, Kind::Dir);
+        } else {
+            out.insert(path, Kind::File(entry.metadata()?.len()));
+        }
+    }
+    Ok(())
+}
+
+/// 收集镜像目录 `dir` 下的内容，路径以 `rel` 为前缀
+fn collect_image<D: ImageDir>(dir: &D, rel: &str, ignore: &Ignore, out: &mut BTreeMap<String, Kind>) -> io::Result<()> {
+    for entry in dir.entries()? {
+        let path = format!("{}/{}", rel, entry.name);
+        if ignore.matches(&path) {
+            continue;
+        }
+        if entry.is_dir {
+            collect_image(&dir.open_dir(&entry.name)?, &path, ignore, out)?;
+            out.insert(path, Kind::Dir);
+        } else {
+            out.insert(path, Kind::File(entry.size));
+        }
+    }
+    Ok(())
+}
+
+/// 逐块比较两个文件的内容
+fn same_contents(a: &mut dyn Read, b: &mut dyn Read) -> io::Result<bool> {
+    let mut buf_a = vec![0; CHUNK_SIZE];
+    let mut buf_b = vec![0; CHUNK_SIZE];
+    loop {
+        let len_a = read_full(a, &mut buf_a)?;
+        let len_b = read_full(b, &mut buf_b)?;
+        if buf_a[..len_a] != buf_b[..len_b] {
+            return Ok(false);
+        }
+        if len_a < CHUNK_SIZE {
+            return Ok(true);
+        }
+    }
+}
+
+/// 比较镜像根目录 `root_dir` 和主机目录 `host` 中的文件和目录，打印所有差异，返回两边是否一致。
+/// 被 `ignore` 匹配的文件和目录（连同目录下的内容）不参与比较
+pub fn verify<D: ImageDir>(root_dir: &D, host: &Path, ignore: &Ignore) -> io::Result<bool> {
+    let mut host_tree = BTreeMap::new();
+    collect_host(host, "", ignore, &mut host_tree)?;
+    let mut image_tree = BTreeMap::new();
+    collect_image(root_dir, "", ignore, &mut image_tree)?;
+    let (mut added, mut removed, mut changed, mut same) = (0, 0, 0, 0);
+    for path in image_tree.keys().filter(|path| !host_tree.contains_key(*path)) {
+        println!("+ {}  only in the image", path);
+        added += 1;
+    }
+    for (path, kind) in &host_tree {
+        let size = match (kind, image_tree.get(path)) {
+            (_, None) => {
+                println!("- {}  missing from the image", path);
+                removed += 1;
+                continue;
+            }
+            (&Kind::Dir, Some(&Kind::Dir)) => continue,
+            (&Kind::Dir, Some(&Kind::File(_))) => {
+                println!("! {}  file in the image, directory on the host", path);
+                changed += 1;
+                continue;
+            }
+            (&Kind::File(_), Some(&Kind::Dir)) => {
+                println!("! {}  directory in the image, file on the host", path);
+                changed += 1;
+                continue;
+            }
+            (&Kind::File(size), Some(&Kind::File(image_size))) if size == image_size => size,
+            (&Kind::File(size), Some(&Kind::File(image_size))) => {
+                println!("! {}  {} bytes in the image, {} bytes on the host", path, image_size, size);
+                changed += 1;
+                continue;
+            }
+        };
+        let host_path: PathBuf = host.join(path.trim_start_matches('/'));
+        let mut host_file = File::open(&host_path)?;
+        let same_file = match path[1..].rsplit_once('/') {
+            Some((parent, name)) => same_contents(&mut *open_path(root_dir, parent)?.file_reader(name)?, &mut host_file)?,
+            None => same_contents(&mut *root_dir.file_reader(&path[1..])?, &mut host_file)?,
+        };
+        if same_file {
+            same += 1;
+        } else {
+            println!("! {}  contents differ ({} bytes)", path, size);
+            changed += 1;
+        }
+    }
+    if added + removed + changed == 0 {
+        println!("校验通过！共 {} 个文件一致", same);
+        Ok(true)
+    } else {
+        println!(
+            "校验失败！{} 项只在镜像中，{} 项镜像中缺失，{} 项不同，{} 个文件一致",
+            added, removed, changed, same
+        );
+        Ok(false)
+    }
+}
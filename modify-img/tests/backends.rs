@@ -0,0 +1,43 @@
+//! 两种实现在同一个镜像上的行为一致：一种实现写入的目录树由另一种列出和取回，结果相同
+
+extern crate fatfs;
+
+mod common;
+
+use common::{host_tree, WorkDir, MTIME};
+
+const BACKENDS: [&str; 2] = ["fatfs", "native"];
+
+fn populate(work: &WorkDir) {
+    work.add_file("top.txt", b"top level\n", MTIME, false);
+    work.add_file("bin/hello", b"\x7fELF hello", MTIME, false);
+    work.add_file("bin/lib/libc.so", &(0..300_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>(), MTIME, false);
+    work.add_file("bin/lib/deep/readme", b"deep\n", MTIME + 86400, true);
+    work.add_file("etc/a-rather-long-file-name.conf", b"long name\n", MTIME, false);
+}
+
+/// 用 `writer` 写入，再用两种实现分别列出和取回
+fn check_written_by(writer: &str) {
+    let work = WorkDir::new(&format!("backends-{}", writer));
+    populate(&work);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap(), "--backend", writer]);
+
+    let listings: Vec<String> = BACKENDS.iter().map(|backend| work.run(&["--list", "--json", "--backend", backend])).collect();
+    assert_eq!(listings[0], listings[1], "fatfs and native list the image written by {} differently", writer);
+    for backend in BACKENDS {
+        let out = work.0.join(format!("out-{}", backend));
+        work.run(&["-x", out.to_str().unwrap(), "--backend", backend]);
+        assert_eq!(host_tree(&out), host_tree(&work.src()), "{} extracts the image written by {} differently", backend, writer);
+    }
+}
+
+#[test]
+fn written_by_fatfs() {
+    check_written_by("fatfs");
+}
+
+#[test]
+fn written_by_native() {
+    check_written_by("native");
+}
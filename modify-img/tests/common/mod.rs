@@ -0,0 +1,126 @@
+//! 集成测试共用的工作目录：源目录中放入主机文件，以它为 `--target` 运行 modify-img，
+//! 再用 fatfs 读出镜像的内容。每个测试文件只用到其中一部分
+
+#![allow(dead_code)]
+
+use std::fs::{create_dir_all, remove_dir_all, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// 2024-03-15 12:34:57 UTC，秒数是奇数，写入镜像后变为 12:34:56
+pub const MTIME: u64 = 1_710_506_097;
+
+/// 临时的工作目录，离开作用域时删除
+pub struct WorkDir(pub PathBuf);
+
+impl WorkDir {
+    pub fn new(name: &str) -> WorkDir {
+        let path = std::env::temp_dir().join(format!("modify-img-test-{}-{}", std::process::id(), name));
+        let _ = remove_dir_all(&path);
+        create_dir_all(path.join("src")).unwrap();
+        WorkDir(path)
+    }
+
+    pub fn src(&self) -> PathBuf {
+        self.0.join("src")
+    }
+
+    /// 在源目录中写入文件 `name`（可以包含子目录），修改时间设为 `mtime`
+    pub fn add_file(&self, name: &str, data: &[u8], mtime: u64, read_only: bool) {
+        let path = self.src().join(name);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        let mut file = File::create(path).unwrap();
+        file.write_all(data).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime)).unwrap();
+        if read_only {
+            let mut permissions = file.metadata().unwrap().permissions();
+            permissions.set_readonly(true);
+            file.set_permissions(permissions).unwrap();
+        }
+    }
+
+    /// 以工作目录为 `--target` 运行 modify-img，镜像是其中的 sdcard.img，返回标准输出
+    pub fn run(&self, args: &[&str]) -> String {
+        let target = format!("{}/", self.0.display());
+        let output = Command::new(env!("CARGO_BIN_EXE_modify-img"))
+            .args(["-t", target.as_str()])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "modify-img {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// 同 `run`，但 modify-img 应当失败，返回标准错误
+    pub fn run_fails(&self, args: &[&str]) -> String {
+        let target = format!("{}/", self.0.display());
+        let output = Command::new(env!("CARGO_BIN_EXE_modify-img"))
+            .args(["-t", target.as_str()])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "modify-img {:?} should fail", args);
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    /// 用 fatfs 打开镜像
+    pub fn image(&self) -> fatfs::FileSystem<File> {
+        let img = OpenOptions::new().read(true).write(true).open(self.0.join("sdcard.img")).unwrap();
+        fatfs::FileSystem::new(img, fatfs::FsOptions::new()).unwrap()
+    }
+
+    /// 用 fatfs 读出镜像中 `path`（相对于根目录）的内容
+    pub fn read_image(&self, path: &str) -> Vec<u8> {
+        let fs = self.image();
+        let mut data = Vec::new();
+        fs.root_dir().open_file(path).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+}
+
+/// 恢复 `dir` 下所有文件的写权限，只读文件也要能删除
+fn make_writable(dir: &Path) {
+    if let Ok(entries) = dir.read_dir() {
+        for entry in entries.flatten() {
+            let mut permissions = entry.metadata().unwrap().permissions();
+            permissions.set_mode(permissions.mode() | 0o200);
+            let _ = std::fs::set_permissions(entry.path(), permissions);
+            make_writable(&entry.path());
+        }
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        make_writable(&self.0);
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+/// 以 `root` 为根的目录树中的所有文件，按相对路径排列的 (路径, 内容)
+pub fn host_tree(root: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    collect(root, "", &mut files);
+    files.sort();
+    files
+}
+
+fn collect(dir: &Path, rel: &str, files: &mut Vec<(String, Vec<u8>)>) {
+    for entry in dir.read_dir().unwrap() {
+        let entry = entry.unwrap();
+        let name = format!("{}{}", rel, entry.file_name().to_str().unwrap());
+        if entry.file_type().unwrap().is_dir() {
+            collect(&entry.path(), &format!("{}/", name), files);
+        } else {
+            files.push((name, std::fs::read(entry.path()).unwrap()));
+        }
+    }
+}
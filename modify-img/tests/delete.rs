@@ -0,0 +1,64 @@
+//! 用 --delete 删除镜像中的嵌套目录树，检查目录树整个消失、空间被回收，没有 --recursive 时什么都不删除
+
+extern crate fatfs;
+
+mod common;
+
+use common::{WorkDir, MTIME};
+
+/// 镜像中要删除的 /old 目录树，其中的大文件占用若干簇，另有一个保留的文件
+fn populate(work: &WorkDir) {
+    work.add_file("keep.txt", b"keep me\n", MTIME, false);
+    work.add_file("old/a.txt", b"a\n", MTIME, false);
+    work.add_file("old/sub/big.bin", &vec![0x5a; 200_000], MTIME, false);
+    work.add_file("old/sub/deeper/frozen", b"read only\n", MTIME, true);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+}
+
+/// `--list --json` 输出中的空闲字节数
+fn free_bytes(listing: &str) -> u64 {
+    let line = listing.lines().find(|line| line.contains("\"free_bytes\"")).unwrap();
+    line.split(':').nth(1).unwrap().trim().trim_end_matches(',').parse().unwrap()
+}
+
+/// 带 --recursive 删除非空目录时整个目录树都被删除，释放的簇计入空闲空间
+fn check_delete_tree(backend: &str) {
+    let work = WorkDir::new(&format!("delete-{}", backend));
+    populate(&work);
+    let before = free_bytes(&work.run(&["--list", "--json"]));
+
+    let empty = work.0.join("empty");
+    std::fs::create_dir_all(&empty).unwrap();
+    work.run(&["-s", empty.to_str().unwrap(), "--delete", "/old", "--recursive", "--backend", backend]);
+
+    let listing = work.run(&["--list", "--json"]);
+    assert!(!listing.contains("/old"), "/old is still in the image:\n{}", listing);
+    assert!(listing.contains("\"path\": \"/keep.txt\""));
+    assert_eq!(work.read_image("keep.txt"), b"keep me\n");
+    let after = free_bytes(&listing);
+    assert!(after >= before + 200_000, "free space grew from {} only to {}", before, after);
+}
+
+#[test]
+fn delete_recursive_tree_fatfs() {
+    check_delete_tree("fatfs");
+}
+
+#[test]
+fn delete_recursive_tree_native() {
+    check_delete_tree("native");
+}
+
+/// 没有 --recursive 时拒绝删除非空目录，镜像保持不变
+#[test]
+fn delete_non_empty_needs_recursive() {
+    let work = WorkDir::new("delete-refused");
+    populate(&work);
+    let before = work.run(&["--list", "--json"]);
+    let empty = work.0.join("empty");
+    std::fs::create_dir_all(&empty).unwrap();
+    let stderr = work.run_fails(&["-s", empty.to_str().unwrap(), "--delete", "/old"]);
+    assert!(stderr.contains("use --recursive"), "unexpected error: {}", stderr);
+    assert_eq!(work.run(&["--list", "--json"]), before);
+}
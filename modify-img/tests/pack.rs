@@ -0,0 +1,76 @@
+//! 把嵌套的主机目录树写入镜像，再用 fatfs 读回每个文件，检查目录结构和内容
+
+extern crate fatfs;
+
+mod common;
+
+use common::{host_tree, WorkDir, MTIME};
+
+/// 三层嵌套的目录树，其中一个文件跨越多个写入块
+fn nested_tree(work: &WorkDir) {
+    work.add_file("top.txt", b"top level\n", MTIME, false);
+    work.add_file("bin/hello", b"\x7fELF hello", MTIME, false);
+    work.add_file("bin/lib/libc.so", &(0..300_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>(), MTIME, false);
+    work.add_file("bin/lib/deep/readme", b"deep\n", MTIME, true);
+    work.add_file("etc/passwd", b"root:x:0:0\n", MTIME, false);
+}
+
+#[test]
+fn pack_recursive_tree() {
+    let work = WorkDir::new("pack-tree");
+    nested_tree(&work);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+
+    for (path, data) in host_tree(&work.src()) {
+        assert_eq!(work.read_image(&path), data, "{} differs in the image", path);
+    }
+    let listing = work.run(&["--list", "--json"]);
+    for dir in ["/bin", "/bin/lib", "/bin/lib/deep", "/etc"] {
+        assert!(
+            listing.contains(&format!("{{\"path\": \"{}\", \"type\": \"dir\"", dir)),
+            "{} is not a directory in the listing:\n{}",
+            dir,
+            listing
+        );
+    }
+    assert!(listing.contains("{\"path\": \"/bin/lib/deep/readme\", \"type\": \"file\", \"size\": 5, \"read_only\": true"));
+}
+
+/// 写入再取回的目录树与源目录逐个文件相同，大文件按块流式取回
+#[test]
+fn extract_round_trip() {
+    let work = WorkDir::new("round-trip");
+    nested_tree(&work);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+    let out = work.0.join("out");
+    work.run(&["-x", out.to_str().unwrap()]);
+    assert_eq!(host_tree(&out), host_tree(&work.src()));
+}
+
+/// 写入结果中 `更新 N 个、跳过 M 个` 的 (N, M)
+fn updated_skipped(stdout: &str) -> (usize, usize) {
+    let count = |key: &str| -> usize {
+        let start = stdout.find(key).unwrap_or_else(|| panic!("no {} in {}", key, stdout)) + key.len();
+        let digits: String = stdout[start..].trim_start().chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().unwrap()
+    };
+    (count("更新"), count("跳过"))
+}
+
+/// --only-changed 只重写改变了的文件，其余文件跳过
+#[test]
+fn only_changed_rewrites_one_of_fifty() {
+    let work = WorkDir::new("only-changed");
+    for i in 0..50 {
+        work.add_file(&format!("data/{:02}.txt", i), format!("file {}\n", i).as_bytes(), MTIME, false);
+    }
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+
+    work.add_file("data/17.txt", b"file 17, changed\n", MTIME + 60, false);
+    let stdout = work.run(&["-s", work.src().to_str().unwrap(), "--only-changed"]);
+    assert_eq!(updated_skipped(&stdout), (1, 49), "{}", stdout);
+    assert_eq!(work.read_image("data/17.txt"), b"file 17, changed\n");
+}
@@ -0,0 +1,115 @@
+//! 运行 modify-img 把主机目录写入新建的镜像，再用 fatfs 读出目录项，
+//! 检查主机文件的修改时间和只读属性原样（修改时间按 FAT 的 2 秒精度）保存在镜像中，取回时又还原到主机上
+
+extern crate fatfs;
+
+mod common;
+
+use common::{WorkDir, MTIME};
+use fatfs::{DateTime, FileAttributes};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 用 fatfs 读出镜像根目录中 `name` 的修改时间和属性
+fn entry(work: &WorkDir, name: &str) -> (DateTime, FileAttributes) {
+    let fs = work.image();
+    let entry = fs
+        .root_dir()
+        .iter()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.file_name() == name)
+        .unwrap_or_else(|| panic!("{} is not in the image", name));
+    (entry.modified(), entry.attributes())
+}
+
+/// FAT 时间戳的 (年, 月, 日, 时, 分, 秒)
+fn fields(t: DateTime) -> (u16, u16, u16, u16, u16, u16) {
+    (t.date.year, t.date.month, t.date.day, t.time.hour, t.time.min, t.time.sec)
+}
+
+fn mtime_of(path: &Path) -> SystemTime {
+    path.metadata().unwrap().modified().unwrap()
+}
+
+/// 两种实现写入的文件都带有主机文件的修改时间和只读属性
+fn check_pack(backend: &str) {
+    let work = WorkDir::new(backend);
+    work.add_file("hello.txt", b"hello, world\n", MTIME, false);
+    work.add_file("frozen.txt", b"read only\n", MTIME + 86400, true);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap(), "--backend", backend]);
+
+    let (modified, attrs) = entry(&work, "hello.txt");
+    assert_eq!(fields(modified), (2024, 3, 15, 12, 34, 56));
+    assert!(!attrs.contains(FileAttributes::READ_ONLY));
+    let (modified, attrs) = entry(&work, "frozen.txt");
+    assert_eq!(fields(modified), (2024, 3, 16, 12, 34, 56));
+    assert!(attrs.contains(FileAttributes::READ_ONLY));
+}
+
+#[test]
+fn pack_keeps_mtime_fatfs() {
+    check_pack("fatfs");
+}
+
+#[test]
+fn pack_keeps_mtime_native() {
+    check_pack("native");
+}
+
+/// --touch 给所有文件同一个时间戳，时区偏移换算成 UTC
+#[test]
+fn touch_overrides_mtime() {
+    let work = WorkDir::new("touch");
+    work.add_file("hello.txt", b"hello, world\n", MTIME, false);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap(), "--touch", "2024-01-01T08:00:00+08:00"]);
+    let (modified, _) = entry(&work, "hello.txt");
+    assert_eq!(fields(modified), (2024, 1, 1, 0, 0, 0));
+}
+
+/// 写入再取回的文件的修改时间与镜像中的一致
+#[test]
+fn extract_restores_mtime() {
+    let work = WorkDir::new("extract");
+    work.add_file("hello.txt", b"hello, world\n", MTIME, false);
+    work.add_file("frozen.txt", b"read only\n", MTIME, true);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+    let out = work.0.join("out");
+    work.run(&["-x", out.to_str().unwrap()]);
+
+    let expected = UNIX_EPOCH + Duration::from_secs(MTIME - 1);
+    assert_eq!(mtime_of(&out.join("hello.txt")), expected);
+    assert_eq!(mtime_of(&out.join("frozen.txt")), expected);
+    assert!(out.join("frozen.txt").metadata().unwrap().permissions().readonly());
+    assert_eq!(std::fs::read(out.join("hello.txt")).unwrap(), b"hello, world\n");
+}
+
+/// --path 指向文件时只取回这个文件
+#[test]
+fn extract_single_file() {
+    let work = WorkDir::new("path");
+    work.add_file("hello.txt", b"hello, world\n", MTIME, false);
+    work.add_file("other.txt", b"other\n", MTIME, false);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+    let out = work.0.join("out");
+    work.run(&["-x", out.to_str().unwrap(), "--path", "/hello.txt"]);
+    assert_eq!(mtime_of(&out.join("hello.txt")), UNIX_EPOCH + Duration::from_secs(MTIME - 1));
+    assert!(!out.join("other.txt").exists());
+}
+
+/// --checksum 按内容比较：大小和修改时间都没变但内容变了的文件要重新写入
+#[test]
+fn checksum_detects_same_size_change() {
+    let work = WorkDir::new("checksum");
+    work.add_file("hello.txt", b"hello, world\n", MTIME, false);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+    work.add_file("hello.txt", b"HELLO, WORLD\n", MTIME, false);
+    work.run(&["-s", work.src().to_str().unwrap(), "--only-changed", "--checksum"]);
+    let out = work.0.join("out");
+    work.run(&["-x", out.to_str().unwrap()]);
+    assert_eq!(std::fs::read(out.join("hello.txt")).unwrap(), b"HELLO, WORLD\n");
+}
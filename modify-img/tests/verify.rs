@@ -0,0 +1,34 @@
+//! 用两种实现校验镜像和主机目录：内容一致时通过，改动一个文件后报告差异并以非零状态退出
+
+extern crate fatfs;
+
+mod common;
+
+use common::{WorkDir, MTIME};
+
+fn check_verify(backend: &str) {
+    let work = WorkDir::new(&format!("verify-{}", backend));
+    work.add_file("top.txt", b"top level\n", MTIME, false);
+    work.add_file("bin/lib/libc.so", &(0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>(), MTIME, false);
+    work.run(&["--create", "--size", "40M"]);
+    work.run(&["-s", work.src().to_str().unwrap()]);
+    let src = work.src();
+    let stdout = work.run(&["--verify", src.to_str().unwrap(), "--backend", backend]);
+    assert!(stdout.contains("校验通过！共 2 个文件一致"), "{}", stdout);
+
+    // 大小不变，只有后面一个数据块的内容不同
+    let mut data = std::fs::read(src.join("bin/lib/libc.so")).unwrap();
+    data[150_000] ^= 0xff;
+    work.add_file("bin/lib/libc.so", &data, MTIME, false);
+    work.run_fails(&["--verify", src.to_str().unwrap(), "--backend", backend]);
+}
+
+#[test]
+fn verify_fatfs() {
+    check_verify("fatfs");
+}
+
+#[test]
+fn verify_native() {
+    check_verify("native");
+}
@@ -4,8 +4,22 @@
 
 /// user app's stack size
 pub const USER_STACK_SIZE: usize = 4096 * 3;
+/// the max size the user stack may grow to (RLIMIT_STACK)
+pub const USER_STACK_LIMIT: usize = 0x80_0000;
+/// how far below the stack a fault may land and still grow the stack
+pub const USER_STACK_GROW_GAP: usize = 0x4_0000;
+/// load bias for position-independent (ET_DYN) executables
+pub const ELF_DYN_BASE: usize = 0x10000;
 /// kernel stack size
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// size of the swap file reserved on the FAT volume when the `swap` feature
+/// is enabled; must be a multiple of 64 pages
+pub const SWAP_SIZE: usize = 0x100_0000;
+/// the max length of a path passed in from user space, including the NUL
+pub const PATH_MAX: usize = 4096;
+/// the default byte budget of a tmpfs, used for /tmp and for mounts without
+/// a size= option
+pub const TMPFS_SIZE: usize = 0x40_0000;
 /// kernel heap size
 pub const KERNEL_HEAP_SIZE: usize = 0x200_0000;
 
@@ -17,14 +31,42 @@ pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
 /// the virtual addr of trapoline
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
-/// the virtual addr of trap context
+/// the virtual addr of the first thread's trap context; further threads take
+/// the pages below it
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
+/// the number of trap context pages reserved below the trampoline, one per thread
+pub const MAX_TRAP_CX_SLOTS: usize = 64;
 /// clock frequency
 pub const CLOCK_FREQ: usize = 12500000;
-/// the physical memory end
+/// the physical memory end, used when the device tree has no memory node
+#[cfg(not(feature = "tiny-mem"))]
 pub const MEMORY_END: usize = 0x88000000;
-/// The base address of control registers in Virtio_Block device
-pub const MMIO: &[(usize, usize)] = &[(0x10001000, 0x1000)];
+/// the highest physical memory end, shrunk to leave only a few MiB of frames
+/// so out-of-memory paths can be exercised whatever the device tree reports
+#[cfg(feature = "tiny-mem")]
+pub const MEMORY_END: usize = 0x82c00000;
+/// The base address of the platform-level interrupt controller, used when the
+/// boot loader passes no device tree
+pub const PLIC_BASE: usize = 0x0c00_0000;
+/// The base address of control registers in the first virtio-mmio slot, used
+/// when the boot loader passes no device tree
+pub const VIRTIO0: usize = 0x10001000;
+/// The PLIC interrupt source of the first virtio-mmio slot
+pub const VIRTIO0_IRQ: u32 = 1;
+/// The base address of the ns16550a UART, used when the boot loader passes no
+/// device tree
+pub const UART0: usize = 0x1000_0000;
+/// The PLIC interrupt source of the UART
+pub const UART0_IRQ: u32 = 10;
+/// The base address of the goldfish real-time clock, used when the boot loader
+/// passes no device tree
+pub const RTC0: usize = 0x0010_1000;
+/// The PLIC interrupt source of the real-time clock
+pub const RTC0_IRQ: u32 = 11;
 
 /// BigStride
-pub const BIGSTRIDE: isize = 2550;
+pub const BIGSTRIDE: u64 = 2550;
+/// the number of timer ticks a task may run before it is preempted
+pub const TIME_SLICE_TICKS: usize = 2;
+/// the size of a task's command name including the trailing NUL, as in Linux
+pub const TASK_COMM_LEN: usize = 16;
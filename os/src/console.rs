@@ -1,18 +1,24 @@
-//! SBI console driver, for text output
-use crate::sbi::console_putchar;
+//! console output: straight to the UART once it is initialized, through SBI before that
+use crate::drivers::uart::putchar;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
-        }
+        write_bytes(s.as_bytes());
         Ok(())
     }
 }
 
+/// Write raw bytes to the console one at a time; the terminal reassembles multi-byte UTF-8 sequences,
+/// even when they are split across calls.
+pub fn write_bytes(bytes: &[u8]) {
+    for &b in bytes {
+        putchar(b);
+    }
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
@@ -0,0 +1,146 @@
+//! 注入故障的块设备
+//!
+//! [`FaultyDevice`] 包装另一个块设备，打开读故障或写故障后所有读请求或写请求（包括刷新）都返回 [`BlockError::Device`]，
+//! 用于测试磁盘出错时错误能沿着块缓存和文件系统一直返回到系统调用，而不是让内核 panic。
+//! 打开写缓存后它模拟带易失写缓存的磁盘：写入的块留在缓存中，刷新时才写入被包装的设备，
+//! [`FaultyDevice::power_cut`] 丢弃还没有刷新的块，用于测试刷新请求是否在正确的时机发出。
+
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use fat32::{BlockDevice, BlockError, BLOCK_SZ};
+
+/// 可以让读写请求失败、可以模拟写缓存和掉电的块设备
+pub struct FaultyDevice {
+    base: Arc<dyn BlockDevice>,
+    /// 读请求是否失败
+    fail_reads: AtomicBool,
+    /// 写请求和刷新请求是否失败
+    fail_writes: AtomicBool,
+    /// 被拒绝的请求数
+    faults: AtomicUsize,
+    /// 是否打开写缓存
+    write_cache: AtomicBool,
+    /// 写缓存中还没有刷新的块
+    dirty: UPSafeCell<BTreeMap<usize, [u8; BLOCK_SZ]>>,
+}
+
+impl FaultyDevice {
+    /// 包装块设备 `base`，初始时不注入故障
+    pub fn new(base: Arc<dyn BlockDevice>) -> Self {
+        Self {
+            base,
+            fail_reads: AtomicBool::new(false),
+            fail_writes: AtomicBool::new(false),
+            faults: AtomicUsize::new(0),
+            write_cache: AtomicBool::new(false),
+            dirty: unsafe { UPSafeCell::new(BTreeMap::new()) },
+        }
+    }
+
+    /// 打开或关闭写缓存，关闭前应当先刷新
+    pub fn set_write_cache(&self, enable: bool) {
+        self.write_cache.store(enable, Ordering::Relaxed);
+    }
+
+    /// 模拟掉电：丢弃写缓存中还没有刷新的块
+    pub fn power_cut(&self) {
+        self.dirty.exclusive_access().clear();
+    }
+
+    /// 打开或关闭读故障
+    pub fn set_fail_reads(&self, fail: bool) {
+        self.fail_reads.store(fail, Ordering::Relaxed);
+    }
+
+    /// 打开或关闭写故障
+    pub fn set_fail_writes(&self, fail: bool) {
+        self.fail_writes.store(fail, Ordering::Relaxed);
+    }
+
+    /// 到目前为止被拒绝的请求数
+    pub fn faults(&self) -> usize {
+        self.faults.load(Ordering::Relaxed)
+    }
+
+    fn check_read(&self) -> Result<(), BlockError> {
+        self.check(&self.fail_reads)
+    }
+
+    fn check_write(&self) -> Result<(), BlockError> {
+        self.check(&self.fail_writes)
+    }
+
+    fn check(&self, fail: &AtomicBool) -> Result<(), BlockError> {
+        if fail.load(Ordering::Relaxed) {
+            self.faults.fetch_add(1, Ordering::Relaxed);
+            return Err(BlockError::Device);
+        }
+        Ok(())
+    }
+
+    /// 用写缓存中的块覆盖从 `start_block` 开始读出的数据
+    fn overlay_dirty(&self, start_block: usize, buf: &mut [u8]) {
+        let dirty = self.dirty.exclusive_access();
+        for (i, block) in buf.chunks_mut(BLOCK_SZ).enumerate() {
+            if let Some(data) = dirty.get(&(start_block + i)) {
+                block.copy_from_slice(data);
+            }
+        }
+    }
+
+    /// 打开写缓存时把从 `start_block` 开始的块放入缓存，返回是否已经放入
+    fn cache_write(&self, start_block: usize, buf: &[u8]) -> bool {
+        if !self.write_cache.load(Ordering::Relaxed) {
+            return false;
+        }
+        let mut dirty = self.dirty.exclusive_access();
+        for (i, block) in buf.chunks(BLOCK_SZ).enumerate() {
+            dirty.insert(start_block + i, block.try_into().unwrap());
+        }
+        true
+    }
+}
+
+impl BlockDevice for FaultyDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_read()?;
+        self.base.read_block(block_id, buf)?;
+        self.overlay_dirty(block_id, buf);
+        Ok(())
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_write()?;
+        if self.cache_write(block_id, buf) {
+            return Ok(());
+        }
+        self.base.write_block(block_id, buf)
+    }
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check_read()?;
+        self.base.read_blocks(start_block, buf)?;
+        self.overlay_dirty(start_block, buf);
+        Ok(())
+    }
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.check_write()?;
+        if self.cache_write(start_block, buf) {
+            return Ok(());
+        }
+        self.base.write_blocks(start_block, buf)
+    }
+    fn is_read_only(&self) -> bool {
+        self.base.is_read_only()
+    }
+    /// 把写缓存中的块按块号顺序写入被包装的设备，再刷新它
+    fn flush(&self) -> Result<(), BlockError> {
+        self.check_write()?;
+        let dirty = core::mem::take(&mut *self.dirty.exclusive_access());
+        for (block_id, data) in dirty.iter() {
+            self.base.write_block(*block_id, data)?;
+        }
+        self.base.flush()
+    }
+    // 中断由被包装的设备处理
+}
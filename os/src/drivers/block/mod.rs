@@ -1,19 +1,161 @@
 //! virtio_blk 设备驱动
+//!
+//! 启动时探测设备树中的每个 virtio-mmio 设备，块设备按地址顺序登记在 [`BLOCK_DEVICES`] 中，
+//! 依次命名为 vda、vdb……，磁盘上的分区命名为 vda1、vda2……。
+//! 根文件系统位于 vda 的第一个 FAT32 分区，vda 没有分区表时位于整个 vda。
+//! 文件系统和分区通过磁盘的请求队列访问磁盘，同时到达的相邻请求被合并成一次设备操作。
 
+mod fault;
+mod partition;
+mod queue;
 mod virtio_blk;
+mod virtio_mmio;
 
-pub use virtio_blk::VirtIOBlock;
+pub use fault::FaultyDevice;
+pub use partition::{partition_test, ram_disk, scan_partitions, Partition, PartitionDevice};
+pub use queue::{block_merges, request_queue_test, RequestQueue};
+pub use virtio_blk::{block_ops, block_reads, can_sleep, dma_dealloc_test, poll_io, VirtIOBlock};
+use virtio_mmio::MAX_REQUEST_BLOCKS;
 
+use super::dtb::virtio_nodes;
+use super::plic::register;
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
-use fat32::BlockDevice;
+use alloc::vec;
+use alloc::vec::Vec;
+use fat32::{BlockDevice, BlockError, BLOCK_SZ};
 use lazy_static::*;
 
-/// 定义 BlockDeviceImpl 类型为 virtio_blk::VirtIOBlock
-type BlockDeviceImpl = virtio_blk::VirtIOBlock;
+/// 一个磁盘和它的分区
+struct Disk {
+    /// PLIC 中断源
+    irq: u32,
+    device: Arc<VirtIOBlock>,
+    /// 磁盘的请求队列，文件系统和分区通过它访问磁盘
+    queue: Arc<RequestQueue>,
+    /// 分区表中的分区和访问它们的设备，没有分区表时为空
+    partitions: Vec<(Partition, Arc<dyn BlockDevice>)>,
+}
+
+impl Disk {
+    /// 磁盘上默认挂载的文件系统：第一个 FAT32 分区，没有分区表时是整个磁盘
+    fn fs_device(&self) -> Option<Arc<dyn BlockDevice>> {
+        if self.partitions.is_empty() {
+            return Some(self.queue.clone());
+        }
+        self.partitions
+            .iter()
+            .find(|(partition, _)| partition.is_fat)
+            .map(|(_, device)| device.clone())
+    }
+}
 
 lazy_static! {
-    /// 使用 lazy_static 创建一个全局的块设备驱动实例: BLOCK_DEVICE，它实现了 BlockDevice 特性
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// 探测到的磁盘，按控制寄存器的地址排列
+    static ref DISKS: Vec<Disk> = {
+        let (nodes, count) = virtio_nodes();
+        nodes[..count]
+            .iter()
+            .filter_map(|node| {
+                let device = Arc::new(VirtIOBlock::probe(node.base)?);
+                let queue = Arc::new(RequestQueue::new(device.clone()));
+                let disk: Arc<dyn BlockDevice> = queue.clone();
+                let partitions = scan_partitions(&disk, device.capacity())
+                    .into_iter()
+                    .map(|partition| {
+                        let part: Arc<dyn BlockDevice> =
+                            Arc::new(PartitionDevice::new(disk.clone(), partition.start_lba, partition.len));
+                        (partition, part)
+                    })
+                    .collect();
+                Some(Disk { irq: node.irq, device, queue, partitions })
+            })
+            .collect()
+    };
+    /// 所有磁盘，下标 i 的设备名为 vd 加上第 i 个小写字母
+    pub static ref BLOCK_DEVICES: Vec<Arc<dyn BlockDevice>> = DISKS
+        .iter()
+        .map(|disk| disk.queue.clone() as Arc<dyn BlockDevice>)
+        .collect();
+    /// 根文件系统所在的块设备
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = {
+        let vda = DISKS.first().expect("no virtio block device found");
+        for (partition, _) in vda.partitions.iter() {
+            info!(
+                "{}{}: {} sectors at {}{}",
+                block_device_name(0),
+                partition.number,
+                partition.len,
+                partition.start_lba,
+                if partition.is_fat { ", FAT32" } else { "" }
+            );
+        }
+        vda.fs_device().expect("no FAT32 partition on vda")
+    };
+}
+
+/// 第 `index` 个磁盘的名字
+pub fn block_device_name(index: usize) -> String {
+    format!("vd{}", (b'a' + index as u8) as char)
+}
+
+/// 按名字查找要挂载的块设备：vdb（或 /dev/vdb）是 vdb 上默认挂载的文件系统，
+/// 即第一个 FAT32 分区或没有分区表的整个磁盘；vdb2 是 vdb 的第 2 个分区
+pub fn block_device_by_name(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    let index = (0..DISKS.len()).find(|&index| name.starts_with(&block_device_name(index)))?;
+    let disk = &DISKS[index];
+    match &name[block_device_name(index).len()..] {
+        "" => disk.fs_device(),
+        number => {
+            let number: usize = number.parse().ok()?;
+            disk.partitions
+                .iter()
+                .find(|(partition, _)| partition.number == number)
+                .map(|(_, device)| device.clone())
+        }
+    }
+}
+
+/// 刷新所有磁盘的写缓存：之前完成的写请求都写入持久的存储后返回
+pub fn flush_disks() -> Result<(), BlockError> {
+    DISKS.iter().try_for_each(|disk| disk.queue.flush())
+}
+
+/// 在 PLIC 上登记所有磁盘的中断处理函数
+pub fn register_irqs() {
+    for disk in DISKS.iter() {
+        register(disk.irq, handle_block_irq);
+    }
+}
+
+/// 处理中断源 `irq` 上的块设备中断
+fn handle_block_irq(irq: u32) {
+    if let Some(disk) = DISKS.iter().find(|disk| disk.irq == irq) {
+        disk.device.handle_irq();
+    }
+}
+
+/// 批量读取连续的块，结果与逐块读取相同，并且每个设备请求读取多个块，
+/// 只需 `BLOCKS / MAX_REQUEST_BLOCKS`（向上取整）次设备通知
+#[allow(unused)]
+pub fn block_batch_test() {
+    const BLOCKS: usize = 40;
+    let block_device = BLOCK_DEVICE.clone();
+    let mut single = vec![0u8; BLOCKS * BLOCK_SZ];
+    for (i, block) in single.chunks_mut(BLOCK_SZ).enumerate() {
+        block_device.read_block(i, block).unwrap();
+    }
+    let (ops, reads) = (block_ops(), block_reads());
+    let mut batched = vec![0u8; BLOCKS * BLOCK_SZ];
+    block_device.read_blocks(0, &mut batched).unwrap();
+    assert!(single == batched, "batched read differs from single-block reads");
+    assert_eq!(block_reads() - reads, BLOCKS);
+    let notifies = block_ops() - ops;
+    let expected = (BLOCKS + MAX_REQUEST_BLOCKS - 1) / MAX_REQUEST_BLOCKS;
+    assert_eq!(notifies, expected, "{} blocks took {} device requests", BLOCKS, notifies);
+    info!("block_batch_test passed! {} blocks in {} device requests", BLOCKS, notifies);
 }
 
 #[allow(unused)]
@@ -31,10 +173,10 @@ pub fn block_device_test() {
         }
         
         // 写入当前块
-        block_device.write_block(i as usize, &write_buffer);
+        block_device.write_block(i as usize, &write_buffer).unwrap();
         
         // 从当前块读取数据
-        block_device.read_block(i as usize, &mut read_buffer);
+        block_device.read_block(i as usize, &mut read_buffer).unwrap();
         
         // 校验写入的数据与读取的数据是否一致
         assert_eq!(write_buffer, read_buffer);
@@ -0,0 +1,290 @@
+//! MBR / GPT 分区表
+//!
+//! [`scan_partitions`] 读取磁盘的第 0 个扇区：它是 FAT 的引导扇区时整个磁盘就是一个文件系统，
+//! 是 MBR 时列出其中的主分区，MBR 中只有一个 0xEE 类型的保护分区时改为读取 LBA 1 处的 GPT。
+//! 每个分区包装成 [`PartitionDevice`]，块号加上分区的起始扇区后再访问磁盘。
+//! 扩展分区中的逻辑分区不会被列出。
+
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use fat32::{BlockDevice, BlockError, BLOCK_SZ};
+
+/// MBR 中分区表的偏移和项数
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRIES: usize = 4;
+/// GPT 的保护分区类型
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// FAT32 分区类型（CHS 和 LBA 寻址）
+const MBR_TYPES_FAT32: [u8; 2] = [0x0b, 0x0c];
+/// GPT 最多读取的分区项数
+const GPT_MAX_ENTRIES: usize = 128;
+/// Microsoft basic data（EBD0A0A2-B9E5-4433-87C0-68B6B72699C7）和
+/// EFI 系统分区（C12A7328-F81F-11D2-BA4B-00A0C93EC93B）的类型 GUID 在磁盘上的字节序
+const GPT_TYPES_FAT: [[u8; 16]; 2] = [
+    [0xa2, 0xa0, 0xd0, 0xeb, 0xe5, 0xb9, 0x33, 0x44, 0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26, 0x99, 0xc7],
+    [0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b],
+];
+
+/// 分区表中的一个分区
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Partition {
+    /// 分区号，从 1 开始，即设备名 vda1 中的数字
+    pub number: usize,
+    /// 起始扇区
+    pub start_lba: usize,
+    /// 扇区数
+    pub len: usize,
+    /// 分区类型表明它是 FAT32 文件系统
+    pub is_fat: bool,
+}
+
+/// 磁盘上的一个分区，块号从分区的起始扇区开始计算
+pub struct PartitionDevice {
+    base: Arc<dyn BlockDevice>,
+    start_lba: usize,
+    len: usize,
+    /// 分区是否被设为只读，磁盘只读时分区也只读
+    read_only: AtomicBool,
+}
+
+impl PartitionDevice {
+    /// 磁盘 `base` 上从 `start_lba` 开始、共 `len` 个扇区的分区
+    pub fn new(base: Arc<dyn BlockDevice>, start_lba: usize, len: usize) -> Self {
+        Self { base, start_lba, len, read_only: AtomicBool::new(false) }
+    }
+
+    /// 分区中从 `block_id` 开始、占 `bytes` 字节的块在磁盘上的起始块号，越过分区末尾时返回 [`BlockError::OutOfRange`]
+    fn translate(&self, block_id: usize, bytes: usize) -> Result<usize, BlockError> {
+        let blocks = bytes.div_ceil(BLOCK_SZ);
+        match block_id.checked_add(blocks) {
+            Some(end) if end <= self.len => Ok(self.start_lba + block_id),
+            _ => Err(BlockError::OutOfRange),
+        }
+    }
+}
+
+impl BlockDevice for PartitionDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.base.read_block(self.translate(block_id, buf.len())?, buf)
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_blocks(block_id, buf)
+    }
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.base.read_blocks(self.translate(start_block, buf.len())?, buf)
+    }
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        if self.read_only.load(Ordering::Relaxed) {
+            return Err(BlockError::ReadOnly);
+        }
+        self.base.write_blocks(self.translate(start_block, buf.len())?, buf)
+    }
+    fn flush(&self) -> Result<(), BlockError> {
+        self.base.flush()
+    }
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed) || self.base.is_read_only()
+    }
+    /// 磁盘只读时分区不能设为可写
+    fn set_read_only(&self, read_only: bool) -> bool {
+        if !read_only && self.base.is_read_only() {
+            return false;
+        }
+        self.read_only.store(read_only, Ordering::Relaxed);
+        true
+    }
+    // 中断由磁盘自己处理
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// 扇区是 FAT 文件系统的引导扇区（扩展 BPB 中的文件系统类型以 FAT 开头）
+fn is_fat_boot_sector(sector: &[u8]) -> bool {
+    sector[82..85] == *b"FAT" || sector[54..57] == *b"FAT"
+}
+
+/// 读取容量为 `capacity` 个扇区的磁盘 `disk` 上的分区表。
+/// 没有分区表（整个磁盘是一个文件系统或无法识别）时返回空表，超出磁盘容量的分区被忽略
+pub fn scan_partitions(disk: &Arc<dyn BlockDevice>, capacity: usize) -> Vec<Partition> {
+    let mut sector = [0u8; BLOCK_SZ];
+    if let Err(err) = disk.read_block(0, &mut sector) {
+        warn!("failed to read the partition table: {:?}", err);
+        return Vec::new();
+    }
+    if sector[510..512] != [0x55, 0xaa] || is_fat_boot_sector(&sector) {
+        return Vec::new();
+    }
+    let entries: Vec<&[u8]> = sector[MBR_TABLE_OFFSET..MBR_TABLE_OFFSET + 16 * MBR_ENTRIES].chunks(16).collect();
+    // 引导标志只能是 0x00 或 0x80，否则这不是 MBR
+    if entries.iter().any(|entry| entry[0] & 0x7f != 0) {
+        return Vec::new();
+    }
+    let partitions = if entries.iter().any(|entry| entry[4] == MBR_TYPE_GPT_PROTECTIVE) {
+        scan_gpt(disk)
+    } else {
+        entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry[4] != 0 && read_u32(entry, 12) != 0)
+            .map(|(i, entry)| Partition {
+                number: i + 1,
+                start_lba: read_u32(entry, 8) as usize,
+                len: read_u32(entry, 12) as usize,
+                is_fat: MBR_TYPES_FAT32.contains(&entry[4]),
+            })
+            .collect()
+    };
+    partitions
+        .into_iter()
+        .filter(|partition| {
+            let inside = partition.start_lba > 0
+                && partition.start_lba.checked_add(partition.len).map_or(false, |end| end <= capacity);
+            if !inside {
+                warn!("partition {} ({} sectors at {}) is outside the disk", partition.number, partition.len, partition.start_lba);
+            }
+            inside
+        })
+        .collect()
+}
+
+/// 读取 LBA 1 处的 GPT 头和它指向的分区项数组，头部无效时返回空表
+fn scan_gpt(disk: &Arc<dyn BlockDevice>) -> Vec<Partition> {
+    let mut header = [0u8; BLOCK_SZ];
+    if let Err(err) = disk.read_block(1, &mut header) {
+        warn!("failed to read the GPT header: {:?}", err);
+        return Vec::new();
+    }
+    if header[..8] != *b"EFI PART" {
+        warn!("protective MBR without a GPT header");
+        return Vec::new();
+    }
+    let entries_lba = read_u64(&header, 72) as usize;
+    let count = (read_u32(&header, 80) as usize).min(GPT_MAX_ENTRIES);
+    let entry_size = read_u32(&header, 84) as usize;
+    if !(128..=BLOCK_SZ).contains(&entry_size) || BLOCK_SZ % entry_size != 0 {
+        warn!("unsupported GPT entry size {}", entry_size);
+        return Vec::new();
+    }
+    let mut entries = vec![0u8; (count * entry_size).div_ceil(BLOCK_SZ) * BLOCK_SZ];
+    if let Err(err) = disk.read_blocks(entries_lba, &mut entries) {
+        warn!("failed to read the GPT entries: {:?}", err);
+        return Vec::new();
+    }
+    entries
+        .chunks(entry_size)
+        .take(count)
+        .enumerate()
+        .filter(|(_, entry)| entry[..16].iter().any(|&byte| byte != 0))
+        .filter_map(|(i, entry)| {
+            let (first, last) = (read_u64(entry, 32) as usize, read_u64(entry, 40) as usize);
+            // 结束 LBA 为全 1 的分区项无法表示长度，跳过
+            let Some(end) = last.checked_add(1) else {
+                warn!("GPT partition {} ends at LBA {:#x}", i + 1, last);
+                return None;
+            };
+            Some(Partition {
+                number: i + 1,
+                start_lba: first,
+                len: end.saturating_sub(first),
+                is_fat: GPT_TYPES_FAT.iter().any(|guid| entry[..16] == guid[..]),
+            })
+        })
+        .collect()
+}
+
+/// 内存中的磁盘，用于测试
+struct RamDisk(UPSafeCell<Vec<u8>>);
+
+/// 一个 `sectors` 块、内容全为 0 的内存磁盘
+pub fn ram_disk(sectors: usize) -> Arc<dyn BlockDevice> {
+    Arc::new(RamDisk(unsafe { UPSafeCell::new(vec![0u8; sectors * BLOCK_SZ]) }))
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        let data = self.0.exclusive_access();
+        let range = block_id * BLOCK_SZ..block_id * BLOCK_SZ + buf.len();
+        buf.copy_from_slice(data.get(range).ok_or(BlockError::OutOfRange)?);
+        Ok(())
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut data = self.0.exclusive_access();
+        let range = block_id * BLOCK_SZ..block_id * BLOCK_SZ + buf.len();
+        data.get_mut(range).ok_or(BlockError::OutOfRange)?.copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// 在内存中的磁盘上构造 MBR 和 GPT 分区表，检查扫描结果和分区的块号转换
+#[allow(unused)]
+pub fn partition_test() {
+    const SECTORS: usize = 64;
+    let new_disk = || ram_disk(SECTORS);
+    let mut sector = [0u8; BLOCK_SZ];
+    sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+    // MBR：Linux 分区在 4，FAT32 分区在 16，一个超出磁盘的分区
+    let mbr = new_disk();
+    for (i, (kind, start, len)) in [(0x83u8, 4u32, 8u32), (0x0c, 16, 32), (0x0c, 60, 8)].iter().enumerate() {
+        let entry = &mut sector[MBR_TABLE_OFFSET + 16 * i..MBR_TABLE_OFFSET + 16 * (i + 1)];
+        entry[4] = *kind;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&len.to_le_bytes());
+    }
+    mbr.write_block(0, &sector).unwrap();
+    let partitions = scan_partitions(&mbr, SECTORS);
+    assert_eq!(
+        partitions,
+        [
+            Partition { number: 1, start_lba: 4, len: 8, is_fat: false },
+            Partition { number: 2, start_lba: 16, len: 32, is_fat: true },
+        ]
+    );
+    let fat = partitions.iter().find(|partition| partition.is_fat).unwrap();
+    let device = PartitionDevice::new(mbr.clone(), fat.start_lba, fat.len);
+    let data = [0x5au8; 2 * BLOCK_SZ];
+    device.write_blocks(30, &data).unwrap();
+    let mut raw = [0u8; 2 * BLOCK_SZ];
+    mbr.read_blocks(46, &mut raw).unwrap();
+    assert!(raw == data, "partition block 30 is not disk block 46");
+    // 越过分区末尾的访问返回错误而不是读到下一个分区
+    assert_eq!(device.read_blocks(31, &mut raw), Err(BlockError::OutOfRange));
+
+    // 整个磁盘是 FAT 文件系统时没有分区表
+    let mut boot = sector;
+    boot[82..90].copy_from_slice(b"FAT32   ");
+    mbr.write_block(0, &boot).unwrap();
+    assert!(scan_partitions(&mbr, SECTORS).is_empty());
+
+    // GPT：保护分区覆盖整个磁盘，第 3 项是 basic data 分区
+    let gpt = new_disk();
+    sector[MBR_TABLE_OFFSET..MBR_TABLE_OFFSET + 16 * MBR_ENTRIES].fill(0);
+    sector[MBR_TABLE_OFFSET + 4] = MBR_TYPE_GPT_PROTECTIVE;
+    gpt.write_block(0, &sector).unwrap();
+    let mut header = [0u8; BLOCK_SZ];
+    header[..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&4u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    gpt.write_block(1, &header).unwrap();
+    let mut entries = [0u8; BLOCK_SZ];
+    entries[2 * 128..2 * 128 + 16].copy_from_slice(&GPT_TYPES_FAT[0]);
+    entries[2 * 128 + 32..2 * 128 + 40].copy_from_slice(&34u64.to_le_bytes());
+    entries[2 * 128 + 40..2 * 128 + 48].copy_from_slice(&63u64.to_le_bytes());
+    // 第 1 项的结束 LBA 为全 1，长度溢出，应被跳过而不是回绕
+    entries[..16].copy_from_slice(&GPT_TYPES_FAT[0]);
+    entries[32..40].copy_from_slice(&34u64.to_le_bytes());
+    entries[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+    gpt.write_block(2, &entries).unwrap();
+    assert_eq!(scan_partitions(&gpt, SECTORS), [Partition { number: 3, start_lba: 34, len: 30, is_fat: true }]);
+    info!("partition_test passed!");
+}
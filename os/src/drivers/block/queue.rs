@@ -0,0 +1,447 @@
+//! 块设备的请求队列
+//!
+//! [`RequestQueue`] 位于文件系统的块缓存和磁盘驱动之间。多个任务同时读写磁盘时，请求先放入队列，
+//! 由其中一个任务（派发者）成批取出：按块号排序，把物理上相邻、方向相同的请求合并成一次多块的设备操作，
+//! 设备完成后把数据交给各个请求并唤醒等待它们的任务。设备正忙，或者排队的请求不到
+//! [`COALESCE_THRESHOLD`] 个而还有其他就绪的任务时，派发者在取第一批请求之前让出一次处理器，
+//! 让其他任务先放入请求，交错读取两个文件的任务的请求因此能够合并；否则立即提交，不多一次任务切换。
+//!
+//! 与排在前面的请求重叠、且其中有写请求的请求不会进入同一批，读请求总能读到排在它之前的写请求写入的数据。
+//! 刷新请求单独成批：排在它之前的写请求全部完成后才刷新设备的写缓存。
+//! 不能睡眠的请求者（见 [`poll_io`](super::poll_io)）不等待其他任务派发：
+//! 写请求放入队列后立即返回，由派发者写入设备；读请求直接读设备，
+//! 再用正在派发和排队的写请求中的数据覆盖重叠的块。
+//! 这样放入队列的请求没有人等待它的结果，失败时由之后的刷新请求报告错误，同步文件系统时就能发现数据没有写入。
+
+use super::fault::FaultyDevice;
+use super::virtio_blk::can_sleep;
+use crate::sync::UPSafeCell;
+use crate::task::{has_ready_tasks, suspend_current_and_run_next, WaitQueue};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use fat32::{BlockDevice, BlockError, BLOCK_SZ};
+
+/// 一批最多派发的请求数
+const MAX_BATCH_REQUESTS: usize = 32;
+/// 排队的请求达到这个数目时，派发者不再等待其他任务放入请求
+const COALESCE_THRESHOLD: usize = 4;
+/// 合并后的一次设备操作最多包含的块数
+const MAX_MERGE_BLOCKS: usize = 128;
+
+/// 合并到其他请求中一起执行、因此省下的设备操作数
+static MERGES: AtomicUsize = AtomicUsize::new(0);
+
+/// 启动以来在请求队列中被合并的请求数
+pub fn block_merges() -> usize {
+    MERGES.load(Ordering::Relaxed)
+}
+
+/// 请求的种类
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Read,
+    Write,
+    /// 刷新设备的写缓存
+    Flush,
+}
+
+/// 队列中的一个请求
+struct Request {
+    /// 起始块号
+    start: usize,
+    /// 块数
+    blocks: usize,
+    kind: Kind,
+    /// 写请求要写入的数据，读请求读到的数据
+    data: UPSafeCell<Vec<u8>>,
+    /// 请求完成后的结果
+    result: UPSafeCell<Option<Result<(), BlockError>>>,
+    /// 等待请求完成的任务
+    done: WaitQueue,
+    /// 请求者不等待结果，失败时记录到队列中由之后的刷新请求报告
+    detached: bool,
+}
+
+impl Request {
+    fn new(start: usize, kind: Kind, data: Vec<u8>) -> Arc<Self> {
+        Self::with(start, kind, data, false)
+    }
+
+    /// 不能睡眠的请求者放入队列、不等待结果的请求
+    fn detached(start: usize, kind: Kind, data: Vec<u8>) -> Arc<Self> {
+        Self::with(start, kind, data, true)
+    }
+
+    fn with(start: usize, kind: Kind, data: Vec<u8>, detached: bool) -> Arc<Self> {
+        Arc::new(Self {
+            start,
+            blocks: data.len() / BLOCK_SZ,
+            kind,
+            data: unsafe { UPSafeCell::new(data) },
+            result: unsafe { UPSafeCell::new(None) },
+            done: WaitQueue::new(),
+            detached,
+        })
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.blocks
+    }
+
+    /// 两个请求必须按到达的顺序执行：其中有刷新请求，或者访问了相同的块并且其中有写请求
+    fn conflicts(&self, other: &Request) -> bool {
+        if self.kind == Kind::Flush || other.kind == Kind::Flush {
+            return true;
+        }
+        (self.kind == Kind::Write || other.kind == Kind::Write)
+            && self.start < other.end()
+            && other.start < self.end()
+    }
+
+    fn complete(&self, result: Result<(), BlockError>) {
+        *self.result.exclusive_access() = Some(result);
+        self.done.wake_all();
+    }
+}
+
+struct QueueInner {
+    /// 还没有派发的请求，按到达的顺序排列
+    pending: VecDeque<Arc<Request>>,
+    /// 派发者正在执行的一批请求
+    in_flight: Vec<Arc<Request>>,
+    /// 是否有任务正在派发请求
+    dispatching: bool,
+    /// 没有人等待的请求失败时的错误，由下一个刷新请求报告
+    deferred: Option<BlockError>,
+}
+
+/// 一个磁盘的请求队列，对文件系统和分区表现为普通的块设备
+pub struct RequestQueue {
+    device: Arc<dyn BlockDevice>,
+    inner: UPSafeCell<QueueInner>,
+}
+
+impl RequestQueue {
+    /// 在块设备 `device` 前面加上请求队列
+    pub fn new(device: Arc<dyn BlockDevice>) -> Self {
+        Self {
+            device,
+            inner: unsafe {
+                UPSafeCell::new(QueueInner {
+                    pending: VecDeque::new(),
+                    in_flight: Vec::new(),
+                    dispatching: false,
+                    deferred: None,
+                })
+            },
+        }
+    }
+
+    /// 把请求放入队列并等待它完成。没有派发者时当前任务成为派发者
+    fn submit(&self, request: &Arc<Request>) -> Result<(), BlockError> {
+        let dispatch = {
+            let mut inner = self.inner.exclusive_access();
+            inner.pending.push_back(request.clone());
+            !core::mem::replace(&mut inner.dispatching, true)
+        };
+        if dispatch {
+            self.dispatch();
+        }
+        // 请求由派发者完成，内核态不响应中断，检查结果和进入等待之间不会错过唤醒
+        loop {
+            if let Some(result) = *request.result.exclusive_access() {
+                return result;
+            }
+            request.done.wait();
+        }
+    }
+
+    /// 不断取出一批请求执行，直到队列为空
+    fn dispatch(&self) {
+        // 让其他就绪的任务先放入请求，以便和当前的请求一起合并
+        if self.should_wait() {
+            suspend_current_and_run_next();
+        }
+        loop {
+            let batch = {
+                let mut inner = self.inner.exclusive_access();
+                let batch = take_batch(&mut inner.pending);
+                if batch.is_empty() {
+                    inner.dispatching = false;
+                    return;
+                }
+                inner.in_flight.clone_from(&batch);
+                batch
+            };
+            let mut rest = &batch[..];
+            while !rest.is_empty() {
+                let merged = merge_len(rest);
+                self.execute(&rest[..merged]);
+                rest = &rest[merged..];
+            }
+            self.inner.exclusive_access().in_flight.clear();
+        }
+    }
+
+    /// 把物理上相邻、方向相同的请求 `requests` 作为一次设备操作执行，完成其中的每个请求
+    fn execute(&self, requests: &[Arc<Request>]) {
+        MERGES.fetch_add(requests.len() - 1, Ordering::Relaxed);
+        let start = requests[0].start;
+        let blocks: usize = requests.iter().map(|request| request.blocks).sum();
+        let result = if requests[0].kind == Kind::Flush {
+            self.device.flush()
+        } else if requests[0].kind == Kind::Write {
+            let mut buf = Vec::with_capacity(blocks * BLOCK_SZ);
+            for request in requests {
+                buf.extend_from_slice(&request.data.exclusive_access());
+            }
+            self.device.write_blocks(start, &buf)
+        } else {
+            let mut buf = vec![0u8; blocks * BLOCK_SZ];
+            let result = self.device.read_blocks(start, &mut buf);
+            if result.is_ok() {
+                let mut chunks = buf.chunks(BLOCK_SZ);
+                for request in requests {
+                    let mut data = request.data.exclusive_access();
+                    for block in data.chunks_mut(BLOCK_SZ) {
+                        block.copy_from_slice(chunks.next().unwrap());
+                    }
+                }
+            }
+            result
+        };
+        if let Err(err) = result {
+            warn!("block request at {} ({} blocks) failed: {:?}", start, blocks, err);
+        }
+        for request in requests {
+            if let (true, Err(err)) = (request.detached, result) {
+                self.inner.exclusive_access().deferred = Some(err);
+            }
+            request.complete(result);
+        }
+    }
+
+    /// 不能睡眠的读请求在有派发者时直接读设备，
+    /// 再按到达的顺序用正在派发和排队的写请求覆盖重叠的块
+    fn read_bypass(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.device.read_blocks(start_block, buf)?;
+        let writes: Vec<Arc<Request>> = {
+            let inner = self.inner.exclusive_access();
+            inner.in_flight.iter().chain(inner.pending.iter()).filter(|request| request.kind == Kind::Write).cloned().collect()
+        };
+        let end = start_block + buf.len() / BLOCK_SZ;
+        for request in writes {
+            let (from, to) = (request.start.max(start_block), request.end().min(end));
+            if from < to {
+                let data = request.data.exclusive_access();
+                buf[(from - start_block) * BLOCK_SZ..(to - start_block) * BLOCK_SZ]
+                    .copy_from_slice(&data[(from - request.start) * BLOCK_SZ..(to - request.start) * BLOCK_SZ]);
+            }
+        }
+        Ok(())
+    }
+
+    /// 派发者是否应当先让出处理器再取第一批请求：设备正忙，反正要等它完成其他请求；
+    /// 或者排队的请求还不够合并，而其他就绪的任务可能放入更多请求
+    fn should_wait(&self) -> bool {
+        if !can_sleep() {
+            return false;
+        }
+        self.device.is_busy()
+            || (self.inner.exclusive_access().pending.len() < COALESCE_THRESHOLD && has_ready_tasks())
+    }
+
+    /// 有其他任务正在派发，而当前的请求者不能睡眠等待它
+    fn must_bypass(&self) -> bool {
+        !can_sleep() && self.inner.exclusive_access().dispatching
+    }
+}
+
+/// 从队列头部按到达的顺序取出一批请求，遇到与这一批中的请求冲突的请求时停止，
+/// 返回按起始块号排序的这一批请求
+fn take_batch(pending: &mut VecDeque<Arc<Request>>) -> Vec<Arc<Request>> {
+    let mut batch: Vec<Arc<Request>> = Vec::new();
+    while let Some(request) = pending.front() {
+        if batch.len() == MAX_BATCH_REQUESTS || batch.iter().any(|queued| queued.conflicts(request)) {
+            break;
+        }
+        batch.push(pending.pop_front().unwrap());
+    }
+    batch.sort_by_key(|request| request.start);
+    batch
+}
+
+/// 按块号排好序的请求 `requests` 中，从第一个请求开始能合并成一次设备操作的请求数
+fn merge_len(requests: &[Arc<Request>]) -> usize {
+    let first = &requests[0];
+    let (mut end, mut blocks) = (first.end(), first.blocks);
+    let mut merged = 1;
+    for request in &requests[1..] {
+        if request.kind != first.kind
+            || first.kind == Kind::Flush
+            || request.start != end
+            || blocks + request.blocks > MAX_MERGE_BLOCKS
+        {
+            break;
+        }
+        end = request.end();
+        blocks += request.blocks;
+        merged += 1;
+    }
+    merged
+}
+
+impl BlockDevice for RequestQueue {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_blocks(block_id, buf)
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_blocks(block_id, buf)
+    }
+
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        if self.must_bypass() {
+            return self.read_bypass(start_block, buf);
+        }
+        let request = Request::new(start_block, Kind::Read, vec![0u8; buf.len()]);
+        self.submit(&request)?;
+        buf.copy_from_slice(&request.data.exclusive_access());
+        Ok(())
+    }
+
+    /// 设备只读时直接拒绝。不能睡眠的请求者只把写请求放入队列，它在之后失败时由下一个刷新请求报告
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        if self.device.is_read_only() {
+            return Err(BlockError::ReadOnly);
+        }
+        if self.must_bypass() {
+            // 由派发者写入设备，之后的读请求会看到这些数据
+            let request = Request::detached(start_block, Kind::Write, buf.to_vec());
+            self.inner.exclusive_access().pending.push_back(request);
+            return Ok(());
+        }
+        self.submit(&Request::new(start_block, Kind::Write, buf.to_vec()))
+    }
+
+    /// 刷新请求排在之前到达的写请求之后执行，之前没有人等待的请求失败时返回它们的错误。
+    /// 不能睡眠的请求者只把它放入队列
+    fn flush(&self) -> Result<(), BlockError> {
+        if self.must_bypass() {
+            self.inner.exclusive_access().pending.push_back(Request::detached(0, Kind::Flush, Vec::new()));
+            return Ok(());
+        }
+        let result = self.submit(&Request::new(0, Kind::Flush, Vec::new()));
+        match self.inner.exclusive_access().deferred.take() {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.device.is_read_only()
+    }
+
+    fn set_read_only(&self, read_only: bool) -> bool {
+        self.device.set_read_only(read_only)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.device.is_busy()
+    }
+
+    fn handle_irq(&self) {
+        self.device.handle_irq();
+    }
+}
+
+/// 记录设备操作次数的块设备，用于测试
+struct CountingDevice {
+    base: Arc<dyn BlockDevice>,
+    ops: AtomicUsize,
+}
+
+impl BlockDevice for CountingDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_blocks(block_id, buf)
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_blocks(block_id, buf)
+    }
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.ops.fetch_add(1, Ordering::Relaxed);
+        self.base.read_blocks(start_block, buf)
+    }
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.ops.fetch_add(1, Ordering::Relaxed);
+        self.base.write_blocks(start_block, buf)
+    }
+}
+
+/// 在内存中的磁盘上检查请求的合并、排序和读写顺序。
+/// 启动阶段没有其他任务，这里直接向队列中放入请求，模拟派发者正在等待设备时其他任务提交的请求
+#[allow(unused)]
+pub fn request_queue_test() {
+    const SECTORS: usize = 64;
+    let disk = Arc::new(CountingDevice { base: super::partition::ram_disk(SECTORS), ops: AtomicUsize::new(0) });
+    let queue = RequestQueue::new(disk.clone());
+    let block = |fill: u8| vec![fill; BLOCK_SZ];
+
+    // 乱序到达的相邻写请求合并成一次设备操作
+    queue.inner.exclusive_access().dispatching = true;
+    for (start, fill) in [(3, 3u8), (1, 1), (2, 2), (10, 10)] {
+        queue.inner.exclusive_access().pending.push_back(Request::new(start, Kind::Write, block(fill)));
+    }
+    // 与排队的写请求重叠的读请求必须读到新数据
+    let mut buf = vec![0u8; 3 * BLOCK_SZ];
+    queue.read_bypass(0, &mut buf).unwrap();
+    assert!(buf[..BLOCK_SZ].iter().all(|&b| b == 0));
+    assert!(buf[BLOCK_SZ..2 * BLOCK_SZ].iter().all(|&b| b == 1));
+    assert!(buf[2 * BLOCK_SZ..].iter().all(|&b| b == 2));
+    let (ops, merges) = (disk.ops.load(Ordering::Relaxed), block_merges());
+    queue.dispatch();
+    assert_eq!(disk.ops.load(Ordering::Relaxed) - ops, 2, "blocks 1..4 were not merged");
+    assert_eq!(block_merges() - merges, 2);
+    let mut sector = [0u8; BLOCK_SZ];
+    for (start, fill) in [(1, 1u8), (2, 2), (3, 3), (10, 10)] {
+        disk.read_block(start, &mut sector).unwrap();
+        assert!(sector.iter().all(|&b| b == fill), "block {} was not written", start);
+    }
+
+    // 读请求排在重叠的写请求之后时不能和它进入同一批，否则按块号排序后会先读到旧数据
+    queue.inner.exclusive_access().dispatching = true;
+    let write = Request::new(20, Kind::Write, block(0xaa));
+    let read = Request::new(19, Kind::Read, vec![0u8; 2 * BLOCK_SZ]);
+    let other = Request::new(21, Kind::Read, vec![0u8; BLOCK_SZ]);
+    for request in [&write, &read, &other] {
+        queue.inner.exclusive_access().pending.push_back(request.clone());
+    }
+    queue.dispatch();
+    for request in [&write, &read, &other] {
+        assert_eq!(*request.result.exclusive_access(), Some(Ok(())));
+    }
+    assert!(read.data.exclusive_access()[BLOCK_SZ..].iter().all(|&b| b == 0xaa), "read missed the queued write");
+
+    // 没有派发者时请求直接执行
+    let ops = disk.ops.load(Ordering::Relaxed);
+    queue.write_blocks(40, &vec![7u8; 4 * BLOCK_SZ]).unwrap();
+    let mut buf = vec![0u8; 4 * BLOCK_SZ];
+    queue.read_blocks(40, &mut buf).unwrap();
+    assert!(buf.iter().all(|&b| b == 7));
+    assert_eq!(disk.ops.load(Ordering::Relaxed) - ops, 2);
+
+    // 没有人等待的写请求失败时，由之后的刷新请求报告错误
+    let faulty = Arc::new(FaultyDevice::new(super::partition::ram_disk(SECTORS)));
+    let queue = RequestQueue::new(faulty.clone());
+    queue.inner.exclusive_access().dispatching = true;
+    queue.inner.exclusive_access().pending.push_back(Request::detached(5, Kind::Write, block(5)));
+    faulty.set_fail_writes(true);
+    queue.dispatch();
+    faulty.set_fail_writes(false);
+    assert_eq!(queue.flush(), Err(BlockError::Device));
+    assert_eq!(queue.flush(), Ok(()));
+    info!("request_queue_test passed!");
+}
@@ -1,52 +1,258 @@
 use super::BlockDevice;
+use fat32::{BlockError, BLOCK_SZ};
 use crate::mm::{
-    frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
-    StepByOne, VirtAddr,
+    frame_alloc_contiguous, frame_allocator, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum, VirtAddr,
 };
 use crate::sync::UPSafeCell;
+use crate::task::{current_task, WaitQueue};
+use alloc::collections::{BTreeSet, VecDeque};
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::*;
-use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
-
-/// Virtio_Block 设备中控制寄存器的基地址
-#[allow(unused)]
-const VIRTIO0: usize = 0x10001000;
+use super::virtio_mmio::{BlkDevice, BlkResp, RespStatus, MAX_REQUEST_BLOCKS};
+use virtio_drivers::{Error, Hal};
 
 /// VirtIOBlock 驱动程序结构体，用于处理 virtio_blk 设备
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+///
+/// 请求提交后，当前任务在该请求令牌（描述符链的第一个描述符）的等待队列上睡眠，
+/// 设备完成请求时触发中断，由 [`BlockDevice::handle_irq`] 取出完成的令牌并唤醒等待者。
+/// 没有当前任务（启动阶段）或在 [`poll_io`] 中时，提交者自己轮询完成的请求。
+pub struct VirtIOBlock {
+    virtio_blk: UPSafeCell<BlkDevice>,
+    /// 已经完成、还没有被提交者取走的请求的令牌
+    completed: UPSafeCell<BTreeSet<u16>>,
+    /// 每个令牌上等待请求完成的任务
+    waiters: Vec<WaitQueue>,
+    /// 等待队列中空出描述符的任务
+    space_waiters: WaitQueue,
+    /// 设备的容量（块数）
+    capacity: usize,
+    /// 是否已经警告过设备不支持刷新
+    flush_warned: AtomicBool,
+    /// 是否拒绝写请求：磁盘写保护，或者被设为只读
+    read_only: AtomicBool,
+    /// 已经提交、还没有被提交者取走结果的请求数
+    in_flight: AtomicUsize,
+}
 
 lazy_static! {
     /// 队列帧的静态引用，用于存储和管理 VirtIO 队列的帧
     static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
 }
 
+/// 从设备读取的块数，块缓存未命中时才会读取设备
+static BLOCK_READS: AtomicUsize = AtomicUsize::new(0);
+
+/// 向设备提交的请求数，每个请求通知设备一次
+static BLOCK_OPS: AtomicUsize = AtomicUsize::new(0);
+
+/// 正在执行的 [`poll_io`] 的层数，不为 0 时块设备请求不让出处理器
+static POLL_IO_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// 启动以来从块设备读取的块数
+pub fn block_reads() -> usize {
+    BLOCK_READS.load(Ordering::Relaxed)
+}
+
+/// 启动以来向块设备提交的请求数，即通知设备的次数
+pub fn block_ops() -> usize {
+    BLOCK_OPS.load(Ordering::Relaxed)
+}
+
+/// 执行 `f`，其中的块设备请求轮询等待完成而不让出处理器。
+/// 调用者持有其他任务也会访问的 UPSafeCell（如进程的地址空间）时使用，避免等待磁盘期间被其他任务借用
+pub fn poll_io<T>(f: impl FnOnce() -> T) -> T {
+    POLL_IO_DEPTH.fetch_add(1, Ordering::Relaxed);
+    let ret = f();
+    POLL_IO_DEPTH.fetch_sub(1, Ordering::Relaxed);
+    ret
+}
+
+/// 当前的请求者能否睡眠：有当前任务并且不在 [`poll_io`] 中
+pub fn can_sleep() -> bool {
+    current_task().is_some() && POLL_IO_DEPTH.load(Ordering::Relaxed) == 0
+}
+
 impl BlockDevice for VirtIOBlock {
     /// 从虚拟块设备读取一个块
-    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0
-            .exclusive_access()
-            .read_block(block_id, buf)
-            .expect("读取 VirtIOBlk 时出错");
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_blocks(block_id, buf)
     }
 
     /// 向虚拟块设备写入一个块
-    fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0
-            .exclusive_access()
-            .write_block(block_id, buf)
-            .expect("写入 VirtIOBlk 时出错");
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_blocks(block_id, buf)
+    }
+
+    /// 从虚拟块设备读取连续的多个块：每个请求读取最多 [`MAX_REQUEST_BLOCKS`] 个块，
+    /// 所有请求一起提交后等待完成
+    fn read_blocks(&self, start_block: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        BLOCK_READS.fetch_add(buf.len() / BLOCK_SZ, Ordering::Relaxed);
+        let mut requests: Vec<&mut [u8]> = buf.chunks_mut(MAX_REQUEST_BLOCKS * BLOCK_SZ).collect();
+        self.submit_batch(requests.len(), |virtio_blk, i, resp| unsafe {
+            virtio_blk.read_blocks_nb(start_block + i * MAX_REQUEST_BLOCKS, requests[i], resp)
+        })
+    }
+
+    /// 向虚拟块设备写入连续的多个块，请求的划分与读取相同；设备只读时返回 [`BlockError::ReadOnly`]
+    fn write_blocks(&self, start_block: usize, buf: &[u8]) -> Result<(), BlockError> {
+        if self.is_read_only() {
+            return Err(BlockError::ReadOnly);
+        }
+        let requests: Vec<&[u8]> = buf.chunks(MAX_REQUEST_BLOCKS * BLOCK_SZ).collect();
+        self.submit_batch(requests.len(), |virtio_blk, i, resp| {
+            virtio_blk.write_blocks_nb(start_block + i * MAX_REQUEST_BLOCKS, requests[i], resp)
+        })
+    }
+
+    /// 刷新设备的写缓存，设备不支持刷新请求时只警告一次，什么都不做
+    fn flush(&self) -> Result<(), BlockError> {
+        if !self.virtio_blk.exclusive_access().supports_flush() {
+            if !self.flush_warned.swap(true, Ordering::Relaxed) {
+                warn!("virtio-blk does not support flush, written data may stay in its volatile cache");
+            }
+            return Ok(());
+        }
+        self.submit_batch(1, |virtio_blk, _, resp| virtio_blk.flush_nb(resp))
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// 写保护的磁盘不能设为可写
+    fn set_read_only(&self, read_only: bool) -> bool {
+        if !read_only && self.virtio_blk.exclusive_access().readonly() {
+            return false;
+        }
+        self.read_only.store(read_only, Ordering::Relaxed);
+        true
+    }
+
+    /// 设备上还有已经提交、没有完成的请求
+    fn is_busy(&self) -> bool {
+        self.in_flight.load(Ordering::Relaxed) != 0
+    }
+
+    /// 处理设备的完成中断
+    fn handle_irq(&self) {
+        self.virtio_blk.exclusive_access().ack_interrupt();
+        self.reap();
     }
 }
 
 impl VirtIOBlock {
-    #[allow(unused)]
-    /// 创建一个新的 VirtIOBlock 驱动，基地址为 VIRTIO0，适用于 virtio_blk 设备
-    pub fn new() -> Self {
-        unsafe {
-            Self(UPSafeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
-            ))
+    /// 探测控制寄存器位于 `base` 的 virtio-mmio 设备，它是块设备时创建驱动，否则返回 `None`
+    pub fn probe(base: usize) -> Option<Self> {
+        let virtio_blk = BlkDevice::new(base)?;
+        // 块设备的配置空间从控制寄存器的 0x100 处开始，第一项是以扇区为单位的容量
+        let capacity = unsafe { core::ptr::read_volatile((base + 0x100) as *const u64) } as usize;
+        let queue_size = virtio_blk.virt_queue_size() as usize;
+        let readonly = virtio_blk.readonly();
+        if readonly {
+            info!("virtio-blk at {:#x} is write-protected", base);
         }
+        Some(Self {
+            virtio_blk: unsafe { UPSafeCell::new(virtio_blk) },
+            completed: unsafe { UPSafeCell::new(BTreeSet::new()) },
+            waiters: (0..queue_size).map(|_| WaitQueue::new()).collect(),
+            space_waiters: WaitQueue::new(),
+            capacity,
+            flush_warned: AtomicBool::new(false),
+            read_only: AtomicBool::new(readonly),
+            in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    /// 设备的容量（块数）
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 连续提交 `count` 个请求（`submit` 把第 i 个请求放入队列并返回令牌），再等待它们全部完成。
+    /// 队列已满时先等待已经提交的请求完成，腾出描述符。
+    /// 请求无法提交或设备返回错误状态时，等已经提交的请求全部完成后返回 [`BlockError::Device`]
+    fn submit_batch(
+        &self,
+        count: usize,
+        mut submit: impl FnMut(&mut BlkDevice, usize, &mut BlkResp) -> virtio_drivers::Result<u16>,
+    ) -> Result<(), BlockError> {
+        // 设备完成请求时写入状态，等待期间它们不能移动
+        let mut resps: Vec<BlkResp> = (0..count).map(|_| BlkResp::default()).collect();
+        let mut pending: VecDeque<u16> = VecDeque::new();
+        let mut submitted = Ok(());
+        let mut i = 0;
+        while i < count {
+            match submit(&mut self.virtio_blk.exclusive_access(), i, &mut resps[i]) {
+                Ok(token) => {
+                    BLOCK_OPS.fetch_add(1, Ordering::Relaxed);
+                    self.in_flight.fetch_add(1, Ordering::Relaxed);
+                    pending.push_back(token);
+                    i += 1;
+                }
+                Err(Error::BufferTooSmall) => match pending.pop_front() {
+                    Some(token) => self.wait_for(token),
+                    // 队列被其他任务的请求占满
+                    None => self.wait_for_space(),
+                },
+                Err(err) => {
+                    warn!("提交 VirtIOBlk 请求时出错: {:?}", err);
+                    submitted = Err(BlockError::Device);
+                    break;
+                }
+            }
+        }
+        // 出错时也要等待已经提交的请求，设备完成前 resps 和缓冲区不能释放
+        for token in pending {
+            self.wait_for(token);
+        }
+        submitted?;
+        if let Some(resp) = resps.iter().find(|resp| resp.status() != RespStatus::Ok) {
+            warn!("VirtIOBlk 请求失败: {:?}", resp.status());
+            return Err(BlockError::Device);
+        }
+        Ok(())
+    }
+
+    /// 取出设备已经完成的所有请求，唤醒等待它们的任务
+    fn reap(&self) {
+        let mut virtio_blk = self.virtio_blk.exclusive_access();
+        while let Ok(token) = virtio_blk.pop_used() {
+            self.completed.exclusive_access().insert(token);
+            self.waiters[token as usize].wake_all();
+            self.space_waiters.wake_all();
+        }
+    }
+
+    /// 等待队列中有请求完成，腾出描述符
+    fn wait_for_space(&self) {
+        if self.can_sleep() {
+            self.space_waiters.wait();
+        } else {
+            self.reap();
+            core::hint::spin_loop();
+        }
+    }
+
+    /// 当前的请求能否睡眠等待完成中断
+    fn can_sleep(&self) -> bool {
+        can_sleep()
+    }
+
+    /// 等待令牌为 `token` 的请求完成。
+    /// 内核态不响应中断，提交请求和进入等待之间不会错过完成中断
+    fn wait_for(&self, token: u16) {
+        let sleep = self.can_sleep();
+        while !self.completed.exclusive_access().remove(&token) {
+            if sleep {
+                self.waiters[token as usize].wait();
+            } else {
+                // 其他任务的请求也可能在这里完成，一并唤醒它们
+                self.reap();
+                core::hint::spin_loop();
+            }
+        }
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -55,27 +261,22 @@ pub struct VirtioHal;
 impl Hal for VirtioHal {
     /// 分配物理页面内存，返回分配的起始物理地址
     fn dma_alloc(pages: usize) -> usize {
-        let mut ppn_base = PhysPageNum(0);
-        for i in 0..pages {
-            let frame = frame_alloc().unwrap();
-            if i == 0 {
-                ppn_base = frame.ppn; // 获取第一个页面的物理页号
-            }
-            assert_eq!(frame.ppn.0, ppn_base.0 + i); // 确保页面连续
-            QUEUE_FRAMES.exclusive_access().push(frame); // 将帧添加到队列中
-        }
+        let frames = frame_alloc_contiguous(pages).unwrap(); // 分配物理上连续的页面
+        let ppn_base = frames[0].ppn; // 获取第一个页面的物理页号
+        QUEUE_FRAMES.exclusive_access().extend(frames); // 将帧添加到队列中
         let pa: PhysAddr = ppn_base.into(); // 将物理页号转换为物理地址
         pa.0
     }
 
-    /// 释放物理页面内存
+    /// 释放物理页面内存：从队列帧中移除对应的追踪器，由追踪器的析构回收页面，
+    /// 不能再直接回收，否则追踪器销毁时会第二次回收同一个页面
     fn dma_dealloc(pa: usize, pages: usize) -> i32 {
-        let pa = PhysAddr::from(pa); // 将地址转换为 PhysAddr 类型
-        let mut ppn_base: PhysPageNum = pa.into(); // 将物理地址转换为物理页号
-        for _ in 0..pages {
-            frame_dealloc(ppn_base); // 释放相应的页面
-            ppn_base.step(); // 移动到下一个物理页
-        }
+        let ppn_base: PhysPageNum = PhysAddr::from(pa).into(); // 将物理地址转换为物理页号
+        let range = ppn_base.0..ppn_base.0 + pages;
+        let mut frames = QUEUE_FRAMES.exclusive_access();
+        let owned = frames.iter().filter(|frame| range.contains(&frame.ppn.0)).count();
+        assert_eq!(owned, pages, "DMA region at {:#x} was not allocated by dma_alloc", pa);
+        frames.retain(|frame| !range.contains(&frame.ppn.0));
         0 // 返回 0 表示成功
     }
 
@@ -92,3 +293,20 @@ impl Hal for VirtioHal {
             .0 // 返回物理地址
     }
 }
+
+/// 分配并释放两次 4 页的 DMA 区域：页面帧只被回收一次，队列帧中不留下追踪器
+#[allow(unused)]
+pub fn dma_dealloc_test() {
+    const PAGES: usize = 4;
+    let free = frame_allocator::stats().free;
+    let queued = QUEUE_FRAMES.exclusive_access().len();
+    for _ in 0..2 {
+        let pa = VirtioHal::dma_alloc(PAGES);
+        assert_eq!(QUEUE_FRAMES.exclusive_access().len(), queued + PAGES);
+        assert_eq!(frame_allocator::stats().free, free - PAGES);
+        assert_eq!(VirtioHal::dma_dealloc(pa, PAGES), 0);
+        assert_eq!(QUEUE_FRAMES.exclusive_access().len(), queued);
+        assert_eq!(frame_allocator::stats().free, free);
+    }
+    info!("dma_dealloc_test passed!");
+}
@@ -0,0 +1,294 @@
+//! 传统 virtio-mmio 接口的块设备
+//!
+//! virtio-drivers 的 `VirtIOBlk` 不协商 VIRTIO_BLK_F_FLUSH，也不能提交刷新请求，
+//! 设备可能把写入的数据留在易失的写缓存中，所以这里直接操作设备寄存器和虚拟队列，
+//! 提供与它相同的非阻塞接口和刷新请求。每个读写请求由请求头、数据和状态描述符组成，
+//! 一个请求可以读写多个连续的扇区，数据在每个页面边界处分成一个描述符；刷新请求没有数据描述符。
+//! 提交后返回第一个描述符的下标作为令牌，设备完成后由 [`BlkDevice::pop_used`] 取出。
+//! 请求头放在队列的第三页中，与第一个描述符的下标一一对应；状态写入调用者提供的 [`BlkResp`]。
+//! 设备提供 VIRTIO_BLK_F_RO 时磁盘是写保护的，驱动拒绝写请求。
+
+use super::virtio_blk::VirtioHal;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use virtio_drivers::{Error, Hal, Result};
+
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const HOST_FEATURES: usize = 0x010;
+const HOST_FEATURES_SEL: usize = 0x014;
+const GUEST_FEATURES: usize = 0x020;
+const GUEST_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FAILED: u32 = 128;
+
+/// virtio-mmio 中块设备的设备号
+const DEVICE_ID_BLOCK: u32 = 2;
+/// 磁盘是写保护的
+const VIRTIO_BLK_F_RO: u32 = 1 << 5;
+/// 设备支持刷新请求（传统接口中也表示设备有写缓存）
+const VIRTIO_BLK_F_FLUSH: u32 = 1 << 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+/// 虚拟队列的描述符数
+const QUEUE_SIZE: usize = 64;
+/// 队列占用的页数：描述符表和可用环、已用环、请求头
+const QUEUE_PAGES: usize = 3;
+const PAGE_SIZE: usize = 4096;
+const SECTOR_SIZE: usize = 512;
+/// 一个读写请求最多包含的扇区数，数据最多占用 `MAX_REQUEST_BLOCKS * SECTOR_SIZE / PAGE_SIZE + 1` 个描述符
+pub const MAX_REQUEST_BLOCKS: usize = 32;
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// 请求头
+#[repr(C)]
+struct BlkReq {
+    ty: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// 请求的完成状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RespStatus {
+    Ok,
+    IoErr,
+    Unsupported,
+    /// 设备还没有完成请求
+    NotReady,
+}
+
+/// 设备写入请求状态的位置，请求完成前不能移动
+#[repr(C)]
+pub struct BlkResp {
+    status: u8,
+}
+
+impl Default for BlkResp {
+    fn default() -> Self {
+        Self { status: u8::MAX }
+    }
+}
+
+impl BlkResp {
+    pub fn status(&self) -> RespStatus {
+        match unsafe { read_volatile(&self.status) } {
+            0 => RespStatus::Ok,
+            1 => RespStatus::IoErr,
+            2 => RespStatus::Unsupported,
+            _ => RespStatus::NotReady,
+        }
+    }
+}
+
+/// 传统接口的 virtio 块设备
+pub struct BlkDevice {
+    base: usize,
+    /// 队列的起始物理地址，与内核虚拟地址相同
+    ring: usize,
+    /// 空闲的描述符
+    free: Vec<u16>,
+    /// 下一个要检查的已用环位置
+    last_used: u16,
+    /// 是否协商了刷新请求
+    flush: bool,
+    /// 磁盘是否是写保护的
+    readonly: bool,
+}
+
+impl BlkDevice {
+    /// 如果 `base` 处是传统接口的 virtio 块设备，初始化它并协商刷新请求和写保护
+    pub fn new(base: usize) -> Option<Self> {
+        let read = |offset: usize| unsafe { read_volatile((base + offset) as *const u32) };
+        let write = |offset: usize, value: u32| unsafe { write_volatile((base + offset) as *mut u32, value) };
+        if read(MAGIC_VALUE) != 0x7472_6976 || read(DEVICE_ID) != DEVICE_ID_BLOCK {
+            return None;
+        }
+        if read(VERSION) != 1 {
+            warn!("virtio-blk at {:#x}: only the legacy interface is supported", base);
+            return None;
+        }
+        write(STATUS, 0);
+        write(STATUS, STATUS_ACKNOWLEDGE);
+        write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        write(HOST_FEATURES_SEL, 0);
+        let features = read(HOST_FEATURES) & (VIRTIO_BLK_F_FLUSH | VIRTIO_BLK_F_RO);
+        write(GUEST_FEATURES_SEL, 0);
+        write(GUEST_FEATURES, features);
+        write(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        write(QUEUE_SEL, 0);
+        if (read(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+            warn!("virtio-blk at {:#x}: queue is too small", base);
+            write(STATUS, STATUS_FAILED);
+            return None;
+        }
+        let ring = VirtioHal::dma_alloc(QUEUE_PAGES);
+        unsafe { core::ptr::write_bytes(ring as *mut u8, 0, QUEUE_PAGES * PAGE_SIZE) };
+        write(QUEUE_NUM, QUEUE_SIZE as u32);
+        write(QUEUE_ALIGN, PAGE_SIZE as u32);
+        write(QUEUE_PFN, (ring / PAGE_SIZE) as u32);
+        write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+        Some(Self {
+            base,
+            ring,
+            free: (0..QUEUE_SIZE as u16).rev().collect(),
+            last_used: 0,
+            flush: features & VIRTIO_BLK_F_FLUSH != 0,
+            readonly: features & VIRTIO_BLK_F_RO != 0,
+        })
+    }
+
+    /// 虚拟队列的描述符数
+    pub fn virt_queue_size(&self) -> u16 {
+        QUEUE_SIZE as u16
+    }
+
+    /// 设备是否支持刷新请求
+    pub fn supports_flush(&self) -> bool {
+        self.flush
+    }
+
+    /// 磁盘是否是写保护的
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// 应答设备的中断
+    pub fn ack_interrupt(&mut self) {
+        let status = unsafe { read_volatile((self.base + INTERRUPT_STATUS) as *const u32) };
+        unsafe { write_volatile((self.base + INTERRUPT_ACK) as *mut u32, status) };
+    }
+
+    /// 提交从块 `block_id` 开始读满 `buf` 的请求，返回令牌。
+    /// `buf` 的长度是扇区大小的整数倍，最多 [`MAX_REQUEST_BLOCKS`] 个扇区，否则返回 [`Error::InvalidParam`]
+    /// # Safety
+    /// 请求完成前 `buf` 和 `resp` 不能被访问或释放
+    pub unsafe fn read_blocks_nb(&mut self, block_id: usize, buf: &mut [u8], resp: &mut BlkResp) -> Result<u16> {
+        self.submit(VIRTIO_BLK_T_IN, block_id, Some((buf.as_ptr() as usize, buf.len(), true)), resp)
+    }
+
+    /// 提交把 `buf` 写入从块 `block_id` 开始的连续块的请求，返回令牌。请求完成前 `buf` 和 `resp` 不能被释放。
+    /// `buf` 的长度要求与 [`BlkDevice::read_blocks_nb`] 相同；磁盘写保护时返回 [`Error::InvalidParam`]
+    pub fn write_blocks_nb(&mut self, block_id: usize, buf: &[u8], resp: &mut BlkResp) -> Result<u16> {
+        if self.readonly {
+            return Err(Error::InvalidParam);
+        }
+        self.submit(VIRTIO_BLK_T_OUT, block_id, Some((buf.as_ptr() as usize, buf.len(), false)), resp)
+    }
+
+    /// 提交刷新请求，返回令牌：请求完成时，之前完成的写请求都已经写入持久的存储
+    pub fn flush_nb(&mut self, resp: &mut BlkResp) -> Result<u16> {
+        if !self.flush {
+            return Err(Error::InvalidParam);
+        }
+        self.submit(VIRTIO_BLK_T_FLUSH, 0, None, resp)
+    }
+
+    /// 取出一个设备已经完成的请求的令牌，回收它的描述符
+    pub fn pop_used(&mut self) -> Result<u16> {
+        let used = (self.ring + PAGE_SIZE) as *const u16;
+        fence(Ordering::SeqCst);
+        if unsafe { read_volatile(used.add(1)) } == self.last_used {
+            return Err(Error::NotReady);
+        }
+        let elem = unsafe { used.add(2 + 4 * (self.last_used as usize % QUEUE_SIZE)) as *const u32 };
+        let head = unsafe { read_volatile(elem) } as u16;
+        self.last_used = self.last_used.wrapping_add(1);
+        let mut index = head;
+        loop {
+            self.free.push(index);
+            let desc = unsafe { read_volatile(self.desc(index)) };
+            if desc.flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            index = desc.next;
+        }
+        Ok(head)
+    }
+
+    fn desc(&self, index: u16) -> *mut Descriptor {
+        (self.ring + index as usize * core::mem::size_of::<Descriptor>()) as *mut Descriptor
+    }
+
+    /// 把请求头、数据（地址、长度和设备是否写入）和状态放入描述符链并通知设备
+    fn submit(&mut self, ty: u32, sector: usize, data: Option<(usize, usize, bool)>, resp: &mut BlkResp) -> Result<u16> {
+        let mut parts: Vec<(usize, u32, u16)> = Vec::new();
+        parts.push((0, core::mem::size_of::<BlkReq>() as u32, 0));
+        if let Some((addr, len, device_writes)) = data {
+            if len == 0 || len % SECTOR_SIZE != 0 || len > MAX_REQUEST_BLOCKS * SECTOR_SIZE {
+                return Err(Error::InvalidParam);
+            }
+            let flags = if device_writes { DESC_F_WRITE } else { 0 };
+            // 虚拟地址连续的缓冲区在物理上不一定连续，每个页面单独翻译
+            let mut offset = 0;
+            while offset < len {
+                let va = addr + offset;
+                let segment = (PAGE_SIZE - va % PAGE_SIZE).min(len - offset);
+                parts.push((VirtioHal::virt_to_phys(va), segment as u32, flags));
+                offset += segment;
+            }
+        }
+        parts.push((VirtioHal::virt_to_phys(resp as *mut BlkResp as usize), 1, DESC_F_WRITE));
+        if self.free.len() < parts.len() {
+            return Err(Error::BufferTooSmall);
+        }
+        let descs: Vec<u16> = (0..parts.len()).map(|_| self.free.pop().unwrap()).collect();
+        let head = descs[0];
+        let req = self.ring + 2 * PAGE_SIZE + head as usize * core::mem::size_of::<BlkReq>();
+        unsafe { write_volatile(req as *mut BlkReq, BlkReq { ty, reserved: 0, sector: sector as u64 }) };
+        parts[0].0 = req;
+        resp.status = u8::MAX;
+        for (i, &(addr, len, flags)) in parts.iter().enumerate() {
+            let (flags, next) = match descs.get(i + 1) {
+                Some(&next) => (flags | DESC_F_NEXT, next),
+                None => (flags, 0),
+            };
+            unsafe { write_volatile(self.desc(descs[i]), Descriptor { addr: addr as u64, len, flags, next }) };
+        }
+        let avail = (self.ring + QUEUE_SIZE * core::mem::size_of::<Descriptor>()) as *mut u16;
+        unsafe {
+            let idx = read_volatile(avail.add(1));
+            write_volatile(avail.add(2 + idx as usize % QUEUE_SIZE), head);
+            fence(Ordering::SeqCst);
+            write_volatile(avail.add(1), idx.wrapping_add(1));
+        }
+        fence(Ordering::SeqCst);
+        unsafe { write_volatile((self.base + QUEUE_NOTIFY) as *mut u32, 0) };
+        Ok(head)
+    }
+}
+
+impl Drop for BlkDevice {
+    /// 复位设备后再释放队列，设备不会再访问这些页面
+    fn drop(&mut self) {
+        unsafe { write_volatile((self.base + STATUS) as *mut u32, 0) };
+        VirtioHal::dma_dealloc(self.ring, QUEUE_PAGES);
+    }
+}
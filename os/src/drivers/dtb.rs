@@ -0,0 +1,320 @@
+//! 从设备树（DTB）中找出物理内存、virtio-mmio 设备、串口、实时时钟、PLIC 和内核启动参数
+//!
+//! SBI 启动内核时在 a1 中传入设备树的物理地址。设备树所在的内存之后会被页帧分配器回收，
+//! 所以必须在 [`crate::mm::init`] 之前调用 [`parse`]，把找到的内存区域和设备记在固定大小的表中，
+//! 把 /chosen 节点的 bootargs（QEMU 的 `-append`）复制到固定大小的缓冲区中。
+//! 内核按这些设备建立 MMIO 的恒等映射（[`mmio_regions`]），按内存区域决定页帧分配器的范围。
+//!
+//! 内核识别的启动参数：`ro` 只读挂载根文件系统，`init=<路径>` 指定初始化程序，
+//! `sched=stride|fifo|rr` 选择调度策略，`loglevel=off|error|warn|info|debug|trace` 设置日志级别，
+//! `ip=` 设置 eth0 的地址。
+
+use crate::config::{PAGE_SIZE, PLIC_BASE, RTC0, RTC0_IRQ, UART0, UART0_IRQ, VIRTIO0, VIRTIO0_IRQ};
+use crate::sync::UPSafeCell;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+/// 支持的最大节点深度
+const MAX_DEPTH: usize = 16;
+/// 最多记录的 virtio-mmio 设备数，QEMU virt 机器提供 8 个
+pub const MAX_VIRTIO_NODES: usize = 8;
+/// 最多保存的启动参数字节数，更长的部分被截断
+const MAX_BOOTARGS: usize = 256;
+/// 最多记录的内存区域数
+const MAX_MEMORY_REGIONS: usize = 4;
+/// 没有设备树时 PLIC 的寄存器区域大小，足够覆盖所有 hart 的上下文
+const PLIC_SIZE: usize = 0x40_0000;
+
+/// 设备树中的一个 MMIO 设备
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MmioNode {
+    /// 控制寄存器的物理地址
+    pub base: usize,
+    /// 控制寄存器区域的大小
+    pub size: usize,
+    /// PLIC 中断源
+    pub irq: u32,
+}
+
+lazy_static! {
+    /// 按地址从小到大排列的 virtio-mmio 设备
+    static ref VIRTIO_NODES: UPSafeCell<([MmioNode; MAX_VIRTIO_NODES], usize)> = unsafe {
+        UPSafeCell::new(([MmioNode { base: 0, size: 0, irq: 0 }; MAX_VIRTIO_NODES], 0))
+    };
+    /// 第一个 ns16550a 兼容的串口
+    static ref UART_NODE: UPSafeCell<Option<MmioNode>> = unsafe { UPSafeCell::new(None) };
+    /// 第一个 goldfish 实时时钟
+    static ref RTC_NODE: UPSafeCell<Option<MmioNode>> = unsafe { UPSafeCell::new(None) };
+    /// 第一个 PLIC
+    static ref PLIC_NODE: UPSafeCell<Option<MmioNode>> = unsafe { UPSafeCell::new(None) };
+    /// 内存节点中的 (起始地址, 大小)
+    static ref MEMORY_REGIONS: UPSafeCell<([(usize, usize); MAX_MEMORY_REGIONS], usize)> =
+        unsafe { UPSafeCell::new(([(0, 0); MAX_MEMORY_REGIONS], 0)) };
+    /// /chosen 节点的 bootargs 和它的长度
+    static ref BOOTARGS: UPSafeCell<([u8; MAX_BOOTARGS], usize)> =
+        unsafe { UPSafeCell::new(([0; MAX_BOOTARGS], 0)) };
+}
+
+/// 设备树中所有 virtio-mmio 设备，按地址从小到大排列
+pub fn virtio_nodes() -> ([MmioNode; MAX_VIRTIO_NODES], usize) {
+    *VIRTIO_NODES.exclusive_access()
+}
+
+/// 设备树中的第一个 ns16550a 串口
+pub fn uart_node() -> Option<MmioNode> {
+    *UART_NODE.exclusive_access()
+}
+
+/// 设备树中的第一个 goldfish 实时时钟
+pub fn rtc_node() -> Option<MmioNode> {
+    *RTC_NODE.exclusive_access()
+}
+
+/// 设备树中的第一个 PLIC
+pub fn plic_node() -> Option<MmioNode> {
+    *PLIC_NODE.exclusive_access()
+}
+
+/// 设备树中的内存区域 (起始地址, 大小)，没有设备树时为空
+pub fn memory_regions() -> Vec<(usize, usize)> {
+    let regions = MEMORY_REGIONS.exclusive_access();
+    regions.0[..regions.1].to_vec()
+}
+
+/// 内核需要恒等映射的 MMIO 区域 (起始地址, 大小)：实时时钟、串口、所有 virtio-mmio 设备和 PLIC。
+/// 区域按页对齐，相邻或重叠的区域被合并
+pub fn mmio_regions() -> Vec<(usize, usize)> {
+    let (nodes, count) = virtio_nodes();
+    let mut ranges: Vec<(usize, usize)> = nodes[..count]
+        .iter()
+        .copied()
+        .chain(rtc_node())
+        .chain(uart_node())
+        .chain(plic_node())
+        .map(|node| (node.base & !(PAGE_SIZE - 1), (node.base + node.size).next_multiple_of(PAGE_SIZE)))
+        .collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged.into_iter().map(|(start, end)| (start, end - start)).collect()
+}
+
+/// 内核启动参数，设备树中没有 bootargs 时为空字符串
+pub fn bootargs() -> String {
+    let bootargs = BOOTARGS.exclusive_access();
+    String::from_utf8_lossy(&bootargs.0[..bootargs.1]).into_owned()
+}
+
+/// 启动参数中 `name=value` 形式的参数 `name` 的值
+pub fn bootarg(name: &str) -> Option<String> {
+    bootargs()
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix(name)?.strip_prefix('=').map(String::from))
+}
+
+/// 解析物理地址 `dtb` 处的设备树，记下其中的内存区域、virtio-mmio 设备、串口、实时时钟、PLIC 和启动参数。
+/// 没有设备树（`dtb` 为 0 或格式不对）时只记下默认的第一个 virtio 设备和 QEMU virt 机器的串口、实时时钟和 PLIC，
+/// 内存大小使用编译时的 [`crate::config::MEMORY_END`]
+pub fn parse(dtb: usize) {
+    let mut table = VIRTIO_NODES.exclusive_access();
+    let (nodes, count) = &mut *table;
+    let mut uart = UART_NODE.exclusive_access();
+    let mut rtc = RTC_NODE.exclusive_access();
+    let mut plic = PLIC_NODE.exclusive_access();
+    let mut memory = MEMORY_REGIONS.exclusive_access();
+    *count = 0;
+    if dtb == 0 || unsafe { read_be32(dtb) } != FDT_MAGIC {
+        warn!("no device tree at {:#x}, assuming a single virtio disk", dtb);
+        nodes[0] = MmioNode { base: VIRTIO0, size: 0x1000, irq: VIRTIO0_IRQ };
+        *count = 1;
+        *uart = Some(MmioNode { base: UART0, size: 0x100, irq: UART0_IRQ });
+        *rtc = Some(MmioNode { base: RTC0, size: 0x1000, irq: RTC0_IRQ });
+        *plic = Some(MmioNode { base: PLIC_BASE, size: PLIC_SIZE, irq: 0 });
+        return;
+    }
+    let mut bootargs = BOOTARGS.exclusive_access();
+    unsafe { walk(dtb, |found| match found {
+        Found::Virtio(node) => {
+            if *count < MAX_VIRTIO_NODES {
+                nodes[*count] = node;
+                *count += 1;
+            }
+        }
+        Found::Uart(node) => {
+            uart.get_or_insert(node);
+        }
+        Found::Rtc(node) => {
+            rtc.get_or_insert(node);
+        }
+        Found::Plic(node) => {
+            plic.get_or_insert(node);
+        }
+        Found::Memory(base, size) => {
+            let (regions, count) = &mut *memory;
+            if *count < MAX_MEMORY_REGIONS {
+                regions[*count] = (base, size);
+                *count += 1;
+            }
+        }
+        Found::Bootargs(args) => {
+            let len = args.len().min(MAX_BOOTARGS);
+            bootargs.0[..len].copy_from_slice(&args[..len]);
+            bootargs.1 = len;
+        }
+    }) };
+    nodes[..*count].sort_unstable_by_key(|node| node.base);
+}
+
+/// 遍历设备树时找到的内容
+enum Found<'a> {
+    /// compatible 含有 "virtio,mmio" 的节点
+    Virtio(MmioNode),
+    /// compatible 含有 "ns16550a" 的节点
+    Uart(MmioNode),
+    /// compatible 含有 "google,goldfish-rtc" 的节点
+    Rtc(MmioNode),
+    /// compatible 含有 "riscv,plic0" 或 "sifive,plic-1.0.0" 的节点
+    Plic(MmioNode),
+    /// device_type 为 "memory" 的节点的 (起始地址, 大小)
+    Memory(usize, usize),
+    /// /chosen 节点的 bootargs，不含结尾的 `\0`
+    Bootargs(&'a [u8]),
+}
+
+unsafe fn read_be32(addr: usize) -> u32 {
+    u32::from_be((addr as *const u32).read_unaligned())
+}
+
+/// 以 `\0` 结尾的字符串
+unsafe fn c_str<'a>(addr: usize) -> &'a [u8] {
+    let mut len = 0;
+    while *((addr + len) as *const u8) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(addr as *const u8, len)
+}
+
+/// 读取 `cells` 个 32 位单元组成的大端数
+unsafe fn read_cells(addr: usize, cells: usize) -> usize {
+    (0..cells).fold(0, |value, i| (value << 32) | read_be32(addr + 4 * i) as usize)
+}
+
+/// 遍历设备树的结构块，对找到的内存区域、virtio-mmio 设备、串口、实时时钟、PLIC 和启动参数调用 `found`
+unsafe fn walk(dtb: usize, mut found: impl FnMut(Found)) {
+    let structs = dtb + read_be32(dtb + 8) as usize;
+    let strings = dtb + read_be32(dtb + 12) as usize;
+    // 每一层节点的子节点使用的 (#address-cells, #size-cells)
+    let mut cells = [(2usize, 1usize); MAX_DEPTH];
+    let mut depth = 0;
+    // 当前节点的 compatible 是否含有 virtio,mmio、ns16550a、google,goldfish-rtc 或 PLIC，
+    // 是否是内存节点，以及它的 reg 和 interrupts
+    let mut virtio = false;
+    let mut uart = false;
+    let mut rtc = false;
+    let mut plic = false;
+    let mut memory = false;
+    let mut reg = None;
+    let mut irq = 0;
+    // 当前节点是否是根节点下的 chosen
+    let mut in_chosen = false;
+    let mut pos = structs;
+    loop {
+        let token = read_be32(pos);
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(pos);
+                pos += (name.len() + 4) & !3;
+                depth += 1;
+                if depth >= MAX_DEPTH {
+                    warn!("device tree is nested too deeply");
+                    return;
+                }
+                cells[depth] = (2, 1);
+                in_chosen = depth == 2 && name == b"chosen";
+                virtio = false;
+                uart = false;
+                rtc = false;
+                plic = false;
+                memory = false;
+                reg = None;
+                irq = 0;
+            }
+            FDT_END_NODE => {
+                if let Some((base, size)) = reg {
+                    if virtio {
+                        found(Found::Virtio(MmioNode { base, size, irq }));
+                    } else if uart {
+                        found(Found::Uart(MmioNode { base, size, irq }));
+                    } else if rtc {
+                        found(Found::Rtc(MmioNode { base, size, irq }));
+                    } else if plic {
+                        found(Found::Plic(MmioNode { base, size, irq }));
+                    } else if memory {
+                        found(Found::Memory(base, size));
+                    }
+                }
+                virtio = false;
+                uart = false;
+                rtc = false;
+                plic = false;
+                memory = false;
+                in_chosen = false;
+                depth = depth.saturating_sub(1);
+            }
+            FDT_PROP => {
+                let len = read_be32(pos) as usize;
+                let name = c_str(strings + read_be32(pos + 4) as usize);
+                let value = pos + 8;
+                pos = value + ((len + 3) & !3);
+                match name {
+                    b"compatible" => {
+                        let list = core::slice::from_raw_parts(value as *const u8, len);
+                        virtio = list.split(|&b| b == 0).any(|compat| compat == b"virtio,mmio");
+                        uart = list.split(|&b| b == 0).any(|compat| compat == b"ns16550a");
+                        rtc = list.split(|&b| b == 0).any(|compat| compat == b"google,goldfish-rtc");
+                        plic = list
+                            .split(|&b| b == 0)
+                            .any(|compat| compat == b"riscv,plic0" || compat == b"sifive,plic-1.0.0");
+                    }
+                    b"device_type" => memory = c_str(value) == b"memory",
+                    b"#address-cells" => cells[depth].0 = read_be32(value) as usize,
+                    b"#size-cells" => cells[depth].1 = read_be32(value) as usize,
+                    b"reg" => {
+                        let (address_cells, size_cells) = cells[depth - 1];
+                        if len >= 4 * (address_cells + size_cells) {
+                            reg = Some((
+                                read_cells(value, address_cells),
+                                read_cells(value + 4 * address_cells, size_cells),
+                            ));
+                        }
+                    }
+                    b"interrupts" if len >= 4 => irq = read_be32(value),
+                    b"bootargs" if in_chosen => {
+                        let args = core::slice::from_raw_parts(value as *const u8, len);
+                        found(Found::Bootargs(args.split(|&b| b == 0).next().unwrap_or(&[])));
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => return,
+            token => {
+                warn!("bad device tree token {:#x}", token);
+                return;
+            }
+        }
+    }
+}
@@ -1,5 +1,34 @@
-//! block device driver
+//! block and network device drivers, the UART, the real-time clock and the
+//! interrupt controller
 
 pub mod block;
+pub mod dtb;
+pub mod net;
+pub mod plic;
+pub mod rtc;
+pub mod uart;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{block_device_by_name, block_merges, block_ops, block_reads, can_sleep, flush_disks, poll_io, BLOCK_DEVICE, BLOCK_DEVICES};
+
+/// Register the interrupt handlers of every probed device with the PLIC and
+/// let the boot hart take them
+pub fn init_interrupts() {
+    plic::init();
+    block::register_irqs();
+    net::register_irqs();
+    uart::register_irq();
+    plic::init_hart(plic::BOOT_HART);
+}
+
+/// Claim and handle every pending external interrupt
+pub fn handle_external_interrupts() {
+    plic::dispatch(plic::BOOT_HART);
+    // hand the received frames to the protocol stack once every interrupt is acknowledged
+    if net::take_rx_pending() {
+        crate::net::poll();
+    }
+    // feed the typed bytes to the line discipline and wake the readers
+    if uart::take_rx_pending() {
+        crate::fs::poll_console_input();
+    }
+}
@@ -0,0 +1,49 @@
+//! 回环网卡
+//!
+//! 发送的帧直接进入自己的接收队列，由协议栈在下一次轮询时取出，
+//! 与 Linux 的 lo 一样使用全零的 MAC 地址，不需要 ARP。
+
+use super::NetDevice;
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// 回环网卡的接收队列中最多缓存的帧数
+const LOOPBACK_QUEUE_LEN: usize = 64;
+
+/// 回环网卡
+pub struct Loopback {
+    queue: UPSafeCell<VecDeque<Vec<u8>>>,
+}
+
+impl Loopback {
+    /// 创建接收队列为空的回环网卡
+    pub fn new() -> Self {
+        Self { queue: unsafe { UPSafeCell::new(VecDeque::new()) } }
+    }
+}
+
+impl Default for Loopback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetDevice for Loopback {
+    fn mac(&self) -> [u8; 6] {
+        [0; 6]
+    }
+
+    fn send(&self, frame: &[u8]) -> bool {
+        let mut queue = self.queue.exclusive_access();
+        if queue.len() >= LOOPBACK_QUEUE_LEN {
+            return false;
+        }
+        queue.push_back(frame.to_vec());
+        true
+    }
+
+    fn recv(&self) -> Option<Vec<u8>> {
+        self.queue.exclusive_access().pop_front()
+    }
+}
@@ -0,0 +1,73 @@
+//! 网卡驱动
+//!
+//! 启动时探测设备树中的 virtio-mmio 网卡，按地址顺序登记在 [`NET_DEVICES`] 中；
+//! 另外总有一个回环网卡 [`LOOPBACK`]。网卡只负责收发以太网帧，协议由 [`crate::net`] 处理。
+
+mod loopback;
+mod virtio_net;
+
+pub use loopback::Loopback;
+pub use virtio_net::{VirtIONet, MAX_FRAME_LEN};
+
+use super::dtb::virtio_nodes;
+use super::plic::register;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// 收发以太网帧的网卡
+pub trait NetDevice: Send + Sync {
+    /// 网卡的 MAC 地址
+    fn mac(&self) -> [u8; 6];
+    /// 发送一个以太网帧，发送缓冲区已满或帧太长时丢弃它并返回 false
+    fn send(&self, frame: &[u8]) -> bool;
+    /// 取出一个已经收到的以太网帧
+    fn recv(&self) -> Option<Vec<u8>>;
+    /// 处理网卡的中断，把收到的帧放入接收队列
+    fn handle_irq(&self) {}
+}
+
+lazy_static! {
+    /// 探测到的网卡和它们的 PLIC 中断源，按控制寄存器的地址排列
+    pub static ref NET_DEVICES: Vec<(u32, Arc<dyn NetDevice>)> = {
+        let (nodes, count) = virtio_nodes();
+        nodes[..count]
+            .iter()
+            .filter_map(|node| {
+                let device: Arc<dyn NetDevice> = Arc::new(VirtIONet::probe(node.base)?);
+                let mac = device.mac();
+                info!(
+                    "virtio-net at {:#x}: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                    node.base, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+                );
+                Some((node.irq, device))
+            })
+            .collect()
+    };
+    /// 回环网卡
+    pub static ref LOOPBACK: Arc<dyn NetDevice> = Arc::new(Loopback::new());
+}
+
+/// 网卡中断处理后还没有交给协议栈的帧
+static RX_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 在 PLIC 上登记所有网卡的中断处理函数
+pub fn register_irqs() {
+    for (irq, _) in NET_DEVICES.iter() {
+        register(*irq, handle_net_irq);
+    }
+}
+
+/// 处理中断源 `irq` 上的网卡中断，收到的帧在所有中断处理完之后才交给协议栈
+fn handle_net_irq(irq: u32) {
+    if let Some((_, device)) = NET_DEVICES.iter().find(|(net_irq, _)| *net_irq == irq) {
+        device.handle_irq();
+        RX_PENDING.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 上次调用以来是否处理过网卡中断
+pub fn take_rx_pending() -> bool {
+    RX_PENDING.swap(false, Ordering::Relaxed)
+}
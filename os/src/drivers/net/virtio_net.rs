@@ -0,0 +1,290 @@
+//! virtio-net 设备驱动（传统 virtio-mmio 接口）
+//!
+//! virtio-drivers 的 `VirtIONet::recv` 提交接收缓冲区后忙等数据到达，不能用于中断驱动的接收，
+//! 所以这里直接操作设备寄存器和两个虚拟队列：接收队列预先放入全部接收缓冲区，
+//! 设备收到帧后触发中断，由 [`VirtIONet::handle_irq`] 把帧复制到接收队列并重新放回缓冲区；
+//! 发送时把帧复制到空闲的发送缓冲区，设备用完后在下一次发送或中断时回收。
+//! 每个帧之前有一个 [`NET_HDR_LEN`] 字节的 virtio_net_hdr，按传统接口的要求放在单独的描述符中。
+
+use super::NetDevice;
+use crate::mm::{frame_alloc_contiguous, FrameTracker, PhysAddr};
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const HOST_FEATURES: usize = 0x010;
+const HOST_FEATURES_SEL: usize = 0x014;
+const GUEST_FEATURES: usize = 0x020;
+const GUEST_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+/// 设备配置空间，网卡的前 6 个字节是 MAC 地址
+const CONFIG: usize = 0x100;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FAILED: u32 = 128;
+
+/// 设备在配置空间中提供 MAC 地址
+const VIRTIO_NET_F_MAC: u32 = 1 << 5;
+/// virtio-mmio 中网卡的设备号
+const DEVICE_ID_NET: u32 = 1;
+
+const QUEUE_RECEIVE: u32 = 0;
+const QUEUE_TRANSMIT: u32 = 1;
+/// 每个虚拟队列的描述符数，每个缓冲区占两个描述符（头部和数据）
+const QUEUE_SIZE: usize = 32;
+/// 每个队列的缓冲区数
+const BUFFERS: usize = QUEUE_SIZE / 2;
+/// 每个缓冲区的大小，头部在开头，数据从 [`DATA_OFFSET`] 开始
+const BUFFER_SIZE: usize = 2048;
+const DATA_OFFSET: usize = 16;
+/// 没有协商任何选项时 virtio_net_hdr 的长度
+const NET_HDR_LEN: usize = 10;
+/// 以太网帧（不含 FCS）的最大长度
+pub const MAX_FRAME_LEN: usize = 1514;
+/// 接收队列中最多缓存的帧数，协议栈来不及处理时丢弃新的帧
+const RX_QUEUE_LEN: usize = 64;
+
+const PAGE_SIZE: usize = 4096;
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// 一个传统布局的虚拟队列：描述符表、可用环，从下一页开始是已用环
+struct VirtQueue {
+    /// 队列占用的页面，物理地址与内核虚拟地址相同
+    _ring: Vec<FrameTracker>,
+    /// 各个缓冲区占用的页面
+    _buffers: Vec<FrameTracker>,
+    ring: usize,
+    buffers: usize,
+    /// 下一个要检查的已用环位置
+    last_used: u16,
+}
+
+impl VirtQueue {
+    /// 分配队列和缓冲区，把第 i 个缓冲区的头部和数据分别放在描述符 2i 和 2i + 1 中
+    fn new(device_writable: bool) -> Option<Self> {
+        let ring_frames = frame_alloc_contiguous(2)?;
+        let buffer_frames = frame_alloc_contiguous(BUFFERS * BUFFER_SIZE / PAGE_SIZE)?;
+        let ring = PhysAddr::from(ring_frames[0].ppn).0;
+        let buffers = PhysAddr::from(buffer_frames[0].ppn).0;
+        let queue = Self { _ring: ring_frames, _buffers: buffer_frames, ring, buffers, last_used: 0 };
+        let flags = if device_writable { DESC_F_WRITE } else { 0 };
+        for i in 0..BUFFERS {
+            let buffer = queue.buffer(i as u16);
+            queue.set_desc(2 * i, buffer, NET_HDR_LEN as u32, flags | DESC_F_NEXT, 2 * i as u16 + 1);
+            queue.set_desc(2 * i + 1, buffer + DATA_OFFSET, (BUFFER_SIZE - DATA_OFFSET) as u32, flags, 0);
+        }
+        Some(queue)
+    }
+
+    fn buffer(&self, index: u16) -> usize {
+        self.buffers + index as usize * BUFFER_SIZE
+    }
+
+    fn set_desc(&self, index: usize, addr: usize, len: u32, flags: u16, next: u16) {
+        let desc = (self.ring + index * core::mem::size_of::<Descriptor>()) as *mut Descriptor;
+        unsafe { write_volatile(desc, Descriptor { addr: addr as u64, len, flags, next }) };
+    }
+
+    fn set_data_len(&self, index: u16, len: usize) {
+        let desc = (self.ring + (2 * index as usize + 1) * core::mem::size_of::<Descriptor>()) as *mut Descriptor;
+        unsafe { write_volatile(&mut (*desc).len, len as u32) };
+    }
+
+    fn avail(&self) -> *mut u16 {
+        (self.ring + QUEUE_SIZE * core::mem::size_of::<Descriptor>()) as *mut u16
+    }
+
+    fn used(&self) -> *mut u16 {
+        (self.ring + PAGE_SIZE) as *mut u16
+    }
+
+    /// 把第 `index` 个缓冲区放入可用环，调用者之后需要通知设备
+    fn push(&self, index: u16) {
+        let avail = self.avail();
+        unsafe {
+            let idx = read_volatile(avail.add(1));
+            write_volatile(avail.add(2 + idx as usize % QUEUE_SIZE), 2 * index);
+            fence(Ordering::SeqCst);
+            write_volatile(avail.add(1), idx.wrapping_add(1));
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// 取出一个设备用完的缓冲区，返回它的下标和设备写入的总长度
+    fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used = self.used();
+        fence(Ordering::SeqCst);
+        if unsafe { read_volatile(used.add(1)) } == self.last_used {
+            return None;
+        }
+        let elem = unsafe { used.add(2 + 4 * (self.last_used as usize % QUEUE_SIZE)) as *const u32 };
+        let (id, len) = unsafe { (read_volatile(elem), read_volatile(elem.add(1))) };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some(((id / 2) as u16, len as usize))
+    }
+}
+
+struct NetInner {
+    rx: VirtQueue,
+    tx: VirtQueue,
+    /// 空闲的发送缓冲区
+    tx_free: Vec<u16>,
+    /// 已经收到、还没有被协议栈取走的帧
+    received: VecDeque<Vec<u8>>,
+}
+
+/// virtio-net 网卡
+pub struct VirtIONet {
+    base: usize,
+    mac: [u8; 6],
+    inner: UPSafeCell<NetInner>,
+}
+
+impl VirtIONet {
+    /// 如果 `base` 处是传统接口的 virtio 网卡，初始化它并放入全部接收缓冲区
+    pub fn probe(base: usize) -> Option<Self> {
+        let read = |offset: usize| unsafe { read_volatile((base + offset) as *const u32) };
+        let write = |offset: usize, value: u32| unsafe { write_volatile((base + offset) as *mut u32, value) };
+        if read(MAGIC_VALUE) != 0x7472_6976 || read(DEVICE_ID) != DEVICE_ID_NET {
+            return None;
+        }
+        if read(VERSION) != 1 {
+            warn!("virtio-net at {:#x}: only the legacy interface is supported", base);
+            return None;
+        }
+        write(STATUS, 0);
+        write(STATUS, STATUS_ACKNOWLEDGE);
+        write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        // 只接受设备提供的 MAC 地址，不使用校验和卸载、合并接收缓冲区等选项
+        write(HOST_FEATURES_SEL, 0);
+        let features = read(HOST_FEATURES) & VIRTIO_NET_F_MAC;
+        write(GUEST_FEATURES_SEL, 0);
+        write(GUEST_FEATURES, features);
+        write(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        let (Some(rx), Some(tx)) = (VirtQueue::new(true), VirtQueue::new(false)) else {
+            write(STATUS, STATUS_FAILED);
+            return None;
+        };
+        for (index, queue) in [(QUEUE_RECEIVE, &rx), (QUEUE_TRANSMIT, &tx)] {
+            write(QUEUE_SEL, index);
+            if (read(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+                warn!("virtio-net at {:#x}: queue {} is too small", base, index);
+                write(STATUS, STATUS_FAILED);
+                return None;
+            }
+            write(QUEUE_NUM, QUEUE_SIZE as u32);
+            write(QUEUE_ALIGN, PAGE_SIZE as u32);
+            write(QUEUE_PFN, (queue.ring / PAGE_SIZE) as u32);
+        }
+        let mut mac = [0u8; 6];
+        if features & VIRTIO_NET_F_MAC != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = unsafe { read_volatile((base + CONFIG + i) as *const u8) };
+            }
+        } else {
+            // 本地管理的单播地址
+            mac = [0x02, 0, 0, 0, 0, 1];
+        }
+        for i in 0..BUFFERS {
+            rx.push(i as u16);
+        }
+        write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+        write(QUEUE_NOTIFY, QUEUE_RECEIVE);
+        let inner = NetInner {
+            rx,
+            tx,
+            tx_free: (0..BUFFERS as u16).collect(),
+            received: VecDeque::new(),
+        };
+        Some(Self { base, mac, inner: unsafe { UPSafeCell::new(inner) } })
+    }
+
+    fn notify(&self, queue: u32) {
+        unsafe { write_volatile((self.base + QUEUE_NOTIFY) as *mut u32, queue) };
+    }
+
+    /// 回收设备已经发送完的缓冲区
+    fn reclaim_tx(inner: &mut NetInner) {
+        while let Some((index, _)) = inner.tx.pop_used() {
+            inner.tx_free.push(index);
+        }
+    }
+}
+
+impl NetDevice for VirtIONet {
+    fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn send(&self, frame: &[u8]) -> bool {
+        if frame.len() > MAX_FRAME_LEN {
+            return false;
+        }
+        let mut inner = self.inner.exclusive_access();
+        Self::reclaim_tx(&mut inner);
+        let Some(index) = inner.tx_free.pop() else {
+            return false;
+        };
+        let buffer = inner.tx.buffer(index);
+        unsafe {
+            core::ptr::write_bytes(buffer as *mut u8, 0, NET_HDR_LEN);
+            core::ptr::copy_nonoverlapping(frame.as_ptr(), (buffer + DATA_OFFSET) as *mut u8, frame.len());
+        }
+        inner.tx.set_data_len(index, frame.len());
+        inner.tx.push(index);
+        drop(inner);
+        self.notify(QUEUE_TRANSMIT);
+        true
+    }
+
+    fn recv(&self) -> Option<Vec<u8>> {
+        self.inner.exclusive_access().received.pop_front()
+    }
+
+    fn handle_irq(&self) {
+        let status = unsafe { read_volatile((self.base + INTERRUPT_STATUS) as *const u32) };
+        unsafe { write_volatile((self.base + INTERRUPT_ACK) as *mut u32, status) };
+        let mut inner = self.inner.exclusive_access();
+        let mut reposted = false;
+        while let Some((index, len)) = inner.rx.pop_used() {
+            let frame_len = len.saturating_sub(NET_HDR_LEN).min(BUFFER_SIZE - DATA_OFFSET);
+            if inner.received.len() < RX_QUEUE_LEN {
+                let data = (inner.rx.buffer(index) + DATA_OFFSET) as *const u8;
+                let frame = unsafe { core::slice::from_raw_parts(data, frame_len) }.to_vec();
+                inner.received.push_back(frame);
+            }
+            inner.rx.push(index);
+            reposted = true;
+        }
+        Self::reclaim_tx(&mut inner);
+        drop(inner);
+        if reposted {
+            self.notify(QUEUE_RECEIVE);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! 平台级中断控制器（PLIC）
+//!
+//! 每个 hart 的 S 态有自己的上下文：打开的中断源、优先级阈值和认领/完成寄存器。
+//! 驱动用 [`register`] 登记中断源的处理函数，PLIC 在启动 hart 的上下文中打开这个中断源；
+//! 外部中断到来时 [`dispatch`] 认领（claim）待处理的中断源，调用登记的处理函数，再通知 PLIC 处理完成（complete）。
+//! 接口都带有 hart 参数，目前只使用 [`BOOT_HART`]，支持多核后每个 hart 打开自己的上下文。
+//! PLIC 的地址由 [`init`] 从设备树中取得。
+
+use super::dtb::plic_node;
+use crate::config::PLIC_BASE;
+use crate::sync::UPSafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// 启动 hart 的编号
+pub const BOOT_HART: usize = 0;
+/// 中断源编号的上限，QEMU virt 机器的 PLIC 有 96 个中断源，0 号保留
+const MAX_IRQS: usize = 128;
+
+/// 中断源的处理函数，参数是中断源编号
+pub type IrqHandler = fn(u32);
+
+/// PLIC 寄存器的基地址
+static BASE: AtomicUsize = AtomicUsize::new(PLIC_BASE);
+
+lazy_static! {
+    /// 中断源 -> 处理函数
+    static ref HANDLERS: UPSafeCell<[Option<IrqHandler>; MAX_IRQS]> = unsafe { UPSafeCell::new([None; MAX_IRQS]) };
+}
+
+/// hart `hart` 的 S 态上下文编号，每个 hart 依次有 M 态和 S 态两个上下文
+fn s_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+/// 从设备树中取得 PLIC 的地址，必须在登记任何中断源之前调用
+pub fn init() {
+    match plic_node() {
+        Some(node) => BASE.store(node.base, Ordering::Relaxed),
+        None => warn!("no PLIC in the device tree, assuming {:#x}", PLIC_BASE),
+    }
+}
+
+fn base() -> usize {
+    BASE.load(Ordering::Relaxed)
+}
+
+fn priority(irq: u32) -> *mut u32 {
+    (base() + 4 * irq as usize) as *mut u32
+}
+
+fn enable_word(hart: usize, irq: u32) -> *mut u32 {
+    (base() + 0x2000 + 0x80 * s_context(hart) + 4 * (irq as usize / 32)) as *mut u32
+}
+
+fn threshold(hart: usize) -> *mut u32 {
+    (base() + 0x20_0000 + 0x1000 * s_context(hart)) as *mut u32
+}
+
+fn claim_complete(hart: usize) -> *mut u32 {
+    (base() + 0x20_0004 + 0x1000 * s_context(hart)) as *mut u32
+}
+
+/// 让 hart `hart` 的 S 态接受所有优先级不为 0 的中断
+pub fn init_hart(hart: usize) {
+    unsafe { threshold(hart).write_volatile(0) }
+}
+
+/// 在 hart `hart` 的 S 态打开中断源 `irq`
+pub fn enable(hart: usize, irq: u32) {
+    let word = enable_word(hart, irq);
+    unsafe { word.write_volatile(word.read_volatile() | 1 << (irq % 32)) }
+}
+
+/// 在 hart `hart` 的 S 态关闭中断源 `irq`
+pub fn disable(hart: usize, irq: u32) {
+    let word = enable_word(hart, irq);
+    unsafe { word.write_volatile(word.read_volatile() & !(1 << (irq % 32))) }
+}
+
+/// 登记中断源 `irq` 的处理函数，并在启动 hart 上以优先级 1 打开它
+pub fn register(irq: u32, handler: IrqHandler) {
+    assert!(irq != 0 && (irq as usize) < MAX_IRQS, "invalid PLIC interrupt source {}", irq);
+    HANDLERS.exclusive_access()[irq as usize] = Some(handler);
+    unsafe { priority(irq).write_volatile(1) };
+    enable(BOOT_HART, irq);
+}
+
+/// 登记了处理函数的中断源
+pub fn registered_irqs() -> impl Iterator<Item = u32> {
+    let handlers = *HANDLERS.exclusive_access();
+    (1..MAX_IRQS as u32).filter(move |&irq| handlers[irq as usize].is_some())
+}
+
+/// 认领 hart `hart` 上一个待处理的中断源，没有时（包括虚假的中断）返回 `None`
+pub fn claim(hart: usize) -> Option<u32> {
+    match unsafe { claim_complete(hart).read_volatile() } {
+        0 => None,
+        irq => Some(irq),
+    }
+}
+
+/// 通知 PLIC hart `hart` 已经处理完中断源 `irq`，之后它可以再次触发
+pub fn complete(hart: usize, irq: u32) {
+    unsafe { claim_complete(hart).write_volatile(irq) }
+}
+
+/// 认领并处理 hart `hart` 上所有待处理的中断源，没有登记处理函数的中断源只警告
+pub fn dispatch(hart: usize) {
+    while let Some(irq) = claim(hart) {
+        // 调用处理函数时不占用处理函数表
+        let handler = HANDLERS.exclusive_access().get(irq as usize).copied().flatten();
+        match handler {
+            Some(handler) => handler(irq),
+            None => warn!("unexpected external interrupt {}", irq),
+        }
+        complete(hart, irq);
+    }
+}
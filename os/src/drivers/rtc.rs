@@ -0,0 +1,48 @@
+//! goldfish 实时时钟驱动
+//!
+//! QEMU virt 机器的 goldfish RTC 以纳秒为单位给出自 1970-01-01 00:00:00 UTC 以来的时间。
+//! [`init`] 在启动时读取一次，记下启动时刻（时钟周期计数为 0 时）对应的真实时间，
+//! 之后的真实时间由这个偏移加上启动以来的时间得出，不再访问设备。
+//! 设备树中没有实时时钟时以 [`FALLBACK_EPOCH_NS`] 作为启动时刻的时间。
+
+use super::dtb::rtc_node;
+use crate::timer::get_time_ns;
+use core::ptr::read_volatile;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// 时间的低 32 位，读取它时设备锁存高 32 位
+const TIME_LOW: usize = 0x00;
+/// 时间的高 32 位
+const TIME_HIGH: usize = 0x04;
+
+/// 每秒的纳秒数
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+/// 没有实时时钟时使用的时间：2024-01-01 00:00:00 UTC
+const FALLBACK_EPOCH_NS: u64 = 1_704_067_200 * NSEC_PER_SEC;
+
+/// 启动时刻对应的 Unix 时间（纳秒）
+static BOOT_EPOCH_NS: AtomicU64 = AtomicU64::new(FALLBACK_EPOCH_NS);
+
+fn read_reg(base: usize, reg: usize) -> u32 {
+    unsafe { read_volatile((base + reg) as *const u32) }
+}
+
+/// 读取设备树中的实时时钟，确定启动时刻对应的真实时间。
+/// 实时时钟位于恒等映射的 MMIO 区域，在页表打开之前或之后调用都可以
+pub fn init() {
+    let Some(node) = rtc_node() else {
+        warn!("no goldfish RTC in the device tree, the wall clock starts at 2024-01-01");
+        return;
+    };
+    // 必须先读低 32 位
+    let low = read_reg(node.base, TIME_LOW) as u64;
+    let high = read_reg(node.base, TIME_HIGH) as u64;
+    let now = high << 32 | low;
+    BOOT_EPOCH_NS.store(now.saturating_sub(get_time_ns()), Ordering::Relaxed);
+    info!("wall clock: {} s since the epoch", now / NSEC_PER_SEC);
+}
+
+/// 当前的 Unix 时间（纳秒）
+pub fn read_epoch() -> u64 {
+    BOOT_EPOCH_NS.load(Ordering::Relaxed) + get_time_ns()
+}
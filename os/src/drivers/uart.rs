@@ -0,0 +1,234 @@
+//! ns16550a 串口驱动
+//!
+//! QEMU virt 机器的控制台是设备树中的 ns16550a 串口。[`init`] 打开收发 FIFO 和接收中断，
+//! 中断处理程序把收到的字节放入环形缓冲区，控制台终端通过 [`getchar`] 取出它们交给行规程。
+//! 输出直接写发送寄存器，不再为每个字符进行一次 SBI 调用。
+//! 初始化之前（以及设备树中没有串口时）输入输出仍然通过 SBI。
+
+use super::dtb::uart_node;
+use super::plic::register;
+use crate::sbi::{console_getchar, console_putchar};
+use crate::sync::UPSafeCell;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use lazy_static::*;
+
+/// 接收缓冲寄存器（读）/ 发送保持寄存器（写），DLAB 为 1 时是除数的低字节
+const RBR_THR: usize = 0;
+/// 中断使能寄存器，DLAB 为 1 时是除数的高字节
+const IER: usize = 1;
+/// FIFO 控制寄存器（写）
+const FCR: usize = 2;
+/// 线路控制寄存器
+const LCR: usize = 3;
+/// 调制解调器控制寄存器
+const MCR: usize = 4;
+/// 线路状态寄存器
+const LSR: usize = 5;
+
+/// IER：接收到数据时产生中断
+const IER_RX_AVAILABLE: u8 = 1;
+/// FCR：打开 FIFO 并清空接收和发送 FIFO
+const FCR_ENABLE_CLEAR: u8 = 0x07;
+/// LCR：访问除数寄存器
+const LCR_DLAB: u8 = 0x80;
+/// LCR：8 位数据、无校验、1 位停止位
+const LCR_8N1: u8 = 0x03;
+/// MCR：OUT2，部分实现需要它才能把中断送到中断控制器
+const MCR_OUT2: u8 = 0x08;
+/// MCR：回环模式，发送的字节直接进入接收 FIFO，不出现在控制台上
+#[cfg(feature = "uart-irq-test")]
+const MCR_LOOPBACK: u8 = 0x10;
+/// LSR：接收缓冲区中有数据
+const LSR_DATA_READY: u8 = 0x01;
+/// LSR：发送保持寄存器为空
+const LSR_THR_EMPTY: u8 = 0x20;
+/// 38400 波特对应的除数（1.8432 MHz 时钟），QEMU 忽略波特率
+const DIVISOR: u16 = 3;
+
+/// 接收环形缓冲区的大小，行规程来不及取走时丢弃新的字节
+const RX_BUFFER_SIZE: usize = 4096;
+
+/// 串口控制寄存器的地址，0 表示还没有初始化
+static UART_BASE: AtomicUsize = AtomicUsize::new(0);
+/// 串口的 PLIC 中断源
+static UART_IRQ: AtomicU32 = AtomicU32::new(0);
+/// 中断处理后还没有交给行规程的输入
+static RX_PENDING: AtomicBool = AtomicBool::new(false);
+/// 处理过的接收中断数
+static RX_IRQS: AtomicUsize = AtomicUsize::new(0);
+
+/// 固定大小的字节环形缓冲区
+struct RingBuffer {
+    buf: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == RX_BUFFER_SIZE {
+            return false;
+        }
+        self.buf[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+lazy_static! {
+    /// 中断处理程序收到、还没有交给行规程的字节
+    static ref RX_BUFFER: UPSafeCell<RingBuffer> =
+        unsafe { UPSafeCell::new(RingBuffer { buf: [0; RX_BUFFER_SIZE], head: 0, len: 0 }) };
+}
+
+fn read_reg(base: usize, reg: usize) -> u8 {
+    unsafe { read_volatile((base + reg) as *const u8) }
+}
+
+fn write_reg(base: usize, reg: usize, value: u8) {
+    unsafe { write_volatile((base + reg) as *mut u8, value) }
+}
+
+/// 初始化设备树中的串口并打开接收中断，之后控制台直接使用串口。
+/// 在解析设备树之后、页表打开之前或之后调用都可以：串口位于恒等映射的 MMIO 区域
+pub fn init() {
+    let Some(node) = uart_node() else {
+        warn!("no ns16550a UART in the device tree, the console stays on SBI");
+        return;
+    };
+    // 缓冲区较大，在启动栈上构造，不占用中断处理时的内核栈
+    lazy_static::initialize(&RX_BUFFER);
+    let base = node.base;
+    write_reg(base, IER, 0);
+    write_reg(base, LCR, LCR_DLAB);
+    write_reg(base, RBR_THR, DIVISOR as u8);
+    write_reg(base, IER, (DIVISOR >> 8) as u8);
+    write_reg(base, LCR, LCR_8N1);
+    write_reg(base, FCR, FCR_ENABLE_CLEAR);
+    write_reg(base, MCR, MCR_OUT2);
+    write_reg(base, IER, IER_RX_AVAILABLE);
+    UART_IRQ.store(node.irq, Ordering::Relaxed);
+    UART_BASE.store(base, Ordering::Release);
+}
+
+/// 串口的中断源，没有初始化串口时为 `None`
+pub fn uart_irq() -> Option<u32> {
+    (UART_BASE.load(Ordering::Acquire) != 0).then(|| UART_IRQ.load(Ordering::Relaxed))
+}
+
+/// 在 PLIC 上登记串口的中断处理函数，没有初始化串口时什么都不做
+pub fn register_irq() {
+    if let Some(irq) = uart_irq() {
+        register(irq, |_| handle_irq());
+    }
+}
+
+/// 上次调用以来是否处理过接收中断
+pub fn take_rx_pending() -> bool {
+    RX_PENDING.swap(false, Ordering::Relaxed)
+}
+
+/// 输出一个字节，串口忙时等待发送保持寄存器空出来
+pub fn putchar(byte: u8) {
+    let base = UART_BASE.load(Ordering::Acquire);
+    if base == 0 {
+        console_putchar(byte as usize);
+        return;
+    }
+    while read_reg(base, LSR) & LSR_THR_EMPTY == 0 {
+        core::hint::spin_loop();
+    }
+    write_reg(base, RBR_THR, byte);
+}
+
+/// 取出一个收到的字节。使用串口时从接收缓冲区中取，否则通过 SBI 查询
+pub fn getchar() -> Option<u8> {
+    if UART_BASE.load(Ordering::Acquire) == 0 {
+        // 没有字符时 SBI 返回 0 或 -1
+        let c = console_getchar();
+        return (c != 0 && c <= u8::MAX as usize).then_some(c as u8);
+    }
+    RX_BUFFER.exclusive_access().pop()
+}
+
+/// 处理串口的接收中断：把接收 FIFO 中的字节全部放入接收缓冲区
+pub fn handle_irq() {
+    let base = UART_BASE.load(Ordering::Acquire);
+    let mut buffer = RX_BUFFER.exclusive_access();
+    let mut dropped = 0;
+    while read_reg(base, LSR) & LSR_DATA_READY != 0 {
+        if !buffer.push(read_reg(base, RBR_THR)) {
+            dropped += 1;
+        }
+    }
+    drop(buffer);
+    RX_IRQS.fetch_add(1, Ordering::Relaxed);
+    RX_PENDING.store(true, Ordering::Relaxed);
+    if dropped > 0 {
+        warn!("console input buffer is full, dropped {} bytes", dropped);
+    }
+}
+
+/// 只打开串口的中断，在回环模式下“键入”几个字节，然后在内核中打开中断忙等：
+/// 这些字节只能由中断处理程序放入接收缓冲区。测试期间其他中断源被关闭，之前收到的输入会保留
+#[cfg(feature = "uart-irq-test")]
+pub fn uart_irq_test() {
+    use super::plic::{self, BOOT_HART};
+    use crate::timer::get_time_ms;
+    use alloc::vec::Vec;
+    use riscv::register::sstatus;
+
+    const INPUT: &[u8] = b"irq!";
+    let Some(irq) = uart_irq() else {
+        warn!("no UART, uart_irq_test skipped");
+        return;
+    };
+    let base = UART_BASE.load(Ordering::Acquire);
+    let others: Vec<u32> = plic::registered_irqs().filter(|&other| other != irq).collect();
+    for &other in others.iter() {
+        plic::disable(BOOT_HART, other);
+    }
+    let typed_ahead: Vec<u8> = core::iter::from_fn(getchar).collect();
+    let irqs = RX_IRQS.load(Ordering::Relaxed);
+
+    write_reg(base, MCR, MCR_OUT2 | MCR_LOOPBACK);
+    for &byte in INPUT {
+        putchar(byte);
+    }
+    let deadline = get_time_ms() + 1000;
+    // 打开中断时中断处理程序会访问接收缓冲区，只在关闭中断后检查它
+    while RX_BUFFER.exclusive_access().len < INPUT.len() {
+        assert!(get_time_ms() < deadline, "UART input did not arrive through the interrupt");
+        unsafe { sstatus::set_sie() };
+        for _ in 0..10_000 {
+            core::hint::spin_loop();
+        }
+        unsafe { sstatus::clear_sie() };
+    }
+    write_reg(base, MCR, MCR_OUT2);
+
+    let received: Vec<u8> = core::iter::from_fn(getchar).collect();
+    let mut buffer = RX_BUFFER.exclusive_access();
+    for &byte in typed_ahead.iter() {
+        buffer.push(byte);
+    }
+    drop(buffer);
+    RX_PENDING.store(!typed_ahead.is_empty(), Ordering::Relaxed);
+    for &other in others.iter() {
+        plic::enable(BOOT_HART, other);
+    }
+    assert_eq!(received, INPUT);
+    assert!(RX_IRQS.load(Ordering::Relaxed) > irqs);
+    info!("uart_irq_test passed!");
+}
@@ -0,0 +1,152 @@
+//! 启动时运行的文件系统自测
+//!
+//! 这些测试需要创建文件、注入故障或模拟掉电，都在 [`test_disk`] 格式化出的内存磁盘上进行，
+//! 不写入根文件系统，也不在根文件系统所在的磁盘上再打开一个文件系统。
+//! 只有打开 `fs-boot-test` 特性时才编译和运行。
+
+use super::{list_dir, tmpfs_mkdir, tmpfs_mknod, tmpfs_unlink, File, OSInode};
+use crate::drivers::block::{ram_disk, FaultyDevice};
+use crate::mm::UserBuffer;
+use crate::syscall::{EIO, ENOENT, ENOTDIR};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use fat32::{BlockDevice, FAT32Manager, ATTRIBUTE_ARCHIVE, ATTRIBUTE_DIRECTORY};
+
+/// 测试用的内存磁盘的扇区数
+const TEST_DISK_SECTORS: usize = 2048;
+
+/// 一个格式化为 FAT32、根目录为空的内存磁盘
+fn test_disk() -> Arc<dyn BlockDevice> {
+    let disk = ram_disk(TEST_DISK_SECTORS);
+    FAT32Manager::format(disk.clone(), TEST_DISK_SECTORS as u32).unwrap();
+    disk
+}
+
+/// 在内存磁盘上的 FAT、tmpfs 和 procfs 中创建目录项，检查列出的结果
+pub fn list_dir_test() {
+    let disk = test_disk();
+    let fs = FAT32Manager::open(disk).unwrap();
+    let root = FAT32Manager::get_root_vfile(&fs);
+    let dir = root.create("list_dir_test", ATTRIBUTE_DIRECTORY).unwrap().unwrap();
+    let file = dir.create("data", ATTRIBUTE_ARCHIVE).unwrap().unwrap();
+    assert_eq!(file.write_at(0, b"hello"), Ok(5));
+    dir.create("sub", ATTRIBUTE_DIRECTORY).unwrap().unwrap();
+    // list_dir 对 FAT 中的路径就是列出 OSInode 的目录项
+    let listed: Vec<(String, bool, u64)> = OSInode::new(true, false, dir)
+        .entries()
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.name, entry.is_dir, entry.size))
+        .collect();
+    assert_eq!(
+        listed,
+        [
+            (String::from("."), true, 0),
+            (String::from(".."), true, 0),
+            (String::from("data"), false, 5),
+            (String::from("sub"), true, 0),
+        ]
+    );
+    assert_eq!(OSInode::new(true, false, file).entries().err(), Some(ENOTDIR));
+    // 只查找，不写入根文件系统
+    assert_eq!(list_dir("/list_dir_test/missing").err(), Some(ENOENT));
+
+    assert_eq!(tmpfs_mkdir("/tmp/list_dir_test"), Some(0));
+    assert_eq!(tmpfs_mknod("/tmp/list_dir_test/node"), Some(0));
+    let names: Vec<String> = list_dir("/tmp/list_dir_test").unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, [".", "..", "node"]);
+    assert_eq!(tmpfs_unlink("/tmp/list_dir_test/node"), Some(0));
+    assert_eq!(tmpfs_unlink("/tmp/list_dir_test"), Some(0));
+
+    let proc_root = list_dir("/proc").unwrap();
+    assert!(proc_root.iter().any(|entry| entry.name == "meminfo" && !entry.is_dir));
+    info!("list_dir_test passed!");
+}
+
+/// 通过注入故障的块设备读取文件：读磁盘失败时 read 返回 -EIO，
+/// 内核不会 panic，文件偏移不变，故障消失后可以照常读出内容
+pub fn io_error_test() {
+    const NAME: &str = "io_error_test";
+    let disk = test_disk();
+    {
+        let fs = FAT32Manager::open(disk.clone()).unwrap();
+        let file = FAT32Manager::get_root_vfile(&fs).create(NAME, ATTRIBUTE_ARCHIVE).unwrap().unwrap();
+        assert_eq!(file.write_at(0, b"hello"), Ok(5));
+        fat32::sync_all().unwrap();
+    }
+
+    // 同一个磁盘的另一个视图：块缓存按设备区分，经过它的读取不会命中上面的缓存
+    let faulty = Arc::new(FaultyDevice::new(disk));
+    let fs = FAT32Manager::open(faulty.clone()).unwrap();
+    let vfile = FAT32Manager::get_root_vfile(&fs).find_vfile_byname(NAME).unwrap().unwrap();
+    let osinode = OSInode::new(true, false, vfile);
+    let mut buf = [0u8; 16];
+    // 测试只在 read 返回前使用这个缓冲区
+    let read = |buf: &mut [u8; 16]| {
+        let slice: &'static mut [u8] = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+        osinode.read(UserBuffer::new(vec![slice]))
+    };
+    faulty.set_fail_reads(true);
+    assert_eq!(read(&mut buf), -EIO);
+    assert!(faulty.faults() > 0);
+    faulty.set_fail_reads(false);
+    assert_eq!(read(&mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+    info!("io_error_test passed!");
+}
+
+/// 在带写缓存的故障设备上检查 fsync 的写回和刷新：只写回不刷新的数据在掉电后丢失，
+/// 经过 fsync 的数据在掉电后仍然在磁盘上，写回失败时 fsync 报告错误
+pub fn flush_order_test() {
+    const NAME: &str = "flush_order_test";
+    let disk = test_disk();
+    {
+        let fs = FAT32Manager::open(disk.clone()).unwrap();
+        let file = FAT32Manager::get_root_vfile(&fs).create(NAME, ATTRIBUTE_ARCHIVE).unwrap().unwrap();
+        assert_eq!(file.write_at(0, b"old data"), Ok(8));
+        fat32::sync_all().unwrap();
+    }
+
+    // 每次都通过新的视图读取，不会命中之前视图的块缓存。
+    // 块缓存按设备的地址区分，视图保留到测试结束，以免新视图重复使用旧视图的地址
+    let views = RefCell::new(Vec::new());
+    let read_back = || {
+        let view: Arc<dyn BlockDevice> = Arc::new(FaultyDevice::new(disk.clone()));
+        views.borrow_mut().push(view.clone());
+        let fs = FAT32Manager::open(view).unwrap();
+        let vfile = FAT32Manager::get_root_vfile(&fs).find_vfile_byname(NAME).unwrap().unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(vfile.read_at(0, &mut buf), Ok(8));
+        buf
+    };
+    let faulty = Arc::new(FaultyDevice::new(disk.clone()));
+    faulty.set_write_cache(true);
+    let fs = FAT32Manager::open(faulty.clone()).unwrap();
+    let vfile = FAT32Manager::get_root_vfile(&fs).find_vfile_byname(NAME).unwrap().unwrap();
+    let osinode = OSInode::new(true, true, vfile.clone());
+
+    // 写回的数据还在设备的写缓存中，掉电后丢失
+    assert_eq!(vfile.write_at(0, b"new data"), Ok(8));
+    fat32::sync_all().unwrap();
+    faulty.power_cut();
+    assert_eq!(&read_back(), b"old data");
+
+    // fsync 写回后刷新文件所在的设备，数据在掉电前已经落盘
+    assert_eq!(vfile.write_at(0, b"new data"), Ok(8));
+    assert_eq!(osinode.sync(), Ok(()));
+    faulty.power_cut();
+    assert_eq!(&read_back(), b"new data");
+
+    // 写回失败时 fsync 返回 EIO，修改留在块缓存中，设备恢复后再次 fsync 时写入
+    assert_eq!(vfile.write_at(0, b"end data"), Ok(8));
+    faulty.set_fail_writes(true);
+    assert_eq!(osinode.sync(), Err(EIO));
+    faulty.set_fail_writes(false);
+    assert_eq!(osinode.sync(), Ok(()));
+    faulty.power_cut();
+    assert_eq!(&read_back(), b"end data");
+    info!("flush_order_test passed!");
+}
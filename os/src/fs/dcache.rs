@@ -0,0 +1,140 @@
+//! 目录查找缓存（dentry cache）
+//!
+//! 路径查找 [`lookup`] 从根目录逐级读取目录项，每级都要扫描父目录的扇区。
+//! 这里缓存已经解析过的目录：键为规范化的绝对路径，值为目录的 VFile。
+//! 查找时从缓存中最长的目录前缀开始，只解析剩下的部分，并把途经的目录加入缓存。
+//! 只缓存目录，普通文件的大小等信息会变化，每次都重新读取目录项。
+//!
+//! 删除目录项时必须调用 [`invalidate`] 移除该路径及其下所有路径，
+//! 否则同名的新目录项会被缓存中已删除的旧目录遮住。缓存满时淘汰最久未使用的目录。
+
+use super::mount::mounted_root;
+use super::{canonical_path, ROOT_INODE};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use fat32::VFile;
+use lazy_static::*;
+
+/// 最多缓存的目录数
+const DCACHE_CAPACITY: usize = 64;
+
+/// 目录缓存
+struct DentryCache {
+    /// 路径 -> (目录, 最近一次使用的时间戳)
+    entries: BTreeMap<String, (Arc<VFile>, u64)>,
+    /// 逻辑时钟，每次使用缓存项时加一
+    clock: u64,
+    /// 从缓存中的目录开始解析的查找次数
+    hits: usize,
+    /// 从根目录或挂载点开始解析的查找次数
+    misses: usize,
+}
+
+lazy_static! {
+    static ref DCACHE: UPSafeCell<DentryCache> = unsafe {
+        UPSafeCell::new(DentryCache {
+            entries: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        })
+    };
+}
+
+impl DentryCache {
+    /// 取出缓存的目录并更新使用时间
+    fn get(&mut self, path: &str) -> Option<Arc<VFile>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(path).map(|(vfile, used)| {
+            *used = clock;
+            vfile.clone()
+        })
+    }
+
+    /// 加入一个目录，缓存满时淘汰最久未使用的目录
+    fn insert(&mut self, path: String, vfile: Arc<VFile>) {
+        if self.entries.len() >= DCACHE_CAPACITY && !self.entries.contains_key(&path) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(path, (vfile, self.clock));
+    }
+}
+
+/// 按相对于根目录的路径查找文件或目录，途经的目录会被缓存。路径中间的部分不是目录或读取目录项失败时返回 `None`。
+/// 经过其他文件系统的挂载点时从挂载的根目录继续
+pub fn lookup(path: &str) -> Option<Arc<VFile>> {
+    let path = canonical_path("/", path);
+    if path == "/" {
+        return Some(ROOT_INODE.clone());
+    }
+    // 从缓存或挂载表中最长的目录前缀开始，`resolved` 是这个前缀的长度。
+    // 从缓存中的目录（包括路径本身）开始算作命中，从根目录或挂载点开始算作未命中
+    let (mut resolved, mut current) = {
+        let mut dcache = DCACHE.exclusive_access();
+        let mut resolved = path.len();
+        loop {
+            if resolved == 0 {
+                dcache.misses += 1;
+                break (0, ROOT_INODE.clone());
+            }
+            let prefix = &path[..resolved];
+            if let Some(vfile) = mounted_root(prefix) {
+                dcache.misses += 1;
+                break (resolved, vfile);
+            }
+            if let Some(vfile) = dcache.get(prefix) {
+                dcache.hits += 1;
+                break (resolved, vfile);
+            }
+            resolved = prefix.rfind('/').unwrap_or(0);
+        }
+    };
+    if resolved == path.len() {
+        return Some(current);
+    }
+    // 读取目录项可能睡眠等待磁盘，这期间不能占用缓存
+    for component in path[resolved + 1..].split('/') {
+        if !current.is_dir() {
+            return None;
+        }
+        current = current.find_vfile_byname(component).ok()??;
+        resolved += component.len() + 1;
+        if current.is_dir() {
+            DCACHE.exclusive_access().insert(String::from(&path[..resolved]), current.clone());
+        }
+    }
+    Some(current)
+}
+
+/// 路径 `path`（规范化的绝对路径）被删除或改名，移除它和它下面的所有缓存项
+pub fn invalidate(path: &str) {
+    let mut dcache = DCACHE.exclusive_access();
+    let prefix = if path.ends_with('/') {
+        String::from(path)
+    } else {
+        String::from(path) + "/"
+    };
+    dcache.entries.retain(|cached, _| cached != path && !cached.starts_with(&prefix));
+}
+
+/// 清空缓存，用于无法得知被删除路径的情况
+pub fn invalidate_all() {
+    DCACHE.exclusive_access().entries.clear();
+}
+
+/// 缓存命中和未命中的次数，以及当前缓存的目录数
+pub fn dcache_stats() -> (usize, usize, usize) {
+    let dcache = DCACHE.exclusive_access();
+    (dcache.hits, dcache.misses, dcache.entries.len())
+}
@@ -0,0 +1,100 @@
+//! 命名管道（FIFO）
+//!
+//! mknod 在 FAT 或 tmpfs 中创建一个长度为 0 的普通文件，使 FIFO 出现在目录列表中，
+//! 同时在内核的 FIFO 表中按规范化的绝对路径登记一个共享的管道缓冲区。
+//! 打开登记过的路径得到该缓冲区上的 [`Pipe`] 一端，而不是 OSInode。
+//! 缓冲区在路径被 unlink 之前一直保留，已打开的一端在 unlink 之后仍可继续使用。
+//! FIFO 表只在内存中，重启后这些目录项成为普通的空文件。
+
+use super::{
+    create_bypath, search_pwd, tmpfs_mknod, OpenFlags, Pipe, PipeRingBuffer, PipeWaiters,
+};
+use crate::sync::UPSafeCell;
+use crate::syscall::{EEXIST, ENOENT, ENXIO};
+use crate::task::{WaitQueue};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use fat32::ATTRIBUTE_ARCHIVE;
+use lazy_static::*;
+use spin::Mutex;
+
+/// 一个命名管道，所有打开者共享同一个缓冲区
+struct Fifo {
+    buffer: Arc<Mutex<PipeRingBuffer>>,
+    waiters: Arc<PipeWaiters>,
+    /// 读端和写端各自被打开过的次数，阻塞的 open 据此发现对端打开过，即使对端已经关闭
+    opens: UPSafeCell<(usize, usize)>,
+    /// 等待对端打开的 open
+    open_waiters: WaitQueue,
+}
+
+lazy_static! {
+    /// 已创建的 FIFO，键为规范化的绝对路径
+    static ref FIFOS: UPSafeCell<BTreeMap<String, Arc<Fifo>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// 创建 FIFO，`path` 是文件系统中规范化的绝对路径。路径已存在返回 -EEXIST，父目录不存在返回 -ENOENT
+pub fn make_fifo(path: &str) -> isize {
+    if let Some(result) = tmpfs_mknod(path) {
+        if result < 0 {
+            return result;
+        }
+    } else if search_pwd(path).is_some() {
+        return -EEXIST;
+    } else if create_bypath(path, ATTRIBUTE_ARCHIVE).is_none() {
+        return -ENOENT;
+    }
+    let fifo = Arc::new(Fifo {
+        buffer: Arc::new(Mutex::new(PipeRingBuffer::new())),
+        waiters: Arc::new(PipeWaiters::new()),
+        opens: unsafe { UPSafeCell::new((0, 0)) },
+        open_waiters: WaitQueue::new(),
+    });
+    FIFOS.exclusive_access().insert(String::from(path), fifo);
+    0
+}
+
+/// 打开 FIFO，`path` 是规范化的绝对路径，不是 FIFO 时返回 `None`。
+/// 只读打开阻塞到有写者打开，只写打开阻塞到有读者打开，读写打开不阻塞；
+/// 带 O_NONBLOCK 时只读打开立即返回，只写打开在没有读者时返回 -ENXIO，打开的一端读写也不阻塞
+pub fn open_fifo(path: &str, flags: OpenFlags) -> Option<Result<Arc<Pipe>, isize>> {
+    let fifo = FIFOS.exclusive_access().get(path)?.clone();
+    let (readable, writable) = flags.read_write();
+    let nonblock = flags.contains(OpenFlags::NONBLOCK);
+    if writable && !readable && nonblock && fifo.buffer.lock().all_read_ends_closed() {
+        return Some(Err(-ENXIO));
+    }
+    let end = Arc::new(Pipe::with_buffer(
+        readable,
+        writable,
+        fifo.buffer.clone(),
+        fifo.waiters.clone(),
+    ).with_nonblock(nonblock));
+    let mut opens = fifo.opens.exclusive_access();
+    let (read_opens, write_opens) = *opens;
+    if readable {
+        opens.0 += 1;
+    }
+    if writable {
+        opens.1 += 1;
+    }
+    drop(opens);
+    fifo.open_waiters.wake_all();
+    if readable && !writable && !nonblock {
+        while fifo.buffer.lock().all_write_ends_closed() && fifo.opens.exclusive_access().1 == write_opens {
+            fifo.open_waiters.wait();
+        }
+    } else if writable && !readable {
+        while fifo.buffer.lock().all_read_ends_closed() && fifo.opens.exclusive_access().0 == read_opens {
+            fifo.open_waiters.wait();
+        }
+    }
+    Some(Ok(end))
+}
+
+/// 路径被 unlink 时从 FIFO 表中移除，已打开的一端不受影响
+pub fn remove_fifo(path: &str) {
+    FIFOS.exclusive_access().remove(path);
+}
@@ -0,0 +1,126 @@
+//! BSD 风格的建议性文件锁（flock）
+//!
+//! 锁表以文件的标识（[`File::lock_key`]）为键，记录持有共享锁和排他锁的打开文件。
+//! 锁属于打开文件而不是进程：dup 和 fork 得到的描述符共享同一把锁，同一个文件的两次 open
+//! 则互相竞争。打开文件被释放时（最后一个引用它的描述符关闭，包括进程退出）它持有的锁自动释放。
+//!
+//! [`File::lock_key`]: super::File::lock_key
+
+use crate::sync::UPSafeCell;
+use crate::syscall::{EINVAL, EWOULDBLOCK};
+use crate::task::WaitQueue;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// 共享锁
+pub const LOCK_SH: u32 = 1;
+/// 排他锁
+pub const LOCK_EX: u32 = 2;
+/// 不阻塞，锁被占用时返回 -EWOULDBLOCK
+pub const LOCK_NB: u32 = 4;
+/// 释放锁
+pub const LOCK_UN: u32 = 8;
+
+/// 文件的标识：(设备号, 设备内的编号)
+pub type LockKey = (u64, u64);
+
+/// 一个文件上的锁，持有者是打开文件的地址
+#[derive(Default)]
+struct LockState {
+    shared: Vec<usize>,
+    exclusive: Option<usize>,
+}
+
+impl LockState {
+    /// `holder` 申请的锁是否与其他打开文件持有的锁冲突
+    fn conflicts(&self, holder: usize, exclusive: bool) -> bool {
+        self.exclusive.is_some_and(|owner| owner != holder)
+            || (exclusive && self.shared.iter().any(|&owner| owner != holder))
+    }
+
+    /// 释放 `holder` 持有的锁，返回它之前是否持有锁
+    fn release(&mut self, holder: usize) -> bool {
+        let before = self.shared.len();
+        self.shared.retain(|&owner| owner != holder);
+        let released = self.shared.len() != before;
+        if self.exclusive == Some(holder) {
+            self.exclusive = None;
+            return true;
+        }
+        released
+    }
+
+    fn is_idle(&self) -> bool {
+        self.shared.is_empty() && self.exclusive.is_none()
+    }
+}
+
+lazy_static! {
+    /// 所有被锁住的文件
+    static ref LOCKS: UPSafeCell<BTreeMap<LockKey, LockState>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+    /// 等待任意一把锁的任务，锁被释放时全部唤醒，由它们重新检查
+    static ref LOCK_WAITERS: WaitQueue = WaitQueue::new();
+}
+
+/// 打开文件 `holder` 对文件 `key` 执行 flock 操作。已持有锁时再次加锁会转换锁的类型。
+/// 与 Linux 一样，转换不是原子的：新的锁与其他持有者冲突时先释放原来的锁再等待（或返回
+/// -EWOULDBLOCK），否则两个持有共享锁的打开文件同时升级为排他锁会互相等待
+pub fn flock(key: LockKey, holder: usize, operation: u32) -> isize {
+    let nonblock = operation & LOCK_NB != 0;
+    let exclusive = match operation & !LOCK_NB {
+        LOCK_SH => false,
+        LOCK_EX => true,
+        LOCK_UN => {
+            release(key, holder);
+            return 0;
+        }
+        _ => return -EINVAL,
+    };
+    loop {
+        let mut locks = LOCKS.exclusive_access();
+        let state = locks.entry(key).or_default();
+        if !state.conflicts(holder, exclusive) {
+            let converted = state.release(holder);
+            if exclusive {
+                state.exclusive = Some(holder);
+            } else {
+                state.shared.push(holder);
+            }
+            drop(locks);
+            if converted {
+                // 排他锁降级为共享锁后，等待共享锁的任务可以继续
+                LOCK_WAITERS.wake_all();
+            }
+            return 0;
+        }
+        let released = state.release(holder);
+        if state.is_idle() {
+            locks.remove(&key);
+        }
+        drop(locks);
+        if released {
+            LOCK_WAITERS.wake_all();
+        }
+        if nonblock {
+            return -EWOULDBLOCK;
+        }
+        LOCK_WAITERS.wait();
+    }
+}
+
+/// 释放打开文件 `holder` 在文件 `key` 上的锁，打开文件被释放时调用
+pub fn release(key: LockKey, holder: usize) {
+    let mut locks = LOCKS.exclusive_access();
+    let Some(state) = locks.get_mut(&key) else {
+        return;
+    };
+    if state.release(holder) {
+        if state.is_idle() {
+            locks.remove(&key);
+        }
+        drop(locks);
+        LOCK_WAITERS.wake_all();
+    }
+}
@@ -1,14 +1,18 @@
-use super::File;
-use crate::task::current_task;
-use crate::{drivers::BLOCK_DEVICE, syscall::AT_FDCWD};
-use crate::mm::UserBuffer;
+use super::flock::release as release_lock;
+use super::{tmpfs_is_dir, vfile_read_only, File, LockKey, SeekWhence, Stat, StatMode};
+use crate::task::{current_process, current_task, sleep_current_and_run_next, suspend_current_and_run_next};
+use crate::timer::get_time_us;
+use crate::{drivers::{can_sleep, flush_disks, handle_external_interrupts, rtc::{read_epoch, NSEC_PER_SEC}, BLOCK_DEVICE}, syscall::{Errno, AT_FDCWD, EINVAL, EIO, ENOENT, ENOSPC, ENOTDIR, EROFS}};
+use crate::mm::{page_cache_invalidate, UserBuffer};
 use crate::sync::UPSafeCell;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
-use fat32::{FAT32Manager, VFile, ATTRIBUTE_ARCHIVE};
+use fat32::{set_relax_hook, set_time_hook, BlockError, FAT32Manager, FatError, VFile, ATTRIBUTE_ARCHIVE, BLOCK_SZ};
 use lazy_static::*;
 
 /// 文件系统中的 inode
@@ -23,6 +27,8 @@ pub struct OSInode {
 /// 存储在 UPSafeCell 中的 inode 的内部结构
 pub struct OSInodeInner {
     offset: usize,     // 当前读取/写入的偏移量
+    append: bool,      // 以 O_APPEND 打开，写入总在文件末尾
+    path: Option<String>, // 打开时的绝对路径
     pub inode: Arc<VFile>, // 文件的 VFile 对象
 }
 
@@ -32,46 +38,187 @@ impl OSInode {
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, append: false, path: None, inode }) },
         }
     }
 
-    /// 从 inode 中读取所有数据
-    pub fn read_all(&self) -> Vec<u8> {
+    /// 设置 O_APPEND
+    pub fn set_append(&self, append: bool) {
+        self.inner.exclusive_access().append = append;
+    }
+
+    /// 记录打开时在文件系统中的绝对路径
+    pub fn set_path(&self, path: String) {
+        self.inner.exclusive_access().path = Some(path);
+    }
+
+    /// 从 inode 中读取所有数据，读取磁盘失败时返回 errno
+    pub fn read_all(&self) -> Result<Vec<u8>, Errno> {
         let mut inner = self.inner.exclusive_access();  // 获取排他访问
-        let mut buffer = [0u8; 512];  // 缓冲区
-        let mut v: Vec<u8> = Vec::new();  // 存放读取数据的 Vector
-        loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);  // 读取数据
-            if len == 0 {
-                break;
-            }
-            inner.offset += len;  // 更新偏移量
-            v.extend_from_slice(&buffer[..len]);  // 将读取的数据扩展到结果 Vector 中
-        }
-        v
+        // 一次读入剩余的全部内容，连续的扇区可以合并成批量请求
+        let size = inner.inode.get_size().map_err(fat_errno)? as usize;
+        let mut v = vec![0u8; size.saturating_sub(inner.offset)];
+        let len = inner.inode.read_at(inner.offset, &mut v).map_err(fat_errno)?;
+        inner.offset += len;  // 更新偏移量
+        v.truncate(len);
+        Ok(v)
+    }
+}
+
+/// 文件系统错误对应的 errno，系统调用返回它的相反数
+pub fn fat_errno(err: FatError) -> Errno {
+    match err {
+        FatError::IoError(BlockError::ReadOnly) => EROFS,
+        FatError::IoError(_) => EIO,
+        FatError::NoSpace => ENOSPC,
+        FatError::InvalidFs => EINVAL,
+    }
+}
+
+/// 目录中的一项，由 getdents64 和 [`list_dir`](super::list_dir) 使用
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntryInfo {
+    /// 文件名
+    pub name: String,
+    /// inode 号，FAT 上与 fstat 一样是首簇号
+    pub ino: u64,
+    /// 是否是目录
+    pub is_dir: bool,
+    /// 普通文件的字节数，目录为 0
+    pub size: u64,
+}
+
+impl DirEntryInfo {
+    /// 创建目录项
+    pub fn new(name: String, ino: u64, is_dir: bool, size: u64) -> Self {
+        Self { name, ino, is_dir, size }
     }
+}
 
-    /// 创建目录
-    pub fn mkdir(&self, name:&str, attribute:u8) -> isize {
-        let inner = self.inner.exclusive_access();
-        inner.inode.create(name, attribute);  // 调用 VFile 创建目录
-        0  // 返回 0，表示成功
+/// linux_dirent64 中文件名的偏移
+const DIRENT64_NAME_OFFSET: usize = 19;
+/// linux_dirent64 中目录的 d_type
+const DT_DIR: u8 = 4;
+/// linux_dirent64 中普通文件的 d_type
+const DT_REG: u8 = 8;
+
+/// 在 `buf` 的开头写入一条 linux_dirent64 记录，`off` 是下一条记录的位置，返回记录的长度；
+/// `buf` 放不下这条记录时返回 `None`
+fn put_dirent64(buf: &mut [u8], ino: u64, off: i64, d_type: u8, name: &str) -> Option<usize> {
+    // d_ino、d_off、d_reclen、d_type 之后是以 \0 结尾的文件名，整条记录按 8 字节对齐
+    let reclen = (DIRENT64_NAME_OFFSET + name.len() + 1).next_multiple_of(8);
+    let record = buf.get_mut(..reclen)?;
+    record.fill(0);
+    record[0..8].copy_from_slice(&ino.to_le_bytes());
+    record[8..16].copy_from_slice(&off.to_le_bytes());
+    record[16..18].copy_from_slice(&(reclen as u16).to_le_bytes());
+    record[18] = d_type;
+    record[DIRENT64_NAME_OFFSET..DIRENT64_NAME_OFFSET + name.len()].copy_from_slice(name.as_bytes());
+    Some(reclen)
+}
+
+/// 从第 `*offset` 个目录项开始，将 `entries`（文件名、inode 号、是否是目录）以 linux_dirent64 格式写入 `buf`
+/// 并更新 `*offset`，返回写入的字节数；读到末尾时返回 0，`buf` 放不下一个目录项时返回 -EINVAL
+pub(super) fn fill_dirents(entries: &[DirEntryInfo], offset: &mut usize, buf: &mut [u8]) -> isize {
+    let mut written = 0;
+    for entry in entries.iter().skip(*offset) {
+        let d_type = if entry.is_dir { DT_DIR } else { DT_REG };
+        match put_dirent64(&mut buf[written..], entry.ino, *offset as i64 + 1, d_type, &entry.name) {
+            Some(reclen) => written += reclen,
+            None => break,
+        }
+        *offset += 1;
+    }
+    if written == 0 && *offset < entries.len() {
+        return -EINVAL;
     }
+    written as isize
 }
 
 lazy_static! {
     /// 文件系统根目录的 inode
     pub static ref ROOT_INODE: Arc<VFile> = {
-        let efs = FAT32Manager::open(BLOCK_DEVICE.clone());  // 打开 FAT32 文件系统
+        // 持有文件系统锁的任务可能在睡眠等待磁盘，锁被占用时不能一直自旋
+        set_relax_hook(relax_fs_lock);
+        // 新建和写入的文件使用实时时钟的时间
+        set_time_hook(|| read_epoch() / NSEC_PER_SEC);
+        super::mount::init_root_mode();
+        let efs = FAT32Manager::open(BLOCK_DEVICE.clone()).expect("无法读取根文件系统");  // 打开 FAT32 文件系统
         Arc::new(FAT32Manager::get_root_vfile(&efs))  // 获取根目录的 VFile
     };
 }
 
+/// 文件系统的锁被占用时调用：持有者可能在等待磁盘，先处理已经到达的磁盘中断再让出处理器，
+/// 否则一直在内核中运行的任务会让持有者永远等不到中断。
+/// 在 [`crate::drivers::poll_io`] 中（如处理缺页时借用着进程的地址空间）只能自旋
+fn relax_fs_lock() {
+    if current_task().is_some() {
+        handle_external_interrupts();
+    }
+    if can_sleep() {
+        suspend_current_and_run_next();
+    } else {
+        core::hint::spin_loop();
+    }
+}
+
+/// 交换文件在根目录下的文件名
+const SWAP_FILE: &str = "swapfile";
+
+/// 打开根目录下的交换文件，不存在时创建
+pub fn open_swap_file() -> Option<Arc<VFile>> {
+    match ROOT_INODE.find_vfile_byname(SWAP_FILE) {
+        Ok(Some(vfile)) => Some(vfile),
+        Ok(None) => ROOT_INODE.create(SWAP_FILE, ATTRIBUTE_ARCHIVE).ok().flatten(),
+        // 读不出根目录时不能确定交换文件是否存在，不能再创建一个
+        Err(_) => None,
+    }
+}
+
+/// 文件系统缓存写回的周期（微秒）
+const FLUSH_INTERVAL_US: usize = 5_000_000;
+
+/// 周期性地将文件系统缓存中被修改的块写回磁盘，作为内核线程运行
+pub fn flush_thread() {
+    loop {
+        sleep_current_and_run_next(get_time_us() + FLUSH_INTERVAL_US);
+        // 写回失败的块留在缓存中，下一个周期再试
+        if let Err(err) = fat32::sync_all() {
+            warn!("写回文件系统缓存失败: {:?}", err);
+        }
+    }
+}
+
+/// 写回文件系统缓存中被修改的块，再刷新所有磁盘的写缓存，返回后写入的数据不会因为掉电丢失。
+/// 写回或刷新失败时返回 EIO，写回失败的块仍然保留修改，下次同步时重试
+pub fn sync_disks() -> Result<(), Errno> {
+    // 先写回再刷新：刷新只保证已经完成的写请求落盘
+    fat32::sync_all().map_err(fat_errno)?;
+    flush_disks().map_err(|_| EIO)
+}
+
 /// 查找当前工作目录的文件
 pub fn search_pwd(name: &str) -> Option<Arc<VFile>> {
-    let path: Vec<&str> = name.split('/').collect();  // 将路径按 '/' 切割
-    ROOT_INODE.find_vfile_bypath(path)  // 根据路径查找文件
+    super::dcache::lookup(name)  // 经过目录缓存查找
+}
+
+/// 在路径 `name`（相对于根目录）的父目录中创建文件或目录，父目录不存在时返回 `None`
+pub fn create_bypath(name: &str, attribute: u8) -> Option<Arc<VFile>> {
+    let path = canonical_path("/", name);
+    let (parent, name) = path.rsplit_once('/')?;
+    let parent = search_pwd(parent).filter(|parent| parent.is_dir())?;
+    if name.is_empty() {
+        return None;
+    }
+    parent.create(name, attribute).ok().flatten()
+}
+
+/// 按 O_TRUNC 清空文件，清空失败时只记录警告，文件仍然可以打开
+fn truncate(inode: &VFile) {
+    page_cache_invalidate(inode);
+    if let Err(err) = inode.clear() {
+        warn!("清空文件 {} 失败: {:?}", inode.get_name(), err);
+    }
 }
 
 bitflags! {
@@ -86,7 +233,11 @@ bitflags! {
         /// 创建新文件
         const CREATE = 1 << 6;
         /// 截断文件大小为 0
-        const TRUNC = 1 << 10;
+        const TRUNC = 1 << 9;
+        /// 追加写入，每次写入前把偏移移到文件末尾
+        const APPEND = 1 << 10;
+        /// 不阻塞，目前只影响 FIFO 的打开
+        const NONBLOCK = 1 << 11;
         /// 目录
         const O_DIRECTORY = 1 << 21;
     }
@@ -95,21 +246,62 @@ bitflags! {
 impl OpenFlags {
     /// 根据 flags 返回文件的可读和可写权限
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
-            (true, false)  // 默认是只读
-        } else if self.contains(Self::WRONLY) {
+        // 只看访问模式的两位，CREATE 等其他标志不影响读写权限
+        if self.contains(Self::WRONLY) {
             (false, true)  // 只写
-        } else {
+        } else if self.contains(Self::RDWR) {
             (true, true)  // 读写
+        } else {
+            (true, false)  // 默认是只读
         }
     }
 }
 
+/// 将相对于 `pwd` 的路径 `path` 转换为不含 `.`、`..` 和多余 `/` 的绝对路径
+pub fn canonical_path(pwd: &str, path: &str) -> String {
+    let joined = if path.starts_with('/') {
+        String::from(path)
+    } else {
+        format!("{}/{}", pwd, path)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// 将进程视角下的路径 `path`（相对于 `pwd`）转换为文件系统中的绝对路径。
+/// `root` 是进程的根目录，".." 在进程的根目录处停止，不能越过它
+pub fn resolve_path(root: &str, pwd: &str, path: &str) -> String {
+    let view = canonical_path(pwd, path);
+    if root == "/" {
+        view
+    } else if view == "/" {
+        String::from(root)
+    } else {
+        format!("{}{}", root, view)
+    }
+}
+
+/// 按当前进程的根目录和工作目录解析 `path`，调用者不能持有当前进程的借用
+pub fn real_path(path: &str) -> String {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    resolve_path(&inner.root, &inner.pwd, path)
+}
+
 /// 打开文件
 pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();  // 获取文件的读写权限
-    let task = current_task().unwrap();  // 获取当前任务
-    let inner = task.inner_exclusive_access();  // 获取当前任务的排他访问
+    let process = current_process();  // 获取当前进程
+    let inner = process.inner_exclusive_access();  // 获取当前进程的排他访问
     let binding1 = inner.pwd.clone();
     let pwd = binding1.as_str();  // 当前工作目录
     let mut vfile: Arc<VFile>;
@@ -117,18 +309,24 @@ pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInod
     
     if name.chars().next().unwrap() == '/' {  // 如果路径以 '/' 开头
         if let Some(vfile) = search_pwd(name) {  // 查找路径对应的文件
+            if flags.contains(OpenFlags::TRUNC) {
+                truncate(&vfile);  // 清空文件
+            }
             return Some(Arc::new(OSInode::new(readable, writable, vfile)));
-        } else {
-            return ROOT_INODE
-                .create(name, ATTRIBUTE_ARCHIVE)  // 创建文件
+        } else if flags.contains(OpenFlags::CREATE) {
+            return create_bypath(name, ATTRIBUTE_ARCHIVE)  // 创建文件
                 .map(|inode| Arc::new(OSInode::new(readable, writable, inode)));
+        } else {
+            return None;  // 文件不存在
         }
     } else if fd as isize == AT_FDCWD || name == "." {  // 如果是相对路径
         if pwd == "/" && name != "." {
             if flags.contains(OpenFlags::CREATE) {
-                if let Some(inode) = ROOT_INODE.find_vfile_bypath(path) {
-                    // 清空文件大小
-                    inode.clear();
+                if let Some(inode) = search_pwd(name) {
+                    // 清空文件大小，O_APPEND 时保留原有内容
+                    if !flags.contains(OpenFlags::APPEND) {
+                        truncate(&inode);
+                    }
                     return Some(Arc::new(OSInode::new(readable, writable, inode)));
                 } else {
                     // 创建文件
@@ -139,13 +337,15 @@ pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInod
                     }
                     return ROOT_INODE
                         .create(name, ATTRIBUTE_ARCHIVE)
+                        .ok()
+                        .flatten()
                         .map(|inode| Arc::new(OSInode::new(readable, writable, inode)));
                 }
             } else {
-                match ROOT_INODE.find_vfile_bypath(path) {
+                match search_pwd(name) {
                     Some(inode) => {
                         if flags.contains(OpenFlags::TRUNC) {
-                            inode.clear();  // 清空文件
+                            truncate(&inode);  // 清空文件
                         }
                         return Some(Arc::new(OSInode::new(readable, writable, inode)));
                     }
@@ -156,9 +356,8 @@ pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInod
             vfile = search_pwd(pwd).unwrap();
         }
     } else {
-        if let Some(file) = &inner.fd_table[fd as usize] {
-            let osinode = file.as_osinode().unwrap();
-            vfile = osinode.inner.exclusive_access().inode.clone();
+        if let Some(dir) = inner.fd_table.get(fd as usize).and_then(|file| file.as_ref()?.vfile()) {
+            vfile = dir;
             drop(inner);
         } else {
             drop(inner);
@@ -167,21 +366,25 @@ pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInod
     }
 
     if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = vfile.find_vfile_bypath(path) {
-            // 清空文件大小
-            inode.clear();
+        if let Some(inode) = vfile.find_vfile_bypath(path).ok().flatten() {
+            // 清空文件大小，O_APPEND 时保留原有内容
+            if !flags.contains(OpenFlags::APPEND) {
+                truncate(&inode);
+            }
             return Some(Arc::new(OSInode::new(readable, writable, inode)));
         } else {
             // 创建文件
             return vfile
                 .create(name, ATTRIBUTE_ARCHIVE)
+                .ok()
+                .flatten()
                 .map(|inode| Arc::new(OSInode::new(readable, writable, inode)));
         }
     } else {
-        match vfile.find_vfile_bypath(path) {
+        match vfile.find_vfile_bypath(path).ok().flatten() {
             Some(inode) => {
                 if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();  // 清空文件
+                    truncate(&inode);  // 清空文件
                 }
                 return Some(Arc::new(OSInode::new(readable, writable, inode)));
             }
@@ -190,43 +393,28 @@ pub fn open_file(fd: i64, mut name: &str, flags: OpenFlags) -> Option<Arc<OSInod
     }
 }
 
-/// 改变当前工作目录
-pub fn chdir(name: &str) -> bool {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    let binding1 = inner.pwd.clone();
-    let pwd = binding1.as_str();
-    let path: Vec<&str> = name.split('/').collect();
-    let path1: Vec<&str> = name.split('/').collect();
-    let mut newpwd: Vec<&str> = pwd.split('/').collect();
-    
-    if pwd == "/" || name.chars().next().unwrap() == '/' {
-        if path[0] == ".." {
-            return false;  // 无效路径
-        }
-        if let Some(_) = ROOT_INODE.find_vfile_bypath(path) {
-            inner.set_pwd(String::from(name));  // 设置新路径
-            return true;
-        } else {
-            return false;
-        }
-    } else {
-        let vfile = search_pwd(name).unwrap();
-        if let Some(_) = vfile.find_vfile_bypath(path) {
-            for pa in path1 {
-                if pa == ".." {
-                    newpwd.pop();  // 返回上一级目录
-                } else if pa == "." {
-                    continue;  // 当前目录，不做任何操作
-                } else {
-                    newpwd.push(pa);  // 添加新目录
-                }
-            }
-            let new_path = newpwd.join("/");
-            inner.set_pwd(new_path);  // 设置新路径
-            return true;
-        } else {
-            return false;
+/// 改变当前工作目录，`name` 不存在返回 ENOENT，不是目录返回 ENOTDIR
+pub fn chdir(name: &str) -> Result<(), Errno> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let pwd = canonical_path(&inner.pwd, name);
+    let real = resolve_path(&inner.root, &inner.pwd, name);
+    let is_dir = match tmpfs_is_dir(&real) {
+        Some(is_dir) => is_dir,
+        None => search_pwd(&real).ok_or(ENOENT)?.is_dir(),
+    };
+    if !is_dir {
+        return Err(ENOTDIR);
+    }
+    inner.set_pwd(pwd);  // 设置新路径
+    Ok(())
+}
+
+impl Drop for OSInode {
+    /// 打开文件被释放时释放它持有的 flock 锁
+    fn drop(&mut self) {
+        if let Some(key) = self.lock_key() {
+            release_lock(key, self as *const Self as usize);
         }
     }
 }
@@ -238,33 +426,144 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable  // 返回文件是否可写
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    // 读取磁盘失败时，已经读到的数据照常返回，一个字节都没读到才返回 -EIO
+    fn read(&self, mut buf: UserBuffer) -> isize {
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);  // 从文件读取数据
+            let read_size = match inner.inode.read_at(inner.offset, *slice) {  // 从文件读取数据
+                Ok(read_size) => read_size,
+                Err(err) if total_read_size == 0 => return -fat_errno(err),
+                Err(_) => break,
+            };
             if read_size == 0 {
                 break;  // 如果没有数据了，停止读取
             }
             inner.offset += read_size;  // 更新偏移量
             total_read_size += read_size;  // 累加读取字节数
         }
-        total_read_size
+        total_read_size as isize
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> isize {
         let mut inner = self.inner.exclusive_access();
+        // 打开文件之后文件系统可能被重新挂载为只读
+        if vfile_read_only(&inner.inode) {
+            return -EROFS;
+        }
+        if inner.append {
+            inner.offset = match inner.inode.get_size() {
+                Ok(size) => size as usize,
+                Err(err) => return -fat_errno(err),
+            };
+        }
         let mut total_write_size = 0usize;
+        // 已缓存的只读映射页面将过期
+        page_cache_invalidate(&inner.inode);
         for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);  // 向文件写入数据
+            let write_size = match inner.inode.write_at(inner.offset, *slice) {  // 向文件写入数据
+                Ok(write_size) => write_size,
+                Err(err) if total_write_size == 0 => return -fat_errno(err),
+                Err(_) => break,
+            };
             assert_eq!(write_size, slice.len());  // 确保写入的字节数与预期一致
             inner.offset += write_size;  // 更新偏移量
             total_write_size += write_size;  // 累加写入字节数
         }
-        total_write_size
+        total_write_size as isize
     }
     
-    // 将文件转换为 OSInode 类型
-    fn as_osinode(&self) -> Option<&OSInode> {
-        Some(self)
+    fn vfile(&self) -> Option<Arc<VFile>> {
+        Some(self.inner.exclusive_access().inode.clone())
+    }
+
+    fn path(&self) -> Option<String> {
+        self.inner.exclusive_access().path.clone()
+    }
+
+    // 短目录项在磁盘上的字节位置在文件的生命周期内不变
+    fn lock_key(&self) -> Option<LockKey> {
+        let (sector, offset) = self.inner.exclusive_access().inode.dirent_pos();
+        Some((0, (sector * BLOCK_SZ + offset) as u64))
+    }
+
+    // 类型由 FAT 目录项的属性决定，其余信息来自短目录项
+    fn stat(&self) -> Stat {
+        let inode = self.inner.exclusive_access().inode.clone();
+        let mode = StatMode::with_permissions(inode.is_dir(), inode.is_readonly().unwrap_or(false));
+        // stat 没有返回错误的途径，读不出短目录项时只填写类型
+        let kstat = match inode.stat() {
+            Ok(kstat) => kstat,
+            Err(err) => {
+                warn!("读取 {} 的目录项失败: {:?}", inode.get_name(), err);
+                return Stat::new_with_defaults(0, 0, mode, 1);
+            }
+        };
+        let mut stat = Stat::new_with_defaults(kstat.st_dev, kstat.st_ino, mode, kstat.st_nlink);
+        stat.uid = kstat.st_uid;
+        stat.gid = kstat.st_gid;
+        stat.size = kstat.st_size;
+        stat.blksize = kstat.st_blksize;
+        stat.blocks = kstat.st_blocks;
+        stat.times = [
+            kstat.st_atime_sec,
+            kstat.st_atime_nsec,
+            kstat.st_mtime_sec,
+            kstat.st_mtime_nsec,
+            kstat.st_ctime_sec,
+            kstat.st_ctime_nsec,
+        ];
+        stat
+    }
+
+    // 目录项直接取自 FAT 目录中的短目录项，包括子目录中的 "." 和 ".."
+    fn entries(&self) -> Result<Vec<DirEntryInfo>, Errno> {
+        let entries = self.inner.exclusive_access().inode.ls_entries().map_err(fat_errno)?.ok_or(ENOTDIR)?;
+        Ok(entries
+            .into_iter()
+            .map(|(name, short_ent)| {
+                let is_dir = short_ent.is_dir();
+                let size = if is_dir { 0 } else { short_ent.get_size() as u64 };
+                DirEntryInfo::new(name, short_ent.first_cluster() as u64, is_dir, size)
+            })
+            .collect())
+    }
+
+    // 目录的位置是下一个要读取的目录项的序号，可以用 seek 设置
+    fn dirents(&self, buf: &mut [u8]) -> isize {
+        match self.entries() {
+            Ok(entries) => fill_dirents(&entries, &mut self.inner.exclusive_access().offset, buf),
+            Err(errno) => -errno,
+        }
+    }
+
+    // 普通文件可以定位到文件末尾之后；目录的位置是 getdents 下一次读取的目录项序号，不支持 SEEK_END
+    fn seek(&self, offset: i64, whence: SeekWhence) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        let base = match whence {
+            SeekWhence::Set => 0,
+            SeekWhence::Cur => inner.offset as i64,
+            SeekWhence::End if inner.inode.is_dir() => return -EINVAL,
+            SeekWhence::End => match inner.inode.get_size() {
+                Ok(size) => size as i64,
+                Err(err) => return -fat_errno(err),
+            },
+        };
+        match base.checked_add(offset) {
+            Some(pos) if pos >= 0 => {
+                inner.offset = pos as usize;
+                pos as isize
+            }
+            _ => -EINVAL,
+        }
+    }
+
+    // 写回全部被修改的块并刷新文件所在的磁盘
+    fn sync(&self) -> Result<(), Errno> {
+        if !self.writable {
+            return Ok(());
+        }
+        // 写回时可能让出处理器，不持有 inner
+        let inode = self.inner.exclusive_access().inode.clone();
+        inode.sync().map_err(fat_errno)
     }
 }
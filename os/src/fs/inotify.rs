@@ -0,0 +1,221 @@
+//! 目录变化通知（inotify 的一个子集）
+//!
+//! inotify_add_watch 在通知表中按被监视目录的标识（FAT 短目录项的位置）登记监视，
+//! FAT 在目录中创建、删除文件或写入文件时通过 [`fat32::set_dir_hook`] 注册的回调
+//! 把事件放入所有监视该目录的 inotify 实例的队列，读 inotify 描述符取出事件。
+//! 事件的格式与 Linux 的 `struct inotify_event` 相同，但名字固定占 [`INOTIFY_NAME_LEN`] 字节，
+//! 每条记录的长度都是 [`INOTIFY_EVENT_SIZE`]。队列满时丢弃新的事件，并在队尾放一个 IN_Q_OVERFLOW。
+
+use super::{File, PollEvents, Stat, StatMode};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::WaitQueue;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use fat32::{set_dir_hook, DirEvent};
+use lazy_static::*;
+
+/// 目录中的文件被写入
+pub const IN_MODIFY: u32 = 0x2;
+/// 在目录中创建了文件
+pub const IN_CREATE: u32 = 0x100;
+/// 从目录中删除了文件
+pub const IN_DELETE: u32 = 0x200;
+/// 事件队列溢出，有事件被丢弃
+pub const IN_Q_OVERFLOW: u32 = 0x4000;
+/// 支持监视的事件
+pub const IN_ALL_EVENTS: u32 = IN_MODIFY | IN_CREATE | IN_DELETE;
+
+/// 事件中名字占用的字节数，包括结尾的 '\0'，更长的名字被截断
+pub const INOTIFY_NAME_LEN: usize = 48;
+/// 一条事件记录的字节数
+pub const INOTIFY_EVENT_SIZE: usize = 16 + INOTIFY_NAME_LEN;
+/// 每个 inotify 实例最多排队的事件数
+const INOTIFY_QUEUE_LEN: usize = 64;
+
+/// 被监视目录的标识，即它的短目录项所在的扇区和偏移
+type DirKey = (usize, usize);
+
+#[derive(Clone, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: u32,
+    name: String,
+}
+
+impl InotifyEvent {
+    fn to_bytes(&self) -> [u8; INOTIFY_EVENT_SIZE] {
+        let mut bytes = [0u8; INOTIFY_EVENT_SIZE];
+        bytes[0..4].copy_from_slice(&self.wd.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.mask.to_le_bytes());
+        // cookie 只用于 rename，始终为 0
+        bytes[12..16].copy_from_slice(&(INOTIFY_NAME_LEN as u32).to_le_bytes());
+        let name = self.name.as_bytes();
+        let len = name.len().min(INOTIFY_NAME_LEN - 1);
+        bytes[16..16 + len].copy_from_slice(&name[..len]);
+        bytes
+    }
+}
+
+/// 一个 inotify 实例的事件队列
+struct InotifyQueue {
+    events: UPSafeCell<VecDeque<InotifyEvent>>,
+    /// 下一个分配的监视描述符
+    next_wd: UPSafeCell<i32>,
+    readers: WaitQueue,
+}
+
+impl InotifyQueue {
+    fn push(&self, event: InotifyEvent) {
+        let mut events = self.events.exclusive_access();
+        // 与队尾相同的事件合并，一次 write 写入多个缓冲区时只产生一个 IN_MODIFY
+        if events.back() == Some(&event) {
+            return;
+        }
+        if events.len() < INOTIFY_QUEUE_LEN {
+            events.push_back(event);
+        } else if events.back().map_or(true, |last| last.mask != IN_Q_OVERFLOW) {
+            events.push_back(InotifyEvent { wd: -1, mask: IN_Q_OVERFLOW, name: String::new() });
+        } else {
+            return;
+        }
+        drop(events);
+        self.readers.wake_all();
+        super::notify_readiness();
+    }
+}
+
+/// 对一个目录的监视
+struct Watch {
+    queue: Weak<InotifyQueue>,
+    wd: i32,
+    mask: u32,
+}
+
+lazy_static! {
+    /// 被监视的目录 -> 监视它的 inotify 实例
+    static ref WATCHES: UPSafeCell<BTreeMap<DirKey, Vec<Watch>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// FAT 目录变化的回调，把事件放入监视该目录的所有队列
+fn dir_changed(dir: DirKey, name: &str, event: DirEvent) {
+    let mask = match event {
+        DirEvent::Create => IN_CREATE,
+        DirEvent::Delete => IN_DELETE,
+        DirEvent::Modify => IN_MODIFY,
+    };
+    let targets: Vec<(Arc<InotifyQueue>, i32)> = match WATCHES.exclusive_access().get(&dir) {
+        Some(watches) => watches
+            .iter()
+            .filter(|watch| watch.mask & mask != 0)
+            .filter_map(|watch| Some((watch.queue.upgrade()?, watch.wd)))
+            .collect(),
+        None => return,
+    };
+    for (queue, wd) in targets {
+        queue.push(InotifyEvent { wd, mask, name: String::from(name) });
+    }
+}
+
+/// inotify 描述符，读出排队的事件
+pub struct Inotify {
+    queue: Arc<InotifyQueue>,
+    nonblock: bool,
+}
+
+impl Inotify {
+    /// 创建 inotify 实例，`nonblock` 为真时没有事件的读取立即返回 0
+    pub fn new(nonblock: bool) -> Arc<Self> {
+        // 第一次使用 inotify 时才注册回调，没有监视时 FAT 不需要调用它
+        set_dir_hook(dir_changed);
+        Arc::new(Self {
+            queue: Arc::new(InotifyQueue {
+                events: unsafe { UPSafeCell::new(VecDeque::new()) },
+                next_wd: unsafe { UPSafeCell::new(1) },
+                readers: WaitQueue::new(),
+            }),
+            nonblock,
+        })
+    }
+
+    /// 监视目录 `dir` 中 `mask` 表示的事件，返回监视描述符；已经监视该目录时更新事件并返回原来的描述符
+    pub fn add_watch(&self, dir: DirKey, mask: u32) -> i32 {
+        let mask = mask & IN_ALL_EVENTS;
+        let mut table = WATCHES.exclusive_access();
+        let watches = table.entry(dir).or_default();
+        let queue = Arc::downgrade(&self.queue);
+        if let Some(watch) = watches.iter_mut().find(|watch| watch.queue.ptr_eq(&queue)) {
+            watch.mask = mask;
+            return watch.wd;
+        }
+        let mut next_wd = self.queue.next_wd.exclusive_access();
+        let wd = *next_wd;
+        *next_wd += 1;
+        watches.push(Watch { queue, wd, mask });
+        wd
+    }
+}
+
+impl Drop for Inotify {
+    /// inotify 描述符全部关闭后移除它的监视
+    fn drop(&mut self) {
+        let queue = Arc::downgrade(&self.queue);
+        let mut table = WATCHES.exclusive_access();
+        table.values_mut().for_each(|watches| watches.retain(|watch| !watch.queue.ptr_eq(&queue)));
+        table.retain(|_, watches| !watches.is_empty());
+    }
+}
+
+impl File for Inotify {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    // 取出能完整放进缓冲区的事件，没有事件时阻塞（非阻塞时返回 0），缓冲区放不下一条事件时返回 0
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let capacity = buf.len() / INOTIFY_EVENT_SIZE;
+        if capacity == 0 {
+            return 0;
+        }
+        loop {
+            let mut events = self.queue.events.exclusive_access();
+            if events.is_empty() {
+                drop(events);
+                if self.nonblock {
+                    return 0;
+                }
+                self.queue.readers.wait();
+                continue;
+            }
+            let count = capacity.min(events.len());
+            let mut offset = 0;
+            for event in events.drain(..count) {
+                offset += buf.write_at(offset, &event.to_bytes());
+            }
+            return offset as isize;
+        }
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        -1
+    }
+    fn as_inotify(&self) -> Option<&Inotify> {
+        Some(self)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if events.contains(PollEvents::IN) && !self.queue.events.exclusive_access().is_empty() {
+            PollEvents::IN
+        } else {
+            PollEvents::empty()
+        }
+    }
+    fn stat(&self) -> Stat {
+        let mut stat = Stat::new_with_defaults(0, 0, StatMode::NULL, 1);
+        stat.size = (self.queue.events.exclusive_access().len() * INOTIFY_EVENT_SIZE) as i64;
+        stat
+    }
+}
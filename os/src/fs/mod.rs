@@ -1,9 +1,26 @@
 //! 文件特征与 inode（目录、文件、管道、标准输入输出）
 
+#[cfg(feature = "fs-boot-test")]
+mod boot_test;
+mod dcache;
+mod fifo;
+mod flock;
+mod inotify;
 mod inode;
+mod mount;
 mod stdio;
 mod pipe;
+mod poll;
+mod procfs;
+mod tmpfs;
+mod tty;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use crate::mm::UserBuffer;
+use crate::net::UdpSocket;
+use crate::syscall::{Errno, ENOENT, ENOTDIR, ENOTTY, ESPIPE};
+use fat32::VFile;
 
 /// 为所有文件类型定义的 File trait
 /// 所有类型的文件（如普通文件、目录、管道等）都应实现这个 trait
@@ -14,22 +31,113 @@ pub trait File: Send + Sync {
     /// 判断文件是否可写
     fn writable(&self) -> bool;
     
-    /// 从文件中读取数据到缓冲区 buf，返回读取的字节数
-    fn read(&self, buf: UserBuffer) -> usize;
+    /// 从文件中读取数据到缓冲区 buf，返回读取的字节数，出错时返回 -errno
+    fn read(&self, buf: UserBuffer) -> isize;
     
-    /// 向文件写入数据从缓冲区 buf，返回写入的字节数
-    fn write(&self, buf: UserBuffer) -> usize;
+    /// 向文件写入数据从缓冲区 buf，返回写入的字节数，出错时返回 -errno
+    fn write(&self, buf: UserBuffer) -> isize;
     
-    /// 尝试获取该文件对应的 OSInode（操作系统级别的 inode）
-    fn as_osinode(&self) -> Option<&OSInode> {
+    /// FAT 文件系统上的文件和目录返回对应的 VFile，其他文件返回 `None`
+    fn vfile(&self) -> Option<Arc<VFile>> {
         None
     }
+
+    /// 经 openat 打开时在文件系统中的绝对路径，用于解析相对于目录 fd 的路径；没有记录时返回 `None`
+    fn path(&self) -> Option<String> {
+        None
+    }
+
+    /// 尝试获取该文件对应的管道端，用于 F_GETPIPE_SZ 和 F_SETPIPE_SZ
+    fn as_pipe(&self) -> Option<&Pipe> {
+        None
+    }
+
+    /// 尝试获取该文件对应的 inotify 实例，用于 inotify_add_watch
+    fn as_inotify(&self) -> Option<&Inotify> {
+        None
+    }
+
+    /// 尝试获取该文件对应的 UDP 套接字，用于 bind、sendto 和 recvfrom
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        None
+    }
+
+    /// 将写入文件但仍在缓存中的数据写回设备，由 fsync 和进程退出时调用，失败时返回 errno
+    fn sync(&self) -> Result<(), Errno> {
+        Ok(())
+    }
+
+    /// 获取文件的状态信息
+    fn stat(&self) -> Stat;
+
+    /// 不阻塞地返回 `events` 中已就绪的事件，ERR 和 HUP 即使没有请求也会返回。
+    /// 默认可读的文件总是可读、可写的文件总是可写（如普通文件和标准输出）
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable() {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+
+    /// 列出目录中的所有项；不是目录的文件返回 ENOTDIR
+    fn entries(&self) -> Result<Vec<DirEntryInfo>, Errno> {
+        Err(ENOTDIR)
+    }
+
+    /// 从目录的当前位置（由 seek 设置）开始，将尽可能多的目录项以 linux_dirent64 格式写入 `buf`，
+    /// 返回写入的字节数，读到目录末尾时返回 0；不是目录的文件返回 -ENOTDIR
+    fn dirents(&self, _buf: &mut [u8]) -> isize {
+        -ENOTDIR
+    }
+
+    /// 按 `whence` 将读写位置移动 `offset` 字节，返回新的位置；
+    /// 不支持定位的文件（如管道和标准输入输出）返回 -ESPIPE
+    fn seek(&self, _offset: i64, _whence: SeekWhence) -> isize {
+        -ESPIPE
+    }
+
+    /// flock 使用的文件标识，同一个文件的所有打开实例返回相同的值；不支持 flock 的文件返回 `None`
+    fn lock_key(&self) -> Option<LockKey> {
+        None
+    }
+
+    /// 设备相关的控制操作，`arg` 通常是用户空间的指针；不是终端的文件返回 -ENOTTY
+    fn ioctl(&self, _request: usize, _arg: usize) -> isize {
+        -ENOTTY
+    }
+}
+
+/// lseek 的 whence 参数，表示偏移量的基准位置
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeekWhence {
+    /// 相对文件开头（SEEK_SET）
+    Set,
+    /// 相对当前位置（SEEK_CUR）
+    Cur,
+    /// 相对文件末尾（SEEK_END）
+    End,
+}
+
+impl SeekWhence {
+    /// 由系统调用的 whence 参数转换，未知的值返回 `None`
+    pub fn from_raw(whence: usize) -> Option<Self> {
+        match whence {
+            0 => Some(Self::Set),
+            1 => Some(Self::Cur),
+            2 => Some(Self::End),
+            _ => None,
+        }
+    }
 }
 
 /// inode 的状态结构体
-/// 描述文件的元数据（如设备 ID、inode 编号、文件类型等）
+/// 描述文件的元数据（如设备 ID、inode 编号、文件类型等），布局与 RISC-V Linux 的 `struct stat` 相同
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Stat {
     /// 文件所在的设备 ID
     pub dev: u64,
@@ -42,62 +150,153 @@ pub struct Stat {
     
     /// 硬链接的数量
     pub nlink: u32,
-    
+
+    /// 所有者的用户 ID
+    pub uid: u32,
+
+    /// 所有者的组 ID
+    pub gid: u32,
+
+    /// 设备文件的设备号
+    pub rdev: u64,
+
+    /// 填充字段，保持结构体对齐
+    pad: u64,
+
+    /// 文件的字节数
+    pub size: i64,
+
+    /// 文件系统 I/O 的块大小
+    pub blksize: u32,
+
     /// 填充字段，保持结构体对齐
-    pad: [u64; 7],
+    pad2: u32,
+
+    /// 文件占用的 512 字节块数
+    pub blocks: u64,
+
+    /// 最近访问、修改和状态改变的时间，依次为秒和纳秒
+    pub times: [i64; 6],
+
+    /// 未使用
+    unused: [u32; 2],
 }
 
 impl Stat {
-    /// 使用默认值来初始化 inode 的状态
+    /// 使用默认值来初始化 inode 的状态，其余字段为 0
     pub fn new_with_defaults(dev: u64, ino: u64, mode: StatMode, nlink: u32) -> Self {
         Stat {
             dev,
             ino,
             mode,
             nlink,
-            pad: [0; 7],  // 默认填充字段初始化为零
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            pad: 0,
+            size: 0,
+            blksize: 0,
+            pad2: 0,
+            blocks: 0,
+            times: [0; 6],
+            unused: [0; 2],
         }
     }
 }
 
 bitflags! {
-    /// inode 的模式（文件类型）
-    /// 这里定义了 inode 的不同类型（如目录、普通文件等）
+    /// inode 的模式（文件类型和权限）
+    /// 这里定义了 inode 的不同类型（如目录、普通文件等）和 rwx 权限位
     pub struct StatMode: u32 {
         /// 空类型
         const NULL  = 0;
         
+        /// 管道类型
+        const FIFO  = 0o010000;
+
+        /// 字符设备类型
+        const CHR   = 0o020000;
+
         /// 目录类型
         const DIR   = 0o040000;
         
         /// 普通文件类型
         const FILE  = 0o100000;
+
+        /// 所有者可读
+        const OWNER_READ  = 0o400;
+        /// 所有者可写
+        const OWNER_WRITE = 0o200;
+        /// 所有者可执行
+        const OWNER_EXEC  = 0o100;
+        /// 同组用户可读
+        const GROUP_READ  = 0o040;
+        /// 同组用户可写
+        const GROUP_WRITE = 0o020;
+        /// 同组用户可执行
+        const GROUP_EXEC  = 0o010;
+        /// 其他用户可读
+        const OTHER_READ  = 0o004;
+        /// 其他用户可写
+        const OTHER_WRITE = 0o002;
+        /// 其他用户可执行
+        const OTHER_EXEC  = 0o001;
     }
 }
 
-pub use inode::ROOT_INODE;  // 引入 ROOT_INODE 常量，表示根目录 inode
-pub use inode::{open_file, OSInode, OpenFlags, search_pwd, chdir};  // 引入与文件操作相关的函数和类型
-pub use stdio::{Stdin, Stdout};  // 引入标准输入输出类型
-pub use pipe::make_pipe;  // 引入管道创建函数
+impl StatMode {
+    /// 所有的写权限位，chmod 时任何一个写权限位都表示文件可写
+    pub const WRITE: StatMode = StatMode::from_bits_truncate(0o222);
 
-/// 列出所有应用程序
-/// 遍历根目录下的文件，并打印出文件名
-pub fn list_apps() -> i32 {
-    // 获取根目录下的文件列表
-    let name = ROOT_INODE.ls();
-    
-    match name {
-        Some(value) => {
-            // 遍历文件列表并打印文件名
-            for i in &value {
-                println!("{}", i.0);
-            }
+    /// 普通文件或目录的类型加上权限位：可写文件是 0644，只读文件是 0444，目录另外加上执行权限
+    pub fn with_permissions(is_dir: bool, readonly: bool) -> Self {
+        let mut mode = StatMode::OWNER_READ | StatMode::GROUP_READ | StatMode::OTHER_READ;
+        if !readonly {
+            mode |= StatMode::OWNER_WRITE;
         }
-        None => {
-            // 如果没有文件，则返回 0
-            0;
+        if is_dir {
+            mode | StatMode::DIR | StatMode::OWNER_EXEC | StatMode::GROUP_EXEC | StatMode::OTHER_EXEC
+        } else {
+            mode | StatMode::FILE
         }
     }
-    
-    0
+}
+
+pub use inode::ROOT_INODE;  // 引入 ROOT_INODE 常量，表示根目录 inode
+pub use inode::{fat_errno, flush_thread, sync_disks, open_file, DirEntryInfo, open_swap_file, OSInode, OpenFlags, search_pwd, create_bypath, chdir, canonical_path, real_path, resolve_path};  // 引入与文件操作相关的函数和类型
+pub use dcache::{dcache_stats, invalidate as dcache_invalidate, invalidate_all as dcache_invalidate_all};  // 目录查找缓存
+pub use fifo::{make_fifo, open_fifo, remove_fifo};  // 命名管道的创建、打开和删除
+pub use flock::{flock, LockKey};  // 建议性文件锁
+pub use inotify::{Inotify, IN_ALL_EVENTS};  // 目录变化通知
+use pipe::{PipeRingBuffer, PipeWaiters};
+pub use stdio::{Stderr, Stdin, Stdout};  // 引入标准输入输出类型
+pub use pipe::{make_pipe, pipe_poll_test, pipe_resize_test, pipe_ring_buffer_test, Pipe, PIPE_BUF, PIPE_MAX_SIZE};  // 引入管道创建函数、管道类型和测试
+pub use poll::{notify_readiness, wait_for_readiness, PollEvents};  // 文件就绪状态的查询和等待
+pub use tty::{line_discipline_test, open_tty, poll_console_input, TtyFile};  // 控制台终端和行规程
+pub use mount::{mount_fat, path_read_only, remount_fat, umount_fat, vfile_read_only};  // 其他磁盘上的 FAT 文件系统和只读挂载
+pub use procfs::open_procfs;  // 打开 /proc 下的文件和目录
+#[cfg(feature = "fs-boot-test")]
+pub use boot_test::{flush_order_test, io_error_test, list_dir_test};  // 在内存磁盘上运行的文件系统自测
+pub use tmpfs::{mount_tmpfs, open_tmpfs, tmpfs_is_dir, tmpfs_mkdir, tmpfs_mknod, tmpfs_test, tmpfs_unlink, umount_tmpfs, TmpFile};  // 挂载在 /tmp 的内存文件系统
+
+/// 列出目录 `path`（文件系统中规范化的绝对路径）中的所有项，依次在 procfs、tmpfs 和 FAT 中查找。
+/// 不需要当前进程，启动时也可以使用；路径不存在返回 ENOENT，不是目录返回 ENOTDIR
+pub fn list_dir(path: &str) -> Result<Vec<DirEntryInfo>, Errno> {
+    let dir: Arc<dyn File + Send + Sync> = if let Some(dir) = open_procfs(path) {
+        dir
+    } else if let Some(dir) = open_tmpfs(path, OpenFlags::RDONLY) {
+        dir?
+    } else {
+        let vfile = search_pwd(path).ok_or(ENOENT)?;
+        Arc::new(OSInode::new(true, false, vfile))
+    };
+    dir.entries()
+}
+
+/// 列出所有应用程序
+/// 启动时打印根目录下的文件名
+pub fn list_apps() {
+    for entry in list_dir("/").unwrap_or_default() {
+        println!("{}", entry.name);
+    }
 }
@@ -0,0 +1,149 @@
+//! 其他磁盘上的 FAT 文件系统的挂载表
+//!
+//! 根文件系统位于 vda。[`mount_fat`] 把其他块设备上的 FAT 文件系统挂载到一个已有的目录上，
+//! 路径查找（[`super::dcache::lookup`]）经过挂载点时改从该文件系统的根目录继续。
+//!
+//! 文件系统可以只读挂载：创建、写入、删除文件的系统调用检查 [`path_read_only`] 或 [`vfile_read_only`]，
+//! 在只读的文件系统上返回 -EROFS；同时块设备被设为只读，块缓存也不会写入它。
+//! 启动参数中有 `ro` 或磁盘写保护时根文件系统只读挂载，之后可以用 [`remount_fat`] 改为可写。
+
+use super::{dcache_invalidate, fat_errno, search_pwd};
+use crate::drivers::{dtb::bootargs, BLOCK_DEVICE};
+use crate::sync::UPSafeCell;
+use crate::syscall::{EBUSY, ENOENT, ENOTDIR, EROFS};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use fat32::{BlockDevice, FAT32Manager, VFile};
+use lazy_static::*;
+
+/// 一个挂载的文件系统
+struct FatMount {
+    root: Arc<VFile>,
+    device: Arc<dyn BlockDevice>,
+    /// 是否只读挂载
+    read_only: bool,
+}
+
+/// 根文件系统是否只读挂载
+static ROOT_READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// 挂载点（规范化的绝对路径）-> 挂载在上面的文件系统
+    static ref FAT_MOUNTS: UPSafeCell<BTreeMap<String, FatMount>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// `path`（规范化的绝对路径）是挂载点时，返回挂载在上面的文件系统的根目录
+pub fn mounted_root(path: &str) -> Option<Arc<VFile>> {
+    FAT_MOUNTS.exclusive_access().get(path).map(|mount| mount.root.clone())
+}
+
+/// 决定根文件系统的挂载方式：启动参数中有 `ro` 时让磁盘拒绝写请求，磁盘只读时只读挂载。
+/// 在打开根文件系统之前调用
+pub(super) fn init_root_mode() {
+    if bootargs().split_whitespace().any(|arg| arg == "ro") {
+        BLOCK_DEVICE.set_read_only(true);
+    }
+    if BLOCK_DEVICE.is_read_only() {
+        info!("root filesystem is mounted read-only");
+        ROOT_READ_ONLY.store(true, Ordering::Relaxed);
+    }
+}
+
+/// `path`（规范化的绝对路径）所在的 FAT 文件系统是否只读挂载
+pub fn path_read_only(path: &str) -> bool {
+    FAT_MOUNTS
+        .exclusive_access()
+        .iter()
+        .filter(|(point, _)| path.strip_prefix(point.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+        .max_by_key(|(point, _)| point.len())
+        .map_or(ROOT_READ_ONLY.load(Ordering::Relaxed), |(_, mount)| mount.read_only)
+}
+
+/// 文件或目录 `vfile` 所在的 FAT 文件系统是否只读挂载
+pub fn vfile_read_only(vfile: &VFile) -> bool {
+    let fs = vfile.get_fs();
+    FAT_MOUNTS
+        .exclusive_access()
+        .values()
+        .find(|mount| Arc::ptr_eq(&mount.root.get_fs(), &fs))
+        .map_or(ROOT_READ_ONLY.load(Ordering::Relaxed), |mount| mount.read_only)
+}
+
+/// 把块设备 `device` 上的 FAT 文件系统挂载到目录 `target`（规范化的绝对路径）上，`read_only` 时只读挂载。
+/// 一个设备只能挂载一次，根文件系统所在的设备也不能再挂载；只读的设备只能只读挂载
+pub fn mount_fat(target: &str, device: Arc<dyn BlockDevice>, read_only: bool) -> isize {
+    match search_pwd(target) {
+        Some(dir) if dir.is_dir() => {}
+        Some(_) => return -ENOTDIR,
+        None => return -ENOENT,
+    }
+    let in_use = Arc::ptr_eq(&device, &BLOCK_DEVICE)
+        || FAT_MOUNTS.exclusive_access().iter().any(|(point, mount)| point == target || Arc::ptr_eq(&mount.device, &device));
+    if in_use {
+        return -EBUSY;
+    }
+    if !read_only && device.is_read_only() {
+        return -EROFS;
+    }
+    let fs = match FAT32Manager::open(device.clone()) {
+        Ok(fs) => fs,
+        Err(err) => return -fat_errno(err),
+    };
+    let root = Arc::new(FAT32Manager::get_root_vfile(&fs));
+    if read_only {
+        device.set_read_only(true);
+    }
+    // 挂载点下缓存的目录属于被遮住的文件系统
+    dcache_invalidate(target);
+    FAT_MOUNTS.exclusive_access().insert(String::from(target), FatMount { root, device, read_only });
+    0
+}
+
+/// 按 `read_only` 重新挂载 `target`（规范化的绝对路径，可以是根目录）上的 FAT 文件系统，
+/// `target` 不是挂载点时返回 `None`。改为只读前写回修改过的缓存块；磁盘写保护时不能改为可写，返回 -EROFS
+pub fn remount_fat(target: &str, read_only: bool) -> Option<isize> {
+    let device = match target {
+        "/" => BLOCK_DEVICE.clone(),
+        _ => FAT_MOUNTS.exclusive_access().get(target)?.device.clone(),
+    };
+    if read_only {
+        if let Err(err) = fat32::sync_all() {
+            return Some(-fat_errno(err));
+        }
+        device.set_read_only(true);
+    } else if !device.set_read_only(false) {
+        return Some(-EROFS);
+    }
+    match FAT_MOUNTS.exclusive_access().get_mut(target) {
+        Some(mount) => mount.read_only = read_only,
+        None => ROOT_READ_ONLY.store(read_only, Ordering::Relaxed),
+    }
+    Some(0)
+}
+
+/// 卸载挂载在 `target` 上的文件系统，写回修改过的缓存块；`target` 不是挂载点时返回 `None`。
+/// 下面还挂载着其他文件系统时返回 -EBUSY
+pub fn umount_fat(target: &str) -> Option<isize> {
+    let mut mounts = FAT_MOUNTS.exclusive_access();
+    mounts.get(target)?;
+    let prefix = String::from(target) + "/";
+    if mounts.keys().any(|point| point.starts_with(&prefix)) {
+        return Some(-EBUSY);
+    }
+    let mount = mounts.remove(target).unwrap();
+    drop(mounts);
+    dcache_invalidate(target);
+    // 写回失败的块仍留在缓存中，由写回线程继续重试
+    let result = match fat32::sync_all() {
+        Ok(()) => 0,
+        Err(err) => -fat_errno(err),
+    };
+    // 设备下次可以可写挂载，写保护的磁盘仍然只读
+    if mount.read_only {
+        mount.device.set_read_only(false);
+    }
+    Some(result)
+}
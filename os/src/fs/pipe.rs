@@ -1,10 +1,13 @@
-use alloc::{sync::Weak, sync::Arc};
+use alloc::{sync::Arc, vec, vec::Vec};
 use spin::Mutex;
-use crate::{mm::UserBuffer, task::suspend_current_and_run_next};
-use super::File;
+use crate::{config::PAGE_SIZE, mm::UserBuffer, task::WaitQueue};
+use crate::syscall::{EAGAIN, EBUSY, EINVAL, EPIPE};
+use super::{notify_readiness, File, PollEvents, Stat, StatMode};
 
-// 定义环形缓冲区的大小
-const RING_BUFFER_SIZE: usize = 32;
+/// 新建管道的缓冲区大小，一个页帧
+pub const PIPE_BUF: usize = PAGE_SIZE;
+/// F_SETPIPE_SZ 允许设置的最大缓冲区大小
+pub const PIPE_MAX_SIZE: usize = 16 * PAGE_SIZE;
 
 // 当前环形缓冲区的状态
 #[derive(Copy, Clone, PartialEq)]
@@ -16,61 +19,152 @@ enum RingBufferStatus {
 
 /// 管道环形缓冲区
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE], // 环形缓冲区存储空间
+    arr: Vec<u8>, // 环形缓冲区存储空间，长度即容量
     head: usize,  // 读指针
     tail: usize,  // 写指针
     status: RingBufferStatus,  // 当前状态
-    write_end: Option<Weak<Pipe>>,  // 写端 (弱引用)
+    readers: usize,  // 打开的读端数量
+    writers: usize,  // 打开的写端数量
 }
 
-// 管道结构体
+/// 管道两端共享的等待队列，不能放进环形缓冲区的锁里，否则持锁阻塞会使另一端自旋
+pub struct PipeWaiters {
+    readers: WaitQueue, // 等待数据的读者
+    writers: WaitQueue, // 等待空闲空间的写者
+}
+
+impl PipeWaiters {
+    /// 创建空的等待队列
+    pub fn new() -> Self {
+        Self {
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }
+    }
+}
+
+impl Default for PipeWaiters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 管道的一端，读端和写端共享同一个环形缓冲区
 pub struct Pipe{
     readable: bool,  // 是否可读
     writable: bool,  // 是否可写
     buffer:Arc<Mutex<PipeRingBuffer>>,  // 环形缓冲区
+    waiters: Arc<PipeWaiters>,  // 两端共享的等待队列
+    nonblock: bool,  // 以 O_NONBLOCK 打开，读写不阻塞而返回 -EAGAIN
 }
 
 impl PipeRingBuffer {
     // 创建新的空环形缓冲区
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; PIPE_BUF],
             head: 0,
             tail: 0,
             status: RingBufferStatus::EMPTY,
-            write_end: None,
+            readers: 0,
+            writers: 0,
         }
     }
 }
 
 impl PipeRingBuffer {
-    // 设置写端
-    pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
-        self.write_end = Some(Arc::downgrade(write_end));
+    // 缓冲区的容量
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+
+    // 重新分配 `size` 字节的缓冲区，保留未读取的数据；新容量放不下这些数据时返回 false
+    pub fn resize(&mut self, size: usize) -> bool {
+        let unread = self.available_read();
+        if size < unread {
+            return false;
+        }
+        let mut arr = vec![0; size];
+        self.read_into(&mut arr[..unread]);
+        self.arr = arr;
+        self.head = 0;
+        self.tail = unread % size;
+        self.status = if unread == 0 {
+            RingBufferStatus::EMPTY
+        } else if unread == size {
+            RingBufferStatus::FULL
+        } else {
+            RingBufferStatus::NORMAL
+        };
+        true
     }
 
     // 读取一个字节
     pub fn read_byte(&mut self) -> u8 {
         self.status = RingBufferStatus::NORMAL;
         let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.head = (self.head + 1) % self.capacity();
         if self.head == self.tail {
             self.status = RingBufferStatus::EMPTY;
         }
         c
     }
 
-    // 写入一个字节
-    pub fn write_byte(&mut self, byte: u8) -> bool{
-        self.status = RingBufferStatus::NORMAL;
+    // 写入一个字节，缓冲区已满时不写入并返回 false，不会覆盖未读取的数据
+    pub fn write_byte(&mut self, byte: u8) -> bool {
+        if self.status == RingBufferStatus::FULL {
+            return false;
+        }
         self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::FULL;
-            return false; // 缓冲区已满，不能继续写入
+        self.tail = (self.tail + 1) % self.capacity();
+        self.status = if self.head == self.tail {
+            RingBufferStatus::FULL
         } else {
-            return true; // 写入成功
+            RingBufferStatus::NORMAL
+        };
+        true
+    }
+
+    // 将尽可能多的字节读入 `buf`，按连续段整体拷贝，返回读取的字节数
+    pub fn read_into(&mut self, buf: &mut [u8]) -> usize {
+        let total = self.available_read().min(buf.len());
+        let mut done = 0;
+        while done < total {
+            // 从 head 开始到数组末尾或数据末尾的连续段
+            let run = (total - done).min(self.capacity() - self.head);
+            buf[done..done + run].copy_from_slice(&self.arr[self.head..self.head + run]);
+            self.head = (self.head + run) % self.capacity();
+            done += run;
+        }
+        if total > 0 {
+            self.status = if self.head == self.tail {
+                RingBufferStatus::EMPTY
+            } else {
+                RingBufferStatus::NORMAL
+            };
+        }
+        total
+    }
+
+    // 将 `data` 中尽可能多的字节写入缓冲区，按连续段整体拷贝，返回写入的字节数
+    pub fn write_from(&mut self, data: &[u8]) -> usize {
+        let total = self.available_write().min(data.len());
+        let mut done = 0;
+        while done < total {
+            // 从 tail 开始到数组末尾或空闲空间末尾的连续段
+            let run = (total - done).min(self.capacity() - self.tail);
+            self.arr[self.tail..self.tail + run].copy_from_slice(&data[done..done + run]);
+            self.tail = (self.tail + run) % self.capacity();
+            done += run;
         }
+        if total > 0 {
+            self.status = if self.head == self.tail {
+                RingBufferStatus::FULL
+            } else {
+                RingBufferStatus::NORMAL
+            };
+        }
+        total
     }
 
     // 获取可读取的字节数
@@ -81,7 +175,7 @@ impl PipeRingBuffer {
             if self.tail > self.head {
                 self.tail - self.head
             } else {
-                self.tail + RING_BUFFER_SIZE - self.head
+                self.tail + self.capacity() - self.head
             }
         }
     }
@@ -92,7 +186,7 @@ impl PipeRingBuffer {
             0
         } else {
             if self.tail >= self.head {
-                self.head + RING_BUFFER_SIZE - self.tail
+                self.head + self.capacity() - self.tail
             } else {
                 self.head - self.tail
             }
@@ -101,97 +195,292 @@ impl PipeRingBuffer {
 
     // 检查是否所有写端都已关闭
     pub fn all_write_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+        self.writers == 0
+    }
+
+    // 检查是否所有读端都已关闭
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.readers == 0
     }
 }
 
 impl Pipe {
-    // 创建读端
-    pub fn read_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
+    /// 在共享的缓冲区上打开一端，读写两个方向都打开的一端（如以 O_RDWR 打开的 FIFO）同时计为读者和写者
+    pub fn with_buffer(
+        readable: bool,
+        writable: bool,
+        buffer: Arc<Mutex<PipeRingBuffer>>,
+        waiters: Arc<PipeWaiters>,
+    ) -> Self {
+        let mut ring_buffer = buffer.lock();
+        if readable {
+            ring_buffer.readers += 1;
+        }
+        if writable {
+            ring_buffer.writers += 1;
+        }
+        drop(ring_buffer);
         Self {
-            readable: true,
-            writable: false,
+            readable,
+            writable,
             buffer,
+            waiters,
+            nonblock: false,
         }
     }
 
-    // 创建写端
-    pub fn write_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>) -> Self {
-        Self {
-            readable: false,
-            writable: true,
-            buffer,
+    /// 设置这一端是否非阻塞
+    pub fn with_nonblock(mut self, nonblock: bool) -> Self {
+        self.nonblock = nonblock;
+        self
+    }
+
+    /// 创建读端
+    pub fn read_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>, waiters: Arc<PipeWaiters>) -> Self {
+        Self::with_buffer(true, false, buffer, waiters)
+    }
+
+    /// 创建写端
+    pub fn write_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>, waiters: Arc<PipeWaiters>) -> Self {
+        Self::with_buffer(false, true, buffer, waiters)
+    }
+}
+
+impl Pipe {
+    /// 管道缓冲区的容量，即 F_GETPIPE_SZ 的结果
+    pub fn capacity(&self) -> usize {
+        self.buffer.lock().capacity()
+    }
+
+    /// 将缓冲区容量设为至少 `size` 字节（向上取整到页），返回新的容量。
+    /// 超过 PIPE_MAX_SIZE 返回 -EINVAL，放不下未读取的数据返回 -EBUSY
+    pub fn set_capacity(&self, size: usize) -> isize {
+        if size > PIPE_MAX_SIZE {
+            return -EINVAL;
+        }
+        let size = size.max(PIPE_BUF).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        if !self.buffer.lock().resize(size) {
+            return -EBUSY;
         }
+        // 容量变大后写者可能可以继续写入
+        self.waiters.writers.wake_all();
+        notify_readiness();
+        size as isize
     }
 }
 
-/// 创建管道，返回读端和写端
-pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+impl Drop for Pipe {
+    // 一端关闭时唤醒另一端的等待者：读者据此发现写端已全部关闭
+    fn drop(&mut self) {
+        let mut ring_buffer = self.buffer.lock();
+        if self.readable {
+            ring_buffer.readers -= 1;
+        }
+        if self.writable {
+            ring_buffer.writers -= 1;
+        }
+        drop(ring_buffer);
+        if self.writable {
+            self.waiters.readers.wake_all();
+        }
+        if self.readable {
+            self.waiters.writers.wake_all();
+        }
+        notify_readiness();
+    }
+}
+
+/// 创建管道，返回读端和写端，`nonblock` 对应 pipe2 的 O_NONBLOCK
+pub fn make_pipe(nonblock: bool) -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+    let waiters = Arc::new(PipeWaiters::new());
     let read_end = Arc::new(
-        Pipe::read_end_with_buffer(buffer.clone())
+        Pipe::read_end_with_buffer(buffer.clone(), waiters.clone()).with_nonblock(nonblock)
     );
     let write_end = Arc::new(
-        Pipe::write_end_with_buffer(buffer.clone())
+        Pipe::write_end_with_buffer(buffer, waiters).with_nonblock(nonblock)
     );
-    buffer.lock().set_write_end(&write_end); // 设置写端
     (read_end, write_end)
 }
 
+/// 检查管道两端的就绪状态：空 -> 有数据 -> 满 -> 读端关闭，以及写端关闭后读端的 HUP
+#[allow(unused)]
+pub fn pipe_poll_test() {
+    let all = PollEvents::IN | PollEvents::OUT;
+    let (read_end, write_end) = make_pipe(false);
+    assert_eq!(read_end.poll(all), PollEvents::empty());
+    assert_eq!(write_end.poll(all), PollEvents::OUT);
+    assert_eq!(write_end.buffer.lock().write_from(b"x"), 1);
+    assert_eq!(read_end.poll(all), PollEvents::IN);
+    assert_eq!(write_end.poll(all), PollEvents::OUT);
+    assert_eq!(write_end.buffer.lock().write_from(&[0; PIPE_BUF]), PIPE_BUF - 1);
+    assert_eq!(read_end.poll(all), PollEvents::IN);
+    assert_eq!(write_end.poll(all), PollEvents::empty());
+    // 没有请求的 ERR 也要报告
+    drop(read_end);
+    assert_eq!(write_end.poll(PollEvents::empty()), PollEvents::ERR);
+
+    let (read_end, write_end) = make_pipe(false);
+    assert_eq!(write_end.buffer.lock().write_from(b"x"), 1);
+    drop(write_end);
+    assert_eq!(read_end.poll(all), PollEvents::IN | PollEvents::HUP);
+    info!("pipe_poll_test passed!");
+}
+
+/// 改变管道容量时保留未读取的数据，包括绕回缓冲区开头的数据
+#[allow(unused)]
+pub fn pipe_resize_test() {
+    let (read_end, write_end) = make_pipe(false);
+    assert_eq!(read_end.capacity(), PIPE_BUF);
+    assert_eq!(write_end.set_capacity(PIPE_MAX_SIZE + 1), -EINVAL);
+    // 让数据绕回缓冲区开头
+    let data: Vec<u8> = (0..PIPE_BUF).map(|i| i as u8).collect();
+    let mut buf = vec![0u8; PIPE_BUF];
+    {
+        let mut ring_buffer = read_end.buffer.lock();
+        assert_eq!(ring_buffer.write_from(&data[..PIPE_BUF - 100]), PIPE_BUF - 100);
+        assert_eq!(ring_buffer.read_into(&mut buf[..PIPE_BUF - 200]), PIPE_BUF - 200);
+        assert_eq!(ring_buffer.write_from(&data), PIPE_BUF - 100);
+        assert_eq!(ring_buffer.available_read(), PIPE_BUF);
+    }
+    // 放大后未读取的数据按原顺序保留，并且可以继续写入
+    assert_eq!(write_end.set_capacity(3 * PAGE_SIZE - 1), 3 * PAGE_SIZE as isize);
+    let mut ring_buffer = read_end.buffer.lock();
+    assert_eq!(ring_buffer.available_write(), 2 * PAGE_SIZE);
+    assert_eq!(ring_buffer.write_from(&data[..200]), 200);
+    drop(ring_buffer);
+    // 缩小到放不下未读取的数据时失败
+    assert_eq!(write_end.set_capacity(0), -EBUSY);
+    let mut ring_buffer = read_end.buffer.lock();
+    assert_eq!(ring_buffer.read_into(&mut buf[..100]), 100);
+    assert!(buf[..100].iter().enumerate().all(|(i, &b)| b == (PIPE_BUF - 200 + i) as u8));
+    assert_eq!(ring_buffer.read_into(&mut buf), PIPE_BUF);
+    assert_eq!(&buf[..PIPE_BUF - 100], &data[..PIPE_BUF - 100]);
+    assert_eq!(&buf[PIPE_BUF - 100..], &data[..100]);
+    drop(ring_buffer);
+    assert_eq!(write_end.set_capacity(0), PIPE_BUF as isize);
+    info!("pipe_resize_test passed!");
+}
+
+/// 逐字节写入在缓冲区满时停止而不覆盖未读数据，按连续段读写在绕回时保持顺序
+#[allow(unused)]
+pub fn pipe_ring_buffer_test() {
+    let mut ring_buffer = PipeRingBuffer::new();
+    for i in 0..PIPE_BUF {
+        assert!(ring_buffer.write_byte(i as u8));
+    }
+    assert!(!ring_buffer.write_byte(0xff));
+    assert_eq!(ring_buffer.available_write(), 0);
+    assert_eq!(ring_buffer.read_byte(), 0);
+    assert_eq!(ring_buffer.read_byte(), 1);
+    // 写满后剩余的数据没有被覆盖
+    let mut buf = vec![0u8; PIPE_BUF];
+    assert_eq!(ring_buffer.write_from(&[0xaa; 3]), 2);
+    assert_eq!(ring_buffer.read_into(&mut buf), PIPE_BUF);
+    assert!(buf[..PIPE_BUF - 2].iter().enumerate().all(|(i, &b)| b == (i + 2) as u8));
+    assert_eq!(&buf[PIPE_BUF - 2..], &[0xaa, 0xaa]);
+    assert_eq!(ring_buffer.available_read(), 0);
+    assert_eq!(ring_buffer.read_into(&mut buf), 0);
+    info!("pipe_ring_buffer_test passed!");
+}
+
 impl File for Pipe {
-    // 通过管道读取数据
-    fn read(&self, buf: UserBuffer) -> usize {
+    // 通过管道读取数据：没有数据时阻塞，读到数据后只取走当前可读的部分就返回，
+    // 不等待填满缓冲区；缓冲区为空且写端全部关闭时返回 0，还有写端时非阻塞的一端返回 -EAGAIN
+    fn read(&self, buf: UserBuffer) -> isize {
         assert_eq!(self.readable, true);
-        let mut buf_iter = buf.into_iter();
         let mut read_size = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.lock();
-            let loop_read = ring_buffer.available_read();
-            if loop_read == 0 {
-                // 如果没有可读字节且所有写端都已关闭，返回读取的字节数
-                if ring_buffer.all_write_ends_closed() {
-                    return read_size;
+        for slice in buf.buffers {
+            let mut done = 0usize;
+            while done < slice.len() {
+                let mut ring_buffer = self.buffer.lock();
+                if ring_buffer.available_read() == 0 {
+                    // 已经读到数据，或者没有可读字节且所有写端都已关闭，返回读取的字节数
+                    if read_size > 0 || ring_buffer.all_write_ends_closed() {
+                        return read_size as isize;
+                    }
+                    if self.nonblock {
+                        return -EAGAIN;
+                    }
+                    drop(ring_buffer);
+                    self.waiters.readers.wait(); // 阻塞到写端写入数据或关闭
+                    continue;
                 }
+                let n = ring_buffer.read_into(&mut slice[done..]);
                 drop(ring_buffer);
-                suspend_current_and_run_next(); // 当前任务挂起，切换到下一个任务
-                continue;
-            }
-            // 读取最多 loop_read 字节
-            for _ in 0..loop_read {
-                if let Some(byte_ref) = buf_iter.next() {
-                    unsafe { *byte_ref = ring_buffer.read_byte(); }
-                    read_size += 1;
-                } else {
-                    return read_size;
-                }
+                self.waiters.writers.wake_all(); // 腾出了空间，唤醒等待的写者
+                notify_readiness();
+                done += n;
+                read_size += n;
             }
         }
+        read_size as isize
     }
 
-    // 通过管道写入数据
-    fn write(&self, buf: UserBuffer) -> usize {
+    // 通过管道写入数据：缓冲区满时阻塞；读端全部关闭后数据不会再被取走，
+    // 返回已写入的字节数，一个字节都没写入时返回 -EPIPE。内核还没有信号，不发送 SIGPIPE。
+    // 非阻塞的一端在缓冲区满时返回已写入的字节数，一个字节都没写入时返回 -EAGAIN
+    fn write(&self, buf: UserBuffer) -> isize {
         assert_eq!(self.writable, true);
-        let mut buf_iter = buf.into_iter();
         let mut write_size = 0usize;
-        loop {
-            let mut ring_buffer = self.buffer.lock();
-            let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
+        for slice in buf.buffers.iter() {
+            let mut done = 0usize;
+            while done < slice.len() {
+                let mut ring_buffer = self.buffer.lock();
+                if ring_buffer.all_read_ends_closed() {
+                    return if write_size > 0 { write_size as isize } else { -EPIPE };
+                }
+                if ring_buffer.available_write() == 0 {
+                    if self.nonblock {
+                        return if write_size > 0 { write_size as isize } else { -EAGAIN };
+                    }
+                    drop(ring_buffer);
+                    self.waiters.writers.wait(); // 阻塞到读端取走数据或关闭
+                    continue;
+                }
+                let n = ring_buffer.write_from(&slice[done..]);
                 drop(ring_buffer);
-                suspend_current_and_run_next(); // 当前任务挂起，切换到下一个任务
-                continue;
+                self.waiters.readers.wake_all(); // 有了新数据，唤醒等待的读者
+                notify_readiness();
+                done += n;
+                write_size += n;
             }
+        }
+        write_size as isize
+    }
 
-            // 写入最多 loop_write 字节
-            for _ in 0..loop_write {
-                if let Some(byte_ref) = buf_iter.next() {
-                    unsafe { ring_buffer.write_byte(*byte_ref); }
-                    write_size += 1;
-                } else {
-                    return write_size;
-                }
+    // 读端在有数据时可读，写端全部关闭时报告 HUP；写端在有空间时可写，读端全部关闭时报告 ERR
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let ring_buffer = self.buffer.lock();
+        let mut ready = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                ready |= PollEvents::IN;
+            }
+            if ring_buffer.all_write_ends_closed() {
+                ready |= PollEvents::HUP;
+            }
+        }
+        if self.writable {
+            if ring_buffer.all_read_ends_closed() {
+                ready |= PollEvents::ERR;
+            } else if ring_buffer.available_write() > 0 {
+                ready |= PollEvents::OUT;
             }
         }
+        ready & (events | PollEvents::ERR | PollEvents::HUP)
+    }
+
+    // 管道的两端共享缓冲区，用缓冲区的地址作为 inode 号
+    fn stat(&self) -> Stat {
+        let ino = Arc::as_ptr(&self.buffer) as usize as u64;
+        let mut stat = Stat::new_with_defaults(0, ino, StatMode::FIFO, 1);
+        stat.blksize = PIPE_BUF as u32;
+        stat
+    }
+
+    fn as_pipe(&self) -> Option<&Pipe> {
+        Some(self)
     }
 
     // 判断是否可读
@@ -0,0 +1,42 @@
+//! 文件就绪状态的查询和等待
+//!
+//! [`File::poll`](super::File::poll) 不阻塞地返回文件已就绪的事件。需要等待多个文件时，
+//! 任务在检查所有文件都未就绪之后调用 [`wait_for_readiness`] 阻塞；任何文件的就绪状态
+//! 可能改变时（管道读写或关闭、控制台收到字符），由 [`notify_readiness`] 唤醒所有等待者重新检查。
+//! 单核且内核态不可抢占，检查和阻塞之间不会丢失唤醒。
+
+use crate::task::WaitQueue;
+use lazy_static::*;
+
+bitflags! {
+    /// poll 的事件，取值与 Linux 的 `struct pollfd` 相同
+    pub struct PollEvents: u16 {
+        /// 有数据可读
+        const IN   = 0x001;
+        /// 有紧急数据可读
+        const PRI  = 0x002;
+        /// 可以写入
+        const OUT  = 0x004;
+        /// 出错，例如管道的读端已全部关闭
+        const ERR  = 0x008;
+        /// 对端已挂断，例如管道的写端已全部关闭
+        const HUP  = 0x010;
+        /// 文件描述符无效
+        const NVAL = 0x020;
+    }
+}
+
+lazy_static! {
+    /// 等待任意文件的就绪状态发生变化的任务
+    static ref POLL_WAITERS: WaitQueue = WaitQueue::new();
+}
+
+/// 阻塞当前任务，直到某个文件的就绪状态可能发生变化；返回后调用者需要重新调用 poll 检查
+pub fn wait_for_readiness() {
+    POLL_WAITERS.wait();
+}
+
+/// 文件的就绪状态可能发生了变化，唤醒所有等待者
+pub fn notify_readiness() {
+    POLL_WAITERS.wake_all();
+}
@@ -0,0 +1,312 @@
+//! 只读的 /proc 文件
+//!
+//! 目前只提供 /proc/meminfo、/proc/stat、/proc/<pid>/maps、/proc/<pid>/comm、/proc/<pid>/stat 和
+//! /proc/<pid>/status（`<pid>` 也可以是 self）。文件内容在打开时生成，
+//! 之后的读取只返回这份快照。目录 /proc 和 /proc/<pid> 同样在打开时记下目录项，
+//! /proc 列出打开时存在的所有进程。
+
+use super::inode::{fill_dirents, DirEntryInfo};
+use super::{canonical_path, dcache_stats, File, SeekWhence, Stat, StatMode};
+use crate::drivers::{block_merges, block_ops, block_reads};
+use crate::config::{CLOCK_FREQ, PAGE_SIZE};
+use crate::mm::{frame_allocator, heap_stats, AreaBacking, MapPermission, MapType, MemorySet, UserBuffer};
+use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, EINVAL};
+use crate::task::{current_process, find_process, list_processes, render_stats, ProcessControlBlock, TaskStatus};
+use crate::timer::get_time;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// maps 中路径名开始的列
+const MAPS_NAME_COLUMN: usize = 73;
+/// stat 中运行时间的单位，每秒的时钟滴答数（Linux 的 USER_HZ）
+const USER_HZ: u64 = 100;
+
+/// 打开时生成内容的只读文件
+pub struct ProcFile {
+    data: Vec<u8>,                // 文件内容
+    offset: UPSafeCell<usize>,    // 当前读取的偏移量
+}
+
+impl ProcFile {
+    fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            offset: unsafe { UPSafeCell::new(0) },
+        }
+    }
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let mut offset = self.offset.exclusive_access();
+        let read_size = buf.write(&self.data[*offset..]);
+        *offset += read_size;
+        read_size as isize
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        0
+    }
+    fn stat(&self) -> Stat {
+        let mut stat = Stat::new_with_defaults(0, 0, StatMode::with_permissions(false, true), 1);
+        stat.size = self.data.len() as i64;
+        stat
+    }
+}
+
+/// /proc 的目录，目录项在打开时确定
+pub struct ProcDir {
+    /// 目录的绝对路径，用于解析相对于它的 fstatat 路径
+    path: String,
+    entries: Vec<DirEntryInfo>,
+    /// 下一个要读取的目录项的序号
+    offset: UPSafeCell<usize>,
+}
+
+impl ProcDir {
+    fn new(path: String, entries: Vec<DirEntryInfo>) -> Self {
+        Self {
+            path,
+            entries,
+            offset: unsafe { UPSafeCell::new(0) },
+        }
+    }
+}
+
+impl File for ProcDir {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> isize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> isize {
+        0
+    }
+    fn path(&self) -> Option<String> {
+        Some(self.path.clone())
+    }
+    fn stat(&self) -> Stat {
+        Stat::new_with_defaults(0, 0, StatMode::with_permissions(true, true), 2)
+    }
+    fn entries(&self) -> Result<Vec<DirEntryInfo>, Errno> {
+        Ok(self.entries.clone())
+    }
+    fn dirents(&self, buf: &mut [u8]) -> isize {
+        fill_dirents(&self.entries, &mut self.offset.exclusive_access(), buf)
+    }
+    // 与其他目录相同，位置是下一个要读取的目录项的序号
+    fn seek(&self, offset: i64, whence: SeekWhence) -> isize {
+        let mut pos = self.offset.exclusive_access();
+        let base = match whence {
+            SeekWhence::Set => 0,
+            SeekWhence::Cur => *pos as i64,
+            SeekWhence::End => return -EINVAL,
+        };
+        match base.checked_add(offset) {
+            Some(new_pos) if new_pos >= 0 => {
+                *pos = new_pos as usize;
+                new_pos as isize
+            }
+            _ => -EINVAL,
+        }
+    }
+}
+
+/// 每个进程目录下的文件
+const PID_FILES: [&str; 4] = ["comm", "maps", "stat", "status"];
+
+/// 如果 `path` 是 /proc 下的文件或目录，打开它；不是或对应的进程不存在时返回 `None`。
+/// `path` 是文件系统中的绝对路径，调用者先按进程的根目录解析，chroot 之后就看不到 /proc
+pub fn open_procfs(path: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    if !path.starts_with('/') {
+        return None;
+    }
+    let path = canonical_path("/", path);
+    let rest = path.strip_prefix("/proc")?;
+    let rest = match rest {
+        "" => return Some(Arc::new(ProcDir::new(path.clone(), render_proc_root()))),
+        rest => rest.strip_prefix('/')?,
+    };
+    let data = match rest {
+        "meminfo" => render_meminfo(),
+        "stat" => {
+            let mut stats = render_stats();
+            let (hits, misses, entries) = dcache_stats();
+            writeln!(
+                stats,
+                "dcache {} {} {}\nblock_reads {}\nblock_ops {}\nblock_merges {}",
+                hits,
+                misses,
+                entries,
+                block_reads(),
+                block_ops(),
+                block_merges()
+            )
+            .unwrap();
+            stats
+        }
+        rest => {
+            let (pid, file) = rest.split_once('/').unwrap_or((rest, ""));
+            let process = match pid {
+                "self" => current_process(),
+                pid => find_process(pid.parse().ok()?)?,
+            };
+            match file {
+                "" => {
+                    let entries = PID_FILES.iter().map(|name| DirEntryInfo::new(String::from(*name), 0, false, 0)).collect();
+                    return Some(Arc::new(ProcDir::new(path.clone(), entries)));
+                }
+                "maps" => render_maps(&process.inner_exclusive_access().memory_set),
+                "comm" => render_comm(&process),
+                "stat" => render_pid_stat(&process),
+                "status" => render_status(&process),
+                _ => return None,
+            }
+        }
+    };
+    Some(Arc::new(ProcFile::new(data.into_bytes())))
+}
+
+/// /proc 的目录项：全局文件、self 和每个存在的进程的目录，进程目录的 inode 号取 pid
+fn render_proc_root() -> Vec<DirEntryInfo> {
+    let mut entries = Vec::from([
+        DirEntryInfo::new(String::from("meminfo"), 0, false, 0),
+        DirEntryInfo::new(String::from("stat"), 0, false, 0),
+        DirEntryInfo::new(String::from("self"), 0, true, 0),
+    ]);
+    for process in list_processes() {
+        let pid = process.getpid();
+        entries.push(DirEntryInfo::new(format!("{}", pid), pid as u64, true, 0));
+    }
+    entries
+}
+
+/// 物理页面帧和内核堆的使用情况，前三行的格式与 Linux 的 /proc/meminfo 相同
+fn render_meminfo() -> String {
+    let frames = frame_allocator::stats();
+    let heap = heap_stats();
+    let kib = |pages: usize| pages * PAGE_SIZE / 1024;
+    format!(
+        "MemTotal:\t{} kB\nMemFree:\t{} kB\nMemAvailable:\t{} kB\nMemPeakUsed:\t{} kB\nHeapTotal:\t{} kB\nHeapUsed:\t{} kB\n",
+        kib(frames.total),
+        kib(frames.free),
+        kib(frames.free),
+        kib(frames.peak_used),
+        heap.total / 1024,
+        heap.allocated / 1024,
+    )
+}
+
+/// 按 Linux 的 "start-end perms offset dev inode path" 格式列出用户可访问的区域
+fn render_maps(memory_set: &MemorySet) -> String {
+    let mut maps = String::new();
+    for area in memory_set.iter_areas() {
+        if !area.perm.contains(MapPermission::U) || area.start == area.end {
+            continue; // 跳过内核使用的区域和空区域
+        }
+        let perms = format!(
+            "{}{}{}{}",
+            if area.perm.contains(MapPermission::R) { 'r' } else { '-' },
+            if area.perm.contains(MapPermission::W) { 'w' } else { '-' },
+            if area.perm.contains(MapPermission::X) { 'x' } else { '-' },
+            if area.map_type == MapType::Shared { 's' } else { 'p' },
+        );
+        let (offset, inode, name) = match area.backing {
+            AreaBacking::File { name, inode, offset } => (offset, inode, name),
+            AreaBacking::Label(label) => (0, 0, String::from(label)),
+            AreaBacking::Anonymous => (0, 0, String::new()),
+        };
+        let line_start = maps.len();
+        write!(maps, "{:08x}-{:08x} {} {:08x} 00:00 {}", area.start, area.end, perms, offset, inode).unwrap();
+        if !name.is_empty() {
+            let width = maps.len() - line_start;
+            maps.extend(core::iter::repeat(' ').take(MAPS_NAME_COLUMN.saturating_sub(width).max(1)));
+            maps.push_str(&name);
+        }
+        maps.push('\n');
+    }
+    maps
+}
+
+/// 进程中第一个未退出的线程（通常是主线程）的命令名，所有线程都已退出时为空
+fn render_comm(process: &ProcessControlBlock) -> String {
+    let inner = process.inner_exclusive_access();
+    let mut comm = inner.threads.iter().flatten().next().map(|thread| thread.name()).unwrap_or_default();
+    comm.push('\n');
+    comm
+}
+
+/// Linux 的 /proc/<pid>/stat 的前 24 个字段，不支持的字段填 0。
+/// 运行时间以 `USER_HZ` 为单位，rss 以页为单位
+fn render_pid_stat(process: &ProcessControlBlock) -> String {
+    let inner = process.inner_exclusive_access();
+    let ticks = |cycles: u64| cycles * USER_HZ / CLOCK_FREQ as u64;
+    let (utime, stime) = inner.cpu_times(get_time());
+    let (mut state, mut priority, mut comm) = (if inner.is_zombie() { 'Z' } else { 'S' }, 0, String::new());
+    for (i, thread) in inner.threads.iter().flatten().enumerate() {
+        let thread_inner = thread.inner_exclusive_access();
+        if matches!(thread_inner.task_status, TaskStatus::Running | TaskStatus::Ready) {
+            state = 'R';
+        }
+        if i == 0 {
+            priority = thread_inner.pri;
+            comm.clone_from(&thread_inner.name);
+        }
+    }
+    let ppid = inner.parent_pid();
+    let vsize: usize = inner
+        .memory_set
+        .iter_areas()
+        .filter(|area| area.perm.contains(MapPermission::U))
+        .map(|area| area.end - area.start)
+        .sum();
+    let mem_stats = inner.memory_set.mem_stats();
+    let children = inner.children_mem_stats;
+    format!(
+        "{} ({}) {} {} 0 0 0 -1 0 {} {} {} {} {} {} {} {} {} 0 {} 0 0 {} {}\n",
+        process.getpid(),
+        comm,
+        state,
+        ppid,
+        mem_stats.minor_faults,
+        children.minor_faults,
+        mem_stats.major_faults,
+        children.major_faults,
+        ticks(utime),
+        ticks(stime),
+        ticks(inner.task_info.cutime),
+        ticks(inner.task_info.cstime),
+        priority,
+        inner.thread_count(),
+        vsize,
+        inner.memory_set.resident_pages(),
+    )
+}
+
+/// 进程的内存占用和未退出线程的上下文切换次数，格式与 Linux 的 /proc/<pid>/status 中对应的行相同
+fn render_status(process: &ProcessControlBlock) -> String {
+    let inner = process.inner_exclusive_access();
+    let (nvcsw, nivcsw) = inner.context_switches();
+    let kib = |pages: usize| pages * PAGE_SIZE / 1024;
+    format!(
+        "Pid:\t{}\nVmHWM:\t{} kB\nVmRSS:\t{} kB\nvoluntary_ctxt_switches:\t{}\nnonvoluntary_ctxt_switches:\t{}\n",
+        process.getpid(),
+        kib(inner.memory_set.mem_stats().peak_rss),
+        kib(inner.memory_set.resident_pages()),
+        nvcsw,
+        nivcsw
+    )
+}
@@ -1,8 +1,18 @@
 //! Stdin & Stdout
-use super::File;
+use super::tty::{tty_ioctl, tty_read, tty_readable};
+use super::{File, PollEvents, Stat, StatMode};
+use crate::console::write_bytes;
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+
+/// 控制台的设备号（/dev/console，主设备号 5，次设备号 1）
+const CONSOLE_RDEV: u64 = (5 << 8) | 1;
+
+/// stdin 和 stdout 都是控制台这个字符设备
+fn console_stat() -> Stat {
+    let mut stat = Stat::new_with_defaults(0, 0, StatMode::CHR, 1);
+    stat.rdev = CONSOLE_RDEV;
+    stat
+}
 
 /// 代表从控制台获取字符的 stdin 文件
 pub struct Stdin;
@@ -10,6 +20,19 @@ pub struct Stdin;
 /// 代表将字符输出到控制台的 stdout 文件
 pub struct Stdout;
 
+/// 代表标准错误的文件，与 stdout 使用同一个控制台，但可以被单独重定向
+pub struct Stderr;
+
+/// 将用户缓冲区按字节原样输出到控制台，不要求是合法的 UTF-8，返回写入的字节数
+fn write_console(user_buf: &UserBuffer) -> usize {
+    let mut len = 0;
+    for slice in user_buf.buffers.iter() {
+        write_bytes(slice);
+        len += slice.len();
+    }
+    len
+}
+
 impl File for Stdin {
     // stdin 是可读的
     fn readable(&self) -> bool {
@@ -21,34 +44,32 @@ impl File for Stdin {
         false
     }
 
-    // 从 stdin 读取一个字符
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);  // 确保用户缓冲区的大小为 1
-        // 持续循环直到获取一个有效的字符
-        let mut c: usize;
-        loop {
-            c = console_getchar(); // 从控制台获取字符
-            if c == 0 {
-                // 如果没有读取到字符，挂起当前任务并切换到下一个任务
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                // 成功读取到字符，退出循环
-                break;
-            }
-        }
-        let ch = c as u8;  // 转换为 u8 字符
-        unsafe {
-            // 将读取到的字符写入用户缓冲区
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
-        1  // 返回读取的字节数，始终是 1
+    // 从 stdin 读取经过行规程处理的输入，没有输入时阻塞
+    fn read(&self, user_buf: UserBuffer) -> isize {
+        tty_read(user_buf) as isize
     }
 
     // 禁止向 stdin 写入
-    fn write(&self, _user_buf: UserBuffer) -> usize {
+    fn write(&self, _user_buf: UserBuffer) -> isize {
         panic!("无法向 stdin 写入数据！");
     }
+
+    fn stat(&self) -> Stat {
+        console_stat()
+    }
+
+    // 行规程中有可读的内容时可读
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if tty_readable() {
+            PollEvents::IN & events
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
 }
 
 impl File for Stdout {
@@ -63,17 +84,57 @@ impl File for Stdout {
     }
 
     // 禁止从 stdout 读取
-    fn read(&self, _user_buf: UserBuffer) -> usize {
+    fn read(&self, _user_buf: UserBuffer) -> isize {
         panic!("无法从 stdout 读取数据！");
     }
 
     // 向 stdout 写入数据
-    fn write(&self, user_buf: UserBuffer) -> usize {
-        // 遍历用户缓冲区并打印内容
-        for buffer in user_buf.buffers.iter() {
-            // 将每个缓冲区的内容作为字符串输出到控制台
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        write_console(&user_buf) as isize
+    }
+
+    fn stat(&self) -> Stat {
+        console_stat()
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
+}
+
+impl File for Stderr {
+    // stderr 不是可读的
+    fn readable(&self) -> bool {
+        false
+    }
+
+    // stderr 是可写的
+    fn writable(&self) -> bool {
+        true
+    }
+
+    // 禁止从 stderr 读取
+    fn read(&self, _user_buf: UserBuffer) -> isize {
+        panic!("无法从 stderr 读取数据！");
+    }
+
+    // 向 stderr 写入数据，启用 stderr-color 时用红色显示
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        if cfg!(feature = "stderr-color") {
+            write_bytes(b"\x1b[31m");
+        }
+        let len = write_console(&user_buf);
+        if cfg!(feature = "stderr-color") {
+            write_bytes(b"\x1b[0m");
         }
-        user_buf.len()  // 返回写入的字节数
+        len as isize
+    }
+
+    fn stat(&self) -> Stat {
+        console_stat()
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
     }
 }
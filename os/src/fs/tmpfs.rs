@@ -0,0 +1,477 @@
+//! 内存文件系统（tmpfs）
+//!
+//! 文件和目录都只保存在内存中，不读写磁盘，适合放测试产生的临时文件。
+//! 启动时在 /tmp 挂载一个 tmpfs，mount 也可以用 "tmpfs" 类型在其他目录上挂载新的实例。
+//! 挂载表 [`MOUNTS`] 以挂载点的规范化绝对路径为键，路径落在某个挂载点之下时
+//! 由最长的挂载点对应的 tmpfs 处理，其余路径仍由 FAT 处理。
+//!
+//! 每个实例的文件数据总量受字节预算限制，超出时写入返回 -ENOSPC。
+//! 文件被删除且所有打开的描述符都关闭后，它占用的预算才会释放。
+
+use super::inode::{fill_dirents, DirEntryInfo};
+use super::flock::release as release_lock;
+use super::{search_pwd, File, LockKey, OpenFlags, SeekWhence, Stat, StatMode};
+use crate::config::TMPFS_SIZE;
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, EBUSY, EEXIST, EINVAL, EISDIR, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::*;
+
+/// 启动时挂载的 tmpfs 的挂载点
+const TMPFS_MOUNT_POINT: &str = "/tmp";
+/// fstat 返回的设备号，用于和 FAT 上的文件区分
+const TMPFS_DEV: u64 = 2;
+/// fstat 返回的块大小
+const TMPFS_BLKSIZE: u32 = 4096;
+
+/// 下一个分配的 inode 号
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+/// 一个 tmpfs 实例的字节预算
+struct Budget {
+    /// 文件数据最多占用的字节数
+    limit: usize,
+    /// 已经占用的字节数
+    used: UPSafeCell<usize>,
+}
+
+impl Budget {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: unsafe { UPSafeCell::new(0) },
+        }
+    }
+
+    /// 剩余可用的字节数
+    fn available(&self) -> usize {
+        self.limit - *self.used.exclusive_access()
+    }
+
+    /// 占用 `bytes` 字节，调用者保证不超过剩余的字节数
+    fn charge(&self, bytes: usize) {
+        *self.used.exclusive_access() += bytes;
+    }
+
+    /// 释放 `bytes` 字节
+    fn release(&self, bytes: usize) {
+        *self.used.exclusive_access() -= bytes;
+    }
+}
+
+/// inode 的内容
+pub enum Inode {
+    /// 普通文件，保存全部数据
+    File(Vec<u8>),
+    /// 目录，文件名到子节点的映射
+    Directory(BTreeMap<String, Arc<RamInode>>),
+}
+
+/// tmpfs 中的一个文件或目录
+pub struct RamInode {
+    ino: u64,
+    budget: Arc<Budget>,
+    inner: UPSafeCell<Inode>,
+}
+
+impl RamInode {
+    fn new(budget: Arc<Budget>, inode: Inode) -> Arc<Self> {
+        Arc::new(Self {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            budget,
+            inner: unsafe { UPSafeCell::new(inode) },
+        })
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(*self.inner.exclusive_access(), Inode::Directory(_))
+    }
+
+    fn size(&self) -> usize {
+        match &*self.inner.exclusive_access() {
+            Inode::File(data) => data.len(),
+            Inode::Directory(entries) => entries.len(),
+        }
+    }
+
+    /// 从 `offset` 开始读取数据到 `buf`，返回读取的字节数
+    fn read_at(&self, offset: usize, buf: &mut UserBuffer) -> usize {
+        match &*self.inner.exclusive_access() {
+            Inode::File(data) if offset < data.len() => buf.write(&data[offset..]),
+            _ => 0,
+        }
+    }
+
+    /// 从 `offset` 开始写入 `buf`，文件变长的部分占用预算。
+    /// 预算不够时只写入放得下的部分，一个字节都放不下时返回 ENOSPC
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, Errno> {
+        let mut inner = self.inner.exclusive_access();
+        let Inode::File(data) = &mut *inner else {
+            return Err(EISDIR);
+        };
+        // 文件最多能增长到的长度，定位到末尾之后留下的空洞也占用预算
+        let limit = data.len() + self.budget.available();
+        let len = buf.len().min(limit.saturating_sub(offset));
+        if len == 0 && !buf.is_empty() {
+            return Err(ENOSPC);
+        }
+        let end = offset + len;
+        if end > data.len() {
+            self.budget.charge(end - data.len());
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    /// 将文件截断为空，释放占用的预算
+    fn truncate(&self) {
+        if let Inode::File(data) = &mut *self.inner.exclusive_access() {
+            self.budget.release(data.len());
+            data.clear();
+        }
+    }
+
+    /// 在目录中查找名为 `name` 的子节点
+    fn find(&self, name: &str) -> Result<Arc<RamInode>, Errno> {
+        match &*self.inner.exclusive_access() {
+            Inode::Directory(entries) => entries.get(name).cloned().ok_or(ENOENT),
+            Inode::File(_) => Err(ENOTDIR),
+        }
+    }
+
+    /// 在目录中创建名为 `name` 的空文件或空目录
+    fn create(&self, name: &str, dir: bool) -> Result<Arc<RamInode>, Errno> {
+        let mut inner = self.inner.exclusive_access();
+        let Inode::Directory(entries) = &mut *inner else {
+            return Err(ENOTDIR);
+        };
+        if entries.contains_key(name) {
+            return Err(EEXIST);
+        }
+        let content = if dir { Inode::Directory(BTreeMap::new()) } else { Inode::File(Vec::new()) };
+        let inode = RamInode::new(self.budget.clone(), content);
+        entries.insert(String::from(name), inode.clone());
+        Ok(inode)
+    }
+
+    /// 从目录中删除名为 `name` 的子节点，非空的目录不能删除
+    fn remove(&self, name: &str) -> Result<(), Errno> {
+        let mut inner = self.inner.exclusive_access();
+        let Inode::Directory(entries) = &mut *inner else {
+            return Err(ENOTDIR);
+        };
+        let child = entries.get(name).ok_or(ENOENT)?;
+        if matches!(&*child.inner.exclusive_access(), Inode::Directory(children) if !children.is_empty()) {
+            return Err(ENOTEMPTY);
+        }
+        entries.remove(name);
+        Ok(())
+    }
+
+    /// 目录中的所有子节点，依次为文件名、inode 号和是否是目录
+    fn entries(&self) -> Vec<DirEntryInfo> {
+        match &*self.inner.exclusive_access() {
+            Inode::Directory(entries) => entries
+                .iter()
+                .map(|(name, inode)| {
+                    let size = if inode.is_dir() { 0 } else { inode.size() as u64 };
+                    DirEntryInfo::new(name.clone(), inode.ino, inode.is_dir(), size)
+                })
+                .collect(),
+            Inode::File(_) => Vec::new(),
+        }
+    }
+}
+
+impl Drop for RamInode {
+    fn drop(&mut self) {
+        if let Inode::File(data) = &*self.inner.exclusive_access() {
+            self.budget.release(data.len());
+        }
+    }
+}
+
+lazy_static! {
+    /// tmpfs 的挂载表，挂载点 -> 根目录
+    static ref MOUNTS: UPSafeCell<BTreeMap<String, Arc<RamInode>>> = {
+        let mut mounts = BTreeMap::new();
+        let root = RamInode::new(Arc::new(Budget::new(TMPFS_SIZE)), Inode::Directory(BTreeMap::new()));
+        mounts.insert(String::from(TMPFS_MOUNT_POINT), root);
+        unsafe { UPSafeCell::new(mounts) }
+    };
+}
+
+/// 找到 `path`（规范化的绝对路径）所在的 tmpfs，返回它的根目录和 `path` 在其中的相对路径；
+/// `path` 不在任何 tmpfs 中时返回 `None`
+fn mount_of(path: &str) -> Option<(Arc<RamInode>, &str)> {
+    let mounts = MOUNTS.exclusive_access();
+    let (point, root) = mounts
+        .iter()
+        .filter(|(point, _)| {
+            path.strip_prefix(point.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|(point, _)| point.len())?;
+    Some((root.clone(), path[point.len()..].trim_start_matches('/')))
+}
+
+/// 从 tmpfs 的根目录开始解析相对路径 `rel`
+fn walk(root: Arc<RamInode>, rel: &str) -> Result<Arc<RamInode>, Errno> {
+    rel.split('/')
+        .filter(|name| !name.is_empty())
+        .try_fold(root, |dir, name| dir.find(name))
+}
+
+/// 解析相对路径 `rel` 的父目录，返回父目录和最后一级的名字；`rel` 是 tmpfs 的根目录时返回 EBUSY
+fn walk_parent(root: Arc<RamInode>, rel: &str) -> Result<(Arc<RamInode>, &str), Errno> {
+    let (parent, name) = rel.rsplit_once('/').unwrap_or(("", rel));
+    if name.is_empty() {
+        return Err(EBUSY);
+    }
+    Ok((walk(root, parent)?, name))
+}
+
+/// 打开 tmpfs 中的文件或目录，`path` 是规范化的绝对路径，不在 tmpfs 中时返回 `None`。
+/// 目录只能以只读方式打开；O_CREAT 在文件不存在时创建，O_TRUNC 截断以可写方式打开的普通文件，
+/// O_APPEND 打开的文件每次写入都在文件末尾
+pub fn open_tmpfs(path: &str, flags: OpenFlags) -> Option<Result<Arc<TmpFile>, Errno>> {
+    let (root, rel) = mount_of(path)?;
+    let (readable, writable) = flags.read_write();
+    let inode = match walk(root.clone(), rel) {
+        Ok(inode) => inode,
+        Err(ENOENT) if flags.contains(OpenFlags::CREATE) => {
+            match walk_parent(root, rel).and_then(|(parent, name)| parent.create(name, false)) {
+                Ok(inode) => inode,
+                Err(errno) => return Some(Err(errno)),
+            }
+        }
+        Err(errno) => return Some(Err(errno)),
+    };
+    if inode.is_dir() && writable {
+        return Some(Err(EISDIR));
+    }
+    if !inode.is_dir() && flags.contains(OpenFlags::O_DIRECTORY) {
+        return Some(Err(ENOTDIR));
+    }
+    if writable && flags.contains(OpenFlags::TRUNC) {
+        inode.truncate();
+    }
+    Some(Ok(Arc::new(TmpFile::new(readable, writable, flags.contains(OpenFlags::APPEND), inode))))
+}
+
+/// 在 tmpfs 中创建目录，`path` 不在 tmpfs 中时返回 `None`
+pub fn tmpfs_mkdir(path: &str) -> Option<isize> {
+    let (root, rel) = mount_of(path)?;
+    Some(create(root, rel, true))
+}
+
+/// 在 tmpfs 中创建空的普通文件，用于 mknod 创建 FIFO 的目录项；`path` 不在 tmpfs 中时返回 `None`
+pub fn tmpfs_mknod(path: &str) -> Option<isize> {
+    let (root, rel) = mount_of(path)?;
+    Some(create(root, rel, false))
+}
+
+/// 在 tmpfs 中创建文件或目录，路径已存在（包括挂载点本身）时返回 -EEXIST
+fn create(root: Arc<RamInode>, rel: &str, dir: bool) -> isize {
+    let created = walk_parent(root, rel)
+        .map_err(|errno| if errno == EBUSY { EEXIST } else { errno })
+        .and_then(|(parent, name)| parent.create(name, dir));
+    match created {
+        Ok(_) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// 删除 tmpfs 中的文件或空目录，`path` 不在 tmpfs 中时返回 `None`；挂载点本身不能删除
+pub fn tmpfs_unlink(path: &str) -> Option<isize> {
+    let (root, rel) = mount_of(path)?;
+    let removed = walk_parent(root, rel).and_then(|(parent, name)| parent.remove(name));
+    Some(match removed {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    })
+}
+
+/// `path` 在 tmpfs 中时返回它是否是一个存在的目录，用于 chdir 和 chroot
+pub fn tmpfs_is_dir(path: &str) -> Option<bool> {
+    let (root, rel) = mount_of(path)?;
+    Some(walk(root, rel).is_ok_and(|inode| inode.is_dir()))
+}
+
+/// 在目录 `target`（规范化的绝对路径）上挂载一个预算为 `size` 字节的新 tmpfs。
+/// 目录不存在返回 -ENOENT，已经是挂载点返回 -EBUSY
+pub fn mount_tmpfs(target: &str, size: usize) -> isize {
+    let is_dir = tmpfs_is_dir(target).unwrap_or_else(|| search_pwd(target).is_some_and(|vfile| vfile.is_dir()));
+    if !is_dir {
+        return -ENOENT;
+    }
+    let mut mounts = MOUNTS.exclusive_access();
+    if mounts.contains_key(target) {
+        return -EBUSY;
+    }
+    let root = RamInode::new(Arc::new(Budget::new(size)), Inode::Directory(BTreeMap::new()));
+    mounts.insert(String::from(target), root);
+    0
+}
+
+/// 卸载挂载在 `target` 上的 tmpfs，其中的文件全部丢弃；`target` 不是 tmpfs 的挂载点时返回 `None`。
+/// 下面还挂载着其他 tmpfs 时返回 -EBUSY
+pub fn umount_tmpfs(target: &str) -> Option<isize> {
+    let mut mounts = MOUNTS.exclusive_access();
+    mounts.get(target)?;
+    let prefix = String::from(target) + "/";
+    if mounts.keys().any(|point| point.starts_with(&prefix)) {
+        return Some(-EBUSY);
+    }
+    mounts.remove(target);
+    Some(0)
+}
+
+/// 打开的 tmpfs 文件或目录
+pub struct TmpFile {
+    readable: bool,
+    writable: bool,
+    /// 以 O_APPEND 打开，写入总在文件末尾
+    append: bool,
+    inode: Arc<RamInode>,
+    /// 普通文件的读写位置，或目录中下一个要读取的目录项的序号
+    offset: UPSafeCell<usize>,
+}
+
+impl TmpFile {
+    fn new(readable: bool, writable: bool, append: bool, inode: Arc<RamInode>) -> Self {
+        Self {
+            readable,
+            writable,
+            append,
+            inode,
+            offset: unsafe { UPSafeCell::new(0) },
+        }
+    }
+}
+
+impl Drop for TmpFile {
+    /// 打开文件被释放时释放它持有的 flock 锁
+    fn drop(&mut self) {
+        release_lock((TMPFS_DEV, self.inode.ino), self as *const Self as usize);
+    }
+}
+
+impl File for TmpFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        let mut offset = self.offset.exclusive_access();
+        let read_size = self.inode.read_at(*offset, &mut buf);
+        *offset += read_size;
+        read_size as isize
+    }
+    // 预算用完时返回已经写入的字节数，一个字节都没有写入时返回 -ENOSPC
+    fn write(&self, buf: UserBuffer) -> isize {
+        let mut offset = self.offset.exclusive_access();
+        if self.append {
+            *offset = self.inode.size();
+        }
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            match self.inode.write_at(*offset, slice) {
+                Ok(write_size) => {
+                    *offset += write_size;
+                    total_write_size += write_size;
+                    if write_size < slice.len() {
+                        break;
+                    }
+                }
+                Err(_) if total_write_size > 0 => break,
+                Err(errno) => return -errno,
+            }
+        }
+        total_write_size as isize
+    }
+    fn lock_key(&self) -> Option<LockKey> {
+        Some((TMPFS_DEV, self.inode.ino))
+    }
+    fn stat(&self) -> Stat {
+        let mode = StatMode::with_permissions(self.inode.is_dir(), false);
+        let mut stat = Stat::new_with_defaults(TMPFS_DEV, self.inode.ino, mode, 1);
+        if !self.inode.is_dir() {
+            stat.size = self.inode.size() as i64;
+            stat.blocks = self.inode.size().div_ceil(512) as u64;
+        }
+        stat.blksize = TMPFS_BLKSIZE;
+        stat
+    }
+    // 目录项依次为 "."、".." 和按文件名排序的子节点
+    fn entries(&self) -> Result<Vec<DirEntryInfo>, Errno> {
+        if !self.inode.is_dir() {
+            return Err(ENOTDIR);
+        }
+        let mut entries = Vec::from([
+            DirEntryInfo::new(String::from("."), self.inode.ino, true, 0),
+            DirEntryInfo::new(String::from(".."), 0, true, 0),
+        ]);
+        entries.extend(self.inode.entries());
+        Ok(entries)
+    }
+    fn dirents(&self, buf: &mut [u8]) -> isize {
+        match self.entries() {
+            Ok(entries) => fill_dirents(&entries, &mut self.offset.exclusive_access(), buf),
+            Err(errno) => -errno,
+        }
+    }
+    // 与 FAT 上的文件相同：普通文件可以定位到末尾之后，目录不支持 SEEK_END
+    fn seek(&self, offset: i64, whence: SeekWhence) -> isize {
+        let mut pos = self.offset.exclusive_access();
+        let base = match whence {
+            SeekWhence::Set => 0,
+            SeekWhence::Cur => *pos as i64,
+            SeekWhence::End if self.inode.is_dir() => return -EINVAL,
+            SeekWhence::End => self.inode.size() as i64,
+        };
+        match base.checked_add(offset) {
+            Some(new_pos) if new_pos >= 0 => {
+                *pos = new_pos as usize;
+                new_pos as isize
+            }
+            _ => -EINVAL,
+        }
+    }
+}
+
+/// 测试 tmpfs 的目录操作和字节预算
+#[allow(unused)]
+pub fn tmpfs_test() {
+    let root = RamInode::new(Arc::new(Budget::new(100)), Inode::Directory(BTreeMap::new()));
+    let dir = root.create("dir", true).unwrap();
+    assert_eq!(root.create("dir", false).err(), Some(EEXIST));
+    let file = dir.create("file", false).unwrap();
+    assert_eq!(walk(root.clone(), "dir/file").unwrap().ino, file.ino);
+    assert_eq!(walk(root.clone(), "dir/file/x").err(), Some(ENOTDIR));
+    assert_eq!(walk(root.clone(), "missing").err(), Some(ENOENT));
+    // 超出预算时只写入放得下的部分
+    assert_eq!(file.write_at(0, &[1; 60]), Ok(60));
+    assert_eq!(file.write_at(80, &[2; 60]), Ok(20));
+    assert_eq!(file.write_at(100, &[3]), Err(ENOSPC));
+    assert_eq!(file.size(), 100);
+    // 覆盖已有的数据不占用新的预算
+    assert_eq!(file.write_at(0, &[4; 100]), Ok(100));
+    assert_eq!(root.remove("dir"), Err(ENOTEMPTY));
+    assert_eq!(dir.remove("file"), Ok(()));
+    // 删除后仍被引用的文件继续占用预算，最后一个引用释放后归还
+    assert_eq!(dir.create("other", false).unwrap().write_at(0, &[5]), Err(ENOSPC));
+    drop(file);
+    assert_eq!(dir.find("other").unwrap().write_at(0, &[5; 100]), Ok(100));
+    assert_eq!(dir.remove("other"), Ok(()));
+    assert_eq!(root.remove("dir"), Ok(()));
+    assert_eq!(root.budget.available(), 100);
+    info!("tmpfs_test passed!");
+}
@@ -0,0 +1,503 @@
+//! 控制台终端
+//!
+//! 文件描述符 0–2 和 /dev/tty 都是同一个控制台终端 [`Tty`]，它保存终端属性（termios）、
+//! 窗口大小和行规程的状态。控制台收到的字符先经过行规程再交给读者：
+//!
+//! - 规范模式（ICANON）按行缓冲：退格和 VERASE 删除一个字符，VKILL 删除整行，
+//!   VEOF 提交当前行（空行表示文件结束）；只有完整的行才能被读取，每次 read 最多返回一行。
+//! - 非规范模式下字符不经编辑直接交给读者，read 按 VMIN 和 VTIME 决定何时返回。
+//! - ECHO 控制是否回显，ICRNL 将回车转换为换行。
+//! - ISIG 时 VINTR（Ctrl-C）丢弃当前行。内核还没有信号和进程组，不发送 SIGINT。
+//!
+//! 终端属性通过 TCGETS/TCSETS/TCSETSW 读写，窗口大小通过 TIOCGWINSZ/TIOCSWINSZ 读写。
+
+use super::{notify_readiness, File, PollEvents, Stat, StatMode};
+use crate::console::write_bytes;
+use crate::mm::{UserBuffer, UserPtr};
+use crate::drivers::uart::{getchar, putchar};
+use crate::sync::UPSafeCell;
+use crate::syscall::{EFAULT, ENOTTY};
+use crate::task::{current_user_token, suspend_current_and_run_next, WaitQueue};
+use crate::timer::get_time_ms;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// ioctl：获取终端属性
+pub const TCGETS: usize = 0x5401;
+/// ioctl：立即设置终端属性
+pub const TCSETS: usize = 0x5402;
+/// ioctl：等待输出完成后设置终端属性，控制台的输出是同步的，与 TCSETS 相同
+pub const TCSETSW: usize = 0x5403;
+/// ioctl：获取窗口大小
+pub const TIOCGWINSZ: usize = 0x5413;
+/// ioctl：设置窗口大小
+pub const TIOCSWINSZ: usize = 0x5414;
+
+/// c_iflag：将输入的回车转换为换行
+pub const ICRNL: u32 = 0o000400;
+/// c_oflag：处理输出
+const OPOST: u32 = 0o000001;
+/// c_oflag：输出换行时加上回车
+const ONLCR: u32 = 0o000004;
+/// c_cflag：38400 波特、8 位字符、允许接收
+const DEFAULT_CFLAG: u32 = 0o000017 | 0o000060 | 0o000200;
+/// c_lflag：收到 VINTR 等字符时产生信号
+pub const ISIG: u32 = 0o000001;
+/// c_lflag：规范模式，按行编辑和读取
+pub const ICANON: u32 = 0o000002;
+/// c_lflag：回显输入的字符
+pub const ECHO: u32 = 0o000010;
+
+/// c_cc 的长度
+const NCCS: usize = 19;
+/// c_cc 下标：中断字符
+const VINTR: usize = 0;
+/// c_cc 下标：删除一个字符
+const VERASE: usize = 2;
+/// c_cc 下标：删除整行
+const VKILL: usize = 3;
+/// c_cc 下标：文件结束
+const VEOF: usize = 4;
+/// c_cc 下标：非规范模式 read 的超时，单位为 0.1 秒
+const VTIME: usize = 5;
+/// c_cc 下标：非规范模式 read 至少返回的字节数
+const VMIN: usize = 6;
+
+const BS: u8 = 0x08;
+/// 控制台的设备号（/dev/tty，主设备号 5，次设备号 0）
+const TTY_RDEV: u64 = 5 << 8;
+
+/// 终端属性，布局与 Linux 内核的 `struct termios` 相同。
+/// 生效的是 c_iflag 的 ICRNL、c_lflag 的 ISIG、ICANON、ECHO 和 c_cc 的 VINTR、VERASE、VKILL、VEOF、VMIN、VTIME
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    /// 输入模式
+    pub iflag: u32,
+    /// 输出模式
+    pub oflag: u32,
+    /// 控制模式
+    pub cflag: u32,
+    /// 本地模式
+    pub lflag: u32,
+    /// 行规程编号
+    pub line: u8,
+    /// 控制字符
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    /// 与 Linux 终端的默认设置相同：规范模式、回显、产生信号
+    fn default() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VMIN] = 1;
+        Self {
+            iflag: ICRNL,
+            oflag: OPOST | ONLCR,
+            cflag: DEFAULT_CFLAG,
+            lflag: ISIG | ICANON | ECHO,
+            line: 0,
+            cc,
+        }
+    }
+}
+
+/// 终端窗口大小，布局与 Linux 的 `struct winsize` 相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WinSize {
+    /// 行数
+    pub row: u16,
+    /// 列数
+    pub col: u16,
+    /// 宽度（像素）
+    pub xpixel: u16,
+    /// 高度（像素）
+    pub ypixel: u16,
+}
+
+/// 控制台终端的属性和行规程状态
+pub struct Tty {
+    termios: Termios,
+    winsize: WinSize,
+    /// 正在编辑、尚未提交的行
+    line: Vec<u8>,
+    /// 可以被读取的字符
+    ready: VecDeque<u8>,
+    /// 以 VEOF 提交的空行在 `ready` 中的位置，每个空行使一次 read 返回 0
+    eofs: VecDeque<usize>,
+}
+
+lazy_static! {
+    /// 控制台终端
+    static ref TTY: UPSafeCell<Tty> = unsafe { UPSafeCell::new(Tty::new()) };
+    /// 等待控制台输入的任务
+    static ref STDIN_WAITERS: WaitQueue = WaitQueue::new();
+}
+
+/// 回显一个字节
+fn echo(c: u8) {
+    putchar(c);
+}
+
+/// 在屏幕上擦除前一个字符
+fn echo_erase() {
+    for c in [BS, b' ', BS] {
+        echo(c);
+    }
+}
+
+impl Tty {
+    /// 默认属性、24 行 80 列的终端
+    fn new() -> Self {
+        Self {
+            termios: Termios::default(),
+            winsize: WinSize { row: 24, col: 80, xpixel: 0, ypixel: 0 },
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            eofs: VecDeque::new(),
+        }
+    }
+
+    fn lflag(&self, flag: u32) -> bool {
+        self.termios.lflag & flag != 0
+    }
+
+    fn canonical(&self) -> bool {
+        self.lflag(ICANON)
+    }
+
+    /// 处理收到的一个字符，返回是否有新的内容可以读取
+    fn receive(&mut self, mut c: u8) -> bool {
+        let echoing = self.lflag(ECHO);
+        let cc = self.termios.cc;
+        if c == b'\r' && self.termios.iflag & ICRNL != 0 {
+            c = b'\n';
+        }
+        if self.lflag(ISIG) && c == cc[VINTR] {
+            self.line.clear();
+            if echoing {
+                for c in *b"^C\n" {
+                    echo(c);
+                }
+            }
+            return false;
+        }
+        if !self.canonical() {
+            if echoing {
+                echo(c);
+            }
+            self.ready.push_back(c);
+            return true;
+        }
+        if c == b'\n' {
+            if echoing {
+                echo(b'\n');
+            }
+            self.line.push(b'\n');
+            self.commit();
+            true
+        } else if c == cc[VERASE] || c == BS {
+            if self.line.pop().is_some() && echoing {
+                echo_erase();
+            }
+            false
+        } else if c == cc[VKILL] {
+            while self.line.pop().is_some() {
+                if echoing {
+                    echo_erase();
+                }
+            }
+            false
+        } else if c == cc[VEOF] {
+            if self.line.is_empty() {
+                self.eofs.push_back(self.ready.len());
+            }
+            self.commit();
+            true
+        } else {
+            if echoing {
+                echo(c);
+            }
+            self.line.push(c);
+            false
+        }
+    }
+
+    /// 将正在编辑的行交给读者
+    fn commit(&mut self) {
+        self.ready.extend(self.line.drain(..));
+    }
+
+    /// 是否有内容可读，包括表示文件结束的空行
+    fn readable(&self) -> bool {
+        !self.ready.is_empty() || !self.eofs.is_empty()
+    }
+
+    /// 取出最多 `len` 字节，规范模式下最多取到行尾；遇到文件结束时返回空的结果
+    fn take(&mut self, len: usize) -> Vec<u8> {
+        if self.eofs.front() == Some(&0) {
+            self.eofs.pop_front();
+            return Vec::new();
+        }
+        let mut count = len.min(self.ready.len());
+        if self.canonical() {
+            // 不越过下一个文件结束位置和行尾
+            if let Some(&eof) = self.eofs.front() {
+                count = count.min(eof);
+            }
+            if let Some(newline) = self.ready.iter().take(count).position(|&c| c == b'\n') {
+                count = newline + 1;
+            }
+        }
+        for eof in self.eofs.iter_mut() {
+            *eof -= count;
+        }
+        self.ready.drain(..count).collect()
+    }
+
+    /// 为读取 `len` 字节的 read 取出数据，需要继续等待时返回 `None`。
+    /// `elapsed_ms` 是 read 已经等待的时间，非规范模式下与 VTIME 比较
+    fn try_read(&mut self, len: usize, elapsed_ms: usize) -> Option<Vec<u8>> {
+        if self.canonical() {
+            return self.readable().then(|| self.take(len));
+        }
+        let min = (self.termios.cc[VMIN] as usize).min(len);
+        let timeout_ms = self.termios.cc[VTIME] as usize * 100;
+        let available = self.ready.len();
+        let timed_out = timeout_ms > 0 && elapsed_ms >= timeout_ms;
+        if available >= min.max(1) || (min == 0 && timeout_ms == 0) || (timed_out && (min == 0 || available > 0)) {
+            Some(self.take(len))
+        } else {
+            None
+        }
+    }
+
+    /// 非规范模式下设置了 VTIME 时，read 需要按时间检查而不是只等输入
+    fn timed(&self) -> bool {
+        !self.canonical() && self.termios.cc[VTIME] > 0
+    }
+
+    /// 设置终端属性。退出规范模式时正在编辑的行立即可读
+    fn set_termios(&mut self, termios: Termios) {
+        self.termios = termios;
+        if !self.canonical() {
+            self.commit();
+            self.eofs.clear();
+        }
+    }
+}
+
+/// 取出串口收到的所有字符交给行规程，有新的内容可读时唤醒等待输入的任务。
+/// 由串口的接收中断调用；控制台退回到 SBI 时没有接收中断，由时钟中断和空闲循环定期调用
+pub fn poll_console_input() {
+    let mut tty = TTY.exclusive_access();
+    let mut arrived = false;
+    while let Some(c) = getchar() {
+        arrived |= tty.receive(c);
+    }
+    drop(tty);
+    if arrived {
+        STDIN_WAITERS.wake_all();
+        notify_readiness();
+    }
+}
+
+/// 读取控制台输入，返回 0 表示文件结束（规范模式下在空行输入 VEOF）或非规范模式下超时。
+/// 设置了 VTIME 时通过让出 CPU 轮询计时，否则阻塞到有输入
+pub fn tty_read(mut user_buf: UserBuffer) -> usize {
+    if user_buf.len() == 0 {
+        return 0;
+    }
+    let start = get_time_ms();
+    loop {
+        poll_console_input();
+        let mut tty = TTY.exclusive_access();
+        if let Some(data) = tty.try_read(user_buf.len(), get_time_ms() - start) {
+            drop(tty);
+            return user_buf.write(&data);
+        }
+        let timed = tty.timed();
+        drop(tty);
+        if timed {
+            suspend_current_and_run_next();
+        } else {
+            STDIN_WAITERS.wait();
+        }
+    }
+}
+
+/// 控制台是否有内容可读
+pub fn tty_readable() -> bool {
+    poll_console_input();
+    TTY.exclusive_access().readable()
+}
+
+/// 将 `value` 写入用户指针 `arg`
+fn put<T: Copy>(arg: usize, value: T) -> isize {
+    match UserPtr::writable(current_user_token(), arg as *mut T).and_then(|ptr| ptr.write(value)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// 从用户指针 `arg` 读取一个值
+fn get<T: Copy>(arg: usize) -> Result<T, isize> {
+    UserPtr::readable(current_user_token(), arg as *const T)
+        .and_then(|ptr| ptr.read())
+        .map_err(|errno| -errno)
+}
+
+/// 控制台的 ioctl，支持读写终端属性和窗口大小，其他请求返回 -ENOTTY
+pub fn tty_ioctl(request: usize, arg: usize) -> isize {
+    if arg == 0 {
+        return -EFAULT;
+    }
+    match request {
+        TCGETS => {
+            let termios = TTY.exclusive_access().termios;
+            put(arg, termios)
+        }
+        TCSETS | TCSETSW => {
+            let termios = match get::<Termios>(arg) {
+                Ok(termios) => termios,
+                Err(errno) => return errno,
+            };
+            TTY.exclusive_access().set_termios(termios);
+            STDIN_WAITERS.wake_all();
+            notify_readiness();
+            0
+        }
+        TIOCGWINSZ => {
+            let winsize = TTY.exclusive_access().winsize;
+            put(arg, winsize)
+        }
+        TIOCSWINSZ => match get::<WinSize>(arg) {
+            Ok(winsize) => {
+                TTY.exclusive_access().winsize = winsize;
+                0
+            }
+            Err(errno) => errno,
+        },
+        _ => -ENOTTY,
+    }
+}
+
+/// 控制台终端的状态信息
+pub fn tty_stat() -> Stat {
+    let mut stat = Stat::new_with_defaults(0, 0, StatMode::CHR, 1);
+    stat.rdev = TTY_RDEV;
+    stat
+}
+
+/// 以 /dev/tty 打开的控制台终端
+pub struct TtyFile {
+    readable: bool,
+    writable: bool,
+}
+
+/// 打开 /dev/tty，`path` 不是 /dev/tty 时返回 `None`
+pub fn open_tty(path: &str, readable: bool, writable: bool) -> Option<Arc<TtyFile>> {
+    (path == "/dev/tty").then(|| Arc::new(TtyFile { readable, writable }))
+}
+
+impl File for TtyFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, user_buf: UserBuffer) -> isize {
+        tty_read(user_buf) as isize
+    }
+    fn write(&self, user_buf: UserBuffer) -> isize {
+        let mut len = 0;
+        for slice in user_buf.buffers.iter() {
+            write_bytes(slice);
+            len += slice.len();
+        }
+        len as isize
+    }
+    fn stat(&self) -> Stat {
+        tty_stat()
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable && tty_readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
+}
+
+/// 检查规范模式的行编辑、按行读取和文件结束，以及非规范模式的直通和 VMIN/VTIME
+#[allow(unused)]
+pub fn line_discipline_test() {
+    let mut tty = Tty::new();
+    tty.termios.lflag &= !ECHO;
+    for &c in b"lx\x7fs\r" {
+        tty.receive(c);
+    }
+    for &c in b"junk\x15pwd\n" {
+        tty.receive(c);
+    }
+    // 每次最多读一行，行可以分多次读完
+    assert_eq!(tty.take(64), b"ls\n");
+    assert_eq!(tty.take(2), b"pw");
+    assert_eq!(tty.take(64), b"d\n");
+    assert!(!tty.readable());
+    // 未提交的行不可读，Ctrl-C 丢弃它
+    for &c in b"abc\x03" {
+        tty.receive(c);
+    }
+    assert!(!tty.readable());
+    // Ctrl-D 提交不带换行的行，空行上的 Ctrl-D 是文件结束
+    for &c in b"ab\x04\x04" {
+        tty.receive(c);
+    }
+    assert_eq!(tty.try_read(64, 0).unwrap(), b"ab");
+    assert_eq!(tty.try_read(64, 0).unwrap(), b"");
+    assert!(tty.try_read(64, 0).is_none());
+
+    // 切到非规范模式后正在编辑的行立即可读，之后的字符不经处理；关闭 ISIG 后 Ctrl-C 也是普通字符
+    tty.receive(b'x');
+    let mut raw = tty.termios;
+    raw.lflag &= !(ICANON | ISIG);
+    raw.iflag &= !ICRNL;
+    tty.set_termios(raw);
+    for &c in b"\x7f\r\x03" {
+        tty.receive(c);
+    }
+    assert_eq!(tty.try_read(64, 0).unwrap(), b"x\x7f\r\x03");
+    // VMIN = 2：只有一个字节时继续等待
+    raw.cc[VMIN] = 2;
+    tty.set_termios(raw);
+    tty.receive(b'a');
+    assert!(tty.try_read(64, 0).is_none());
+    tty.receive(b'b');
+    assert_eq!(tty.try_read(64, 0).unwrap(), b"ab");
+    // VMIN = 0、VTIME = 0：没有输入时立即返回 0 字节
+    raw.cc[VMIN] = 0;
+    tty.set_termios(raw);
+    assert_eq!(tty.try_read(64, 0).unwrap(), b"");
+    // VMIN = 0、VTIME = 1：超时前等待，超时后返回 0 字节
+    raw.cc[VTIME] = 1;
+    tty.set_termios(raw);
+    assert!(tty.timed());
+    assert!(tty.try_read(64, 50).is_none());
+    assert_eq!(tty.try_read(64, 100).unwrap(), b"");
+    info!("line_discipline_test passed!");
+}
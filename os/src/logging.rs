@@ -43,3 +43,15 @@ pub fn init() {
         _ => LevelFilter::Off,
     });
 }
+
+/// override the compile-time `LOG` level with the `loglevel=` boot argument, if any;
+/// must run after `mm::init` since reading the boot arguments allocates
+pub fn apply_bootargs() {
+    let Some(level) = crate::drivers::dtb::bootarg("loglevel") else {
+        return;
+    };
+    match level.parse::<LevelFilter>() {
+        Ok(filter) => log::set_max_level(filter),
+        Err(_) => warn!("unknown loglevel {:?}, keeping {}", level, log::max_level()),
+    }
+}
@@ -41,6 +41,7 @@ pub mod lang_items;
 pub mod logging;
 /// mm module
 pub mod mm;
+pub mod net;
 pub mod sbi;
 pub mod sync;
 pub mod syscall;
@@ -67,18 +68,74 @@ fn clear_bss() {
 }
 
 #[no_mangle]
-/// the rust entry-point of os
-pub fn rust_main() -> ! {
+/// the rust entry-point of os; the SBI passes the hart id in a0 and the
+/// physical address of the device tree in a1, and `_start` leaves both intact
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     clear_bss();
     println!("[kernel] Hello, world!");
     logging::init();
+    // 设备树所在的内存稍后会被页帧分配器使用
+    drivers::dtb::parse(dtb);
+    // 此后控制台直接读写串口
+    drivers::uart::init();
+    drivers::rtc::init();
     mm::init();
+    // 读取启动参数需要堆分配
+    logging::apply_bootargs();
     mm::remap_test();
+    #[cfg(feature = "boot-self-test")]
+    {
+        mm::user_copy_test();
+        mm::area_overlap_test();
+        mm::mprotect_test();
+        mm::trap_cx_slot_test();
+        mm::frame_ref_test();
+        mm::bitmap_allocator_test();
+        mm::asid_test();
+        task::stride_wrap_test();
+        task::scheduler_policy_test();
+        fs::pipe_poll_test();
+        fs::pipe_resize_test();
+        fs::pipe_ring_buffer_test();
+        fs::line_discipline_test();
+        fs::tmpfs_test();
+    }
+    #[cfg(feature = "bench")]
+    mm::frame_allocator_bench();
+    #[cfg(feature = "bench")]
+    task::stride_queue_bench();
     trap::init();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
+    #[cfg(feature = "boot-trap-test")]
+    trap::boot_timer_test();
+    #[cfg(feature = "boot-self-test")]
+    {
+        drivers::block::partition_test();
+        drivers::block::block_batch_test();
+        drivers::block::dma_dealloc_test();
+        drivers::block::request_queue_test();
+    }
+    #[cfg(feature = "fs-boot-test")]
+    {
+        fs::list_dir_test();
+        fs::io_error_test();
+        fs::flush_order_test();
+    }
     fs::list_apps();
+    net::init();
+    #[cfg(feature = "boot-self-test")]
+    net::udp_loopback_test();
+    #[cfg(feature = "swap")]
+    mm::swap_init(fs::open_swap_file().expect("failed to create the swap file"));
+    // 此后有当前任务的块设备请求睡眠等待完成中断
+    drivers::init_interrupts();
+    trap::enable_external_interrupt();
+    #[cfg(feature = "uart-irq-test")]
+    drivers::uart::uart_irq_test();
+    // initproc 从文件系统加载，必须在内存管理和块设备初始化之后创建
     task::add_initproc();
+    task::spawn_kernel_thread("fsflush", fs::flush_thread);
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
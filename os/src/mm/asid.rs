@@ -0,0 +1,145 @@
+//! 地址空间标识符（ASID）的分配
+//!
+//! 每个页表持有一个 ASID 并写入 satp，TLB 表项按 ASID 区分，
+//! 因此切换地址空间时无需刷新整个 TLB，只在修改映射时刷新对应的表项。
+//! 硬件没有实现 ASID 时所有地址空间（包括内核）都使用 ASID 0，
+//! 与 ASID 耗尽后共享最大 ASID 一样，每次切换地址空间都要刷新 TLB。
+//! 内核地址空间使用保留的 [`KERNEL_ASID`]，不经过分配器，用户地址空间从 2 开始分配；
+//! 只有 1 位 ASID 时它会与共享的最大 ASID 相同，这时同样让所有地址空间使用 ASID 0。
+
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::arch::asm;
+use lazy_static::*;
+use riscv::register::satp;
+
+/// satp 中 ASID 字段的偏移
+const SATP_ASID_SHIFT: usize = 44;
+/// satp 中 ASID 字段的掩码（Sv39 下最多 16 位）
+const SATP_ASID_MASK: usize = 0xffff;
+/// 保留给内核地址空间的 ASID，不会分配给用户地址空间
+pub const KERNEL_ASID: usize = 1;
+
+/// ASID 分配器，回收的 ASID 在再次分配前会刷新其 TLB 表项
+struct AsidAllocator {
+    current: usize,       // 下一个从未分配过的 ASID
+    max: usize,           // 硬件支持的最大 ASID，保留给 ASID 耗尽后共享使用；为 0 时硬件没有实现 ASID
+    recycled: Vec<usize>, // 回收的 ASID
+}
+
+impl AsidAllocator {
+    /// 探测硬件实现的 ASID 位数并创建分配器
+    fn new() -> Self {
+        // 向 ASID 字段写入全 1，读回的值即为硬件支持的最大 ASID
+        let max = unsafe {
+            let old = satp::read().bits();
+            satp::write(old | (SATP_ASID_MASK << SATP_ASID_SHIFT));
+            let max = token_asid(satp::read().bits());
+            satp::write(old);
+            max
+        };
+        // 只有 ASID 0 和 1 时内核无法与用户地址空间区分，按没有实现 ASID 处理
+        let max = if max <= 1 {
+            warn!("硬件未实现 ASID（或只有 1 位），每次切换地址空间都将刷新 TLB");
+            0
+        } else {
+            max
+        };
+        // 性能对比：启动参数 asid=off 时按没有实现 ASID 处理，即引入 ASID 之前每次切换都刷新 TLB 的行为，
+        // 用 ch6b_switch_bench 比较两种情况下的切换开销
+        #[cfg(feature = "bench")]
+        let max = if crate::drivers::dtb::bootarg("asid").as_deref() == Some("off") {
+            info!("asid=off：所有地址空间使用 ASID 0，每次切换地址空间都刷新 TLB");
+            0
+        } else {
+            max
+        };
+        Self {
+            current: KERNEL_ASID + 1,
+            max,
+            recycled: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> usize {
+        if let Some(asid) = self.recycled.pop() {
+            // 之前的地址空间可能仍在 TLB 中留有表项
+            flush_asid(asid);
+            asid
+        } else if self.current < self.max {
+            self.current += 1;
+            self.current - 1
+        } else {
+            // ASID 耗尽（或硬件没有实现 ASID），之后的地址空间共享最大的 ASID，每次进入时都要刷新
+            self.max
+        }
+    }
+
+    fn dealloc(&mut self, asid: usize) {
+        if asid == self.max {
+            return;
+        }
+        assert!(asid < self.current, "asid {} 尚未分配", asid);
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    /// 全局 ASID 分配器
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> =
+        unsafe { UPSafeCell::new(AsidAllocator::new()) };
+}
+
+/// ASID 抽象结构，被释放时自动回收
+pub struct AsidHandle(pub usize);
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// 内核地址空间的 ASID：硬件实现了 ASID 时为保留的 [`KERNEL_ASID`]，否则为 0
+pub fn kernel_asid() -> usize {
+    if ASID_ALLOCATOR.exclusive_access().max == 0 {
+        0
+    } else {
+        KERNEL_ASID
+    }
+}
+
+/// 为用户地址空间分配一个新的 ASID
+pub fn asid_alloc() -> AsidHandle {
+    AsidHandle(ASID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// 从 satp token 中取出 ASID
+pub fn token_asid(token: usize) -> usize {
+    (token >> SATP_ASID_SHIFT) & SATP_ASID_MASK
+}
+
+/// 刷新 `asid` 的全部 TLB 表项
+pub fn flush_asid(asid: usize) {
+    unsafe {
+        asm!("sfence.vma zero, {}", in(reg) asid);
+    }
+}
+
+/// 进入地址空间 `token` 之前调用：共享 ASID 的地址空间可能命中其他地址空间留下的表项，需要先刷新
+pub fn flush_if_shared(token: usize) {
+    let asid = token_asid(token);
+    if asid == ASID_ALLOCATOR.exclusive_access().max {
+        flush_asid(asid);
+    }
+}
+
+/// 内核地址空间使用保留的 ASID，分配给用户地址空间的 ASID 都不与它相同
+#[allow(unused)]
+pub fn asid_test() {
+    assert_eq!(token_asid(super::kernel_token()), kernel_asid());
+    let handles: Vec<AsidHandle> = (0..8).map(|_| asid_alloc()).collect();
+    if kernel_asid() != 0 {
+        assert!(handles.iter().all(|handle| handle.0 != KERNEL_ASID));
+    }
+    info!("asid_test passed!");
+}
@@ -1,27 +1,44 @@
 //! 实现 [`FrameAllocator`]，控制操作系统中的所有物理页面帧。
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
+use crate::drivers::dtb::memory_regions;
 use crate::sync::UPSafeCell;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
 
-/// 物理页面帧分配和回收的追踪器
+/// 物理页面帧耗尽，无法完成分配；与页表共用 [`sv39::OutOfMemory`]
+pub use sv39::OutOfMemory;
+
+/// 物理页面帧分配和回收的追踪器。
+/// 同一个页面帧可以被多个追踪器共享（写时复制、只读文件页、全零页），
+/// 最后一个追踪器被销毁时才回收页面帧。
 pub struct FrameTracker {
     /// 物理页面号
     pub ppn: PhysPageNum,
 }
 
 impl FrameTracker {
-    /// 创建一个新的 FrameTracker
+    /// 为新分配的页面帧创建第一个 FrameTracker
     pub fn new(ppn: PhysPageNum) -> Self {
         // 页面清理
         let bytes_array = ppn.get_bytes_array();
         for i in bytes_array {
             *i = 0;
         }
+        assert_eq!(frame_ref_inc(ppn), 1, "Frame ppn={:#x} 已被引用！", ppn.0);
         Self { ppn }
     }
+    /// 创建共享同一个页面帧的追踪器，引用计数加一
+    pub fn clone_shared(&self) -> Self {
+        frame_ref_inc(self.ppn);
+        Self { ppn: self.ppn }
+    }
+    /// 当前引用该页面帧的追踪器个数
+    pub fn ref_count(&self) -> usize {
+        frame_ref_count(self.ppn)
+    }
 }
 
 impl Debug for FrameTracker {
@@ -32,16 +49,60 @@ impl Debug for FrameTracker {
 
 impl Drop for FrameTracker {
     fn drop(&mut self) {
-        // 当 FrameTracker 被销毁时，回收相应的物理页面帧
-        frame_dealloc(self.ppn);
+        // 最后一个引用该页面帧的 FrameTracker 被销毁时，回收相应的物理页面帧
+        if frame_ref_dec(self.ppn) == 0 {
+            frame_dealloc(self.ppn);
+        }
     }
 }
 
+/// 按物理页号索引的页面帧引用计数表，覆盖 [ekernel, memory_end()) 内的全部页面帧
+struct FrameRefTable {
+    start: usize,     // 第一个页面帧号
+    counts: Vec<u16>, // 各页面帧的引用计数
+}
+
+impl FrameRefTable {
+    fn count_mut(&mut self, ppn: PhysPageNum) -> &mut u16 {
+        assert!(
+            ppn.0 >= self.start && ppn.0 - self.start < self.counts.len(),
+            "Frame ppn={:#x} 不在可分配范围内！",
+            ppn.0
+        );
+        &mut self.counts[ppn.0 - self.start]
+    }
+}
+
+/// 增加页面帧的引用计数，返回增加后的值
+pub fn frame_ref_inc(ppn: PhysPageNum) -> usize {
+    let mut refs = FRAME_REFS.exclusive_access();
+    let count = refs.count_mut(ppn);
+    *count = count.checked_add(1).expect("frame reference count overflow");
+    *count as usize
+}
+
+/// 减少页面帧的引用计数，返回减少后的值，为 0 时调用者应当回收该页面帧
+pub fn frame_ref_dec(ppn: PhysPageNum) -> usize {
+    let mut refs = FRAME_REFS.exclusive_access();
+    let count = refs.count_mut(ppn);
+    *count = count
+        .checked_sub(1)
+        .unwrap_or_else(|| panic!("Frame ppn={:#x} 没有被引用！", ppn.0));
+    *count as usize
+}
+
+/// 页面帧当前的引用计数
+pub fn frame_ref_count(ppn: PhysPageNum) -> usize {
+    *FRAME_REFS.exclusive_access().count_mut(ppn) as usize
+}
+
 /// 定义 FrameAllocator 特征，作为物理页面帧分配器的接口
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    /// 分配 `pages` 个物理上连续的页面帧，返回第一个页面帧号
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum>;
 }
 
 /// 物理页面帧分配器的栈式实现
@@ -94,26 +155,221 @@ impl FrameAllocator for StackFrameAllocator {
         // 将页面帧加入回收列表
         self.recycled.push(ppn);
     }
+
+    /// 只从尚未分配过的区域中切出连续的页面帧
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 0 || self.end - self.current < pages {
+            return None;
+        }
+        self.current += pages;
+        Some((self.current - pages).into())
+    }
+}
+
+/// 物理页面帧分配器的位图实现，每一位表示一个页面帧是否已分配
+pub struct BitmapFrameAllocator {
+    start: usize,      // 第一个页面帧号
+    end: usize,        // 最后一个页面帧号（不含）
+    bitmap: Vec<u64>,  // 分配位图，置位表示已分配
+    cursor: usize,     // 可能含有空闲页面帧的第一个字的下标
+}
+
+impl BitmapFrameAllocator {
+    /// 初始化分配器，设定起始页号和结束页号
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
+        self.end = r.0;
+        let frames = r.0 - l.0;
+        self.bitmap = vec![0; (frames + 63) / 64];
+        // 最后一个字中超出范围的位视为已分配
+        if frames % 64 != 0 {
+            *self.bitmap.last_mut().unwrap() = !0u64 << (frames % 64);
+        }
+        self.cursor = 0;
+    }
+
+    fn is_allocated(&self, index: usize) -> bool {
+        self.bitmap[index / 64] & (1 << (index % 64)) != 0
+    }
+}
+
+impl FrameAllocator for BitmapFrameAllocator {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            end: 0,
+            bitmap: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// 从游标处开始寻找第一个含空闲位的字并分配其中最低的空闲位
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        while self.cursor < self.bitmap.len() {
+            let word = self.bitmap[self.cursor];
+            if word != !0 {
+                let bit = (!word).trailing_zeros() as usize;
+                self.bitmap[self.cursor] |= 1 << bit;
+                return Some((self.start + self.cursor * 64 + bit).into());
+            }
+            self.cursor += 1;
+        }
+        None
+    }
+
+    /// 清除对应的位，并把游标移回该页面帧所在的字
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let ppn = ppn.0;
+        // 校验页面帧是否有效
+        if ppn < self.start || ppn >= self.end || !self.is_allocated(ppn - self.start) {
+            panic!("Frame ppn={:#x} 尚未分配！", ppn);
+        }
+        let index = ppn - self.start;
+        self.bitmap[index / 64] &= !(1 << (index % 64));
+        self.cursor = self.cursor.min(index / 64);
+    }
+
+    /// 从游标处开始寻找长度为 `pages` 的连续空闲位
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 0 {
+            return None;
+        }
+        let frames = self.end - self.start;
+        let mut run_start = self.cursor * 64;
+        let mut index = run_start;
+        while index < frames {
+            if self.is_allocated(index) {
+                run_start = index + 1;
+            } else if index + 1 - run_start == pages {
+                for i in run_start..=index {
+                    self.bitmap[i / 64] |= 1 << (i % 64);
+                }
+                return Some((self.start + run_start).into());
+            }
+            index += 1;
+        }
+        None
+    }
 }
 
+/// 页面帧使用情况的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    /// 可分配的页面帧总数
+    pub total: usize,
+    /// 当前空闲的页面帧数
+    pub free: usize,
+    /// 启动以来同时占用页面帧数的峰值
+    pub peak_used: usize,
+}
+
+/// 在具体分配器之外记录使用情况的全局页面帧分配器
+pub struct CountingFrameAllocator {
+    allocator: FrameAllocatorImpl,
+    total: usize,
+    used: usize,
+    peak_used: usize,
+}
+
+impl CountingFrameAllocator {
+    fn new() -> Self {
+        Self {
+            allocator: FrameAllocatorImpl::new(),
+            total: 0,
+            used: 0,
+            peak_used: 0,
+        }
+    }
+
+    fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.allocator.init(l, r);
+        self.total = r.0 - l.0;
+    }
+
+    fn record_alloc(&mut self, pages: usize) {
+        self.used += pages;
+        self.peak_used = self.peak_used.max(self.used);
+    }
+
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        let ppn = self.allocator.alloc()?;
+        self.record_alloc(1);
+        Some(ppn)
+    }
+
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        let ppn = self.allocator.alloc_contiguous(pages)?;
+        self.record_alloc(pages);
+        Some(ppn)
+    }
+
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        self.allocator.dealloc(ppn);
+        self.used -= 1;
+    }
+
+    fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            free: self.total - self.used,
+            peak_used: self.peak_used,
+        }
+    }
+}
+
+/// FrameAllocator 的实现类型
+#[cfg(not(feature = "stack-frame-allocator"))]
+type FrameAllocatorImpl = BitmapFrameAllocator;
 /// FrameAllocator 的实现类型
+#[cfg(feature = "stack-frame-allocator")]
 type FrameAllocatorImpl = StackFrameAllocator;
 
 lazy_static! {
     /// 通过 lazy_static! 实现的全局 FrameAllocator 实例
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: UPSafeCell<CountingFrameAllocator> =
+        unsafe { UPSafeCell::new(CountingFrameAllocator::new()) };
+    /// 全局页面帧引用计数表
+    static ref FRAME_REFS: UPSafeCell<FrameRefTable> = unsafe {
+        UPSafeCell::new(FrameRefTable {
+            start: 0,
+            counts: Vec::new(),
+        })
+    };
 }
 
-/// 初始化页面帧分配器，使用 `ekernel` 和 `MEMORY_END` 作为起始和结束地址
+/// 物理内存的结束地址：设备树中包含内核的内存区域的结束地址，设备树中没有内存节点时为 `MEMORY_END`。
+/// 打开 tiny-mem 时不超过 `MEMORY_END`
+pub fn memory_end() -> usize {
+    extern "C" {
+        fn ekernel();
+    }
+    let kernel_end = ekernel as usize;
+    let end = memory_regions()
+        .into_iter()
+        .find(|&(base, size)| base <= kernel_end && kernel_end < base + size)
+        .map_or(MEMORY_END, |(base, size)| base + size);
+    if cfg!(feature = "tiny-mem") {
+        end.min(MEMORY_END)
+    } else {
+        end
+    }
+}
+
+/// 初始化页面帧分配器，使用 `ekernel` 和 [`memory_end`] 作为起始和结束地址。
+/// 总页帧数总是打印出来，scripts/check_frames.sh 据此检查内存大小来自设备树
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
-    );
+    let start = PhysAddr::from(ekernel as usize).ceil();
+    let memory_end = memory_end();
+    let end = PhysAddr::from(memory_end).floor();
+    println!("[kernel] frames: {}, memory end {:#x}", end.0 - start.0, memory_end);
+    FRAME_ALLOCATOR.exclusive_access().init(start, end);
+    *FRAME_REFS.exclusive_access() = FrameRefTable {
+        start: start.0,
+        counts: vec![0; end.0 - start.0],
+    };
 }
 
 /// 分配一个物理页面帧，返回 FrameTracker 样式的分配器
@@ -124,7 +380,125 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+/// 分配 `pages` 个物理上连续的页面帧，按页号从小到大返回
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let base = FRAME_ALLOCATOR.exclusive_access().alloc_contiguous(pages)?;
+    Some(
+        (0..pages)
+            .map(|i| FrameTracker::new(PhysPageNum(base.0 + i)))
+            .collect(),
+    )
+}
+
 /// 释放一个指定的物理页面帧
 pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
+
+/// 获取页面帧的使用情况
+pub fn stats() -> FrameStats {
+    FRAME_ALLOCATOR.exclusive_access().stats()
+}
+
+/// 检查共享页面帧的引用计数：只有最后一个追踪器被销毁时页面帧才被回收
+#[allow(unused)]
+pub fn frame_ref_test() {
+    let free = stats().free;
+    let frame = frame_alloc().unwrap();
+    let ppn = frame.ppn;
+    assert_eq!(frame.ref_count(), 1);
+    let mut shared: Vec<FrameTracker> = (0..3).map(|_| frame.clone_shared()).collect();
+    assert_eq!(frame_ref_count(ppn), 4);
+    assert_eq!(stats().free, free - 1);
+    // 先销毁最初的追踪器，页面帧仍然被共享者持有
+    drop(frame);
+    assert_eq!(frame_ref_count(ppn), 3);
+    assert_eq!(stats().free, free - 1);
+    // 在共享者之间交替复制和销毁，计数始终与存活的追踪器个数一致
+    for round in 0..8 {
+        if round % 2 == 0 {
+            let copy = shared[0].clone_shared();
+            shared.push(copy);
+        } else {
+            shared.pop();
+        }
+        assert_eq!(frame_ref_count(ppn), shared.len());
+    }
+    drop(shared);
+    assert_eq!(frame_ref_count(ppn), 0);
+    assert_eq!(stats().free, free);
+    // 回收后再次分配到的页面帧从计数 1 开始
+    let again = frame_alloc().unwrap();
+    assert_eq!(again.ref_count(), 1);
+    drop(again);
+    assert_eq!(stats().free, free);
+    info!("frame_ref_test passed!");
+}
+
+/// 在一段虚构的页面帧范围上检查位图分配器：每个页面帧只分配一次，用完后分配失败，
+/// 释放的页面帧可以再次分配，连续分配跳过不够长的空洞
+#[allow(unused)]
+pub fn bitmap_allocator_test() {
+    const FRAMES: usize = 1000;
+    // 范围只用于计数，不会访问其中的物理内存
+    let l = PhysPageNum(0x80000);
+    let r = PhysPageNum(0x80000 + FRAMES);
+    let mut allocator = BitmapFrameAllocator::new();
+    allocator.init(l, r);
+    let mut ppns: Vec<PhysPageNum> = (0..FRAMES).map(|_| allocator.alloc().unwrap()).collect();
+    assert_eq!(allocator.alloc(), None);
+    ppns.sort_by_key(|ppn| ppn.0);
+    ppns.dedup();
+    assert_eq!(ppns.len(), FRAMES);
+    assert!(ppns.iter().all(|ppn| l.0 <= ppn.0 && ppn.0 < r.0));
+    for ppn in ppns.drain(..) {
+        allocator.dealloc(ppn);
+    }
+    assert!(allocator.alloc().is_some());
+
+    let mut allocator = BitmapFrameAllocator::new();
+    allocator.init(l, r);
+    let a = allocator.alloc().unwrap();
+    let b = allocator.alloc().unwrap();
+    let c = allocator.alloc().unwrap();
+    allocator.dealloc(b);
+    assert_eq!(allocator.alloc_contiguous(2), Some(PhysPageNum(c.0 + 1)));
+    assert_eq!(allocator.alloc(), Some(b));
+    allocator.dealloc(a);
+    assert_eq!(allocator.alloc_contiguous(FRAMES), None);
+    info!("bitmap_allocator_test passed!");
+}
+
+/// 在一段虚构的页面帧范围上分别测量两种分配器分配、释放 `FRAMES` 个页面帧的耗时
+#[cfg(feature = "bench")]
+pub fn frame_allocator_bench() {
+    use crate::timer::get_time_us;
+
+    const FRAMES: usize = 10_000;
+    fn bench<A: FrameAllocator>(name: &str, init: impl FnOnce(&mut A)) {
+        let mut allocator = A::new();
+        init(&mut allocator);
+        let mut ppns = Vec::with_capacity(FRAMES);
+        let begin = get_time_us();
+        for _ in 0..FRAMES {
+            ppns.push(allocator.alloc().unwrap());
+        }
+        let allocated = get_time_us();
+        for ppn in ppns.drain(..) {
+            allocator.dealloc(ppn);
+        }
+        let freed = get_time_us();
+        info!(
+            "{}: 分配 {} 个页面帧耗时 {}us，释放耗时 {}us",
+            name,
+            FRAMES,
+            allocated - begin,
+            freed - allocated
+        );
+    }
+    // 范围只用于计数，不会访问其中的物理内存
+    let l = PhysPageNum(0x80000);
+    let r = PhysPageNum(0x80000 + FRAMES);
+    bench::<StackFrameAllocator>("StackFrameAllocator", |a| a.init(l, r));
+    bench::<BitmapFrameAllocator>("BitmapFrameAllocator", |a| a.init(l, r));
+}
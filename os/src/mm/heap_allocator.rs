@@ -15,6 +15,24 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 /// 堆空间，大小为 KERNEL_HEAP_SIZE 的字节数组
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
+/// 内核堆的使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// 已分配出去的字节数（按伙伴系统实际占用计算）
+    pub allocated: usize,
+    /// 堆空间的总字节数
+    pub total: usize,
+}
+
+/// 获取内核堆的使用情况
+pub fn heap_stats() -> HeapStats {
+    let heap = HEAP_ALLOCATOR.lock();
+    HeapStats {
+        allocated: heap.stats_alloc_actual(),
+        total: heap.stats_total_bytes(),
+    }
+}
+
 /// 初始化堆分配器
 pub fn init_heap() {
     unsafe {
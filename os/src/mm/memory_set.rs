@@ -1,14 +1,29 @@
 //! [`MapArea`] 和 [`MemorySet`] 的实现
-use super::{frame_alloc, FrameTracker};
+use super::{frame_alloc, frame_allocator, FrameTracker, OutOfMemory};
+use super::page_cache::{
+    frame_alloc_or_evict, page_cache_insert, page_cache_lookup, page_cache_shrink, PageCacheKey,
+};
+use super::page_table::{HUGE_PAGE_PAGES, USER_SPACE_END};
+use super::shm::SharedSegment;
+use super::swap::{record_eviction, swap_enabled, SwapSlot};
 use super::{PTEFlags, PageTable, PageTableEntry};
+use super::{copy_from_user, copy_to_user, get_user, put_user, BadAddress};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use crate::config::{
+    ELF_DYN_BASE, MAX_TRAP_CX_SLOTS, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_GROW_GAP,
+    USER_STACK_LIMIT, USER_STACK_SIZE,
+};
+use crate::drivers::dtb::mmio_regions;
 use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, EINVAL, EIO, ENOMEM};
+use alloc::string::String;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::asm;
+use fat32::{FatError, VFile};
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -29,6 +44,17 @@ lazy_static! {
     /// 内核的初始内存映射（内核地址空间）
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
         Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    /// 全局共享的全零页帧，按需清零的页面在第一次被写入之前都映射到它（不带 W 权限）
+    static ref ZERO_FRAME: FrameTracker = frame_alloc().unwrap();
+}
+
+/// 处理缺页前至少保留的空闲页帧数，不足时先换出页面，
+/// 足以容纳缺页的页面本身以及可能需要新建的页表页
+const SWAP_RESERVE_FRAMES: usize = 4;
+
+/// 判断页帧是否是全局共享的全零页帧
+fn is_zero_frame(frame: &FrameTracker) -> bool {
+    frame.ppn == ZERO_FRAME.ppn
 }
 
 /// 内核令牌
@@ -40,31 +66,252 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// 用户栈可以占据的虚拟页号范围，栈区域从其顶端开始向下增长
+    stack_range: Option<VPNRange>,
+    /// 已分配的陷阱上下文槽位，第 i 位对应第 i 个槽位
+    trap_cx_slots: u64,
+    /// 换出页面时时钟算法的指针，从这个虚拟页号开始寻找下一个被换出的页面
+    swap_hand: VirtPageNum,
+    /// 驻留页面数的峰值和缺页次数
+    stats: MemStats,
+}
+
+/// 地址空间的内存使用统计
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MemStats {
+    /// 驻留页面数（见 [`MemorySet::resident_pages`]）的峰值
+    pub peak_rss: usize,
+    /// 不需要读取文件或交换文件就能处理的缺页次数
+    pub minor_faults: u64,
+    /// 需要从文件或交换文件读入页面的缺页次数
+    pub major_faults: u64,
+}
+
+impl MemStats {
+    /// 合并另一份统计：缺页次数相加，峰值取较大者
+    pub fn merge(&mut self, other: &MemStats) {
+        self.peak_rss = self.peak_rss.max(other.peak_rss);
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+    }
+}
+
+/// 槽位 `slot` 的陷阱上下文页的虚拟地址，槽位从 TRAP_CONTEXT_BASE 开始向下排列
+pub fn trap_cx_va(slot: usize) -> usize {
+    TRAP_CONTEXT_BASE - slot * PAGE_SIZE
 }
 
 impl MemorySet {
     /// 创建一个新的空的 `MemorySet`。
-    pub fn new_bare() -> Self {
+    pub fn new_bare() -> Result<Self, OutOfMemory> {
+        Ok(Self::with_page_table(PageTable::new()?))
+    }
+    /// 在页表 `page_table` 上创建空的 `MemorySet`
+    fn with_page_table(page_table: PageTable) -> Self {
         Self {
-            page_table: PageTable::new(),
+            page_table,
             areas: Vec::new(),
+            stack_range: None,
+            trap_cx_slots: 0,
+            swap_hand: VirtPageNum(0),
+            stats: MemStats::default(),
         }
     }
     /// 获取页表令牌
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
-    /// 假设没有冲突。
+    /// 与已有区域重叠时返回 [`MapError::Overlap`]。
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> Result<(), MapError> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
+    }
+    /// 在 [start_va, end_va) 插入一个按需分配的区域，页面在第一次缺页时才分配，
+    /// `file` 不为空时页面内容从文件中读取。与已有区域重叠时返回 [`MapError::Overlap`]。
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        file: Option<MapFile>,
+    ) -> Result<(), MapError> {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, permission);
+        map_area.file = file;
+        self.push(map_area, None)
+    }
+    /// 从 `start_va` 开始映射整个共享内存段 `segment`。
+    /// 与已有区域重叠时返回 [`MapError::Overlap`]。
+    pub fn insert_shared_area(
+        &mut self,
+        start_va: VirtAddr,
+        permission: MapPermission,
+        segment: Arc<SharedSegment>,
+    ) -> Result<(), MapError> {
+        let end_va = VirtAddr::from(start_va.0 + segment.page_count() * PAGE_SIZE);
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Shared, permission).with_label("[shm]");
+        map_area.shared = Some(SharedMapping {
+            segment,
+            first_page: 0,
+        });
+        self.push(map_area, None)
+    }
+    /// 解除从 `start` 开始映射的共享内存段，返回 `start` 处是否映射了某个段的起始页
+    pub fn detach_shared(&mut self, start: VirtPageNum) -> bool {
+        let Some(segment) = self
+            .areas
+            .iter()
+            .find(|area| area.vpn_range.get_start() == start)
+            .and_then(|area| area.shared.as_ref())
+            .filter(|shared| shared.first_page == 0)
+            .map(|shared| shared.segment.clone())
+        else {
+            return false;
+        };
+        let end = VirtPageNum(start.0 + segment.page_count());
+        // 段可能被 mprotect 拆分成多个区域，只移除属于该段的部分
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let area = &self.areas[idx];
+            let belongs = area
+                .shared
+                .as_ref()
+                .map_or(false, |shared| Arc::ptr_eq(&shared.segment, &segment));
+            if belongs && start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end {
+                let mut area = self.areas.remove(idx);
+                area.unmap(&mut self.page_table);
+            } else {
+                idx += 1;
+            }
+        }
+        true
+    }
+    /// 判断 `range` 是否与已有的某个区域重叠
+    pub fn overlaps(&self, range: VPNRange) -> bool {
+        self.areas
+            .iter()
+            .any(|area| ranges_intersect(area.vpn_range, range))
+    }
+    /// 从 `hint` 开始向上寻找一段长度为 `page_count` 且未被占用的虚拟页号范围
+    pub fn find_free_range(&self, hint: VirtPageNum, page_count: usize) -> VPNRange {
+        let mut start = hint;
+        loop {
+            let range = VPNRange::new(start, VirtPageNum(start.0 + page_count));
+            match self
+                .areas
+                .iter()
+                .find(|area| ranges_intersect(area.vpn_range, range))
+            {
+                Some(area) => start = area.vpn_range.get_end(),
+                None => return range,
+            }
+        }
+    }
+    /// 解除用户程序请求的 [start, start + len) 内的映射，部分覆盖的区域会被拆分。
+    /// 范围必须按页对齐、位于用户地址空间内，且只涉及用户可访问的区域，陷阱上下文等内核管理的区域不能被解除；
+    /// 不满足条件或范围内没有任何映射时返回 `EINVAL`
+    pub fn unmap_user(&mut self, start: usize, len: usize) -> Result<(), Errno> {
+        let range = user_range(start, len)?;
+        if !self.user_only(range) || !self.remove_range(range.get_start(), range.get_end()) {
+            return Err(EINVAL);
+        }
+        Ok(())
+    }
+    /// 与 `range` 重叠的区域是否都是用户可访问的
+    fn user_only(&self, range: VPNRange) -> bool {
+        self.areas
+            .iter()
+            .filter(|area| ranges_intersect(area.vpn_range, range))
+            .all(|area| area.map_perm.contains(MapPermission::U))
+    }
+    /// 解除 [start, end) 内的所有映射，部分覆盖的区域会被拆分。
+    /// 返回该范围内是否存在映射。
+    pub fn remove_range(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        let mut found = false;
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let area_start = self.areas[idx].vpn_range.get_start();
+            let area_end = self.areas[idx].vpn_range.get_end();
+            if !ranges_intersect(self.areas[idx].vpn_range, VPNRange::new(start, end)) {
+                idx += 1;
+                continue;
+            }
+            found = true;
+            if area_start < start {
+                // 保留范围之前的部分，剩余部分在下一轮处理
+                let upper = self.areas[idx].split_at(start);
+                self.areas.insert(idx + 1, upper);
+                idx += 1;
+                continue;
+            }
+            if end < area_end {
+                // 保留范围之后的部分
+                let upper = self.areas[idx].split_at(end);
+                self.areas.insert(idx + 1, upper);
+            }
+            let mut area = self.areas.remove(idx);
+            area.unmap(&mut self.page_table);
+        }
+        found
+    }
+    /// 将 [start, start + len) 内所有页面的权限修改为 `perm`，部分覆盖的区域会被拆分。
+    /// 已映射页面的页表项立即更新，惰性区域中尚未加载的页面在缺页时使用新的权限。
+    /// `perm` 不含 R、W、X 时（PROT_NONE）页面保留但不可访问。
+    /// `start` 未按页对齐、范围溢出或涉及用户不可访问的区域时返回 `EINVAL`，范围内存在未映射的空洞时返回 `ENOMEM`。
+    pub fn protect(&mut self, start: usize, len: usize, perm: MapPermission) -> Result<(), Errno> {
+        let range = user_range(start, len)?;
+        if !self.user_only(range) {
+            return Err(EINVAL);
+        }
+        // 先确认整个范围都被区域覆盖，避免修改一半后失败
+        let covered: usize = self
+            .areas
+            .iter()
+            .filter(|area| ranges_intersect(area.vpn_range, range))
+            .map(|area| {
+                let lo = area.vpn_range.get_start().max(range.get_start());
+                let hi = area.vpn_range.get_end().min(range.get_end());
+                hi.0 - lo.0
+            })
+            .sum();
+        if covered != range.get_end().0 - range.get_start().0 {
+            return Err(ENOMEM);
+        }
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            if !ranges_intersect(self.areas[idx].vpn_range, range) {
+                idx += 1;
+                continue;
+            }
+            if self.areas[idx].vpn_range.get_start() < range.get_start() {
+                // 保留范围之前的部分，剩余部分在下一轮处理
+                let upper = self.areas[idx].split_at(range.get_start());
+                self.areas.insert(idx + 1, upper);
+                idx += 1;
+                continue;
+            }
+            if range.get_end() < self.areas[idx].vpn_range.get_end() {
+                // 保留范围之后的部分
+                let upper = self.areas[idx].split_at(range.get_end());
+                self.areas.insert(idx + 1, upper);
+            }
+            let area = &mut self.areas[idx];
+            area.map_perm = perm;
+            let flags = PTEFlags::from_bits(perm.bits).unwrap();
+            for vpn in area.vpn_range {
+                // 被共享的页帧保持只读，写入时再复制
+                let flags = area.data_frames.get(&vpn).map_or(flags, |frame| area.frame_flags(frame));
+                self.page_table.set_flags(vpn, flags);
+            }
+            idx += 1;
+        }
+        Ok(())
     }
     /// 移除指定起始虚拟页号的区域
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
@@ -79,27 +326,35 @@ impl MemorySet {
         }
     }
     /// 向该 `MemorySet` 中添加一个新的 `MapArea`。
-    /// 假设虚拟地址空间中没有冲突。
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// 与已有区域重叠或内存不足时区域不会被加入。
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> Result<(), MapError> {
+        if self.overlaps(map_area.vpn_range) {
+            return Err(MapError::Overlap);
+        }
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        self.note_rss();
+        Ok(())
     }
     /// 提到 trampoline 不会被区域回收。
-    fn map_trampoline(&mut self) {
+    fn map_trampoline(&mut self) -> Result<(), OutOfMemory> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
     /// 不包含内核栈。
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        Self::build_kernel().expect("创建内核地址空间时内存不足")
+    }
+    fn build_kernel() -> Result<Self, MapError> {
+        let mut memory_set = Self::with_page_table(PageTable::new_kernel()?);
         // 映射 trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // 映射内核段
         info!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         info!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
@@ -117,7 +372,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
+        )?;
         info!("映射 .rodata 段");
         memory_set.push(
             MapArea::new(
@@ -127,7 +382,7 @@ impl MemorySet {
                 MapPermission::R,
             ),
             None,
-        );
+        )?;
         info!("映射 .data 段");
         memory_set.push(
             MapArea::new(
@@ -137,7 +392,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         info!("映射 .bss 段");
         memory_set.push(
             MapArea::new(
@@ -147,37 +402,40 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         info!("映射物理内存");
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
-                MEMORY_END.into(),
-                MapType::Identical,
+                frame_allocator::memory_end().into(),
+                MapType::IdenticalHuge,
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
         info!("映射内存映射寄存器");
-        for pair in MMIO {
+        for (base, size) in mmio_regions() {
             memory_set.push(
                 MapArea::new(
-                    (*pair).0.into(),
-                    ((*pair).0 + (*pair).1).into(),
-                    MapType::Identical,
+                    base.into(),
+                    (base + size).into(),
+                    MapType::IdenticalHuge,
                     MapPermission::R | MapPermission::W,
                 ),
                 None,
-            );
+            )?;
         }
-        memory_set
+        info!("内核页表占用 {} 个物理页帧", memory_set.page_table.frame_count());
+        Ok(memory_set)
     }
     /// 包含 elf 中的各个段和 trampoline、TrapContext、用户栈，
     /// 同时返回用户栈基址和入口点。
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    ///
+    /// 各段数据在创建时立即拷贝，仅用于内嵌在内核镜像中的 `initproc`。
+    pub fn from_elf_data(elf_data: &[u8]) -> Result<(Self, usize, ElfInfo), MapError> {
+        let mut memory_set = Self::new_bare()?;
         // 映射 trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // 映射 elf 的程序头，带有 U 标志
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
@@ -190,85 +448,355 @@ impl MemorySet {
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
                 let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                let map_area = MapArea::new(start_va, end_va, MapType::Framed, elf_map_perm(&ph));
                 max_end_vpn = map_area.vpn_range.get_end();
                 memory_set.push(
                     map_area,
                     Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                )?;
             }
         }
+        let user_stack_top = memory_set.map_user_stack(max_end_vpn)?;
+        let mut elf_info = ElfInfo::new(&elf, 0).unwrap_or(ElfInfo {
+            entry: elf.header.pt2.entry_point() as usize,
+            phdr: 0,
+            phent: 0,
+            phnum: 0,
+            tls: None,
+        });
+        // 内嵌的程序出错时只能放弃 TLS 模板，不能像 exec 那样返回错误
+        elf_info.tls = elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Tls))
+            .and_then(|ph| {
+                let image = |offset: usize, len: usize| {
+                    let end = offset.checked_add(len).ok_or(ExecError::NotElf)?;
+                    elf.input.get(offset..end).map(<[u8]>::to_vec).ok_or(ExecError::NotElf)
+                };
+                TlsTemplate::new(&ph, image).map_err(|_| warn!("忽略不合法的 PT_TLS 段")).ok()
+            });
+        Ok((memory_set, user_stack_top, elf_info))
+    }
+    /// 与 [`MemorySet::from_elf_data`] 相同，但 Load 段按需从文件中读取：
+    /// 这里只记录每个段在文件中的位置，页面在第一次缺页时才分配并填充。
+    pub fn from_elf(elf_file: &Arc<VFile>) -> Result<(Self, usize, ElfInfo), ExecError> {
+        // 只读取 elf 头和程序头表
+        let mut head = vec![0u8; PAGE_SIZE];
+        let head_len = elf_file.read_at(0, &mut head)?;
+        head.truncate(head_len);
+        let elf_header = xmas_elf::ElfFile::new(&head).map_err(|_| ExecError::NotElf)?.header;
+        if elf_header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(ExecError::NotElf);
+        }
+        let ph_table_end = elf_header.pt2.ph_offset() as usize
+            + elf_header.pt2.ph_count() as usize * elf_header.pt2.ph_entry_size() as usize;
+        if ph_table_end > head.len() {
+            // 程序头表不在第一页内
+            head.resize(ph_table_end, 0);
+            if elf_file.read_at(0, &mut head)? != ph_table_end {
+                return Err(ExecError::NotElf);
+            }
+        }
+        let elf = xmas_elf::ElfFile::new(&head).map_err(|_| ExecError::NotElf)?;
+        // 位置无关的程序（ET_DYN）链接在地址 0 附近，整体平移到 ELF_DYN_BASE 处加载
+        let bias = match elf.header.pt2.type_().as_type() {
+            xmas_elf::header::Type::SharedObject => ELF_DYN_BASE,
+            _ => 0,
+        };
+        let mut memory_set = Self::new_bare()?;
+        // 映射 trampoline
+        memory_set.map_trampoline()?;
+        let mut max_end_vpn = VirtPageNum(0);
+        let mut dynamic = None;
+        let mut tls = None;
+        for i in 0..elf.header.pt2.ph_count() {
+            let ph = elf.program_header(i).map_err(|_| ExecError::NotElf)?;
+            match ph.get_type().map_err(|_| ExecError::NotElf)? {
+                xmas_elf::program::Type::Load => {
+                    let start_va: VirtAddr = (bias + ph.virtual_addr() as usize).into();
+                    let end_va: VirtAddr = (bias + (ph.virtual_addr() + ph.mem_size()) as usize).into();
+                    let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, elf_map_perm(&ph));
+                    map_area.file = Some(MapFile {
+                        file: elf_file.clone(),
+                        start_va: bias + ph.virtual_addr() as usize,
+                        offset: ph.offset() as usize,
+                        file_size: ph.file_size() as usize,
+                    });
+                    max_end_vpn = map_area.vpn_range.get_end();
+                    memory_set.push(map_area, None)?;
+                }
+                xmas_elf::program::Type::Dynamic => {
+                    dynamic = Some((ph.offset() as usize, ph.file_size() as usize));
+                }
+                xmas_elf::program::Type::Tls => {
+                    // TLS 初始映像很小，直接读入内存，exec 时复制到主线程的 TLS 块中
+                    tls = Some(TlsTemplate::new(&ph, |offset, len| {
+                        let mut image = vec![0u8; len];
+                        if elf_file.read_at(offset, &mut image)? != len {
+                            return Err(ExecError::NotElf);
+                        }
+                        Ok(image)
+                    })?);
+                }
+                _ => {}
+            }
+        }
+        if let (true, Some(dynamic)) = (bias != 0, dynamic) {
+            memory_set.apply_relocations(elf_file, &elf, bias, dynamic)?;
+        }
+        let user_stack_top = memory_set.map_user_stack(max_end_vpn)?;
+        let mut elf_info = ElfInfo::new(&elf, bias).ok_or(ExecError::NotElf)?;
+        elf_info.tls = tls;
+        Ok((memory_set, user_stack_top, elf_info))
+    }
+    /// 处理位置无关程序的动态重定位表：对每个 R_RISCV_RELATIVE 表项，
+    /// 将 `bias + addend` 写入 `bias + offset`。`dynamic` 是 PT_DYNAMIC 段在文件中的 (偏移, 长度)。
+    /// 静态链接的程序只应包含相对重定位，遇到其他类型时返回 `NotElf`。
+    fn apply_relocations(
+        &mut self,
+        elf_file: &VFile,
+        elf: &xmas_elf::ElfFile,
+        bias: usize,
+        dynamic: (usize, usize),
+    ) -> Result<(), ExecError> {
+        let read_exact = |offset: usize, len: usize| {
+            let mut buf = vec![0u8; len];
+            if elf_file.read_at(offset, &mut buf)? != len {
+                return Err(ExecError::NotElf);
+            }
+            Ok(buf)
+        };
+        let word = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+        let (mut rela_va, mut rela_size, mut rela_ent) = (None, 0, RELA_ENTRY_SIZE);
+        for entry in read_exact(dynamic.0, dynamic.1)?.chunks_exact(16) {
+            match word(entry) {
+                DT_NULL => break,
+                DT_RELA => rela_va = Some(word(&entry[8..])),
+                DT_RELASZ => rela_size = word(&entry[8..]),
+                DT_RELAENT => rela_ent = word(&entry[8..]),
+                _ => {}
+            }
+        }
+        let Some(rela_va) = rela_va else {
+            return Ok(());
+        };
+        if rela_ent < RELA_ENTRY_SIZE {
+            return Err(ExecError::NotElf);
+        }
+        // 重定位表的地址是链接时的虚拟地址，需要换算为文件偏移
+        let rela_offset = elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+            .find(|ph| {
+                let start = ph.virtual_addr() as usize;
+                start <= rela_va && rela_va + rela_size <= start + ph.file_size() as usize
+            })
+            .map(|ph| rela_va - ph.virtual_addr() as usize + ph.offset() as usize)
+            .ok_or(ExecError::NotElf)?;
+        for entry in read_exact(rela_offset, rela_size)?.chunks_exact(rela_ent) {
+            let (offset, info, addend) = (word(entry), word(&entry[8..]), word(&entry[16..]));
+            match info & 0xffff_ffff {
+                R_RISCV_NONE => {}
+                R_RISCV_RELATIVE => self.write_word(bias + offset, bias.wrapping_add(addend))?,
+                _ => return Err(ExecError::NotElf),
+            }
+        }
+        Ok(())
+    }
+    /// 在尚未激活的地址空间中向 `va` 写入一个字，页面尚未加载时先加载；
+    /// 与页缓存共享的页帧或全零页先换成私有副本
+    fn write_word(&mut self, va: usize, value: usize) -> Result<(), ExecError> {
+        let va = VirtAddr::from(va);
+        if va.page_offset() % core::mem::size_of::<usize>() != 0 {
+            return Err(ExecError::NotElf);
+        }
+        let vpn = va.floor();
+        let idx = self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+            .ok_or(ExecError::NotElf)?;
+        if !self.areas[idx].data_frames.contains_key(&vpn) && !self.handle_page_fault(va, false) {
+            return Err(ExecError::OutOfMemory);
+        }
+        let area = &mut self.areas[idx];
+        if area.data_frames[&vpn].ref_count() > 1 {
+            area.unshare_page(&mut self.page_table, vpn)?;
+        }
+        let ppn = self.areas[idx].data_frames[&vpn].ppn;
+        let offset = va.page_offset();
+        ppn.get_bytes_array()[offset..offset + core::mem::size_of::<usize>()]
+            .copy_from_slice(&value.to_le_bytes());
+        // 页面内容已与文件不同，换出时不能直接丢弃
+        self.page_table.mark_accessed(vpn, true);
+        Ok(())
+    }
+    /// 在 elf 各段之后映射用户栈和 sbrk 区域，返回用户栈顶
+    fn map_user_stack(&mut self, max_end_vpn: VirtPageNum) -> Result<usize, MapError> {
         // 映射用户栈，带有 U 标志
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // 保护页
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
+        let mut user_stack_floor: usize = max_end_va.into();
+        // 保护页，栈永远不会增长到这一页
+        user_stack_floor += PAGE_SIZE;
+        // 为栈的向下增长预留 USER_STACK_LIMIT 的空间，初始只映射顶部的 USER_STACK_SIZE
+        let user_stack_top = user_stack_floor + USER_STACK_LIMIT;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
+        self.stack_range = Some(VPNRange::new(
+            VirtAddr::from(user_stack_floor).floor(),
+            VirtAddr::from(user_stack_top).floor(),
+        ));
+        self.push(
             MapArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
+            )
+            .with_label("[stack]"),
             None,
-        );
-        // 用于 sbrk
-        memory_set.push(
+        )?;
+        // 用于 sbrk 的堆区域，初始为空，由 brk 向上扩展
+        self.push(
             MapArea::new(
                 user_stack_top.into(),
-                (user_stack_top+4).into(),
+                user_stack_top.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
+            )
+            .with_label("[heap]"),
             None,
-        );
-        // 映射 TrapContext
-        memory_set.push(
+        )?;
+        Ok(user_stack_top)
+    }
+    /// 为一个线程分配并映射陷阱上下文页，返回槽位号；槽位用尽时同样返回 [`MapError::OutOfMemory`]
+    pub fn alloc_trap_cx(&mut self) -> Result<usize, MapError> {
+        let slot = (!self.trap_cx_slots).trailing_zeros() as usize;
+        if slot >= MAX_TRAP_CX_SLOTS {
+            return Err(MapError::OutOfMemory);
+        }
+        let va = trap_cx_va(slot);
+        self.push(
             MapArea::new(
-                TRAP_CONTEXT_BASE.into(),
-                TRAMPOLINE.into(),
+                va.into(),
+                (va + PAGE_SIZE).into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        )?;
+        self.trap_cx_slots |= 1 << slot;
+        Ok(slot)
     }
-    /// 通过复制退出进程的地址空间中的代码和数据创建新的地址空间。
-    pub fn from_existed_user(user_space: &Self) -> Self {
-        let mut memory_set = Self::new_bare();
+    /// 解除槽位 `slot` 的陷阱上下文页的映射，槽位可以被再次分配
+    pub fn dealloc_trap_cx(&mut self, slot: usize) {
+        assert!(self.trap_cx_slots & (1 << slot) != 0, "trap context slot {} is not allocated", slot);
+        self.remove_area_with_start_vpn(VirtAddr::from(trap_cx_va(slot)).floor());
+        self.trap_cx_slots &= !(1 << slot);
+    }
+    /// 槽位 `slot` 的陷阱上下文页的物理页号
+    pub fn trap_cx_ppn(&self, slot: usize) -> PhysPageNum {
+        self.translate(VirtAddr::from(trap_cx_va(slot)).floor())
+            .unwrap()
+            .ppn()
+    }
+    /// 依次列出地址空间中的各个区域，按起始地址排序
+    pub fn iter_areas(&self) -> impl Iterator<Item = AreaInfo> + '_ {
+        let mut areas: Vec<&MapArea> = self.areas.iter().collect();
+        areas.sort_by_key(|area| area.vpn_range.get_start());
+        areas.into_iter().map(|area| {
+            let start: usize = VirtAddr::from(area.vpn_range.get_start()).into();
+            let backing = if let Some(file) = &area.file {
+                AreaBacking::File {
+                    name: file.file.get_name().into(),
+                    inode: file.file.first_cluster().unwrap_or(0) as usize,
+                    // 映射起始地址不一定按页对齐，区域从其所在页的开头算起
+                    offset: file.offset.saturating_sub(file.start_va - start),
+                }
+            } else if let Some(label) = area.label {
+                AreaBacking::Label(label)
+            } else {
+                AreaBacking::Anonymous
+            };
+            AreaInfo {
+                start,
+                end: VirtAddr::from(area.vpn_range.get_end()).into(),
+                perm: area.map_perm,
+                map_type: area.map_type,
+                backing,
+            }
+        })
+    }
+    /// 用户可访问的区域中驻留在内存中的页面数，作为 RSS 的估计，由页表在映射和取消映射时维护：
+    /// 与其他地址空间共享的页帧（共享内存、写时复制页面）也计入
+    pub fn resident_pages(&self) -> usize {
+        self.page_table.user_pages()
+    }
+    /// 驻留页面数的峰值和缺页次数
+    pub fn mem_stats(&self) -> MemStats {
+        self.stats
+    }
+    /// 沿用 `old` 的统计，exec 替换地址空间后进程的缺页次数和 RSS 峰值继续累计
+    pub fn inherit_stats(&mut self, old: &Self) {
+        self.stats.merge(&old.stats);
+    }
+    /// 驻留页面数可能增加后更新峰值
+    fn note_rss(&mut self) {
+        self.stats.peak_rss = self.stats.peak_rss.max(self.resident_pages());
+    }
+    /// 通过复制已有进程的地址空间中的代码和数据创建新的地址空间。
+    /// 惰性区域中已加载的页面与原地址空间共享，双方都映射为只读，写入时再复制。
+    pub fn from_existed_user(user_space: &mut Self) -> Result<Self, MapError> {
+        let mut memory_set = Self::new_bare()?;
+        memory_set.stack_range = user_space.stack_range;
+        memory_set.trap_cx_slots = user_space.trap_cx_slots;
         // 映射 trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // 复制数据段、trap_context、用户栈
         for area in user_space.areas.iter() {
             let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
+            memory_set.push(new_area, None)?;
+            if area.map_type == MapType::Shared {
+                // 共享区域映射到相同的物理页帧，无需复制
+                continue;
+            }
+            if area.map_type == MapType::Lazy {
+                // 惰性区域只共享父进程已经访问过的页面，其余页面继续按需加载；
+                // 父进程中的页面同样改为只读，任何一方写入时才复制
+                let new_area = memory_set.areas.last_mut().unwrap();
+                for (&vpn, frame) in area.data_frames.iter() {
+                    new_area.map_frame(&mut memory_set.page_table, vpn, frame.clone_shared())?;
+                    user_space.page_table.write_protect(vpn);
+                }
+            }
+            // 已换出的页面直接从交换文件读入子进程的页帧
+            let new_area = memory_set.areas.last_mut().unwrap();
+            for (&vpn, slot) in area.swapped.iter() {
+                if area.map_type == MapType::Lazy {
+                    new_area.map_one(&mut memory_set.page_table, vpn)?;
+                }
+                slot.read(memory_set.page_table.translate(vpn).unwrap().ppn());
+                memory_set.page_table.mark_accessed(vpn, true);
+            }
             // 从另一个空间复制数据
             for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let Some(src_pte) = user_space.translate(vpn).filter(|pte| pte.is_valid()) else {
+                    continue;
+                };
+                if src_pte.flags().contains(PTEFlags::D) {
+                    // 保留修改位，只有未修改过的文件页才能在换出时直接丢弃
+                    memory_set.page_table.mark_accessed(vpn, true);
+                }
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                if dst_ppn == src_pte.ppn() {
+                    continue; // 共享的页帧
+                }
                 dst_ppn
                     .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+                    .copy_from_slice(src_pte.ppn().get_bytes_array());
             }
         }
-        memory_set
+        memory_set.note_rss();
+        Ok(memory_set)
     }
     /// 通过写入 satp CSR 寄存器更改页表。
     pub fn activate(&self) {
@@ -283,9 +811,180 @@ impl MemorySet {
         self.page_table.translate(vpn)
     }
 
-    /// 清除所有 `MapArea`
-    pub fn recycle_data_pages(&mut self) {
+    /// 处理用户地址 `va` 上的缺页：如果它落在某个惰性区域内且尚未映射，
+    /// 则分配物理页、按需从文件填充并建立映射；如果它已被换出，则从交换文件读回；
+    /// 如果它紧邻用户栈下方，则向下扩展用户栈。内容全为零的页面在读缺页（`write` 为假）时
+    /// 只映射共享的全零页，之后的写缺页再换成私有页帧。
+    /// 空闲页帧不足时先换出本地址空间的其他页面。返回缺页是否已被处理，物理内存不足时同样返回 `false`。
+    /// 处理成功的缺页按是否读取了文件或交换文件分别计入主要和次要缺页次数。
+    pub fn handle_page_fault(&mut self, va: VirtAddr, write: bool) -> bool {
+        let Some(major) = self.fault_in(va.floor(), write) else {
+            return false;
+        };
+        if major {
+            self.stats.major_faults += 1;
+        } else {
+            self.stats.minor_faults += 1;
+        }
+        self.note_rss();
+        true
+    }
+
+    /// [`MemorySet::handle_page_fault`] 的实现，缺页已被处理时返回是否读取了文件或交换文件
+    fn fault_in(&mut self, vpn: VirtPageNum, write: bool) -> Option<bool> {
+        self.reclaim_frames();
+        if self.grow_stack(vpn) {
+            return Some(false);
+        }
+        if self.page_table.translate(vpn).map_or(false, |pte| pte.is_swapped()) {
+            let area = self.areas.iter_mut().find(|area| {
+                area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end()
+            })?;
+            return area.swap_in(&mut self.page_table, vpn).ok().map(|_| true);
+        }
+        if let Some(area) = self.areas.iter_mut().find(|area| {
+            area.map_type == MapType::Lazy
+                && area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+        }) {
+            if !area.map_perm.intersects(MapPermission::R | MapPermission::W | MapPermission::X) {
+                return None; // PROT_NONE 的页面不可访问
+            }
+            if area.data_frames.contains_key(&vpn) {
+                // 页面已经存在：对可写区域中只读映射的共享页帧（写时复制页面、全零页）的写入
+                // 需要复制或独占页帧，其余都是权限错误
+                if !write || !area.map_perm.contains(MapPermission::W) {
+                    return None;
+                }
+                return area.unshare_page(&mut self.page_table, vpn).ok().map(|_| false);
+            }
+            if !write && area.file.as_ref().map_or(true, |file| file.is_zero_page(vpn)) {
+                return area
+                    .map_frame(&mut self.page_table, vpn, ZERO_FRAME.clone_shared())
+                    .ok()
+                    .map(|_| false);
+            }
+            // 不可写的文件页面可以与映射同一文件的其他进程共享
+            let cache_key = area
+                .file
+                .as_ref()
+                .filter(|_| !area.map_perm.contains(MapPermission::W))
+                .and_then(|file| file.cache_key(vpn));
+            if let Some(frame) = cache_key.and_then(page_cache_lookup) {
+                return area.map_frame(&mut self.page_table, vpn, frame).ok().map(|_| false);
+            }
+            area.map_one(&mut self.page_table, vpn).ok()?;
+            if let Some(file) = &area.file {
+                // 从后备文件中读取页面内容，读取失败时撤销映射，访问按非法地址处理，进程被杀死
+                // （相当于 SIGBUS，内核还没有信号）
+                if let Err(errno) = file.fill_page(vpn, area.data_frames[&vpn].ppn) {
+                    warn!("缺页时读取文件失败 (errno {})，地址 {:#x}", errno, VirtAddr::from(vpn).0);
+                    area.unmap_one(&mut self.page_table, vpn);
+                    return None;
+                }
+            }
+            if let Some(key) = cache_key {
+                page_cache_insert(key, area.data_frames[&vpn].clone_shared());
+            }
+            // 文件末尾之后（如 bss）的页面只需清零，不算读取了文件
+            Some(area.file.as_ref().map_or(false, |file| !file.is_zero_page(vpn)))
+        } else {
+            None
+        }
+    }
+
+    /// 空闲页帧少于 `SWAP_RESERVE_FRAMES` 时回收页缓存并换出本地址空间的页面，
+    /// 直到空闲页帧足够或者无法再换出
+    fn reclaim_frames(&mut self) {
+        while frame_allocator::stats().free < SWAP_RESERVE_FRAMES
+            && (page_cache_shrink() > 0 || self.swap_out_one())
+        {}
+    }
+
+    /// 按时钟（二次机会）算法从本地址空间换出一个页面，返回是否换出成功。
+    /// 只考虑用户可访问区域中的私有页帧，陷阱上下文、共享内存以及与其他区域共享的页帧不会被换出。
+    pub fn swap_out_one(&mut self) -> bool {
+        if !swap_enabled() {
+            return false;
+        }
+        let mut candidates: Vec<(usize, VirtPageNum)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.swappable())
+            .flat_map(|(idx, area)| {
+                area.data_frames
+                    .iter()
+                    .filter(|(_, frame)| frame.ref_count() == 1)
+                    .map(move |(&vpn, _)| (idx, vpn))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+        candidates.sort_unstable_by_key(|&(_, vpn)| vpn);
+        let start = candidates.partition_point(|&(_, vpn)| vpn < self.swap_hand);
+        // 第一轮清除访问位，第二轮一定能找到未被访问过的页面
+        for i in 0..2 * candidates.len() {
+            let (idx, vpn) = candidates[(start + i) % candidates.len()];
+            if self.page_table.clear_accessed(vpn) {
+                continue;
+            }
+            self.swap_hand = VirtPageNum(vpn.0 + 1);
+            if !self.areas[idx].swap_out(&mut self.page_table, vpn) {
+                return false; // 交换文件已满
+            }
+            record_eviction();
+            return true;
+        }
+        false
+    }
+
+    /// 若 `vpn` 位于栈区域下方 `USER_STACK_GROW_GAP` 以内且未超过栈大小限制，
+    /// 将栈区域向下扩展到 `vpn`
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> bool {
+        let Some(stack_range) = self.stack_range else {
+            return false;
+        };
+        let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_end() == stack_range.get_end())
+        else {
+            return false;
+        };
+        let stack_start = area.vpn_range.get_start();
+        if vpn < stack_range.get_start()
+            || vpn >= stack_start
+            || stack_start.0 - vpn.0 > USER_STACK_GROW_GAP / PAGE_SIZE
+        {
+            return false;
+        }
+        area.prepend_to(&mut self.page_table, vpn).is_ok()
+    }
+
+    /// 判断 `va` 处无法处理的缺页是否是用户栈溢出，即落在栈区域与其下方保护页之间
+    pub fn is_stack_overflow(&self, va: VirtAddr) -> bool {
+        let vpn = va.floor();
+        let Some(stack_range) = self.stack_range else {
+            return false;
+        };
+        let guard_vpn = VirtPageNum(stack_range.get_start().0 - 1);
+        let stack_start = self
+            .areas
+            .iter()
+            .find(|area| area.vpn_range.get_end() == stack_range.get_end())
+            .map_or(stack_range.get_end(), |area| area.vpn_range.get_start());
+        guard_vpn <= vpn && vpn < stack_start
+    }
+
+    /// 立即回收所有 `MapArea` 的数据页以及页表自身占用的页帧，
+    /// 不必等到持有该进程的最后一个 `Arc` 被释放
+    pub fn recycle_all(&mut self) {
         self.areas.clear();
+        self.stack_range = None;
+        self.trap_cx_slots = 0;
+        self.page_table.recycle();
     }
 
     /// 将区域缩小到新的结束地址
@@ -303,40 +1002,91 @@ impl MemorySet {
         }
     }
 
-    /// 将区域扩展到新的结束地址
+    /// 将区域扩展到新的结束地址，与其他区域重叠或内存不足时区域保持不变并返回 `false`
     #[allow(unused)]
     pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
-        if let Some(area) = self
+        let Some(idx) = self
             .areas
-            .iter_mut()
-            .find(|area| area.vpn_range.get_start() == start.floor())
-        {   
-            area.append_to(&mut self.page_table, new_end.ceil());
-            true
-        } else {
-            false
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start.floor())
+        else {
+            return false;
+        };
+        let grown = VPNRange::new(self.areas[idx].vpn_range.get_end(), new_end.ceil());
+        if self.overlaps(grown) {
+            return false;
         }
+        let grown = self.areas[idx]
+            .append_to(&mut self.page_table, new_end.ceil())
+            .is_ok();
+        self.note_rss();
+        grown
     }
-
-    /// 映射
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> isize{
-        let _ = self.page_table.map(vpn, ppn, flags);
-        0
-    }
-
-    /// 解除映射
-    pub fn unmap(&mut self, vpn: VirtPageNum) -> isize{
-        let _ = self.page_table.unmap(vpn);
-        0
-    }    
 }
 
 /// 映射区域结构，控制一个连续的虚拟内存区域
 pub struct MapArea {
     vpn_range: VPNRange, // 虚拟页号范围
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>, // 存储虚拟页号到帧跟踪器的映射
+    data_frames: BTreeMap<VirtPageNum, FrameTracker>, // 存储虚拟页号到帧跟踪器的映射，惰性区域中的帧可能被共享
     map_type: MapType, // 映射类型
     map_perm: MapPermission, // 映射权限
+    file: Option<MapFile>, // 文件映射的后备信息
+    shared: Option<SharedMapping>, // 共享内存区域对应的段
+    label: Option<&'static str>, // 在 /proc/<pid>/maps 中显示的名称，如 "[stack]"
+    swapped: BTreeMap<VirtPageNum, SwapSlot>, // 已换出页面的交换槽位
+}
+
+/// [`MemorySet::iter_areas`] 返回的区域描述
+pub struct AreaInfo {
+    /// 起始虚拟地址
+    pub start: usize,
+    /// 结束虚拟地址（不含）
+    pub end: usize,
+    /// 映射权限
+    pub perm: MapPermission,
+    /// 映射类型
+    pub map_type: MapType,
+    /// 区域的后备
+    pub backing: AreaBacking,
+}
+
+/// 区域的后备描述
+pub enum AreaBacking {
+    /// 文件映射
+    File {
+        /// 文件名
+        name: String,
+        /// 文件的首簇号，作为 inode 编号显示
+        inode: usize,
+        /// 区域起始地址对应的文件偏移
+        offset: usize,
+    },
+    /// 带有名称的匿名区域，如 "[heap]"、"[stack]"、"[shm]"
+    Label(&'static str),
+    /// 匿名区域
+    Anonymous,
+}
+
+/// 共享内存区域的后备信息
+#[derive(Clone)]
+pub struct SharedMapping {
+    /// 区域映射的共享内存段
+    pub segment: Arc<SharedSegment>,
+    /// 区域起始页对应段内的页下标
+    pub first_page: usize,
+}
+
+/// 文件映射区域的后备信息，缺页时据此从文件中读取页面内容
+#[derive(Clone)]
+pub struct MapFile {
+    /// 后备文件
+    pub file: Arc<VFile>,
+    /// 映射在虚拟地址空间中的起始地址，不要求按页对齐
+    pub start_va: usize,
+    /// `start_va` 对应的文件偏移
+    pub offset: usize,
+    /// 文件中属于该映射的字节数，其后的部分（如 bss）填零
+    pub file_size: usize,
 }
 
 impl MapArea {
@@ -354,9 +1104,19 @@ impl MapArea {
             data_frames: BTreeMap::new(), // 初始化数据帧为空
             map_type, // 映射类型
             map_perm, // 映射权限
+            file: None, // 默认不关联文件
+            shared: None, // 默认不是共享内存
+            label: None, // 默认没有名称
+            swapped: BTreeMap::new(), // 没有换出的页面
         }
     }
 
+    /// 设置区域在 /proc/<pid>/maps 中显示的名称
+    pub fn with_label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
     /// 通过另一个映射区域创建新映射区域
     pub fn from_another(another: &Self) -> Self {
         Self {
@@ -364,43 +1124,198 @@ impl MapArea {
             data_frames: BTreeMap::new(), // 数据帧为空
             map_type: another.map_type, // 映射类型
             map_perm: another.map_perm, // 映射权限
+            file: another.file.clone(), // 共享同一个后备文件
+            shared: another.shared.clone(), // 共享同一个共享内存段
+            label: another.label, // 名称不变
+            swapped: BTreeMap::new(), // 换出的页面由调用者另行复制
         }
     }
 
     /// 映射一个虚拟页号到物理页号
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), OutOfMemory> {
         let ppn: PhysPageNum;
+        let mut frame = None;
         match self.map_type {
-            MapType::Identical => {
+            MapType::Identical | MapType::IdenticalHuge => {
                 ppn = PhysPageNum(vpn.0); // 如果是Identical映射，则物理页号与虚拟页号相同
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap(); // 分配一个新的帧
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame); // 将虚拟页号和帧映射关系存入data_frames
+            MapType::Framed | MapType::Lazy => {
+                let new_frame = frame_alloc_or_evict().ok_or(OutOfMemory)?; // 分配一个新的帧
+                ppn = new_frame.ppn;
+                frame = Some(new_frame);
+            }
+            MapType::Shared => {
+                // 共享内存的页帧属于共享内存段
+                let shared = self.shared.as_ref().unwrap();
+                ppn = shared
+                    .segment
+                    .ppn(shared.first_page + vpn.0 - self.vpn_range.get_start().0);
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap(); // 获取页表项标志
-        page_table.map(vpn, ppn, pte_flags); // 在页表中进行映射
+        page_table.map(vpn, ppn, pte_flags)?; // 在页表中进行映射
+        if let Some(frame) = frame {
+            self.data_frames.insert(vpn, frame); // 映射成功后才将帧存入data_frames
+        }
+        Ok(())
+    }
+
+    /// 映射页帧 `frame` 使用的页表项标志：被共享的页帧（写时复制页面、只读文件页、全零页）不可写
+    fn frame_flags(&self, frame: &FrameTracker) -> PTEFlags {
+        let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if frame.ref_count() > 1 {
+            flags - PTEFlags::W
+        } else {
+            flags
+        }
+    }
+
+    /// 将已有的物理页帧 `frame` 映射到 `vpn`，该页帧可能同时被其他区域映射
+    fn map_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: FrameTracker,
+    ) -> Result<(), OutOfMemory> {
+        page_table.map(vpn, frame.ppn, self.frame_flags(&frame))?;
+        self.data_frames.insert(vpn, frame);
+        Ok(())
+    }
+
+    /// 让 `vpn` 处的页面独占其页帧并按区域权限重新映射：页帧没有其他引用时直接复用，
+    /// 否则复制到新分配的页帧，全零页的副本只需新分配的已清零页帧
+    fn unshare_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), OutOfMemory> {
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if page_table.translate(vpn).map_or(false, |pte| pte.flags().contains(PTEFlags::D)) {
+            pte_flags |= PTEFlags::D; // 副本与原页面一样视为已修改
+        }
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        if frame.ref_count() > 1 {
+            let private = frame_alloc_or_evict().ok_or(OutOfMemory)?;
+            if !is_zero_frame(frame) {
+                private
+                    .ppn
+                    .get_bytes_array()
+                    .copy_from_slice(frame.ppn.get_bytes_array());
+            }
+            *frame = private;
+        }
+        page_table.unmap(vpn);
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        Ok(())
+    }
+
+    /// 区域中的页面是否可以被换出：只有用户可访问的私有区域参与换出
+    fn swappable(&self) -> bool {
+        matches!(self.map_type, MapType::Framed | MapType::Lazy)
+            && self.map_perm.contains(MapPermission::U)
+    }
+
+    /// 换出页面 `vpn`：惰性文件映射中未修改过的页面直接丢弃，缺页时重新从文件读取；
+    /// 其余页面写入交换文件。交换文件已满时返回 `false`
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let dirty = page_table
+            .translate(vpn)
+            .map_or(true, |pte| pte.flags().contains(PTEFlags::D));
+        if self.map_type == MapType::Lazy && self.file.is_some() && !dirty {
+            self.data_frames.remove(&vpn);
+            page_table.unmap(vpn);
+            return true;
+        }
+        let Some(slot) = SwapSlot::write(self.data_frames[&vpn].ppn) else {
+            return false;
+        };
+        page_table.set_swapped(vpn, slot.index());
+        self.data_frames.remove(&vpn);
+        self.swapped.insert(vpn, slot);
+        true
+    }
+
+    /// 将已换出的页面 `vpn` 从交换文件读回新分配的页帧
+    fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), OutOfMemory> {
+        let frame = frame_alloc_or_evict().ok_or(OutOfMemory)?;
+        let slot = &self.swapped[&vpn];
+        debug_assert_eq!(page_table.translate(vpn).unwrap().swap_slot(), slot.index());
+        slot.read(frame.ppn);
+        // 读回的页面视为已修改，再次换出时必须写回交换文件
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap() | PTEFlags::D;
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        self.swapped.remove(&vpn);
+        self.data_frames.insert(vpn, frame);
+        Ok(())
     }
 
     /// 解除映射一个虚拟页号
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn); // 如果是Framed类型，移除数据帧
+        match self.map_type {
+            MapType::Framed => {
+                self.data_frames.remove(&vpn); // 如果是Framed类型，移除数据帧
+                self.swapped.remove(&vpn); // 已换出的页面归还其交换槽位
+            }
+            MapType::Lazy => {
+                if self.data_frames.remove(&vpn).is_none() && self.swapped.remove(&vpn).is_none() {
+                    return; // 尚未加载的页面没有页表项
+                }
+            }
+            MapType::Identical | MapType::IdenticalHuge | MapType::Shared => {}
         }
         page_table.unmap(vpn); // 解除页表中的映射
     }
 
-    /// 映射整个虚拟页号范围
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn); // 对每个虚拟页号执行映射
+    /// 映射整个虚拟页号范围，失败时撤销已建立的映射
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), OutOfMemory> {
+        if self.map_type == MapType::Lazy {
+            return Ok(()); // 惰性区域在缺页时才映射
         }
+        if self.map_type == MapType::IdenticalHuge {
+            return self.map_identical_huge(page_table);
+        }
+        self.map_range(page_table, self.vpn_range)
+    }
+
+    /// 按 Identical 方式映射整个区域，其中按 2 MiB 对齐的部分使用大页
+    fn map_identical_huge(&mut self, page_table: &mut PageTable) -> Result<(), OutOfMemory> {
+        let flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let end = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end {
+            if vpn.0 % HUGE_PAGE_PAGES == 0 && vpn.0 + HUGE_PAGE_PAGES <= end.0 {
+                page_table.map_huge(vpn, PhysPageNum(vpn.0), flags)?;
+                vpn = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+            } else {
+                self.map_one(page_table, vpn)?;
+                vpn.step();
+            }
+        }
+        Ok(())
+    }
+
+    /// 映射 `range` 内的每一页，失败时撤销本次已建立的映射
+    fn map_range(&mut self, page_table: &mut PageTable, range: VPNRange) -> Result<(), OutOfMemory> {
+        for vpn in range {
+            if let Err(err) = self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
     }
 
     /// 解除整个虚拟页号范围的映射
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::IdenticalHuge {
+            // 大页的叶子节点只需清除一次
+            let end = self.vpn_range.get_end();
+            let mut vpn = self.vpn_range.get_start();
+            while vpn < end {
+                let huge = vpn.0 % HUGE_PAGE_PAGES == 0 && vpn.0 + HUGE_PAGE_PAGES <= end.0;
+                page_table.unmap(vpn);
+                vpn = VirtPageNum(vpn.0 + if huge { HUGE_PAGE_PAGES } else { 1 });
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn); // 对每个虚拟页号执行解除映射
         }
@@ -417,11 +1332,40 @@ impl MapArea {
 
     /// 扩展映射区域到新的结束虚拟页号
     #[allow(unused)]
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn) // 为新的虚拟页号范围执行映射
-        }
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Result<(), OutOfMemory> {
+        // 为新的虚拟页号范围执行映射
+        self.map_range(page_table, VPNRange::new(self.vpn_range.get_end(), new_end))?;
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end); // 更新虚拟页号范围
+        Ok(())
+    }
+
+    /// 将映射区域向下扩展到新的起始虚拟页号
+    pub fn prepend_to(&mut self, page_table: &mut PageTable, new_start: VirtPageNum) -> Result<(), OutOfMemory> {
+        // 为新的虚拟页号范围执行映射
+        self.map_range(page_table, VPNRange::new(new_start, self.vpn_range.get_start()))?;
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end()); // 更新虚拟页号范围
+        Ok(())
+    }
+
+    /// 在 `vpn` 处将区域一分为二：自身保留 [start, vpn)，返回 [vpn, end)，
+    /// 已映射的物理帧随页面一起转移
+    pub fn split_at(&mut self, vpn: VirtPageNum) -> MapArea {
+        let shared = self.shared.as_ref().map(|shared| SharedMapping {
+            segment: shared.segment.clone(),
+            first_page: shared.first_page + vpn.0 - self.vpn_range.get_start().0,
+        });
+        let upper = MapArea {
+            vpn_range: VPNRange::new(vpn, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            file: self.file.clone(),
+            shared,
+            label: self.label,
+            swapped: self.swapped.split_off(&vpn),
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        upper
     }
 
     /// 复制数据到映射区域中（假设所有帧已被清除）
@@ -447,11 +1391,218 @@ impl MapArea {
     }
 }
 
+/// 动态段中的表项类型：结束标记、重定位表地址、重定位表大小、重定位表项大小
+const DT_NULL: usize = 0;
+const DT_RELA: usize = 7;
+const DT_RELASZ: usize = 8;
+const DT_RELAENT: usize = 9;
+/// Elf64_Rela 表项的大小
+const RELA_ENTRY_SIZE: usize = 24;
+/// RISC-V 的重定位类型
+const R_RISCV_NONE: usize = 0;
+const R_RISCV_RELATIVE: usize = 3;
+
+/// 加载 elf 后构造初始用户栈所需的信息，地址均已加上加载偏移
+pub struct ElfInfo {
+    /// 程序入口
+    pub entry: usize,
+    /// 程序头表在用户地址空间中的地址
+    pub phdr: usize,
+    /// 程序头表项的大小
+    pub phent: usize,
+    /// 程序头表项的数量
+    pub phnum: usize,
+    /// PT_TLS 段描述的线程局部存储模板，程序不使用 TLS 时为 `None`
+    pub tls: Option<TlsTemplate>,
+}
+
+/// 线程局部存储的初始模板：每个线程的 TLS 块以 `image`（.tdata）开头，
+/// 其后补零（.tbss）到 `mem_size` 字节，起始地址按 `align` 对齐
+pub struct TlsTemplate {
+    /// .tdata 的初始内容
+    pub image: Vec<u8>,
+    /// TLS 块的总大小
+    pub mem_size: usize,
+    /// TLS 块的对齐要求
+    pub align: usize,
+}
+
+impl TlsTemplate {
+    /// 按 PT_TLS 段 `ph` 创建模板，`read_image(offset, len)` 读出文件中的 .tdata。
+    /// 先检查段的描述：.tdata 不能超过整个 TLS 块，对齐必须是 2 的幂，
+    /// 整个块要能放进初始用户栈（exec 时放在栈顶），检查通过后才读取和分配
+    fn new<F>(ph: &xmas_elf::program::ProgramHeader, read_image: F) -> Result<Self, ExecError>
+    where
+        F: FnOnce(usize, usize) -> Result<Vec<u8>, ExecError>,
+    {
+        let (file_size, mem_size, align) = (ph.file_size() as usize, ph.mem_size() as usize, ph.align() as usize);
+        if file_size > mem_size || !align.max(1).is_power_of_two() {
+            return Err(ExecError::NotElf);
+        }
+        if mem_size > USER_STACK_SIZE {
+            return Err(ExecError::TooBig);
+        }
+        Ok(Self {
+            image: read_image(ph.offset() as usize, file_size)?,
+            mem_size,
+            align,
+        })
+    }
+}
+
+impl ElfInfo {
+    /// 从 elf 头中读取入口和程序头表的信息，程序头表不在任何 Load 段中时返回 `None`
+    fn new(elf: &xmas_elf::ElfFile, bias: usize) -> Option<Self> {
+        let ph_offset = elf.header.pt2.ph_offset() as usize;
+        let phdr = elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+            .find(|ph| {
+                let offset = ph.offset() as usize;
+                offset <= ph_offset && ph_offset < offset + ph.file_size() as usize
+            })
+            .map(|ph| bias + ph.virtual_addr() as usize + ph_offset - ph.offset() as usize)?;
+        Some(Self {
+            entry: bias + elf.header.pt2.entry_point() as usize,
+            phdr,
+            phent: elf.header.pt2.ph_entry_size() as usize,
+            phnum: elf.header.pt2.ph_count() as usize,
+            tls: None,
+        })
+    }
+}
+
+/// 从文件创建用户地址空间失败的原因
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExecError {
+    /// 文件不是合法的 elf
+    NotElf,
+    /// 物理内存不足
+    OutOfMemory,
+    /// 参数和环境变量超出初始用户栈的大小
+    TooBig,
+    /// 读取 elf 文件时磁盘出错
+    Io,
+}
+
+impl From<MapError> for ExecError {
+    fn from(err: MapError) -> Self {
+        match err {
+            // 段之间互相重叠，说明 elf 文件不合法
+            MapError::Overlap => ExecError::NotElf,
+            MapError::OutOfMemory => ExecError::OutOfMemory,
+        }
+    }
+}
+
+impl From<FatError> for ExecError {
+    fn from(_: FatError) -> Self {
+        ExecError::Io
+    }
+}
+
+impl From<OutOfMemory> for ExecError {
+    fn from(_: OutOfMemory) -> Self {
+        ExecError::OutOfMemory
+    }
+}
+
+/// 向地址空间中加入区域失败的原因
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapError {
+    /// 与已有区域重叠
+    Overlap,
+    /// 物理内存不足
+    OutOfMemory,
+}
+
+impl From<OutOfMemory> for MapError {
+    fn from(_: OutOfMemory) -> Self {
+        MapError::OutOfMemory
+    }
+}
+
+/// 判断两个虚拟页号范围是否有公共页，空范围不与任何范围相交
+/// 用户给出的 [start, start + len) 覆盖的虚拟页号范围，
+/// `start` 未按页对齐、范围溢出或超出用户地址空间时返回 `EINVAL`
+fn user_range(start: usize, len: usize) -> Result<VPNRange, Errno> {
+    let end = start.checked_add(len).ok_or(EINVAL)?;
+    if start % PAGE_SIZE != 0 || end > USER_SPACE_END {
+        return Err(EINVAL);
+    }
+    Ok(VPNRange::new(VirtAddr::from(start).floor(), VirtAddr::from(end).ceil()))
+}
+
+fn ranges_intersect(a: VPNRange, b: VPNRange) -> bool {
+    a.get_start() < b.get_end()
+        && b.get_start() < a.get_end()
+        && a.get_start() < a.get_end()
+        && b.get_start() < b.get_end()
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 /// 映射类型，表示内存集合的类型：Identical 或 Framed
 pub enum MapType {
-    Identical, // Identical类型映射
-    Framed, // Framed类型映射
+    /// Identical类型映射
+    Identical,
+    /// Framed类型映射
+    Framed,
+    /// 按需分配的映射，页面在第一次缺页时才分配
+    Lazy,
+    /// Identical类型映射，按 2 MiB 对齐的部分使用大页
+    IdenticalHuge,
+    /// 共享内存映射，页帧属于共享内存段
+    Shared,
+}
+
+impl MapFile {
+    /// 虚拟页 `vpn` 在页缓存中的键，只有整页都来自文件内容时才能被缓存
+    fn cache_key(&self, vpn: VirtPageNum) -> Option<PageCacheKey> {
+        let page_start: usize = VirtAddr::from(vpn).into();
+        if page_start < self.start_va || page_start + PAGE_SIZE > self.start_va + self.file_size {
+            return None;
+        }
+        let cluster = self.file.first_cluster().ok().filter(|&cluster| cluster != 0)?;
+        Some((cluster, self.offset + page_start - self.start_va))
+    }
+    /// 虚拟页 `vpn` 是否完全位于文件内容之外，即内容全为零
+    fn is_zero_page(&self, vpn: VirtPageNum) -> bool {
+        let page_start: usize = VirtAddr::from(vpn).into();
+        page_start + PAGE_SIZE <= self.start_va || page_start >= self.start_va + self.file_size
+    }
+    /// 将虚拟页 `vpn` 对应的文件内容读入物理页 `ppn`（假设该页已被清零）。
+    /// 读取失败或文件在映射之后变短、读不满这一页的内容时返回 EIO，不能映射只有部分内容的页面
+    fn fill_page(&self, vpn: VirtPageNum, ppn: PhysPageNum) -> Result<(), Errno> {
+        let page_start: usize = VirtAddr::from(vpn).into();
+        let page_end = page_start + PAGE_SIZE;
+        // 页面与文件内容 [start_va, start_va + file_size) 的交集
+        let start = page_start.max(self.start_va);
+        let end = page_end.min(self.start_va + self.file_size);
+        if start >= end {
+            return Ok(());
+        }
+        let dst = &mut ppn.get_bytes_array()[start - page_start..end - page_start];
+        match self.file.read_at(self.offset + start - self.start_va, dst) {
+            Ok(len) if len == end - start => Ok(()),
+            _ => Err(EIO),
+        }
+    }
+}
+
+/// 根据程序头的标志得到映射权限
+fn elf_map_perm(ph: &xmas_elf::program::ProgramHeader) -> MapPermission {
+    let mut map_perm = MapPermission::U;
+    let ph_flags = ph.flags();
+    if ph_flags.is_read() {
+        map_perm |= MapPermission::R;
+    }
+    if ph_flags.is_write() {
+        map_perm |= MapPermission::W;
+    }
+    if ph_flags.is_execute() {
+        map_perm |= MapPermission::X;
+    }
+    map_perm
 }
 
 bitflags! {
@@ -493,3 +1644,164 @@ pub fn remap_test() {
     println!("remap_test passed!"); // 如果测试通过，输出提示信息
 }
 
+/// 检查用户空间拷贝函数在跨越页面边界时的正确性
+#[allow(unused)]
+pub fn user_copy_test() {
+    let mut memory_set = MemorySet::new_bare().unwrap();
+    let start: usize = 0x1000_0000;
+    memory_set
+        .insert_framed_area(
+            start.into(),
+            (start + 2 * PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        )
+        .unwrap();
+    let token = memory_set.token();
+    // 跨越两页的字节缓冲区
+    let boundary = start + PAGE_SIZE;
+    let src: Vec<u8> = (0..32u8).collect();
+    let dst = (boundary - 13) as *mut u8;
+    assert_eq!(copy_to_user(token, dst, &src), Ok(src.len()));
+    let mut back = [0u8; 32];
+    assert_eq!(copy_from_user(token, dst, &mut back), Ok(back.len()));
+    assert_eq!(&back[..], &src[..]);
+    // 跨越页面边界且不对齐的结构体
+    let ptr = (boundary - 3) as *mut [u64; 2];
+    let value = [0x0123_4567_89ab_cdef_u64, 0xfedc_ba98_7654_3210];
+    assert_eq!(put_user(token, ptr, value), Ok(()));
+    assert_eq!(get_user(token, ptr as *const [u64; 2]), Ok(value));
+    // 超出映射区域的部分必须报错
+    let tail = (start + 2 * PAGE_SIZE - 4) as *mut u64;
+    assert_eq!(put_user(token, tail, 0), Err(BadAddress));
+    assert_eq!(get_user(token, tail as *const u64), Err(BadAddress));
+    assert_eq!(copy_to_user(token, core::ptr::null_mut(), &src), Err(BadAddress));
+    println!("user_copy_test passed!");
+}
+
+/// 检查重叠的区域会被拒绝，且不会破坏已有的映射
+#[allow(unused)]
+pub fn area_overlap_test() {
+    let mut memory_set = MemorySet::new_bare().unwrap();
+    let perm = MapPermission::R | MapPermission::W | MapPermission::U;
+    let start: usize = 0x1000_0000;
+    memory_set
+        .insert_framed_area(start.into(), (start + 4 * PAGE_SIZE).into(), perm)
+        .unwrap();
+    // 与已有区域的后半部分重叠
+    assert_eq!(
+        memory_set.insert_framed_area(
+            (start + 2 * PAGE_SIZE).into(),
+            (start + 6 * PAGE_SIZE).into(),
+            perm
+        ),
+        Err(MapError::Overlap)
+    );
+    // 完全包含已有区域
+    assert_eq!(
+        memory_set.insert_lazy_area((start - PAGE_SIZE).into(), (start + 5 * PAGE_SIZE).into(), perm, None),
+        Err(MapError::Overlap)
+    );
+    // 紧邻已有区域不算重叠
+    memory_set
+        .insert_framed_area((start + 4 * PAGE_SIZE).into(), (start + 5 * PAGE_SIZE).into(), perm)
+        .unwrap();
+    // 向上扩展到另一个区域时失败，区域保持不变
+    assert!(!memory_set.append_to(start.into(), (start + 6 * PAGE_SIZE).into()));
+    assert!(memory_set
+        .translate(VirtAddr::from(start + 3 * PAGE_SIZE).floor())
+        .unwrap()
+        .is_valid());
+    println!("area_overlap_test passed!");
+}
+
+/// 检查修改权限时区域的拆分和页表项的更新
+#[allow(unused)]
+pub fn mprotect_test() {
+    let mut memory_set = MemorySet::new_bare().unwrap();
+    let rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let ro = MapPermission::R | MapPermission::U;
+    let start: usize = 0x1000_0000;
+    let page = |i: usize| VirtAddr::from(start + i * PAGE_SIZE).floor();
+    memory_set
+        .insert_framed_area(start.into(), (start + 4 * PAGE_SIZE).into(), rw)
+        .unwrap();
+    // 范围位于区域中间，区域被拆分为三段
+    let ppn = memory_set.translate(page(1)).unwrap().ppn();
+    assert_eq!(memory_set.protect(start + PAGE_SIZE, 2 * PAGE_SIZE, ro), Ok(()));
+    assert_eq!(memory_set.areas.len(), 3);
+    assert!(memory_set.translate(page(0)).unwrap().writable());
+    assert!(!memory_set.translate(page(1)).unwrap().writable());
+    assert!(!memory_set.translate(page(2)).unwrap().writable());
+    assert!(memory_set.translate(page(3)).unwrap().writable());
+    // 物理帧随页面转移到拆分出的区域中
+    assert_eq!(memory_set.translate(page(1)).unwrap().ppn(), ppn);
+    assert!(memory_set.areas[1].data_frames.contains_key(&page(1)));
+    assert!(!memory_set.areas[0].data_frames.contains_key(&page(1)));
+    // 范围跨越两个相邻的区域
+    assert_eq!(memory_set.protect(start, 2 * PAGE_SIZE, rw), Ok(()));
+    assert!(memory_set.translate(page(1)).unwrap().writable());
+    assert!(!memory_set.translate(page(2)).unwrap().writable());
+    // 范围内存在未映射的空洞，不做任何修改
+    memory_set
+        .insert_framed_area((start + 5 * PAGE_SIZE).into(), (start + 6 * PAGE_SIZE).into(), rw)
+        .unwrap();
+    assert_eq!(memory_set.protect(start + 3 * PAGE_SIZE, 3 * PAGE_SIZE, ro), Err(ENOMEM));
+    assert!(memory_set.translate(page(3)).unwrap().writable());
+    // PROT_NONE 保留页面但不可访问
+    let none = MapPermission::U;
+    assert_eq!(memory_set.protect(start, PAGE_SIZE, none), Ok(()));
+    let pte = memory_set.translate(page(0)).unwrap();
+    assert!(pte.is_valid() && !pte.readable() && !pte.writable() && !pte.executable());
+    // 范围溢出，或者涉及陷阱上下文这样的内核区域时拒绝修改
+    assert_eq!(memory_set.protect(start, usize::MAX, rw), Err(EINVAL));
+    let slot = memory_set.alloc_trap_cx().unwrap();
+    assert_eq!(memory_set.protect(trap_cx_va(slot), PAGE_SIZE, rw), Err(EINVAL));
+    let kernel_only: usize = 0x2000_0000;
+    memory_set
+        .insert_framed_area(kernel_only.into(), (kernel_only + PAGE_SIZE).into(), MapPermission::R | MapPermission::W)
+        .unwrap();
+    assert_eq!(memory_set.protect(kernel_only, PAGE_SIZE, rw), Err(EINVAL));
+    assert!(!memory_set.translate(VirtAddr::from(kernel_only).floor()).unwrap().flags().contains(PTEFlags::U));
+    println!("mprotect_test passed!");
+}
+
+/// 陷阱上下文槽位的分配、回收，以及各槽位的页面互不干扰
+#[allow(unused)]
+pub fn trap_cx_slot_test() {
+    let mut memory_set = MemorySet::new_bare().unwrap();
+    let slots: Vec<usize> = (0..3).map(|_| memory_set.alloc_trap_cx().unwrap()).collect();
+    assert_eq!(slots, [0, 1, 2]);
+    // 每个槽位写入不同的内容，互相不会覆盖
+    for &slot in slots.iter() {
+        memory_set.trap_cx_ppn(slot).get_bytes_array().fill(slot as u8 + 1);
+    }
+    for &slot in slots.iter() {
+        let page = memory_set.trap_cx_ppn(slot).get_bytes_array();
+        assert!(page.iter().all(|&byte| byte == slot as u8 + 1));
+        let pte = memory_set.translate(VirtAddr::from(trap_cx_va(slot)).floor()).unwrap();
+        assert!(pte.readable() && pte.writable() && !pte.flags().contains(PTEFlags::U));
+    }
+    // 用户程序不能解除陷阱上下文和其他内核管理区域的映射，范围溢出时不会回绕
+    assert_eq!(memory_set.unmap_user(trap_cx_va(0), PAGE_SIZE), Err(EINVAL));
+    let kernel_only: usize = 0x1000_0000;
+    memory_set
+        .insert_framed_area(kernel_only.into(), (kernel_only + PAGE_SIZE).into(), MapPermission::R | MapPermission::W)
+        .unwrap();
+    assert_eq!(memory_set.unmap_user(kernel_only, PAGE_SIZE), Err(EINVAL));
+    assert_eq!(memory_set.unmap_user(kernel_only, usize::MAX), Err(EINVAL));
+    assert!(memory_set.translate(VirtAddr::from(kernel_only).floor()).unwrap().is_valid());
+    // 释放的槽位被再次分配，其页面已经解除映射
+    memory_set.dealloc_trap_cx(1);
+    assert!(memory_set
+        .translate(VirtAddr::from(trap_cx_va(1)).floor())
+        .map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(memory_set.alloc_trap_cx(), Ok(1));
+    // fork 得到的地址空间保留父进程的槽位和内容
+    let copy = MemorySet::from_existed_user(&mut memory_set).unwrap();
+    assert_ne!(copy.trap_cx_ppn(2), memory_set.trap_cx_ppn(2));
+    assert!(copy.trap_cx_ppn(2).get_bytes_array().iter().all(|&byte| byte == 3));
+    // 槽位用尽
+    while memory_set.alloc_trap_cx().is_ok() {}
+    assert_eq!(memory_set.trap_cx_slots, u64::MAX);
+    println!("trap_cx_slot_test passed!");
+}
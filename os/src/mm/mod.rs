@@ -4,21 +4,39 @@
 // 每个任务或进程都有一个`memory_set`用于控制其虚拟内存。
 
 mod address; // 地址相关模块
-mod frame_allocator; // 帧分配器模块
+mod asid; // 地址空间标识符模块
+pub mod frame_allocator; // 帧分配器模块
 mod heap_allocator; // 堆分配器模块
 mod memory_set; // 内存集模块
+mod page_cache; // 只读文件页缓存模块
+mod stack_builder; // 初始用户栈构造模块
+mod swap; // 交换空间模块
+mod user_ptr; // 预先检查的用户指针模块
+pub mod shm; // 共享内存模块
 pub(crate) mod page_table; // 页表模块，仅限内部访问
 
 // 对外暴露的模块和结构
 pub use address::VPNRange; // 虚拟页号范围
+pub use asid::{asid_test, flush_if_shared}; // 进入共享 ASID 的地址空间前刷新 TLB；ASID 自检
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum}; // 物理地址、虚拟地址及相关工具
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker}; // 帧分配与释放，帧跟踪器
-pub use memory_set::remap_test; // 重新映射测试
-pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE}; // 内核标识符、映射权限、内存集、内核空间
+pub use frame_allocator::{
+    bitmap_allocator_test, frame_alloc, frame_alloc_contiguous, frame_dealloc, frame_ref_test,
+    FrameStats, FrameTracker, OutOfMemory,
+}; // 帧分配与释放，帧跟踪器
+#[cfg(feature = "bench")]
+pub use frame_allocator::frame_allocator_bench; // 两种帧分配器的耗时对比
+pub use heap_allocator::{heap_stats, HeapStats}; // 内核堆使用情况
+pub use page_cache::page_cache_invalidate; // 文件内容改变时丢弃其缓存页
+pub use memory_set::{area_overlap_test, mprotect_test, remap_test, trap_cx_slot_test, user_copy_test}; // 内存管理自检
+pub use memory_set::{kernel_token, trap_cx_va, AreaBacking, AreaInfo, ElfInfo, ExecError, MapError, MapFile, MapPermission, MapType, MemStats, MemorySet, KERNEL_SPACE}; // 内核标识符、映射权限、内存集、内核空间
 use page_table::PTEFlags; // 页表项标志
+pub use stack_builder::StackBuilder; // 按 ABI 布置新程序的初始用户栈
+pub use swap::swap_init; // 启用交换文件
+pub use user_ptr::{UserPtr, UserSlice}; // 预先检查映射、权限和对齐的用户指针
 pub use page_table::{
-    translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
-    PageTableEntry, UserBuffer, UserBufferIterator,
+    copy_from_user, copy_to_user, get_user, put_user, translated_byte_buffer,
+    translated_byte_buffer_mut, translated_ref, translated_refmut, translated_str, BadAddress,
+    PageTable, PageTableEntry, UserBuffer, UserBufferIterator,
 }; // 页表相关操作、用户缓冲区与迭代器
 
 /// 初始化堆分配器、帧分配器和内核空间
@@ -26,4 +44,13 @@ pub fn init() {
     heap_allocator::init_heap(); // 初始化堆分配器
     frame_allocator::init_frame_allocator(); // 初始化帧分配器
     KERNEL_SPACE.exclusive_access().activate(); // 激活内核空间
+    let frames = frame_allocator::stats();
+    let heap = heap_stats();
+    info!(
+        "启动时占用物理页帧 {} / {}，内核堆 {} / {} 字节",
+        frames.total - frames.free,
+        frames.total,
+        heap.allocated,
+        heap.total
+    );
 }
@@ -0,0 +1,72 @@
+//! 只读文件页缓存
+//!
+//! 同一个程序的多个实例共享其代码段和只读数据段的物理页帧：
+//! 不可写的文件映射在缺页时先按 (文件首簇号, 页起始处的文件偏移) 查找缓存，
+//! 命中则直接映射缓存的页帧，否则读入新页帧后放入缓存。
+//! 文件被写入、截断或删除时丢弃其全部缓存页，已经映射这些页帧的进程不受影响。
+
+use super::frame_allocator::{self, frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use fat32::VFile;
+use lazy_static::*;
+
+/// 空闲页帧少于总数的 1/LOW_FREE_RATIO 时不再缓存新页面，并回收无人映射的缓存页
+const LOW_FREE_RATIO: usize = 16;
+
+/// 缓存键：(文件首簇号, 页起始处的文件偏移)
+pub type PageCacheKey = (u32, usize);
+
+lazy_static! {
+    /// 全局只读文件页缓存
+    static ref PAGE_CACHE: UPSafeCell<BTreeMap<PageCacheKey, FrameTracker>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// 查找缓存的页帧
+pub fn page_cache_lookup(key: PageCacheKey) -> Option<FrameTracker> {
+    PAGE_CACHE
+        .exclusive_access()
+        .get(&key)
+        .map(FrameTracker::clone_shared)
+}
+
+/// 将已填充文件内容的页帧放入缓存，内存紧张时放弃缓存并回收无人映射的缓存页
+pub fn page_cache_insert(key: PageCacheKey, frame: FrameTracker) {
+    let stats = frame_allocator::stats();
+    if stats.free < stats.total / LOW_FREE_RATIO {
+        page_cache_shrink();
+        return;
+    }
+    PAGE_CACHE.exclusive_access().insert(key, frame);
+}
+
+/// 回收只被缓存引用的页帧，返回回收的页数
+pub fn page_cache_shrink() -> usize {
+    let mut cache = PAGE_CACHE.exclusive_access();
+    let before = cache.len();
+    cache.retain(|_, frame| frame.ref_count() > 1);
+    before - cache.len()
+}
+
+/// 丢弃文件 `file` 的全部缓存页，在文件内容改变或文件被删除前调用
+pub fn page_cache_invalidate(file: &VFile) {
+    // 读不出目录项时缓存页也无法按首簇号查找，只能跳过
+    let cluster = file.first_cluster().unwrap_or(0);
+    if cluster == 0 {
+        return; // 空文件不会有缓存页
+    }
+    PAGE_CACHE
+        .exclusive_access()
+        .retain(|&(c, _), _| c != cluster);
+}
+
+/// 分配一个物理页帧，失败时先回收无人映射的缓存页再重试
+pub fn frame_alloc_or_evict() -> Option<FrameTracker> {
+    frame_alloc().or_else(|| {
+        if page_cache_shrink() == 0 {
+            return None;
+        }
+        frame_alloc()
+    })
+}
@@ -1,224 +1,221 @@
-//! 实现 [`PageTableEntry`] 和 [`PageTable`]。
+//! 内核使用的页表，以及把用户缓冲区翻译为内核可以直接访问的切片。
+//!
+//! 页表项和三级页表的建立、查找位于 [`sv39`] crate 中，可以在主机上测试；
+//! 内核的 [`PageTable`] 从帧分配器分配页表页，通过恒等映射直接访问它们，并为每个页表分配 ASID。
 
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::asid::{asid_alloc, kernel_asid, AsidHandle};
+use super::swap::evictions;
+use super::{frame_alloc, FrameTracker, OutOfMemory, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::config::PAGE_SIZE;
+use crate::syscall::{Errno, EFAULT, ENAMETOOLONG};
+use crate::task::handle_page_fault;
 use alloc::string::String;
-use alloc::vec;
 use alloc::vec::Vec;
-use bitflags::*;
-
-bitflags! {
-    /// 页表项标志
-    pub struct PTEFlags: u8 {
-        const V = 1 << 0;  // 有效位
-        const R = 1 << 1;  // 可读位
-        const W = 1 << 2;  // 可写位
-        const X = 1 << 3;  // 可执行位
-        const U = 1 << 4;  // 用户态访问位
-        const G = 1 << 5;  // 全局位
-        const A = 1 << 6;  // 已访问位
-        const D = 1 << 7;  // 已修改位
-    }
-}
+use core::ops::{Deref, DerefMut};
+use riscv::asm::sfence_vma;
+use sv39::FrameSource;
 
-#[derive(Copy, Clone)]
-#[repr(C)]
-/// 页表项结构
-pub struct PageTableEntry {
-    /// 页表项的比特位
-    pub bits: usize,
-}
+pub use sv39::{BadAddress, PTEFlags, PageTableEntry, HUGE_PAGE_PAGES, USER_SPACE_END};
 
-impl PageTableEntry {
-    /// 创建新的页表项
-    pub fn new(ppn: PhysPageNum, flags: PTEFlags) -> Self {
-        PageTableEntry {
-            bits: ppn.0 << 10 | flags.bits as usize,
-        }
-    }
-    /// 创建空的页表项
-    pub fn empty() -> Self {
-        PageTableEntry { bits: 0 }
-    }
-    /// 从页表项获取物理页号
-    pub fn ppn(&self) -> PhysPageNum {
-        (self.bits >> 10 & ((1usize << 44) - 1)).into()
-    }
-    /// 从页表项获取标志位
-    pub fn flags(&self) -> PTEFlags {
-        PTEFlags::from_bits(self.bits as u8).unwrap()
-    }
-    /// 判断页表项指向的页面是否有效
-    pub fn is_valid(&self) -> bool {
-        (self.flags() & PTEFlags::V) != PTEFlags::empty()
+/// 内核页表的页帧来源：从帧分配器分配，物理内存恒等映射，可以直接访问
+pub struct KernelFrames;
+
+impl FrameSource for KernelFrames {
+    type Frame = FrameTracker;
+
+    fn alloc(&self) -> Option<FrameTracker> {
+        frame_alloc()
     }
-    /// 判断页表项指向的页面是否可读
-    pub fn readable(&self) -> bool {
-        (self.flags() & PTEFlags::R) != PTEFlags::empty()
+
+    fn ppn(frame: &FrameTracker) -> PhysPageNum {
+        frame.ppn
     }
-    /// 判断页表项指向的页面是否可写
-    pub fn writable(&self) -> bool {
-        (self.flags() & PTEFlags::W) != PTEFlags::empty()
+
+    fn frame_ptr(&self, ppn: PhysPageNum) -> *mut u8 {
+        PhysAddr::from(ppn).0 as *mut u8
     }
-    /// 判断页表项指向的页面是否可执行
-    pub fn executable(&self) -> bool {
-        (self.flags() & PTEFlags::X) != PTEFlags::empty()
+
+    fn flush(&self, asid: usize, vpn: VirtPageNum) {
+        let va: VirtAddr = vpn.into();
+        unsafe {
+            sfence_vma(asid, va.0);
+        }
     }
 }
 
-/// 页表结构
+/// 页表结构：使用内核页帧的 [`sv39::PageTable`]，以及它持有的 ASID
 pub struct PageTable {
-    root_ppn: PhysPageNum,      // 根物理页号
-    frames: Vec<FrameTracker>, // 页框的跟踪器
+    inner: sv39::PageTable<KernelFrames>,
+    _asid_handle: Option<AsidHandle>, // 持有的 ASID，随页表释放而回收；从 token 构造的页表不持有
 }
 
-/// 假设创建/映射时不会发生内存不足。
+/// 创建/映射时物理内存不足会返回 [`OutOfMemory`]。
 impl PageTable {
     /// 创建新的页表
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
-            root_ppn: frame.ppn,
-            frames: vec![frame],
-        }
+    pub fn new() -> Result<Self, OutOfMemory> {
+        let asid_handle = asid_alloc();
+        Ok(PageTable {
+            inner: sv39::PageTable::new(KernelFrames, asid_handle.0)?,
+            _asid_handle: Some(asid_handle),
+        })
+    }
+    /// 创建内核地址空间的页表，使用保留的内核 ASID，不占用用户地址空间的 ASID
+    pub fn new_kernel() -> Result<Self, OutOfMemory> {
+        Ok(PageTable {
+            inner: sv39::PageTable::new(KernelFrames, kernel_asid())?,
+            _asid_handle: None,
+        })
     }
     /// 用于从用户空间获取参数
     pub fn from_token(satp: usize) -> Self {
         Self {
-            root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
-            frames: Vec::new(),
-        }
-    }
-    /// 根据虚拟页号查找页表项，如果不存在则为4KB页表创建一个框架
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
-        let idxs = vpn.indexes();
-        let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
-        for (i, idx) in idxs.iter().enumerate() {
-            let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
-            }
-            if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
-                *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
-                self.frames.push(frame);
-            }
-            ppn = pte.ppn();
-        }
-        result
-    }
-    /// 根据虚拟页号查找页表项
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
-        let idxs = vpn.indexes();
-        let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
-        for (i, idx) in idxs.iter().enumerate() {
-            let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
-            }
-            if !pte.is_valid() {
-                return None;
-            }
-            ppn = pte.ppn();
+            inner: sv39::PageTable::from_token(satp, KernelFrames),
+            _asid_handle: None,
         }
-        result
-    }
-    /// 设置虚拟页号与物理页号之间的映射
-    #[allow(unused)]
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
-        assert!(!pte.is_valid(), "vpn {:?} 在映射之前已经映射", vpn);
-        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
-    /// 移除虚拟页号与物理页号之间的映射
-    #[allow(unused)]
-    pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte(vpn).unwrap();
-        assert!(pte.is_valid(), "vpn {:?} 在取消映射之前无效", vpn);
-        *pte = PageTableEntry::empty();
+    /// 立即释放页表自身占用的物理页帧并归还 ASID，此后不能再使用该页表
+    pub fn recycle(&mut self) {
+        self.inner.recycle();
+        self._asid_handle = None;
     }
-    /// 从虚拟页号获取页表项
-    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
-    }
-    /// 从虚拟地址获取物理地址
-    pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
-            let aligned_pa: PhysAddr = pte.ppn().into();
-            let offset = va.page_offset();
-            let aligned_pa_usize: usize = aligned_pa.into();
-            (aligned_pa_usize + offset).into()
-        })
+}
+
+impl Deref for PageTable {
+    type Target = sv39::PageTable<KernelFrames>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
     }
-    /// 从页表获取 token
-    pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+}
+
+impl DerefMut for PageTable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
     }
 }
 
-/// 通过页表将一个 `ptr[u8]` 数组（长度为 `len`）翻译并复制到一个可变的 `u8` 向量
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+/// 将用户虚拟地址翻译为物理地址；若该页属于尚未加载的惰性区域、已被换出，
+/// 或者要写入的页面当前映射为只读的全零页，先为其处理缺页。
+/// 其余的检查见 [`sv39::PageTable::translate_user`]
+fn translate_user_va(page_table: &PageTable, va: usize, write: bool) -> Result<PhysAddr, BadAddress> {
+    page_table.translate_user(va, write, handle_page_fault)
+}
+
+/// 将用户空间 [ptr, ptr + len) 按页切分为若干物理内存切片。
+/// 翻译后面的页面时可能为了腾出页帧换出前面已经翻译的页面，此时重新翻译整个缓冲区。
+fn translate_user_buffer(
+    token: usize,
+    ptr: usize,
+    len: usize,
+    write: bool,
+) -> Result<Vec<&'static mut [u8]>, BadAddress> {
+    loop {
+        let before = evictions();
+        let buffers = translate_user_pages(token, ptr, len, write)?;
+        if evictions() == before {
+            return Ok(buffers);
         }
-        start = end_va.into();
     }
-    v
 }
 
-/// 通过页表将一个以 `\0` 结尾的 `ptr[u8]` 数组翻译为一个 `String`
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// 逐页翻译用户空间 [ptr, ptr + len)
+fn translate_user_pages(
+    token: usize,
+    ptr: usize,
+    len: usize,
+    write: bool,
+) -> Result<Vec<&'static mut [u8]>, BadAddress> {
     let page_table = PageTable::from_token(token);
+    let ranges = page_table.translate_user_range(ptr, len, write, handle_page_fault)?;
+    Ok(ranges
+        .into_iter()
+        .map(|(pa, len)| &mut pa.floor().get_bytes_array()[pa.page_offset()..pa.page_offset() + len])
+        .collect())
+}
+
+/// 通过页表将一个 `ptr[u8]` 数组（长度为 `len`）翻译为若干切片，内核只会读取其中的内容
+pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Result<Vec<&'static mut [u8]>, BadAddress> {
+    translate_user_buffer(token, ptr as usize, len, false)
+}
+
+/// 与 [`translated_byte_buffer`] 相同，但要求用户缓冲区可写，用于内核向用户写入数据
+pub fn translated_byte_buffer_mut(token: usize, ptr: *mut u8, len: usize) -> Result<Vec<&'static mut [u8]>, BadAddress> {
+    translate_user_buffer(token, ptr as usize, len, true)
+}
+
+/// 通过页表将一个以 `\0` 结尾的 `ptr[u8]` 数组翻译为一个 `String`，每次翻译一整页。
+/// 包括结尾的 `\0` 在内最多读取 `max_len` 字节，超出时返回 `ENAMETOOLONG`，
+/// 访问到无效地址时返回 `EFAULT`。
+pub fn translated_str(token: usize, ptr: *const u8, max_len: usize) -> Result<String, Errno> {
     let mut string = String::new();
     let mut va = ptr as usize;
-    loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
+    let mut read = 0;
+    while read < max_len {
+        // 读到当前页的末尾为止
+        let len = (PAGE_SIZE - va % PAGE_SIZE).min(max_len - read);
+        let buffers = translate_user_buffer(token, va, len, false).map_err(|_| EFAULT)?;
+        for &ch in buffers.iter().flat_map(|buffer| buffer.iter()) {
+            if ch == 0 {
+                return Ok(string);
+            }
+            string.push(ch as char);
         }
-        string.push(ch as char);
-        va += 1;
+        va += len;
+        read += len;
     }
-    string
+    Err(ENAMETOOLONG)
 }
 
 #[allow(unused)]
 /// 通过页表将一个 `ptr[u8]` 数组翻译为 `T` 类型的引用
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Result<&'static T, BadAddress> {
     let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_ref()
+    Ok(translate_user_va(&page_table, ptr as usize, false)?.get_ref())
 }
-/// 通过页表将一个 `ptr[u8]` 数组翻译为 `T` 类型的可变引用
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+/// 通过页表将一个 `ptr[u8]` 数组翻译为 `T` 类型的可变引用，要求该页可写
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, BadAddress> {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    Ok(translate_user_va(&page_table, ptr as usize, true)?.get_mut())
+}
+
+/// 将内核数据 `src` 复制到用户空间 `dst` 处，可以跨越多个页面，返回复制的字节数
+pub fn copy_to_user(token: usize, dst: *mut u8, src: &[u8]) -> Result<usize, BadAddress> {
+    let mut copied = 0;
+    for buffer in translated_byte_buffer_mut(token, dst, src.len())? {
+        let len = buffer.len();
+        buffer.copy_from_slice(&src[copied..copied + len]);
+        copied += len;
+    }
+    Ok(copied)
+}
+
+/// 将用户空间 `src` 处的数据复制到内核缓冲区 `dst`，可以跨越多个页面，返回复制的字节数
+pub fn copy_from_user(token: usize, src: *const u8, dst: &mut [u8]) -> Result<usize, BadAddress> {
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(token, src, dst.len())? {
+        let len = buffer.len();
+        dst[copied..copied + len].copy_from_slice(buffer);
+        copied += len;
+    }
+    Ok(copied)
+}
+
+/// 将 `value` 写入用户空间 `dst` 处，`dst` 不要求对齐，也可以跨越页面边界
+pub fn put_user<T: Copy>(token: usize, dst: *mut T, value: T) -> Result<(), BadAddress> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(token, dst as *mut u8, bytes).map(|_| ())
+}
+
+/// 从用户空间 `src` 处读取一个 `T`，`src` 不要求对齐，也可以跨越页面边界
+pub fn get_user<T: Copy>(token: usize, src: *const T) -> Result<T, BadAddress> {
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, core::mem::size_of::<T>())
+    };
+    copy_from_user(token, src as *const u8, bytes)?;
+    Ok(unsafe { value.assume_init() })
 }
 
 /// 一个抽象结构，用于表示从用户空间传递到内核空间的缓冲区
@@ -247,6 +244,54 @@ impl UserBuffer {
         }
         total
     }
+
+    /// 从缓冲区开头写入 `data`，返回实际写入的字节数
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        self.write_at(0, data)
+    }
+
+    /// 从缓冲区的第 `offset` 个字节开始写入 `data`，超出缓冲区的部分被截断，
+    /// 返回实际写入的字节数
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> usize {
+        let mut skip = offset;
+        let mut written = 0;
+        for buffer in self.buffers.iter_mut() {
+            if written == data.len() {
+                break;
+            }
+            if skip >= buffer.len() {
+                skip -= buffer.len();
+                continue;
+            }
+            let dst = &mut buffer[skip..];
+            let n = dst.len().min(data.len() - written);
+            dst[..n].copy_from_slice(&data[written..written + n]);
+            written += n;
+            skip = 0;
+        }
+        written
+    }
+
+    /// 用 `byte` 填满整个缓冲区
+    pub fn fill(&mut self, byte: u8) {
+        for buffer in self.buffers.iter_mut() {
+            buffer.fill(byte);
+        }
+    }
+
+    /// 从缓冲区开头读出最多 `out.len()` 个字节，返回实际读出的字节数
+    pub fn read(&self, out: &mut [u8]) -> usize {
+        let mut read = 0;
+        for buffer in self.buffers.iter() {
+            if read == out.len() {
+                break;
+            }
+            let n = buffer.len().min(out.len() - read);
+            out[read..read + n].copy_from_slice(&buffer[..n]);
+            read += n;
+        }
+        read
+    }
 }
 
 impl IntoIterator for UserBuffer {
@@ -0,0 +1,107 @@
+//! 进程间共享内存
+//!
+//! 共享内存段由一组物理页帧组成，映射它的每个区域都持有段的一个 `Arc`，
+//! 最后一个映射消失（并且段已不在注册表中）时页帧被回收。
+//! 段可以通过 `mmap(MAP_SHARED | MAP_ANONYMOUS)` 匿名创建，在 fork 后由父子进程共享；
+//! 也可以通过 System V 风格的 shmget/shmat 按 key 在任意进程间共享。
+
+use super::{frame_alloc, FrameTracker, OutOfMemory, PhysPageNum};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, EEXIST, EINVAL, ENOENT, ENOMEM};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// 私有段的 key，每次 shmget 都创建新段
+pub const IPC_PRIVATE: usize = 0;
+/// key 对应的段不存在时创建
+pub const IPC_CREAT: usize = 0o1000;
+/// 与 `IPC_CREAT` 一起使用，key 对应的段已存在时报错
+pub const IPC_EXCL: usize = 0o2000;
+
+/// 共享内存段
+pub struct SharedSegment {
+    size: usize,               // 创建时请求的字节数
+    frames: Vec<FrameTracker>, // 段内各页对应的物理页帧
+}
+
+impl SharedSegment {
+    /// 分配一个 `size` 字节的共享内存段，页面内容为零
+    pub fn new(size: usize) -> Result<Arc<Self>, OutOfMemory> {
+        let page_count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let mut frames = Vec::with_capacity(page_count);
+        for _ in 0..page_count {
+            frames.push(frame_alloc().ok_or(OutOfMemory)?);
+        }
+        Ok(Arc::new(Self { size, frames }))
+    }
+    /// 段占用的页面数
+    pub fn page_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// 段内第 `page` 页的物理页号
+    pub fn ppn(&self, page: usize) -> PhysPageNum {
+        self.frames[page].ppn
+    }
+}
+
+/// System V 共享内存段的注册表
+struct ShmRegistry {
+    next_id: usize,                                     // 下一个段标识符
+    segments: BTreeMap<usize, (usize, Arc<SharedSegment>)>, // 段标识符到 (key, 段) 的映射
+}
+
+lazy_static! {
+    /// 全局共享内存注册表
+    static ref SHM_REGISTRY: UPSafeCell<ShmRegistry> = unsafe {
+        UPSafeCell::new(ShmRegistry {
+            next_id: 1,
+            segments: BTreeMap::new(),
+        })
+    };
+}
+
+/// 按 `key` 查找或创建大小至少为 `size` 字节的段，返回段标识符
+pub fn shm_get(key: usize, size: usize, flags: usize) -> Result<usize, Errno> {
+    let mut registry = SHM_REGISTRY.exclusive_access();
+    if key != IPC_PRIVATE {
+        if let Some((&id, (_, segment))) =
+            registry.segments.iter().find(|(_, (k, _))| *k == key)
+        {
+            if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                return Err(EEXIST);
+            }
+            if size > segment.size {
+                return Err(EINVAL);
+            }
+            return Ok(id);
+        }
+        if flags & IPC_CREAT == 0 {
+            return Err(ENOENT);
+        }
+    }
+    if size == 0 {
+        return Err(EINVAL);
+    }
+    let segment = SharedSegment::new(size).map_err(|_| ENOMEM)?;
+    let id = registry.next_id;
+    registry.next_id += 1;
+    registry.segments.insert(id, (key, segment));
+    Ok(id)
+}
+
+/// 获取标识符为 `id` 的段
+pub fn shm_find(id: usize) -> Option<Arc<SharedSegment>> {
+    SHM_REGISTRY
+        .exclusive_access()
+        .segments
+        .get(&id)
+        .map(|(_, segment)| segment.clone())
+}
+
+/// 从注册表中移除标识符为 `id` 的段，已有的映射保持有效，最后一个映射解除后段被回收
+pub fn shm_remove(id: usize) -> bool {
+    SHM_REGISTRY.exclusive_access().segments.remove(&id).is_some()
+}
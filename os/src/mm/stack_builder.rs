@@ -0,0 +1,129 @@
+//! 新程序初始用户栈的构造
+//!
+//! 按照 RISC-V Linux ABI 的约定布置初始栈，从高地址到低地址依次为：
+//! 主线程的 TLS 块（程序有 PT_TLS 段时）、参数和环境变量字符串、AT_RANDOM 指向的 16 个随机字节，
+//! 然后是 16 字节对齐的 auxv 键值对、envp 指针数组、argv 指针数组，
+//! 最后 sp 处存放 argc。
+
+use super::memory_set::{ElfInfo, ExecError, TlsTemplate};
+use super::page_table::copy_to_user;
+use crate::config::{PAGE_SIZE, USER_STACK_SIZE};
+use crate::timer::get_time;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// auxv 表项的类型
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// 在新地址空间已映射的用户栈上自顶向下写入数据
+pub struct StackBuilder {
+    token: usize,  // 新地址空间的页表
+    sp: usize,     // 当前栈顶
+    bottom: usize, // 已映射的栈底，写入不能越过这里
+}
+
+impl StackBuilder {
+    /// 从栈顶 `user_stack_top` 开始构造，只使用初始映射的 `USER_STACK_SIZE` 字节
+    pub fn new(token: usize, user_stack_top: usize) -> Self {
+        Self {
+            token,
+            sp: user_stack_top,
+            bottom: user_stack_top - USER_STACK_SIZE,
+        }
+    }
+
+    /// 将 `data` 压入栈中，起始地址按 `align` 对齐，返回其地址
+    fn push_bytes(&mut self, data: &[u8], align: usize) -> Result<usize, ExecError> {
+        let sp = self
+            .sp
+            .checked_sub(data.len())
+            .map(|sp| sp & !(align - 1))
+            .filter(|&sp| sp >= self.bottom)
+            .ok_or(ExecError::TooBig)?;
+        copy_to_user(self.token, sp as *mut u8, data).map_err(|_| ExecError::TooBig)?;
+        self.sp = sp;
+        Ok(sp)
+    }
+
+    /// 将以 `\0` 结尾的字符串压入栈中，返回其地址
+    fn push_str(&mut self, s: &str) -> Result<usize, ExecError> {
+        let mut bytes = Vec::with_capacity(s.len() + 1);
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.push(0);
+        self.push_bytes(&bytes, 1)
+    }
+
+    /// 按模板 `tls` 压入主线程的 TLS 块，返回块的起始地址。
+    /// RISC-V 的 TLS 采用 variant I 布局，且线程控制块大小为 0，tp 直接指向 TLS 块的起始处。
+    /// 模板在创建时已经检查过，这里只检查剩余的栈空间，放不下时不分配整个块
+    pub fn push_tls(&mut self, tls: &TlsTemplate) -> Result<usize, ExecError> {
+        if tls.mem_size > self.sp - self.bottom {
+            return Err(ExecError::TooBig);
+        }
+        let mut block = vec![0u8; tls.mem_size];
+        block[..tls.image.len()].copy_from_slice(&tls.image);
+        self.push_bytes(&block, tls.align.max(size_of::<usize>()))
+    }
+
+    /// 写入参数、环境变量和 auxv，返回新程序入口处的 sp
+    pub fn build(mut self, args: &[String], envs: &[String], elf: &ElfInfo) -> Result<usize, ExecError> {
+        let mut env_ptrs = Vec::with_capacity(envs.len());
+        for env in envs.iter().rev() {
+            env_ptrs.push(self.push_str(env)?);
+        }
+        env_ptrs.reverse();
+        let mut arg_ptrs = Vec::with_capacity(args.len());
+        for arg in args.iter().rev() {
+            arg_ptrs.push(self.push_str(arg)?);
+        }
+        arg_ptrs.reverse();
+        let random = self.push_bytes(&random_bytes(), 16)?;
+        let auxv = [
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_PHDR, elf.phdr),
+            (AT_PHENT, elf.phent),
+            (AT_PHNUM, elf.phnum),
+            (AT_ENTRY, elf.entry),
+            (AT_RANDOM, random),
+            (AT_NULL, 0),
+        ];
+        // argc、argv（含结尾的空指针）、envp（含结尾的空指针）、auxv
+        let mut words = Vec::with_capacity(3 + args.len() + envs.len() + 2 * auxv.len());
+        words.push(args.len());
+        words.extend_from_slice(&arg_ptrs);
+        words.push(0);
+        words.extend_from_slice(&env_ptrs);
+        words.push(0);
+        for (key, value) in auxv {
+            words.push(key);
+            words.push(value);
+        }
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        debug_assert_eq!(bytes.len(), words.len() * size_of::<usize>());
+        self.push_bytes(&bytes, 16)
+    }
+}
+
+/// AT_RANDOM 使用的 16 个随机字节，由时钟计数经 splitmix64 混合得到
+fn random_bytes() -> [u8; 16] {
+    let mut state = get_time() as u64;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&next().to_le_bytes());
+    bytes[8..].copy_from_slice(&next().to_le_bytes());
+    bytes
+}
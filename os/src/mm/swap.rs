@@ -0,0 +1,113 @@
+//! 交换空间
+//!
+//! 物理内存不足时，缺页处理按时钟（二次机会）算法从当前地址空间中选出一个页面换出：
+//! 未修改过的文件页直接丢弃，其余页面写入 FAT 卷上预留的交换文件，
+//! 页表项被标记为已换出并记录交换槽位，再次访问时从交换文件读回。
+//! 交换文件只在启用 `swap` 特性时于启动阶段创建，未创建时不会换出任何页面。
+
+use super::PhysPageNum;
+use crate::drivers::poll_io;
+use crate::config::{PAGE_SIZE, SWAP_SIZE};
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use fat32::VFile;
+use lazy_static::*;
+
+/// 交换文件及其槽位分配位图
+struct SwapMap {
+    file: Arc<VFile>, // 交换文件
+    bitmap: Vec<u64>, // 第 i 位表示第 i 个槽位已被占用
+}
+
+impl SwapMap {
+    /// 分配一个空闲槽位
+    fn alloc(&mut self) -> Option<usize> {
+        let (word, bits) = self
+            .bitmap
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != u64::MAX)?;
+        let bit = (!*bits).trailing_zeros() as usize;
+        *bits |= 1 << bit;
+        Some(word * 64 + bit)
+    }
+    /// 回收槽位 `slot`
+    fn dealloc(&mut self, slot: usize) {
+        let (word, bit) = (slot / 64, slot % 64);
+        assert!(
+            self.bitmap[word] & (1 << bit) != 0,
+            "swap slot {} has not been allocated",
+            slot
+        );
+        self.bitmap[word] &= !(1 << bit);
+    }
+}
+
+lazy_static! {
+    /// 全局交换空间，未初始化时为 `None`
+    static ref SWAP_MAP: UPSafeCell<Option<SwapMap>> = unsafe { UPSafeCell::new(None) };
+    /// 到目前为止换出的页面总数
+    static ref EVICTIONS: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
+}
+
+/// 使用 `file` 作为交换文件，预先为全部 `SWAP_SIZE / PAGE_SIZE` 个槽位分配磁盘空间
+pub fn swap_init(file: Arc<VFile>) {
+    file.write_at(SWAP_SIZE - PAGE_SIZE, &vec![0u8; PAGE_SIZE])
+        .expect("failed to allocate the swap file");
+    let slots = SWAP_SIZE / PAGE_SIZE;
+    *SWAP_MAP.exclusive_access() = Some(SwapMap {
+        file,
+        bitmap: vec![0; slots / 64],
+    });
+}
+
+/// 是否已经初始化交换空间
+pub fn swap_enabled() -> bool {
+    SWAP_MAP.exclusive_access().is_some()
+}
+
+/// 交换文件中的一个槽位，被释放时自动归还
+pub struct SwapSlot(usize);
+
+impl SwapSlot {
+    /// 分配一个槽位并写入物理页 `ppn` 的内容，未启用交换、交换文件已满或写入失败时返回 `None`
+    pub fn write(ppn: PhysPageNum) -> Option<Self> {
+        let mut swap = SWAP_MAP.exclusive_access();
+        let swap = swap.as_mut()?;
+        let slot = swap.alloc()?;
+        // 换出可能发生在持有其他进程地址空间的时候，不睡眠等待磁盘
+        if poll_io(|| swap.file.write_at(slot * PAGE_SIZE, ppn.get_bytes_array())).is_err() {
+            swap.dealloc(slot);
+            return None;
+        }
+        Some(Self(slot))
+    }
+    /// 将槽位中保存的页面读入物理页 `ppn`。换出的页面只有这一份，读不回来时无法继续运行
+    pub fn read(&self, ppn: PhysPageNum) {
+        let swap = SWAP_MAP.exclusive_access();
+        poll_io(|| swap.as_ref().unwrap().file.read_at(self.0 * PAGE_SIZE, ppn.get_bytes_array()))
+            .expect("failed to read a page back from the swap file");
+    }
+    /// 槽位编号，记录在已换出页面的页表项中
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_MAP.exclusive_access().as_mut().unwrap().dealloc(self.0);
+    }
+}
+
+/// 记录一次页面换出
+pub fn record_eviction() {
+    *EVICTIONS.exclusive_access() += 1;
+}
+
+/// 到目前为止换出的页面总数，内核据此判断已经翻译的用户缓冲区是否可能失效
+pub fn evictions() -> usize {
+    *EVICTIONS.exclusive_access()
+}
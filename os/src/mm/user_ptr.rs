@@ -0,0 +1,84 @@
+//! 经过预先检查的用户指针
+//!
+//! 系统调用在访问用户内存之前先构造 [`UserPtr`] 或 [`UserSlice`]：
+//! 构造时检查整个字节范围都位于用户地址空间内、已映射且具有所需的权限，并按要求对齐，
+//! 否则返回 `EFAULT`。之后的读写通过 [`copy_to_user`] 等函数完成，可以跨越页面边界。
+
+use super::page_table::{
+    copy_from_user, copy_to_user, get_user, put_user, translated_byte_buffer,
+    translated_byte_buffer_mut,
+};
+use crate::syscall::{Errno, EFAULT};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+
+/// 检查用户空间 [ptr, ptr + len) 按 `align` 对齐且整体可访问（`write` 为真时要求可写）
+fn check_user_range(token: usize, ptr: usize, len: usize, align: usize, write: bool) -> Result<(), Errno> {
+    if ptr % align != 0 {
+        return Err(EFAULT);
+    }
+    let result = if write {
+        translated_byte_buffer_mut(token, ptr as *mut u8, len)
+    } else {
+        translated_byte_buffer(token, ptr as *const u8, len)
+    };
+    result.map(|_| ()).map_err(|_| EFAULT)
+}
+
+/// 指向用户空间中一个 `T` 的指针，构造时已检查映射、权限和对齐
+pub struct UserPtr<T> {
+    token: usize,
+    ptr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// 检查 `ptr` 指向的 `T` 可读
+    pub fn readable(token: usize, ptr: *const T) -> Result<Self, Errno> {
+        check_user_range(token, ptr as usize, size_of::<T>(), align_of::<T>(), false)?;
+        Ok(Self { token, ptr: ptr as usize, _marker: PhantomData })
+    }
+    /// 检查 `ptr` 指向的 `T` 可写
+    pub fn writable(token: usize, ptr: *mut T) -> Result<Self, Errno> {
+        check_user_range(token, ptr as usize, size_of::<T>(), align_of::<T>(), true)?;
+        Ok(Self { token, ptr: ptr as usize, _marker: PhantomData })
+    }
+    /// 读取用户空间中的值
+    pub fn read(&self) -> Result<T, Errno> {
+        get_user(self.token, self.ptr as *const T).map_err(|_| EFAULT)
+    }
+    /// 将 `value` 写入用户空间
+    pub fn write(&self, value: T) -> Result<(), Errno> {
+        put_user(self.token, self.ptr as *mut T, value).map_err(|_| EFAULT)
+    }
+}
+
+/// 用户空间中的一段字节缓冲区，构造时已检查映射、权限和对齐
+pub struct UserSlice {
+    token: usize,
+    ptr: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    /// 检查 [ptr, ptr + len) 可读且 `ptr` 按 `align` 对齐
+    pub fn readable(token: usize, ptr: *const u8, len: usize, align: usize) -> Result<Self, Errno> {
+        check_user_range(token, ptr as usize, len, align, false)?;
+        Ok(Self { token, ptr: ptr as usize, len })
+    }
+    /// 检查 [ptr, ptr + len) 可写且 `ptr` 按 `align` 对齐
+    pub fn writable(token: usize, ptr: *mut u8, len: usize, align: usize) -> Result<Self, Errno> {
+        check_user_range(token, ptr as usize, len, align, true)?;
+        Ok(Self { token, ptr: ptr as usize, len })
+    }
+    /// 将缓冲区开头的 `out.len()` 字节读入 `out`，超出缓冲区的部分不读取，返回读取的字节数
+    pub fn read(&self, out: &mut [u8]) -> Result<usize, Errno> {
+        let len = out.len().min(self.len);
+        copy_from_user(self.token, self.ptr as *const u8, &mut out[..len]).map_err(|_| EFAULT)
+    }
+    /// 将 `data` 写入缓冲区开头，超出缓冲区的部分被截断，返回写入的字节数
+    pub fn write_slice(&self, data: &[u8]) -> Result<usize, Errno> {
+        let len = data.len().min(self.len);
+        copy_to_user(self.token, self.ptr as *mut u8, &data[..len]).map_err(|_| EFAULT)
+    }
+}
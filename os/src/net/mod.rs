@@ -0,0 +1,305 @@
+//! 最小的 IPv4 + UDP 协议栈
+//!
+//! 每个网卡对应一个接口：回环网卡是 lo（127.0.0.1/8），第一块 virtio 网卡是 eth0，
+//! 它的地址来自启动参数 `ip=<地址>[:<服务器>:<网关>:<掩码>]`（与 Linux 的 ip= 参数前几项相同），
+//! 没有这个参数时使用 QEMU 用户网络分配给客户机的 10.0.2.15/24。
+//!
+//! 协议栈只实现 UDP 回显需要的部分：以太网帧中只处理 ARP 和 IPv4；ARP 只回答询问本机地址的请求，
+//! 不主动发出请求，发往的地址的 MAC 从收到的帧中学习，未知时广播；IPv4 不支持分片，
+//! 收到的分片被丢弃，发送的数据报不超过一个以太网帧；IPv4 之上只有 UDP，见 [`udp`]。
+//!
+//! 网卡中断处理完后调用 [`poll`] 取出收到的帧，发往回环网卡的帧在发送后立即处理。
+
+mod udp;
+
+pub use udp::{udp_loopback_test, UdpSocket, UDP_MAX_PAYLOAD};
+
+use crate::drivers::dtb::bootarg;
+use crate::drivers::net::{NetDevice, LOOPBACK, MAX_FRAME_LEN, NET_DEVICES};
+use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, ENETUNREACH};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use lazy_static::*;
+
+/// IPv4 地址，按主机字节序保存，例如 127.0.0.1 是 0x7f00_0001
+pub type Ipv4Addr = u32;
+
+/// 回环地址 127.0.0.1
+pub const LOOPBACK_ADDR: Ipv4Addr = 0x7f00_0001;
+/// 本地广播地址 255.255.255.255
+pub const BROADCAST_ADDR: Ipv4Addr = 0xffff_ffff;
+/// 没有启动参数时 eth0 的地址，QEMU 用户网络分配给客户机的地址
+const DEFAULT_ADDR: Ipv4Addr = 0x0a00_020f;
+const DEFAULT_NETMASK: Ipv4Addr = 0xffff_ff00;
+
+const ETH_HDR_LEN: usize = 14;
+const ETH_TYPE_IPV4: u16 = 0x0800;
+const ETH_TYPE_ARP: u16 = 0x0806;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+const ARP_LEN: usize = 28;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const IPV4_HDR_LEN: usize = 20;
+const IP_PROTO_UDP: u8 = 17;
+/// IPv4 头部的 Don't Fragment 标志
+const IP_DF: u16 = 0x4000;
+/// 分片偏移和 More Fragments 标志，不为 0 表示这是一个分片
+const IP_FRAGMENT_MASK: u16 = 0x3fff;
+const IP_TTL: u8 = 64;
+/// 一个 IPv4 数据报的最大长度，不分片时受以太网帧长度限制
+pub const IP_MAX_LEN: usize = MAX_FRAME_LEN - ETH_HDR_LEN;
+
+/// 一个网络接口：网卡和它的 IPv4 地址
+struct Interface {
+    name: &'static str,
+    device: Arc<dyn NetDevice>,
+    addr: Ipv4Addr,
+    netmask: Ipv4Addr,
+}
+
+impl Interface {
+    fn is_loopback(&self) -> bool {
+        self.name == "lo"
+    }
+
+    /// 发往 `dst` 的数据报是否由本接口接收
+    fn accepts(&self, dst: Ipv4Addr) -> bool {
+        if self.is_loopback() {
+            return is_local(dst);
+        }
+        dst == self.addr || dst == BROADCAST_ADDR || dst == self.addr | !self.netmask
+    }
+}
+
+lazy_static! {
+    /// 所有网络接口，第一个是 lo
+    static ref INTERFACES: Vec<Interface> = {
+        let mut interfaces = vec![Interface {
+            name: "lo",
+            device: LOOPBACK.clone(),
+            addr: LOOPBACK_ADDR,
+            netmask: 0xff00_0000,
+        }];
+        if let Some((_, device)) = NET_DEVICES.first() {
+            let (addr, netmask) = bootarg("ip").and_then(|arg| parse_ip_arg(&arg)).unwrap_or_else(|| {
+                warn!("no usable ip= boot argument, eth0 defaults to 10.0.2.15/24");
+                (DEFAULT_ADDR, DEFAULT_NETMASK)
+            });
+            interfaces.push(Interface { name: "eth0", device: device.clone(), addr, netmask });
+        }
+        interfaces
+    };
+    /// 从收到的帧中学到的 IPv4 地址到 MAC 地址的映射
+    static ref ARP_TABLE: UPSafeCell<BTreeMap<Ipv4Addr, [u8; 6]>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// 下一个发送的 IPv4 数据报的标识
+static NEXT_IP_ID: AtomicU16 = AtomicU16::new(1);
+
+/// 点分十进制的 IPv4 地址
+pub fn parse_ipv4(s: &str) -> Option<Ipv4Addr> {
+    let mut addr = 0u32;
+    let mut parts = 0;
+    for part in s.split('.') {
+        addr = addr << 8 | part.parse::<u8>().ok()? as u32;
+        parts += 1;
+    }
+    (parts == 4).then_some(addr)
+}
+
+/// 解析 ip= 启动参数，返回地址和掩码，掩码缺省为 /24
+fn parse_ip_arg(arg: &str) -> Option<(Ipv4Addr, Ipv4Addr)> {
+    let mut fields = arg.split(':');
+    let addr = parse_ipv4(fields.next()?)?;
+    let netmask = fields.nth(2).and_then(parse_ipv4).unwrap_or(DEFAULT_NETMASK);
+    Some((addr, netmask))
+}
+
+/// 用点分十进制格式化 IPv4 地址
+struct Dotted(Ipv4Addr);
+
+impl core::fmt::Display for Dotted {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let [a, b, c, d] = self.0.to_be_bytes();
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// 配置网络接口，在中断打开之前调用
+pub fn init() {
+    for interface in INTERFACES.iter() {
+        let prefix = interface.netmask.leading_ones();
+        info!("{}: {}/{}", interface.name, Dotted(interface.addr), prefix);
+    }
+}
+
+/// `addr` 是否是本机地址：127.0.0.0/8 或某个接口的地址
+pub fn is_local(addr: Ipv4Addr) -> bool {
+    addr >> 24 == 127 || INTERFACES.iter().any(|interface| interface.addr == addr)
+}
+
+/// 取出所有网卡收到的帧并交给协议栈处理
+pub fn poll() {
+    for interface in INTERFACES.iter() {
+        while let Some(frame) = interface.device.recv() {
+            receive_frame(interface, &frame);
+        }
+    }
+}
+
+/// 按 RFC 1071 计算 `data` 的反码和校验和，`initial` 是已经累加的部分（如 UDP 的伪头部）
+fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    for chunk in data.chunks(2) {
+        sum += u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]) as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn receive_frame(interface: &Interface, frame: &[u8]) {
+    if frame.len() < ETH_HDR_LEN {
+        return;
+    }
+    let src_mac: [u8; 6] = frame[6..12].try_into().unwrap();
+    let payload = &frame[ETH_HDR_LEN..];
+    match read_u16(frame, 12) {
+        ETH_TYPE_ARP => receive_arp(interface, payload),
+        ETH_TYPE_IPV4 => receive_ipv4(interface, src_mac, payload),
+        _ => {}
+    }
+}
+
+/// 回答询问本接口地址的 ARP 请求，并记下请求者的地址
+fn receive_arp(interface: &Interface, arp: &[u8]) {
+    if interface.is_loopback() || arp.len() < ARP_LEN {
+        return;
+    }
+    // 只处理以太网上的 IPv4 地址
+    if read_u16(arp, 0) != 1 || read_u16(arp, 2) != ETH_TYPE_IPV4 || arp[4] != 6 || arp[5] != 4 {
+        return;
+    }
+    let sender_mac: [u8; 6] = arp[8..14].try_into().unwrap();
+    let sender_ip = read_u32(arp, 14);
+    if read_u32(arp, 24) != interface.addr {
+        return;
+    }
+    ARP_TABLE.exclusive_access().insert(sender_ip, sender_mac);
+    if read_u16(arp, 6) != ARP_REQUEST {
+        return;
+    }
+    let mut reply = [0u8; ARP_LEN];
+    reply[..6].copy_from_slice(&arp[..6]);
+    reply[6..8].copy_from_slice(&ARP_REPLY.to_be_bytes());
+    reply[8..14].copy_from_slice(&interface.device.mac());
+    reply[14..18].copy_from_slice(&interface.addr.to_be_bytes());
+    reply[18..24].copy_from_slice(&sender_mac);
+    reply[24..28].copy_from_slice(&sender_ip.to_be_bytes());
+    send_frame(interface, sender_mac, ETH_TYPE_ARP, &reply);
+}
+
+fn receive_ipv4(interface: &Interface, src_mac: [u8; 6], packet: &[u8]) {
+    if packet.len() < IPV4_HDR_LEN || packet[0] >> 4 != 4 {
+        return;
+    }
+    let header_len = (packet[0] & 0xf) as usize * 4;
+    let total_len = read_u16(packet, 2) as usize;
+    if header_len < IPV4_HDR_LEN || total_len < header_len || total_len > packet.len() {
+        return;
+    }
+    if checksum(&packet[..header_len], 0) != 0 || read_u16(packet, 6) & IP_FRAGMENT_MASK != 0 {
+        return;
+    }
+    let src = read_u32(packet, 12);
+    let dst = read_u32(packet, 16);
+    if !interface.accepts(dst) {
+        return;
+    }
+    if !interface.is_loopback() && src != 0 {
+        ARP_TABLE.exclusive_access().insert(src, src_mac);
+    }
+    if packet[9] == IP_PROTO_UDP {
+        udp::receive(src, dst, &packet[header_len..total_len]);
+    }
+}
+
+/// 选择发往 `dst` 的接口：本机地址走 lo，其他地址走 eth0
+fn route(dst: Ipv4Addr) -> Option<&'static Interface> {
+    if is_local(dst) {
+        return INTERFACES.first();
+    }
+    INTERFACES.iter().find(|interface| !interface.is_loopback())
+}
+
+/// 发往 `dst` 的数据报使用的源地址
+fn source_addr(dst: Ipv4Addr) -> Result<Ipv4Addr, Errno> {
+    let interface = route(dst).ok_or(ENETUNREACH)?;
+    if !interface.is_loopback() {
+        Ok(interface.addr)
+    } else if dst >> 24 == 127 {
+        Ok(LOOPBACK_ADDR)
+    } else {
+        Ok(dst)
+    }
+}
+
+/// 把协议为 `protocol` 的 `payload` 封装成 IPv4 数据报从 `src` 发往 `dst`，`src` 为 0 时使用出口接口的地址。
+/// 数据报不能超过 [`IP_MAX_LEN`]；网卡的发送缓冲区已满时数据报被丢弃，与线路上丢包一样不报告错误
+fn send_ipv4(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Result<(), Errno> {
+    let interface = route(dst).ok_or(ENETUNREACH)?;
+    let src = if src == 0 { source_addr(dst)? } else { src };
+    let total_len = IPV4_HDR_LEN + payload.len();
+    assert!(total_len <= IP_MAX_LEN, "IPv4 datagram of {} bytes needs fragmentation", total_len);
+    let mut packet = vec![0u8; total_len];
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    packet[4..6].copy_from_slice(&NEXT_IP_ID.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    packet[6..8].copy_from_slice(&IP_DF.to_be_bytes());
+    packet[8] = IP_TTL;
+    packet[9] = protocol;
+    packet[12..16].copy_from_slice(&src.to_be_bytes());
+    packet[16..20].copy_from_slice(&dst.to_be_bytes());
+    let sum = checksum(&packet[..IPV4_HDR_LEN], 0);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet[IPV4_HDR_LEN..].copy_from_slice(payload);
+    let dst_mac = if interface.is_loopback() {
+        [0; 6]
+    } else if dst == BROADCAST_ADDR || dst == interface.addr | !interface.netmask {
+        BROADCAST_MAC
+    } else {
+        ARP_TABLE.exclusive_access().get(&dst).copied().unwrap_or(BROADCAST_MAC)
+    };
+    send_frame(interface, dst_mac, ETH_TYPE_IPV4, &packet);
+    if interface.is_loopback() {
+        poll();
+    }
+    Ok(())
+}
+
+fn send_frame(interface: &Interface, dst_mac: [u8; 6], ether_type: u16, payload: &[u8]) {
+    let mut frame = Vec::with_capacity(ETH_HDR_LEN + payload.len());
+    frame.extend_from_slice(&dst_mac);
+    frame.extend_from_slice(&interface.device.mac());
+    frame.extend_from_slice(&ether_type.to_be_bytes());
+    frame.extend_from_slice(payload);
+    if !interface.device.send(&frame) {
+        warn!("{}: transmit queue full, dropping a frame", interface.name);
+    }
+}
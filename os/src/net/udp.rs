@@ -0,0 +1,283 @@
+//! UDP 套接字
+//!
+//! 绑定了端口的套接字登记在端口表中，收到的数据报按目的端口放入对应套接字的接收队列，
+//! 队列满时丢弃新的数据报。没有绑定就发送的套接字自动绑定一个临时端口。
+//! 套接字的所有描述符都关闭后释放它的端口。
+
+use super::{checksum, is_local, read_u16, send_ipv4, source_addr, Ipv4Addr, IP_MAX_LEN, IP_PROTO_UDP, IPV4_HDR_LEN, LOOPBACK_ADDR};
+use crate::fs::{notify_readiness, File, PollEvents, Stat, StatMode};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::{Errno, EADDRINUSE, EADDRNOTAVAIL, EAGAIN, EDESTADDRREQ, EINVAL, EMSGSIZE};
+use crate::task::WaitQueue;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const UDP_HDR_LEN: usize = 8;
+/// 一个 UDP 数据报最多携带的数据，不分片时受以太网帧长度限制
+pub const UDP_MAX_PAYLOAD: usize = IP_MAX_LEN - IPV4_HDR_LEN - UDP_HDR_LEN;
+/// 每个套接字的接收队列中最多缓存的数据报数
+const UDP_QUEUE_LEN: usize = 64;
+/// 自动分配的临时端口的范围，与 Linux 的默认值相同
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 32768..=60999;
+
+/// 一个收到的数据报和它的来源
+pub struct Datagram {
+    pub addr: Ipv4Addr,
+    pub port: u16,
+    pub data: Vec<u8>,
+}
+
+/// 一个套接字的接收队列，端口表通过弱引用找到它
+struct UdpQueue {
+    datagrams: UPSafeCell<VecDeque<Datagram>>,
+    readers: WaitQueue,
+}
+
+impl UdpQueue {
+    fn push(&self, datagram: Datagram) {
+        let mut datagrams = self.datagrams.exclusive_access();
+        if datagrams.len() >= UDP_QUEUE_LEN {
+            return;
+        }
+        datagrams.push_back(datagram);
+        drop(datagrams);
+        self.readers.wake_all();
+        notify_readiness();
+    }
+}
+
+/// 绑定在一个端口上的套接字
+struct Binding {
+    /// 绑定的本机地址，0 表示所有地址
+    addr: Ipv4Addr,
+    queue: Weak<UdpQueue>,
+}
+
+lazy_static! {
+    /// 已绑定的端口
+    static ref PORTS: UPSafeCell<BTreeMap<u16, Binding>> = unsafe { UPSafeCell::new(BTreeMap::new()) };
+    /// 下一次尝试分配的临时端口
+    static ref NEXT_EPHEMERAL: UPSafeCell<u16> = unsafe { UPSafeCell::new(*EPHEMERAL_PORTS.start()) };
+}
+
+/// 处理发给本机的 UDP 数据报，目的端口没有绑定时丢弃它
+pub(super) fn receive(src: Ipv4Addr, dst: Ipv4Addr, segment: &[u8]) {
+    if segment.len() < UDP_HDR_LEN {
+        return;
+    }
+    let len = read_u16(segment, 4) as usize;
+    if len < UDP_HDR_LEN || len > segment.len() {
+        return;
+    }
+    let segment = &segment[..len];
+    // 校验和为 0 表示发送者没有计算校验和
+    if read_u16(segment, 6) != 0 && checksum(segment, pseudo_header_sum(src, dst, len)) != 0 {
+        return;
+    }
+    let src_port = read_u16(segment, 0);
+    let dst_port = read_u16(segment, 2);
+    let queue = match PORTS.exclusive_access().get(&dst_port) {
+        Some(binding) if binding.addr == 0 || binding.addr == dst => binding.queue.upgrade(),
+        _ => None,
+    };
+    if let Some(queue) = queue {
+        queue.push(Datagram { addr: src, port: src_port, data: segment[UDP_HDR_LEN..].to_vec() });
+    }
+}
+
+/// UDP 伪头部的反码和（未取反）
+fn pseudo_header_sum(src: Ipv4Addr, dst: Ipv4Addr, len: usize) -> u32 {
+    (src >> 16) + (src & 0xffff) + (dst >> 16) + (dst & 0xffff) + IP_PROTO_UDP as u32 + len as u32
+}
+
+/// 把 `data` 封装成 UDP 数据报从 `src:src_port` 发往 `dst:dst_port`
+fn send(src: Ipv4Addr, src_port: u16, dst: Ipv4Addr, dst_port: u16, data: &[u8]) -> Result<(), Errno> {
+    let src = if src == 0 { source_addr(dst)? } else { src };
+    let len = UDP_HDR_LEN + data.len();
+    let mut segment = Vec::with_capacity(len);
+    segment.extend_from_slice(&src_port.to_be_bytes());
+    segment.extend_from_slice(&dst_port.to_be_bytes());
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    segment.extend_from_slice(&[0, 0]);
+    segment.extend_from_slice(data);
+    let sum = match checksum(&segment, pseudo_header_sum(src, dst, len)) {
+        // 计算结果为 0 时发送全 1，0 表示没有校验和
+        0 => 0xffff,
+        sum => sum,
+    };
+    segment[6..8].copy_from_slice(&sum.to_be_bytes());
+    send_ipv4(src, dst, IP_PROTO_UDP, &segment)
+}
+
+/// AF_INET/SOCK_DGRAM 套接字
+pub struct UdpSocket {
+    queue: Arc<UdpQueue>,
+    /// 绑定的本机地址和端口
+    local: UPSafeCell<Option<(Ipv4Addr, u16)>>,
+    nonblock: bool,
+}
+
+impl UdpSocket {
+    /// 创建没有绑定的套接字，`nonblock` 为真时没有数据报的接收返回 EAGAIN
+    pub fn new(nonblock: bool) -> Arc<Self> {
+        Arc::new(Self {
+            queue: Arc::new(UdpQueue {
+                datagrams: unsafe { UPSafeCell::new(VecDeque::new()) },
+                readers: WaitQueue::new(),
+            }),
+            local: unsafe { UPSafeCell::new(None) },
+            nonblock,
+        })
+    }
+
+    /// 绑定本机地址 `addr`（0 表示所有地址）和端口 `port`（0 表示分配一个临时端口）
+    pub fn bind(&self, addr: Ipv4Addr, port: u16) -> Result<(), Errno> {
+        let mut local = self.local.exclusive_access();
+        if local.is_some() {
+            return Err(EINVAL);
+        }
+        if addr != 0 && !is_local(addr) {
+            return Err(EADDRNOTAVAIL);
+        }
+        let mut ports = PORTS.exclusive_access();
+        let port = if port != 0 {
+            if ports.contains_key(&port) {
+                return Err(EADDRINUSE);
+            }
+            port
+        } else {
+            // 从上次分配的端口之后开始找空闲的临时端口
+            let mut next = NEXT_EPHEMERAL.exclusive_access();
+            let (start, count) = (*EPHEMERAL_PORTS.start(), EPHEMERAL_PORTS.len() as u16);
+            let port = (0..count)
+                .map(|i| start + (*next - start + i) % count)
+                .find(|port| !ports.contains_key(port))
+                .ok_or(EADDRINUSE)?;
+            *next = start + (port - start + 1) % count;
+            port
+        };
+        ports.insert(port, Binding { addr, queue: Arc::downgrade(&self.queue) });
+        *local = Some((addr, port));
+        Ok(())
+    }
+
+    /// 绑定的本机地址和端口
+    pub fn local_addr(&self) -> Option<(Ipv4Addr, u16)> {
+        *self.local.exclusive_access()
+    }
+
+    /// 把 `data` 作为一个数据报发往 `dst:port`，返回发送的字节数；还没有绑定时先绑定一个临时端口
+    pub fn send_to(&self, data: &[u8], dst: Ipv4Addr, port: u16) -> Result<usize, Errno> {
+        if data.len() > UDP_MAX_PAYLOAD {
+            return Err(EMSGSIZE);
+        }
+        if port == 0 {
+            return Err(EINVAL);
+        }
+        if self.local_addr().is_none() {
+            self.bind(0, 0)?;
+        }
+        let (src, src_port) = self.local_addr().unwrap();
+        // 绑定在回环地址上的套接字只能与本机通信
+        if src == LOOPBACK_ADDR && !is_local(dst) {
+            return Err(EINVAL);
+        }
+        send(src, src_port, dst, port, data)?;
+        Ok(data.len())
+    }
+
+    /// 取出一个数据报，没有数据报时阻塞；非阻塞的套接字或 `dontwait` 为真时返回 EAGAIN
+    pub fn recv_from(&self, dontwait: bool) -> Result<Datagram, Errno> {
+        loop {
+            if let Some(datagram) = self.queue.datagrams.exclusive_access().pop_front() {
+                return Ok(datagram);
+            }
+            if self.nonblock || dontwait {
+                return Err(EAGAIN);
+            }
+            self.queue.readers.wait();
+        }
+    }
+}
+
+impl Drop for UdpSocket {
+    /// 套接字的描述符全部关闭后释放端口
+    fn drop(&mut self) {
+        if let Some((_, port)) = *self.local.exclusive_access() {
+            PORTS.exclusive_access().remove(&port);
+        }
+    }
+}
+
+impl File for UdpSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    // read 取出一个数据报，放不进缓冲区的部分被丢弃
+    fn read(&self, mut buf: UserBuffer) -> isize {
+        match self.recv_from(false) {
+            Ok(datagram) => buf.write(&datagram.data) as isize,
+            Err(errno) => -errno,
+        }
+    }
+    // 没有 connect，write 不知道发往哪里
+    fn write(&self, _buf: UserBuffer) -> isize {
+        -EDESTADDRREQ
+    }
+    fn as_udp_socket(&self) -> Option<&UdpSocket> {
+        Some(self)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::OUT;
+        if !self.queue.datagrams.exclusive_access().is_empty() {
+            ready |= PollEvents::IN;
+        }
+        ready & events
+    }
+    fn stat(&self) -> Stat {
+        Stat::new_with_defaults(0, 0, StatMode::NULL, 1)
+    }
+}
+
+/// 两个套接字经过回环网卡互发数据报，检查端口分配、校验和、poll 和关闭后释放端口
+#[allow(unused)]
+pub fn udp_loopback_test() {
+    let server = UdpSocket::new(true);
+    server.bind(LOOPBACK_ADDR, 7).unwrap();
+    assert_eq!(UdpSocket::new(true).bind(0, 7), Err(EADDRINUSE));
+    assert_eq!(server.poll(PollEvents::IN | PollEvents::OUT), PollEvents::OUT);
+    assert_eq!(server.recv_from(false).err(), Some(EAGAIN));
+
+    let client = UdpSocket::new(true);
+    assert_eq!(client.send_to(b"ping", LOOPBACK_ADDR, 7), Ok(4));
+    let (_, client_port) = client.local_addr().unwrap();
+    assert!(EPHEMERAL_PORTS.contains(&client_port));
+    assert_eq!(server.poll(PollEvents::IN), PollEvents::IN);
+    let request = server.recv_from(false).unwrap();
+    assert_eq!((request.addr, request.port, &request.data[..]), (LOOPBACK_ADDR, client_port, &b"ping"[..]));
+
+    // 奇数长度的数据报和最大的数据报
+    let big = vec![0x5a; UDP_MAX_PAYLOAD];
+    assert_eq!(server.send_to(b"pong!", request.addr, request.port), Ok(5));
+    assert_eq!(server.send_to(&big, request.addr, request.port), Ok(UDP_MAX_PAYLOAD));
+    assert_eq!(server.send_to(&[0; UDP_MAX_PAYLOAD + 1], request.addr, request.port), Err(EMSGSIZE));
+    assert_eq!(client.recv_from(false).unwrap().data, b"pong!");
+    assert_eq!(client.recv_from(false).unwrap().data, big);
+
+    // 发往没有绑定的端口的数据报被丢弃
+    assert_eq!(client.send_to(b"lost", LOOPBACK_ADDR, 9), Ok(4));
+    drop(server);
+    assert_eq!(client.send_to(b"lost", LOOPBACK_ADDR, 7), Ok(4));
+    assert_eq!(client.recv_from(false).err(), Some(EAGAIN));
+    let server = UdpSocket::new(true);
+    server.bind(0, 7).unwrap();
+    assert_eq!(server.recv_from(false).err(), Some(EAGAIN));
+    info!("udp_loopback_test passed!");
+}
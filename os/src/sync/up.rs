@@ -21,4 +21,9 @@ impl<T> UPSafeCell<T> {
     pub fn exclusive_access(&self) -> RefMut<'_, T> {
         self.inner.borrow_mut()
     }
+    /// Like [`Self::exclusive_access`], but returns `None` instead of panicking
+    /// if the data has been borrowed, e.g. when diagnosing a fault in the kernel.
+    pub fn try_exclusive_access(&self) -> Option<RefMut<'_, T>> {
+        self.inner.try_borrow_mut().ok()
+    }
 }
@@ -1,20 +1,31 @@
-use core::ptr::copy_nonoverlapping;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
-use crate::fs::{chdir, make_pipe, open_file, search_pwd, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
-use crate::task::{current_task, current_user_token};
-use super::AT_FDCWD;
+use crate::fs::{
+    canonical_path, chdir, create_bypath, dcache_invalidate, dcache_invalidate_all, fat_errno, make_fifo, make_pipe, mount_fat, mount_tmpfs, open_fifo, open_file, flock, open_procfs, Inotify, IN_ALL_EVENTS, open_tmpfs, open_tty, path_read_only, real_path, remount_fat,
+    remove_fifo, search_pwd, sync_disks, tmpfs_is_dir, tmpfs_mkdir, tmpfs_unlink, umount_fat, umount_tmpfs, vfile_read_only, File, OSInode, OpenFlags, SeekWhence, Stat, StatMode,
+};
+use alloc::sync::Arc;
+use crate::mm::{
+    copy_to_user, page_cache_invalidate, translated_byte_buffer, translated_byte_buffer_mut, translated_str,
+    UserBuffer, UserPtr, UserSlice,
+};
+use crate::drivers::block_device_by_name;
+use core::mem::align_of;
+use crate::config::{PATH_MAX, TMPFS_SIZE};
+use crate::task::{current_process, current_user_token};
+use super::{AT_FDCWD, EACCES, EBADF, EEXIST, EFAULT, EINVAL, ENOENT, ENOTDIR, EROFS};
+use crate::config::PAGE_SIZE;
 
 /// sys_write 系统调用，向文件描述符写入数据
 /// fd: 文件描述符
 /// buf: 数据缓冲区
 /// len: 写入的字节数
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    trace!("kernel:pid[{}] sys_write", current_task().unwrap().pid.0);
+    trace!("kernel:pid[{}] sys_write", current_process().getpid());
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
     // 检查文件描述符是否合法
     if fd >= inner.fd_table.len() {
         return -1;
@@ -26,7 +37,10 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // 手动释放当前任务 TCB，以避免多次借用
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match translated_byte_buffer(token, buf, len) {
+            Ok(buffers) => file.write(UserBuffer::new(buffers)),
+            Err(_) => -EFAULT,
+        }
     } else {
         -1
     }
@@ -36,12 +50,12 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
 /// fd: 文件描述符
 /// buf: 数据缓冲区
 /// len: 读取的字节数
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    trace!("kernel:pid[{}] sys_read", current_task().unwrap().pid.0);
+pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
+    trace!("kernel:pid[{}] sys_read", current_process().getpid());
     
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
     // 检查文件描述符是否合法
     if fd >= inner.fd_table.len() {
         return -1;
@@ -54,7 +68,10 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         // 手动释放当前任务 TCB，以避免多次借用
         drop(inner);
         trace!("kernel: sys_read .. file.read");
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        match translated_byte_buffer_mut(token, buf, len) {
+            Ok(buffers) => file.read(UserBuffer::new(buffers)),
+            Err(_) => -EFAULT,
+        }
     } else {
         -1
     }
@@ -63,28 +80,100 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
 /// sys_openat 系统调用，打开文件
 /// fd: 基准文件描述符（可以是AT_FDCWD，表示当前工作目录）
 pub fn sys_openat(fd: i64, path: *const u8, flags: u32) -> isize {
-    trace!("kernel:pid[{}] sys_open", current_task().unwrap().pid.0);
+    trace!("kernel:pid[{}] sys_open", current_process().getpid());
     let token = current_user_token();
-    let binding = translated_str(token, path);
-    
+    let binding = match translated_str(token, path, PATH_MAX) {
+        Ok(binding) => binding,
+        Err(errno) => return -errno,
+    };
+
     let path = binding.as_str();
-    if let Some(inode) = open_file(fd, path, OpenFlags::from_bits(flags).unwrap()) {
-        
-        let task = current_task().unwrap();
-        let mut inner = task.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+    let flags = OpenFlags::from_bits_truncate(flags);
+    // FIFO 和 tmpfs 只按相对于当前工作目录的路径查找，FAT 也按进程根目录下的绝对路径查找
+    let cwd_relative = fd as isize == AT_FDCWD || path.starts_with('/');
+    let real = real_path(path);
+    let (readable, writable) = flags.read_write();
+    // 只读的 FAT 文件不能以写方式打开，在 open_file 截断文件之前检查
+    if writable || flags.contains(OpenFlags::TRUNC) {
+        let existing = if cwd_relative {
+            search_pwd(&real)
+        } else {
+            open_file(fd, path, OpenFlags::RDONLY).map(|osinode| osinode.inner.exclusive_access().inode.clone())
+        };
+        if existing.is_some_and(|vfile| vfile.is_readonly().unwrap_or(false)) {
+            return -EACCES;
+        }
+    }
+    let fifo = if cwd_relative { open_fifo(&real, flags) } else { None };
+    // /proc 按进程根目录解析：chroot 之后的 /proc 是新根目录下的 proc，不再是 procfs
+    let proc_file = if cwd_relative { open_procfs(&real) } else { None };
+    let file: Arc<dyn File + Send + Sync> = if let Some(proc_file) = proc_file {
+        proc_file
+    } else if let Some(tty) = open_tty(path, readable, writable) {
+        tty
+    } else if let Some(fifo) = fifo {
+        match fifo {
+            Ok(end) => end,
+            Err(errno) => return errno,
+        }
+    } else if let Some(tmp_file) = cwd_relative.then(|| open_tmpfs(&real, flags)).flatten() {
+        match tmp_file {
+            Ok(file) => file,
+            Err(errno) => return -errno,
+        }
+    } else if (writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC)) && fat_read_only(fd, path, &real, cwd_relative) {
+        return -EROFS;
+    } else if let Some(inode) = open_file(fd, if cwd_relative { &real } else { path }, flags) {
+        inode.set_append(flags.contains(OpenFlags::APPEND));
+        if let Some(full) = if cwd_relative { Some(real.clone()) } else { dirfd_path(fd, path) } {
+            inode.set_path(full);
+        }
+        inode
     } else {
-        -1
+        return -1;
+    };
+    // /proc 和 tmpfs 中的文件同样不能以 O_DIRECTORY 打开
+    if flags.contains(OpenFlags::O_DIRECTORY) && !file.stat().mode.contains(StatMode::DIR) {
+        return -ENOTDIR;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(file);
+    fd as isize
+}
+
+/// 相对于目录 `fd` 的路径 `path` 在文件系统中的绝对路径，目录没有记录打开时的路径时返回 `None`
+fn dirfd_path(fd: i64, path: &str) -> Option<String> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let dir = inner.fd_table.get(fd as usize)?.as_ref()?.path()?;
+    Some(canonical_path(&dir, path))
+}
+
+/// 要在 FAT 文件系统上打开的路径所在的文件系统是否只读挂载。
+/// `cwd_relative` 时按规范化的绝对路径 `real` 判断，否则按目录 `fd` 下 `path` 的绝对路径判断，
+/// 路径可能越过挂载点进入另一个文件系统；目录没有记录路径时才按它所在的文件系统判断
+fn fat_read_only(fd: i64, path: &str, real: &str, cwd_relative: bool) -> bool {
+    if cwd_relative {
+        return path_read_only(real);
+    }
+    if let Some(full) = dirfd_path(fd, path) {
+        return path_read_only(&full);
+    }
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd as usize).and_then(|file| file.as_ref()?.vfile()) {
+        Some(vfile) => vfile_read_only(&vfile),
+        None => false,
     }
 }
 
 /// sys_close 系统调用，关闭文件描述符
 pub fn sys_close(fd: usize) -> isize {
-    trace!("kernel:pid[{}] sys_close", current_task().unwrap().pid.0);
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    trace!("kernel:pid[{}] sys_close", current_process().getpid());
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     // 检查文件描述符是否合法
     if fd >= inner.fd_table.len() {
         return -1;
@@ -97,79 +186,321 @@ pub fn sys_close(fd: usize) -> isize {
     0
 }
 
+/// sys_fsync 系统调用，将文件描述符 `fd` 对应文件的缓存数据写回设备
+pub fn sys_fsync(fd: usize) -> isize {
+    trace!("kernel:pid[{}] sys_fsync", current_process().getpid());
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    // 写回块设备时不需要持有 PCB
+    drop(inner);
+    match file.sync() {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// sys_sync 系统调用，写回所有文件系统缓存中被修改的块并刷新磁盘的写缓存
+pub fn sys_sync() -> isize {
+    trace!("kernel:pid[{}] sys_sync", current_process().getpid());
+    match sync_disks() {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// sys_lseek 系统调用，按 `whence` 移动文件描述符 `fd` 的读写位置，返回新的位置
+pub fn sys_lseek(fd: usize, offset: i64, whence: usize) -> isize {
+    trace!("kernel:pid[{}] sys_lseek", current_process().getpid());
+    let Some(whence) = SeekWhence::from_raw(whence) else {
+        return -EINVAL;
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.seek(offset, whence),
+        _ => -EBADF,
+    }
+}
+
+/// fcntl 的命令：设置管道缓冲区的容量
+const F_SETPIPE_SZ: usize = 1031;
+/// fcntl 的命令：获取管道缓冲区的容量
+const F_GETPIPE_SZ: usize = 1032;
+
+/// sys_fcntl 系统调用，目前只支持获取和设置管道的容量，其他命令返回 -EINVAL
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    trace!("kernel:pid[{}] sys_fcntl", current_process().getpid());
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    match (cmd, file.as_pipe()) {
+        (F_GETPIPE_SZ, Some(pipe)) => pipe.capacity() as isize,
+        (F_SETPIPE_SZ, Some(pipe)) => pipe.set_capacity(arg),
+        _ => -EINVAL,
+    }
+}
+
+/// sys_ioctl 系统调用，将请求交给文件自己处理，目前只有控制台支持 TCGETS 和 TCSETS
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    trace!("kernel:pid[{}] sys_ioctl", current_process().getpid());
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    file.ioctl(request, arg)
+}
+
+/// sys_flock 系统调用，对打开文件加 BSD 风格的建议性锁；不支持加锁的文件（如管道）返回 -EINVAL
+pub fn sys_flock(fd: usize, operation: u32) -> isize {
+    trace!("kernel:pid[{}] sys_flock", current_process().getpid());
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(key) = file.lock_key() else {
+        return -EINVAL;
+    };
+    // 锁的持有者是打开文件本身，与 Drop 中使用的地址相同
+    flock(key, Arc::as_ptr(&file) as *const () as usize, operation)
+}
+
+/// inotify_init1 的 IN_NONBLOCK 标志，与 O_NONBLOCK 相同
+const IN_NONBLOCK: u32 = OpenFlags::NONBLOCK.bits();
+/// inotify_init1 的 IN_CLOEXEC 标志，没有 exec 时关闭描述符的支持，被忽略
+const IN_CLOEXEC: u32 = 0o2000000;
+
+/// sys_inotify_init1 系统调用，创建 inotify 实例并返回它的描述符
+pub fn sys_inotify_init1(flags: u32) -> isize {
+    trace!("kernel:pid[{}] sys_inotify_init1", current_process().getpid());
+    if flags & !(IN_NONBLOCK | IN_CLOEXEC) != 0 {
+        return -EINVAL;
+    }
+    let inotify = Inotify::new(flags & IN_NONBLOCK != 0);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(inotify);
+    fd as isize
+}
+
+/// sys_inotify_add_watch 系统调用，监视 FAT 目录 `path` 中的创建、删除和写入事件，返回监视描述符。
+/// tmpfs 中的目录不支持监视，返回 -EINVAL
+pub fn sys_inotify_add_watch(fd: usize, path: *const u8, mask: u32) -> isize {
+    trace!("kernel:pid[{}] sys_inotify_add_watch", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(inotify) = file.as_inotify() else {
+        return -EINVAL;
+    };
+    if mask & IN_ALL_EVENTS == 0 {
+        return -EINVAL;
+    }
+    let path = real_path(&path);
+    if tmpfs_is_dir(&path).is_some() {
+        return -EINVAL;
+    }
+    match search_pwd(&path) {
+        Some(dir) if dir.is_dir() => inotify.add_watch(dir.dirent_pos(), mask) as isize,
+        Some(_) => -ENOTDIR,
+        None => -ENOENT,
+    }
+}
+
 /// sys_getcwd 系统调用，获取当前工作目录
 pub fn sys_getcwd(buf: *mut u8, size:u32) -> isize {
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
     
-    let pwd = inner.pwd.clone();
+    let mut pwd = inner.pwd.clone();
+    drop(inner);
+    // 包括结尾的 '\0'
+    pwd.push('\0');
     if pwd.len() > size as usize{
         return -1;
     }
-    drop(inner);
-
-    let mut ti = translated_byte_buffer(current_user_token(),  buf, size as usize);
-    let total_bytes = pwd.len();
-    let mut bytes_written = 0;
-    let src_ptr = pwd.as_ptr();
-    for slice in ti.iter_mut(){
-        let slice_len = slice.len();
-        let mut offset = 0;
-        while offset < slice_len && bytes_written < total_bytes{
-            unsafe {
-                let to_write = (total_bytes - bytes_written).min(slice_len - offset);
-                let ptr = slice.as_mut_ptr().add(offset);
-                copy_nonoverlapping(src_ptr.add(bytes_written), ptr, to_write);
-            }
-            offset += slice_len;
-            bytes_written += slice_len;
-        }
-        if bytes_written >= total_bytes {
-            break;
-        }
+    if copy_to_user(current_user_token(), buf, pwd.as_bytes()).is_err() {
+        return -EFAULT;
     }
-    return pwd.as_ptr() as isize;
+    buf as isize
 }
 
 /// sys_mkdirat 系统调用，创建目录
 pub fn sys_mkdirat(fd: i64, path: *const u8, attri: u8) -> isize {
-    let task = current_task().unwrap();
+    let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
-    let inner = task.inner_exclusive_access();
-    if fd as isize == AT_FDCWD {
-        let pwd = inner.pwd.clone();
-        if let Some(file) = search_pwd(pwd.as_str()) {
-            file.create(path.as_str(), attri);
-            return 0;
-        } else {
-            return -1;
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if fd as isize == AT_FDCWD || path.starts_with('/') {
+        let path = real_path(&path);
+        if let Some(result) = tmpfs_mkdir(&path) {
+            return result;
+        }
+        if search_pwd(&path).is_some() {
+            return -EEXIST;
+        }
+        if path_read_only(&path) {
+            return -EROFS;
+        }
+        match create_bypath(&path, attri) {
+            Some(_) => 0,
+            None => -ENOENT,
         }
     } else {
-        if let Some(file) = &inner.fd_table[fd as usize] {
-            let osinode = file.as_osinode().unwrap();
-            osinode.mkdir(path.as_str(), attri)
-        } else {
-            -1
+        // 只能在 FAT 目录中创建目录
+        let inner = process.inner_exclusive_access();
+        match inner.fd_table.get(fd as usize) {
+            Some(Some(file)) => match file.vfile() {
+                Some(vfile) if vfile_read_only(&vfile) => -EROFS,
+                Some(vfile) => match vfile.create(path.as_str(), attri) {
+                    Ok(_) => 0,
+                    Err(err) => -fat_errno(err),
+                },
+                None => -ENOTDIR,
+            },
+            _ => -EBADF,
         }
     }
 }
 
-/// sys_chdir 系统调用，改变当前工作目录
+/// mknod 的 mode 中表示文件类型的位
+const S_IFMT: u32 = 0o170000;
+
+/// sys_mknodat 系统调用，目前只支持创建 FIFO，路径相对于当前工作目录；其他文件类型返回 -EINVAL
+pub fn sys_mknodat(fd: i64, path: *const u8, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_mknodat", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if fd as isize != AT_FDCWD && !path.starts_with('/') {
+        return -EINVAL;
+    }
+    if mode & S_IFMT != StatMode::FIFO.bits() {
+        return -EINVAL;
+    }
+    make_fifo(&real_path(&path))
+}
+
+/// sys_chdir 系统调用，改变当前工作目录；`path` 不存在返回 -ENOENT，不是目录返回 -ENOTDIR
 pub fn sys_chdir(path: *const u8) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if chdir(path.as_str()) {
-        return 0;
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    match chdir(path.as_str()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// sys_chroot 系统调用，把进程的根目录改为 `path`，并把工作目录移到新的根目录。
+/// 内核没有用户凭据，所有进程都视为 root；`path` 不存在返回 -ENOENT，不是目录返回 -ENOTDIR
+pub fn sys_chroot(path: *const u8) -> isize {
+    trace!("kernel:pid[{}] sys_chroot", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let root = real_path(&path);
+    let is_dir = match tmpfs_is_dir(&root) {
+        Some(is_dir) => is_dir,
+        None => match search_pwd(&root) {
+            Some(vfile) => vfile.is_dir(),
+            None => return -ENOENT,
+        },
+    };
+    if !is_dir {
+        return -ENOTDIR;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    inner.root = root;
+    inner.set_pwd(String::from("/"));
+    0
+}
+
+/// mode 中没有任何写权限位时文件只读
+fn chmod_readonly(mode: u32) -> bool {
+    StatMode::from_bits_truncate(mode) & StatMode::WRITE == StatMode::empty()
+}
+
+/// sys_fchmod 系统调用，修改文件的权限。FAT 文件只记录是否只读，其余权限位和其他文件的权限被忽略
+pub fn sys_fchmod(fd: usize, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_fchmod", current_process().getpid());
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return -EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    if let Some(vfile) = file.vfile() {
+        if let Err(err) = vfile.set_readonly(chmod_readonly(mode)) {
+            return -fat_errno(err);
+        }
+    }
+    0
+}
+
+/// sys_fchmodat 系统调用，按路径修改文件的权限，规则与 sys_fchmod 相同；路径不存在返回 -ENOENT
+pub fn sys_fchmodat(fd: i64, path: *const u8, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_fchmodat", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let vfile = if fd as isize == AT_FDCWD || path.starts_with('/') {
+        let path = real_path(&path);
+        if let Some(result) = open_tmpfs(&path, OpenFlags::RDONLY) {
+            return result.map_or_else(|errno| -errno, |_| 0);
+        }
+        search_pwd(&path)
     } else {
-        return -1;
+        open_file(fd, &path, OpenFlags::RDONLY).map(|osinode| osinode.inner.exclusive_access().inode.clone())
+    };
+    match vfile {
+        Some(vfile) => match vfile.set_readonly(chmod_readonly(mode)) {
+            Ok(()) => 0,
+            Err(err) => -fat_errno(err),
+        },
+        None => -ENOENT,
     }
 }
 
 /// sys_dup 系统调用，复制文件描述符
 pub fn sys_dup(fd:usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     if fd < inner.fd_table.len() && !inner.fd_table[fd].is_none() {
         let newfd = inner.alloc_fd();
         inner.fd_table[newfd] = inner.fd_table[fd].clone();
@@ -181,8 +512,8 @@ pub fn sys_dup(fd:usize) -> isize {
 
 /// sys_dup3 系统调用，复制文件描述符并指定新描述符
 pub fn sys_dup3(fd:usize, newfd:usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     if fd < inner.fd_table.len() && !inner.fd_table[fd].is_none() {
         for _ in inner.fd_table.len().. newfd + 1 {
             inner.fd_table.push(None);
@@ -194,52 +525,136 @@ pub fn sys_dup3(fd:usize, newfd:usize) -> isize {
     }
 }
 
-/// sys_pipe2 系统调用，创建管道
-pub fn sys_pipe2(pipe: *mut u32) -> isize {
-    let task = current_task().unwrap();
+/// sys_pipe2 系统调用，创建管道，`flags` 中只处理 O_NONBLOCK
+pub fn sys_pipe2(pipe: *mut u32, flags: u32) -> isize {
+    let process = current_process();
     let token = current_user_token();
-    let mut inner = task.inner_exclusive_access();
-    let (pipe_read, pipe_write) = make_pipe();
+    // 先检查用户指针，避免创建管道后无法返回文件描述符
+    let pipe = match UserPtr::writable(token, pipe as *mut [u32; 2]) {
+        Ok(pipe) => pipe,
+        Err(errno) => return -errno,
+    };
+    let mut inner = process.inner_exclusive_access();
+    let nonblock = OpenFlags::from_bits_truncate(flags).contains(OpenFlags::NONBLOCK);
+    let (pipe_read, pipe_write) = make_pipe(nonblock);
     let read_fd = inner.alloc_fd();
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd as u32;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd as u32;
+    drop(inner);
+    pipe.write([read_fd as u32, write_fd as u32]).unwrap();
     0
 }
 
 /// sys_fstat 系统调用，获取文件状态信息
-pub fn sys_fstat(fd:usize, lkstat:*mut u8) -> isize {
+pub fn sys_fstat(fd:usize, kst:*mut Stat) -> isize {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd < inner.fd_table.len() && !inner.fd_table[fd].is_none() {
-        let file = &inner.fd_table[fd];
-        let vfile = file.clone().unwrap().as_osinode().unwrap().inner.exclusive_access().inode.clone();
-        let all = vfile.stat().to_bytes();
-        let mut ti = translated_byte_buffer(token,  lkstat, 128 as usize);
-        let total_bytes = 128;
-        let mut bytes_written = 0;
-        let src_ptr = all.as_ptr();
-        for slice in ti.iter_mut(){
-            let slice_len = slice.len();
-            let mut offset = 0;
-            while offset < slice_len && bytes_written < total_bytes{
-                unsafe {
-                    let to_write = (total_bytes - bytes_written).min(slice_len - offset);
-                    let ptr = slice.as_mut_ptr().add(offset);
-                    copy_nonoverlapping(src_ptr.add(bytes_written), ptr, to_write);
-                }
-                offset += slice_len;
-                bytes_written += slice_len;
-            }
-            if bytes_written >= total_bytes {
-                break;
-            }
-        }
+    let kst = match UserPtr::writable(token, kst) {
+        Ok(kst) => kst,
+        Err(errno) => return -errno,
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    match kst.write(file.stat()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// 按文件系统中的绝对路径 `path` 打开要取得状态的文件，依次查找 /proc、tmpfs 和 FAT，失败时返回 -errno
+fn open_for_stat(path: &str) -> Result<Arc<dyn File + Send + Sync>, isize> {
+    if let Some(file) = open_procfs(path) {
+        return Ok(file);
+    }
+    match open_tmpfs(path, OpenFlags::RDONLY) {
+        Some(Ok(file)) => Ok(file),
+        Some(Err(errno)) => Err(-errno),
+        None => match search_pwd(path) {
+            Some(vfile) => Ok(Arc::new(OSInode::new(true, false, vfile))),
+            None => Err(-ENOENT),
+        },
+    }
+}
+
+/// sys_fstatat 系统调用，按相对于目录 `fd` 的路径取得文件状态，不需要先打开文件。
+/// 内核没有符号链接，flags 被忽略；支持 /proc、tmpfs 和 FAT 上的文件，`path` 不存在返回 -ENOENT
+pub fn sys_fstatat(fd: i64, path: *const u8, kst: *mut Stat) -> isize {
+    trace!("kernel:pid[{}] sys_fstatat", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let kst = match UserPtr::writable(token, kst) {
+        Ok(kst) => kst,
+        Err(errno) => return -errno,
+    };
+    let file = match open_at_for_stat(fd, &path) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    match kst.write(file.stat()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// 打开相对于目录 `fd` 的路径 `path` 以取得状态，fstatat 和 faccessat 共用，失败时返回 -errno
+fn open_at_for_stat(fd: i64, path: &str) -> Result<Arc<dyn File + Send + Sync>, isize> {
+    if fd as isize == AT_FDCWD || path.starts_with('/') {
+        open_for_stat(&real_path(path))
+    } else if let Some(full) = dirfd_path(fd, path) {
+        // 目录记录了路径（如 /proc 下的目录）时按完整路径查找，可以越过挂载点
+        open_for_stat(&full)
     } else {
-        return -1;
+        open_file(fd, path, OpenFlags::RDONLY).map(|inode| inode as Arc<dyn File + Send + Sync>).ok_or(-ENOENT)
+    }
+}
+
+/// faccessat 的 `mode`：可读
+const R_OK: u32 = 4;
+/// faccessat 的 `mode`：可写
+const W_OK: u32 = 2;
+/// faccessat 的 `mode`：可执行
+const X_OK: u32 = 1;
+
+/// sys_faccessat 系统调用，检查相对于目录 `fd` 的路径 `path` 是否存在以及能否按 `mode` 访问。
+/// 与 fstatat 一样查找 /proc、tmpfs 和 FAT；按 stat 报告的所有者权限位判断读写，
+/// 只读挂载的 FAT 上的文件不可写（-EROFS）。FAT 不保存执行权限，exec 也不检查，普通文件都可执行
+pub fn sys_faccessat(fd: i64, path: *const u8, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_faccessat", current_process().getpid());
+    let token = current_user_token();
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if mode & !(R_OK | W_OK | X_OK) != 0 {
+        return -EINVAL;
+    }
+    let file = match open_at_for_stat(fd, &path) {
+        Ok(file) => file,
+        Err(errno) => return errno,
+    };
+    let stat = file.stat();
+    if mode & R_OK != 0 && !stat.mode.contains(StatMode::OWNER_READ) {
+        return -EACCES;
+    }
+    if mode & W_OK != 0 {
+        let cwd_relative = fd as isize == AT_FDCWD || path.starts_with('/');
+        if file.vfile().is_some() && fat_read_only(fd, &path, &real_path(&path), cwd_relative) {
+            return -EROFS;
+        }
+        if !stat.mode.contains(StatMode::OWNER_WRITE) {
+            return -EACCES;
+        }
+    }
+    if mode & X_OK != 0 && stat.mode.contains(StatMode::DIR) && !stat.mode.contains(StatMode::OWNER_EXEC) {
+        return -EACCES;
     }
     0
 }
@@ -247,10 +662,25 @@ pub fn sys_fstat(fd:usize, lkstat:*mut u8) -> isize {
 /// sys_unlink 系统调用，删除文件或目录
 pub fn sys_unlink(dir:i32, path: *const u8) -> isize {
     let token = current_user_token();
-    let mut path = translated_str(token, path);
-    if path.chars().next().unwrap() == '/' {
-        if let Some(vfile) = search_pwd(path.as_str()) {
-            vfile.remove();
+    let mut path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if dir as isize == AT_FDCWD || path.starts_with('/') {
+        let path = real_path(&path);
+        remove_fifo(&path);
+        if let Some(result) = tmpfs_unlink(&path) {
+            return result;
+        }
+        if let Some(vfile) = search_pwd(&path) {
+            if path_read_only(&path) {
+                return -EROFS;
+            }
+            page_cache_invalidate(&vfile);
+            dcache_invalidate(&path);
+            if let Err(err) = vfile.remove() {
+                return -fat_errno(err);
+            }
         } else {
             return -1;
         }
@@ -258,34 +688,25 @@ pub fn sys_unlink(dir:i32, path: *const u8) -> isize {
         if path.chars().next().unwrap() == '.' {
             path = path[2..].to_string();
         }
-        if dir as isize == AT_FDCWD {
-            let task = current_task().unwrap();
-            let inner = task.inner_exclusive_access();
-            let mut pwd = inner.pwd.clone();
-            if pwd != "/" {
-                pwd.push_str("/");
-            }
-            pwd.push_str(&path);
-            if let Some(vfile) = search_pwd(path.as_str()) {
-                vfile.remove();
-            } else {
-                return -1;
-            }
-        } else {
-            let task = current_task().unwrap();
-            let inner = task.inner_exclusive_access();
-            if let Some(file) = &inner.fd_table[dir as usize] {
-                let osinode = file.as_osinode().unwrap();
-                let vfile = osinode.inner.exclusive_access().inode.clone();
-                let path: Vec<&str> = path.split('/').collect();
-                if let Some(vfile1) = vfile.find_vfile_bypath(path) {
-                    vfile1.remove();       
-                } else {
-                    return -1;
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        if let Some(vfile) = inner.fd_table.get(dir as usize).and_then(|file| file.as_ref()?.vfile()) {
+            let path: Vec<&str> = path.split('/').collect();
+            if let Some(vfile1) = vfile.find_vfile_bypath(path).ok().flatten() {
+                if vfile_read_only(&vfile1) {
+                    return -EROFS;
+                }
+                page_cache_invalidate(&vfile1);
+                // 不知道目录的路径，清空整个目录缓存
+                dcache_invalidate_all();
+                if let Err(err) = vfile1.remove() {
+                    return -fat_errno(err);
                 }
             } else {
                 return -1;
             }
+        } else {
+            return -1;
         }
     }
     0
@@ -308,25 +729,8 @@ pub fn sys_uname(utsname:*mut u8) -> isize {
     all[65*4..65*4+machine.len()].copy_from_slice(machine.as_bytes());
     all[65*5..65*5+domainname.len()].copy_from_slice(domainname.as_bytes());
 
-    let mut ti = translated_byte_buffer(token,  utsname, 65*6 as usize);
-    let total_bytes = 65*6;
-    let mut bytes_written = 0;
-    let src_ptr = all.as_ptr();
-    for slice in ti.iter_mut(){
-        let slice_len = slice.len();
-        let mut offset = 0;
-        while offset < slice_len && bytes_written < total_bytes{
-            unsafe {
-                let to_write = (total_bytes - bytes_written).min(slice_len - offset);
-                let ptr = slice.as_mut_ptr().add(offset);
-                copy_nonoverlapping(src_ptr.add(bytes_written), ptr, to_write);
-            }
-            offset += slice_len;
-            bytes_written += slice_len;
-        }
-        if bytes_written >= total_bytes {
-            break;
-        }
+    if copy_to_user(token, utsname, &all).is_err() {
+        return -EFAULT;
     }
     0
 }
@@ -334,50 +738,88 @@ pub fn sys_uname(utsname:*mut u8) -> isize {
 /// sys_getdents64 系统调用，读取目录项
 pub fn sys_getdents64(fd:usize, buf:*mut u8, len:usize) -> isize {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd < inner.fd_table.len() && !inner.fd_table[fd].is_none() {
-        let file = &inner.fd_table[fd];
-        let vfile = file.clone().unwrap().as_osinode().unwrap().inner.exclusive_access().inode.clone();
-        let all = vfile.dirent_info().unwrap().to_bytes();
-        let mut ti = translated_byte_buffer(token,  buf, len as usize);
-        let total_bytes = len;
-        let mut bytes_written = 0;
-        let src_ptr = all.as_ptr();
-        for slice in ti.iter_mut(){
-            let slice_len = slice.len();
-            let mut offset = 0;
-            while offset < slice_len && bytes_written < total_bytes{
-                unsafe {
-                    let to_write = (total_bytes - bytes_written).min(slice_len - offset);
-                    let ptr = slice.as_mut_ptr().add(offset);
-                    copy_nonoverlapping(src_ptr.add(bytes_written), ptr, to_write);
-                }
-                offset += slice_len;
-                bytes_written += slice_len;
-            }
-            if bytes_written >= total_bytes {
-                break;
-            }
-        }
-    } else {
-        return -1;
+    // linux_dirent64 以 8 字节对齐
+    let buf = match UserSlice::writable(token, buf, len, align_of::<u64>()) {
+        Ok(buf) => buf,
+        Err(errno) => return -errno,
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    // 一次最多返回一页的目录项，剩下的由下一次调用从目录的当前位置继续读取
+    let mut dirents = vec![0u8; len.min(PAGE_SIZE)];
+    let written = file.dirents(&mut dirents);
+    if written < 0 {
+        return written;
     }
-    return len as isize;
+    match buf.write_slice(&dirents[..written as usize]) {
+        Ok(_) => written,
+        Err(errno) => -errno,
+    }
+}
+
+/// 从 tmpfs 的挂载选项中取出 size=<字节数>（可以带 k、m、g 后缀），没有指定时为 `TMPFS_SIZE`；
+/// 选项无法解析时返回 `None`
+fn tmpfs_size(data: &str) -> Option<usize> {
+    let Some(size) = data.split(',').find_map(|option| option.strip_prefix("size=")) else {
+        return Some(TMPFS_SIZE);
+    };
+    let (digits, shift) = match size.as_bytes().last()? {
+        b'k' | b'K' => (&size[..size.len() - 1], 10),
+        b'm' | b'M' => (&size[..size.len() - 1], 20),
+        b'g' | b'G' => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(1 << shift)
 }
 
-/// sys_mount 系统调用，挂载文件系统
-pub fn sys_mount(source:*const u8, target:*const u8, filesystem:*const u8, _flags:i64, data:*const u8) -> isize {
+/// mount 的标志：只读挂载
+const MS_RDONLY: i64 = 1;
+/// mount 的标志：改变已经挂载的文件系统的挂载方式
+const MS_REMOUNT: i64 = 32;
+
+/// sys_mount 系统调用，挂载文件系统；支持 "tmpfs"，data 中的 size= 指定字节预算；
+/// "vfat" 可以用 MS_RDONLY 只读挂载，用 MS_REMOUNT 改变读写方式
+pub fn sys_mount(source:*const u8, target:*const u8, filesystem:*const u8, flags:i64, data:*const u8) -> isize {
     let token = current_user_token();
-    let source = translated_str(token, source);
-    let target = translated_str(token, target);
-    let filesystem = translated_str(token, filesystem);
+    let strings = (
+        translated_str(token, source, PATH_MAX),
+        translated_str(token, target, PATH_MAX),
+        translated_str(token, filesystem, PATH_MAX),
+    );
+    let (source, target, filesystem) = match strings {
+        (Ok(source), Ok(target), Ok(filesystem)) => (source, target, filesystem),
+        (Err(errno), _, _) | (_, Err(errno), _) | (_, _, Err(errno)) => return -errno,
+    };
     let mut data1:String = String::new();
     if !data.is_null(){
-        data1 = translated_str(token, data);
+        let data = match translated_str(token, data, PATH_MAX) {
+            Ok(data) => data,
+            Err(errno) => return -errno,
+        };
+        data1 = data;
+    }
+    let read_only = flags & MS_RDONLY != 0;
+    // 重新挂载只改变已经挂载的 FAT 文件系统的读写方式，忽略 source 和 filesystem
+    if flags & MS_REMOUNT != 0 {
+        return remount_fat(&real_path(&target), read_only).unwrap_or(-EINVAL);
+    }
+    if filesystem == "tmpfs" {
+        let Some(size) = tmpfs_size(&data1) else {
+            return -EINVAL;
+        };
+        return mount_tmpfs(&real_path(&target), size);
     }
     if filesystem == "vfat" {
-        if let Some(inode) = open_file(AT_FDCWD as i64, &target, OpenFlags::from_bits(0).unwrap()) {
+        if let Some(device) = block_device_by_name(&source) {
+            return mount_fat(&real_path(&target), device, read_only);
+        }
+        // 测试用例中不存在的分区（如 /dev/vda2）：只检查挂载点
+        if let Some(inode) = open_file(AT_FDCWD as i64, &real_path(&target), OpenFlags::from_bits(0).unwrap()) {
             // todo()!
             return 0;    
         } else {
@@ -391,8 +833,17 @@ pub fn sys_mount(source:*const u8, target:*const u8, filesystem:*const u8, _flag
 /// sys_umount2 系统调用，卸载文件系统
 pub fn sys_umount2(target:*const u8, flags:i32) -> isize {
     let token = current_user_token();
-    let target = translated_str(token, target);
-    if let Some(inode) = open_file(AT_FDCWD as i64, &target, OpenFlags::from_bits(0).unwrap()) {
+    let target = match translated_str(token, target, PATH_MAX) {
+        Ok(target) => target,
+        Err(errno) => return -errno,
+    };
+    if let Some(result) = umount_tmpfs(&real_path(&target)) {
+        return result;
+    }
+    if let Some(result) = umount_fat(&real_path(&target)) {
+        return result;
+    }
+    if let Some(inode) = open_file(AT_FDCWD as i64, &real_path(&target), OpenFlags::from_bits(0).unwrap()) {
         // todo()!
         return 0;    
     } else {
@@ -16,6 +16,18 @@ const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 23;
 /// dup3
 const SYSCALL_DUP3: usize = 24;
+/// fcntl syscall
+const SYSCALL_FCNTL: usize = 25;
+/// inotify_init1 syscall
+const SYSCALL_INOTIFY_INIT1: usize = 26;
+/// inotify_add_watch syscall
+const SYSCALL_INOTIFY_ADD_WATCH: usize = 27;
+/// ioctl syscall
+const SYSCALL_IOCTL: usize = 29;
+/// flock syscall
+const SYSCALL_FLOCK: usize = 32;
+/// mknodat syscall
+const SYSCALL_MKNODAT: usize = 33;
 /// mkdir
 const SYSCALL_MKDIRT: usize = 34;
 /// unlinkat syscall
@@ -26,8 +38,16 @@ const SYSCALL_LINKAT: usize = 37;
 const SYSCALL_UMOUNNT2: usize = 39;
 /// mount
 const SYSCALL_MOUNT: usize = 40;
+/// faccessat
+const SYSCALL_FACCESSAT: usize = 48;
 /// chdir
 const SYSCALL_CHDIR: usize = 49;
+/// chroot
+const SYSCALL_CHROOT: usize = 51;
+/// fchmod
+const SYSCALL_FCHMOD: usize = 52;
+/// fchmodat
+const SYSCALL_FCHMODAT: usize = 53;
 /// open syscall
 const SYSCALL_OPEN: usize = 56;
 /// close syscall
@@ -40,18 +60,32 @@ const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 /// write syscall
 const SYSCALL_WRITE: usize = 64;
+/// lseek syscall
+const SYSCALL_LSEEK: usize = 62;
+/// fstatat syscall
+const SYSCALL_FSTATAT: usize = 79;
 /// fstat syscall
 const SYSCALL_FSTAT: usize = 80;
+/// sync syscall
+const SYSCALL_SYNC: usize = 81;
+/// fsync syscall
+const SYSCALL_FSYNC: usize = 82;
 /// exit syscall
 const SYSCALL_EXIT: usize = 93;
 /// nanosleep
 const SYSCALL_NANOSLEEP: usize = 101;
+/// clock_gettime syscall
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 /// yield syscall
 const SYSCALL_YIELD: usize = 124;
 /// setpriority syscall
 const SYSCALL_SET_PRIORITY: usize = 140;
 /// times
 const SYSCALL_TIMES: usize = 153;
+/// getrusage
+const SYSCALL_GETRUSAGE: usize = 165;
+/// prctl
+const SYSCALL_PRCTL: usize = 167;
 /// uname
 const SYSCALL_UNAME: usize = 160;
 /// gettime syscall
@@ -60,6 +94,24 @@ const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 /// getppid
 const SYSCALL_GETPPID: usize = 173;
+/// sysinfo
+const SYSCALL_SYSINFO: usize = 179;
+/// shmget
+const SYSCALL_SHMGET: usize = 194;
+/// shmctl
+const SYSCALL_SHMCTL: usize = 195;
+/// shmat
+const SYSCALL_SHMAT: usize = 196;
+/// shmdt
+const SYSCALL_SHMDT: usize = 197;
+/// socket
+const SYSCALL_SOCKET: usize = 198;
+/// bind
+const SYSCALL_BIND: usize = 200;
+/// sendto
+const SYSCALL_SENDTO: usize = 206;
+/// recvfrom
+const SYSCALL_RECVFROM: usize = 207;
 /// sbrk syscall
 const SYSCALL_BRK: usize = 214;
 /// munmap syscall
@@ -70,64 +122,171 @@ const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 /// mmap syscall
 const SYSCALL_MMAP: usize = 222;
+/// mprotect syscall
+const SYSCALL_MPROTECT: usize = 226;
 /// waitpid syscall
 const SYSCALL_WAITPID: usize = 260;
 /// spawn syscall
 const SYSCALL_SPAWN: usize = 400;
 /// taskinfo syscall
 const SYSCALL_TASK_INFO: usize = 410;
+/// ps syscall
+const SYSCALL_PS: usize = 411;
+/// print scheduler statistics to the console
+const SYSCALL_PERF_DUMP: usize = 412;
 /// fs
 pub const AT_FDCWD: isize = -100;
 /// shutdown
 pub const SYSCALL_SHUTDOWN: usize = 210;
+/// error number reported to user space, returned from syscalls negated
+pub type Errno = isize;
+/// no such file or directory
+pub const ENOENT: Errno = 2;
+/// input/output error
+pub const EIO: Errno = 5;
+/// no such device or address
+pub const ENXIO: Errno = 6;
+/// argument list too long
+pub const E2BIG: Errno = 7;
+/// exec format error
+pub const ENOEXEC: Errno = 8;
+/// bad file descriptor
+pub const EBADF: Errno = 9;
+/// resource temporarily unavailable
+pub const EAGAIN: Errno = 11;
+/// operation would block, the same as EAGAIN
+pub const EWOULDBLOCK: Errno = EAGAIN;
+/// out of memory
+pub const ENOMEM: Errno = 12;
+/// permission denied
+pub const EACCES: Errno = 13;
+/// bad address
+pub const EFAULT: Errno = 14;
+/// device or resource busy
+pub const EBUSY: Errno = 16;
+/// file exists
+pub const EEXIST: Errno = 17;
+/// no such device, e.g. a mapping the file cannot support
+pub const ENODEV: Errno = 19;
+/// not a directory
+pub const ENOTDIR: Errno = 20;
+/// is a directory
+pub const EISDIR: Errno = 21;
+/// invalid argument
+pub const EINVAL: Errno = 22;
+/// not a typewriter
+pub const ENOTTY: Errno = 25;
+/// no space left on device
+pub const ENOSPC: Errno = 28;
+/// illegal seek
+pub const ESPIPE: Errno = 29;
+/// read-only file system
+pub const EROFS: Errno = 30;
+/// broken pipe
+pub const EPIPE: Errno = 32;
+/// file name too long
+pub const ENAMETOOLONG: Errno = 36;
+/// directory not empty
+pub const ENOTEMPTY: Errno = 39;
+/// socket operation on non-socket
+pub const ENOTSOCK: Errno = 88;
+/// destination address required
+pub const EDESTADDRREQ: Errno = 89;
+/// message too long
+pub const EMSGSIZE: Errno = 90;
+/// protocol not supported
+pub const EPROTONOSUPPORT: Errno = 93;
+/// socket type not supported
+pub const ESOCKTNOSUPPORT: Errno = 94;
+/// address family not supported by protocol
+pub const EAFNOSUPPORT: Errno = 97;
+/// address already in use
+pub const EADDRINUSE: Errno = 98;
+/// cannot assign requested address
+pub const EADDRNOTAVAIL: Errno = 99;
+/// network is unreachable
+pub const ENETUNREACH: Errno = 101;
 mod fs;
+mod net;
 mod process;
 use fat32::ATTRIBUTE_DIRECTORY;
 use fs::*;
+use net::*;
 use process::*;
 
-use crate::{task::processor::update_time, timer::get_time};
+use crate::fs::Stat;
+use crate::task::{count_syscall, current_task};
 
 /// handle syscall exception with `syscall_id` and other arguments
 pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
-    let ms = get_time();
+    count_syscall(syscall_id);
     let result = match syscall_id {
         SYSCALL_OPEN => sys_openat(args[0] as i64, args[1] as *const u8, args[2] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_LSEEK => sys_lseek(args[0], args[1] as i64, args[2]),
+        SYSCALL_SYNC => sys_sync(),
+        SYSCALL_FSYNC => sys_fsync(args[0]),
         SYSCALL_DUP => sys_dup(args[0]),
         SYSCALL_DUP3 => sys_dup3(args[0], args[1]),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
         // SYSCALL_LINKAT => sys_linkat(args[1] as *const u8, args[3] as *const u8),
-        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_FORK => sys_fork(args[0], args[1], args[2], args[3], args[4]),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
+        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize, args[2] as *const usize),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2] as isize),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
         SYSCALL_MMAP => sys_mmap(args[0] as usize, args[1] as usize, args[2] as usize, args[3] as i32, args[4] as i32, args[5] as i32),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMCTL => sys_shmctl(args[0], args[1], args[2]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
+        SYSCALL_SOCKET => sys_socket(args[0], args[1], args[2]),
+        SYSCALL_BIND => sys_bind(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_SENDTO => sys_sendto(args[0], args[1] as *const u8, args[2], args[3] as u32, args[4] as *const u8, args[5]),
+        SYSCALL_RECVFROM => sys_recvfrom(args[0], args[1] as *mut u8, args[2], args[3] as u32, args[4] as *mut u8, args[5] as *mut u32),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
         SYSCALL_BRK => sys_brk(args[0] as *const i64),
         SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1] as u32),
+        SYSCALL_MKNODAT => sys_mknodat(args[0] as i64, args[1] as *const u8, args[2] as u32),
         SYSCALL_MKDIRT => sys_mkdirat(args[0] as i64, args[1] as *const u8, ATTRIBUTE_DIRECTORY),
         SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
-        SYSCALL_PIPE2 => sys_pipe2(args[0] as *mut u32),
+        SYSCALL_INOTIFY_INIT1 => sys_inotify_init1(args[0] as u32),
+        SYSCALL_INOTIFY_ADD_WATCH => sys_inotify_add_watch(args[0], args[1] as *const u8, args[2] as u32),
+        SYSCALL_FLOCK => sys_flock(args[0], args[1] as u32),
+        SYSCALL_CHROOT => sys_chroot(args[0] as *const u8),
+        SYSCALL_FACCESSAT => sys_faccessat(args[0] as i64, args[1] as *const u8, args[2] as u32),
+        SYSCALL_FCHMOD => sys_fchmod(args[0], args[1] as u32),
+        SYSCALL_FCHMODAT => sys_fchmodat(args[0] as i64, args[1] as *const u8, args[2] as u32),
+        SYSCALL_PIPE2 => sys_pipe2(args[0] as *mut u32, args[1] as u32),
         SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_SYSINFO => sys_sysinfo(args[0] as *mut SysInfo),
         SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *mut TimeVal, args[1] as *mut TimeVal),
-        SYSCALL_TIMES => sys_times(args[0] as *mut u64, ms),
-        SYSCALL_FSTAT => sys_fstat(args[0] as usize, args[1] as *mut u8),
+        SYSCALL_TIMES => sys_times(args[0] as *mut u64),
+        SYSCALL_FSTATAT => sys_fstatat(args[0] as i64, args[1] as *const u8, args[2] as *mut Stat),
+        SYSCALL_FSTAT => sys_fstat(args[0] as usize, args[1] as *mut Stat),
         SYSCALL_UNLINKAT => sys_unlink(args[0] as i32, args[1] as *const u8),
         SYSCALL_UNAME => sys_uname(args[0] as *mut u8),
         SYSCALL_GETDENTS64 => sys_getdents64(args[0] as usize, args[1] as *mut u8, args[2] as usize),
         SYSCALL_SHUTDOWN => sys_shutdown(),
+        SYSCALL_PRCTL => sys_prctl(args[0], args[1]),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut RUsage),
+        SYSCALL_PS => sys_ps(args[0] as *mut PsEntry, args[1]),
+        SYSCALL_PERF_DUMP => sys_perf_dump(),
         SYSCALL_MOUNT => sys_mount(args[0] as *const u8, args[1] as *const u8, args[2] as *const u8, args[3] as i64, args[4] as *const u8),
         SYSCALL_UMOUNNT2 => sys_umount2(args[0] as *const u8, args[1] as i32),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        _ => {
+            let task = current_task().unwrap();
+            panic!("Unsupported syscall_id: {} (pid {}, {})", syscall_id, task.getpid(), task.name())
+        }
     };
-    let ms1 = get_time();
-    update_time(ms1-ms);
     return result;
 }
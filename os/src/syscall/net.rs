@@ -0,0 +1,156 @@
+//! 套接字相关的系统调用，只支持 AF_INET/SOCK_DGRAM（UDP）
+//!
+//! 地址使用 Linux 的 `struct sockaddr_in`：2 字节的地址族（主机字节序），
+//! 2 字节的端口和 4 字节的 IPv4 地址（网络字节序），再填充 8 字节的 0。
+
+use super::{
+    Errno, EAFNOSUPPORT, EBADF, EDESTADDRREQ, EFAULT, EINVAL, EMSGSIZE, ENOTSOCK, EPROTONOSUPPORT, ESOCKTNOSUPPORT,
+};
+use crate::fs::File;
+use crate::mm::{UserPtr, UserSlice};
+use crate::net::{Ipv4Addr, UdpSocket, UDP_MAX_PAYLOAD};
+use crate::task::{current_process, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec;
+
+/// IPv4 地址族
+const AF_INET: usize = 2;
+/// 数据报套接字
+const SOCK_DGRAM: usize = 2;
+/// socket 的 type 参数中的 SOCK_NONBLOCK 标志
+const SOCK_NONBLOCK: usize = 0o4000;
+/// socket 的 type 参数中的 SOCK_CLOEXEC 标志，没有 exec 时关闭描述符的支持，被忽略
+const SOCK_CLOEXEC: usize = 0o2000000;
+/// UDP 的协议号，protocol 为 0 时也表示 UDP
+const IPPROTO_UDP: usize = 17;
+/// recvfrom 的 MSG_DONTWAIT 标志：没有数据报时不阻塞
+const MSG_DONTWAIT: u32 = 0x40;
+/// `struct sockaddr_in` 的大小
+const SOCKADDR_IN_LEN: usize = 16;
+
+/// 取出描述符 `fd` 对应的文件，不存在时返回 EBADF，不是套接字时返回 ENOTSOCK
+fn socket_file(fd: usize) -> Result<Arc<dyn File + Send + Sync>, Errno> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(EBADF),
+    };
+    if file.as_udp_socket().is_none() {
+        return Err(ENOTSOCK);
+    }
+    Ok(file)
+}
+
+/// 从用户空间读取长度为 `len` 的 `struct sockaddr_in`，返回地址和端口
+fn read_sockaddr_in(addr: *const u8, len: usize) -> Result<(Ipv4Addr, u16), Errno> {
+    if len < SOCKADDR_IN_LEN {
+        return Err(EINVAL);
+    }
+    let mut raw = [0u8; SOCKADDR_IN_LEN];
+    UserSlice::readable(current_user_token(), addr, SOCKADDR_IN_LEN, 1)?.read(&mut raw)?;
+    if u16::from_ne_bytes([raw[0], raw[1]]) as usize != AF_INET {
+        return Err(EAFNOSUPPORT);
+    }
+    let port = u16::from_be_bytes([raw[2], raw[3]]);
+    let addr = u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]);
+    Ok((addr, port))
+}
+
+/// 把地址和端口以 `struct sockaddr_in` 的格式写入用户空间的 `addr`，`*len` 是缓冲区的大小，
+/// 放不下的部分被截断；之后把 `*len` 改为地址的实际大小
+fn write_sockaddr_in(addr: *mut u8, len: *mut u32, ip: Ipv4Addr, port: u16) -> Result<(), Errno> {
+    let token = current_user_token();
+    let len = UserPtr::writable(token, len)?;
+    let capacity = len.read()? as usize;
+    let mut raw = [0u8; SOCKADDR_IN_LEN];
+    raw[..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+    raw[2..4].copy_from_slice(&port.to_be_bytes());
+    raw[4..8].copy_from_slice(&ip.to_be_bytes());
+    let capacity = capacity.min(SOCKADDR_IN_LEN);
+    UserSlice::writable(token, addr, capacity, 1)?.write_slice(&raw[..capacity])?;
+    len.write(SOCKADDR_IN_LEN as u32)
+}
+
+/// sys_socket 系统调用，创建一个 UDP 套接字并返回它的描述符
+pub fn sys_socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    trace!("kernel:pid[{}] sys_socket", current_process().getpid());
+    if domain != AF_INET {
+        return -EAFNOSUPPORT;
+    }
+    if ty & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != SOCK_DGRAM {
+        return -ESOCKTNOSUPPORT;
+    }
+    if protocol != 0 && protocol != IPPROTO_UDP {
+        return -EPROTONOSUPPORT;
+    }
+    let socket = UdpSocket::new(ty & SOCK_NONBLOCK != 0);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(socket);
+    fd as isize
+}
+
+/// sys_bind 系统调用，把套接字绑定到本机地址和端口，端口为 0 时分配一个临时端口
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    trace!("kernel:pid[{}] sys_bind", current_process().getpid());
+    let result = socket_file(fd).and_then(|file| {
+        let (addr, port) = read_sockaddr_in(addr, addrlen)?;
+        file.as_udp_socket().unwrap().bind(addr, port)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// sys_sendto 系统调用，把 `buf` 中的 `len` 字节作为一个数据报发往 `dest_addr`，返回发送的字节数。
+/// 套接字不支持 connect，`dest_addr` 不能为空
+pub fn sys_sendto(fd: usize, buf: *const u8, len: usize, _flags: u32, dest_addr: *const u8, addrlen: usize) -> isize {
+    trace!("kernel:pid[{}] sys_sendto", current_process().getpid());
+    let result = socket_file(fd).and_then(|file| {
+        if dest_addr.is_null() {
+            return Err(EDESTADDRREQ);
+        }
+        let (addr, port) = read_sockaddr_in(dest_addr, addrlen)?;
+        // 先检查长度，避免为过长的数据报复制用户数据
+        if len > UDP_MAX_PAYLOAD {
+            return Err(EMSGSIZE);
+        }
+        let mut data = vec![0u8; len];
+        UserSlice::readable(current_user_token(), buf, len, 1)?.read(&mut data)?;
+        file.as_udp_socket().unwrap().send_to(&data, addr, port)
+    });
+    match result {
+        Ok(sent) => sent as isize,
+        Err(errno) => -errno,
+    }
+}
+
+/// sys_recvfrom 系统调用，取出一个数据报放入 `buf`，返回放入的字节数，放不下的部分被丢弃。
+/// `src_addr` 不为空时在其中写入数据报的来源。没有数据报时阻塞，`flags` 含有 MSG_DONTWAIT 时返回 -EAGAIN
+pub fn sys_recvfrom(fd: usize, buf: *mut u8, len: usize, flags: u32, src_addr: *mut u8, addrlen: *mut u32) -> isize {
+    trace!("kernel:pid[{}] sys_recvfrom", current_process().getpid());
+    if flags & !MSG_DONTWAIT != 0 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let result = socket_file(fd).and_then(|file| {
+        // 先检查缓冲区，避免取出数据报之后才发现无法写入而丢失它
+        let buf = UserSlice::writable(token, buf, len, 1)?;
+        if !src_addr.is_null() && addrlen.is_null() {
+            return Err(EFAULT);
+        }
+        let datagram = file.as_udp_socket().unwrap().recv_from(flags & MSG_DONTWAIT != 0)?;
+        let received = buf.write_slice(&datagram.data)?;
+        if !src_addr.is_null() {
+            write_sockaddr_in(src_addr, addrlen, datagram.addr, datagram.port)?;
+        }
+        Ok(received)
+    });
+    match result {
+        Ok(received) => received as isize,
+        Err(errno) => -errno,
+    }
+}
@@ -1,16 +1,17 @@
 //! 进程管理系统调用
 //!
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use crate::{
-    config::PAGE_SIZE, fs::{open_file, OpenFlags}, mm::{self, frame_alloc, page_table::PTEFlags, translated_byte_buffer, translated_ref, translated_refmut, translated_str, VPNRange, VirtAddr }, syscall::AT_FDCWD, task::{
-        add_task, current_task, current_user_token, exit_current_and_run_next, processor::{map_one, unmap_one}, suspend_current_and_run_next, TaskInfo
-    }, timer::{get_time, get_time_us}
+    config::{CLOCK_FREQ, PAGE_SIZE, PATH_MAX, TASK_COMM_LEN, USER_STACK_SIZE}, drivers::rtc::{read_epoch, NSEC_PER_SEC}, fs::{open_file, real_path, OpenFlags}, mm::{frame_allocator, get_user, put_user, shm::{shm_find, shm_get, shm_remove, SharedSegment}, translated_byte_buffer_mut, translated_str, ExecError, UserPtr, MapFile, MapPermission, MemorySet, VPNRange, VirtAddr, VirtPageNum}, syscall::{Errno, AT_FDCWD, E2BIG, EFAULT, EINVAL, EIO, ENAMETOOLONG, ENODEV, ENOEXEC, ENOMEM}, task::{
+        add_task, current_process, current_task, current_user_token, exit_current_and_run_next, list_processes, pid_count, render_stats, sleep_current_and_run_next, suspend_current_and_run_next, ProcessControlBlock, TaskInfo, TaskStatus
+    }, timer::{get_time, get_time_ms, get_time_ns, get_time_us}
 };
-use core::ptr::write_unaligned;
 
 // 用于存储时间的结构体
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct TimeVal {
     pub sec: usize,  // 秒
     pub usec: usize, // 微秒
@@ -18,7 +19,12 @@ pub struct TimeVal {
 
 // 进程退出系统调用
 pub fn sys_exit(exit_code: i32) -> ! {
-    trace!("kernel:pid[{}] sys_exit", current_task().unwrap().pid.0);
+    trace!(
+        "kernel:pid[{}] ({}) sys_exit with code {}",
+        current_process().getpid(),
+        current_task().unwrap().name(),
+        exit_code
+    );
     exit_current_and_run_next(exit_code); // 退出当前进程并运行下一个进程
     panic!("Unreachable in sys_exit!"); // 如果代码运行到这里，则会发生错误
 }
@@ -31,45 +37,140 @@ pub fn sys_yield() -> isize {
 
 // 获取当前进程的 PID 系统调用
 pub fn sys_getpid() -> isize {
-    trace!("kernel: sys_getpid pid:{}", current_task().unwrap().pid.0);
-    current_task().unwrap().pid.0 as isize
+    trace!("kernel: sys_getpid pid:{}", current_process().getpid());
+    current_process().getpid() as isize
 }
 
-// 进程创建（fork）系统调用
+// 与父进程共享地址空间
+const CLONE_VM: usize = 0x100;
+// 新任务是当前进程中的一个线程
+const CLONE_THREAD: usize = 0x10000;
+// 新任务的 tp 设置为参数 `tls`
+const CLONE_SETTLS: usize = 0x80000;
+
+// 进程创建（fork/clone）系统调用
+//
+// 带 CLONE_THREAD 时在当前进程中创建线程（必须同时带 CLONE_VM），返回其线程号，
+// 否则创建子进程并返回其 pid；其余标志位和 `ptid`、`ctid` 目前被忽略
 pub fn sys_fork(flags:usize, stack:usize, ptid:usize, tls:usize, ctid:usize) -> isize {
-    trace!("kernel:pid[{}] sys_fork", current_task().unwrap().pid.0);
+    trace!("kernel:pid[{}] sys_fork flags {:#x}", current_process().getpid(), flags);
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork(); // 创建新进程
-    let new_pid = new_task.pid.0;
+    let (new_task, ret) = if flags & CLONE_THREAD != 0 {
+        if flags & CLONE_VM == 0 {
+            return -EINVAL;
+        }
+        // 创建新线程
+        let Ok(new_task) = current_task.process().clone_thread(&current_task) else {
+            return -ENOMEM; // 内存不足或陷阱上下文槽位用尽
+        };
+        let tid = new_task.tid;
+        (new_task, tid)
+    } else {
+        // 创建新进程
+        let Ok(new_task) = current_task.process().fork(&current_task) else {
+            return -ENOMEM; // 内存不足
+        };
+        let pid = new_task.getpid();
+        (new_task, pid)
+    };
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
     trap_cx.x[10] = 0; // 设置系统调用的返回值
     if stack != 0{
         trap_cx.set_sp(stack); // 如果指定了栈地址，则设置栈指针
     }
-    add_task(new_task); // 将新进程添加到调度队列
-    new_pid as isize
+    if flags & CLONE_SETTLS != 0 {
+        trap_cx.set_tp(tls); // 新任务使用自己的线程局部存储块
+    }
+    add_task(new_task); // 将新任务添加到调度队列
+    ret as isize
 }
 
-// 进程执行（exec）系统调用
-pub fn sys_exec(path: *const u8) -> isize {
-    trace!("kernel:pid[{}] sys_exec", current_task().unwrap().pid.0);
+// 进程执行（exec）系统调用，`argv` 和 `envp` 是以空指针结尾的字符串指针数组，可以为空
+pub fn sys_exec(path: *const u8, argv: *const usize, envp: *const usize) -> isize {
+    trace!("kernel:pid[{}] sys_exec", current_process().getpid());
     let token = current_user_token();
-    let path = translated_str(token, path); // 获取进程的路径
-    if let Some(app_inode) = open_file(AT_FDCWD as i64, path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all(); // 读取文件数据
+    // 获取进程的路径
+    let path = match translated_str(token, path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let (args, envs) = match (translated_str_array(token, argv), translated_str_array(token, envp)) {
+        (Ok(args), Ok(envs)) => (args, envs),
+        (Err(errno), _) | (_, Err(errno)) => return -errno,
+    };
+    if let Some(app_inode) = open_file(AT_FDCWD as i64, &real_path(&path), OpenFlags::RDONLY) {
+        let start = get_time_ms();
+        let vfile = app_inode.inner.exclusive_access().inode.clone();
         let task = current_task().unwrap();
-        task.exec(all_data.as_slice()); // 执行新程序
+        // 执行新程序，各段在缺页时才从文件中读取
+        let process = task.process();
+        if let Err(err) = process.exec(&task, comm_name(&path), &vfile, &args, &envs) {
+            return exec_errno(err);
+        }
+        trace!("kernel:pid[{}] exec {} took {} ms", process.getpid(), path, get_time_ms() - start);
         0
     } else {
         -1 // 文件打开失败
     }
 }
 
-// 等待指定进程结束的系统调用
+// 以程序路径的最后一个分量作为命令名
+fn comm_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// 将加载程序的错误转换为错误码
+fn exec_errno(err: ExecError) -> isize {
+    match err {
+        ExecError::NotElf => -ENOEXEC, // 不是合法的 elf 文件
+        ExecError::OutOfMemory => -ENOMEM, // 内存不足
+        ExecError::TooBig => -E2BIG, // 参数和环境变量放不进初始用户栈
+        ExecError::Io => -EIO, // 读取程序文件时磁盘出错
+    }
+}
+
+// 读取用户空间中以空指针结尾的字符串指针数组，`ptr` 为空时返回空数组；
+// 总长度超过初始用户栈的大小时返回 E2BIG
+fn translated_str_array(token: usize, ptr: *const usize) -> Result<Vec<String>, Errno> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
+    let mut total = 0;
+    loop {
+        let str_ptr = get_user(token, ptr.wrapping_add(strings.len())).map_err(|_| EFAULT)?;
+        if str_ptr == 0 {
+            return Ok(strings);
+        }
+        let string = match translated_str(token, str_ptr as *const u8, USER_STACK_SIZE) {
+            Err(ENAMETOOLONG) => return Err(E2BIG),
+            result => result?,
+        };
+        // 字符串本身、结尾的 \0 以及栈上的指针
+        total += string.len() + 1 + core::mem::size_of::<usize>();
+        if total > USER_STACK_SIZE {
+            return Err(E2BIG);
+        }
+        strings.push(string);
+    }
+}
+
+// waitpid 的选项：没有已退出的子进程时立即返回
+const WNOHANG: isize = 1;
+
+// 等待子进程退出的系统调用，`pid` 为 -1 时等待任意子进程；
+// 阻塞在当前进程的 child_exit_wq 上，每次被唤醒后重新检查子进程列表
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options:isize) -> isize{
+    // 在回收子进程之前检查用户指针
+    if !exit_code_ptr.is_null()
+        && translated_byte_buffer_mut(current_user_token(), exit_code_ptr as *mut u8, core::mem::size_of::<i32>()).is_err()
+    {
+        return -EFAULT;
+    }
     loop{
         match waitpid(pid, exit_code_ptr){ // 调用等待函数
-            -2 => {sys_yield();} // 如果没有找到进程，挂起当前进程
+            -2 if options & WNOHANG != 0 => return 0, // 不阻塞，没有已退出的子进程时返回 0
+            -2 => current_process().child_exit_wq.wait(), // 子进程尚未退出，阻塞到有子进程退出
             n => {return n;} // 返回子进程的 PID 或错误码
         }
     }
@@ -77,8 +178,8 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options:isize) -> isize{
 
 // 等待进程结束的实现函数
 pub fn waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     if !inner
         .children
         .iter()
@@ -94,9 +195,21 @@ pub fn waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         let child = inner.children.remove(idx); // 移除子进程
         assert_eq!(Arc::strong_count(&child), 1); // 确保子进程没有其他引用
         let found_pid = child.getpid();
-        let exit_code = child.inner_exclusive_access().exit_code;
+        let child_inner = child.inner_exclusive_access();
+        let exit_code = child_inner.exit_code;
+        // 被回收子进程（及其已回收的后代）的运行时间计入父进程
+        let child_info = child_inner.task_info;
+        let mut child_mem_stats = child_inner.memory_set.mem_stats();
+        child_mem_stats.merge(&child_inner.children_mem_stats);
+        drop(child_inner);
+        inner.task_info.update_cu(child_info.all - child_info.stime + child_info.cutime);
+        inner.task_info.update_cs(child_info.stime + child_info.cstime);
+        inner.children_mem_stats.merge(&child_mem_stats);
+        let token = inner.memory_set.token();
+        // 写用户内存时可能触发缺页，需要先释放 PCB
+        drop(inner);
         if exit_code_ptr != core::ptr::null_mut(){
-            *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code << 8; // 将退出码写入用户内存
+            let _ = put_user(token, exit_code_ptr, exit_code << 8); // 将退出码写入用户内存
         }
         found_pid as isize
     } else {
@@ -108,119 +221,361 @@ pub fn waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
 pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
     trace!(
         "kernel:pid[{}] sys_get_time NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
+        current_process().getpid()
     );
     let us = get_time_us(); // 获取当前时间（微秒）
     let tv_sec = us / 1_000_000;
     let tv_usec = us % 1_000_000;
-    let mut ts = translated_byte_buffer(current_user_token(), _ts as *const u8, core::mem::size_of::<TimeVal>());
+    let time_val = TimeVal { sec: tv_sec, usec: tv_usec };
+    let result = UserPtr::writable(current_user_token(), _ts).and_then(|ts| ts.write(time_val));
+    if let Err(errno) = result {
+        return -errno;
+    }
+    0
+}
+
+// 纳秒精度的时间，布局与 Linux 的 struct timespec 一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeSpec {
+    pub sec: usize,  // 秒
+    pub nsec: usize, // 纳秒
+}
+
+// 真实时间（自 1970-01-01 00:00:00 UTC 以来）
+const CLOCK_REALTIME: usize = 0;
+// 启动以来的时间
+const CLOCK_MONOTONIC: usize = 1;
+// 不受时间调整影响的启动以来的时间
+const CLOCK_MONOTONIC_RAW: usize = 4;
+// 精度较低的真实时间
+const CLOCK_REALTIME_COARSE: usize = 5;
+// 精度较低的启动以来的时间
+const CLOCK_MONOTONIC_COARSE: usize = 6;
+// 包括睡眠时间在内的启动以来的时间，内核不会挂起，与 CLOCK_MONOTONIC 相同
+const CLOCK_BOOTTIME: usize = 7;
+
+// 读取时钟的系统调用，真实时间来自实时时钟，其余时钟都是启动以来的时间
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> isize {
+    trace!("kernel:pid[{}] sys_clock_gettime", current_process().getpid());
+    let ns = match clock_id {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => read_epoch(),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => get_time_ns(),
+        _ => return -EINVAL,
+    };
+    let time_spec = TimeSpec {
+        sec: (ns / NSEC_PER_SEC) as usize,
+        nsec: (ns % NSEC_PER_SEC) as usize,
+    };
+    let result = UserPtr::writable(current_user_token(), tp).and_then(|tp| tp.write(time_spec));
+    if let Err(errno) = result {
+        return -errno;
+    }
+    0
+}
+
+// 系统整体信息，布局与 Linux 的 struct sysinfo 一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SysInfo {
+    pub uptime: isize,     // 启动以来的秒数
+    pub loads: [usize; 3], // 1、5、15 分钟平均负载
+    pub totalram: usize,   // 内存总量
+    pub freeram: usize,    // 空闲内存
+    pub sharedram: usize,  // 共享内存
+    pub bufferram: usize,  // 缓冲区占用的内存
+    pub totalswap: usize,  // 交换区总量
+    pub freeswap: usize,   // 空闲交换区
+    pub procs: u16,        // 进程数量
+    pub pad: u16,
+    pub totalhigh: usize,  // 高端内存总量
+    pub freehigh: usize,   // 空闲高端内存
+    pub mem_unit: u32,     // 内存大小的单位（字节）
+}
+
+// 获取系统信息系统调用
+pub fn sys_sysinfo(info: *mut SysInfo) -> isize {
+    trace!("kernel:pid[{}] sys_sysinfo", current_process().getpid());
+    let stats = frame_allocator::stats();
+    let sysinfo = SysInfo {
+        uptime: (get_time_ms() / 1000) as isize,
+        totalram: stats.total,
+        freeram: stats.free,
+        procs: pid_count() as u16,
+        mem_unit: PAGE_SIZE as u32,
+        ..Default::default()
+    };
+    if put_user(current_user_token(), info, sysinfo).is_err() {
+        return -EFAULT;
+    }
+    0
+}
+
+// ps 中进程的状态：就绪
+pub const PS_READY: usize = 0;
+// ps 中进程的状态：正在运行
+pub const PS_RUNNING: usize = 1;
+// ps 中进程的状态：所有线程都在睡眠或等待
+pub const PS_SLEEPING: usize = 2;
+// ps 中进程的状态：已退出，等待父进程回收
+pub const PS_ZOMBIE: usize = 3;
+
+// sys_ps 为每个进程填写的记录
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PsEntry {
+    pub pid: usize,
+    pub ppid: usize,
+    pub state: usize,                // PS_READY 等
+    pub priority: isize,             // 主线程的优先级
+    pub utime: u64,                  // 用户态运行时间（时钟周期）
+    pub stime: u64,                  // 内核态运行时间（时钟周期）
+    pub rss: usize,                  // 驻留内存的页数估计
+    pub name: [u8; TASK_COMM_LEN],   // 主线程的命令名，以 \0 结尾
+}
 
-    unsafe {
-        // 获取缓冲区的原始指针
-        let ptr = ts[0].as_mut_ptr() as *mut i64;
+// 生成进程 `process` 的 ps 记录，`now` 为当前时间
+fn ps_entry(process: &ProcessControlBlock, now: usize) -> PsEntry {
+    let inner = process.inner_exclusive_access();
+    let mut entry = PsEntry {
+        pid: process.getpid(),
+        ppid: inner.parent_pid(),
+        state: if inner.is_zombie() { PS_ZOMBIE } else { PS_SLEEPING },
+        rss: inner.memory_set.resident_pages(),
+        ..Default::default()
+    };
+    (entry.utime, entry.stime) = inner.cpu_times(now);
+    for (i, thread) in inner.threads.iter().flatten().enumerate() {
+        let thread_inner = thread.inner_exclusive_access();
+        match thread_inner.task_status {
+            TaskStatus::Running => entry.state = PS_RUNNING,
+            TaskStatus::Ready if entry.state == PS_SLEEPING => entry.state = PS_READY,
+            _ => {}
+        }
+        if i == 0 {
+            entry.priority = thread_inner.pri;
+            let name = thread_inner.name.as_bytes();
+            entry.name[..name.len()].copy_from_slice(name);
+        }
+    }
+    entry
+}
 
-        // 将 tv_sec 写入偏移 0 的位置
-        write_unaligned(ptr, tv_sec as i64);
+// 列出所有进程的系统调用，最多向 `buf` 写入 `count` 条记录，返回进程总数
+pub fn sys_ps(buf: *mut PsEntry, count: usize) -> isize {
+    trace!("kernel:pid[{}] sys_ps", current_process().getpid());
+    let now = get_time();
+    let processes = list_processes();
+    let entries: Vec<PsEntry> = processes.iter().map(|process| ps_entry(process, now)).collect();
+    drop(processes);
+    // 写用户内存时可能触发缺页，需要先生成全部记录
+    let token = current_user_token();
+    for (i, entry) in entries.iter().take(count).enumerate() {
+        if put_user(token, buf.wrapping_add(i), *entry).is_err() {
+            return -EFAULT;
+        }
+    }
+    entries.len() as isize
+}
 
-        // 将 tv_usec 写入偏移 8 的位置
-        write_unaligned(ptr.add(1), tv_usec as i64);        
+// 将调度统计和各进程的上下文切换次数打印到控制台，用于调试
+pub fn sys_perf_dump() -> isize {
+    print!("{}", render_stats());
+    for process in list_processes() {
+        let (nvcsw, nivcsw) = process.inner_exclusive_access().context_switches();
+        println!("pid {} voluntary {} involuntary {}", process.getpid(), nvcsw, nivcsw);
     }
     0
 }
 
+// 共享映射标志
+const MAP_SHARED: i32 = 0x01;
+// 固定地址映射标志
+const MAP_FIXED: i32 = 0x10;
+// 匿名映射标志
+const MAP_ANONYMOUS: i32 = 0x20;
+
 // 内存映射系统调用
 pub fn sys_mmap(_start: usize, _len: usize, _port: usize, flags:i32, fd:i32, offset:i32) -> isize {
-    trace!(
-        "kernel:pid[{}] sys_mmap NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
-    );
-    // 检查映射的起始地址和端口
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    let mut start:usize = _start;
-    if _start % 4096 != 0 || _port & !0x7 != 0 || _port & 0x7 == 0{
+    trace!("kernel:pid[{}] sys_mmap", current_process().getpid());
+    if _start % PAGE_SIZE != 0 || _len == 0 || _port & !0x7 != 0 || _port & 0x7 == 0 || offset < 0 {
         return -1; // 地址不对齐或端口无效
-    }else if _start == 0{
-        start = inner.program_brk + PAGE_SIZE * 8;
-    }
-    let start_va = VirtAddr::from(start).floor();
-    let end_va = VirtAddr::from(start + _len).ceil();
-    let vir = VPNRange::new(start_va, end_va);
-    let port = (_port as u8) << 5 >> 4;
-    let mut flag = PTEFlags::U;
-    drop(inner);
-    flag |= PTEFlags::from_bits(port).unwrap();
-    for vpn in vir{
-        let page_table = mm::page_table::PageTable::from_token(token);
-        let frame = frame_alloc().unwrap();
-        let result = page_table.translate(vpn);
-        match result{
-            Some(pey) => {
-                if !pey.is_valid(){
-                    map_one(vpn, frame.ppn, flag);
-                }else{
-                    return -1; // 页面已存在，无法映射
-                }
-            },
-            None => {
-                map_one(vpn, frame.ppn, flag);
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    // 文件映射：映射区域的页面在缺页时从文件中读取。
+    // 还不支持写回文件的共享文件映射，返回 -ENODEV 而不是悄悄按私有映射处理
+    let file = if flags & MAP_ANONYMOUS != 0 || fd < 0 {
+        None
+    } else if flags & MAP_SHARED != 0 {
+        return -ENODEV;
+    } else {
+        let vfile = match inner.fd_table.get(fd as usize) {
+            Some(Some(file)) => match file.vfile() {
+                Some(vfile) => vfile,
+                None => return -1,
             },
+            _ => return -1, // 文件映射失败
+        };
+        let Ok(file_size) = vfile.get_size() else {
+            return -EIO;
+        };
+        let file_size = (file_size as usize).saturating_sub(offset as usize);
+        Some((vfile, file_size.min(_len)))
+    };
+    let page_count = VirtAddr::from(_len).ceil().0;
+    let Some(vir) = choose_map_range(&inner.memory_set, inner.program_brk, _start, page_count, flags & MAP_FIXED != 0) else {
+        return -ENOMEM; // 页面已存在，无法映射
+    };
+    let start_va: VirtAddr = vir.get_start().into();
+    let end_va: VirtAddr = vir.get_end().into();
+    let perm = MapPermission::from_bits(((_port as u8) & 0x7) << 1).unwrap() | MapPermission::U;
+    if flags & MAP_SHARED != 0 && file.is_none() {
+        // 共享匿名映射：页面属于一个新的共享内存段，fork 后父子进程映射同一组页帧
+        let Ok(segment) = SharedSegment::new(_len) else {
+            return -ENOMEM;
+        };
+        if inner.memory_set.insert_shared_area(start_va, perm, segment).is_err() {
+            return -ENOMEM;
         }
+        return start_va.0 as isize;
     }
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if let Some(file) = &inner.fd_table[fd as usize] {
-        let osinode = file.as_osinode().unwrap();
-        let vfile = osinode.inner.exclusive_access().inode.clone();
-        let ts = translated_byte_buffer(token, (start_va.0 * PAGE_SIZE) as *const u8, vfile.get_size() as usize);
-        let mut read = 0;
-        for slice in ts{
-            let len = vfile.read_at(read,slice);
-            read += len;
-        }
-        return (start_va.0 * PAGE_SIZE) as *const u8 as isize;
-    }else{
-        drop(inner);
-        return -1; // 文件映射失败
+    let map_file = file.map(|(file, file_size)| MapFile {
+        file,
+        start_va: start_va.0,
+        offset: offset as usize,
+        file_size,
+    });
+    if inner.memory_set.insert_lazy_area(start_va, end_va, perm, map_file).is_err() {
+        return -ENOMEM; // 内存不足或地址冲突
+    }
+    start_va.0 as isize
+}
+
+// 为 page_count 页的映射选择虚拟页号范围：start 为 0 时从堆顶之上寻找，否则 start 作为提示；
+// 要求固定地址且该范围已被占用时返回 None
+fn choose_map_range(memory_set: &MemorySet, program_brk: usize, start: usize, page_count: usize, fixed: bool) -> Option<VPNRange> {
+    let hint = if start == 0 {
+        VirtAddr::from(program_brk + PAGE_SIZE * 8).ceil()
+    } else {
+        VirtAddr::from(start).floor()
+    };
+    let range = VPNRange::new(hint, VirtPageNum(hint.0 + page_count));
+    if !memory_set.overlaps(range) {
+        Some(range)
+    } else if fixed {
+        None
+    } else {
+        // 起始地址只是提示，换一段空闲的地址
+        Some(memory_set.find_free_range(hint, page_count))
+    }
+}
+
+// 以只读方式挂载共享内存段
+const SHM_RDONLY: usize = 0o10000;
+// 从注册表中删除共享内存段
+const IPC_RMID: usize = 0;
+
+// 获取共享内存段系统调用
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    trace!("kernel:pid[{}] sys_shmget", current_process().getpid());
+    match shm_get(key, size, shmflg) {
+        Ok(id) => id as isize,
+        Err(errno) => -errno,
+    }
+}
+
+// 挂载共享内存段系统调用
+pub fn sys_shmat(shmid: usize, shmaddr: usize, shmflg: usize) -> isize {
+    trace!("kernel:pid[{}] sys_shmat", current_process().getpid());
+    if shmaddr % PAGE_SIZE != 0 {
+        return -EINVAL; // 地址不对齐
+    }
+    let Some(segment) = shm_find(shmid) else {
+        return -EINVAL; // 段不存在
+    };
+    let perm = if shmflg & SHM_RDONLY != 0 {
+        MapPermission::R | MapPermission::U
+    } else {
+        MapPermission::R | MapPermission::W | MapPermission::U
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let Some(vir) = choose_map_range(&inner.memory_set, inner.program_brk, shmaddr, segment.page_count(), shmaddr != 0) else {
+        return -EINVAL; // 指定的地址已被占用
+    };
+    let start_va: VirtAddr = vir.get_start().into();
+    if inner.memory_set.insert_shared_area(start_va, perm, segment).is_err() {
+        return -ENOMEM;
+    }
+    start_va.0 as isize
+}
+
+// 卸载共享内存段系统调用
+pub fn sys_shmdt(shmaddr: usize) -> isize {
+    trace!("kernel:pid[{}] sys_shmdt", current_process().getpid());
+    if shmaddr % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.detach_shared(VirtAddr::from(shmaddr).floor()) {
+        0
+    } else {
+        -EINVAL // 该地址没有挂载共享内存段
+    }
+}
+
+// 控制共享内存段系统调用，目前只支持 IPC_RMID
+pub fn sys_shmctl(shmid: usize, cmd: usize, _buf: usize) -> isize {
+    trace!("kernel:pid[{}] sys_shmctl", current_process().getpid());
+    if cmd != IPC_RMID {
+        return -EINVAL;
+    }
+    if shm_remove(shmid) {
+        0
+    } else {
+        -EINVAL // 段不存在
     }
 }
 
 // 内存解除映射系统调用
-pub fn sys_munmap(_start: usize, _len: usize) -> isize {
-    trace!(
-        "kernel:pid[{}] sys_munmap NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
-    );
-    if _start % 4096 != 0{
-        return -1; // 地址不对齐
-    }
-    let start_va = VirtAddr::from(_start).floor();
-    let end_va = VirtAddr::from(_start + _len).ceil();
-    let vir = VPNRange::new(start_va, end_va);    
-    for vpn in vir{
-        let page_table = mm::page_table::PageTable::from_token(current_user_token());
-        let result = page_table.translate(vpn);
-        match result{
-            Some(pey) => {
-                if !pey.is_valid(){
-                    return -1; // 页面无效
-                }
-                unmap_one(vpn); // 解除映射
-            },
-            None => return -1, // 未找到页面
-        }
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    trace!("kernel:pid[{}] sys_munmap", current_process().getpid());
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.memory_set.unmap_user(start, len) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// 修改内存权限系统调用
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    trace!("kernel:pid[{}] sys_mprotect", current_process().getpid());
+    if prot & !0x7 != 0 {
+        return -EINVAL; // 端口无效
+    }
+    let mut perm = MapPermission::from_bits(((prot as u8) & 0x7) << 1).unwrap() | MapPermission::U;
+    if perm.contains(MapPermission::W) {
+        // 页表项不支持只写，可写的页面同时可读
+        perm |= MapPermission::R;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.memory_set.protect(start, len, perm) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
     }
-    0
 }
 
 // 进程内存增长系统调用
 pub fn sys_brk(size: *const i64) -> isize {
-    trace!("kernel:pid[{}] sys_sbrk", current_task().unwrap().pid.0);
-    if let Some(new_brk) = current_task().unwrap().change_program_brk(size as i64) {
+    trace!("kernel:pid[{}] sys_sbrk", current_process().getpid());
+    if let Some(new_brk) = current_process().change_program_brk(size as i64) {
         new_brk as isize
     } else {
-        -1 // 内存增长失败
+        -ENOMEM // 内存增长失败
     }
 }
 
@@ -228,15 +583,21 @@ pub fn sys_brk(size: *const i64) -> isize {
 pub fn sys_spawn(_path: *const u8) -> isize {
     trace!(
         "kernel:pid[{}] sys_spawn NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
+        current_process().getpid()
     );
     let token = current_user_token();
-    let path = translated_str(token, _path);
-    if let Some(app_inode) = open_file(AT_FDCWD as i64, path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let new_task = task.spawn(all_data.as_slice()); // 启动新进程
-        let new_pid = new_task.pid.0;
+    let path = match translated_str(token, _path, PATH_MAX) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    if let Some(app_inode) = open_file(AT_FDCWD as i64, &real_path(&path), OpenFlags::RDONLY) {
+        let vfile = app_inode.inner.exclusive_access().inode.clone();
+        // 启动新进程，以路径作为 argv[0]
+        let new_task = match current_process().spawn(comm_name(&path), &vfile, &[path.clone()]) {
+            Ok(new_task) => new_task,
+            Err(err) => return exec_errno(err),
+        };
+        let new_pid = new_task.getpid();
         add_task(new_task); // 将新进程添加到调度队列
         new_pid as isize
     } else {
@@ -248,7 +609,7 @@ pub fn sys_spawn(_path: *const u8) -> isize {
 pub fn sys_set_priority(_prio: isize) -> isize {
     trace!(
         "kernel:pid[{}] sys_set_priority NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
+        current_process().getpid()
     );
     if _prio <= 1{
         return -1; // 无效的优先级值
@@ -260,50 +621,169 @@ pub fn sys_set_priority(_prio: isize) -> isize {
 
 // 获取父进程的 PID 系统调用
 pub fn sys_getppid() -> isize{
-    current_task().unwrap().ppid as isize
+    current_process().getppid() as isize
 }
 
 // 纳秒级睡眠系统调用
 pub fn sys_nanosleep(ti:*mut TimeVal, te:*mut TimeVal) -> isize{
     let us = get_time_us(); // 获取当前时间（微秒）
     let token = current_user_token();
-    let target = translated_ref(token, ti);
+    let Ok(target) = get_user(token, ti as *const TimeVal) else {
+        return -EFAULT;
+    };
     let t_us = target.sec * 1_000_000 + target.usec;
-    loop{
-        let now = get_time_us();
-        if now - us < t_us{
-            suspend_current_and_run_next(); // 睡眠并让出 CPU
-        }else{
-            return 0; // 睡眠时间结束
-        }
+    // 挂到定时器队列上，由时钟中断在到期后唤醒
+    sleep_current_and_run_next(us + t_us);
+    0
+}
+
+// 获取进程时间信息的系统调用
+pub fn sys_times(time:*mut u64) -> isize{
+    let token = current_user_token();
+    // 检查用户指针时可能触发缺页，需要在借用 TCB 之前完成
+    let time = match UserPtr::writable(token, time as *mut [u64; 4]) {
+        Ok(time) => time,
+        Err(errno) => return -errno,
+    };
+    let task = current_task().unwrap();
+    let info = *task.inner_exclusive_access().task_info;
+    // 已退出线程的时间累计在进程中
+    let process_info = task.process().inner_exclusive_access().task_info;
+    let now = get_time();
+    // 本次系统调用已经运行的时间尚未计入 stime
+    let stime = info.sys_time(now);
+    let utime = info.cpu_time(now) - stime;
+    let values = [
+        utime + process_info.all - process_info.stime,
+        stime + process_info.stime,
+        process_info.cutime,
+        process_info.cstime,
+    ];
+    if let Err(errno) = time.write(values) {
+        return -errno;
     }
+    return info.cpu_time(now) as isize;
+}
+
+// getrusage 统计调用进程自身
+const RUSAGE_SELF: isize = 0;
+// getrusage 统计已回收的子进程
+const RUSAGE_CHILDREN: isize = -1;
+// getrusage 统计调用线程
+const RUSAGE_THREAD: isize = 1;
+
+// getrusage 返回的资源使用统计，布局与 Linux 的 struct rusage 相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,  // 用户态运行时间
+    pub ru_stime: TimeVal,  // 内核态运行时间
+    pub ru_maxrss: isize,   // RSS 峰值（KiB）
+    pub ru_ixrss: isize,
+    pub ru_idrss: isize,
+    pub ru_isrss: isize,
+    pub ru_minflt: isize,   // 次要缺页次数
+    pub ru_majflt: isize,   // 主要缺页次数
+    pub ru_nswap: isize,
+    pub ru_inblock: isize,
+    pub ru_oublock: isize,
+    pub ru_msgsnd: isize,
+    pub ru_msgrcv: isize,
+    pub ru_nsignals: isize,
+    pub ru_nvcsw: isize,    // 主动让出处理器的次数
+    pub ru_nivcsw: isize,   // 被抢占的次数
+}
+
+// 将时钟周期数转换为 TimeVal
+fn cycles_to_timeval(cycles: u64) -> TimeVal {
+    let us = cycles as usize / (CLOCK_FREQ / 1_000_000);
+    TimeVal { sec: us / 1_000_000, usec: us % 1_000_000 }
 }
 
-// 获取进程时间信息系统调用
-pub fn sys_times(time:*mut u64, ms:usize) -> isize{
+// 获取资源使用统计的系统调用，`who` 为 RUSAGE_SELF、RUSAGE_CHILDREN 或 RUSAGE_THREAD；
+// 缺页次数和 RSS 峰值按进程统计，RUSAGE_THREAD 也返回进程的值
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
     let token = current_user_token();
+    let usage = match UserPtr::writable(token, usage) {
+        Ok(usage) => usage,
+        Err(errno) => return -errno,
+    };
     let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    let utime = inner.task_info.all - inner.task_info.stime;
-    let mut cutime:u64 = 0;
-    let mut cstime:u64 = 0;
-    let ms1 = get_time() as u64;
-    for child in inner.children.iter(){
-        let little = child.inner_exclusive_access();
-        if little.is_zombie(){
-            cutime += little.task_info.cutime;
-            cstime += little.task_info.cstime;
+    let process = task.process();
+    let now = get_time();
+    let inner = process.inner_exclusive_access();
+    let (utime, stime, mem_stats, (nvcsw, nivcsw)) = match who {
+        RUSAGE_SELF => {
+            let (utime, stime) = inner.cpu_times(now);
+            (utime, stime, inner.memory_set.mem_stats(), inner.context_switches())
+        }
+        RUSAGE_CHILDREN => (inner.task_info.cutime, inner.task_info.cstime, inner.children_mem_stats, (0, 0)),
+        RUSAGE_THREAD => {
+            let thread_inner = task.inner_exclusive_access();
+            let info = &thread_inner.task_info;
+            (
+                info.cpu_time(now) - info.sys_time(now),
+                info.sys_time(now),
+                inner.memory_set.mem_stats(),
+                (thread_inner.nvcsw, thread_inner.nivcsw),
+            )
+        }
+        _ => return -EINVAL,
+    };
+    drop(inner);
+    let rusage = RUsage {
+        ru_utime: cycles_to_timeval(utime),
+        ru_stime: cycles_to_timeval(stime),
+        ru_maxrss: (mem_stats.peak_rss * PAGE_SIZE / 1024) as isize,
+        ru_minflt: mem_stats.minor_faults as isize,
+        ru_majflt: mem_stats.major_faults as isize,
+        ru_nvcsw: nvcsw as isize,
+        ru_nivcsw: nivcsw as isize,
+        ..Default::default()
+    };
+    if let Err(errno) = usage.write(rusage) {
+        return -errno;
+    }
+    0
+}
+
+// 设置当前线程的命令名
+const PR_SET_NAME: usize = 15;
+// 读取当前线程的命令名
+const PR_GET_NAME: usize = 16;
+
+// 进程控制系统调用，目前只支持设置和读取命令名，`arg2` 指向 TASK_COMM_LEN 字节的缓冲区
+pub fn sys_prctl(option: usize, arg2: usize) -> isize {
+    let token = current_user_token();
+    let buf = arg2 as *mut [u8; TASK_COMM_LEN];
+    match option {
+        PR_SET_NAME => {
+            let Ok(bytes) = get_user(token, buf as *const [u8; TASK_COMM_LEN]) else {
+                return -EFAULT;
+            };
+            let len = bytes.iter().position(|&b| b == 0).unwrap_or(TASK_COMM_LEN);
+            let name = String::from_utf8_lossy(&bytes[..len]);
+            current_task().unwrap().inner_exclusive_access().set_name(&name);
+            0
         }
+        PR_GET_NAME => {
+            let mut bytes = [0u8; TASK_COMM_LEN];
+            let name = current_task().unwrap().name();
+            bytes[..name.len()].copy_from_slice(name.as_bytes());
+            if put_user(token, buf, bytes).is_err() {
+                return -EFAULT;
+            }
+            0
+        }
+        _ => -EINVAL,
     }
-    *translated_refmut(token, time) = utime + ms1 - inner.task_info.start;
-    *translated_refmut(token, unsafe { time.add(1) }) = inner.task_info.stime + ms1 - ms as u64;
-    *translated_refmut(token, unsafe { time.add(2) }) = cutime;
-    *translated_refmut(token, unsafe { time.add(3) }) = cstime;
-    return inner.task_info.all as isize;
 }
 
-// 系统关闭（关机）调用
+// 系统关闭（关机）调用，关机前把缓存中的数据写入磁盘
 pub fn sys_shutdown() -> isize{
+    if let Err(errno) = crate::fs::sync_disks() {
+        warn!("关机前写回磁盘失败: errno {}", errno);
+    }
     crate::sbi::shutdown(); // 调用 SBI 关机接口
     0
 }
@@ -1,4 +1,5 @@
 //! [`TaskContext`] 的实现
+use super::switch::__kernel_thread_entry;
 use crate::trap::trap_return;
 
 #[repr(C)]
@@ -29,4 +30,15 @@ impl TaskContext {
             s: [0; 12],              // 初始化 s0-s11 为 0
         }
     }
+    /// 创建内核线程的初始上下文：切换后从 `__kernel_thread_entry` 开始，
+    /// 由它取出保存在 s1 中的入口函数并调用
+    pub fn goto_kernel_thread(kstack_ptr: usize, entry: fn()) -> Self {
+        let mut s = [0; 12];
+        s[1] = entry as usize;
+        Self {
+            ra: __kernel_thread_entry as usize,
+            sp: kstack_ptr,
+            s,
+        }
+    }
 }
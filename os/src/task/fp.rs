@@ -0,0 +1,40 @@
+//! 浮点寄存器的保存区，封装 `fp.S` 中的保存和恢复函数
+//!
+//! 线程第一次使用浮点指令时才分配保存区：新线程返回用户态时 sstatus.FS 为 Off，
+//! 浮点指令触发非法指令异常后再启用。切换时只保存 FS 为 Dirty 的线程。
+
+use core::arch::global_asm;
+use riscv::register::sstatus::{self, FS};
+
+global_asm!(include_str!("fp.S"));
+
+extern "C" {
+    fn __fp_save(fp_cx_ptr: *mut FpContext);
+    fn __fp_restore(fp_cx_ptr: *const FpContext);
+}
+
+/// 浮点上下文：f0-f31 和 fcsr
+#[repr(C)]
+#[derive(Clone, Default)]
+pub struct FpContext {
+    f: [u64; 32],
+    fcsr: usize,
+}
+
+impl FpContext {
+    /// 把当前的浮点寄存器保存到保存区
+    pub fn save(&mut self) {
+        unsafe {
+            // 内核态的 FS 仍是陷入前用户态的值，访问浮点寄存器前需要打开
+            sstatus::set_fs(FS::Clean);
+            __fp_save(self);
+        }
+    }
+    /// 用保存区的内容恢复浮点寄存器
+    pub fn restore(&self) {
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            __fp_restore(self);
+        }
+    }
+}
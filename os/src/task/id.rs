@@ -3,7 +3,7 @@
 //! 在这里为进程分配 PID。同时，根据 PID 确定应用程序内核栈的位置。
 
 use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
-use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::mm::{MapError, MapPermission, VirtAddr, KERNEL_SPACE};
 use crate::sync::UPSafeCell;
 use alloc::vec::Vec;
 use lazy_static::*;
@@ -33,6 +33,14 @@ impl RecycleAllocator {
             self.current - 1
         }
     }
+    /// 当前已分配且尚未回收的 ID 数量
+    pub fn in_use(&self) -> usize {
+        self.current - 1 - self.recycled.len()
+    }
+    /// 判断 `id` 当前是否已分配
+    pub fn is_allocated(&self, id: usize) -> bool {
+        id < self.current && !self.recycled.contains(&id)
+    }
     /// 回收指定的 PID
     pub fn dealloc(&mut self, id: usize) {
         assert!(id < self.current); // 确保回收的 PID 小于当前最大 PID
@@ -70,26 +78,87 @@ pub fn pid_alloc() -> PidHandle {
     PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
 }
 
-/// 返回内核空间中内核栈的底部和顶部地址
+/// 当前存在的进程数量
+pub fn pid_count() -> usize {
+    PID_ALLOCATOR.exclusive_access().in_use()
+}
+
+/// 返回内核空间中内核栈的底部和顶部地址，
+/// 栈底下方的一页是保护页，始终不映射，栈溢出时会在此触发缺页
 pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
 
+/// 如果 `addr` 落在某个已分配内核栈下方的保护页中，返回该内核栈的 ID
+pub fn kstack_guard_id(addr: usize) -> Option<usize> {
+    if addr >= TRAMPOLINE {
+        return None;
+    }
+    // 每个内核栈连同其保护页占据一个槽位，从 TRAMPOLINE 向下排列
+    let slot = KERNEL_STACK_SIZE + PAGE_SIZE;
+    let depth = TRAMPOLINE - 1 - addr;
+    let (kstack_id, offset) = (depth / slot, depth % slot);
+    if offset < KERNEL_STACK_SIZE {
+        return None;
+    }
+    let allocator = KSTACK_ALLOCATOR.try_exclusive_access()?;
+    allocator.is_allocated(kstack_id).then_some(kstack_id)
+}
+
 /// 表示进程（任务）的内核栈
 pub struct KernelStack(pub usize);
 
-/// 分配一个新的内核栈
-pub fn kstack_alloc() -> KernelStack {
+/// 分配一个新的内核栈，物理内存不足时回收内核栈 ID 并返回错误
+pub fn kstack_alloc() -> Result<KernelStack, MapError> {
     let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
     let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
-    KERNEL_SPACE.exclusive_access().insert_framed_area(
+    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    let result = kernel_space.insert_framed_area(
         kstack_bottom.into(),
         kstack_top.into(),
         MapPermission::R | MapPermission::W, // 设置为可读写
     );
-    KernelStack(kstack_id)
+    if let Err(err) = result {
+        drop(kernel_space);
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(kstack_id);
+        return Err(err);
+    }
+    // 保护页必须保持未映射
+    let guard_vpn = VirtAddr::from(kstack_bottom - PAGE_SIZE).floor();
+    assert!(kernel_space
+        .translate(guard_vpn)
+        .map_or(true, |pte| !pte.is_valid()));
+    drop(kernel_space);
+    #[cfg(feature = "kstack-watermark")]
+    unsafe {
+        // 填充固定的值，退出时据此统计栈的使用峰值
+        core::slice::from_raw_parts_mut(
+            kstack_bottom as *mut usize,
+            KERNEL_STACK_SIZE / core::mem::size_of::<usize>(),
+        )
+        .fill(KSTACK_FILL);
+    }
+    Ok(KernelStack(kstack_id))
+}
+
+/// 新分配的内核栈的填充值
+#[cfg(feature = "kstack-watermark")]
+const KSTACK_FILL: usize = 0x5a5a_5a5a_5a5a_5a5a;
+
+/// 内核栈分配以来使用过的最大字节数：从栈底向上找到第一个被改写过的字
+#[cfg(feature = "kstack-watermark")]
+pub fn kstack_usage_high_watermark(kstack: &KernelStack) -> usize {
+    let (bottom, top) = kernel_stack_position(kstack.0);
+    let words = unsafe {
+        core::slice::from_raw_parts(
+            bottom as *const usize,
+            (top - bottom) / core::mem::size_of::<usize>(),
+        )
+    };
+    let untouched = words.iter().take_while(|&&word| word == KSTACK_FILL).count();
+    top - bottom - untouched * core::mem::size_of::<usize>()
 }
 
 /// 当 `KernelStack` 被释放时自动回收内核栈
@@ -1,49 +1,139 @@
 //! [`TaskManager`] 的实现
 //!
-//! 实现任务管理器，用于管理任务的调度和运行。
+//! 实现任务管理器，用于管理任务的调度和运行，具体的调度策略见 [`super::scheduler`]。
 
+use super::scheduler::{new_scheduler, Scheduler, DEFAULT_SCHEDULER};
+use super::stats::note_ready_len;
 use super::TaskControlBlock;
+use crate::config::BIGSTRIDE;
+use crate::drivers::dtb::bootarg;
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
+use core::cmp::Ordering;
 use lazy_static::*;
 
-/// 一个线程安全的 `TaskControlBlock` 队列
+/// 单次调度推进的最大步幅：不超过计数器范围的一半，回绕比较才有意义
+const MAX_PASS: u64 = u64::MAX / 2;
+
+/// 优先级为 `pri` 的任务每次被调度时 stride 增加的值
+pub fn stride_pass(pri: isize) -> u64 {
+    (BIGSTRIDE / pri.max(1) as u64).min(MAX_PASS)
+}
+
+/// 按回绕距离比较两个 stride：就绪任务之间的 stride 相差不超过 `MAX_PASS`，
+/// 因此把 `a - b` 视为有符号数即可判断先后，即使计数器已经溢出回绕
+pub fn stride_cmp(a: u64, b: u64) -> Ordering {
+    (a.wrapping_sub(b) as i64).cmp(&0)
+}
+
+/// 就绪队列中的一项，入队时记录 stride，在队列中期间 stride 不会改变
+struct ReadyEntry<T> {
+    stride: u64,   // 入队时的 stride
+    seq: usize,    // 入队序号，stride 相同时先入队者先出队
+    item: T,
+}
+
+impl<T> PartialEq for ReadyEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for ReadyEntry<T> {}
+
+impl<T> PartialOrd for ReadyEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ReadyEntry<T> {
+    /// 反向比较，使大顶堆先弹出 (stride, seq) 最小的一项
+    fn cmp(&self, other: &Self) -> Ordering {
+        stride_cmp(other.stride, self.stride).then(other.seq.cmp(&self.seq))
+    }
+}
+
+/// 按 stride 从小到大出队的优先队列，stride 相同时保持 FIFO 顺序
+pub(super) struct StrideQueue<T> {
+    heap: BinaryHeap<ReadyEntry<T>>,
+    next_seq: usize, // 下一个入队序号，单调递增
+}
+
+impl<T> StrideQueue<T> {
+    /// 创建一个空队列
+    pub(super) fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+    /// 以 `stride` 将 `item` 加入队列，O(log n)
+    pub(super) fn push(&mut self, stride: u64, item: T) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(ReadyEntry { stride, seq, item });
+    }
+    /// 取出 stride 最小的一项，O(log n)
+    pub(super) fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|entry| entry.item)
+    }
+    /// 队列中的项数
+    pub(super) fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+/// 一个线程安全的 `TaskControlBlock` 队列，排队和选择的方式由调度策略决定
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>, // 就绪队列，存储任务的控制块
+    scheduler: Box<dyn Scheduler>, // 调度策略，持有就绪队列
 }
 
-/// 一个简单的 FIFO 调度器
 impl TaskManager {
-    /// 创建一个空的 `TaskManager`
+    /// 创建一个空的 `TaskManager`，调度策略由启动参数 `sched=` 选择，没有给出或名称未知时使用编译时选择的策略
     pub fn new() -> Self {
-        Self {
-            ready_queue: VecDeque::new(),
-        }
+        let scheduler = bootarg("sched")
+            .and_then(|name| {
+                let scheduler = new_scheduler(&name);
+                if scheduler.is_none() {
+                    warn!("unknown scheduler {:?}, using {}", name, DEFAULT_SCHEDULER);
+                }
+                scheduler
+            })
+            .unwrap_or_else(|| new_scheduler(DEFAULT_SCHEDULER).unwrap());
+        Self { scheduler }
     }
-    /// 将任务添加回就绪队列
+    /// 将任务添加到就绪队列，调用者不能持有该任务的 TCB 借用
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task); // 将任务加入队列尾部
+        self.scheduler.add(task);
+        note_ready_len(self.scheduler.len());
     }
-    /// 从就绪队列中取出一个任务
+    /// 将让出处理器但仍可运行的任务放回就绪队列
+    pub fn requeue(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.on_yield(task);
+        note_ready_len(self.scheduler.len());
+    }
+    /// 按调度策略取出下一个任务，队列为空（例如所有任务都在睡眠）时返回 `None`
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        let mut id = 0; // 初始化最小 stride 的任务索引
-        let inner1 = self.ready_queue.get(0).unwrap().inner_exclusive_access();
-        let mut stride = inner1.stride; // 记录第一个任务的 stride 值
-        drop(inner1); // 手动释放锁
-        for (i, task) in self.ready_queue.iter_mut().enumerate() {
-            // 遍历队列中的任务
-            let inner = task.inner_exclusive_access();
-            if inner.stride <= stride {
-                // 找到 stride 最小的任务
-                id = i;
-                stride = inner.stride;
-            }
-            drop(inner); // 释放锁
-        }
-        self.ready_queue.remove(id) // 移除并返回 stride 最小的任务
-        // 如果使用 FIFO 调度，可以直接替换为以下代码：
-        // self.ready_queue.pop_front()
+        self.scheduler.pick_next()
+    }
+    /// 当前任务经历一次时钟中断，返回它是否应当让出处理器
+    pub fn tick(&mut self, current: &TaskControlBlock) -> bool {
+        self.scheduler.on_tick(current)
+    }
+    /// 被唤醒的任务 `woken` 是否应当抢占当前任务 `current`
+    pub fn preempts(&self, woken: &TaskControlBlock, current: &TaskControlBlock) -> bool {
+        self.scheduler.preempts(woken, current)
+    }
+    /// 就绪队列中的任务数
+    pub fn ready_len(&self) -> usize {
+        self.scheduler.len()
+    }
+    /// 调度策略的名称
+    pub fn policy(&self) -> &'static str {
+        self.scheduler.name()
     }
 }
 
@@ -59,8 +149,101 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.exclusive_access().add(task); // 调用 TaskManager 的 add 方法
 }
 
+/// 将让出处理器但仍可运行的任务放回就绪队列
+pub fn requeue_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().requeue(task);
+}
+
+/// 就绪队列中是否有等待运行的任务
+pub fn has_ready_tasks() -> bool {
+    TASK_MANAGER.exclusive_access().ready_len() != 0
+}
+
 /// 从就绪队列中取出一个任务
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     // trace!("kernel: TaskManager::fetch_task"); // 调试日志
     TASK_MANAGER.exclusive_access().fetch() // 调用 TaskManager 的 fetch 方法
 }
+
+/// 比较 [`StrideQueue`] 与原先逐项扫描的就绪队列取出任务的开销
+#[cfg(feature = "bench")]
+pub fn stride_queue_bench() {
+    use crate::timer::get_time_us;
+    use alloc::collections::VecDeque;
+
+    const TASKS: usize = 1000;
+    // 伪随机的 stride，只有少数几个不同的值，以便检查相同 stride 的 FIFO 顺序
+    let strides: alloc::vec::Vec<u64> = (0..TASKS)
+        .map(|i| ((i * 7919 + 13) % 17) as u64 * 150)
+        .collect();
+
+    // 原先的实现：每次 fetch 扫描整个队列，取最后一个最小 stride 的任务
+    let mut linear: VecDeque<(u64, usize)> = strides.iter().copied().zip(0..).collect();
+    let begin = get_time_us();
+    while !linear.is_empty() {
+        let mut id = 0;
+        let mut stride = linear[0].0;
+        for (i, entry) in linear.iter().enumerate() {
+            if entry.0 <= stride {
+                id = i;
+                stride = entry.0;
+            }
+        }
+        linear.remove(id);
+    }
+    let linear_us = get_time_us() - begin;
+
+    let mut queue = StrideQueue::new();
+    for (i, &stride) in strides.iter().enumerate() {
+        queue.push(stride, i);
+    }
+    assert_eq!(queue.heap.len(), TASKS);
+    let begin = get_time_us();
+    let mut last: Option<(u64, usize)> = None;
+    while let Some(i) = queue.pop() {
+        let entry = (strides[i], i);
+        if let Some(last) = last {
+            assert!(last < entry, "stride queue popped {:?} after {:?}", entry, last);
+        }
+        last = Some(entry);
+    }
+    let heap_us = get_time_us() - begin;
+    info!(
+        "取出 {} 个任务：线性扫描耗时 {}us，优先队列耗时 {}us",
+        TASKS, linear_us, heap_us
+    );
+    info!("stride_queue_bench passed!");
+}
+
+/// 两个任务的 stride 从回绕点附近开始推进，越过回绕点后
+/// 优先级较低的任务仍然按优先级比例获得调度
+#[allow(unused)]
+pub fn stride_wrap_test() {
+    const ROUNDS: usize = 3000;
+    const START: u64 = u64::MAX - 1000;
+    let pri = [2, 8];
+    let mut strides = [START; 2];
+    let mut runs = [0usize; 2];
+    let mut queue = StrideQueue::new();
+    for (i, &stride) in strides.iter().enumerate() {
+        queue.push(stride, i);
+    }
+    for _ in 0..ROUNDS {
+        let i = queue.pop().unwrap();
+        runs[i] += 1;
+        strides[i] = strides[i].wrapping_add(stride_pass(pri[i]));
+        queue.push(strides[i], i);
+    }
+    assert!(strides.iter().all(|&stride| stride < START), "strides did not wrap");
+    // 调度次数之比应接近步幅之比的倒数
+    let expected = stride_pass(pri[0]) * 100 / stride_pass(pri[1]);
+    let actual = (runs[1] * 100 / runs[0]) as u64;
+    assert!(
+        actual.abs_diff(expected) <= 5,
+        "runs {:?}: ratio {}% but expected {}%",
+        runs,
+        actual,
+        expected
+    );
+    info!("stride_wrap_test passed!");
+}
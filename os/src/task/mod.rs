@@ -1,33 +1,52 @@
 // 任务管理实现
 // 有关任务管理的所有内容，例如启动和切换任务，均在此模块中实现。
-// 在整个操作系统中，由一个全局唯一的 [`TaskManager`] 实例 `TASK_MANAGER` 控制所有任务。
+// 在整个操作系统中，由一个全局唯一的 [`TaskManager`] 实例 `TASK_MANAGER` 控制所有任务，
+// 具体的调度策略（stride、FIFO、时间片轮转）通过 `scheduler` 模块中的 `Scheduler` trait 替换。
 // 每个核心都有一个全局唯一的 [`Processor`] 实例 `PROCESSOR`，负责监控当前运行的任务。
 // 全局唯一的 `PID_ALLOCATOR` 实例用于为用户应用分配 PID。
 // 当你看到 `switch.S` 文件中的 `__switch` 汇编函数时请务必小心。该函数周围的控制流可能并不像你预期的那样。
 
 mod context;       // 任务上下文模块
+mod fp;            // 浮点上下文模块
 mod id;            // PID 分配模块
 mod manager;       // 任务管理器模块
+mod process;       // 进程控制块模块
 pub(crate) mod processor; // 处理器模块
+mod scheduler;     // 调度策略模块
+mod stats;         // 调度统计模块
 mod switch;        // 任务切换模块
 #[allow(clippy::module_inception)]
 #[allow(rustdoc::private_intra_doc_links)]
 mod task;          // 任务模块
+mod wait_queue;    // 等待队列模块
 
-use crate::{loader::get_app_data_by_name, timer::get_time}; // 导入应用加载器和计时器模块
+use crate::{drivers::dtb::bootarg, fs::{search_pwd, OSInode}, loader::get_app_data_by_name, sync::UPSafeCell, timer::{add_timer, get_time}}; // 导入文件系统、应用加载器和计时器模块
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use alloc::sync::Arc; // 引用计数同步模块
 pub use context::TaskContext; // 导出任务上下文
 use lazy_static::*; // 懒加载静态变量
-pub use manager::{fetch_task, TaskManager}; // 导出任务管理器
+pub use manager::{fetch_task, has_ready_tasks, stride_wrap_test, TaskManager}; // 导出任务管理器
+pub use scheduler::scheduler_policy_test; // 各调度策略的自检
+#[cfg(feature = "bench")]
+pub use manager::stride_queue_bench; // 优先队列与线性扫描的耗时对比
 use switch::__switch; // 使用任务切换的低级实现
+pub use process::{find_process, list_processes, ProcessControlBlock, ProcessControlBlockInner}; // 导出进程控制块和进程表
 pub use task::{TaskControlBlock, TaskStatus, TaskInfo}; // 导出任务控制块、状态和信息
+pub use wait_queue::WaitQueue; // 导出等待队列
+pub use stats::{count_syscall, render_stats}; // 导出统计接口
 
-pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle}; // 导出 PID 和内核栈分配相关
+pub use id::{kstack_alloc, kstack_guard_id, pid_alloc, pid_count, KernelStack, PidHandle};
+#[cfg(feature = "kstack-watermark")]
+pub use id::kstack_usage_high_watermark; // 导出 PID 和内核栈分配相关
 pub use manager::add_task; // 导出添加任务方法
+use manager::requeue_task;
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
-    Processor,
+    consume_time_slice, current_pid, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, enter_kernel, handle_page_fault, is_stack_overflow, leave_kernel, run_tasks, schedule,
+    enable_fp_on_first_use, take_current_task, take_need_resched, Processor,
 }; // 导出处理器的功能接口
+use processor::{check_preempt_wakeup, release_after_switch, release_fp};
 
 /// 挂起当前状态为 "Running" 的任务，并运行任务列表中的下一个任务。
 pub fn suspend_current_and_run_next() {
@@ -37,57 +56,123 @@ pub fn suspend_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // 将状态改为 Ready
     task_inner.task_status = TaskStatus::Ready;
-    let ms = get_time();
-    task_inner.task_info.all += ms as u64 - task_inner.task_info.start;
+    task_inner.task_info.switch_out(get_time());
+    task_inner.save_fp_if_dirty();
+    task_inner.nivcsw += 1;
     drop(task_inner);
-    // 将任务重新加入就绪队列。
-    add_task(task);
+    // 将任务重新加入就绪队列，由调度策略决定它的位置。
+    requeue_task(task);
     // 跳转到调度循环
     schedule(task_cx_ptr);
 }
 
+/// 阻塞当前状态为 "Running" 的任务并运行下一个任务。
+/// 任务不会回到就绪队列，而是交给 `park` 保存（如放入等待队列），之后由 [`wakeup_task`] 唤醒。
+pub fn block_current_and_run_next(park: impl FnOnce(Arc<TaskControlBlock>)) {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    task_inner.task_info.switch_out(get_time());
+    task_inner.save_fp_if_dirty();
+    task_inner.nvcsw += 1;
+    drop(task_inner);
+    park(task);
+    schedule(task_cx_ptr);
+}
+
+/// 将阻塞的任务放回就绪队列，它应当优先于当前任务运行时请求抢占
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    task.inner_exclusive_access().task_status = TaskStatus::Ready;
+    check_preempt_wakeup(&task);
+    add_task(task);
+}
+
+/// 让当前任务睡眠到 `expire_us`（微秒）时刻，由时钟中断将它唤醒后重新加入就绪队列
+pub fn sleep_current_and_run_next(expire_us: usize) {
+    block_current_and_run_next(|task| add_timer(expire_us, task));
+}
+
 /// 用户测试应用程序在 `make run TEST=1` 中的 pid
 pub const IDLE_PID: usize = 0;
 
-/// 退出当前状态为 "Running" 的任务，并运行任务列表中的下一个任务。
+/// 退出当前状态为 "Running" 的线程，并运行任务列表中的下一个任务。
+/// 进程的最后一个线程退出时，进程以 `exit_code` 退出并成为僵尸进程，等待父进程回收
 pub fn exit_current_and_run_next(exit_code: i32) {
-    // 从处理器中取出当前任务
+    // 从处理器中取出当前线程
     let task = take_current_task().unwrap();
+    let process = task.process();
+    let pid = process.getpid();
+    #[cfg(feature = "kstack-watermark")]
+    info!(
+        "pid {} tid {} 内核栈使用峰值 {} / {} 字节",
+        pid,
+        task.tid,
+        kstack_usage_high_watermark(&task.kernel_stack),
+        crate::config::KERNEL_STACK_SIZE
+    );
+    let mut task_inner = task.inner_exclusive_access();
+    // 将线程状态改为 Zombie（僵尸态）
+    task_inner.task_info.switch_out(get_time());
+    task_inner.task_status = TaskStatus::Zombie;
+    task_inner.fp = None;
+    let task_info = *task_inner.task_info;
+    let trap_cx_slot = task_inner.trap_cx_slot;
+    drop(task_inner);
+    release_fp(&task);
 
-    let pid = task.getpid();
-    if pid == IDLE_PID {
-        println!(
-            "[kernel] 空闲进程以退出码 {} 退出 ...",
-            exit_code
-        );
-        panic!("所有应用程序已完成！");
-    }
-    let mut inner = task.inner_exclusive_access();
-    // 将状态改为 Zombie（僵尸态）
-    let ms = get_time();
-    inner.task_info.all += ms as u64 - inner.task_info.start;
-    inner.task_status = TaskStatus::Zombie;
-    // 记录退出码
-    inner.exit_code = exit_code;
-    // 将任务移动到 `initproc` 的子任务下，而非其父任务
-    {
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+    let mut inner = process.inner_exclusive_access();
+    inner.task_info.add_thread(&task_info);
+    // 释放陷阱上下文的槽位，并从线程表中移除
+    inner.memory_set.dealloc_trap_cx(trap_cx_slot);
+    inner.threads[task.tid] = None;
+    if inner.thread_count() == 0 {
+        if pid == IDLE_PID {
+            println!(
+                "[kernel] 空闲进程以退出码 {} 退出 ...",
+                exit_code
+            );
+            panic!("所有应用程序已完成！");
+        }
+        // 记录退出码，进程成为僵尸进程
+        inner.is_zombie = true;
+        inner.exit_code = exit_code;
+        // 将子进程移动到 `initproc` 的子进程下，而非其父进程。
+        // initproc（用户 Shell）只等待自己创建的子进程，相当于对过继来的孤儿忽略了 SIGCHLD：
+        // 已退出的孤儿直接释放，仍在运行的孤儿退出时由内核回收
+        let initproc = initproc();
+        {
+            let mut initproc_inner = initproc.inner_exclusive_access();
+            for child in core::mem::take(&mut inner.children) {
+                let mut child_inner = child.inner_exclusive_access();
+                if child_inner.is_zombie() {
+                    continue;
+                }
+                child_inner.parent = Some(Arc::downgrade(&initproc));
+                child_inner.auto_reap = true;
+                drop(child_inner);
+                initproc_inner.children.push(child);
+            }
+        }
+        if inner.auto_reap {
+            // 从 initproc 的子进程列表中移除，当前函数释放最后一个引用时回收 PCB 和 PID
+            initproc
+                .inner_exclusive_access()
+                .children
+                .retain(|child| !Arc::ptr_eq(child, &process));
+        } else if let Some(parent) = inner.parent.as_ref().and_then(|parent| parent.upgrade()) {
+            // 唤醒在 waitpid 中等待的父进程；内核还没有信号机制，父进程不会收到 SIGCHLD
+            parent.child_exit_wq.wake_all();
         }
+        // 写回并关闭所有文件，进程没有调用 fsync 时写入的数据也不会留在缓存中
+        inner.close_all_files();
+        // 回收用户空间内存和页表
+        inner.memory_set.recycle_all();
     }
-    
-    inner.children.clear();
-    // 回收用户空间内存
-    
-    inner.memory_set.recycle_data_pages();
-    // 清空文件描述符表
-    
-    inner.fd_table.clear();
     drop(inner);
-    // 手动释放任务以正确维护引用计数
-    drop(task);
+    drop(process);
+    // 当前仍运行在线程的内核栈上，线程在切换回调度循环之后才能释放
+    release_after_switch(task);
     // 无需保存任务上下文
     let mut _unused = TaskContext::zero_init();
 
@@ -95,22 +180,86 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     
 }
 
+/// 创建名为 `name` 的内核线程执行 `entry`，与用户任务一样参与调度。
+/// 内核态不响应时钟中断，内核线程需要通过睡眠或等待主动让出处理器
+pub fn spawn_kernel_thread(name: &str, entry: fn()) -> Arc<TaskControlBlock> {
+    let task = Arc::new(TaskControlBlock::new_kernel(entry, name));
+    info!("内核线程 {} 已创建", name);
+    add_task(task.clone());
+    task
+}
+
+/// 内核线程的起点，由 `__kernel_thread_entry` 调用：执行入口函数，返回后退出线程
+#[no_mangle]
+extern "C" fn kernel_thread_main(entry: usize) -> ! {
+    // 由 `TaskContext::goto_kernel_thread` 从 `fn()` 转换而来
+    let entry: fn() = unsafe { core::mem::transmute(entry) };
+    entry();
+    exit_kernel_thread();
+}
+
+/// 退出当前内核线程。内核线程不属于任何进程，
+/// 不经过用户线程的退出流程，TCB 和内核栈在切换回调度循环后释放
+fn exit_kernel_thread() -> ! {
+    let task = take_current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.task_info.switch_out(get_time());
+    inner.task_status = TaskStatus::Zombie;
+    drop(inner);
+    release_after_switch(task);
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+    unreachable!("退出的内核线程被再次调度");
+}
+
 lazy_static! {
-    /// 初始化进程的创建
+    /// 初始化进程，由 [`add_initproc`] 在文件系统就绪后显式创建
     ///
-    /// 名称 "initproc" 可以改为任何其他应用程序名称，比如 "usertests"，
-    /// 但我们已经有用户 Shell，因此不需要更改。
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
-        get_app_data_by_name("ch6b_user_shell").unwrap()
-        // let vfile = ROOT_INODE.find_vfile_byname("ch6b_initproc.elf").unwrap();
-        // let v1 = OSInode::new(true, false, vfile);
-        // let v = v1.read_all();
-        // TaskControlBlock::new(v.as_slice())
-    ));
-    
+    /// 不在首次访问时创建，以免在内存管理和块设备初始化之前加载 ELF
+    static ref INITPROC: UPSafeCell<Option<Arc<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(None) };
+}
+
+/// 依次在文件系统中查找的初始化程序路径
+const INITPROC_PATHS: [&str; 2] = ["/init", "/ch6b_user_shell"];
+
+/// 文件系统中找不到初始化程序时，从内核镜像内嵌的应用中加载的程序名
+const EMBEDDED_INITPROC: &str = "ch6b_user_shell";
+
+/// 返回初始化进程，必须在 [`add_initproc`] 之后调用
+pub fn initproc() -> Arc<ProcessControlBlock> {
+    INITPROC.exclusive_access().clone().expect("initproc 尚未创建")
+}
+
+/// 读取初始化程序的 ELF 数据，返回程序名和数据
+///
+/// 启动参数 `init=` 给出的路径最先查找，然后从文件系统中按 [`INITPROC_PATHS`] 的顺序查找，保证与 exec 加载的是同一份程序；
+/// 都不存在时才使用内嵌在内核镜像中的应用。此时还没有当前进程，不能使用 `open_file`。
+fn load_initproc_elf() -> (String, Vec<u8>) {
+    let requested = bootarg("init");
+    for path in requested.as_deref().into_iter().chain(INITPROC_PATHS) {
+        match search_pwd(path).filter(|vfile| !vfile.is_dir()) {
+            Some(vfile) => match OSInode::new(true, false, vfile).read_all() {
+                Ok(elf_data) => {
+                    info!("从文件系统加载 initproc：{}", path);
+                    return (path.trim_start_matches('/').to_string(), elf_data);
+                }
+                Err(errno) => warn!("读取 {} 失败：errno {}", path, errno),
+            },
+            None if Some(path) == requested.as_deref() => warn!("启动参数指定的 initproc {} 不存在", path),
+            None => {}
+        }
+    }
+    warn!("文件系统中没有 initproc，使用内嵌的 {}", EMBEDDED_INITPROC);
+    let elf_data = get_app_data_by_name(EMBEDDED_INITPROC).expect("找不到 initproc");
+    (EMBEDDED_INITPROC.to_string(), elf_data.to_vec())
 }
 
-/// 将初始化进程添加到任务管理器中
+/// 创建初始化进程并将它的主线程添加到任务管理器中，需要在内存管理和文件系统初始化之后调用
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    let (name, elf_data) = load_initproc_elf();
+    let process = ProcessControlBlock::new(&name, &elf_data);
+    let thread = process.inner_exclusive_access().threads[0].clone().unwrap();
+    *INITPROC.exclusive_access() = Some(process);
+    add_task(thread);
 }
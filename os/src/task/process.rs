@@ -0,0 +1,477 @@
+//! 进程控制块
+//!
+//! 进程拥有同一进程内所有线程共享的资源：地址空间、文件描述符表、工作目录和进程树，
+//! 线程（[`TaskControlBlock`]）只保存各自的执行状态并参与调度。
+
+use super::{pid_alloc, PidHandle, TaskControlBlock, TaskInfo, TaskStatus, WaitQueue};
+use crate::fs::{File, Stderr, Stdin, Stdout};
+use crate::mm::{ExecError, MapError, MemStats, MemorySet, StackBuilder, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+use fat32::VFile;
+use lazy_static::*;
+
+lazy_static! {
+    /// 所有存在的进程（包括僵尸进程），按 pid 索引。
+    /// 进程创建时登记，PCB 释放时移除，使用 `Weak` 不影响进程的释放
+    static ref PROCESS_TABLE: UPSafeCell<BTreeMap<usize, Weak<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// 按 pid 查找进程
+pub fn find_process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PROCESS_TABLE.exclusive_access().get(&pid)?.upgrade()
+}
+
+/// 按 pid 从小到大列出所有存在的进程
+pub fn list_processes() -> Vec<Arc<ProcessControlBlock>> {
+    PROCESS_TABLE
+        .exclusive_access()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// 进程控制块结构体
+///
+/// 直接保存运行期间不会改变的内容
+pub struct ProcessControlBlock {
+    // 不可变部分
+    /// 进程标识符
+    pub pid: PidHandle,
+    /// 在 waitpid 中等待子进程退出的线程
+    pub child_exit_wq: WaitQueue,
+    /// 可变部分
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// 进程控制块内部结构
+pub struct ProcessControlBlockInner {
+    /// 所有线程都已退出，等待父进程回收
+    pub is_zombie: bool,
+
+    /// 应用程序地址空间，由进程的所有线程共享
+    pub memory_set: MemorySet,
+
+    /// 当前进程的父进程。
+    /// 使用 `Weak` 不会影响父进程的引用计数
+    pub parent: Option<Weak<ProcessControlBlock>>,
+
+    /// 包含当前进程所有子进程的 PCB 的向量
+    pub children: Vec<Arc<ProcessControlBlock>>,
+
+    /// 进程的退出码，最后一个线程退出时设置
+    pub exit_code: i32,
+
+    /// 文件描述符表
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+
+    /// 应用程序数据只能出现在应用地址空间低于 `base_size` 的区域
+    pub base_size: usize,
+
+    /// 堆底地址
+    pub heap_bottom: usize,
+
+    /// brk
+    pub program_brk: usize,
+
+    /// 当前工作目录，是相对于 `root` 的绝对路径
+    pub pwd: String,
+
+    /// 进程的根目录，是文件系统中规范化的绝对路径，由 chroot 设置
+    pub root: String,
+
+    /// 进程的线程，下标为线程号，已退出的线程留下 `None`
+    pub threads: Vec<Option<Arc<TaskControlBlock>>>,
+
+    /// 已退出线程累计的运行时间，以及已回收子进程的运行时间
+    pub task_info: TaskInfo,
+
+    /// 已回收子进程（及其已回收的后代）的内存使用统计
+    pub children_mem_stats: MemStats,
+
+    /// 父进程退出后被过继给 initproc，退出时由内核直接回收，不需要 initproc 调用 waitpid
+    pub auto_reap: bool,
+}
+
+impl ProcessControlBlockInner {
+    /// 获取应用程序页表的地址
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// 进程是否已经退出、等待父进程回收
+    pub fn is_zombie(&self) -> bool {
+        self.is_zombie
+    }
+    /// 父进程的 pid，按当前的父进程计算，过继给 initproc 的孤儿得到 initproc 的 pid；没有父进程时为 0
+    pub fn parent_pid(&self) -> usize {
+        self.parent.as_ref().and_then(Weak::upgrade).map_or(0, |parent| parent.getpid())
+    }
+    /// 分配一个空闲的文件描述符
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    /// 设置当前工作目录
+    pub fn set_pwd(&mut self, new_pwd:String){
+        self.pwd = new_pwd;
+    }
+    /// 分配一个空闲的线程号
+    pub fn alloc_tid(&mut self) -> usize {
+        if let Some(tid) = (0..self.threads.len()).find(|tid| self.threads[*tid].is_none()) {
+            tid
+        } else {
+            self.threads.push(None);
+            self.threads.len() - 1
+        }
+    }
+    /// 进程中尚未退出的线程数
+    pub fn thread_count(&self) -> usize {
+        self.threads.iter().filter(|thread| thread.is_some()).count()
+    }
+    /// 截至 `now` 进程的用户态和内核态运行时间（时钟周期），包括已退出的线程
+    pub fn cpu_times(&self, now: usize) -> (u64, u64) {
+        let (mut all, mut stime) = (self.task_info.all, self.task_info.stime);
+        for thread in self.threads.iter().flatten() {
+            let inner = thread.inner_exclusive_access();
+            // 正在运行的线程本次运行的时间还没有结算
+            let (thread_all, thread_stime) = match inner.task_status {
+                TaskStatus::Running => (inner.task_info.cpu_time(now), inner.task_info.sys_time(now)),
+                _ => (inner.task_info.all, inner.task_info.stime),
+            };
+            all += thread_all;
+            stime += thread_stime;
+        }
+        (all - stime, stime)
+    }
+    /// 进程退出时关闭所有文件：先写回文件被修改的数据，再清空文件描述符表。
+    /// 管道的最后一个端点在这里释放时会唤醒另一端的等待者。不需要访问用户内存
+    pub fn close_all_files(&mut self) {
+        for file in self.fd_table.iter().flatten() {
+            // 退出时无处报告错误，写回失败的块留在块缓存中，之后的同步会重试
+            if let Err(errno) = file.sync() {
+                warn!("进程退出时写回文件失败: errno {}", errno);
+            }
+        }
+        self.fd_table.clear();
+    }
+    /// 未退出线程主动和被动让出处理器的次数之和
+    pub fn context_switches(&self) -> (u64, u64) {
+        self.threads.iter().flatten().fold((0, 0), |(nvcsw, nivcsw), thread| {
+            let inner = thread.inner_exclusive_access();
+            (nvcsw + inner.nvcsw, nivcsw + inner.nivcsw)
+        })
+    }
+}
+
+/// 新进程的标准输入、标准输出和标准错误
+fn std_fd_table() -> Vec<Option<Arc<dyn File + Send + Sync>>> {
+    vec![
+        // 0 -> 标准输入 stdin
+        Some(Arc::new(Stdin)),
+        // 1 -> 标准输出 stdout
+        Some(Arc::new(Stdout)),
+        // 2 -> 标准错误 stderr
+        Some(Arc::new(Stderr)),
+    ]
+}
+
+impl ProcessControlBlock {
+    /// 获取 PCB 内部结构的可变引用
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+
+    /// 获取应用程序页表的地址
+    pub fn get_user_token(&self) -> usize {
+        self.inner_exclusive_access().get_user_token()
+    }
+
+    /// 获取进程的 pid
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+
+    /// 获取父进程的 pid
+    pub fn getppid(&self) -> usize {
+        self.inner_exclusive_access().parent_pid()
+    }
+
+    /// 创建一个进程控制块，尚不包含任何线程
+    fn new_empty(
+        parent: Option<Weak<ProcessControlBlock>>,
+        memory_set: MemorySet,
+        fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+        pwd: String,
+        root: String,
+    ) -> Arc<Self> {
+        let process = Arc::new(Self {
+            pid: pid_alloc(),
+            child_exit_wq: WaitQueue::new(),
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table,
+                    base_size: 0,
+                    heap_bottom: 0,
+                    program_brk: 0,
+                    pwd,
+                    root,
+                    threads: Vec::new(),
+                    task_info: TaskInfo::new(),
+                    children_mem_stats: MemStats::default(),
+                    auto_reap: false,
+                })
+            },
+        });
+        PROCESS_TABLE
+            .exclusive_access()
+            .insert(process.getpid(), Arc::downgrade(&process));
+        process
+    }
+
+    /// 在进程中创建一个使用陷阱上下文槽位 `trap_cx_slot`、命令名为 `name` 的线程并登记到线程表
+    fn add_thread(self: &Arc<Self>, trap_cx_slot: usize, name: &str) -> Result<Arc<TaskControlBlock>, MapError> {
+        let mut inner = self.inner_exclusive_access();
+        let tid = inner.alloc_tid();
+        let trap_cx_ppn = inner.memory_set.trap_cx_ppn(trap_cx_slot);
+        let thread = Arc::new(TaskControlBlock::new(self, tid, trap_cx_slot, trap_cx_ppn, name)?);
+        inner.threads[tid] = Some(thread.clone());
+        Ok(thread)
+    }
+
+    /// 创建一个新进程，返回它的主线程
+    ///
+    /// 当前仅用于创建 `initproc`，`name` 作为它的 argv[0]，内存不足时直接 panic
+    pub fn new(name: &str, elf_data: &[u8]) -> Arc<Self> {
+        // 从 ELF 程序头创建 memory_set，并包含 trampoline、trap 上下文以及用户栈
+        let (mut memory_set, user_sp, elf_info) =
+            MemorySet::from_elf_data(elf_data).expect("创建 initproc 时内存不足");
+        let mut stack = StackBuilder::new(memory_set.token(), user_sp);
+        let tp = elf_info
+            .tls
+            .as_ref()
+            .map_or(Ok(0), |tls| stack.push_tls(tls))
+            .expect("initproc 的 TLS 段不合法");
+        let user_stack_sp = stack
+            .build(&[String::from(name)], &[], &elf_info)
+            .expect("initproc 的初始用户栈放不下参数");
+        // 为主线程分配陷阱上下文页
+        let trap_cx_slot = memory_set.alloc_trap_cx().expect("创建 initproc 时内存不足");
+        let process = Self::new_empty(None, memory_set, std_fd_table(), String::from("/"), String::from("/"));
+        {
+            let mut inner = process.inner_exclusive_access();
+            inner.base_size = user_sp;
+            inner.heap_bottom = user_sp;
+            inner.program_brk = user_sp;
+        }
+        // 分配内核栈，准备用户空间的 TrapContext
+        let thread = process.add_thread(trap_cx_slot, "initproc").expect("创建 initproc 时内存不足");
+        let trap_cx = thread.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            elf_info.entry,
+            user_stack_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            thread.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.set_tp(tp);
+        process
+    }
+
+    /// 加载一个新的 ELF 文件以替换原来的应用程序地址空间，由调用 exec 的线程 `thread`
+    /// 以命令名 `name` 开始执行
+    ///
+    /// 文件不是合法的 ELF、内存不足或参数放不进初始用户栈时返回错误，原地址空间保持不变
+    pub fn exec(&self, thread: &TaskControlBlock, name: &str, elf_file: &Arc<VFile>, args: &[String], envs: &[String]) -> Result<(), ExecError> {
+        // 从 ELF 程序头创建 memory_set，并包含 trampoline、trap 上下文以及用户栈
+        let (mut memory_set, user_sp, elf_info) = MemorySet::from_elf(elf_file)?;
+        // 在新的用户栈上放置主线程的 TLS 块、参数、环境变量和 auxv
+        let mut stack = StackBuilder::new(memory_set.token(), user_sp);
+        let tp = elf_info.tls.as_ref().map_or(Ok(0), |tls| stack.push_tls(tls))?;
+        let user_stack_sp = stack.build(args, envs, &elf_info)?;
+        let trap_cx_slot = memory_set.alloc_trap_cx()?;
+        let trap_cx_ppn = memory_set.trap_cx_ppn(trap_cx_slot);
+        // **** 独占访问当前 PCB
+        let mut inner = self.inner_exclusive_access();
+        // 立即回收旧的地址空间，再替换 memory_set；缺页次数和 RSS 峰值继续累计
+        memory_set.inherit_stats(&inner.memory_set);
+        inner.memory_set.recycle_all();
+        inner.memory_set = memory_set;
+        // 新程序的堆从用户栈顶开始，初始为空
+        inner.heap_bottom = user_sp;
+        inner.program_brk = user_sp;
+        drop(inner);
+        // **** 释放当前 PCB
+
+        // 更新线程的命令名、trap_cx 物理页号和槽位，并初始化 trap_cx，新程序重新从未使用浮点开始
+        let mut thread_inner = thread.inner_exclusive_access();
+        thread_inner.set_name(name);
+        thread_inner.fp = None;
+        thread_inner.trap_cx_ppn = trap_cx_ppn;
+        thread_inner.trap_cx_slot = trap_cx_slot;
+        let trap_cx = thread_inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            elf_info.entry,
+            user_stack_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            thread.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.set_tp(tp);
+        Ok(())
+    }
+
+    /// 由线程 `thread` fork 出子进程，返回子进程的主线程，内存不足时返回错误
+    pub fn fork(self: &Arc<Self>, thread: &TaskControlBlock) -> Result<Arc<TaskControlBlock>, MapError> {
+        // 先保存父线程修改过的浮点寄存器，复制的陷阱上下文和浮点上下文才一致
+        thread.inner_exclusive_access().save_fp_if_dirty();
+        // ---- 锁定父 PCB
+        let mut parent_inner = self.inner_exclusive_access();
+        // 拷贝用户空间（包括陷阱上下文）
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
+        // 拷贝文件描述符表
+        let new_fd_table = parent_inner.fd_table.clone();
+        let child = Self::new_empty(
+            Some(Arc::downgrade(self)),
+            memory_set,
+            new_fd_table,
+            parent_inner.pwd.clone(),
+            parent_inner.root.clone(),
+        );
+        {
+            let mut child_inner = child.inner_exclusive_access();
+            child_inner.base_size = parent_inner.base_size;
+            child_inner.heap_bottom = parent_inner.heap_bottom;
+            child_inner.program_brk = parent_inner.program_brk;
+        }
+        // 子进程的主线程沿用父线程的槽位和命令名，其陷阱上下文页已随地址空间复制
+        let thread_inner = thread.inner_exclusive_access();
+        let (trap_cx_slot, name, fp) = (thread_inner.trap_cx_slot, thread_inner.name.clone(), thread_inner.fp.clone());
+        drop(thread_inner);
+        let child_thread = child.add_thread(trap_cx_slot, &name)?;
+        child_thread.inner_exclusive_access().fp = fp;
+        // 添加子进程
+        parent_inner.children.push(child);
+        // 修改 trap_cx 中的 kernel_sp
+        let trap_cx = child_thread.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = child_thread.kernel_stack.get_top();
+        Ok(child_thread)
+        // ---- 释放父 PCB
+    }
+
+    /// 在本进程中为线程 `thread` 创建一个共享地址空间的新线程，
+    /// 新线程的陷阱上下文和浮点上下文复制自 `thread`，内存不足或槽位用尽时返回错误
+    pub fn clone_thread(self: &Arc<Self>, thread: &TaskControlBlock) -> Result<Arc<TaskControlBlock>, MapError> {
+        thread.inner_exclusive_access().save_fp_if_dirty();
+        let trap_cx_slot = self.inner_exclusive_access().memory_set.alloc_trap_cx()?;
+        let thread_inner = thread.inner_exclusive_access();
+        let (trap_cx, name, fp) = (thread_inner.get_trap_cx(), thread_inner.name.clone(), thread_inner.fp.clone());
+        drop(thread_inner);
+        let new_thread = match self.add_thread(trap_cx_slot, &name) {
+            Ok(new_thread) => new_thread,
+            Err(err) => {
+                self.inner_exclusive_access().memory_set.dealloc_trap_cx(trap_cx_slot);
+                return Err(err);
+            }
+        };
+        let mut new_inner = new_thread.inner_exclusive_access();
+        new_inner.fp = fp;
+        *new_inner.get_trap_cx() = TrapContext {
+            kernel_sp: new_thread.kernel_stack.get_top(),
+            ..*trap_cx
+        };
+        drop(new_inner);
+        Ok(new_thread)
+    }
+
+    /// spawn 创建命令名为 `name` 的子进程，以 `args` 作为其参数，返回子进程的主线程；
+    /// 文件不是合法的 ELF 或内存不足时返回错误
+    pub fn spawn(self: &Arc<Self>, name: &str, elf_file: &Arc<VFile>, args: &[String]) -> Result<Arc<TaskControlBlock>, ExecError> {
+        let (mut memory_set, user_sp, elf_info) = MemorySet::from_elf(elf_file)?;
+        let mut stack = StackBuilder::new(memory_set.token(), user_sp);
+        let tp = elf_info.tls.as_ref().map_or(Ok(0), |tls| stack.push_tls(tls))?;
+        let user_stack_sp = stack.build(args, &[], &elf_info)?;
+        let trap_cx_slot = memory_set.alloc_trap_cx()?;
+        // ---- 独占访问父 PCB
+        let mut parent_inner = self.inner_exclusive_access();
+        let child = Self::new_empty(
+            Some(Arc::downgrade(self)),
+            memory_set,
+            std_fd_table(),
+            parent_inner.pwd.clone(),
+            parent_inner.root.clone(),
+        );
+        {
+            let mut child_inner = child.inner_exclusive_access();
+            child_inner.base_size = user_sp;
+            child_inner.heap_bottom = user_sp;
+            child_inner.program_brk = user_sp;
+        }
+        let child_thread = child.add_thread(trap_cx_slot, name)?;
+        // 添加子进程
+        parent_inner.children.push(child);
+        let trap_cx = child_thread.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            elf_info.entry,
+            user_stack_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            child_thread.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.set_tp(tp);
+        Ok(child_thread)
+        // ---- 释放父 PCB
+    }
+
+    /// 修改brk，`new_add` 为新的堆顶地址，为 0 时只返回当前的 brk。
+    /// 低于堆底、与其他区域重叠或内存不足时返回 `None`
+    pub fn change_program_brk(&self, new_add: i64) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let heap_bottom = inner.heap_bottom;
+        let old_break = inner.program_brk;
+        if new_add == 0{
+            return Some(old_break);
+        }
+        if new_add < heap_bottom as i64 {
+            return None;
+        }
+        let new_brk = new_add as usize;
+        let result = if new_brk < old_break {
+            inner
+                .memory_set
+                .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk))
+        } else {
+            inner
+                .memory_set
+                .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk))
+        };
+        if result {
+            inner.program_brk = new_brk;
+            Some(new_brk)
+        } else {
+            None
+        }
+    }
+}
+
+/// 进程被回收时从进程表中移除，之后 pid 随 `PidHandle` 一起释放
+impl Drop for ProcessControlBlock {
+    fn drop(&mut self) {
+        PROCESS_TABLE.exclusive_access().remove(&self.pid.0);
+    }
+}
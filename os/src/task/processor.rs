@@ -4,15 +4,22 @@
 // 并执行了不同应用程序的控制流替换和切换。
 
 use super::__switch;
+use super::fp::FpContext;
+use super::manager::TASK_MANAGER;
+use super::stats::{add_idle_cycles, count_context_switch};
 use super::{fetch_task, TaskStatus};
-use super::{TaskContext, TaskControlBlock};
-use crate::mm::page_table::PTEFlags;
-use crate::mm::{PhysPageNum, VirtPageNum};
+use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::drivers::poll_io;
+use crate::fs::poll_console_input;
+use crate::mm::VirtAddr;
 use crate::sync::UPSafeCell;
-use crate::timer::get_time;
-use crate::trap::TrapContext;
+use crate::config::TIME_SLICE_TICKS;
+use crate::timer::{check_timer, get_time};
+use crate::trap::{wait_for_interrupt, TrapContext};
+use alloc::boxed::Box;
 use alloc::sync::Arc;
 use lazy_static::*;
+use riscv::register::sstatus::FS;
 
 /// 处理器管理结构
 pub struct Processor {
@@ -21,6 +28,15 @@ pub struct Processor {
 
     /// 每个核心的基本控制流，辅助选择和切换进程
     idle_task_cx: TaskContext,
+
+    /// 已经退出的线程，回到调度循环、离开它的内核栈之后才能释放其 TCB 和内核栈
+    exited: Option<Arc<TaskControlBlock>>,
+
+    /// 被唤醒的任务应当抢占当前任务，返回用户态之前需要重新调度
+    need_resched: bool,
+
+    /// 浮点寄存器中是哪个线程的浮点上下文（TCB 的地址），0 表示不属于任何线程
+    fp_owner: usize,
 }
 
 impl Processor {
@@ -29,6 +45,9 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            exited: None,
+            need_resched: false,
+            fp_owner: 0,
         }
     }
 
@@ -58,30 +77,102 @@ lazy_static! {
 pub fn run_tasks() {
     loop {
         let mut processor = PROCESSOR.exclusive_access();
+        // 已经切换到调度循环的栈上，可以释放退出的线程
+        drop(processor.exited.take());
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // 独占访问即将运行任务的 TCB
             let mut task_inner = task.inner_exclusive_access();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
-            let ms1 = get_time();
-            task_inner.task_info.start = ms1 as u64;
+            // 每次被调度都获得一个完整的时间片
+            task_inner.time_slice = TIME_SLICE_TICKS;
+            // 浮点寄存器中是其他线程的内容时，恢复这个线程的浮点上下文
+            if let Some(fp) = task_inner.fp.as_ref() {
+                let owner = Arc::as_ptr(&task) as usize;
+                if processor.fp_owner != owner {
+                    fp.restore();
+                    processor.fp_owner = owner;
+                }
+            }
+            task_inner.task_info.switch_in(get_time());
             // 手动释放 task_inner 的独占访问
             drop(task_inner);
-            // 手动释放任务的 TCB
-            task.update_stri();
             processor.current = Some(task);
+            processor.need_resched = false;
             // 手动释放处理器的独占访问
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
         } else {
-            warn!("在 run_tasks 中没有可用的任务");
+            // 没有就绪的任务（例如所有任务都在睡眠），停下处理器等待时钟或设备中断，再唤醒到期的任务
+            drop(processor);
+            let idle_start = get_time();
+            wait_for_interrupt();
+            add_idle_cycles((get_time() - idle_start) as u64);
+            check_timer();
+            poll_console_input();
         }
     }
 }
 
+/// 在时钟中断中由调度策略处理当前任务的一个时钟周期（如消耗时间片），返回当前任务是否应当让出处理器
+pub fn consume_time_slice() -> bool {
+    let task = current_task().unwrap();
+    TASK_MANAGER.exclusive_access().tick(&task)
+}
+
+/// 唤醒 `task` 之后调用：调度策略认为它应当抢占当前任务时（如 stride 更小），
+/// 当前任务不再用完剩余的时间片，而是在返回用户态之前让出处理器，让被唤醒的任务尽快运行
+pub fn check_preempt_wakeup(task: &TaskControlBlock) {
+    let mut processor = PROCESSOR.exclusive_access();
+    let Some(current) = processor.current.as_ref() else {
+        return; // 在调度循环中唤醒，马上就会重新调度
+    };
+    if TASK_MANAGER.exclusive_access().preempts(task, current) {
+        processor.need_resched = true;
+    }
+}
+
+/// 处理当前线程在 sstatus.FS 为 Off 时执行浮点指令引起的非法指令异常：
+/// 第一次使用浮点指令时为线程分配清零的浮点上下文并启用浮点单元，返回 true 后重新执行该指令；
+/// 浮点单元已经启用时是真正的非法指令，返回 false
+pub fn enable_fp_on_first_use() -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let trap_cx = inner.get_trap_cx();
+    if trap_cx.sstatus.fs() != FS::Off {
+        return false;
+    }
+    let fp = Box::new(FpContext::default());
+    fp.restore();
+    trap_cx.sstatus.set_fs(FS::Clean);
+    inner.fp = Some(fp);
+    PROCESSOR.exclusive_access().fp_owner = Arc::as_ptr(&task) as usize;
+    true
+}
+
+/// 线程 `task` 退出时调用：浮点寄存器中是它的内容时清除记录，
+/// 以免之后分配在同一地址上的 TCB 被误认为已经恢复了浮点上下文
+pub fn release_fp(task: &Arc<TaskControlBlock>) {
+    let mut processor = PROCESSOR.exclusive_access();
+    if processor.fp_owner == Arc::as_ptr(task) as usize {
+        processor.fp_owner = 0;
+    }
+}
+
+/// 取出并清除重新调度的请求
+pub fn take_need_resched() -> bool {
+    core::mem::take(&mut PROCESSOR.exclusive_access().need_resched)
+}
+
+/// 记录退出的线程 `task`，在切换回调度循环之后释放。线程退出时仍运行在自己的内核栈上，
+/// 不能在退出流程中回收内核栈；这样僵尸进程只保留 PCB，不再占用内核栈
+pub fn release_after_switch(task: Arc<TaskControlBlock>) {
+    PROCESSOR.exclusive_access().exited = Some(task);
+}
+
 /// 通过 take 获取当前任务，同时留下一个 None
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSOR.exclusive_access().take_current()
@@ -92,44 +183,76 @@ pub fn current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSOR.exclusive_access().current()
 }
 
+/// 获取当前任务的 PID，处理器正被借用时（如在内核 trap 中诊断错误）返回 None
+pub fn current_pid() -> Option<usize> {
+    PROCESSOR
+        .try_exclusive_access()?
+        .current
+        .as_ref()
+        .and_then(|task| task.process.upgrade())
+        .map(|process| process.getpid())
+}
+
+/// 获取当前线程所属的进程
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process()
+}
+
 /// 获取当前用户态的 token（页表地址）
 pub fn current_user_token() -> usize {
-    let task = current_task().unwrap();
-    task.get_user_token()
+    current_process().get_user_token()
 }
 
 /// 获取当前任务的 trap 上下文的可变引用
 pub fn current_trap_cx() -> &'static mut TrapContext {
     current_task()
-        .unwrap()
+        .expect("没有当前任务，无法获取 trap 上下文")
         .inner_exclusive_access()
         .get_trap_cx()
 }
 
-/// 更新任务的时间信息
-pub fn update_time(ms: usize) {
+/// 获取当前任务的 trap 上下文在用户地址空间中的虚拟地址
+pub fn current_trap_cx_user_va() -> usize {
     current_task()
-        .unwrap()
+        .expect("没有当前任务，无法返回用户态")
         .inner_exclusive_access()
-        .task_info.update_sys(ms);
+        .trap_cx_user_va()
 }
 
-/// 映射一页虚拟内存到物理内存
-pub fn map_one(vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> isize {
-    current_task()
-        .unwrap()
-        .map(vpn, ppn, flags)
+/// 当前任务从用户态陷入内核，开始计算系统时间
+pub fn enter_kernel() {
+    let task = current_task().expect("没有当前任务，无法记录陷入内核的时间");
+    task.inner_exclusive_access().task_info.enter_kernel(get_time());
+}
+
+/// 当前任务即将返回用户态，将本段在内核中占用 CPU 的时间计入系统时间。
+/// 期间被换下 CPU 的时间已经在换下时结算，不会计入
+pub fn leave_kernel() {
+    let task = current_task().expect("没有当前任务，无法返回用户态");
+    task.inner_exclusive_access().task_info.leave_kernel(get_time());
 }
 
-/// 取消映射一页虚拟内存
-pub fn unmap_one(vpn: VirtPageNum) -> isize {
+/// 处理当前任务在用户地址 `va` 上的缺页（`write` 表示由写访问引起），返回缺页是否已被处理
+pub fn handle_page_fault(va: VirtAddr, write: bool) -> bool {
     current_task()
-        .unwrap()
-        .unmap(vpn)
+        .and_then(|task| task.process.upgrade())
+        .map_or(false, |process| {
+            // 持有进程的地址空间时不能睡眠等待磁盘，同一进程的其他线程会访问它
+            poll_io(|| process.inner_exclusive_access().memory_set.handle_page_fault(va, write))
+        })
+}
+
+/// 判断当前任务在用户地址 `va` 上未能处理的缺页是否是栈溢出
+pub fn is_stack_overflow(va: VirtAddr) -> bool {
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .is_stack_overflow(va)
 }
 
 /// 返回到空闲的控制流以便进行新的调度
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    count_context_switch();
     let mut processor = PROCESSOR.exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
@@ -0,0 +1,247 @@
+//! 可替换的调度策略
+//!
+//! [`TaskManager`](super::TaskManager) 只通过 [`Scheduler`] trait 使用调度策略。
+//! 默认使用 stride 调度，cargo feature `sched-fifo` 和 `sched-rr` 分别选择
+//! 先来先服务和时间片轮转，便于用 /proc/stat 中的上下文切换次数比较不同策略。
+//! 启动参数 `sched=stride|fifo|rr` 在启动时覆盖编译时的选择，不必重新编译内核。
+
+use super::manager::{stride_cmp, stride_pass, StrideQueue};
+use super::TaskControlBlock;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+/// 编译时选择的调度策略名称，启动参数没有指定调度策略时使用
+pub const DEFAULT_SCHEDULER: &str = if cfg!(feature = "sched-fifo") {
+    "fifo"
+} else if cfg!(feature = "sched-rr") {
+    "rr"
+} else {
+    "stride"
+};
+
+/// 调度策略：维护就绪队列，决定下一个运行的任务以及何时抢占当前任务。
+/// 实例保存在全局的 `TASK_MANAGER` 中，因此要求 `Send`
+pub trait Scheduler: Send {
+    /// 策略名称，显示在 /proc/stat 中
+    fn name(&self) -> &'static str;
+    /// 将就绪的任务加入队列
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// 取出下一个要运行的任务，队列为空时返回 `None`
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// 当前任务 `current` 经历了一次时钟中断，返回它是否应当让出处理器
+    fn on_tick(&mut self, current: &TaskControlBlock) -> bool;
+    /// 仍可运行的任务 `task` 让出处理器（yield 或时间片用完），将它放回队列
+    fn on_yield(&mut self, task: Arc<TaskControlBlock>) {
+        self.add(task);
+    }
+    /// 被唤醒的任务 `woken` 是否应当抢占当前任务 `current`
+    fn preempts(&self, _woken: &TaskControlBlock, _current: &TaskControlBlock) -> bool {
+        false
+    }
+    /// 队列中的任务数
+    fn len(&self) -> usize;
+}
+
+/// 按名称创建调度策略，名称未知时返回 `None`
+pub fn new_scheduler(name: &str) -> Option<Box<dyn Scheduler>> {
+    match name {
+        "stride" => Some(Box::new(StrideScheduler::new())),
+        "fifo" => Some(Box::new(FifoScheduler::new())),
+        "rr" => Some(Box::new(RoundRobinScheduler::new())),
+        _ => None,
+    }
+}
+
+/// 消耗当前任务的一个时间片，返回时间片是否已经用完
+fn consume_time_slice(current: &TaskControlBlock) -> bool {
+    let mut inner = current.inner_exclusive_access();
+    inner.time_slice = inner.time_slice.saturating_sub(1);
+    inner.time_slice == 0
+}
+
+/// stride 调度：每次取出 stride 最小的任务并按优先级推进它的 stride，
+/// 时间片用完或被唤醒的任务 stride 更小时抢占当前任务
+pub struct StrideScheduler {
+    ready_queue: StrideQueue<Arc<TaskControlBlock>>, // 按 stride 排序的就绪队列
+}
+
+impl StrideScheduler {
+    /// 创建一个空的 stride 调度器
+    fn new() -> Self {
+        Self {
+            ready_queue: StrideQueue::new(),
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn name(&self) -> &'static str {
+        "stride"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        // stride 在任务被调度时更新，任务在队列中期间保持不变
+        let stride = task.inner_exclusive_access().stride;
+        self.ready_queue.push(stride, task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.ready_queue.pop()?;
+        task.update_stri();
+        Some(task)
+    }
+    fn on_tick(&mut self, current: &TaskControlBlock) -> bool {
+        // stride 仍然决定下一个任务，时间片只限制当前任务连续运行的时间
+        consume_time_slice(current)
+    }
+    fn preempts(&self, woken: &TaskControlBlock, current: &TaskControlBlock) -> bool {
+        let woken_stride = woken.inner_exclusive_access().stride;
+        let current_stride = current.inner_exclusive_access().stride;
+        stride_cmp(woken_stride, current_stride) == Ordering::Less
+    }
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
+
+/// 先来先服务：任务一直运行到阻塞、yield 或退出，时钟中断不抢占
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl FifoScheduler {
+    /// 创建一个空的先来先服务调度器
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for FifoScheduler {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, _current: &TaskControlBlock) -> bool {
+        false
+    }
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
+
+/// 时间片轮转：按 FIFO 顺序运行，时间片用完的任务排到队尾，忽略优先级
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RoundRobinScheduler {
+    /// 创建一个空的时间片轮转调度器
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn pick_next(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, current: &TaskControlBlock) -> bool {
+        consume_time_slice(current)
+    }
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
+
+/// 通过 [`new_scheduler`] 分别创建三种策略，用不会运行的内核线程检查各自的出队顺序和抢占
+#[allow(unused)]
+pub fn scheduler_policy_test() {
+    use alloc::vec::Vec;
+
+    fn never_run() {}
+    let new_tasks = |n: usize| -> Vec<Arc<TaskControlBlock>> {
+        (0..n).map(|_| Arc::new(TaskControlBlock::new_kernel(never_run, "sched_test"))).collect()
+    };
+    let same = |a: &Option<Arc<TaskControlBlock>>, b: &Arc<TaskControlBlock>| {
+        a.as_ref().is_some_and(|a| Arc::ptr_eq(a, b))
+    };
+
+    // fifo：按加入的顺序出队，时钟中断从不抢占
+    let mut fifo = new_scheduler("fifo").unwrap();
+    assert_eq!(fifo.name(), "fifo");
+    let tasks = new_tasks(3);
+    for task in &tasks {
+        fifo.add(task.clone());
+    }
+    assert_eq!(fifo.len(), 3);
+    for task in &tasks {
+        assert!(same(&fifo.pick_next(), task));
+    }
+    assert!(fifo.pick_next().is_none());
+    tasks[0].inner_exclusive_access().time_slice = 1;
+    assert!((0..10).all(|_| !fifo.on_tick(&tasks[0])));
+
+    // rr：按加入的顺序出队，时间片用完时抢占，让出的任务排到队尾
+    let mut rr = new_scheduler("rr").unwrap();
+    assert_eq!(rr.name(), "rr");
+    let tasks = new_tasks(3);
+    for task in &tasks {
+        rr.add(task.clone());
+    }
+    let first = rr.pick_next();
+    assert!(same(&first, &tasks[0]));
+    tasks[0].inner_exclusive_access().time_slice = 2;
+    assert!(!rr.on_tick(&tasks[0]));
+    assert!(rr.on_tick(&tasks[0]));
+    rr.on_yield(first.unwrap());
+    for i in [1, 2, 0] {
+        assert!(same(&rr.pick_next(), &tasks[i]));
+    }
+
+    // stride：stride 最小的任务先出队；两个任务被调度的次数之比接近步幅之比的倒数
+    let mut stride = new_scheduler("stride").unwrap();
+    assert_eq!(stride.name(), "stride");
+    let tasks = new_tasks(3);
+    for (task, value) in tasks.iter().zip([30, 10, 20]) {
+        task.inner_exclusive_access().stride = value;
+        stride.add(task.clone());
+    }
+    for i in [1, 2, 0] {
+        assert!(same(&stride.pick_next(), &tasks[i]));
+    }
+    // 被唤醒的任务 stride 更小时抢占当前任务
+    assert!(stride.preempts(&tasks[1], &tasks[0]));
+    assert!(!stride.preempts(&tasks[0], &tasks[1]));
+    let tasks = new_tasks(2);
+    let mut runs = [0usize; 2];
+    for (task, pri) in tasks.iter().zip([2, 8]) {
+        task.set_priority(pri);
+        task.inner_exclusive_access().stride = 0;
+        stride.add(task.clone());
+    }
+    for _ in 0..500 {
+        let task = stride.pick_next().unwrap();
+        let i = tasks.iter().position(|t| Arc::ptr_eq(t, &task)).unwrap();
+        runs[i] += 1;
+        stride.on_yield(task);
+    }
+    let expected = stride_pass(2) * 100 / stride_pass(8);
+    let actual = (runs[1] * 100 / runs[0]) as u64;
+    assert!(actual.abs_diff(expected) <= 20, "runs {:?}: ratio {}% but expected {}%", runs, actual, expected);
+    info!("scheduler_policy_test passed!");
+}
@@ -0,0 +1,82 @@
+//! 调度和系统调用统计
+//!
+//! 热路径上只做一次自增或比较，读取时（/proc/stat、`sys_perf_dump`）再整理输出。
+//! 目前只有一个核心，统计放在一个全局的 `UPSafeCell` 中。
+
+use crate::sync::UPSafeCell;
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+use lazy_static::*;
+
+/// 按调用号分别统计的系统调用号上限，大于所有已定义的调用号；更大的调用号只统计总数
+pub const MAX_SYSCALL_ID: usize = 512;
+
+/// 全局的调度和系统调用计数器
+pub struct SchedStats {
+    /// 上下文切换的总次数
+    pub context_switches: u64,
+    /// 就绪队列长度的最大值
+    pub ready_high_watermark: usize,
+    /// 处理器空闲（停在 wfi 上）的总时间，单位为时钟周期
+    pub idle_cycles: u64,
+    /// 各系统调用的调用次数，下标为系统调用号
+    pub syscalls: [u64; MAX_SYSCALL_ID],
+    /// 调用号不小于 MAX_SYSCALL_ID 的系统调用次数，不与任何一个调用号混在一起
+    pub unknown_syscalls: u64,
+}
+
+lazy_static! {
+    /// 全局唯一的统计实例
+    pub static ref SCHED_STATS: UPSafeCell<SchedStats> = unsafe {
+        UPSafeCell::new(SchedStats {
+            context_switches: 0,
+            ready_high_watermark: 0,
+            idle_cycles: 0,
+            syscalls: [0; MAX_SYSCALL_ID],
+            unknown_syscalls: 0,
+        })
+    };
+}
+
+/// 记录一次上下文切换
+pub fn count_context_switch() {
+    SCHED_STATS.exclusive_access().context_switches += 1;
+}
+
+/// 记录入队后就绪队列的长度 `len`
+pub fn note_ready_len(len: usize) {
+    let mut stats = SCHED_STATS.exclusive_access();
+    stats.ready_high_watermark = stats.ready_high_watermark.max(len);
+}
+
+/// 记录一段 `cycles` 个时钟周期的空闲时间
+pub fn add_idle_cycles(cycles: u64) {
+    SCHED_STATS.exclusive_access().idle_cycles += cycles;
+}
+
+/// 记录一次调用号为 `syscall_id` 的系统调用
+pub fn count_syscall(syscall_id: usize) {
+    let mut stats = SCHED_STATS.exclusive_access();
+    match stats.syscalls.get_mut(syscall_id) {
+        Some(count) => *count += 1,
+        None => stats.unknown_syscalls += 1,
+    }
+}
+
+/// 以 /proc/stat 的风格输出统计：每行一个名称和数值，系统调用只列出调用过的
+pub fn render_stats() -> String {
+    let stats = SCHED_STATS.exclusive_access();
+    let policy = super::manager::TASK_MANAGER.exclusive_access().policy();
+    let mut out = format!(
+        "sched {}\nctxt {}\nidle {}\nready_max {}\n",
+        policy, stats.context_switches, stats.idle_cycles, stats.ready_high_watermark
+    );
+    for (id, count) in stats.syscalls.iter().enumerate().filter(|(_, &count)| count > 0) {
+        writeln!(out, "syscall {} {}", id, count).unwrap();
+    }
+    if stats.unknown_syscalls > 0 {
+        writeln!(out, "syscall_unknown {}", stats.unknown_syscalls).unwrap();
+    }
+    out
+}
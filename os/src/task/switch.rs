@@ -6,4 +6,5 @@ global_asm!(include_str!("switch.S"));
 
 extern "C" {
     pub fn __switch(current_task_cx_ptr: *mut TaskContext, next_task_cx_ptr: *const TaskContext);
+    pub fn __kernel_thread_entry();
 }
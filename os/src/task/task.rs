@@ -1,32 +1,36 @@
-//! 与任务管理相关的类型 & 完全更改 TCB 的函数
+//! 与线程管理相关的类型 & 完全更改 TCB 的函数
+use super::fp::FpContext;
 use super::TaskContext;
-use super::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
-use crate::fs::{File, Stdin, Stdout};
-use crate::config::{BIGSTRIDE, PAGE_SIZE, TRAP_CONTEXT_BASE};
-use crate::mm::page_table::PTEFlags;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, VirtPageNum, KERNEL_SPACE};
+use super::{kstack_alloc, KernelStack, ProcessControlBlock};
+use super::manager::stride_pass;
+use crate::config::{TASK_COMM_LEN, TIME_SLICE_TICKS};
+use crate::mm::{trap_cx_va, MapError, PhysPageNum};
 use crate::sync::UPSafeCell;
 use crate::timer::get_time;
-use crate::trap::{trap_handler, TrapContext};
+use crate::trap::TrapContext;
+use riscv::register::sstatus::FS;
 use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
-use alloc::vec;
-use alloc::vec::Vec;
 use core::cell::RefMut;
 
-/// 任务信息结构体
+/// 任务信息结构体，时间均以时钟周期为单位，只统计任务占用 CPU 的时间
 #[derive(Copy, Clone)]
 pub struct TaskInfo {
-    /// 任务开始时间
+    /// 本次被调度运行的开始时间
     pub start:u64,
-    /// 任务运行的总时间
+    /// 被换下 CPU 之前累计运行的总时间
     pub all:u64,
-    /// 系统运行时间
+    /// 在内核中（处理系统调用、缺页和中断）运行的时间
     pub stime:u64,
-    /// 子任务用户态运行时间
+    /// 本段内核运行时间的开始：从用户态陷入内核，或在内核中被重新调度的时间
+    pub kernel_start:u64,
+    /// 是否正在内核中处理用户态的陷入，在内核中被换下 CPU 时保持为 true
+    pub in_kernel:bool,
+    /// 已回收子任务的用户态运行时间
     pub cutime:u64,
-    /// 子任务系统态运行时间
+    /// 已回收子任务的系统态运行时间
     pub cstime:u64,
 }
 
@@ -37,35 +41,70 @@ impl TaskInfo {
             start:get_time() as u64, // 设置为当前时间
             all:0,                  // 总时间初始为 0
             stime:0,                // 系统时间初始为 0
+            kernel_start:0,         // 尚未进入内核
+            in_kernel:false,        // 新线程从 trap_return 开始运行，不计入系统时间
             cutime:0,               // 子任务用户态时间初始为 0
             cstime:0,               // 子任务系统态时间初始为 0
         }
     }
 
-    /// 更新系统运行时间
-    pub fn update_sys(mut self, ms:usize){
-        self.stime += ms as u64; 
+    /// 截至 `now` 占用 CPU 的总时间：已累计的时间加上本次被调度以来的时间
+    pub fn cpu_time(&self, now: usize) -> u64 {
+        self.all + now as u64 - self.start
     }
-    /// 更新子任务用户态运行时间
-    pub fn update_cu(mut self, time:usize){
-        self.cutime = time as u64;
+    /// 正在运行的任务截至 `now` 的系统时间：已累计的时间加上本段内核运行的时间
+    pub fn sys_time(&self, now: usize) -> u64 {
+        if self.in_kernel {
+            self.stime + now as u64 - self.kernel_start
+        } else {
+            self.stime
+        }
+    }
+    /// 任务在 `now` 时刻被调度运行，在内核中被换下的任务从此刻重新开始计算系统时间
+    pub fn switch_in(&mut self, now: usize) {
+        self.start = now as u64;
+        self.kernel_start = now as u64;
+    }
+    /// 任务在 `now` 时刻被换下 CPU，累计本次运行的时间和其中在内核中运行的时间
+    pub fn switch_out(&mut self, now: usize) {
+        self.all = self.cpu_time(now);
+        self.stime = self.sys_time(now);
+    }
+    /// 任务在 `now` 时刻从用户态陷入内核
+    pub fn enter_kernel(&mut self, now: usize) {
+        self.in_kernel = true;
+        self.kernel_start = now as u64;
+    }
+    /// 任务在 `now` 时刻返回用户态，累计本段内核运行的时间
+    pub fn leave_kernel(&mut self, now: usize) {
+        self.stime = self.sys_time(now);
+        self.in_kernel = false;
+    }
+    /// 累加已回收子任务的用户态运行时间
+    pub fn update_cu(&mut self, time:u64){
+        self.cutime += time;
     }
-    /// 更新子任务系统态运行时间
-    pub fn update_cs(mut self, time:usize){
-        self.cstime = time as u64;
+    /// 累加已回收子任务的系统态运行时间
+    pub fn update_cs(&mut self, time:u64){
+        self.cstime += time;
+    }
+    /// 累加一个已退出线程的运行时间
+    pub fn add_thread(&mut self, thread: &TaskInfo){
+        self.all += thread.all;
+        self.stime += thread.stime;
     }
 }
 
-/// 任务控制块结构体
+/// 任务控制块结构体，描述一个线程
 ///
-/// 直接保存运行期间不会改变的内容
+/// 直接保存运行期间不会改变的内容，进程共享的资源保存在所属的 [`ProcessControlBlock`] 中
 pub struct TaskControlBlock {
     // 不可变部分
-    /// 进程标识符
-    pub pid: PidHandle,
-    /// 父进程 ID
-    pub ppid: usize,
-    /// 与 PID 对应的内核栈
+    /// 所属的进程，内核线程不属于任何进程
+    pub process: Weak<ProcessControlBlock>,
+    /// 线程在进程内的编号
+    pub tid: usize,
+    /// 线程的内核栈
     pub kernel_stack: KernelStack,
     /// 可变部分
     inner: UPSafeCell<TaskControlBlockInner>,
@@ -76,47 +115,38 @@ pub struct TaskControlBlockInner {
     /// 放置陷阱上下文的帧的物理页号
     pub trap_cx_ppn: PhysPageNum,
 
-    /// 应用程序数据只能出现在应用地址空间低于 `base_size` 的区域
-    pub base_size: usize,
+    /// 陷阱上下文在地址空间中的槽位，决定其虚拟地址
+    pub trap_cx_slot: usize,
 
     /// 保存任务上下文
     pub task_cx: TaskContext,
 
-    /// 维护当前进程的执行状态
+    /// 维护当前线程的执行状态
     pub task_status: TaskStatus,
 
-    /// 应用程序地址空间
-    pub memory_set: MemorySet,
-
-    /// 当前进程的父进程。
-    /// 使用 `Weak` 不会影响父进程的引用计数
-    pub parent: Option<Weak<TaskControlBlock>>,
-
-    /// 包含当前进程所有子进程的 TCB 的向量
-    pub children: Vec<Arc<TaskControlBlock>>,
-
-    /// 当发生主动退出或执行错误时设置
-    pub exit_code: i32,
-    /// 文件描述符表
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
-
-    /// 堆底地址
-    pub heap_bottom: usize,
-
-    /// brk
-    pub program_brk: usize,
-
     /// 任务信息
     pub task_info:Box<TaskInfo>,   
 
-    /// 步幅值，用于 stride 调度
-    pub stride: isize,
+    /// 步幅值，用于 stride 调度，按回绕距离比较
+    pub stride: u64,
 
     /// 任务优先级
     pub pri: isize, 
 
-    /// 当前工作目录
-    pub pwd: String,
+    /// 剩余的时间片（时钟中断次数），耗尽时被抢占
+    pub time_slice: usize,
+
+    /// 命令名，用于日志和 /proc/<pid>/comm，最长 `TASK_COMM_LEN - 1` 字节
+    pub name: String,
+
+    /// 主动让出处理器（阻塞或睡眠）的次数
+    pub nvcsw: u64,
+
+    /// 仍可运行时被换下（时间片用完、被抢占或 yield）的次数
+    pub nivcsw: u64,
+
+    /// 浮点寄存器的保存区，线程第一次使用浮点指令时分配
+    pub fp: Option<Box<FpContext>>,
 }
 
 
@@ -124,8 +154,9 @@ impl TaskControlBlockInner {
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+    /// 陷阱上下文在用户地址空间中的虚拟地址，返回用户态时写入 sscratch
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_va(self.trap_cx_slot)
     }
     fn get_status(&self) -> TaskStatus {
         self.task_status
@@ -133,16 +164,25 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+    /// 换下线程时调用：用户态修改过浮点寄存器（sstatus.FS 为 Dirty）时保存它们，并标记为 Clean
+    pub fn save_fp_if_dirty(&mut self) {
+        let trap_cx_ppn = self.trap_cx_ppn;
+        let Some(fp) = self.fp.as_mut() else {
+            return; // 没有使用过浮点指令，也可能是内核线程
+        };
+        let trap_cx = trap_cx_ppn.get_mut::<TrapContext>();
+        if trap_cx.sstatus.fs() == FS::Dirty {
+            fp.save();
+            trap_cx.sstatus.set_fs(FS::Clean);
         }
     }
-    pub fn set_pwd(&mut self, new_pwd:String){
-        self.pwd = new_pwd;
+    /// 设置命令名，和 Linux 一样截断到 `TASK_COMM_LEN - 1` 字节
+    pub fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(TASK_COMM_LEN - 1);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name = String::from(&name[..len]);
     }
 }
 
@@ -152,231 +192,68 @@ impl TaskControlBlock {
         self.inner.exclusive_access()
     }
 
-    /// 获取应用程序页表的地址
-    pub fn get_user_token(&self) -> usize {
-        let inner = self.inner_exclusive_access();
-        inner.memory_set.token()
-    }    
-
-    /// 创建一个新进程
-    ///
-    /// 当前仅用于创建 `initproc`
-    pub fn new(elf_data: &[u8]) -> Self {
-        // 从 ELF 程序头创建 memory_set，并包含 trampoline、trap 上下文以及用户栈
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        
-        // 获取陷阱上下文所在物理页号
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // 分配 PID 并在内核空间分配一个内核栈
-        let pid_handle = pid_alloc();
-        let kernel_stack = kstack_alloc();
+    /// 为进程 `process` 创建编号为 `tid`、命令名为 `name` 的线程，陷阱上下文位于槽位
+    /// `trap_cx_slot` 对应的物理页 `trap_cx_ppn`；线程首次被调度时从 `trap_return` 返回用户态。
+    /// 调用者负责初始化陷阱上下文并把线程登记到进程的线程表
+    pub fn new(
+        process: &Arc<ProcessControlBlock>,
+        tid: usize,
+        trap_cx_slot: usize,
+        trap_cx_ppn: PhysPageNum,
+        name: &str,
+    ) -> Result<Self, MapError> {
+        let kernel_stack = kstack_alloc()?;
         let kernel_stack_top = kernel_stack.get_top();
-        // 在内核栈顶推入一个任务上下文，用于跳转到 `trap_return`
-        let task_control_block = Self {
-            pid: pid_handle,
-            ppid: 0,
+        Ok(Self {
+            process: Arc::downgrade(process),
+            tid,
             kernel_stack,
             inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
+                UPSafeCell::new(TaskControlBlockInner::new(
                     trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> 标准输入 stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> 标准输出 stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> 标准错误 stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                    heap_bottom: user_sp,
-                    program_brk: user_sp + PAGE_SIZE,
-                    task_info:Box::new(TaskInfo::new()),
-                    stride: 0,
-                    pri: 16,
-                    pwd: String::from("/"),
-                })
+                    trap_cx_slot,
+                    TaskContext::goto_trap_return(kernel_stack_top),
+                    name,
+                ))
             },
-        };
-        // 准备用户空间的 TrapContext
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-       
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
-    }
-
-    /// 加载一个新的 ELF 文件以替换原来的应用程序地址空间，并开始执行
-    pub fn exec(&self, elf_data: &[u8]) {
-        // 从 ELF 程序头创建 memory_set，并包含 trampoline、trap 上下文以及用户栈
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // **** 独占访问当前 TCB
-        let mut inner = self.inner_exclusive_access();
-        // 替换 memory_set
-        inner.memory_set = memory_set;
-        // 更新 trap_cx 的物理页号
-        inner.trap_cx_ppn = trap_cx_ppn;
-        
-        // 初始化 trap_cx
-        let trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        
-        *inner.get_trap_cx() = trap_cx;
-        // **** 释放当前 PCB
+        })
     }
 
-    /// 父进程 fork 子进程
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
-        // ---- 锁定父 PCB
-        let mut parent_inner = self.inner_exclusive_access();
-        // 拷贝用户空间（包括陷阱上下文）
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // 在内核空间分配 PID 和内核栈
-        let pid_handle = pid_alloc();
-        let kernel_stack = kstack_alloc();
+    /// 创建一个内核线程，命令名为 "kthread:`name`"
+    ///
+    /// 内核线程不属于任何进程，没有用户地址空间和陷阱上下文，在内核地址空间中运行：
+    /// 首次被调度时在自己的内核栈上执行 `entry`，返回后退出。内存不足时直接 panic
+    pub fn new_kernel(entry: fn(), name: &str) -> Self {
+        let kernel_stack = kstack_alloc().unwrap_or_else(|_| panic!("创建内核线程 {} 时内存不足", name));
         let kernel_stack_top = kernel_stack.get_top();
-        // 拷贝文件描述符表
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            ppid: self.getpid(),
+        Self {
+            process: Weak::new(),
+            tid: 0,
             kernel_stack,
             inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    task_info:Box::new(TaskInfo::new()),
-                    stride: 0,
-                    pri: 16,
-                    pwd: parent_inner.pwd.clone(),
-                })
+                UPSafeCell::new(TaskControlBlockInner::new(
+                    PhysPageNum(0),
+                    0,
+                    TaskContext::goto_kernel_thread(kernel_stack_top, entry),
+                    &format!("kthread:{}", name),
+                ))
             },
-        });
-        // 添加子进程
-        parent_inner.children.push(task_control_block.clone());
-        // 修改 trap_cx 中的 kernel_sp
-        // **** 独占访问子 PCB
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // 返回子进程
-        task_control_block
-        // **** 释放子 PCB
-        // ---- 释放父 PCB
+        }
     }
 
-    /// spawn 创建子进程
-    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
-        // ---- 独占访问父 PCB
-        let mut parent_inner = self.inner_exclusive_access();
-        // 拷贝用户空间（包括陷阱上下文）
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
-            .unwrap()
-            .ppn();
-        // 分配 PID 和内核栈
-        let pid_handle = pid_alloc();
-        let kernel_stack = kstack_alloc();
-        let kernel_stack_top = kernel_stack.get_top();
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            ppid: self.getpid(),
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> 标准输入 stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> 标准输出 stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> 标准错误 stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    task_info:Box::new(TaskInfo::new()),
-                    stride: 0,
-                    pri: 16,
-                    pwd: parent_inner.pwd.clone(),
-                })
-            },
-        });
-        // 添加子进程
-        parent_inner.children.push(task_control_block.clone());
-        // 修改 trap_cx 中的 kernel_sp
-        // **** 独占访问子 PCB
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        // 返回子进程
-        task_control_block
-        // **** 释放子 PCB
-        // ---- 释放父 PCB
+    /// 线程所属的进程，内核线程调用时 panic
+    pub fn process(&self) -> Arc<ProcessControlBlock> {
+        self.process.upgrade().expect("内核线程不属于任何进程")
     }
 
-    /// 获取进程的 pid
+    /// 获取所属进程的 pid，内核线程调用时 panic
     pub fn getpid(&self) -> usize {
-        self.pid.0
+        self.process().getpid()
     }
 
-    /// 获取父进程的 pid
-    pub fn getppid(&self) -> usize{
-        self.ppid
+    /// 获取命令名的副本
+    pub fn name(&self) -> String {
+        self.inner_exclusive_access().name.clone()
     }
 
     /// 设置优先级
@@ -389,46 +266,10 @@ impl TaskControlBlock {
     /// 更新 stride 值
     pub fn update_stri(&self){
         let mut inner = self.inner_exclusive_access();
-        inner.stride += BIGSTRIDE/inner.pri;
+        inner.stride = inner.stride.wrapping_add(stride_pass(inner.pri));
         drop(inner);
     }
 
-    /// 修改brk
-    pub fn change_program_brk(&self, new_add: i64) -> Option<usize> {
-        let mut inner = self.inner_exclusive_access();
-        let heap_bottom = inner.heap_bottom;
-        let old_break = inner.program_brk;
-        if new_add == 0{
-            return Some(old_break as usize);
-        }
-        let size = new_add - old_break as i64;
-        let new_brk = inner.program_brk as isize + size as isize;
-        if new_brk < heap_bottom as isize {
-            return None;
-        }
-        if size > PAGE_SIZE as i64{
-            let result = if size < 0 {
-                inner
-                    .memory_set
-                    .shrink_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-            } else {
-                inner
-                    .memory_set
-                    .append_to(VirtAddr(heap_bottom), VirtAddr(new_brk as usize))
-            };
-            if result {
-                inner.program_brk = new_brk as usize;
-                Some(old_break)
-            } else {
-                None
-            }
-        }else{
-            inner.program_brk = new_brk as usize;
-            Some(new_brk as usize)
-        }
-
-    }
-
     /// 显示任务信息
     pub fn show_info(&self) -> TaskInfo{
         let inner = self.inner.exclusive_access();
@@ -437,26 +278,30 @@ impl TaskControlBlock {
         task_info
     }
 
-    /// 映射虚拟页号到物理页号
-    pub fn map(&self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> isize{
-        let mut inner = self.inner.exclusive_access();
-        let task = &mut inner.memory_set;
-        task.map(vpn, ppn, flags);
-        drop(inner);
-        0
-    }
+}
 
-    /// 取消映射虚拟页号
-    pub fn unmap(&self, vpn: VirtPageNum) -> isize{
-        let mut inner = self.inner.exclusive_access();
-        let task = &mut inner.memory_set;
-        task.unmap(vpn);
-        drop(inner);
-        0
+impl TaskControlBlockInner {
+    /// 新线程的初始状态：就绪，使用默认的优先级和完整的时间片
+    fn new(trap_cx_ppn: PhysPageNum, trap_cx_slot: usize, task_cx: TaskContext, name: &str) -> Self {
+        let mut inner = Self {
+            trap_cx_ppn,
+            trap_cx_slot,
+            task_cx,
+            task_status: TaskStatus::Ready,
+            task_info: Box::new(TaskInfo::new()),
+            stride: 0,
+            pri: 16,
+            time_slice: TIME_SLICE_TICKS,
+            name: String::new(),
+            nvcsw: 0,
+            nivcsw: 0,
+            fp: None,
+        };
+        inner.set_name(name);
+        inner
     }
 }
 
-
 #[derive(Copy, Clone, PartialEq)]
 /// task status: UnInit, Ready, Running, Exited
 pub enum TaskStatus {
@@ -466,6 +311,8 @@ pub enum TaskStatus {
     Ready,
     /// running
     Running,
+    /// blocked on a wait queue or the timer list
+    Blocked,
     /// exited
     Zombie,
 }
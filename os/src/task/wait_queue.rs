@@ -0,0 +1,46 @@
+//! 等待队列
+//!
+//! 需要等待某个条件的任务调用 [`WaitQueue::wait`] 阻塞自己，不再进入就绪队列，
+//! 直到条件的另一方调用 `wake_one`/`wake_all` 把它放回就绪队列。
+//! 单核且内核态不可抢占，检查条件和进入等待之间不会丢失唤醒。
+
+use super::{block_current_and_run_next, wakeup_task, TaskControlBlock};
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// 阻塞在某个条件上的任务队列，按 FIFO 顺序唤醒
+pub struct WaitQueue {
+    waiters: UPSafeCell<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    /// 创建一个空的等待队列
+    pub fn new() -> Self {
+        Self {
+            waiters: unsafe { UPSafeCell::new(VecDeque::new()) },
+        }
+    }
+    /// 阻塞当前任务直到被唤醒，返回后调用者需要重新检查等待的条件
+    pub fn wait(&self) {
+        block_current_and_run_next(|task| self.waiters.exclusive_access().push_back(task));
+    }
+    /// 唤醒最早进入等待的任务，返回是否有任务被唤醒
+    pub fn wake_one(&self) -> bool {
+        let task = self.waiters.exclusive_access().pop_front();
+        task.map(wakeup_task).is_some()
+    }
+    /// 唤醒所有等待的任务，返回被唤醒的任务数
+    pub fn wake_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.exclusive_access());
+        let count = waiters.len();
+        waiters.into_iter().for_each(wakeup_task);
+        count
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
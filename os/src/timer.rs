@@ -2,6 +2,12 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 /// The number of ticks per second
 const TICKS_PER_SEC: usize = 100;
@@ -9,6 +15,8 @@ const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
 /// The number of microseconds per second
 const MICRO_PER_SEC: usize = 1_000_000;
+/// The number of nanoseconds per second
+const NANO_PER_SEC: u64 = 1_000_000_000;
 
 /// Get the current time in ticks
 pub fn get_time() -> usize {
@@ -25,7 +33,59 @@ pub fn get_time_us() -> usize {
     time::read() * MICRO_PER_SEC / CLOCK_FREQ
 }
 
+/// get current time in nanoseconds
+pub fn get_time_ns() -> u64 {
+    (time::read() as u128 * NANO_PER_SEC as u128 / CLOCK_FREQ as u128) as u64
+}
+
 /// Set the next timer interrupt
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// A task sleeping on the timer list until `expire_us`
+pub struct TimerCondVar {
+    /// The time in microseconds at which the task becomes ready again
+    pub expire_us: usize,
+    /// The sleeping task
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_us == other.expire_us
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerCondVar {
+    /// Reversed so that the `BinaryHeap` pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_us.cmp(&self.expire_us)
+    }
+}
+
+lazy_static! {
+    /// Tasks sleeping until a deadline, earliest first
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+}
+
+/// Put `task` on the timer list until `expire_us`
+pub fn add_timer(expire_us: usize, task: Arc<TaskControlBlock>) {
+    TIMERS.exclusive_access().push(TimerCondVar { expire_us, task });
+}
+
+/// Move every task whose deadline has passed back to the ready queue,
+/// called on each timer tick and while the scheduler is idle
+pub fn check_timer() {
+    let now = get_time_us();
+    let mut timers = TIMERS.exclusive_access();
+    while timers.peek().map_or(false, |timer| timer.expire_us <= now) {
+        wakeup_task(timers.pop().unwrap().task);
+    }
+}
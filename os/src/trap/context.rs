@@ -1,5 +1,5 @@
 //! Implementation of [`TrapContext`]
-use riscv::register::sstatus::{self, Sstatus, SPP};
+use riscv::register::sstatus::{self, Sstatus, FS, SPP};
 
 #[repr(C)]
 #[derive(Debug)]
@@ -24,6 +24,10 @@ impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    /// put the tp(thread pointer) into x\[4\] field of TrapContext
+    pub fn set_tp(&mut self, tp: usize) {
+        self.x[4] = tp;
+    }
     /// init the trap context of an application
     pub fn app_init_context(
         entry: usize,
@@ -35,6 +39,8 @@ impl TrapContext {
         let mut sstatus = sstatus::read();
         // set CPU privilege to User after trapping back
         sstatus.set_spp(SPP::User);
+        // the FPU stays off until the first FP instruction traps, see `enable_fp_on_first_use`
+        sstatus.set_fs(FS::Off);
         let mut cx = Self {
             x: [0; 32],
             sstatus,
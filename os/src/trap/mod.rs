@@ -14,13 +14,20 @@
 
 mod context;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT_BASE};
+use crate::config::TRAMPOLINE;
+use crate::drivers::{handle_external_interrupts, plic};
+use crate::drivers::uart::uart_irq;
+use crate::fs::poll_console_input;
+use crate::mm::flush_if_shared;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    consume_time_slice, current_pid, current_task, enable_fp_on_first_use, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, enter_kernel, exit_current_and_run_next, handle_page_fault, is_stack_overflow,
+    kstack_guard_id, leave_kernel, suspend_current_and_run_next, take_need_resched,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
 use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
@@ -35,8 +42,11 @@ pub fn init() {
 }
 
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kerneltrap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__kerneltrap as usize, TrapMode::Direct);
     }
 }
 
@@ -53,12 +63,45 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// enable external interrupts in supervisor mode, once the PLIC is set up
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Idle the hart until the next timer tick or device interrupt when no task
+/// is ready.
+///
+/// `sstatus.SIE` stays clear in the kernel, but `wfi` still returns once an
+/// interrupt enabled in `sie` is pending, so the tick wakes the hart without
+/// trapping, and device interrupts are claimed from the PLIC right here.
+/// Re-arming the timer clears a pending tick; if a device woke us first this
+/// delays the next tick by at most one interval.
+pub fn wait_for_interrupt() {
+    enable_timer_interrupt();
+    unsafe {
+        riscv::asm::wfi();
+    }
+    handle_external_interrupts();
+    set_next_trigger();
+}
+
 /// trap handler
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
     let scause = scause::read();
     let stval = stval::read();
+    // only a scheduled task can return to user mode, so a user trap always has one
+    assert!(
+        current_task().is_some(),
+        "trap {:?} from user mode with no current task, stval = {:#x}",
+        scause.cause(),
+        stval
+    );
+    // everything from here until trap_return is charged to the task as system time
+    enter_kernel();
     // println!("into {:?}", scause.cause());
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
@@ -71,21 +114,45 @@ pub fn trap_handler() -> ! {
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+            if handle_page_fault(
+                stval.into(),
+                scause.cause() == Trap::Exception(Exception::StorePageFault),
+            ) =>
+        {
+            // 惰性映射的页面已经装入，返回用户态重新执行该指令
+        }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] trap_handler:  {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
+            if is_stack_overflow(stval.into()) {
+                println!(
+                    "[kernel] trap_handler:  stack overflow in application (pid {}, {}), bad addr = {:#x}, kernel killed it.",
+                    current_task().unwrap().getpid(),
+                    current_task().unwrap().name(),
+                    stval,
+                );
+            } else {
+                println!(
+                    "[kernel] trap_handler:  {:?} in application (pid {}, {}), bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
+                    scause.cause(),
+                    current_task().unwrap().getpid(),
+                    current_task().unwrap().name(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+            }
             // page fault exit code
             exit_current_and_run_next(-2);
         }
+        Trap::Exception(Exception::IllegalInstruction) if enable_fp_on_first_use() => {
+            // first FP instruction of this thread; the FPU is on now, so run it again
+        }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, kernel killed it.");
             // illegal instruction exit code
@@ -93,7 +160,19 @@ pub fn trap_handler() -> ! {
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            check_timer();
+            // the SBI console has no receive interrupt, so its input is picked up on every tick
+            if uart_irq().is_none() {
+                poll_console_input();
+            }
+            // Stride still picks the next task; the slice only bounds how long this one runs
+            if consume_time_slice() {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            // completes disk requests, takes received frames and console input, and wakes the waiters
+            handle_external_interrupts();
         }
         _ => {
             panic!(
@@ -103,6 +182,10 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // A task woken while handling this trap should run before the rest of our slice
+    if take_need_resched() {
+        suspend_current_and_run_next();
+    }
     //println!("before trap_return");
     trap_return();
 }
@@ -113,9 +196,12 @@ pub fn trap_handler() -> ! {
 /// set the reg a0 = trap_cx_ptr, reg a1 = phy addr of usr page table,
 /// finally, jump to new addr of __restore asm function
 pub fn trap_return() -> ! {
+    leave_kernel();
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT_BASE;
+    // each thread's trap context lives in its own slot below the trampoline
+    let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
+    flush_if_shared(user_satp);
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -134,14 +220,78 @@ pub fn trap_return() -> ! {
     }
 }
 
+/// Timer interrupts taken in S-mode since boot
+static KERNEL_TICKS: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+/// handle an interrupt taken in S-mode on the interrupted stack; `__kerneltrap`
+/// saves the caller-saved registers and resumes the kernel afterwards.
+/// The kernel normally runs with `sstatus.SIE` clear, so this only happens
+/// while interrupts are deliberately enabled, possibly before any task exists:
+/// it must not touch the current task, and only re-arms the timer since the
+/// interrupted code may hold the timer list. Sleepers are woken on the next
+/// tick taken from user mode or in the idle loop. External interrupts only run
+/// the device handlers; the console and network follow-up waits for the next
+/// `handle_external_interrupts`.
+pub extern "C" fn kernel_interrupt() {
+    match scause::read().cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            KERNEL_TICKS.fetch_add(1, Ordering::Relaxed);
+            set_next_trigger();
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => plic::dispatch(plic::BOOT_HART),
+        cause => panic!("unexpected interrupt {:?} from kernel", cause),
+    }
+}
+
+/// Force a timer interrupt while the kernel runs before the first task is
+/// scheduled and check that it is handled in place
+#[cfg(feature = "boot-trap-test")]
+pub fn boot_timer_test() {
+    let before = KERNEL_TICKS.load(Ordering::Relaxed);
+    crate::sbi::set_timer(crate::timer::get_time());
+    unsafe {
+        riscv::register::sstatus::set_sie();
+    }
+    while KERNEL_TICKS.load(Ordering::Relaxed) == before {
+        core::hint::spin_loop();
+    }
+    unsafe {
+        riscv::register::sstatus::clear_sie();
+    }
+    println!("boot_timer_test passed!");
+}
+
 #[no_mangle]
-/// handle trap from kernel
+/// handle an exception from kernel, running on a dedicated stack; `sp` is the stack
+/// pointer at the time of the trap
 /// Unimplement: traps/interrupts/exceptions from kernel mode
 /// Todo: Chapter 9: I/O device
-pub fn trap_from_kernel() -> ! {
+pub extern "C" fn trap_from_kernel(sp: usize) -> ! {
     use riscv::register::sepc;
-    
-    trace!("stval = {:#x}, sepc = {:#x}", stval::read(), sepc::read());
+
+    let stval = stval::read();
+    trace!("stval = {:#x}, sepc = {:#x}", stval, sepc::read());
+    if let Trap::Exception(
+        Exception::StoreFault
+        | Exception::StorePageFault
+        | Exception::LoadFault
+        | Exception::LoadPageFault,
+    ) = scause::read().cause()
+    {
+        if let Some(kstack_id) = kstack_guard_id(stval) {
+            match current_pid() {
+                Some(pid) => panic!(
+                    "kernel stack overflow in pid {} (stack id {}), sp = {:#x}, bad addr = {:#x}",
+                    pid, kstack_id, sp, stval
+                ),
+                None => panic!(
+                    "kernel stack overflow (stack id {}), sp = {:#x}, bad addr = {:#x}",
+                    kstack_id, sp, stval
+                ),
+            }
+        }
+    }
     panic!("a trap {:?} from kernel!", scause::read().cause());
 }
 
@@ -0,0 +1,33 @@
+#!/bin/sh
+# Boot kernel-qemu (left behind by `make all`) with 128M and 512M of guest
+# memory and check that the frame allocator takes the size from the device
+# tree: the frame count printed by init_frame_allocator must grow by exactly
+# (512M - 128M) / 4K. Each boot stops once that line has been printed.
+set -eu
+
+ROOT_IMG=${ROOT_IMG:-sdcard-riscv.img}
+BOOT_TIMEOUT=${BOOT_TIMEOUT:-30}
+
+frames() {
+	timeout "$BOOT_TIMEOUT" qemu-system-riscv64 \
+		-machine virt -m "$1" -nographic -smp 2 \
+		-bios sbi-qemu -kernel kernel-qemu \
+		-drive file="$ROOT_IMG",if=none,format=raw,id=x0 \
+		-device virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0 \
+		</dev/null 2>&1 |
+		sed -n '/^\[kernel\] frames: /{s/^\[kernel\] frames: \([0-9]*\),.*/\1/p;q}'
+}
+
+small=$(frames 128M)
+large=$(frames 512M)
+if [ -z "$small" ] || [ -z "$large" ]; then
+	echo "no frame count in the boot log (128M: '$small', 512M: '$large')" >&2
+	exit 1
+fi
+expected=$(( (512 - 128) * 1024 * 1024 / 4096 ))
+echo "128M: $small frames, 512M: $large frames"
+if [ $((large - small)) -ne "$expected" ]; then
+	echo "512M should have $expected more frames than 128M" >&2
+	exit 1
+fi
+echo "frame count follows the memory size"
@@ -0,0 +1,21 @@
+//! Sv39 的地址、页号和页表
+//!
+//! 内核 mm 模块中与物理内存无关的部分：地址和页号的换算、页表项、三级页表的建立和查找，
+//! 以及把用户缓冲区按页翻译为物理地址范围。页表通过 [`FrameSource`] 分配页帧、访问页帧的内容
+//! 和刷新 TLB：内核从帧分配器分配页帧并直接访问恒等映射的物理内存，
+//! 主机上的测试（`cargo test`）用内存中的页面数组代替物理内存。
+
+#![no_std]
+
+extern crate alloc;
+
+mod address;
+mod page_table;
+
+pub use address::{PhysAddr, PhysPageNum, SimpleRange, SimpleRangeIterator, StepByOne, VPNRange, VirtAddr, VirtPageNum};
+pub use page_table::{BadAddress, FrameSource, OutOfMemory, PTEFlags, PageTable, PageTableEntry, HUGE_PAGE_PAGES, USER_SPACE_END};
+
+/// 页面大小：4 KiB
+pub const PAGE_SIZE: usize = 0x1000;
+/// 页内偏移的位宽
+pub const PAGE_SIZE_BITS: usize = 0xc;
@@ -0,0 +1,391 @@
+//! 实现 [`PageTableEntry`] 和 [`PageTable`]。
+
+use crate::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum, PAGE_SIZE};
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::*;
+
+bitflags! {
+    /// 页表项标志
+    pub struct PTEFlags: u8 {
+        const V = 1 << 0;  // 有效位
+        const R = 1 << 1;  // 可读位
+        const W = 1 << 2;  // 可写位
+        const X = 1 << 3;  // 可执行位
+        const U = 1 << 4;  // 用户态访问位
+        const G = 1 << 5;  // 全局位
+        const A = 1 << 6;  // 已访问位
+        const D = 1 << 7;  // 已修改位
+    }
+}
+
+/// 已换出页面的页表项中使用的软件保留位（RSW 的低位），此时有效位为 0，
+/// 物理页号字段保存交换槽位
+const PTE_SWAPPED: usize = 1 << 8;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+/// 页表项结构
+pub struct PageTableEntry {
+    /// 页表项的比特位
+    pub bits: usize,
+}
+
+impl PageTableEntry {
+    /// 创建新的页表项
+    pub fn new(ppn: PhysPageNum, flags: PTEFlags) -> Self {
+        PageTableEntry {
+            bits: ppn.0 << 10 | flags.bits as usize,
+        }
+    }
+    /// 创建空的页表项
+    pub fn empty() -> Self {
+        PageTableEntry { bits: 0 }
+    }
+    /// 创建已换出页面的页表项，记录其交换槽位
+    pub fn swapped(slot: usize) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+    /// 判断页表项指向的页面是否已被换出
+    pub fn is_swapped(&self) -> bool {
+        !self.is_valid() && self.bits & PTE_SWAPPED != 0
+    }
+    /// 已换出页面的交换槽位
+    pub fn swap_slot(&self) -> usize {
+        self.bits >> 10
+    }
+    /// 从页表项获取物理页号
+    pub fn ppn(&self) -> PhysPageNum {
+        (self.bits >> 10 & ((1usize << 44) - 1)).into()
+    }
+    /// 从页表项获取标志位
+    pub fn flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.bits as u8).unwrap()
+    }
+    /// 判断页表项指向的页面是否有效
+    pub fn is_valid(&self) -> bool {
+        (self.flags() & PTEFlags::V) != PTEFlags::empty()
+    }
+    /// 判断页表项指向的页面是否可读
+    pub fn readable(&self) -> bool {
+        (self.flags() & PTEFlags::R) != PTEFlags::empty()
+    }
+    /// 判断页表项指向的页面是否可写
+    pub fn writable(&self) -> bool {
+        (self.flags() & PTEFlags::W) != PTEFlags::empty()
+    }
+    /// 判断页表项指向的页面是否可执行
+    pub fn executable(&self) -> bool {
+        (self.flags() & PTEFlags::X) != PTEFlags::empty()
+    }
+    /// 判断页表项是否为叶子节点，即指向页面而不是下一级页表
+    pub fn is_leaf(&self) -> bool {
+        self.flags().intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
+}
+
+/// 一个 2 MiB 大页包含的 4 KiB 页数
+pub const HUGE_PAGE_PAGES: usize = 512;
+
+/// 一个页表页中的页表项数
+const PTES_PER_PAGE: usize = PAGE_SIZE / core::mem::size_of::<PageTableEntry>();
+
+/// 物理页面帧耗尽，无法完成分配
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfMemory;
+
+/// 页表使用的物理页帧：分配存放页表的页帧，访问页帧的内容，修改页表项后刷新 TLB
+pub trait FrameSource {
+    /// 分配到的页帧，被丢弃时回收
+    type Frame;
+    /// 分配一个内容全为 0 的页帧，物理内存不足时返回 `None`
+    fn alloc(&self) -> Option<Self::Frame>;
+    /// 页帧的物理页号
+    fn ppn(frame: &Self::Frame) -> PhysPageNum;
+    /// 物理页帧 `ppn` 的内容所在的位置，整个页帧都可以通过它读写
+    fn frame_ptr(&self, ppn: PhysPageNum) -> *mut u8;
+    /// 刷新地址空间 `asid` 中 `vpn` 的 TLB 表项
+    fn flush(&self, asid: usize, vpn: VirtPageNum);
+}
+
+/// 页表结构
+pub struct PageTable<F: FrameSource> {
+    root_ppn: PhysPageNum,      // 根物理页号
+    asid: usize,                // 地址空间标识符
+    frames: Vec<F::Frame>,      // 页框的跟踪器
+    source: F,                  // 页帧的来源
+    user_pages: usize,          // 带有 U 标志的有效叶子页表项数
+}
+
+/// 创建/映射时物理内存不足会返回 [`OutOfMemory`]。
+impl<F: FrameSource> PageTable<F> {
+    /// 在 `source` 中分配根页表，创建使用地址空间标识符 `asid` 的页表
+    pub fn new(source: F, asid: usize) -> Result<Self, OutOfMemory> {
+        let frame = source.alloc().ok_or(OutOfMemory)?;
+        Ok(PageTable {
+            root_ppn: F::ppn(&frame),
+            asid,
+            frames: vec![frame],
+            source,
+            user_pages: 0,
+        })
+    }
+    /// 用于从用户空间获取参数：按 token 访问已有的页表，不持有任何页帧
+    pub fn from_token(satp: usize, source: F) -> Self {
+        Self {
+            root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
+            asid: (satp >> 44) & 0xffff,
+            frames: Vec::new(),
+            source,
+            user_pages: 0,
+        }
+    }
+    /// `source` 中物理页帧 `ppn` 里的页表项。
+    /// 页表页只通过所属的页表访问，一次查找中同一个页表项只有一个引用
+    #[allow(clippy::mut_from_ref)]
+    fn pte_array(source: &F, ppn: PhysPageNum) -> &mut [PageTableEntry] {
+        let ptr = source.frame_ptr(ppn) as *mut PageTableEntry;
+        unsafe { core::slice::from_raw_parts_mut(ptr, PTES_PER_PAGE) }
+    }
+    /// 根据虚拟页号查找页表项，如果不存在则为4KB页表创建一个框架
+    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Result<&mut PageTableEntry, OutOfMemory> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for idx in &idxs[..2] {
+            let pte = &mut Self::pte_array(&self.source, ppn)[*idx];
+            if !pte.is_valid() {
+                let frame = self.source.alloc().ok_or(OutOfMemory)?;
+                *pte = PageTableEntry::new(F::ppn(&frame), PTEFlags::V);
+                self.frames.push(frame);
+            }
+            ppn = pte.ppn();
+        }
+        Ok(&mut Self::pte_array(&self.source, ppn)[idxs[2]])
+    }
+    /// 根据虚拟页号查找页表项，遇到大页的叶子节点时提前返回，同时返回其所在的级别
+    fn find_pte_level(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut Self::pte_array(&self.source, ppn)[*idx];
+            if i == 2 || (pte.is_valid() && pte.is_leaf()) {
+                return Some((pte, i));
+            }
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
+    /// 根据虚拟页号查找页表项
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_level(vpn).map(|(pte, _)| pte)
+    }
+    /// 设置虚拟页号与物理页号之间的映射
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Result<(), OutOfMemory> {
+        let pte = self.find_pte_create(vpn)?;
+        assert!(!pte.is_valid(), "vpn {:?} 在映射之前已经映射", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
+        if flags.contains(PTEFlags::U) {
+            self.user_pages += 1;
+        }
+        Ok(())
+    }
+    /// 在第 1 级页表中建立一个 2 MiB 大页的映射，`vpn` 和 `ppn` 都必须按 2 MiB 对齐
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Result<(), OutOfMemory> {
+        assert!(
+            vpn.0 % HUGE_PAGE_PAGES == 0 && ppn.0 % HUGE_PAGE_PAGES == 0,
+            "大页映射 vpn {:?} 未按 2 MiB 对齐",
+            vpn
+        );
+        let idxs = vpn.indexes();
+        let root_pte = &mut Self::pte_array(&self.source, self.root_ppn)[idxs[0]];
+        if !root_pte.is_valid() {
+            let frame = self.source.alloc().ok_or(OutOfMemory)?;
+            *root_pte = PageTableEntry::new(F::ppn(&frame), PTEFlags::V);
+            self.frames.push(frame);
+        }
+        let pte = &mut Self::pte_array(&self.source, root_pte.ppn())[idxs[1]];
+        assert!(!pte.is_valid(), "vpn {:?} 在映射之前已经映射", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
+        Ok(())
+    }
+    /// 立即释放页表自身占用的物理页帧，此后不能再使用该页表
+    pub fn recycle(&mut self) {
+        self.frames.clear();
+        self.user_pages = 0;
+    }
+    /// 本页表自身占用的物理页帧数
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// 驻留在内存中的用户页面数，即带有 U 标志的有效叶子页表项数，在映射、取消映射和换出时增减
+    pub fn user_pages(&self) -> usize {
+        self.user_pages
+    }
+    /// 移除虚拟页号与物理页号之间的映射，已换出的页面同样清除其页表项
+    pub fn unmap(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid() || pte.is_swapped(), "vpn {:?} 在取消映射之前无效", vpn);
+        let user = pte.is_valid() && pte.flags().contains(PTEFlags::U);
+        *pte = PageTableEntry::empty();
+        self.flush(vpn);
+        if user {
+            self.user_pages -= 1;
+        }
+    }
+    /// 修改已映射页面的权限标志，保留访问位和修改位，页面未映射时返回 `false`
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> bool {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() => {
+                let was_user = pte.flags().contains(PTEFlags::U);
+                let kept = pte.flags() & (PTEFlags::A | PTEFlags::D);
+                *pte = PageTableEntry::new(pte.ppn(), flags | kept | PTEFlags::V);
+                self.flush(vpn);
+                match (was_user, flags.contains(PTEFlags::U)) {
+                    (false, true) => self.user_pages += 1,
+                    (true, false) => self.user_pages -= 1,
+                    _ => {}
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+    /// 清除已映射页面的可写位，用于写时复制
+    pub fn write_protect(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_pte(vpn).filter(|pte| pte.is_valid()) {
+            pte.bits &= !(PTEFlags::W.bits() as usize);
+            self.flush(vpn);
+        }
+    }
+    /// 将已映射页面的页表项改为已换出，记录交换槽位 `slot`
+    pub fn set_swapped(&mut self, vpn: VirtPageNum, slot: usize) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} 在换出之前无效", vpn);
+        let user = pte.flags().contains(PTEFlags::U);
+        *pte = PageTableEntry::swapped(slot);
+        self.flush(vpn);
+        if user {
+            self.user_pages -= 1;
+        }
+    }
+    /// 清除已映射页面的访问位，返回清除之前页面是否被访问过
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() && pte.flags().contains(PTEFlags::A) => {
+                pte.bits &= !(PTEFlags::A.bits() as usize);
+                self.flush(vpn);
+                true
+            }
+            _ => false,
+        }
+    }
+    /// 为已映射的页面设置访问位，`dirty` 为真时同时设置修改位，
+    /// 用于内核绕过 MMU 读写用户页面之后
+    pub fn mark_accessed(&self, vpn: VirtPageNum, dirty: bool) {
+        if let Some(pte) = self.find_pte(vpn).filter(|pte| pte.is_valid()) {
+            let mut flags = PTEFlags::A;
+            if dirty {
+                flags |= PTEFlags::D;
+            }
+            pte.bits |= flags.bits() as usize;
+        }
+    }
+    /// 从虚拟页号获取页表项；落在大页中时，返回的页表项指向 `vpn` 对应的 4 KiB 物理页
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.find_pte_level(vpn).map(|(pte, level)| {
+            if level == 2 || !pte.is_valid() {
+                return *pte;
+            }
+            // 大页内的页号偏移：第 1 级为 2 MiB，第 0 级为 1 GiB
+            let pages = HUGE_PAGE_PAGES.pow(2 - level as u32);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + vpn.0 % pages), pte.flags())
+        })
+    }
+    /// 从虚拟地址获取物理地址
+    pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
+        self.translate(va.floor()).map(|pte| {
+            let aligned_pa: PhysAddr = pte.ppn().into();
+            let offset = va.page_offset();
+            let aligned_pa_usize: usize = aligned_pa.into();
+            (aligned_pa_usize + offset).into()
+        })
+    }
+    /// 将用户虚拟地址翻译为物理地址；页面未映射或权限不足时先调用 `fault(va, write)` 处理缺页，
+    /// 它返回 `false` 表示无法处理。要求该页带有 U 标志，`write` 为真时还要求可写，否则只要求可读。
+    /// 翻译成功后设置页面的访问位（写入时还有修改位），与用户态的访问一样参与换出的选择。
+    pub fn translate_user(
+        &self,
+        va: usize,
+        write: bool,
+        fault: impl FnOnce(VirtAddr, bool) -> bool,
+    ) -> Result<PhysAddr, BadAddress> {
+        if va == 0 || va >= USER_SPACE_END {
+            return Err(BadAddress);
+        }
+        let va = VirtAddr::from(va);
+        let vpn = va.floor();
+        let pte = match self
+            .translate(vpn)
+            .filter(|pte| pte.is_valid() && (!write || pte.writable()))
+        {
+            Some(pte) => pte,
+            None => {
+                if !fault(va, write) {
+                    return Err(BadAddress);
+                }
+                self.translate(vpn).filter(|pte| pte.is_valid()).ok_or(BadAddress)?
+            }
+        };
+        let allowed = if write { pte.writable() } else { pte.readable() };
+        if !pte.flags().contains(PTEFlags::U) || !allowed {
+            return Err(BadAddress);
+        }
+        self.mark_accessed(vpn, write);
+        let aligned_pa: PhysAddr = pte.ppn().into();
+        Ok(PhysAddr(aligned_pa.0 + va.page_offset()))
+    }
+    /// 将用户空间 [ptr, ptr + len) 按页切分，逐页用 [`PageTable::translate_user`] 翻译，
+    /// 返回每一段的起始物理地址和长度
+    pub fn translate_user_range(
+        &self,
+        ptr: usize,
+        len: usize,
+        write: bool,
+        mut fault: impl FnMut(VirtAddr, bool) -> bool,
+    ) -> Result<Vec<(PhysAddr, usize)>, BadAddress> {
+        let mut start = ptr;
+        let end = start.checked_add(len).ok_or(BadAddress)?;
+        let mut ranges = Vec::new();
+        while start < end {
+            let pa = self.translate_user(start, write, &mut fault)?;
+            let mut vpn = VirtAddr::from(start).floor();
+            vpn.step();
+            let page_end = VirtAddr::from(vpn).0.min(end);
+            ranges.push((pa, page_end - start));
+            start = page_end;
+        }
+        Ok(ranges)
+    }
+    /// 刷新本地址空间中 `vpn` 的 TLB 表项
+    fn flush(&self, vpn: VirtPageNum) {
+        self.source.flush(self.asid, vpn);
+    }
+    /// 从页表获取 token，其中包含本页表的 ASID
+    pub fn token(&self) -> usize {
+        8usize << 60 | self.asid << 44 | self.root_ppn.0
+    }
+}
+
+/// 用户传入的指针无效：为空、不属于用户地址空间、未映射或权限不足
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadAddress;
+
+/// 用户地址空间的上界（SV39 低半部分）
+pub const USER_SPACE_END: usize = 1 << 38;
@@ -0,0 +1,439 @@
+//! 在主机上用内存中的页面数组代替物理内存，检查地址换算、页表的建立和查找以及用户缓冲区的切分
+
+extern crate sv39;
+
+use std::cell::{RefCell, UnsafeCell};
+use sv39::{
+    BadAddress, FrameSource, PTEFlags, PageTable, PhysAddr, PhysPageNum, VPNRange, VirtAddr, VirtPageNum,
+    HUGE_PAGE_PAGES, PAGE_SIZE, USER_SPACE_END,
+};
+
+/// 模拟的物理内存中第一个页帧的物理页号
+const BASE_PPN: usize = 0x80000;
+
+/// 按页对齐的一个页帧，其中可以存放页表项
+#[repr(C, align(4096))]
+struct Page([u8; PAGE_SIZE]);
+
+/// 模拟的物理内存：页帧存放在数组中，第 i 个页帧的物理页号为 `BASE_PPN + i`
+struct MockFrames {
+    pages: Vec<UnsafeCell<Page>>,
+    /// 空闲页帧的下标
+    free: RefCell<Vec<usize>>,
+    /// 刷新过的 (ASID, 页号)
+    flushed: RefCell<Vec<(usize, VirtPageNum)>>,
+}
+
+impl MockFrames {
+    fn new(pages: usize) -> Self {
+        Self {
+            pages: (0..pages).map(|_| UnsafeCell::new(Page([0; PAGE_SIZE]))).collect(),
+            free: RefCell::new((0..pages).rev().collect()),
+            flushed: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// 已分配出去的页帧数
+    fn allocated(&self) -> usize {
+        self.pages.len() - self.free.borrow().len()
+    }
+
+    fn page(&self, ppn: PhysPageNum) -> &UnsafeCell<Page> {
+        ppn.0
+            .checked_sub(BASE_PPN)
+            .and_then(|i| self.pages.get(i))
+            .unwrap_or_else(|| panic!("{:?} is outside the mock memory", ppn))
+    }
+
+    /// 物理地址 `pa` 处的 `len` 个字节
+    fn bytes(&self, pa: PhysAddr, len: usize) -> Vec<u8> {
+        assert!(pa.page_offset() + len <= PAGE_SIZE);
+        let page = unsafe { &*self.page(pa.floor()).get() };
+        page.0[pa.page_offset()..pa.page_offset() + len].to_vec()
+    }
+
+    fn take_flushed(&self) -> Vec<(usize, VirtPageNum)> {
+        self.flushed.borrow_mut().drain(..).collect()
+    }
+}
+
+/// 从 [`MockFrames`] 分配的页帧，丢弃时归还
+struct MockFrame<'a> {
+    frames: &'a MockFrames,
+    index: usize,
+}
+
+impl Drop for MockFrame<'_> {
+    fn drop(&mut self) {
+        self.frames.free.borrow_mut().push(self.index);
+    }
+}
+
+impl<'a> FrameSource for &'a MockFrames {
+    type Frame = MockFrame<'a>;
+
+    fn alloc(&self) -> Option<MockFrame<'a>> {
+        let index = self.free.borrow_mut().pop()?;
+        unsafe { (*self.pages[index].get()).0.fill(0) };
+        Some(MockFrame { frames: self, index })
+    }
+
+    fn ppn(frame: &MockFrame<'a>) -> PhysPageNum {
+        PhysPageNum(BASE_PPN + frame.index)
+    }
+
+    fn frame_ptr(&self, ppn: PhysPageNum) -> *mut u8 {
+        self.page(ppn).get() as *mut u8
+    }
+
+    fn flush(&self, asid: usize, vpn: VirtPageNum) {
+        self.flushed.borrow_mut().push((asid, vpn));
+    }
+}
+
+/// 用户可读写页面的标志
+fn user_rw() -> PTEFlags {
+    PTEFlags::R | PTEFlags::W | PTEFlags::U
+}
+
+/// 不处理缺页
+fn no_fault(_: VirtAddr, _: bool) -> bool {
+    false
+}
+
+#[test]
+fn ceil_and_floor_at_boundaries() {
+    let max_page = usize::MAX / PAGE_SIZE;
+    let cases = [
+        (0, 0),
+        (1, 1),
+        (PAGE_SIZE - 1, 1),
+        (PAGE_SIZE, 1),
+        (PAGE_SIZE + 1, 2),
+        (usize::MAX - PAGE_SIZE + 1, max_page),
+        (usize::MAX - 1, max_page + 1),
+        (usize::MAX, max_page + 1),
+    ];
+    for (addr, page) in cases {
+        assert_eq!(VirtAddr(addr).ceil().0, page, "VirtAddr({:#x}).ceil()", addr);
+        assert_eq!(PhysAddr(addr).ceil().0, page, "PhysAddr({:#x}).ceil()", addr);
+    }
+    assert_eq!(VirtAddr(PAGE_SIZE - 1).floor().0, 0);
+    assert_eq!(VirtAddr(usize::MAX).floor().0, max_page);
+    // 从 0 开始的空区域对应空的页号范围
+    let empty = VPNRange::new(VirtAddr(0).floor(), VirtAddr(0).ceil());
+    assert!(empty.into_iter().next().is_none());
+}
+
+#[test]
+fn virtual_addresses_are_sign_extended() {
+    // 从 usize 构造时只保留低 39 位，转换回 usize 时按第 38 位扩展
+    let high = VirtAddr::from(usize::MAX - PAGE_SIZE + 1);
+    assert_eq!(high.0, (1 << 39) - PAGE_SIZE);
+    assert_eq!(usize::from(high), usize::MAX - PAGE_SIZE + 1);
+    assert_eq!(usize::from(VirtAddr::from(0x1234)), 0x1234);
+    assert_eq!(VirtPageNum(0x12345).indexes(), [0, 0x91, 0x145]);
+    assert_eq!(VirtPageNum((1 << 27) - 1).indexes(), [511, 511, 511]);
+}
+
+#[test]
+fn vpn_range_edges() {
+    let base = VirtPageNum(0x100);
+    assert_eq!(VPNRange::new(base, base).into_iter().count(), 0);
+    assert_eq!(VPNRange::new(base, VirtPageNum(base.0 + 1)).into_iter().collect::<Vec<_>>(), [base]);
+    let range = VPNRange::new(base, VirtPageNum(base.0 + 3));
+    assert_eq!(range.into_iter().last(), Some(VirtPageNum(base.0 + 2)));
+    assert_eq!(range.get_start(), base);
+    assert_eq!(range.get_end(), VirtPageNum(base.0 + 3));
+}
+
+#[test]
+#[should_panic]
+fn vpn_range_rejects_reversed_bounds() {
+    VPNRange::new(VirtPageNum(2), VirtPageNum(1));
+}
+
+#[test]
+fn map_translate_unmap_round_trip() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 3).unwrap();
+    let vpn = VirtPageNum(0x12345);
+    let ppn = PhysPageNum(0x9_0000);
+    page_table.map(vpn, ppn, user_rw()).unwrap();
+    assert_eq!(frames.take_flushed(), [(3, vpn)]);
+    let pte = page_table.translate(vpn).unwrap();
+    assert!(pte.is_valid() && pte.readable() && pte.writable() && !pte.executable());
+    assert_eq!(pte.ppn(), ppn);
+    let va = VirtAddr::from(VirtAddr::from(vpn).0 + 0x123);
+    assert_eq!(page_table.translate_va(va), Some(PhysAddr(PhysAddr::from(ppn).0 + 0x123)));
+    // 同一个第 2 级页表中的其他页号没有映射
+    assert!(!page_table.translate(VirtPageNum(vpn.0 + 1)).unwrap().is_valid());
+    // 其他根页表项下的页号连页表都没有
+    assert!(page_table.translate(VirtPageNum(vpn.0 + (1 << 18))).is_none());
+
+    page_table.unmap(vpn);
+    assert_eq!(frames.take_flushed(), [(3, vpn)]);
+    assert!(!page_table.translate(vpn).unwrap().is_valid());
+    // 解除映射之后可以再次映射
+    page_table.map(vpn, PhysPageNum(ppn.0 + 1), user_rw()).unwrap();
+    assert_eq!(page_table.translate(vpn).unwrap().ppn(), PhysPageNum(ppn.0 + 1));
+}
+
+#[test]
+fn intermediate_tables_are_created_once() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    assert_eq!(page_table.frame_count(), 1);
+    let vpn = VirtPageNum(0x12345);
+    // 第一次映射创建第 1、2 级页表
+    page_table.map(vpn, PhysPageNum(0x9_0000), user_rw()).unwrap();
+    assert_eq!(page_table.frame_count(), 3);
+    // 同一个第 2 级页表内的映射不再创建页表
+    page_table.map(VirtPageNum(vpn.0 + 1), PhysPageNum(0x9_0001), user_rw()).unwrap();
+    assert_eq!(page_table.frame_count(), 3);
+    // 同一个第 1 级页表、不同的第 2 级页表
+    page_table.map(VirtPageNum(vpn.0 + 512), PhysPageNum(0x9_0002), user_rw()).unwrap();
+    assert_eq!(page_table.frame_count(), 4);
+    // 不同的根页表项需要新的第 1、2 级页表
+    page_table.map(VirtPageNum(vpn.0 + (1 << 18)), PhysPageNum(0x9_0003), user_rw()).unwrap();
+    assert_eq!(page_table.frame_count(), 6);
+    assert_eq!(frames.allocated(), 6);
+    // 中间级的页表项只有有效位，指向新分配的页表
+    let root = VirtPageNum(vpn.0 + (1 << 18)).indexes()[0];
+    let token = page_table.token();
+    assert_eq!(token >> 60, 8);
+    assert_eq!(token >> 44 & 0xffff, 1);
+    assert_eq!(token & ((1 << 44) - 1), BASE_PPN);
+    let root_ptes = unsafe { &*((&frames).frame_ptr(PhysPageNum(BASE_PPN)) as *const [usize; 512]) };
+    assert_eq!(root_ptes[root] & 0x3ff, PTEFlags::V.bits() as usize);
+
+    // 页表丢弃后它占用的页帧全部归还
+    drop(page_table);
+    assert_eq!(frames.allocated(), 0);
+}
+
+#[test]
+fn recycle_frees_table_frames() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0000), user_rw()).unwrap();
+    assert_eq!(frames.allocated(), 3);
+    page_table.recycle();
+    assert_eq!(page_table.frame_count(), 0);
+    assert_eq!(frames.allocated(), 0);
+}
+
+#[test]
+fn running_out_of_frames_is_reported() {
+    // 根页表和第 1 级页表之后没有页帧可以存放第 2 级页表
+    let frames = MockFrames::new(2);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    assert!(page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0000), user_rw()).is_err());
+    assert!(page_table.translate(VirtPageNum(0x10)).is_none());
+    drop(page_table);
+    let frames = MockFrames::new(0);
+    assert!(PageTable::new(&frames, 1).is_err());
+}
+
+#[test]
+#[should_panic]
+fn mapping_twice_panics() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0000), user_rw()).unwrap();
+    page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0001), user_rw()).unwrap();
+}
+
+#[test]
+#[should_panic]
+fn unmapping_an_unmapped_page_panics() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0000), user_rw()).unwrap();
+    page_table.unmap(VirtPageNum(0x11));
+}
+
+#[test]
+#[should_panic]
+fn unmapping_without_tables_panics() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    page_table.unmap(VirtPageNum(0x10));
+}
+
+#[test]
+fn huge_pages_translate_to_their_small_pages() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let vpn = VirtPageNum(3 * HUGE_PAGE_PAGES);
+    let ppn = PhysPageNum(0x10_0000);
+    page_table.map_huge(vpn, ppn, PTEFlags::R | PTEFlags::X).unwrap();
+    // 大页只需要第 1 级页表
+    assert_eq!(page_table.frame_count(), 2);
+    let pte = page_table.translate(VirtPageNum(vpn.0 + 7)).unwrap();
+    assert!(pte.is_valid() && pte.executable());
+    assert_eq!(pte.ppn(), PhysPageNum(ppn.0 + 7));
+    let va = VirtAddr::from(VirtAddr::from(VirtPageNum(vpn.0 + HUGE_PAGE_PAGES - 1)).0 + 8);
+    assert_eq!(
+        page_table.translate_va(va),
+        Some(PhysAddr(PhysAddr::from(PhysPageNum(ppn.0 + HUGE_PAGE_PAGES - 1)).0 + 8))
+    );
+}
+
+#[test]
+fn flag_updates_keep_accessed_and_dirty() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let vpn = VirtPageNum(0x10);
+    page_table.map(vpn, PhysPageNum(0x9_0000), user_rw()).unwrap();
+    page_table.mark_accessed(vpn, true);
+    assert!(page_table.set_flags(vpn, PTEFlags::R | PTEFlags::U));
+    let flags = page_table.translate(vpn).unwrap().flags();
+    assert!(flags.contains(PTEFlags::A | PTEFlags::D) && !flags.contains(PTEFlags::W));
+    assert!(!page_table.set_flags(VirtPageNum(0x11), user_rw()));
+
+    assert!(page_table.clear_accessed(vpn));
+    assert!(!page_table.clear_accessed(vpn));
+    page_table.set_flags(vpn, user_rw());
+    page_table.write_protect(vpn);
+    assert!(!page_table.translate(vpn).unwrap().writable());
+}
+
+#[test]
+fn swapped_entries_keep_their_slot() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let vpn = VirtPageNum(0x10);
+    page_table.map(vpn, PhysPageNum(0x9_0000), user_rw()).unwrap();
+    page_table.set_swapped(vpn, 42);
+    let pte = page_table.translate(vpn).unwrap();
+    assert!(!pte.is_valid() && pte.is_swapped());
+    assert_eq!(pte.swap_slot(), 42);
+    assert_eq!(page_table.translate_va(VirtAddr::from(vpn)).map(|pa| pa.floor()), Some(PhysPageNum(42)));
+    // 已换出的页面也可以解除映射
+    page_table.unmap(vpn);
+    assert!(!page_table.translate(vpn).unwrap().is_swapped());
+}
+
+#[test]
+fn user_pages_follow_mappings() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    // 内核页面不计入
+    page_table.map(VirtPageNum(0x10), PhysPageNum(0x9_0000), PTEFlags::R | PTEFlags::W).unwrap();
+    for i in 0..3 {
+        page_table.map(VirtPageNum(0x20 + i), PhysPageNum(0x9_0001 + i), user_rw()).unwrap();
+    }
+    assert_eq!(page_table.user_pages(), 3);
+    page_table.unmap(VirtPageNum(0x20));
+    page_table.unmap(VirtPageNum(0x10));
+    assert_eq!(page_table.user_pages(), 2);
+    // 换出的页面不再驻留，解除它的映射不再减少计数
+    page_table.set_swapped(VirtPageNum(0x21), 7);
+    assert_eq!(page_table.user_pages(), 1);
+    page_table.unmap(VirtPageNum(0x21));
+    assert_eq!(page_table.user_pages(), 1);
+    // 去掉和加上 U 标志
+    page_table.set_flags(VirtPageNum(0x22), PTEFlags::R);
+    assert_eq!(page_table.user_pages(), 0);
+    page_table.set_flags(VirtPageNum(0x22), PTEFlags::U);
+    assert_eq!(page_table.user_pages(), 1);
+    page_table.recycle();
+    assert_eq!(page_table.user_pages(), 0);
+}
+
+#[test]
+fn user_buffer_across_three_pages() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let data: Vec<_> = (0..3).map(|_| (&frames).alloc().unwrap()).collect();
+    let base = VirtPageNum(0x100);
+    for (i, frame) in data.iter().enumerate() {
+        page_table.map(VirtPageNum(base.0 + i), <&MockFrames>::ppn(frame), user_rw()).unwrap();
+    }
+    let start = VirtAddr::from(base).0 + PAGE_SIZE - 16;
+    let ranges = page_table.translate_user_range(start, PAGE_SIZE + 32, false, no_fault).unwrap();
+    let ppn = |i: usize| <&MockFrames>::ppn(&data[i]);
+    assert_eq!(
+        ranges,
+        [
+            (PhysAddr(PhysAddr::from(ppn(0)).0 + PAGE_SIZE - 16), 16),
+            (PhysAddr::from(ppn(1)), PAGE_SIZE),
+            (PhysAddr::from(ppn(2)), 16),
+        ]
+    );
+    // 读取设置访问位，写入同时设置修改位
+    for i in 0..3 {
+        let flags = page_table.translate(VirtPageNum(base.0 + i)).unwrap().flags();
+        assert!(flags.contains(PTEFlags::A) && !flags.contains(PTEFlags::D));
+    }
+    page_table.translate_user_range(start, 1, true, no_fault).unwrap();
+    assert!(page_table.translate(base).unwrap().flags().contains(PTEFlags::D));
+
+    // 恰好在页边界结束的缓冲区不会多出一段空的切片
+    let ranges = page_table.translate_user_range(VirtAddr::from(base).0, 2 * PAGE_SIZE, false, no_fault).unwrap();
+    assert_eq!(ranges.iter().map(|&(_, len)| len).collect::<Vec<_>>(), [PAGE_SIZE, PAGE_SIZE]);
+    assert_eq!(page_table.translate_user_range(start, 0, false, no_fault), Ok(Vec::new()));
+    // 超出映射范围的缓冲区必须报错
+    assert_eq!(page_table.translate_user_range(start, 3 * PAGE_SIZE, false, no_fault), Err(BadAddress));
+}
+
+#[test]
+fn user_access_checks() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let ro = VirtPageNum(0x100);
+    let kernel = VirtPageNum(0x101);
+    page_table.map(ro, PhysPageNum(0x9_0000), PTEFlags::R | PTEFlags::U).unwrap();
+    page_table.map(kernel, PhysPageNum(0x9_0001), PTEFlags::R | PTEFlags::W).unwrap();
+    let va = |vpn: VirtPageNum| VirtAddr::from(vpn).0;
+    assert!(page_table.translate_user(va(ro), false, no_fault).is_ok());
+    assert_eq!(page_table.translate_user(va(ro), true, no_fault), Err(BadAddress));
+    // 没有 U 标志的页面不能被用户访问
+    assert_eq!(page_table.translate_user(va(kernel), false, no_fault), Err(BadAddress));
+    assert_eq!(page_table.translate_user(0, false, no_fault), Err(BadAddress));
+    assert_eq!(page_table.translate_user(USER_SPACE_END, false, no_fault), Err(BadAddress));
+    assert_eq!(page_table.translate_user_range(va(ro), usize::MAX, false, no_fault), Err(BadAddress));
+}
+
+#[test]
+fn page_faults_are_handled_through_the_callback() {
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let vpn = VirtPageNum(0x100);
+    page_table.map(vpn, PhysPageNum(0x9_0000), PTEFlags::R | PTEFlags::U).unwrap();
+    // 内核通过 token 构造的另一个视图翻译，缺页处理修改的是进程自己的页表
+    let view = PageTable::from_token(page_table.token(), &frames);
+    let mut faults = Vec::new();
+    let pa = view.translate_user(VirtAddr::from(vpn).0 + 5, true, |va, write| {
+        faults.push((va, write));
+        // 写时复制：换成可写的新页面
+        page_table.unmap(vpn);
+        page_table.map(vpn, PhysPageNum(0x9_0001), user_rw()).unwrap();
+        true
+    });
+    assert_eq!(pa, Ok(PhysAddr(PhysAddr::from(PhysPageNum(0x9_0001)).0 + 5)));
+    assert_eq!(faults, [(VirtAddr::from(VirtAddr::from(vpn).0 + 5), true)]);
+
+    // 处理缺页失败或处理之后仍然没有映射
+    let missing = VirtAddr::from(VirtPageNum(0x200)).0;
+    assert_eq!(view.translate_user(missing, false, no_fault), Err(BadAddress));
+    assert_eq!(view.translate_user(missing, false, |_, _| true), Err(BadAddress));
+}
+
+#[test]
+fn pages_hold_their_contents() {
+    // 页表项就存放在模拟的物理内存中，与映射的数据页互不干扰
+    let frames = MockFrames::new(16);
+    let mut page_table = PageTable::new(&frames, 1).unwrap();
+    let data = (&frames).alloc().unwrap();
+    let ppn = <&MockFrames>::ppn(&data);
+    let ptr = (&frames).frame_ptr(ppn);
+    unsafe { core::ptr::copy_nonoverlapping(b"hello".as_ptr(), ptr.add(100), 5) };
+    page_table.map(VirtPageNum(0x100), ppn, user_rw()).unwrap();
+    let ranges = page_table
+        .translate_user_range(VirtAddr::from(VirtPageNum(0x100)).0 + 100, 5, false, no_fault)
+        .unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(frames.bytes(ranges[0].0, ranges[0].1), b"hello");
+}
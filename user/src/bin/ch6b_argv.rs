@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, getauxval, waitpid, AT_ENTRY, AT_PAGESZ, AT_RANDOM};
+
+/// 作为子进程被 exec 时收到的参数
+const CHILD_ARGS: [&str; 3] = ["ch6b_argv", "child", "hello world"];
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    for (i, arg) in argv.iter().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    let page_size = getauxval(AT_PAGESZ).expect("no AT_PAGESZ in auxv");
+    println!("AT_PAGESZ = {}", page_size);
+    assert_eq!(page_size, 4096);
+    assert_eq!(getauxval(AT_ENTRY), Some(user_lib::_start as usize));
+    assert!(getauxval(AT_RANDOM).is_some(), "no AT_RANDOM in auxv");
+    if argc > 1 && argv[1] == "child" {
+        assert_eq!(argv, &CHILD_ARGS);
+        return 0;
+    }
+    let pid = fork();
+    if pid == 0 {
+        exec(
+            "ch6b_argv\0",
+            &[
+                "ch6b_argv\0".as_ptr(),
+                "child\0".as_ptr(),
+                "hello world\0".as_ptr(),
+                core::ptr::null::<u8>(),
+            ],
+        );
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("argv passed!");
+    0
+}
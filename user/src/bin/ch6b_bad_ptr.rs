@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, open, sys_getcwd, syscall, OpenFlags, SYSCALL_FSTAT, SYSCALL_GETDENTS64,
+    SYSCALL_GETTIMEOFDAY, SYSCALL_PIPE, SYSCALL_READ, SYSCALL_TIMES, SYSCALL_WRITE,
+};
+
+const EFAULT: isize = 14;
+
+/// 空指针、内核空间地址（trampoline 与内核镜像）和未映射的地址
+const BAD_PTRS: [usize; 4] = [0, 0xffff_ffff_ffff_f000, 0x8020_0000, 0x30_0000_0000];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("ch6b_bad_ptr.elf\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open ch6b_bad_ptr.elf");
+    let fd = fd as usize;
+    for &ptr in BAD_PTRS.iter() {
+        assert_eq!(syscall(SYSCALL_READ, [fd, ptr, 16]), -EFAULT, "read {:#x}", ptr);
+        assert_eq!(syscall(SYSCALL_WRITE, [1, ptr, 16]), -EFAULT, "write {:#x}", ptr);
+        assert_eq!(sys_getcwd(ptr as *mut u8, 64), -EFAULT, "getcwd {:#x}", ptr);
+        check_struct_ptrs(fd, ptr);
+    }
+    // 已映射但未按结构体要求对齐的指针
+    let mut buf = [0u64; 64];
+    let misaligned = buf.as_mut_ptr() as usize + 1;
+    check_struct_ptrs(fd, misaligned);
+    // 只读的代码段不能作为输出缓冲区
+    let text = main as usize;
+    assert_eq!(syscall(SYSCALL_READ, [fd, text, 16]), -EFAULT, "read into .text");
+    close(fd);
+    println!("bad_ptr passed!");
+    0
+}
+
+/// 写入结构体的系统调用在 `ptr` 无效或未对齐时都应返回 EFAULT
+fn check_struct_ptrs(fd: usize, ptr: usize) {
+    assert_eq!(syscall(SYSCALL_GETTIMEOFDAY, [ptr, 0, 0]), -EFAULT, "gettimeofday {:#x}", ptr);
+    assert_eq!(syscall(SYSCALL_TIMES, [ptr, 0, 0]), -EFAULT, "times {:#x}", ptr);
+    assert_eq!(syscall(SYSCALL_PIPE, [ptr, 0, 0]), -EFAULT, "pipe2 {:#x}", ptr);
+    assert_eq!(syscall(SYSCALL_GETDENTS64, [fd, ptr, 64]), -EFAULT, "getdents64 {:#x}", ptr);
+    assert_eq!(syscall(SYSCALL_FSTAT, [fd, ptr, 0]), -EFAULT, "fstat {:#x}", ptr);
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+//! chmod 0444 之后 FAT 文件带上只读属性：stat 报告 0444，以写方式打开返回 -EACCES，
+//! 只读打开的文件写入失败；chmod 0644 之后又可以写入。fchmod 的效果相同。
+//! access 按同样的权限位回答，也能检查 /proc 和 tmpfs 中的文件。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    access, chmod, close, fchmod, fstat, open, unlink, write, OpenFlags, Stat, StatMode, F_OK, R_OK, W_OK, X_OK,
+};
+
+const ENOENT: isize = 2;
+const EACCES: isize = 13;
+const PATH: &str = "/chmod_test\0";
+
+fn permissions() -> u32 {
+    let fd = open(PATH, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    close(fd as usize);
+    assert_eq!(st.mode.file_type(), StatMode::FILE);
+    st.mode.permissions()
+}
+
+/// /proc 中的文件只读，tmpfs 中的文件可写
+fn access_other_filesystems() {
+    assert_eq!(access("/proc/meminfo\0", R_OK), 0);
+    assert_eq!(access("/proc/meminfo\0", W_OK), -EACCES);
+    assert_eq!(access("/proc/missing\0", F_OK), -ENOENT);
+    let tmp = "/tmp/access_test\0";
+    let fd = open(tmp, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert_eq!(access(tmp, R_OK | W_OK), 0);
+    assert_eq!(access("/tmp\0", X_OK), 0);
+    assert_eq!(unlink(tmp), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"data"), 4);
+    close(fd as usize);
+    assert_eq!(permissions(), 0o644);
+    assert_eq!(access(PATH, R_OK | W_OK), 0);
+
+    assert_eq!(chmod(PATH, 0o444), 0);
+    assert_eq!(permissions(), 0o444);
+    assert_eq!(access(PATH, R_OK), 0);
+    assert_eq!(access(PATH, W_OK), -EACCES);
+    assert_eq!(open(PATH, OpenFlags::WRONLY), -EACCES);
+    assert_eq!(open(PATH, OpenFlags::RDWR), -EACCES);
+    assert_eq!(open(PATH, OpenFlags::WRONLY | OpenFlags::TRUNC), -EACCES);
+    let fd = open(PATH, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    assert!(write(fd as usize, b"x") < 0, "wrote to a read-only file");
+    // 只读文件没有被截断
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    assert_eq!(st.size, 4);
+
+    // 任何一个写权限位都清除只读属性，其他位被忽略
+    assert_eq!(fchmod(fd as usize, 0o4020), 0);
+    close(fd as usize);
+    assert_eq!(permissions(), 0o644);
+    let fd = open(PATH, OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"more"), 4);
+    close(fd as usize);
+
+    assert_eq!(access(PATH, W_OK), 0);
+    assert_eq!(access("/chmod_missing\0", F_OK), -ENOENT);
+    assert_eq!(chmod("/chmod_missing\0", 0o644), -ENOENT);
+    access_other_filesystems();
+    assert_eq!(unlink(PATH), 0);
+    println!("chmod passed!");
+    0
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+//! 子进程 chroot 到 /chroot_test 之后，"/file" 打开的是 /chroot_test/file，
+//! "/.." 和 "../" 都停在新的根目录，不能访问根目录之外的文件；
+//! 新的根目录下也看不到 /proc；chroot 之后 fork 出的进程继承新的根目录，父进程的根目录不受影响。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{chroot, close, exit, fork, getpwd, mkdir, open, read, unlink, waitpid, write, OpenFlags};
+
+const ENOENT: isize = 2;
+const ENOTDIR: isize = 20;
+const CONTENT: &[u8] = b"inside the jail";
+
+fn read_file(path: &str) -> Option<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    assert!(len >= 0);
+    Some(String::from_utf8(buf[..len as usize].to_vec()).unwrap())
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0, "failed to create {}", path);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+/// 在 chroot 之后的进程中检查路径的解析
+fn check_jail() {
+    assert_eq!(read_file("/file\0").as_deref(), Some("inside the jail"));
+    assert_eq!(read_file("file\0").as_deref(), Some("inside the jail"));
+    assert!(read_file("/chroot_outside\0").is_none(), "saw a file outside the root");
+    assert!(read_file("/../chroot_outside\0").is_none(), "escaped through /..");
+    assert!(read_file("../chroot_outside\0").is_none(), "escaped through ../");
+    assert_eq!(read_file("../../file\0").as_deref(), Some("inside the jail"));
+    // 新的根目录下没有 proc，procfs 不可见
+    assert!(read_file("/proc/meminfo\0").is_none(), "procfs is visible inside the jail");
+    let mut cwd = String::new();
+    assert_eq!(getpwd(&mut cwd, 64), 0);
+    assert_eq!(cwd, "/");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/chroot_test\0");
+    write_file("/chroot_test/file\0", CONTENT);
+    write_file("/chroot_outside\0", b"outside");
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(chroot("/chroot_missing\0"), -ENOENT);
+        assert_eq!(chroot("/chroot_outside\0"), -ENOTDIR);
+        assert_eq!(chroot("/chroot_test\0"), 0);
+        check_jail();
+        // 子进程继承新的根目录
+        let pid = fork();
+        if pid == 0 {
+            check_jail();
+            exit(0);
+        }
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "chroot child failed");
+
+    // 父进程的根目录没有改变
+    assert_eq!(read_file("/chroot_outside\0").as_deref(), Some("outside"));
+    assert!(read_file("/file\0").is_none());
+    unlink("/chroot_test/file\0");
+    unlink("/chroot_test\0");
+    unlink("/chroot_outside\0");
+    println!("chroot passed!");
+    0
+}
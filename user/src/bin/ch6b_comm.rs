@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+//! exec 之后命令名是程序文件名，prctl 可以修改和读取它，
+//! 超过 15 字节的名字被截断，/proc/self/comm 与之保持一致。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, get_name, open, read, set_name, OpenFlags};
+
+fn name() -> [u8; 16] {
+    let mut buf = [0u8; 16];
+    assert_eq!(get_name(&mut buf), 0, "PR_GET_NAME failed");
+    buf
+}
+
+fn name_str(buf: &[u8; 16]) -> &str {
+    let len = buf.iter().position(|&b| b == 0).expect("name is not NUL-terminated");
+    core::str::from_utf8(&buf[..len]).unwrap()
+}
+
+fn proc_comm(buf: &mut [u8; 32]) -> &str {
+    let fd = open("/proc/self/comm\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /proc/self/comm");
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    assert!(len > 0, "read /proc/self/comm failed");
+    core::str::from_utf8(&buf[..len as usize]).unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut comm = [0u8; 32];
+    assert_eq!(name_str(&name()), "ch6b_comm");
+    assert_eq!(proc_comm(&mut comm), "ch6b_comm\n");
+
+    assert_eq!(set_name("renamed"), 0);
+    assert_eq!(name_str(&name()), "renamed");
+    assert_eq!(proc_comm(&mut comm), "renamed\n");
+
+    assert_eq!(set_name("a-much-too-long-command-name"), 0);
+    assert_eq!(name_str(&name()), "a-much-too-long");
+    println!("comm passed!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, sysinfo, waitpid, SysInfo};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 64;
+/// 每轮同时存在的子进程数
+const CHILDREN: usize = 8;
+const ROUNDS: usize = 16;
+
+fn free_frames() -> usize {
+    let mut info = SysInfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    info.freeram
+}
+
+fn page(start: usize, i: usize) -> *mut u64 {
+    (start + i * PAGE_SIZE) as *mut u64
+}
+
+/// 子进程：检查继承的内容，改写一半的页面，确认改写只对自己可见
+fn child(start: usize, id: usize) -> ! {
+    for i in 0..PAGES {
+        assert_eq!(unsafe { page(start, i).read_volatile() }, i as u64);
+    }
+    for i in (0..PAGES).step_by(2) {
+        unsafe { page(start, i).write_volatile((id << 32 | i) as u64) };
+    }
+    for i in 0..PAGES {
+        let expected = if i % 2 == 0 { (id << 32 | i) as u64 } else { i as u64 };
+        assert_eq!(unsafe { page(start, i).read_volatile() }, expected);
+    }
+    exit(0);
+}
+
+/// fork 一批子进程并等待它们全部退出
+fn storm(start: usize, round: usize) {
+    let mut pids = [0isize; CHILDREN];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        assert!(*pid >= 0, "fork failed");
+        if *pid == 0 {
+            child(start, round * CHILDREN + i + 1);
+        }
+    }
+    for pid in pids {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = mmap(0, PAGES * PAGE_SIZE, 3);
+    assert!(start > 0, "mmap failed");
+    let start = start as usize;
+    for i in 0..PAGES {
+        unsafe { page(start, i).write_volatile(i as u64) };
+    }
+    // 第一轮可能为内核栈等建立新的页表，不计入统计
+    storm(start, 0);
+    let before = free_frames();
+    for round in 1..ROUNDS {
+        storm(start, round);
+        // 子进程的改写不影响父进程，共享页帧的引用全部归还
+        for i in 0..PAGES {
+            assert_eq!(unsafe { page(start, i).read_volatile() }, i as u64);
+        }
+        let after = free_frames();
+        assert_eq!(before, after, "round {} leaked {} frames", round, before as isize - after as isize);
+    }
+    // 子进程都已退出，父进程写入时直接复用原来的页帧
+    for i in 0..PAGES {
+        unsafe { page(start, i).write_volatile(!(i as u64)) };
+    }
+    assert_eq!(free_frames(), before);
+    println!("cow_storm passed!");
+    0
+}
@@ -0,0 +1,110 @@
+#![no_std]
+#![no_main]
+
+//! 目录缓存的正确性和效果：创建目录并反复查找使其进入缓存，删除后在同一路径创建普通文件，
+//! 再次打开时必须得到新的文件而不是缓存中的旧目录。之后对深层路径重复打开 33 次，
+//! 每次都要从缓存中的父目录开始解析，命中次数至少增加 33；
+//! 打印期间目录缓存的命中/未命中次数和块设备读取次数（来自 /proc/stat）。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{close, fstat, mkdir, open, read, unlink, write, OpenFlags, Stat, StatMode};
+
+const DIR: &str = "/dc_test\0";
+const SUB: &str = "/dc_test/sub\0";
+const FILE: &str = "/dc_test/sub/file\0";
+const ROUNDS: usize = 33;
+
+/// 读取 /proc/stat 中的 (dcache 命中, dcache 未命中, 块设备读取次数)
+fn counters() -> (usize, usize, usize) {
+    let fd = open("/proc/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /proc/stat");
+    let mut buf = [0u8; 1024];
+    let mut text = String::new();
+    loop {
+        let n = read(fd as usize, &mut buf);
+        if n <= 0 {
+            break;
+        }
+        text.push_str(core::str::from_utf8(&buf[..n as usize]).unwrap());
+    }
+    close(fd as usize);
+    let (mut hits, mut misses, mut reads) = (0, 0, 0);
+    for line in text.lines() {
+        let mut fields = line.split(' ');
+        match fields.next() {
+            Some("dcache") => {
+                hits = fields.next().unwrap().parse().unwrap();
+                misses = fields.next().unwrap().parse().unwrap();
+            }
+            Some("block_reads") => reads = fields.next().unwrap().parse().unwrap(),
+            _ => {}
+        }
+    }
+    (hits, misses, reads)
+}
+
+fn mode_of(path: &str) -> Option<StatMode> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    close(fd as usize);
+    Some(st.mode.file_type())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    unlink(FILE);
+    unlink(SUB);
+    mkdir(DIR);
+    assert_eq!(mkdir(SUB), 0);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0, "failed to create {}", FILE);
+    close(fd as usize);
+    // 反复查找，使 /dc_test 和 /dc_test/sub 进入缓存
+    for _ in 0..3 {
+        assert_eq!(mode_of(SUB), Some(StatMode::DIR));
+        assert_eq!(mode_of(FILE), Some(StatMode::FILE));
+    }
+
+    // 删除目录后在同一路径创建普通文件
+    assert_eq!(unlink(FILE), 0);
+    assert_eq!(unlink(SUB), 0);
+    assert_eq!(mode_of(FILE), None, "removed file is still visible");
+    let fd = open(SUB, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0, "failed to create {}", SUB);
+    assert_eq!(write(fd as usize, b"new"), 3);
+    close(fd as usize);
+    assert_eq!(mode_of(SUB), Some(StatMode::FILE), "stale directory returned from the cache");
+
+    // 重新建成目录，测量重复打开深层路径时的缓存效果
+    assert_eq!(unlink(SUB), 0);
+    assert_eq!(mkdir(SUB), 0);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    let (hits0, misses0, reads0) = counters();
+    for _ in 0..ROUNDS {
+        assert_eq!(mode_of(FILE), Some(StatMode::FILE));
+    }
+    let (hits1, misses1, reads1) = counters();
+    println!(
+        "{} opens: dcache hits {} misses {}, block reads {}",
+        ROUNDS,
+        hits1 - hits0,
+        misses1 - misses0,
+        reads1 - reads0
+    );
+    assert!(hits1 - hits0 >= ROUNDS, "repeated lookups did not hit the dcache");
+    unlink(FILE);
+    unlink(SUB);
+    unlink(DIR);
+    println!("dcache passed!");
+    0
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+//! 子进程在用户态空转计数，父进程用一次 read 读入一个不在块缓存中的大文件。
+//! 块设备请求睡眠等待完成中断时，这次 read 期间子进程可以运行，计数会增加；
+//! 如果内核轮询等待磁盘，整个 read 期间处理器都被父进程占用，计数不变。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, mmap, mmap_shared, open, read, unlink, waitpid, write, yield_, OpenFlags};
+
+const PAGE_SIZE: usize = 4096;
+const FILE_SIZE: usize = 16 * PAGE_SIZE;
+const PATH: &str = "/disk_irq_test\0";
+
+fn pattern(offset: usize) -> u8 {
+    (offset % 251) as u8
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 写入的块会陆续挤出块缓存，之后的读取一定要访问磁盘
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let mut chunk = [0u8; 512];
+    for block in 0..FILE_SIZE / chunk.len() {
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = pattern(block * 512 + i);
+        }
+        assert_eq!(write(fd as usize, &chunk), chunk.len() as isize);
+    }
+    close(fd as usize);
+
+    let buf = mmap(0, FILE_SIZE, 3);
+    assert!(buf > 0, "mmap failed");
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, FILE_SIZE) };
+    // 先访问一遍，read 期间不会因为缺页而轮询磁盘
+    buf.fill(0);
+
+    let shared = mmap_shared(0, PAGE_SIZE, 3);
+    assert!(shared > 0, "mmap failed");
+    let stop = shared as *mut u64;
+    let counter = unsafe { stop.add(1) };
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        while unsafe { stop.read_volatile() } == 0 {
+            unsafe { counter.write_volatile(counter.read_volatile() + 1) };
+        }
+        exit(0);
+    }
+    while unsafe { counter.read_volatile() } == 0 {
+        yield_();
+    }
+
+    let fd = open(PATH, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    // 从一个新的时间片开始，避免子进程在 read 之外被调度
+    yield_();
+    let before = unsafe { counter.read_volatile() };
+    assert_eq!(read(fd as usize, buf), FILE_SIZE as isize);
+    let after = unsafe { counter.read_volatile() };
+    close(fd as usize);
+    unsafe { stop.write_volatile(1) };
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    assert!(buf.iter().enumerate().all(|(i, &byte)| byte == pattern(i)), "file content mismatch");
+    assert!(after > before, "CPU-bound task made no progress during disk reads");
+    println!("counter advanced by {} during the read", after - before);
+    assert_eq!(unlink(PATH), 0);
+    println!("disk_irq passed!");
+    0
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, getpid, mmap, waitpid, yield_};
+
+/// 各进程都在同一个虚拟地址上映射自己的页面
+const ADDR: usize = 0x4000_0000;
+const PAGE_SIZE: usize = 4096;
+const ROUNDS: usize = 32;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for round in 0..ROUNDS {
+        let pid = fork();
+        if pid == 0 {
+            assert_eq!(mmap(ADDR, PAGE_SIZE, 3), ADDR as isize);
+            let value = unsafe { &mut *(ADDR as *mut usize) };
+            *value = getpid() as usize;
+            // 让其他进程运行，它们在同一地址上的映射不能被本进程看到
+            yield_();
+            assert_eq!(*value, getpid() as usize, "round {}: stale TLB entry", round);
+            // exec 后的新页表必须看不到旧页表的映射
+            exec("ch6b_exec_flush_child\0", &[core::ptr::null::<u8>()]);
+            panic!("exec failed");
+        }
+        let mut exit_code: i32 = 0;
+        waitpid(pid as usize, &mut exit_code);
+        assert_eq!(exit_code, 0, "round {} failed", round);
+    }
+    println!("exec_flush passed!");
+    0
+}
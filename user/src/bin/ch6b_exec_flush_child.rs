@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::mmap;
+
+const ADDR: usize = 0x4000_0000;
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // exec 前映射在同一地址上的页面写入过非零值，新映射的页面必须为全零
+    assert_eq!(mmap(ADDR, PAGE_SIZE, 3), ADDR as isize);
+    assert_eq!(unsafe { *(ADDR as *const usize) }, 0);
+    0
+}
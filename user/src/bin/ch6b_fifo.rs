@@ -0,0 +1,179 @@
+#![no_std]
+#![no_main]
+
+//! 命名管道：两个兄弟进程（没有通过 fork 继承管道）经由 /tmp/fifo 通信。
+//! 写者先打开，应阻塞到读者打开；读者读到写者关闭后得到 EOF。
+//! 另外检查 O_NONBLOCK 的打开和读写语义、重复创建时的 EEXIST，以及在 FAT 子目录中创建的 FIFO
+//! 出现在该子目录而不是根目录中。
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fork, fstat, getdents64, mkdir, mkfifo, open, pipe2, read, sleep_blocking, unlink,
+    waitpid, write, OpenFlags, Stat, StatMode,
+};
+
+const FIFO: &str = "/tmp/fifo\0";
+const EEXIST: isize = 17;
+const ENXIO: isize = 6;
+const EAGAIN: isize = 11;
+const MESSAGES: usize = 50;
+const FAT_DIR: &str = "/fifo_dir\0";
+const FAT_FIFO: &str = "/fifo_dir/fifo\0";
+
+/// 列出目录 `path` 中的文件名
+fn list(path: &str) -> Vec<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut names = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = getdents64(fd as usize, &mut buf);
+        assert!(len >= 0, "getdents64 failed: {}", len);
+        if len == 0 {
+            break;
+        }
+        let mut pos = 0;
+        while pos < len as usize {
+            let record = &buf[pos..];
+            let reclen = u16::from_le_bytes([record[16], record[17]]) as usize;
+            let name = &record[19..reclen];
+            let name_len = name.iter().position(|&b| b == 0).unwrap();
+            names.push(String::from_utf8(name[..name_len].to_vec()).unwrap());
+            pos += reclen;
+        }
+    }
+    close(fd as usize);
+    names
+}
+
+/// FAT 上的 FIFO 创建在路径的父目录中，以最后一个分量为名
+fn fifo_in_fat_subdir() {
+    mkdir(FAT_DIR);
+    unlink(FAT_FIFO);
+    assert_eq!(mkfifo(FAT_FIFO), 0);
+    assert!(list(FAT_DIR).iter().any(|name| name == "fifo"), "fifo is not in {}", FAT_DIR);
+    assert!(
+        !list("/\0").iter().any(|name| name.contains("fifo") && name != "fifo_dir"),
+        "fifo was created in /"
+    );
+    let fd = open(FAT_FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    assert!(fd >= 0, "failed to open {}", FAT_FIFO);
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    assert_eq!(st.mode, StatMode::FIFO);
+    close(fd as usize);
+    assert_eq!(unlink(FAT_FIFO), 0);
+}
+
+/// 非阻塞的读端在还有写端时读空缓冲区返回 -EAGAIN 而不是 EOF，写端全部关闭后才返回 0；
+/// 非阻塞的写端在缓冲区满时返回 -EAGAIN
+fn check_nonblocking_io(read_fd: usize, write_fd: usize) {
+    let mut buf = [0u8; 8];
+    assert_eq!(read(read_fd, &mut buf), -EAGAIN);
+    assert_eq!(write(write_fd, b"abc"), 3);
+    assert_eq!(read(read_fd, &mut buf), 3);
+    assert_eq!(read(read_fd, &mut buf), -EAGAIN);
+    let chunk = [0u8; 512];
+    let mut written = 0;
+    loop {
+        let n = write(write_fd, &chunk);
+        if n == -EAGAIN {
+            break;
+        }
+        assert!(n > 0, "write returned {}", n);
+        written += n;
+    }
+    assert!(written > 0);
+    while read(read_fd, &mut buf) > 0 {}
+    close(write_fd);
+    assert_eq!(read(read_fd, &mut buf), 0);
+    close(read_fd);
+}
+
+fn nonblocking_io() {
+    let read_fd = open(FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    assert!(read_fd >= 0);
+    let write_fd = open(FIFO, OpenFlags::WRONLY | OpenFlags::NONBLOCK);
+    assert!(write_fd >= 0);
+    check_nonblocking_io(read_fd as usize, write_fd as usize);
+
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe2(&mut fds, OpenFlags::NONBLOCK), 0);
+    check_nonblocking_io(fds[0], fds[1]);
+}
+
+fn writer() -> ! {
+    let fd = open(FIFO, OpenFlags::WRONLY);
+    assert!(fd >= 0, "writer failed to open {}", FIFO);
+    for i in 0..MESSAGES {
+        assert_eq!(write(fd as usize, &[i as u8; 8]), 8);
+    }
+    close(fd as usize);
+    exit(0);
+}
+
+fn reader() -> ! {
+    // 让写者先打开并阻塞
+    sleep_blocking(100);
+    let fd = open(FIFO, OpenFlags::RDONLY);
+    assert!(fd >= 0, "reader failed to open {}", FIFO);
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    assert_eq!(st.mode, StatMode::FIFO);
+    let mut buf = [0u8; 64];
+    let mut received = 0usize;
+    loop {
+        let n = read(fd as usize, &mut buf);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        for &byte in &buf[..n as usize] {
+            assert_eq!(byte as usize, received / 8, "out of order at byte {}", received);
+            received += 1;
+        }
+    }
+    assert_eq!(received, MESSAGES * 8);
+    close(fd as usize);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/tmp\0");
+    unlink(FIFO);
+    assert_eq!(mkfifo(FIFO), 0);
+    assert_eq!(mkfifo(FIFO), -EEXIST);
+
+    // 没有读者时非阻塞的只写打开失败，非阻塞的只读打开立即成功并读到 EOF
+    assert_eq!(open(FIFO, OpenFlags::WRONLY | OpenFlags::NONBLOCK), -ENXIO);
+    let fd = open(FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    assert!(fd >= 0);
+    assert_eq!(read(fd as usize, &mut [0u8; 8]), 0);
+    close(fd as usize);
+    nonblocking_io();
+
+    let writer_pid = fork();
+    if writer_pid == 0 {
+        writer();
+    }
+    let reader_pid = fork();
+    if reader_pid == 0 {
+        reader();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(writer_pid as usize, &mut exit_code), writer_pid);
+    assert_eq!(exit_code, 0, "writer failed");
+    assert_eq!(waitpid(reader_pid as usize, &mut exit_code), reader_pid);
+    assert_eq!(exit_code, 0, "reader failed");
+    assert_eq!(unlink(FIFO), 0);
+    fifo_in_fat_subdir();
+    println!("fifo passed!");
+    0
+}
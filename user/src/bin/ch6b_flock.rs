@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+
+//! 两个进程分别打开同一个文件并申请 LOCK_EX：父进程先拿到锁，子进程的 LOCK_NB 返回
+//! -EWOULDBLOCK，阻塞的 LOCK_EX 一直等到父进程 LOCK_UN。共享锁之间不冲突；
+//! 进程退出时它持有的锁自动释放；fork 继承的描述符与父进程共享同一把锁；
+//! 两个持有共享锁的打开文件同时升级为排他锁不会死锁。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, flock, fork, get_time, open, read, sleep, unlink, waitpid, write, OpenFlags, LOCK_EX, LOCK_NB,
+    LOCK_SH, LOCK_UN,
+};
+
+const EWOULDBLOCK: isize = 11;
+const EINVAL: isize = 22;
+const PATH: &str = "/flock_test\0";
+const HOLD_MS: isize = 100;
+
+fn open_file() -> usize {
+    let fd = open(PATH, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    fd as usize
+}
+
+fn wait_child(pid: isize) {
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "child failed");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd >= 0);
+    close(fd as usize);
+
+    let fd = open_file();
+    assert_eq!(flock(fd, LOCK_EX), 0);
+    let start = get_time();
+    let pid = fork();
+    if pid == 0 {
+        // 子进程自己打开文件，与父进程的打开文件竞争
+        let own = open_file();
+        assert_eq!(flock(own, LOCK_EX | LOCK_NB), -EWOULDBLOCK);
+        assert_eq!(flock(own, LOCK_SH | LOCK_NB), -EWOULDBLOCK);
+        assert_eq!(flock(own, LOCK_EX), 0);
+        assert!(get_time() - start >= HOLD_MS, "LOCK_EX did not block");
+        // 父进程在解锁之前写入的内容已经可见
+        let mut buf = [0u8; 8];
+        assert_eq!(read(own, &mut buf), 6);
+        assert_eq!(&buf[..6], b"parent");
+        // 退出时不解锁，由内核释放
+        exit(0);
+    }
+    sleep(HOLD_MS as usize);
+    assert_eq!(write(fd, b"parent"), 6);
+    assert_eq!(flock(fd, LOCK_UN), 0);
+    wait_child(pid);
+    assert_eq!(flock(fd, LOCK_EX | LOCK_NB), 0, "lock of an exited process was not released");
+    assert_eq!(flock(fd, LOCK_UN), 0);
+
+    // 共享锁可以同时持有，排他锁与它们冲突
+    assert_eq!(flock(fd, LOCK_SH), 0);
+    let other = open_file();
+    assert_eq!(flock(other, LOCK_SH | LOCK_NB), 0);
+    let third = open_file();
+    assert_eq!(flock(third, LOCK_EX | LOCK_NB), -EWOULDBLOCK);
+    assert_eq!(flock(other, LOCK_UN), 0);
+    // 关闭最后一个描述符释放锁
+    close(fd);
+    assert_eq!(flock(third, LOCK_EX | LOCK_NB), 0);
+
+    // fork 继承的描述符指向同一个打开文件，子进程不会与父进程冲突
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(flock(third, LOCK_EX | LOCK_NB), 0);
+        exit(0);
+    }
+    wait_child(pid);
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), -EWOULDBLOCK);
+    close(third);
+    assert_eq!(flock(other, LOCK_EX | LOCK_NB), 0);
+    close(other);
+    // 两个共享锁同时升级：等待中的升级先释放了共享锁，另一方的升级才能完成
+    let fd = open_file();
+    assert_eq!(flock(fd, LOCK_SH), 0);
+    let pid = fork();
+    if pid == 0 {
+        let own = open_file();
+        assert_eq!(flock(own, LOCK_SH), 0);
+        assert_eq!(flock(own, LOCK_EX), 0);
+        assert_eq!(flock(own, LOCK_UN), 0);
+        exit(0);
+    }
+    sleep(HOLD_MS as usize);
+    assert_eq!(flock(fd, LOCK_EX), 0);
+    assert_eq!(flock(fd, LOCK_UN), 0);
+    wait_child(pid);
+    close(fd);
+    // 标准输入不支持加锁
+    assert_eq!(flock(0, LOCK_EX), -EINVAL);
+    unlink(PATH);
+    println!("flock passed!");
+    0
+}
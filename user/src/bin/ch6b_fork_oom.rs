@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, pipe, read, wait};
+
+const ENOMEM: isize = 12;
+const MAX_CHILDREN: usize = 4096;
+
+/// 在 `make run FEATURES=tiny-mem` 下运行：不断 fork 直到内存耗尽，
+/// 确认内核返回 -ENOMEM 而不是 panic，并且之后仍能回收子进程、继续 fork。
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let mut children = 0;
+    loop {
+        let pid = fork();
+        if pid == 0 {
+            // 子进程一直阻塞到父进程关闭写端，从而占住自己的内存
+            close(pipe_fd[1]);
+            let mut buf = [0u8; 1];
+            read(pipe_fd[0], &mut buf);
+            exit(0);
+        }
+        if pid < 0 {
+            assert_eq!(pid, -ENOMEM, "fork should fail with ENOMEM");
+            break;
+        }
+        children += 1;
+        if children == MAX_CHILDREN {
+            println!("fork_oom: memory never ran out, build the kernel with FEATURES=tiny-mem");
+            break;
+        }
+    }
+    println!("fork_oom: {} children before ENOMEM", children);
+    // 释放所有子进程
+    close(pipe_fd[1]);
+    let mut exit_code: i32 = 0;
+    for _ in 0..children {
+        assert!(wait(&mut exit_code) > 0);
+        assert_eq!(exit_code, 0);
+    }
+    // 内存回收后应当可以再次 fork
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert!(pid > 0, "fork should succeed after reaping children");
+    assert!(wait(&mut exit_code) > 0);
+    println!("fork_oom passed!");
+    0
+}
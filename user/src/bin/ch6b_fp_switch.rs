@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+//! 父子进程交替进行双精度累加，期间频繁 yield 并被时钟中断抢占，
+//! 内核必须在切换时保存各自的浮点寄存器，两边的结果才都是精确值。
+
+#[macro_use]
+extern crate user_lib;
+
+use core::hint::black_box;
+use user_lib::{exit, fork, waitpid, yield_};
+
+const ITERATIONS: u64 = 200_000;
+const YIELD_EVERY: u64 = 1000;
+
+/// 累加 step * i，部分和都是 step 的整数倍且小于 2^53，因此结果是精确的
+fn accumulate(step: f64) -> f64 {
+    let mut acc = 0.0f64;
+    for i in 0..ITERATIONS {
+        acc += black_box(step) * i as f64;
+        if i % YIELD_EVERY == 0 {
+            yield_();
+        }
+    }
+    acc
+}
+
+fn expected(step: f64) -> f64 {
+    step * (ITERATIONS * (ITERATIONS - 1) / 2) as f64
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    let step = if pid == 0 { 0.25 } else { 0.5 };
+    let acc = accumulate(step);
+    if pid == 0 {
+        exit(if acc == expected(step) { 0 } else { 1 });
+    }
+    assert!(acc == expected(step), "parent got {}, expected {}", acc, expected(step));
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "child computed a wrong sum");
+    println!("fp_switch passed!");
+    0
+}
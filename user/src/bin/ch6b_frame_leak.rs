@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, sysinfo, waitpid, SysInfo};
+
+const ROUNDS: usize = 500;
+
+fn free_frames() -> usize {
+    let mut info = SysInfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    info.freeram
+}
+
+/// fork 一个子进程，子进程 exec 一个简单程序后退出
+fn fork_exec_exit() {
+    let pid = fork();
+    if pid == 0 {
+        exec("ch6b_exec_flush_child\0", &[core::ptr::null::<u8>()]);
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 第一轮可能为内核栈等建立新的页表，不计入统计
+    fork_exec_exit();
+    let before = free_frames();
+    for _ in 0..ROUNDS {
+        fork_exec_exit();
+    }
+    let after = free_frames();
+    assert_eq!(before, after, "leaked {} frames", before as isize - after as isize);
+    println!("frame_leak passed!");
+    0
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sysinfo, SysInfo};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut info = SysInfo::default();
+    if sysinfo(&mut info) != 0 {
+        println!("free: sysinfo failed");
+        return -1;
+    }
+    let unit = info.mem_unit as usize;
+    let total = info.totalram * unit / 1024;
+    let free = info.freeram * unit / 1024;
+    println!("{:>12} {:>12} {:>12}", "total", "used", "free");
+    println!("Mem: {:>7}K {:>11}K {:>11}K", total, total - free, free);
+    println!("procs: {}, uptime: {}s", info.procs, info.uptime);
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+//! 对每种文件描述符调用 fstat 和 getdents64：标准输入输出、管道两端、普通文件、目录、
+//! /proc 文件，以及已关闭和越界的描述符。内核不能崩溃，类型和错误码要正确。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, getdents64, open, pipe, unlink, write, OpenFlags, Stat, StatMode, STDIN, STDOUT,
+};
+
+const EBADF: isize = 9;
+const ENOTDIR: isize = 20;
+const TEST_FILE: &str = "fstat_test\0";
+
+/// fstat `fd`，检查文件类型；不是目录时 getdents64 应返回 ENOTDIR
+fn check(fd: usize, what: &str, mode: StatMode) -> Stat {
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd, &mut st), 0, "fstat {} failed", what);
+    assert_eq!(st.mode.file_type(), mode, "{} has mode {:?}", what, st.mode);
+    let mut buf = [0u8; 256];
+    let ret = getdents64(fd, &mut buf);
+    if mode == StatMode::DIR {
+        assert!(ret > 0, "getdents64 on {} returned {}", what, ret);
+    } else {
+        assert_eq!(ret, -ENOTDIR, "getdents64 on {}", what);
+    }
+    println!("{}: mode {:#o} ino {} size {}", what, st.mode.bits(), st.ino, st.size);
+    st
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    check(STDIN, "stdin", StatMode::CHR);
+    check(STDOUT, "stdout", StatMode::CHR);
+
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let read_end = check(fds[0], "pipe read end", StatMode::FIFO);
+    let write_end = check(fds[1], "pipe write end", StatMode::FIFO);
+    assert_eq!(read_end.ino, write_end.ino, "pipe ends report different inodes");
+
+    let fd = open(TEST_FILE, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd >= 0, "failed to create {}", TEST_FILE);
+    assert_eq!(write(fd as usize, b"0123456789"), 10);
+    let st = check(fd as usize, "regular file", StatMode::FILE);
+    assert_eq!(st.size, 10);
+
+    let dir = open("/\0", OpenFlags::RDONLY);
+    assert!(dir >= 0, "failed to open /");
+    check(dir as usize, "root directory", StatMode::DIR);
+
+    let proc_fd = open("/proc/self/status\0", OpenFlags::RDONLY);
+    assert!(proc_fd >= 0, "failed to open /proc/self/status");
+    let st = check(proc_fd as usize, "/proc/self/status", StatMode::FILE);
+    assert!(st.size > 0);
+
+    for fd in [fds[0], fds[1], fd as usize, dir as usize, proc_fd as usize] {
+        close(fd);
+    }
+    // 已关闭和越界的描述符
+    let mut st = Stat::new();
+    assert_eq!(fstat(fds[0], &mut st), -EBADF);
+    assert_eq!(fstat(usize::MAX, &mut st), -EBADF);
+    assert_eq!(getdents64(4096, &mut [0u8; 64]), -EBADF);
+    unlink(TEST_FILE);
+    println!("fstat_fds passed!");
+    0
+}
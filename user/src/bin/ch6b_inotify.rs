@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+//! 监视一个目录，在其中创建、写入并删除文件，按顺序读回 IN_CREATE、IN_MODIFY 和 IN_DELETE；
+//! 没有监视的事件不会出现。然后产生超过队列容量的事件，队尾是 IN_Q_OVERFLOW。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, inotify_add_watch, inotify_init1, mkdir, open, read, unlink, write, OpenFlags, IN_CREATE, IN_DELETE,
+    IN_MODIFY, IN_NONBLOCK, IN_Q_OVERFLOW,
+};
+
+const ENOENT: isize = 2;
+const ENOTDIR: isize = 20;
+const EINVAL: isize = 22;
+/// 一条事件记录的字节数：16 字节的头部加上固定 48 字节的名字
+const EVENT_SIZE: usize = 64;
+const DIR: &str = "/inotify_dir\0";
+
+fn u32_at(record: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]])
+}
+
+/// 读出所有排队的事件 (wd, mask, name)
+fn read_events(fd: usize) -> Vec<(i32, u32, String)> {
+    let mut events = Vec::new();
+    let mut buf = [0u8; EVENT_SIZE * 4];
+    loop {
+        let len = read(fd, &mut buf);
+        assert!(len >= 0 && len as usize % EVENT_SIZE == 0, "bad read length {}", len);
+        if len == 0 {
+            return events;
+        }
+        for record in buf[..len as usize].chunks(EVENT_SIZE) {
+            let wd = u32_at(record, 0) as i32;
+            let mask = u32_at(record, 4);
+            let name_len = u32_at(record, 12) as usize;
+            let name = &record[16..16 + name_len];
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            events.push((wd, mask, String::from_utf8(name[..end].to_vec()).unwrap()));
+        }
+    }
+}
+
+fn touch(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    if !data.is_empty() {
+        assert_eq!(write(fd as usize, data), data.len() as isize);
+    }
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir(DIR);
+    let fd = inotify_init1(IN_NONBLOCK);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(inotify_add_watch(fd, "/inotify_missing\0", IN_CREATE), -ENOENT);
+    assert_eq!(inotify_add_watch(fd, DIR, 0), -EINVAL);
+    touch("/inotify_dir/plain\0", b"");
+    assert_eq!(inotify_add_watch(fd, "/inotify_dir/plain\0", IN_CREATE), -ENOTDIR);
+    let wd = inotify_add_watch(fd, DIR, IN_CREATE | IN_DELETE) as i32;
+    assert!(wd > 0);
+    assert!(read_events(fd).is_empty());
+
+    touch("/inotify_dir/new_file\0", b"");
+    assert_eq!(unlink("/inotify_dir/new_file\0"), 0);
+    let events = read_events(fd);
+    assert_eq!(
+        events,
+        [(wd, IN_CREATE, String::from("new_file")), (wd, IN_DELETE, String::from("new_file"))]
+    );
+
+    // 再次监视同一个目录返回同一个描述符，并更新事件
+    assert_eq!(inotify_add_watch(fd, DIR, IN_MODIFY) as i32, wd);
+    touch("/inotify_dir/plain\0", b"some data");
+    assert_eq!(read_events(fd), [(wd, IN_MODIFY, String::from("plain"))]);
+    assert_eq!(unlink("/inotify_dir/plain\0"), 0);
+    assert!(read_events(fd).is_empty(), "unwatched IN_DELETE delivered");
+
+    // 队列满后丢弃事件，队尾是 IN_Q_OVERFLOW
+    assert_eq!(inotify_add_watch(fd, DIR, IN_CREATE | IN_DELETE) as i32, wd);
+    for _ in 0..40 {
+        touch("/inotify_dir/churn\0", b"");
+        unlink("/inotify_dir/churn\0");
+    }
+    let events = read_events(fd);
+    let (last, kept) = events.split_last().unwrap();
+    assert_eq!(last.1, IN_Q_OVERFLOW);
+    assert_eq!(last.0, -1);
+    assert!(kept.iter().all(|event| event.0 == wd && event.1 != IN_Q_OVERFLOW));
+    println!("{} events queued before overflow", kept.len());
+
+    close(fd);
+    unlink(DIR);
+    println!("inotify passed!");
+    0
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+//! 交替写入两个文件，使它们的簇在磁盘上交错排列，再由两个进程同时读取这两个文件：
+//! 相邻的请求在请求队列中合并，/proc/stat 中被合并的请求数（block_merges）应该增加。
+//! 设备操作次数与调度和时钟有关，只打印出来作参考。读出的内容必须与写入的一致。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{close, exit, fork, open, read, unlink, waitpid, write, OpenFlags};
+
+const FILES: [&str; 2] = ["/iom_a\0", "/iom_b\0"];
+/// 每次交替写入的字节数
+const CHUNK: usize = 512;
+/// 每个文件的块数
+const CHUNKS: usize = 128;
+
+/// 读取 /proc/stat 中名为 `name` 的计数
+fn stat(name: &str) -> usize {
+    let fd = open("/proc/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /proc/stat");
+    let mut buf = [0u8; 1024];
+    let mut text = String::new();
+    loop {
+        let n = read(fd as usize, &mut buf);
+        if n <= 0 {
+            break;
+        }
+        text.push_str(core::str::from_utf8(&buf[..n as usize]).unwrap());
+    }
+    close(fd as usize);
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {} in /proc/stat", name))
+        .parse()
+        .unwrap()
+}
+
+/// 第 `file` 个文件中第 `chunk` 块的内容
+fn pattern(file: usize, chunk: usize) -> u8 {
+    (file * 101 + chunk * 7) as u8
+}
+
+/// 读出第 `file` 个文件并检查内容
+fn read_file(file: usize) {
+    let fd = open(FILES[file], OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", FILES[file]);
+    let mut buf = [0u8; 4096];
+    let mut offset = 0;
+    loop {
+        let n = read(fd as usize, &mut buf);
+        assert!(n >= 0, "read failed");
+        if n == 0 {
+            break;
+        }
+        for (i, &byte) in buf[..n as usize].iter().enumerate() {
+            assert_eq!(byte, pattern(file, (offset + i) / CHUNK), "bad data in {}", FILES[file]);
+        }
+        offset += n as usize;
+    }
+    assert_eq!(offset, CHUNK * CHUNKS);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fds: [usize; 2] = FILES.map(|path| {
+        unlink(path);
+        let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+        assert!(fd >= 0, "failed to create {}", path);
+        fd as usize
+    });
+    for chunk in 0..CHUNKS {
+        for (file, &fd) in fds.iter().enumerate() {
+            assert_eq!(write(fd, &[pattern(file, chunk); CHUNK]), CHUNK as isize);
+        }
+    }
+    fds.iter().for_each(|&fd| {
+        close(fd);
+    });
+
+    let ops = stat("block_ops");
+    read_file(0);
+    read_file(1);
+    let sequential = stat("block_ops") - ops;
+
+    let (ops, merges) = (stat("block_ops"), stat("block_merges"));
+    let pids = [0, 1].map(|file| {
+        let pid = fork();
+        if pid == 0 {
+            read_file(file);
+            exit(0);
+        }
+        pid
+    });
+    for pid in pids {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0, "reader failed");
+    }
+    let concurrent = stat("block_ops") - ops;
+    let merged = stat("block_merges") - merges;
+    println!("block ops: sequential {}, concurrent {} ({} requests merged)", sequential, concurrent, merged);
+    assert!(merged > 0, "concurrent reads were not merged");
+
+    FILES.iter().for_each(|path| {
+        unlink(path);
+    });
+    println!("io_merge passed!");
+    0
+}
@@ -0,0 +1,198 @@
+#![no_std]
+#![no_main]
+
+//! ls [-a] [-l] [路径...]：列出目录中的文件，不给路径时列出当前目录。
+//! 目录以 O_DIRECTORY 打开，反复调用 getdents64 直到返回 0，名称按字母排序后输出，目录名后加 `/`；
+//! `-l` 时另外用 fstatat 取得每一项的类型、大小和修改时间，`-a` 时也列出以 `.` 开头的项
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{civil_from_days, close, fstatat, getdents64, open, stat, OpenFlags, Stat, StatMode};
+
+const ENOTDIR: isize = 20;
+/// linux_dirent64 中目录的 d_type
+const DT_DIR: u8 = 4;
+
+/// getdents64 的缓冲区，要按 8 字节对齐；比较小，大目录要分多次读取
+#[repr(C, align(8))]
+struct DirentBuf([u8; 512]);
+
+struct Options {
+    all: bool,
+    long: bool,
+}
+
+/// 目录中的一项
+struct Item {
+    name: String,
+    is_dir: bool,
+}
+
+/// 从已经打开的目录 `fd` 读出所有的项
+fn read_dir(fd: usize) -> Result<Vec<Item>, isize> {
+    let mut items = Vec::new();
+    let mut buf = DirentBuf([0; 512]);
+    loop {
+        let len = getdents64(fd, &mut buf.0);
+        if len < 0 {
+            return Err(len);
+        }
+        if len == 0 {
+            return Ok(items);
+        }
+        // d_ino、d_off、d_reclen、d_type 之后是以 \0 结尾的文件名
+        let mut pos = 0;
+        while pos < len as usize {
+            let record = &buf.0[pos..];
+            let reclen = u16::from_le_bytes([record[16], record[17]]) as usize;
+            let name = &record[19..reclen];
+            let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            items.push(Item {
+                name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                is_dir: record[18] == DT_DIR,
+            });
+            pos += reclen;
+        }
+    }
+}
+
+/// Unix 时间（秒）对应的 UTC 时间，形如 2024-01-31 12:00:00
+fn format_time(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let secs_of_day = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    alloc::format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// 一项的类型字符
+fn type_char(mode: StatMode) -> char {
+    if mode.contains(StatMode::DIR) {
+        'd'
+    } else if mode.contains(StatMode::FIFO) {
+        'p'
+    } else if mode.contains(StatMode::CHR) {
+        'c'
+    } else {
+        '-'
+    }
+}
+
+/// 输出一项，`st` 是 `-l` 时取得的文件状态
+fn print_item(name: &str, is_dir: bool, st: Option<&Stat>) {
+    let suffix = if is_dir { "/" } else { "" };
+    match st {
+        Some(st) => println!(
+            "{}  {}  {:>10}  {}{}",
+            type_char(st.mode),
+            format_time(st.times[2]),
+            if is_dir { String::from("-") } else { alloc::format!("{}", st.size) },
+            name,
+            suffix
+        ),
+        None => println!("{}{}", name, suffix),
+    }
+}
+
+/// 列出 `path`，它不是目录时只列出它自己；出错时报告并返回 false
+fn list(path: &str, options: &Options) -> bool {
+    let mut c_path = String::from(path);
+    c_path.push('\0');
+    let fd = open(c_path.as_str(), OpenFlags::RDONLY | OpenFlags::O_DIRECTORY);
+    if fd == -ENOTDIR {
+        let mut st = Stat::new();
+        let ret = stat(c_path.as_str(), &mut st);
+        if ret < 0 {
+            eprintln!("ls: cannot access '{}' ({})", path, ret);
+            return false;
+        }
+        print_item(path, false, options.long.then_some(&st));
+        return true;
+    }
+    if fd < 0 {
+        eprintln!("ls: cannot open directory '{}' ({})", path, fd);
+        return false;
+    }
+    let fd = fd as usize;
+    let mut items = match read_dir(fd) {
+        Ok(items) => items,
+        Err(errno) => {
+            eprintln!("ls: cannot read directory '{}' ({})", path, errno);
+            close(fd);
+            return false;
+        }
+    };
+    items.retain(|item| options.all || !item.name.starts_with('.'));
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut ok = true;
+    for item in items.iter() {
+        if !options.long {
+            print_item(&item.name, item.is_dir, None);
+            continue;
+        }
+        let mut name = item.name.clone();
+        name.push('\0');
+        let mut st = Stat::new();
+        let ret = fstatat(fd, name.as_str(), &mut st);
+        if ret < 0 {
+            eprintln!("ls: cannot access '{}/{}' ({})", path, item.name, ret);
+            ok = false;
+            continue;
+        }
+        print_item(&item.name, item.is_dir, Some(&st));
+    }
+    close(fd);
+    ok
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let mut options = Options { all: false, long: false };
+    let mut paths = Vec::new();
+    for arg in argv.iter().skip(1) {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'a' => options.all = true,
+                        'l' => options.long = true,
+                        _ => {
+                            eprintln!("ls: invalid option -- '{}'", flag);
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => paths.push(*arg),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(".");
+    }
+    let mut ok = true;
+    for (i, path) in paths.iter().enumerate() {
+        if paths.len() > 1 {
+            if i > 0 {
+                println!("");
+            }
+            println!("{}:", path);
+        }
+        ok &= list(path, &options);
+    }
+    if ok {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+
+//! 用 ch6b_ls 列出一个有几十个项的目录：目录项要分多次 getdents64 才能读完，
+//! 检查每一项都只出现一次并且按字母排序，`-l` 的大小和类型来自 fstatat，`-a` 才列出以 `.` 开头的项。
+//! 另外列出 /proc：`-l` 对 /proc 中的项同样能取得状态，/proc 中的文件按文件列出，
+//! 目录的权限是 0555，文件是 0444
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dup3, exec, fork, mkdir, open, read, stat, unlink, waitpid, write, OpenFlags, Stat, StatMode, STDOUT,
+};
+
+const DIR: &str = "ls_test_dir";
+const OUTPUT: &str = "ls_test_out.txt\0";
+const FILES: usize = 40;
+
+fn file_name(i: usize) -> String {
+    format!("entry_with_a_long_name_{:02}", i)
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0, "failed to create {}", path);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).unwrap()
+}
+
+/// 以参数 `args` 运行 ch6b_ls，返回它的标准输出
+fn run_ls(args: &[&str]) -> String {
+    let pid = fork();
+    if pid == 0 {
+        let out = open(OUTPUT, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+        assert!(out >= 0);
+        dup3(out as usize, STDOUT);
+        close(out as usize);
+        let args: Vec<String> = args.iter().map(|arg| format!("{}\0", arg)).collect();
+        let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.push(core::ptr::null());
+        exec("ch6b_ls\0", &argv);
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "ls failed");
+    read_file(OUTPUT)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir(&format!("{}\0", DIR));
+    mkdir(&format!("{}/sub\0", DIR));
+    write_file(&format!("{}/.hidden\0", DIR), b"");
+    // 按和名称不同的顺序创建，排序不能依赖目录中的顺序；第 i 个文件有 i 个字节
+    for i in (0..FILES).rev() {
+        write_file(&format!("{}/{}\0", DIR, file_name(i)), &[b'x'; FILES][..i]);
+    }
+
+    let mut expected: Vec<String> = (0..FILES).map(file_name).collect();
+    expected.push(String::from("sub/"));
+    let output = run_ls(&["ch6b_ls", DIR]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines, expected);
+
+    let output = run_ls(&["ch6b_ls", "-l", DIR]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), FILES + 1);
+    for (i, line) in lines[..FILES].iter().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // 类型、日期、时间、大小、名称
+        assert_eq!(fields.len(), 5, "bad line {}", line);
+        assert_eq!(fields[0], "-");
+        assert_eq!(fields[3], format!("{}", i));
+        assert_eq!(fields[4], file_name(i));
+    }
+    assert!(lines[FILES].starts_with('d') && lines[FILES].ends_with(" sub/"));
+
+    let output = run_ls(&["ch6b_ls", "-a", DIR]);
+    assert!(output.lines().any(|line| line == ".hidden"));
+    assert_eq!(output.lines().filter(|line| line.starts_with("entry_")).count(), FILES);
+
+    let output = run_ls(&["ch6b_ls", "/proc"]);
+    assert!(output.lines().any(|line| line == "meminfo") && output.lines().any(|line| line == "self/"), "{}", output);
+    // /proc 中目录的 fd 记下了路径，fstatat 相对于它查找其中的项；
+    // 只列出自己的目录，其他进程可能在列出和 fstatat 之间退出
+    let output = run_ls(&["ch6b_ls", "-l", "/proc/self"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", output);
+    assert!(lines.iter().all(|line| line.starts_with('-')), "{}", output);
+    let output = run_ls(&["ch6b_ls", "/proc/meminfo"]);
+    assert_eq!(output, "/proc/meminfo\n");
+    let mut st = Stat::new();
+    assert_eq!(stat("/proc/self\0", &mut st), 0);
+    assert_eq!((st.mode.file_type(), st.mode.permissions()), (StatMode::DIR, 0o555));
+    assert_eq!(stat("/proc/meminfo\0", &mut st), 0);
+    assert_eq!((st.mode.file_type(), st.mode.permissions()), (StatMode::FILE, 0o444));
+
+    for i in 0..FILES {
+        unlink(&format!("{}/{}\0", DIR, file_name(i)));
+    }
+    unlink(&format!("{}/.hidden\0", DIR));
+    unlink(&format!("{}/sub\0", DIR));
+    unlink(&format!("{}\0", DIR));
+    unlink(OUTPUT);
+    println!("ls passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, open, read, sbrk, OpenFlags};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 堆区域为空时不会出现在 maps 中
+    assert!(sbrk(4096) > 0, "sbrk failed");
+    let fd = open("/proc/self/maps\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /proc/self/maps");
+    let fd = fd as usize;
+    let mut maps = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd, &mut buf);
+        assert!(len >= 0, "read failed");
+        if len == 0 {
+            break;
+        }
+        maps.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd);
+    let maps = String::from_utf8(maps).expect("maps is not utf-8");
+    print!("{}", maps);
+    let perms_of = |name: &str| {
+        maps.lines()
+            .find(|line| line.ends_with(name))
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap_or_else(|| panic!("no {} line in maps", name))
+    };
+    assert_eq!(perms_of("[stack]"), "rw-p");
+    assert_eq!(perms_of("[heap]"), "rw-p");
+    // 代码段映射自程序文件
+    assert!(
+        maps.lines().any(|line| line.split(' ').nth(1) == Some("r-xp")),
+        "no executable mapping in maps"
+    );
+    println!("maps passed!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+//! 文件映射的页面在第一次访问时从文件读入。映射之后文件被截断，访问读不到内容的页面时
+//! 进程被杀死（以缺页的退出码 -2 退出），而不是看到全零或只有一部分内容的页面。
+//! 还不支持共享的文件映射，MAP_SHARED 返回 -ENODEV。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, mmap_file, open, sys_mmap, unlink, waitpid, write, OpenFlags, MAP_SHARED};
+
+const PATH: &str = "/mmap_file_test\0";
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: usize = 1;
+const ENODEV: isize = 19;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(PATH, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    for byte in [b'a', b'b'] {
+        assert_eq!(write(fd as usize, &[byte; PAGE_SIZE]), PAGE_SIZE as isize);
+    }
+    close(fd as usize);
+
+    let fd = open(PATH, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    assert_eq!(sys_mmap(0, PAGE_SIZE, PROT_READ, MAP_SHARED, fd as usize, 0), -ENODEV);
+    let addr = mmap_file(0, 2 * PAGE_SIZE, PROT_READ, fd as usize, 0);
+    assert!(addr > 0, "mmap failed");
+    let pages = unsafe { core::slice::from_raw_parts(addr as *const u8, 2 * PAGE_SIZE) };
+    assert!(pages[..PAGE_SIZE].iter().all(|&b| b == b'a'));
+
+    let pid = fork();
+    if pid == 0 {
+        // 截断文件，第二页还没有被访问过
+        let trunc = open(PATH, OpenFlags::WRONLY | OpenFlags::TRUNC);
+        assert!(trunc >= 0);
+        close(trunc as usize);
+        let byte = unsafe { core::ptr::read_volatile(&pages[PAGE_SIZE]) };
+        println!("read {:#x} past the end of a truncated file", byte);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2, "fault on a truncated file mapping did not kill the process");
+    close(fd as usize);
+    unlink(PATH);
+    println!("mmap_file passed!");
+    0
+}
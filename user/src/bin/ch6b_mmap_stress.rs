@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, munmap, waitpid};
+
+const ROUNDS: usize = 64;
+const PAGES: usize = 16;
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let len = PAGES * PAGE_SIZE;
+    for round in 0..ROUNDS {
+        // 读写权限
+        let start = mmap(0, len, 3);
+        assert!(start > 0, "mmap failed in round {}", round);
+        let buf = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+        for (i, byte) in buf.iter_mut().enumerate().step_by(PAGE_SIZE / 2) {
+            *byte = (i + round) as u8;
+        }
+        let pid = fork();
+        if pid == 0 {
+            // 子进程拥有独立的副本，写入不影响父进程
+            for (i, byte) in buf.iter_mut().enumerate().step_by(PAGE_SIZE / 2) {
+                assert_eq!(*byte, (i + round) as u8);
+                *byte = 0;
+            }
+            return 0;
+        }
+        let mut exit_code: i32 = 0;
+        waitpid(pid as usize, &mut exit_code);
+        assert_eq!(exit_code, 0);
+        for (i, byte) in buf.iter().enumerate().step_by(PAGE_SIZE / 2) {
+            assert_eq!(*byte, (i + round) as u8);
+        }
+        // 先解除中间一段，再解除剩余部分
+        assert_eq!(munmap(start as usize + PAGE_SIZE, PAGE_SIZE * 2), 0);
+        assert_eq!(munmap(start as usize, len), 0);
+    }
+    println!("mmap_stress passed!");
+    0
+}
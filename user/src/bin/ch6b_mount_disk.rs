@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+//! 把第二块磁盘 /dev/vdb 上的 FAT 文件系统挂载到 /mnt，从根文件系统复制一个文件过去，
+//! 卸载后文件不再可见，重新挂载后内容不变；/mnt 只读时相对于根目录 fd 创建 mnt 下的文件返回 -EROFS。需要用 `make SCRATCH_IMG=<FAT 镜像>` 启动，
+//! 没有第二块磁盘时跳过。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, mkdir, mount, mount_flags, open, read, sys_openat, umount, unlink, write, OpenFlags, MS_RDONLY, MS_REMOUNT,
+};
+
+const ENOENT: isize = 2;
+const EBUSY: isize = 16;
+const EROFS: isize = 30;
+const SOURCE: &str = "/mount_disk_src\0";
+const COPY: &str = "/mnt/mount_disk_copy\0";
+
+fn mount_vdb() -> isize {
+    mount("/dev/vdb\0", "/mnt\0", "vfat\0", "\0")
+}
+
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0, "cannot create {}", path);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    mkdir("/mnt\0");
+    let ret = mount_vdb();
+    if ret == -ENOENT {
+        println!("no /dev/vdb, skipped");
+        println!("mount_disk passed!");
+        return 0;
+    }
+    assert_eq!(ret, 0);
+    assert_eq!(mount_vdb(), -EBUSY);
+    assert_eq!(mount("/dev/vda\0", "/\0", "vfat\0", "\0"), -EBUSY);
+
+    let mut data = [0u8; 3000];
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = (i % 253) as u8;
+    }
+    write_file(SOURCE, &data);
+    let mut buf = [0u8; 4096];
+    assert_eq!(read_file(SOURCE, &mut buf), data.len() as isize);
+    write_file(COPY, &buf[..data.len()]);
+    assert_eq!(unlink(SOURCE), 0);
+
+    // 卸载后 /mnt 回到根文件系统中的空目录
+    assert_eq!(umount("/mnt\0"), 0);
+    assert_eq!(read_file(COPY, &mut buf), -1);
+    assert_eq!(mount_vdb(), 0);
+    buf.fill(0);
+    assert_eq!(read_file(COPY, &mut buf), data.len() as isize);
+    assert!(buf[..data.len()] == data, "copied file differs after remount");
+
+    // 相对于根目录 fd 的路径越过挂载点，按 /mnt 的挂载判断是否只读
+    assert_eq!(mount_flags("\0", "/mnt\0", "vfat\0", MS_REMOUNT | MS_RDONLY, "\0"), 0);
+    let root = open("/\0", OpenFlags::RDONLY);
+    assert!(root >= 0);
+    let flags = (OpenFlags::CREATE | OpenFlags::WRONLY).bits();
+    assert_eq!(sys_openat(root as usize, "mnt/mount_disk_ro\0", flags, 0), -EROFS);
+    close(root as usize);
+    assert_eq!(mount_flags("\0", "/mnt\0", "vfat\0", MS_REMOUNT, "\0"), 0);
+    assert_eq!(unlink(COPY), 0);
+    assert_eq!(umount("/mnt\0"), 0);
+    println!("mount_disk passed!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+//! 把根文件系统重新挂载为只读：创建、以写方式打开、删除文件和创建目录都返回 -EROFS，
+//! 读取文件不受影响；重新挂载为可写后可以照常创建和删除文件。
+//! 根文件系统本来就是只读的（启动参数 `ro` 或磁盘写保护）时跳过。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount_flags, open, read, unlink, write, OpenFlags, MS_RDONLY, MS_REMOUNT};
+
+const EROFS: isize = 30;
+const FILE: &str = "/mount_ro_file\0";
+const NEW_FILE: &str = "/mount_ro_new\0";
+
+fn remount(flags: usize) -> isize {
+    mount_flags("\0", "/\0", "vfat\0", MS_REMOUNT | flags, "\0")
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    if fd == -EROFS {
+        println!("root filesystem is read-only, skipped");
+        println!("mount_ro passed!");
+        return 0;
+    }
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+
+    assert_eq!(remount(MS_RDONLY), 0);
+    assert_eq!(open(NEW_FILE, OpenFlags::CREATE | OpenFlags::WRONLY), -EROFS);
+    assert_eq!(open(FILE, OpenFlags::WRONLY), -EROFS);
+    assert_eq!(open(FILE, OpenFlags::RDONLY | OpenFlags::TRUNC), -EROFS);
+    assert_eq!(mkdir("/mount_ro_dir\0"), -EROFS);
+    assert_eq!(unlink(FILE), -EROFS);
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd as usize, &mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+    close(fd as usize);
+
+    assert_eq!(remount(0), 0);
+    let fd = open(NEW_FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert_eq!(unlink(NEW_FILE), 0);
+    assert_eq!(unlink(FILE), 0);
+    println!("mount_ro passed!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+//! 子进程再 fork 一次后退出，孙进程被过继给 initproc。initproc 不会等待这些孤儿，
+//! 内核应在它们退出后直接回收：一秒之后 ps 中不应有僵尸进程，进程数回到开始时的值。
+//! 分别测试孙进程在父进程之后退出和在父进程之前退出两种情况。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, ps, sleep_blocking, waitpid, PsEntry, PS_ZOMBIE};
+
+const MAX_PROCS: usize = 64;
+
+/// 返回进程总数和其中僵尸进程的数量
+fn count_processes() -> (usize, usize) {
+    let mut buf = [PsEntry::default(); MAX_PROCS];
+    let total = ps(&mut buf);
+    assert!(total > 0, "ps failed");
+    let total = total as usize;
+    let zombies = buf[..total.min(MAX_PROCS)].iter().filter(|e| e.state == PS_ZOMBIE).count();
+    (total, zombies)
+}
+
+/// 创建一个子进程，子进程 fork 出一个睡眠 `grandchild_ms` 毫秒的孙进程后，
+/// 睡眠 `child_ms` 毫秒再退出；父进程回收子进程
+fn double_fork(child_ms: usize, grandchild_ms: usize) {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        let grandchild = fork();
+        assert!(grandchild >= 0, "fork failed");
+        if grandchild == 0 {
+            sleep_blocking(grandchild_ms);
+            exit(0);
+        }
+        sleep_blocking(child_ms);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let (start_total, start_zombies) = count_processes();
+    // 孙进程在被过继之后退出
+    double_fork(0, 200);
+    // 孙进程在被过继之前已经退出，成为僵尸孤儿
+    double_fork(200, 0);
+    sleep_blocking(1000);
+    let (total, zombies) = count_processes();
+    println!("processes: {} -> {}, zombies: {} -> {}", start_total, total, start_zombies, zombies);
+    assert_eq!(zombies, 0, "orphans were left as zombies");
+    assert_eq!(total, start_total, "orphans were not reclaimed");
+    println!("orphan_reap passed!");
+    0
+}
@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+//! 管道吞吐量测试：子进程以 4 KiB 为单位向管道写入 1 MiB 数据，父进程读出并校验。
+//! 分别使用默认的一页缓冲区和用 F_SETPIPE_SZ 放大到 64 KiB 的缓冲区。
+//! 读者只在管道为空时阻塞，缓冲区越大阻塞的次数越少：由 /proc/self/status 中读者主动让出处理器的次数检查。
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, exit, fcntl, fork, get_time, open, pipe, read, waitpid, write, OpenFlags, F_GETPIPE_SZ, F_SETPIPE_SZ,
+};
+
+const TOTAL: usize = 1 << 20;
+const CHUNK: usize = 4096;
+const EINVAL: isize = 22;
+
+/// 本进程主动让出处理器（阻塞）的次数，来自 /proc/self/status
+fn voluntary_switches() -> usize {
+    let fd = open("/proc/self/status\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /proc/self/status");
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0, "read failed");
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    let text = String::from_utf8(data).expect("not utf-8");
+    text.lines()
+        .find_map(|line| line.strip_prefix("voluntary_ctxt_switches:"))
+        .and_then(|value| value.trim().parse().ok())
+        .expect("no voluntary_ctxt_switches in /proc/self/status")
+}
+
+/// 通过容量为 `capacity` 的管道传输 TOTAL 字节，返回 (耗时（毫秒）, 读者阻塞的次数)
+fn bench(capacity: usize) -> (isize, usize) {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fcntl(pipe_fd[1], F_SETPIPE_SZ, capacity), capacity as isize);
+    assert_eq!(fcntl(pipe_fd[0], F_GETPIPE_SZ, 0), capacity as isize);
+    let start = get_time();
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        let mut chunk = [0u8; CHUNK];
+        let mut sent = 0;
+        while sent < TOTAL {
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = (sent + i) as u8;
+            }
+            assert_eq!(write(pipe_fd[1], &chunk), CHUNK as isize);
+            sent += CHUNK;
+        }
+        close(pipe_fd[1]);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let switches = voluntary_switches();
+    let mut buf = [0u8; CHUNK];
+    let mut received = 0;
+    loop {
+        let n = read(pipe_fd[0], &mut buf);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        for (i, byte) in buf[..n as usize].iter().enumerate() {
+            assert_eq!(*byte, (received + i) as u8, "corrupted at byte {}", received + i);
+        }
+        received += n as usize;
+    }
+    close(pipe_fd[0]);
+    let blocked = voluntary_switches() - switches;
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(received, TOTAL);
+    (get_time() - start, blocked)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut blocked = [0usize; 2];
+    for (i, capacity) in [4096, 65536].into_iter().enumerate() {
+        let (ms, waits) = bench(capacity);
+        println!(
+            "pipe_bench: {} bytes through a {} byte pipe in {} ms, reader blocked {} times",
+            TOTAL, capacity, ms, waits
+        );
+        blocked[i] = waits;
+    }
+    // 16 倍的缓冲区至少应当把读者阻塞的次数减半
+    assert!(blocked[1] * 2 < blocked[0], "larger pipe did not reduce blocking: {:?}", blocked);
+    // 容量超过 64 KiB 以及对非管道文件的请求都会失败
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(fcntl(pipe_fd[0], F_SETPIPE_SZ, 65536 + 1), -EINVAL);
+    assert_eq!(fcntl(0, F_GETPIPE_SZ, 0), -EINVAL);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("pipe_bench passed!");
+    0
+}
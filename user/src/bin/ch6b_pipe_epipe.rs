@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+//! 读端全部关闭后写管道返回 EPIPE：先测试读端已关闭时的写入，
+//! 再让子进程读取一个字节后关闭读端退出，父进程不停写入，
+//! 应在缓冲区写满、阻塞等待时被唤醒并得到 EPIPE，而不是永远挂起。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, pipe, read, waitpid, write};
+
+const EPIPE: isize = 32;
+const CHUNK: usize = 1024;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    close(fds[0]);
+    assert_eq!(write(fds[1], b"lost"), -EPIPE);
+    close(fds[1]);
+
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        close(fds[1]);
+        let mut byte = [0u8; 1];
+        assert_eq!(read(fds[0], &mut byte), 1);
+        close(fds[0]);
+        exit(0);
+    }
+    close(fds[0]);
+    let chunk = [b'y'; CHUNK];
+    let mut written = 0usize;
+    let ret = loop {
+        let ret = write(fds[1], &chunk);
+        if ret <= 0 {
+            break ret;
+        }
+        written += ret as usize;
+    };
+    println!("wrote {} bytes before the reader went away", written);
+    assert_eq!(ret, -EPIPE);
+    close(fds[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("pipe_epipe passed!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+//! 低优先级的子进程写入管道后继续空转，阻塞在 read 上的高优先级父进程
+//! 应当立即被唤醒运行，而不是等子进程用完剩余的时间片。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, get_time, pipe, read, set_priority, sleep_blocking, waitpid, write};
+
+const ROUNDS: usize = 10;
+/// 写入之后空转的时间，长于一个时间片
+const SPIN_MS: isize = 50;
+/// 从写入到读取返回允许的平均延迟
+const MAX_AVG_LATENCY_MS: isize = 2;
+
+fn spin(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        close(pipe_fd[0]);
+        for _ in 0..ROUNDS {
+            // 让父进程先阻塞在 read 上
+            sleep_blocking(20);
+            let now = get_time().to_ne_bytes();
+            assert_eq!(write(pipe_fd[1], &now), now.len() as isize);
+            spin(SPIN_MS);
+        }
+        close(pipe_fd[1]);
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    assert_eq!(set_priority(64), 64);
+    let mut total = 0;
+    for round in 0..ROUNDS {
+        let mut buf = [0u8; 8];
+        assert_eq!(read(pipe_fd[0], &mut buf), buf.len() as isize);
+        let latency = get_time() - isize::from_ne_bytes(buf);
+        println!("round {}: {} ms", round, latency);
+        total += latency;
+    }
+    close(pipe_fd[0]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let avg = total / ROUNDS as isize;
+    assert!(avg <= MAX_AVG_LATENCY_MS, "average wakeup latency {} ms", avg);
+    println!("pipe_latency passed!");
+    0
+}
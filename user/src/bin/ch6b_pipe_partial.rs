@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+//! 管道的部分读取：读到数据后立即返回当前可读的部分，不等待填满缓冲区；
+//! 写满整个管道容量的写入不会覆盖未读数据；读写交替进行时数据保持顺序；
+//! 写端全部关闭且数据读完后 read 返回 0。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fcntl, fork, pipe, read, waitpid, write, F_GETPIPE_SZ};
+
+const ROUNDS: usize = 100;
+
+/// 写端保持打开，只写入 10 字节，读端用大缓冲区读取应立即返回 10
+fn short_read() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(write(fds[1], b"0123456789"), 10);
+    let mut buf = [0u8; 4096];
+    assert_eq!(read(fds[0], &mut buf), 10);
+    assert_eq!(&buf[..10], b"0123456789");
+    close(fds[1]);
+    assert_eq!(read(fds[0], &mut buf), 0);
+    close(fds[0]);
+}
+
+/// 恰好写满管道容量的写入不阻塞，读出的数据与写入的一致
+fn exact_capacity() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let capacity = fcntl(fds[0], F_GETPIPE_SZ, 0);
+    assert!(capacity > 0, "F_GETPIPE_SZ failed");
+    let capacity = capacity as usize;
+    let mut data = [0u8; 4096];
+    assert!(capacity <= data.len());
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    assert_eq!(write(fds[1], &data[..capacity]), capacity as isize);
+    let mut buf = [0u8; 4096];
+    assert_eq!(read(fds[0], &mut buf), capacity as isize);
+    assert_eq!(&buf[..capacity], &data[..capacity]);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+/// 父子进程通过两个管道一问一答，每次读取都只得到对方刚写入的一个短消息
+fn interleaved() {
+    let mut to_child = [0usize; 2];
+    let mut to_parent = [0usize; 2];
+    assert_eq!(pipe(&mut to_child), 0);
+    assert_eq!(pipe(&mut to_parent), 0);
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    let mut buf = [0u8; 64];
+    if pid == 0 {
+        close(to_child[1]);
+        close(to_parent[0]);
+        for round in 0..ROUNDS {
+            assert_eq!(read(to_child[0], &mut buf), 2);
+            assert_eq!(buf[..2], [round as u8, !(round as u8)]);
+            assert_eq!(write(to_parent[1], &buf[..2]), 2);
+        }
+        exit(0);
+    }
+    close(to_child[0]);
+    close(to_parent[1]);
+    for round in 0..ROUNDS {
+        assert_eq!(write(to_child[1], &[round as u8, !(round as u8)]), 2);
+        assert_eq!(read(to_parent[0], &mut buf), 2);
+        assert_eq!(buf[..2], [round as u8, !(round as u8)]);
+    }
+    close(to_child[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 子进程退出后写端全部关闭
+    assert_eq!(read(to_parent[0], &mut buf), 0);
+    close(to_parent[0]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    short_read();
+    exact_capacity();
+    interleaved();
+    println!("pipe_partial passed!");
+    0
+}
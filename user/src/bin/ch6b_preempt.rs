@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+//! 子进程在不做任何系统调用的死循环中空转，父进程（扮演交互式 shell）
+//! 必须依靠时钟中断抢占才能继续运行，并且每次睡眠都应在合理的时间内醒来。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, mmap_shared, sleep_blocking, waitpid};
+
+const PAGE_SIZE: usize = 4096;
+const ROUNDS: usize = 10;
+const SLEEP_MS: usize = 20;
+/// 一次睡眠允许的最大延迟
+const MAX_LATENCY_MS: isize = 200;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let flag = mmap_shared(0, PAGE_SIZE, 3);
+    assert!(flag > 0, "mmap failed");
+    let flag = flag as *mut u64;
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        // 空转直到父进程写入标志，期间不陷入内核
+        let mut spins: u64 = 0;
+        while unsafe { flag.read_volatile() } == 0 {
+            spins = spins.wrapping_add(1);
+        }
+        exit(if spins > 0 { 0 } else { 1 });
+    }
+    for round in 0..ROUNDS {
+        let start = get_time();
+        sleep_blocking(SLEEP_MS);
+        let elapsed = get_time() - start;
+        assert!(elapsed >= SLEEP_MS as isize, "round {} woke after {} ms", round, elapsed);
+        assert!(elapsed < MAX_LATENCY_MS, "round {} took {} ms", round, elapsed);
+    }
+    unsafe { flag.write_volatile(1) };
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("preempt passed!");
+    0
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+//! /proc 的目录列出当前存在的进程：fork 出的子进程在被回收之前出现在 /proc 中，
+//! 回收之后消失。/proc/<pid> 列出进程的文件，/proc/meminfo 给出内存的使用情况。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exit, fork, fstat, getdents64, getpid, open, read, sleep, waitpid, OpenFlags, Stat, StatMode};
+
+/// 读出目录中的所有文件名
+fn list_dir(path: &str) -> Vec<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    assert_eq!(st.mode, StatMode::DIR);
+    let mut names = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = getdents64(fd as usize, &mut buf);
+        assert!(len >= 0, "getdents64 failed: {}", len);
+        if len == 0 {
+            break;
+        }
+        let mut pos = 0;
+        while pos < len as usize {
+            let record = &buf[pos..];
+            let reclen = u16::from_le_bytes([record[16], record[17]]) as usize;
+            let name = &record[19..reclen];
+            let name_len = name.iter().position(|&b| b == 0).unwrap();
+            names.push(String::from_utf8(name[..name_len].to_vec()).unwrap());
+            pos += reclen;
+        }
+    }
+    close(fd as usize);
+    names
+}
+
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0, "read failed");
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).expect("not utf-8")
+}
+
+/// meminfo 中 `key` 一行的千字节数
+fn meminfo_kb(text: &str, key: &str) -> usize {
+    text.lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no {} in {}", key, text))
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        sleep(100);
+        exit(0);
+    }
+    let names = list_dir("/proc\0");
+    for expected in ["meminfo", "stat", "self"] {
+        assert!(names.iter().any(|name| name == expected), "no {} in /proc", expected);
+    }
+    let me = format!("{}", getpid());
+    let child = format!("{}", pid);
+    assert!(names.contains(&me), "own pid {} missing from /proc", me);
+    assert!(names.contains(&child), "child pid {} missing from /proc", child);
+
+    let mut files = list_dir(&format!("/proc/{}\0", child));
+    files.sort();
+    assert_eq!(files, ["comm", "maps", "stat", "status"]);
+    assert!(read_file(&format!("/proc/{}/status\0", child)).starts_with(&format!("Pid:\t{}\n", child)));
+
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(!list_dir("/proc/\0").contains(&child), "reaped child still listed");
+    assert!(open(&format!("/proc/{}/stat\0", child), OpenFlags::RDONLY) < 0);
+
+    let meminfo = read_file("/proc/meminfo\0");
+    print!("{}", meminfo);
+    let total = meminfo_kb(&meminfo, "MemTotal:");
+    let free = meminfo_kb(&meminfo, "MemFree:");
+    assert!(free > 0 && free < total);
+    assert!(meminfo_kb(&meminfo, "HeapUsed:") <= meminfo_kb(&meminfo, "HeapTotal:"));
+    println!("procfs passed!");
+    0
+}
@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+
+//! 列出所有进程，同时检查自己、睡眠中的子进程和未回收的僵尸子进程的状态，
+//! 以及父进程退出后过继给 initproc 的孤儿：ps 和它自己的 getppid 都报告新的父进程。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpid, getppid, pipe, ps, read, sleep_blocking, waitpid, write, PsEntry, PS_READY,
+    PS_RUNNING, PS_SLEEPING, PS_ZOMBIE,
+};
+
+const MAX_PROCS: usize = 64;
+
+fn state_name(state: usize) -> &'static str {
+    match state {
+        PS_READY => "R",
+        PS_RUNNING => "R+",
+        PS_SLEEPING => "S",
+        PS_ZOMBIE => "Z",
+        _ => "?",
+    }
+}
+
+fn print_table(entries: &[PsEntry]) {
+    println!("{:>5} {:>5} {:>3} {:>4} {:>12} {:>12} {:>6} CMD", "PID", "PPID", "S", "PRI", "UTIME", "STIME", "RSS");
+    for e in entries {
+        let name = if e.state == PS_ZOMBIE { "<defunct>" } else { e.name() };
+        println!(
+            "{:>5} {:>5} {:>3} {:>4} {:>12} {:>12} {:>6} {}",
+            e.pid, e.ppid, state_name(e.state), e.priority, e.utime, e.stime, e.rss, name
+        );
+    }
+}
+
+fn snapshot(buf: &mut [PsEntry; MAX_PROCS]) -> &[PsEntry] {
+    let total = ps(buf);
+    assert!(total > 0, "ps failed");
+    &buf[..(total as usize).min(MAX_PROCS)]
+}
+
+fn find(entries: &[PsEntry], pid: usize) -> &PsEntry {
+    entries.iter().find(|e| e.pid == pid).unwrap_or_else(|| panic!("pid {} not listed", pid))
+}
+
+/// 从管道读出一个 usize
+fn read_usize(fd: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    assert_eq!(read(fd, &mut bytes), 8);
+    usize::from_ne_bytes(bytes)
+}
+
+/// 子进程再 fork 出孙进程后退出，孙进程成为孤儿。
+/// 子进程经管道告知孙进程的 pid，孙进程在过继之后经管道告知 getppid 的结果
+fn check_orphan(buf: &mut [PsEntry; MAX_PROCS]) {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let child = fork();
+    if child == 0 {
+        let orphan = fork();
+        if orphan == 0 {
+            sleep_blocking(200);
+            write(fds[1], &(getppid() as usize).to_ne_bytes());
+            exit(0);
+        }
+        write(fds[1], &(orphan as usize).to_ne_bytes());
+        exit(0);
+    }
+    close(fds[1]);
+    let orphan = read_usize(fds[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    let ppid = find(snapshot(buf), orphan).ppid;
+    assert!(ppid != 0 && ppid != child as usize, "orphan still reports ppid {}", ppid);
+    assert_eq!(read_usize(fds[0]), ppid, "getppid disagrees with ps");
+    close(fds[0]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let sleeper = fork();
+    if sleeper == 0 {
+        sleep_blocking(1000);
+        exit(0);
+    }
+    let zombie = fork();
+    if zombie == 0 {
+        exit(7);
+    }
+    // 等待两个子进程分别进入睡眠和退出
+    sleep_blocking(200);
+
+    let mut buf = [PsEntry::default(); MAX_PROCS];
+    let entries = snapshot(&mut buf);
+    print_table(entries);
+
+    let me = find(entries, getpid() as usize);
+    assert_eq!(me.state, PS_RUNNING);
+    assert!(me.ppid > 0, "no parent");
+    assert_eq!(me.name(), "ch6b_ps");
+    assert!(me.rss > 0, "no resident pages");
+    let sleeping = find(entries, sleeper as usize);
+    assert_eq!(sleeping.state, PS_SLEEPING);
+    assert_eq!(sleeping.ppid, getpid() as usize);
+    assert_eq!(find(entries, zombie as usize).state, PS_ZOMBIE);
+
+    let mut exit_code = 0;
+    assert_eq!(waitpid(zombie as usize, &mut exit_code), zombie);
+    assert_eq!(waitpid(sleeper as usize, &mut exit_code), sleeper);
+    let entries = snapshot(&mut buf);
+    assert!(entries.iter().all(|e| e.pid != zombie as usize && e.pid != sleeper as usize));
+    check_orphan(&mut buf);
+    println!("ps passed!");
+    0
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+//! CLOCK_REALTIME 来自 goldfish 实时时钟，在 QEMU 下应该是真实的日期；
+//! CLOCK_MONOTONIC 是启动以来的时间，两者都不应该倒退。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{civil_from_days, clock_gettime, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+const SECS_PER_DAY: usize = 86400;
+
+fn read_clock(clock_id: usize) -> TimeSpec {
+    let mut time = TimeSpec::default();
+    assert_eq!(clock_gettime(clock_id, &mut time), 0, "clock_gettime failed");
+    assert!(time.nsec < 1_000_000_000, "nsec out of range");
+    time
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let now = read_clock(CLOCK_REALTIME);
+    let (year, month, day) = civil_from_days((now.sec / SECS_PER_DAY) as i64);
+    let secs = now.sec % SECS_PER_DAY;
+    println!(
+        "realtime: {} s, {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        now.sec,
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    assert!(year >= 2024, "wall clock is not set");
+
+    let mono = read_clock(CLOCK_MONOTONIC);
+    let later = read_clock(CLOCK_REALTIME);
+    let mono_later = read_clock(CLOCK_MONOTONIC);
+    assert!((later.sec, later.nsec) >= (now.sec, now.nsec), "realtime went backwards");
+    assert!((mono_later.sec, mono_later.nsec) >= (mono.sec, mono.nsec), "monotonic went backwards");
+    assert!(mono.sec < now.sec, "monotonic clock is not time since boot");
+
+    let mut time = TimeSpec::default();
+    assert!(clock_gettime(42, &mut time) < 0, "bad clock id accepted");
+    println!("rtc passed!");
+    0
+}
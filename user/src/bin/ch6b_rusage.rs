@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+//! 写入一个 1 MiB 的惰性映射，RSS 和次要缺页次数都应增加约 256 页；
+//! 回收子进程后，它的缺页次数计入 RUSAGE_CHILDREN。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, getrusage, mmap, open, read, waitpid, OpenFlags, RUsage, RUSAGE_CHILDREN, RUSAGE_SELF};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 256;
+/// 栈增长、页表等带来的误差
+const SLACK: isize = 16;
+
+fn rusage(who: isize) -> RUsage {
+    let mut usage = RUsage::default();
+    assert_eq!(getrusage(who, &mut usage), 0);
+    usage
+}
+
+/// /proc/self/stat 的第 24 个字段：驻留页数
+fn rss_pages() -> isize {
+    let fd = open("/proc/self/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "open /proc/self/stat failed");
+    let mut buf = [0u8; 256];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    assert!(len > 0);
+    let stat = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    // comm 可能包含空格，从右括号之后开始数，状态是第 3 个字段
+    let rest = &stat[stat.rfind(')').unwrap() + 2..];
+    rest.split_whitespace().nth(24 - 3).unwrap().parse().unwrap()
+}
+
+fn touch_pages() {
+    let start = mmap(0, PAGES * PAGE_SIZE, 3);
+    assert!(start > 0, "mmap failed");
+    for i in 0..PAGES {
+        unsafe { ((start as usize + i * PAGE_SIZE) as *mut u64).write_volatile(i as u64) };
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let before = rusage(RUSAGE_SELF);
+    let rss_before = rss_pages();
+    touch_pages();
+    let after = rusage(RUSAGE_SELF);
+    let rss_after = rss_pages();
+    let minflt = after.ru_minflt - before.ru_minflt;
+    println!(
+        "rss {} -> {} pages, minflt +{}, majflt +{}, maxrss {} -> {} KiB",
+        rss_before, rss_after, minflt, after.ru_majflt - before.ru_majflt, before.ru_maxrss, after.ru_maxrss
+    );
+    assert!((rss_after - rss_before - PAGES as isize).abs() <= SLACK, "rss did not grow by {} pages", PAGES);
+    assert!((minflt - PAGES as isize).abs() <= SLACK, "minor faults did not grow by {}", PAGES);
+    assert!(after.ru_maxrss - before.ru_maxrss >= (PAGES * PAGE_SIZE / 1024) as isize - SLACK * 4);
+
+    let pid = fork();
+    if pid == 0 {
+        touch_pages();
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let children = rusage(RUSAGE_CHILDREN);
+    println!("children: minflt {}, maxrss {} KiB", children.ru_minflt, children.ru_maxrss);
+    assert!(children.ru_minflt >= PAGES as isize - SLACK, "child faults not accumulated");
+    println!("rusage passed!");
+    0
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+//! lseek 对不同类型的文件：普通文件按字节定位，目录按目录项序号定位 getdents64
+//! 的读取位置，管道不支持定位。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, getdents64, lseek, open, pipe, read, unlink, write, OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+const EINVAL: isize = 22;
+const ESPIPE: isize = 29;
+const TEST_FILE: &str = "seek_test\0";
+
+/// 从目录的当前位置读到末尾，返回读到的文件名。缓冲区很小，目录项会分多次读取
+fn read_names(fd: usize) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = getdents64(fd, &mut buf);
+        assert!(len >= 0, "getdents64 failed: {}", len);
+        if len == 0 {
+            return names;
+        }
+        let mut pos = 0;
+        while pos < len as usize {
+            let record = &buf[pos..];
+            let reclen = u16::from_le_bytes([record[16], record[17]]) as usize;
+            let name = &record[19..reclen];
+            let name_len = name.iter().position(|&b| b == 0).unwrap();
+            names.push(String::from_utf8(name[..name_len].to_vec()).unwrap());
+            pos += reclen;
+        }
+    }
+}
+
+fn seek_file() {
+    let fd = open(TEST_FILE, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::RDWR);
+    assert!(fd >= 0, "failed to create {}", TEST_FILE);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello, world"), 12);
+    let mut buf = [0u8; 5];
+    assert_eq!(lseek(fd, 7, SEEK_SET), 7);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf, b"world");
+    assert_eq!(lseek(fd, -12, SEEK_CUR), 0);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf, b"hello");
+    assert_eq!(lseek(fd, -5, SEEK_END), 7);
+    assert_eq!(read(fd, &mut buf), 5);
+    assert_eq!(&buf, b"world");
+    assert_eq!(lseek(fd, -1, SEEK_SET), -EINVAL);
+    assert_eq!(lseek(fd, 0, 3), -EINVAL);
+    close(fd);
+    unlink(TEST_FILE);
+}
+
+fn seek_dir() {
+    let fd = open("/\0", OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open /");
+    let fd = fd as usize;
+    let names = read_names(fd);
+    assert!(names.len() > 1, "root directory listed {} entries", names.len());
+    // 回到开头重新读取，得到同样的目录项
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(read_names(fd), names);
+    // 从第二个目录项开始读取
+    assert_eq!(lseek(fd, 1, SEEK_SET), 1);
+    assert_eq!(read_names(fd), names[1..]);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), names.len() as isize);
+    assert_eq!(lseek(fd, 0, SEEK_END), -EINVAL);
+    close(fd);
+    println!("listed {} entries in /", names.len());
+}
+
+fn seek_pipe() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(lseek(fds[0], 0, SEEK_SET), -ESPIPE);
+    assert_eq!(lseek(fds[1], 0, SEEK_CUR), -ESPIPE);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    seek_file();
+    seek_dir();
+    seek_pipe();
+    println!("seek passed!");
+    0
+}
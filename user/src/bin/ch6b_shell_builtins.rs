@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+//! 用脚本模式运行 shell，检查内建命令 cd、export 和 exit：cd 改变的是 shell 自己的工作目录，
+//! 之后 `./prog` 从新目录解析；export 的变量传给之后启动的命令；exit 以给定的退出码结束脚本。
+//! 本程序复制一份到 builtins_dir/prog，以 report 参数运行时输出工作目录和 GREETING 变量
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exec, fork, getenv, getpwd, mkdir, open, read, unlink, waitpid, write, OpenFlags};
+
+const SCRIPT: &str = "builtins_script.sh\0";
+const PROG: &str = "builtins_dir/prog\0";
+const REPORT: &str = "builtins_dir/report.txt\0";
+const AFTER_EXIT: &str = "builtins_after_exit.txt\0";
+
+const SCRIPT_TEXT: &str = "\
+export GREETING=hello
+cd builtins_dir
+./prog report > report.txt
+cd builtins_no_such_dir
+exit 7
+ch6b_shell_builtins report > builtins_after_exit.txt
+";
+
+/// 读出文件的全部内容，打不开时返回 None
+fn read_file(path: &str) -> Option<Vec<u8>> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    Some(data)
+}
+
+fn write_file(path: &str, data: &[u8]) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0, "failed to create {}", path);
+    assert_eq!(write(fd as usize, data), data.len() as isize);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let mut cwd = String::new();
+    assert_eq!(getpwd(&mut cwd, 128), 0);
+    if argc > 1 && argv[1] == "report" {
+        println!("cwd={}", cwd);
+        println!("GREETING={}", getenv("GREETING").unwrap_or("<unset>"));
+        return 0;
+    }
+    mkdir("builtins_dir\0");
+    let image = read_file("ch6b_shell_builtins\0").expect("cannot read own executable");
+    write_file(PROG, &image);
+    write_file(SCRIPT, SCRIPT_TEXT.as_bytes());
+    unlink(AFTER_EXIT);
+
+    let pid = fork();
+    if pid == 0 {
+        exec(
+            "ch6b_user_shell.elf\0",
+            &["ch6b_user_shell.elf\0".as_ptr(), SCRIPT.as_ptr(), core::ptr::null::<u8>()],
+        );
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    // exit 7 结束了脚本，后面的命令没有运行
+    assert_eq!((exit_code >> 8) & 0xff, 7);
+    assert!(read_file(AFTER_EXIT).is_none());
+
+    let report = String::from_utf8(read_file(REPORT).expect("./prog did not run in builtins_dir")).unwrap();
+    let expected_cwd = format!("{}/builtins_dir", cwd.trim_end_matches('/'));
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines, [format!("cwd={}", expected_cwd).as_str(), "GREETING=hello"]);
+
+    // shell 的 cd 不影响本进程
+    let mut after = String::new();
+    assert_eq!(getpwd(&mut after, 128), 0);
+    assert_eq!(after, cwd);
+
+    for file in [REPORT, PROG, SCRIPT] {
+        unlink(file);
+    }
+    println!("shell builtins passed!");
+    0
+}
@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+//! 用脚本模式运行 shell，检查 `&` 后台作业、`jobs`、`fg %n` 以及作业结束时的通知。
+//! shell 的标准输出重定向到文件，脚本中的命令就是本程序自己，由第一个参数选择角色：
+//! - `reader FIFO`：等到 shell 的输出中出现自己的命令行（`fg` 在等待前显示它），再读 FIFO 直到写者退出
+//! - `writer FIFO`：打开 FIFO 的写端，阻塞到有读者打开后立即退出
+//! - `exit N`：以 N 退出
+//!
+//! 两个后台作业只在 `fg %1` 之后才能结束，不依赖进程启动和调度的快慢
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, dup3, exec, fork, mkfifo, open, read, sleep_blocking, unlink, waitpid, write, OpenFlags, STDOUT,
+};
+
+const SCRIPT: &str = "jobs_script.sh\0";
+const OUTPUT: &str = "jobs_out.txt\0";
+const FIFO: &str = "/tmp/jobs_fifo\0";
+
+const SCRIPT_TEXT: &str = "\
+ch6b_shell_jobs reader /tmp/jobs_fifo &
+ch6b_shell_jobs writer /tmp/jobs_fifo&
+jobs
+fg %1
+jobs
+fg %9
+ch6b_shell_jobs exit 0
+";
+
+/// 读出文件的全部内容
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).expect("file is not UTF-8")
+}
+
+/// `reader`：等到 `fg` 显示了自己的命令行，再打开 FIFO 读到 EOF。
+/// 写者只有退出时才关闭写端，读到 EOF 时它已经可以被回收
+fn reader(argv: &[&str]) -> i32 {
+    let command = argv.join(" ");
+    while !read_file(OUTPUT).lines().any(|line| line == command) {
+        sleep_blocking(10);
+    }
+    let path = format!("{}\0", argv[2]);
+    let fd = open(&path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 16];
+    while read(fd as usize, &mut buf) > 0 {}
+    close(fd as usize);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 2 {
+        match argv[1] {
+            "reader" => return reader(argv),
+            // 写端留给退出时关闭
+            "writer" => return if open(&format!("{}\0", argv[2]), OpenFlags::WRONLY) >= 0 { 0 } else { 1 },
+            "exit" => return argv[2].parse().expect("bad exit code"),
+            _ => panic!("unknown role {}", argv[1]),
+        }
+    }
+    unlink(FIFO);
+    assert_eq!(mkfifo(FIFO), 0);
+    unlink(OUTPUT);
+    let fd = open(SCRIPT, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, SCRIPT_TEXT.as_bytes()), SCRIPT_TEXT.len() as isize);
+    close(fd as usize);
+
+    let pid = fork();
+    if pid == 0 {
+        let out = open(OUTPUT, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+        assert!(out >= 0);
+        dup3(out as usize, STDOUT);
+        close(out as usize);
+        exec(
+            "ch6b_user_shell.elf\0",
+            &["ch6b_user_shell.elf\0".as_ptr(), SCRIPT.as_ptr(), core::ptr::null::<u8>()],
+        );
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    let output = read_file(OUTPUT);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 6);
+    // 后台作业启动时报告编号和 pid，shell 不等待它们结束
+    assert!(lines[0].starts_with("[1] "));
+    assert!(lines[1].starts_with("[2] "));
+    // 读者还没看到 fg 的输出，写者还在等待读者
+    assert_eq!(lines[2], "[1] Running ch6b_shell_jobs reader /tmp/jobs_fifo");
+    assert_eq!(lines[3], "[2] Running ch6b_shell_jobs writer /tmp/jobs_fifo");
+    // fg 先显示命令再等待它结束；读者读到 EOF 时写者已经退出，运行下一行之前报告
+    assert_eq!(lines[4], "ch6b_shell_jobs reader /tmp/jobs_fifo");
+    assert_eq!(lines[5], "[2] Done ch6b_shell_jobs writer /tmp/jobs_fifo");
+
+    unlink(SCRIPT);
+    unlink(OUTPUT);
+    unlink(FIFO);
+    println!("shell jobs passed!");
+    0
+}
@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+
+//! 用脚本模式运行 shell，检查 `<`、`>`、`>>`、`2>` 重定向以及和管道的组合。
+//! 脚本中的命令就是本程序自己，按第一个参数充当 echo、cat 和向标准错误输出的 err
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exec, fork, open, read, unlink, waitpid, write, OpenFlags, STDERR, STDIN, STDOUT};
+
+const SCRIPT: &str = "redir_script.sh\0";
+
+/// 脚本用到的文件，测试结束时删除
+const FILES: [&str; 5] = ["redir_out.txt\0", "redir_copy.txt\0", "redir_err.txt\0", "redir_done.txt\0", SCRIPT];
+
+const SCRIPT_TEXT: &str = "\
+# 覆盖写入，再不带空格地追加
+ch6b_shell_redir echo hello > redir_out.txt
+ch6b_shell_redir echo world>>redir_out.txt
+ch6b_shell_redir cat <redir_out.txt | ch6b_shell_redir cat > redir_copy.txt
+ch6b_shell_redir err oops 2> redir_err.txt
+# 打不开的文件：报告错误，不运行命令，脚本继续
+ch6b_shell_redir cat < redir_missing.txt
+ch6b_shell_redir echo lost > /redir_no_such_dir/x
+ch6b_shell_redir echo done > redir_done.txt
+";
+
+/// 把参数用空格连接后加上换行写到 `fd`
+fn write_args(fd: usize, args: &[&str]) {
+    let mut line = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            line.push(' ');
+        }
+        line.push_str(arg);
+    }
+    line.push('\n');
+    write(fd, line.as_bytes());
+}
+
+/// 读出文件的全部内容，打不开时返回 None
+fn read_file(path: &str) -> Option<String> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    Some(String::from_utf8(data).expect("file is not UTF-8"))
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        match argv[1] {
+            "echo" => write_args(STDOUT, &argv[2..]),
+            "err" => write_args(STDERR, &argv[2..]),
+            "cat" => {
+                let mut buf = [0u8; 64];
+                loop {
+                    let len = read(STDIN, &mut buf);
+                    if len <= 0 {
+                        break;
+                    }
+                    write(STDOUT, &buf[..len as usize]);
+                }
+            }
+            mode => panic!("unknown mode {}", mode),
+        }
+        return 0;
+    }
+    for file in FILES.iter() {
+        unlink(file);
+    }
+    let fd = open(SCRIPT, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, SCRIPT_TEXT.as_bytes()), SCRIPT_TEXT.len() as isize);
+    close(fd as usize);
+
+    let pid = fork();
+    if pid == 0 {
+        exec(
+            "ch6b_user_shell.elf\0",
+            &["ch6b_user_shell.elf\0".as_ptr(), SCRIPT.as_ptr(), core::ptr::null::<u8>()],
+        );
+        panic!("exec failed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    // 最后一行命令成功
+    assert_eq!(exit_code, 0);
+
+    assert_eq!(read_file("redir_out.txt\0").as_deref(), Some("hello\nworld\n"));
+    assert_eq!(read_file("redir_copy.txt\0").as_deref(), Some("hello\nworld\n"));
+    assert_eq!(read_file("redir_err.txt\0").as_deref(), Some("oops\n"));
+    assert_eq!(read_file("redir_done.txt\0").as_deref(), Some("done\n"));
+    // 打不开的重定向不会创建文件
+    assert!(read_file("redir_missing.txt\0").is_none());
+
+    for file in FILES.iter() {
+        unlink(file);
+    }
+    println!("shell redirection passed!");
+    0
+}
@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, mmap_shared, shmat, shmctl, shmdt, shmget, waitpid, yield_, IPC_CREAT, IPC_PRIVATE,
+    IPC_RMID,
+};
+
+const PAGE_SIZE: usize = 4096;
+const MESSAGES: usize = 10_000;
+const SLOTS: usize = 64;
+
+/// 放在共享内存中的单生产者单消费者环形缓冲区
+#[repr(C)]
+struct Ring {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    slots: [u64; SLOTS],
+}
+
+fn message(i: usize) -> u64 {
+    (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ 0x5555
+}
+
+/// 共享匿名映射在 fork 后由父子进程共享
+fn shared_anonymous() {
+    let addr = mmap_shared(0, PAGE_SIZE, 3);
+    assert!(addr > 0);
+    let value = unsafe { &mut *(addr as *mut usize) };
+    *value = 1;
+    let pid = fork();
+    if pid == 0 {
+        *value = 42;
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(*value, 42, "child write not visible in parent");
+}
+
+fn consume(ring: &Ring) {
+    for i in 0..MESSAGES {
+        let tail = ring.tail.load(Ordering::Relaxed);
+        while ring.head.load(Ordering::Acquire) == tail {
+            yield_();
+        }
+        assert_eq!(ring.slots[tail % SLOTS], message(i), "message {} corrupted", i);
+        ring.tail.store(tail + 1, Ordering::Release);
+    }
+}
+
+fn produce(ring: &mut Ring) {
+    for i in 0..MESSAGES {
+        let head = ring.head.load(Ordering::Relaxed);
+        while head - ring.tail.load(Ordering::Acquire) == SLOTS {
+            yield_();
+        }
+        ring.slots[head % SLOTS] = message(i);
+        ring.head.store(head + 1, Ordering::Release);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    shared_anonymous();
+    let id = shmget(IPC_PRIVATE, core::mem::size_of::<Ring>(), IPC_CREAT);
+    assert!(id > 0);
+    let addr = shmat(id as usize, 0, 0);
+    assert!(addr > 0);
+    // 删除后段仍然有效，直到最后一个映射被解除
+    assert_eq!(shmctl(id as usize, IPC_RMID), 0);
+    let ring = unsafe { &mut *(addr as *mut Ring) };
+    let pid = fork();
+    if pid == 0 {
+        // 子进程退出时自动卸载
+        consume(ring);
+        return 0;
+    }
+    produce(ring);
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    assert_eq!(ring.tail.load(Ordering::Relaxed), MESSAGES);
+    assert_eq!(shmdt(addr as usize), 0);
+    println!("shm_ring passed!");
+    0
+}
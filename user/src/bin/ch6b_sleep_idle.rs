@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+//! 父子进程同时睡眠，就绪队列中没有可运行的任务（shell 的 waitpid 除外），
+//! 内核必须停在 wfi 上等待时钟中断，而不是因为取不到任务而崩溃。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, sleep_blocking, waitpid};
+
+const SLEEP_MS: usize = 500;
+
+fn sleep_checked(ms: usize) {
+    let start = get_time();
+    sleep_blocking(ms);
+    let elapsed = get_time() - start;
+    assert!(elapsed >= ms as isize, "woke after {} ms, expected {} ms", elapsed, ms);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        sleep_checked(SLEEP_MS);
+        exit(0);
+    }
+    sleep_checked(SLEEP_MS + 100);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("sleep_idle passed!");
+    0
+}
@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid};
+
+/// 每层递归占用约 1 KiB 栈空间
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    frame[depth % 1024] = depth as u8;
+    core::hint::black_box(&mut frame);
+    if depth == 0 {
+        return frame[0] as usize;
+    }
+    recurse(depth - 1) + frame[depth % 1024] as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 约 1 MiB 的栈，应当通过自动增长满足
+    let pid = fork();
+    if pid == 0 {
+        recurse(1024);
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0, "1 MiB recursion should succeed");
+    // 超过栈大小限制，应当被内核杀死而不是挂起
+    let pid = fork();
+    if pid == 0 {
+        recurse(usize::MAX);
+        return 0;
+    }
+    waitpid(pid as usize, &mut exit_code);
+    assert_ne!(exit_code, 0, "unbounded recursion should be killed");
+    println!("stack_grow passed!");
+    0
+}
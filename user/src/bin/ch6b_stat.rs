@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+//! 每次 yield 都会经过一次上下文切换，/proc/stat 中的 ctxt 至少增加 yield 的次数，
+//! 被动让出处理器的次数记在 /proc/self/status 中。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, open, perf_dump, read, yield_, OpenFlags};
+
+const YIELDS: u64 = 100;
+
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0, "read failed");
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).expect("not utf-8")
+}
+
+/// 在 `text` 中找到以 `key` 开头的行，返回其后的数值
+fn field(text: &str, key: &str) -> u64 {
+    text.lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no {} in {}", key, text))
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let stat = read_file("/proc/stat\0");
+    let status = read_file("/proc/self/status\0");
+    let (ctxt, nivcsw) = (field(&stat, "ctxt "), field(&status, "nonvoluntary_ctxt_switches:"));
+    for _ in 0..YIELDS {
+        yield_();
+    }
+    let stat = read_file("/proc/stat\0");
+    let status = read_file("/proc/self/status\0");
+    print!("{}", stat);
+    let grown = field(&stat, "ctxt ") - ctxt;
+    assert!(grown >= YIELDS, "ctxt grew by {} over {} yields", grown, YIELDS);
+    assert!(field(&status, "nonvoluntary_ctxt_switches:") - nivcsw >= YIELDS);
+    assert!(field(&stat, "ready_max ") >= 1);
+    // yield 的系统调用号为 124
+    assert!(field(&stat, "syscall 124 ") >= YIELDS);
+    assert_eq!(perf_dump(), 0);
+    println!("stat passed!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+//! 标准输出和标准错误是两个不同的文件：都指向控制台，但关闭或重定向其中一个不影响另一个。
+//! 在 shell 中运行 `ch6b_stderr 2> err.txt` 时，err.txt 中只有写入 fd 2 的内容。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dup, fstat, write, Stat, StatMode, STDERR, STDOUT};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("this line goes to stdout");
+    eprintln!("this line goes to stderr");
+    let mut st = Stat::new();
+    assert_eq!(fstat(STDERR, &mut st), 0);
+    assert_eq!(st.mode, StatMode::CHR);
+    // 关闭 fd 2 的副本不影响 fd 2 本身
+    let copy = dup(STDERR);
+    assert!(copy > 2);
+    close(copy as usize);
+    assert_eq!(write(STDERR, b"stderr still open\n"), 18);
+    assert_eq!(write(STDOUT, b"stdout still open\n"), 18);
+    println!("stderr passed!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+//! 向标准输出写入任意字节：全部 256 个字节值，以及被拆成两次 write 的多字节 UTF-8 字符。
+//! 内核不能崩溃，每次 write 都应返回写入的全部长度。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{flush, write, STDOUT};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    flush();
+    let mut bytes = [0u8; 256];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    assert_eq!(write(STDOUT, &bytes), 256);
+    assert_eq!(write(STDOUT, b"\n"), 1);
+    // “中”的 UTF-8 编码拆成两次写入，终端上仍应显示为一个字符
+    let zhong = "中\n".as_bytes();
+    assert_eq!(write(STDOUT, &zhong[..1]), 1);
+    assert_eq!(write(STDOUT, &zhong[1..]), zhong.len() as isize - 1);
+    println!("stdout_bytes passed!");
+    0
+}
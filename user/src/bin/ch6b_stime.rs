@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+//! 系统时间只计入真正在内核中运行的任务：父进程在 nanosleep 中睡眠 1 秒，
+//! 期间子进程不停地调用 getpid。父进程的系统时间应接近 0，子进程则积累大量系统时间。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, getpid, sleep_blocking, times, waitpid};
+
+const SLEEP_MS: usize = 1000;
+/// 时钟频率，times 返回的时间以时钟周期为单位
+const CLOCK_FREQ: u64 = 12_500_000;
+
+/// 返回当前进程的用户态和内核态运行时间
+fn cpu_times() -> (u64, u64) {
+    let mut t = [0u64; 4];
+    assert!(times(&mut t) >= 0, "times failed");
+    (t[0], t[1])
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        let start = get_time();
+        let mut calls = 0usize;
+        while get_time() < start + SLEEP_MS as isize {
+            getpid();
+            calls += 1;
+        }
+        let (utime, stime) = cpu_times();
+        println!("getpid loop: {} calls, utime {} stime {}", calls, utime, stime);
+        // 至少 10 ms 的系统时间
+        assert!(stime > CLOCK_FREQ / 100, "syscall time was not charged to the caller");
+        exit(0);
+    }
+    let (_, stime_start) = cpu_times();
+    sleep_blocking(SLEEP_MS);
+    let (_, stime_end) = cpu_times();
+    let stime = stime_end - stime_start;
+    println!("sleeper: stime {} during a {} ms sleep", stime, SLEEP_MS);
+    // 睡眠期间其他任务运行的时间不应计入，系统时间应远小于睡眠时长的 1%
+    assert!(stime < CLOCK_FREQ * SLEEP_MS as u64 / 1000 / 100, "time spent in other tasks was charged to the sleeper");
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "getpid loop failed");
+    println!("stime passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+//! 申请并写满两倍于物理内存的匿名映射，再逐页校验内容。
+//! 需要以 FEATURES="tiny-mem swap" 构建内核，交换文件才能容纳被换出的页面。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, sysinfo, SysInfo};
+
+const PAGE_SIZE: usize = 4096;
+
+/// 第 `page` 页中第 `word` 个字应当保存的值
+fn pattern(page: usize, word: usize) -> u64 {
+    ((page as u64) << 32) | (word as u64 * 0x9e37_79b9 & 0xffff_ffff)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut info = SysInfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    let pages = info.totalram * 2;
+    let len = pages * PAGE_SIZE;
+    let start = mmap(0, len, 3);
+    assert!(start > 0, "mmap of {} pages failed", pages);
+    let start = start as usize;
+    // 每页写入首尾两个字，既触发换出，又能检查换入的页面是否完整
+    let last_word = PAGE_SIZE / 8 - 1;
+    for page in 0..pages {
+        let words = (start + page * PAGE_SIZE) as *mut u64;
+        unsafe {
+            words.write_volatile(pattern(page, 0));
+            words.add(last_word).write_volatile(pattern(page, last_word));
+        }
+    }
+    println!("swap: touched {} pages", pages);
+    for page in 0..pages {
+        let words = (start + page * PAGE_SIZE) as *const u64;
+        let (first, last) = unsafe { (words.read_volatile(), words.add(last_word).read_volatile()) };
+        assert_eq!(first, pattern(page, 0), "page {} corrupted", page);
+        assert_eq!(last, pattern(page, last_word), "page {} corrupted", page);
+    }
+    assert_eq!(munmap(start, len), 0);
+    println!("swap passed!");
+    0
+}
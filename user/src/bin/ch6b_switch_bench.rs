@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+//! 上下文切换开销：父子进程经两个管道来回传递一个字节，每个来回包含两次进程切换，
+//! 每次切换都要进出用户地址空间。用 `bench` 构建的内核分别以默认参数和 `asid=off` 启动运行本程序，
+//! 比较带 ASID 的切换与每次都刷新 TLB 的切换。
+//! 同时从 /proc/stat 和 /proc/self/status 读出期间的上下文切换次数：读者在等待队列上阻塞，
+//! 每个来回中父进程最多主动让出一次处理器，不会像轮询那样反复切换
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exit, fork, get_time, open, pipe, read, waitpid, write, OpenFlags};
+
+const ROUNDS: usize = 10000;
+
+fn read_file(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0, "read failed");
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).expect("not utf-8")
+}
+
+/// 在文件 `path` 中找到以 `key` 开头的行，返回其后的数值
+fn field(path: &str, key: &str) -> usize {
+    let text = read_file(path);
+    text.lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_else(|| panic!("no {} in {}", key, path))
+}
+
+/// (系统的上下文切换总数, 本进程主动让出处理器的次数)
+fn switches() -> (usize, usize) {
+    (
+        field("/proc/stat\0", "ctxt "),
+        field("/proc/self/status\0", "voluntary_ctxt_switches:"),
+    )
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut ping = [0usize; 2];
+    let mut pong = [0usize; 2];
+    assert_eq!(pipe(&mut ping), 0);
+    assert_eq!(pipe(&mut pong), 0);
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    let mut byte = [0u8; 1];
+    if pid == 0 {
+        close(ping[1]);
+        close(pong[0]);
+        for _ in 0..ROUNDS {
+            assert_eq!(read(ping[0], &mut byte), 1);
+            assert_eq!(write(pong[1], &byte), 1);
+        }
+        exit(0);
+    }
+    close(ping[0]);
+    close(pong[1]);
+    let (ctxt0, voluntary0) = switches();
+    let start = get_time();
+    for i in 0..ROUNDS {
+        byte[0] = i as u8;
+        assert_eq!(write(ping[1], &byte), 1);
+        assert_eq!(read(pong[0], &mut byte), 1);
+        assert_eq!(byte[0], i as u8);
+    }
+    let ms = get_time() - start;
+    let (ctxt1, voluntary1) = switches();
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!(
+        "switch_bench: {} round trips in {} ms ({} us each)",
+        ROUNDS,
+        ms,
+        ms as usize * 1000 / ROUNDS
+    );
+    let (ctxt, voluntary) = (ctxt1 - ctxt0, voluntary1 - voluntary0);
+    println!(
+        "switch_bench: {} context switches ({}.{:02} per round trip), parent blocked {} times",
+        ctxt,
+        ctxt / ROUNDS,
+        ctxt % ROUNDS * 100 / ROUNDS,
+        voluntary
+    );
+    assert!(voluntary <= ROUNDS + 10, "parent gave up the cpu {} times in {} round trips", voluntary, ROUNDS);
+    println!("switch_bench passed!");
+    0
+}
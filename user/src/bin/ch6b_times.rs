@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+//! 纯用户态的空转只增加 utime，频繁的系统调用主要增加 stime，
+//! 子进程被回收后它的时间计入 cutime/cstime。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, times, waitpid};
+
+const SPINS: u64 = 20_000_000;
+const SYSCALLS: usize = 20_000;
+
+fn read_times() -> [u64; 4] {
+    let mut t = [0u64; 4];
+    assert!(times(&mut t) >= 0, "times failed");
+    t
+}
+
+fn spin() {
+    let mut x: u64 = 0;
+    for i in 0..SPINS {
+        x = unsafe { core::ptr::read_volatile(&x) }.wrapping_add(i);
+    }
+}
+
+fn syscall_loop() {
+    for _ in 0..SYSCALLS {
+        getpid();
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let t0 = read_times();
+    spin();
+    let t1 = read_times();
+    let (du, ds) = (t1[0] - t0[0], t1[1] - t0[1]);
+    println!("user spin: utime +{}, stime +{}", du, ds);
+    assert!(du > ds * 10, "user spin charged to stime");
+
+    syscall_loop();
+    let t2 = read_times();
+    let (du, ds) = (t2[0] - t1[0], t2[1] - t1[1]);
+    println!("syscall loop: utime +{}, stime +{}", du, ds);
+    assert!(ds > du, "syscalls charged to utime");
+
+    let pid = fork();
+    if pid == 0 {
+        spin();
+        syscall_loop();
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let t3 = read_times();
+    println!("reaped child: cutime {}, cstime {}", t3[2], t3[3]);
+    assert!(t3[2] > 0 && t3[3] > 0, "child times not accumulated");
+    println!("times passed!");
+    0
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+//! 主线程的 TLS 块由 exec 按 PT_TLS 段初始化，用 CLONE_SETTLS 创建的线程使用自己的块，
+//! 两个线程交替累加同一个 `#[thread_local]` 计数器，各自看到的值互不干扰。
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use user_lib::{clone_thread, yield_};
+
+const MAIN_ROUNDS: u64 = 100;
+const WORKER_ROUNDS: u64 = 300;
+const MAGIC: u64 = 0x5a5a_1234;
+/// 两个 TLS 变量共 16 字节，多复制一些也无妨
+const TLS_SIZE: usize = 64;
+const STACK_SIZE: usize = 16384;
+
+/// 位于 .tdata，检查初始值是否被复制到 TLS 块
+#[thread_local]
+static mut TLS_MAGIC: u64 = MAGIC;
+/// 位于 .tbss
+#[thread_local]
+static mut COUNTER: u64 = 0;
+
+#[repr(C, align(64))]
+struct TlsBlock([u8; TLS_SIZE]);
+#[repr(C, align(16))]
+struct Stack([u8; STACK_SIZE]);
+
+static mut WORKER_TLS: TlsBlock = TlsBlock([0; TLS_SIZE]);
+static mut WORKER_STACK: Stack = Stack([0; STACK_SIZE]);
+static WORKER_DONE: AtomicBool = AtomicBool::new(false);
+static WORKER_COUNT: AtomicU64 = AtomicU64::new(0);
+
+fn tp() -> usize {
+    let tp: usize;
+    unsafe { core::arch::asm!("mv {}, tp", out(reg) tp) };
+    tp
+}
+
+fn magic() -> u64 {
+    unsafe { core::ptr::addr_of!(TLS_MAGIC).read_volatile() }
+}
+
+fn count(rounds: u64) -> u64 {
+    for _ in 0..rounds {
+        unsafe { COUNTER += 1 };
+        yield_();
+    }
+    unsafe { COUNTER }
+}
+
+extern "C" fn worker(rounds: usize) -> i32 {
+    assert_eq!(magic(), MAGIC, "worker tdata not copied");
+    WORKER_COUNT.store(count(rounds as u64), Ordering::Release);
+    WORKER_DONE.store(true, Ordering::Release);
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let main_tp = tp();
+    assert!(main_tp != 0, "tp not initialized by exec");
+    assert_eq!(magic(), MAGIC, "main tdata not copied");
+    // 在修改计数器之前，以主线程的 TLS 块作为新线程的初始映像
+    let worker_tls = unsafe {
+        WORKER_TLS.0.copy_from_slice(core::slice::from_raw_parts(main_tp as *const u8, TLS_SIZE));
+        WORKER_TLS.0.as_ptr() as usize
+    };
+    // 主线程改写自己的 .tdata 变量，新线程仍应看到初始值
+    unsafe { core::ptr::addr_of_mut!(TLS_MAGIC).write_volatile(0) };
+    let stack_top = unsafe { WORKER_STACK.0.as_ptr() as usize + STACK_SIZE };
+    let tid = clone_thread(worker, WORKER_ROUNDS as usize, stack_top, worker_tls);
+    assert!(tid > 0, "clone failed: {}", tid);
+    let mine = count(MAIN_ROUNDS);
+    while !WORKER_DONE.load(Ordering::Acquire) {
+        yield_();
+    }
+    assert_eq!(tp(), main_tp, "tp clobbered across traps");
+    assert_eq!(magic(), 0, "worker wrote to main's tdata");
+    println!("main counter {}, worker counter {}", mine, WORKER_COUNT.load(Ordering::Acquire));
+    assert_eq!(mine, MAIN_ROUNDS);
+    assert_eq!(WORKER_COUNT.load(Ordering::Acquire), WORKER_ROUNDS);
+    println!("tls passed!");
+    0
+}
@@ -0,0 +1,168 @@
+#![no_std]
+#![no_main]
+
+//! 在 FAT 的目录和 /tmp（tmpfs）上执行同样的文件操作（open、read、write、lseek、fstat、
+//! mkdir、getdents64、unlink），两边的结果应当相同；然后挂载一个只有 8 KiB 的 tmpfs，
+//! 写满后 write 返回 -ENOSPC，删除文件后空间可以重新使用。
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, fstat, getdents64, lseek, mkdir, mount, open, read, umount, unlink, write, OpenFlags, Stat, StatMode,
+    SEEK_END, SEEK_SET,
+};
+
+const EBUSY: isize = 16;
+const ENOSPC: isize = 28;
+const ENOTEMPTY: isize = 39;
+/// 小 tmpfs 的挂载点和大小
+const SMALL: &str = "/tmp/small";
+const SMALL_SIZE: usize = 8192;
+
+/// 读出目录中除 "." 和 ".." 以外的文件名，按名字排序
+fn list_dir(path: &str) -> Vec<String> {
+    let fd = open(&format!("{}\0", path), OpenFlags::RDONLY);
+    assert!(fd >= 0, "failed to open {}", path);
+    let mut names = Vec::new();
+    let mut buf = [0u8; 128];
+    loop {
+        let len = getdents64(fd as usize, &mut buf);
+        assert!(len >= 0, "getdents64 failed: {}", len);
+        if len == 0 {
+            break;
+        }
+        let mut pos = 0;
+        while pos < len as usize {
+            let record = &buf[pos..];
+            let reclen = u16::from_le_bytes([record[16], record[17]]) as usize;
+            let name = &record[19..reclen];
+            let name_len = name.iter().position(|&b| b == 0).unwrap();
+            let name = String::from_utf8(name[..name_len].to_vec()).unwrap();
+            if name != "." && name != ".." {
+                names.push(name);
+            }
+            pos += reclen;
+        }
+    }
+    close(fd as usize);
+    names.sort();
+    names
+}
+
+fn stat_of(path: &str) -> Option<Stat> {
+    let fd = open(&format!("{}\0", path), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut st = Stat::new();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    close(fd as usize);
+    Some(st)
+}
+
+/// 在目录 `base` 下执行一组文件操作，返回观察到的结果，用于比较不同的文件系统
+fn exercise(base: &str) -> Vec<String> {
+    let dir = format!("{}/work", base);
+    let file = format!("{}/data", dir);
+    let sub = format!("{}/sub", dir);
+    let mut log = Vec::new();
+    assert_eq!(mkdir(&format!("{}\0", dir)), 0, "mkdir {}", dir);
+    assert_eq!(mkdir(&format!("{}\0", sub)), 0, "mkdir {}", sub);
+
+    let fd = open(&format!("{}\0", file), OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd >= 0, "failed to create {}", file);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello, world"), 12);
+    assert_eq!(lseek(fd, 7, SEEK_SET), 7);
+    let mut buf = [0u8; 16];
+    let len = read(fd, &mut buf);
+    log.push(format!("read {:?}", core::str::from_utf8(&buf[..len as usize]).unwrap()));
+    log.push(format!("end {}", lseek(fd, 0, SEEK_END)));
+    // 从末尾之后写入，中间留下空洞
+    assert_eq!(lseek(fd, 16, SEEK_SET), 16);
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 12, SEEK_SET), 12);
+    log.push(format!("read after hole {}", read(fd, &mut buf)));
+    close(fd);
+
+    let st = stat_of(&file).unwrap();
+    log.push(format!("file mode {:?} size {}", st.mode, st.size));
+    log.push(format!("dir mode {:?}", stat_of(&sub).unwrap().mode));
+    log.push(format!("ls {:?}", list_dir(&dir)));
+
+    // 以 O_TRUNC 打开后文件为空
+    let fd = open(&format!("{}\0", file), OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    close(fd as usize);
+    log.push(format!("truncated size {}", stat_of(&file).unwrap().size));
+
+    assert_eq!(unlink(&format!("{}\0", file)), 0);
+    log.push(format!("after unlink {}", stat_of(&file).is_some()));
+    log.push(format!("ls {:?}", list_dir(&dir)));
+    assert_eq!(unlink(&format!("{}\0", sub)), 0);
+    assert_eq!(unlink(&format!("{}\0", dir)), 0);
+    log.push(format!("dir removed {}", stat_of(&dir).is_none()));
+    log
+}
+
+/// 在小 tmpfs 上写满空间
+fn fill_small() {
+    assert_eq!(mkdir(&format!("{}\0", SMALL)), 0);
+    assert_eq!(mount("tmpfs\0", &format!("{}\0", SMALL), "tmpfs\0", "size=8k\0"), 0);
+    let path = format!("{}/fill\0", SMALL);
+    let fd = open(&path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let chunk = [0x5au8; 3000];
+    let mut written = 0;
+    loop {
+        let len = write(fd as usize, &chunk);
+        if len == -ENOSPC {
+            break;
+        }
+        assert!(len > 0, "write failed: {}", len);
+        written += len as usize;
+    }
+    close(fd as usize);
+    assert_eq!(written, SMALL_SIZE);
+    // 挂载点不能删除
+    assert_eq!(unlink("/tmp\0"), -EBUSY);
+    // 删除文件归还空间
+    assert_eq!(unlink(&path), 0);
+    let fd = open(&path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert_eq!(write(fd as usize, &chunk), chunk.len() as isize);
+    close(fd as usize);
+    assert_eq!(unlink(&path), 0);
+    assert_eq!(umount(&format!("{}\0", SMALL)), 0);
+    assert_eq!(unlink(&format!("{}\0", SMALL)), 0);
+    println!("wrote {} bytes into a {}-byte tmpfs before ENOSPC", written, SMALL_SIZE);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let st = stat_of("/tmp").expect("/tmp is not mounted");
+    assert_eq!(st.mode.file_type(), StatMode::DIR);
+    assert_eq!(mkdir("/tmp/busy\0"), 0);
+    let fd = open("/tmp/busy/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    close(fd as usize);
+    assert_eq!(unlink("/tmp/busy\0"), -ENOTEMPTY);
+    assert_eq!(unlink("/tmp/busy/file\0"), 0);
+    assert_eq!(unlink("/tmp/busy\0"), 0);
+
+    mkdir("/tmpfs_cmp\0");
+    let fat = exercise("/tmpfs_cmp");
+    let tmp = exercise("/tmp");
+    unlink("/tmpfs_cmp\0");
+    for (fat, tmp) in fat.iter().zip(tmp.iter()) {
+        println!("{}", tmp);
+        assert_eq!(fat, tmp, "FAT and tmpfs disagree");
+    }
+    assert_eq!(fat.len(), tmp.len());
+    fill_small();
+    println!("tmpfs passed!");
+    0
+}
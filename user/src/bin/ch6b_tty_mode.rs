@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+//! 控制台终端的属性和窗口大小：默认处于规范模式并回显；tcsetattr 切换到原始模式再恢复后
+//! tcgetattr 读到设置的值；fd 0–2 和 /dev/tty 是同一个终端；非规范模式下 VMIN = 0、VTIME > 0 的
+//! read 在没有输入时超时返回 0；不是终端的文件（管道）的 ioctl 返回 ENOTTY。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    cfmakeraw, close, fstat, get_time, get_winsize, ioctl, open, pipe, read, tcgetattr, tcsetattr, OpenFlags,
+    Stat, StatMode, Termios, WinSize, ECHO, ICANON, ISIG, STDIN, STDOUT, TCGETS, VMIN, VTIME,
+};
+
+const ENOTTY: isize = 25;
+
+fn get(fd: usize) -> Termios {
+    let mut termios = Termios::default();
+    assert_eq!(tcgetattr(fd, &mut termios), 0);
+    termios
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let saved = get(STDIN);
+    assert_eq!(saved.lflag & (ICANON | ECHO | ISIG), ICANON | ECHO | ISIG, "console should start in cooked mode");
+
+    let mut raw = saved;
+    cfmakeraw(&mut raw);
+    assert_eq!(tcsetattr(STDIN, &raw), 0);
+    // stdin、stdout 和 /dev/tty 是同一个终端
+    assert_eq!(get(STDOUT).lflag & (ICANON | ECHO | ISIG), 0);
+    let tty = open("/dev/tty\0", OpenFlags::RDWR);
+    assert!(tty >= 0, "failed to open /dev/tty");
+    let tty = tty as usize;
+    assert_eq!(get(tty).lflag & ICANON, 0);
+    let mut st = Stat::new();
+    assert_eq!(fstat(tty, &mut st), 0);
+    assert_eq!(st.mode, StatMode::CHR);
+
+    // 没有输入时 0.2 秒后超时
+    let mut timed = raw;
+    timed.cc[VMIN] = 0;
+    timed.cc[VTIME] = 2;
+    assert_eq!(tcsetattr(tty, &timed), 0);
+    let start = get_time();
+    assert_eq!(read(STDIN, &mut [0u8; 8]), 0);
+    let waited = get_time() - start;
+    assert!(waited >= 200, "read returned after {} ms", waited);
+
+    assert_eq!(tcsetattr(tty, &saved), 0);
+    assert_eq!(get(STDIN).lflag & (ICANON | ECHO), ICANON | ECHO);
+
+    let mut winsize = WinSize::default();
+    assert_eq!(get_winsize(tty, &mut winsize), 0);
+    assert!(winsize.row > 0 && winsize.col > 0);
+    println!("window size {}x{}", winsize.col, winsize.row);
+    close(tty);
+
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut termios = Termios::default();
+    assert_eq!(ioctl(fds[0], TCGETS, &mut termios as *mut Termios as usize), -ENOTTY);
+    close(fds[0]);
+    close(fds[1]);
+    println!("tty_mode passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+//! UDP 回显服务：绑定所有地址的 7 号端口，把收到的每个数据报原样发回来源。
+//! 参数给出回显的数据报数，之后退出；没有参数时一直运行。
+//! 在 QEMU 用户网络中，主机的 UDP 7007 端口转发到这里，可以用 `nc -u localhost 7007` 测试。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{bind, close, recvfrom, sendto, socket, SockAddrIn, AF_INET, SOCK_DGRAM};
+
+const ECHO_PORT: u16 = 7;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let count = if argc > 1 { argv[1].parse::<usize>().ok() } else { None };
+    let fd = socket(AF_INET, SOCK_DGRAM, 0);
+    assert!(fd >= 0, "socket failed: {}", fd);
+    let fd = fd as usize;
+    let ret = bind(fd, &SockAddrIn::new([0; 4], ECHO_PORT));
+    if ret < 0 {
+        println!("udp_echo: cannot bind port {}: {}", ECHO_PORT, ret);
+        return 1;
+    }
+    println!("udp_echo: listening on port {}", ECHO_PORT);
+    let mut buf = [0u8; 2048];
+    let mut answered = 0;
+    while count.map_or(true, |count| answered < count) {
+        let mut from = SockAddrIn::default();
+        let n = recvfrom(fd, &mut buf, 0, &mut from);
+        if n < 0 {
+            println!("udp_echo: recvfrom failed: {}", n);
+            return 1;
+        }
+        let [a, b, c, d] = from.addr;
+        println!("udp_echo: {} bytes from {}.{}.{}.{}:{}", n, a, b, c, d, from.port());
+        assert_eq!(sendto(fd, &buf[..n as usize], 0, &from), n);
+        answered += 1;
+    }
+    close(fd);
+    0
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+//! 两个进程经过 127.0.0.1 用 UDP 通信：父进程绑定端口后 fork，子进程用新的套接字
+//! 发送一组数据报，父进程把每个数据报转成大写发回来源，子进程检查回复。
+//! 另外检查非阻塞接收、端口冲突和不支持的参数返回的错误码。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    bind, close, exit, fork, recvfrom, sendto, socket, waitpid, SockAddrIn, AF_INET, MSG_DONTWAIT,
+    SOCK_DGRAM, SOCK_NONBLOCK,
+};
+
+const SERVER_PORT: u16 = 9007;
+const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+const MESSAGES: usize = 20;
+const EBADF: isize = 9;
+const EAGAIN: isize = 11;
+const ENOTSOCK: isize = 88;
+const EMSGSIZE: isize = 90;
+const ESOCKTNOSUPPORT: isize = 94;
+const EAFNOSUPPORT: isize = 97;
+const EADDRINUSE: isize = 98;
+/// 不分片时一个 UDP 数据报最多携带的字节数
+const UDP_MAX_PAYLOAD: usize = 1472;
+
+fn client() -> ! {
+    let fd = socket(AF_INET, SOCK_DGRAM, 0);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let server = SockAddrIn::new(LOCALHOST, SERVER_PORT);
+    let mut buf = [0u8; 64];
+    for i in 0..MESSAGES {
+        let mut message = *b"message 00";
+        message[8] = b'0' + (i / 10) as u8;
+        message[9] = b'0' + (i % 10) as u8;
+        assert_eq!(sendto(fd, &message, 0, &server), message.len() as isize);
+        let mut from = SockAddrIn::default();
+        let n = recvfrom(fd, &mut buf, 0, &mut from);
+        assert_eq!(n, message.len() as isize);
+        assert_eq!(from, server, "reply from an unexpected address");
+        message.make_ascii_uppercase();
+        assert_eq!(&buf[..n as usize], &message[..]);
+    }
+    close(fd);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(socket(1, SOCK_DGRAM, 0), -EAFNOSUPPORT);
+    assert_eq!(socket(AF_INET, 1, 0), -ESOCKTNOSUPPORT);
+    assert_eq!(bind(1, &SockAddrIn::new(LOCALHOST, SERVER_PORT)), -ENOTSOCK);
+    assert_eq!(bind(100, &SockAddrIn::new(LOCALHOST, SERVER_PORT)), -EBADF);
+
+    let fd = socket(AF_INET, SOCK_DGRAM | SOCK_NONBLOCK, 0);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(bind(fd, &SockAddrIn::new(LOCALHOST, SERVER_PORT)), 0);
+    let other = socket(AF_INET, SOCK_DGRAM, 0);
+    assert!(other >= 0);
+    assert_eq!(bind(other as usize, &SockAddrIn::new([0; 4], SERVER_PORT)), -EADDRINUSE);
+    let big = [0u8; UDP_MAX_PAYLOAD + 1];
+    let to_self = SockAddrIn::new(LOCALHOST, SERVER_PORT);
+    assert_eq!(sendto(other as usize, &big, 0, &to_self), -EMSGSIZE);
+    close(other as usize);
+
+    let mut buf = [0u8; 64];
+    let mut from = SockAddrIn::default();
+    assert_eq!(recvfrom(fd, &mut buf, 0, &mut from), -EAGAIN);
+
+    let pid = fork();
+    if pid == 0 {
+        close(fd);
+        client();
+    }
+    let mut answered = 0;
+    while answered < MESSAGES {
+        let n = recvfrom(fd, &mut buf, MSG_DONTWAIT, &mut from);
+        if n == -EAGAIN {
+            user_lib::yield_();
+            continue;
+        }
+        assert!(n > 0);
+        assert_eq!(from.addr, LOCALHOST);
+        buf[..n as usize].make_ascii_uppercase();
+        assert_eq!(sendto(fd, &buf[..n as usize], 0, &from), n);
+        answered += 1;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0, "client failed");
+    close(fd);
+    println!("udp_loopback passed!");
+    0
+}
@@ -3,25 +3,519 @@
 extern crate alloc;
 #[macro_use]
 extern crate user_lib;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    cfmakeraw, chdir, close, dup3, environ, exec, execve, exit, flush, fork, getpid, getpwd, open, pipe, read, shutdown,
+    tcgetattr, tcsetattr, waitpid, waitpid_nohang, OpenFlags, Termios, STDERR, STDIN, STDOUT,
+};
+
 const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
 const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
+const EOT: u8 = 0x04u8;
+const ESC: u8 = 0x1bu8;
+
+/// 初始化进程的 pid，它退出时内核会 panic
+const INITPROC_PID: isize = 0;
+
+/// 结束 shell。交互模式下的 shell 是初始化进程，不能退出，改为写回磁盘后关机
+fn leave(exit_code: i32) -> ! {
+    if getpid() == INITPROC_PID {
+        shutdown();
+    }
+    exit(exit_code)
+}
+
+fn getchar() -> Option<u8> {
+    let mut c = [0u8; 1];
+    (read(STDIN, &mut c) == 1).then(|| c[0])
+}
+
+/// 在原始模式下读取一行命令，自己处理回显和退格，为方向键历史记录留出位置。
+/// 读到文件结束或空行上的 Ctrl-D 时返回 false
+fn read_line(line: &mut String, cooked: &Termios) -> bool {
+    let mut raw = *cooked;
+    cfmakeraw(&mut raw);
+    tcsetattr(STDIN, &raw);
+    line.clear();
+    let ok = loop {
+        let Some(c) = getchar() else {
+            break false;
+        };
+        match c {
+            LF | CR => {
+                print!("\n");
+                break true;
+            }
+            BS | DL => {
+                if line.pop().is_some() {
+                    print!("{} {}", BS as char, BS as char);
+                }
+            }
+            EOT if line.is_empty() => break false,
+            ESC => {
+                // 跳过方向键等转义序列 ESC [ x
+                if getchar() == Some(b'[') {
+                    getchar();
+                }
+            }
+            c if c >= b' ' => {
+                print!("{}", c as char);
+                line.push(c as char);
+            }
+            _ => {}
+        }
+        flush();
+    };
+    flush();
+    // 运行命令时恢复规范模式
+    tcsetattr(STDIN, cooked);
+    ok
+}
+/// 重定向：把打开的 `path` 放到描述符 `fd` 上
+struct Redirect {
+    fd: usize,
+    /// 末尾带 `\0`
+    path: String,
+    flags: OpenFlags,
+}
+
+/// 管道中的一个阶段
+struct Stage {
+    /// 以空白分隔的参数，每个参数末尾带 `\0`
+    args: Vec<String>,
+    redirects: Vec<Redirect>,
+}
+
+/// 结束当前的词：前面是重定向符号时作为重定向的目标，否则作为参数
+fn finish_word(stage: &mut Stage, word: &mut String, pending: &mut Option<(usize, OpenFlags)>) {
+    if word.is_empty() {
+        return;
+    }
+    let mut word = core::mem::take(word);
+    word.push('\0');
+    match pending.take() {
+        Some((fd, flags)) => stage.redirects.push(Redirect { fd, path: word, flags }),
+        None => stage.args.push(word),
+    }
+}
+
+/// 解析一个阶段，识别 `< in`、`> out`、`>> out` 和 `2> err`，符号前后的空白可以省略。
+/// 没有命令或者重定向符号后面没有文件名时返回 None
+fn parse_stage(text: &str) -> Option<Stage> {
+    let mut stage = Stage { args: Vec::new(), redirects: Vec::new() };
+    let mut word = String::new();
+    // 已经读到重定向符号、正在等待文件名的重定向
+    let mut pending: Option<(usize, OpenFlags)> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '<' || c == '>' {
+            // 单独的 2 紧挨着 > 表示重定向标准错误
+            let fd = if c == '<' {
+                STDIN
+            } else if word == "2" && pending.is_none() {
+                word.clear();
+                STDERR
+            } else {
+                STDOUT
+            };
+            finish_word(&mut stage, &mut word, &mut pending);
+            if pending.is_some() {
+                return None;
+            }
+            let flags = if c == '<' {
+                OpenFlags::RDONLY
+            } else if chars.peek() == Some(&'>') {
+                chars.next();
+                OpenFlags::WRONLY | OpenFlags::CREATE | OpenFlags::APPEND
+            } else {
+                OpenFlags::WRONLY | OpenFlags::CREATE | OpenFlags::TRUNC
+            };
+            pending = Some((fd, flags));
+        } else if c.is_whitespace() {
+            finish_word(&mut stage, &mut word, &mut pending);
+        } else {
+            word.push(c);
+        }
+    }
+    finish_word(&mut stage, &mut word, &mut pending);
+    if pending.is_some() || stage.args.is_empty() {
+        return None;
+    }
+    Some(stage)
+}
+
+/// 把命令行按 `|` 分成管道的各个阶段，有阶段不合法时返回 None
+fn parse_pipeline(line: &str) -> Option<Vec<Stage>> {
+    line.split('|').map(parse_stage).collect()
+}
+
+/// 打开所有阶段的重定向目标，返回每个阶段要放到目标描述符上的 (打开的描述符, 目标描述符)。
+/// 在创建任何子进程之前打开，有文件打不开时报告错误、关闭已经打开的文件并返回 None，整条命令都不运行
+fn open_redirects(stages: &[Stage]) -> Option<Vec<Vec<(usize, usize)>>> {
+    let mut opened: Vec<Vec<(usize, usize)>> = Vec::new();
+    for stage in stages {
+        let mut fds = Vec::new();
+        for redirect in stage.redirects.iter() {
+            let fd = open(redirect.path.as_str(), redirect.flags);
+            if fd < 0 {
+                eprintln!("shell: {}: cannot open ({})", redirect.path.trim_end_matches('\0'), fd);
+                for &(fd, _) in opened.iter().flatten().chain(fds.iter()) {
+                    close(fd);
+                }
+                return None;
+            }
+            fds.push((fd as usize, redirect.fd));
+        }
+        opened.push(fds);
+    }
+    Some(opened)
+}
+
+/// 在子进程中执行一个阶段，失败时退出子进程
+fn exec_stage(args: &[String], env: &[String]) -> ! {
+    let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    argv.push(core::ptr::null());
+    let mut envp: Vec<*const u8> = env.iter().map(|var| var.as_ptr()).collect();
+    envp.push(core::ptr::null());
+    execve(args[0].as_str(), argv.as_slice(), envp.as_slice());
+    eprintln!("{}: command not found", args[0].trim_end_matches('\0'));
+    exit(-4);
+}
+
+/// 启动管道：每个阶段一个子进程，前一阶段的标准输出接到后一阶段的标准输入，重定向在管道之后生效。
+/// 父进程在创建子进程后立即关闭自己持有的管道端，这样某个阶段退出（包括 exec 失败）后，
+/// 相邻阶段能读到文件结束或在写入时得到 EPIPE，不会一直阻塞。
+/// 返回已经启动的各阶段组成的作业，不等待它们结束；重定向的文件打不开时返回 None
+fn spawn_pipeline(stages: &[Stage], command: &str, env: &[String]) -> Option<Job> {
+    let redirects = open_redirects(stages)?;
+    let mut pids = Vec::new();
+    // 上一阶段输出管道的读端
+    let mut prev_read: Option<usize> = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let last = i + 1 == stages.len();
+        let mut fds = [0usize; 2];
+        if !last && pipe(&mut fds) != 0 {
+            eprintln!("shell: pipe failed");
+            break;
+        }
+        let pid = fork();
+        if pid == 0 {
+            if let Some(read_end) = prev_read {
+                dup3(read_end, STDIN);
+                close(read_end);
+            }
+            if !last {
+                dup3(fds[1], STDOUT);
+                close(fds[0]);
+                close(fds[1]);
+            }
+            for &(fd, target) in redirects[i].iter() {
+                dup3(fd, target);
+            }
+            for &(fd, _) in redirects.iter().flatten() {
+                close(fd);
+            }
+            exec_stage(&stage.args, env);
+        }
+        if let Some(read_end) = prev_read.take() {
+            close(read_end);
+        }
+        if !last {
+            close(fds[1]);
+            prev_read = Some(fds[0]);
+        }
+        if pid < 0 {
+            eprintln!("shell: fork failed");
+            break;
+        }
+        pids.push(pid);
+    }
+    if let Some(read_end) = prev_read {
+        close(read_end);
+    }
+    for &(fd, _) in redirects.iter().flatten() {
+        close(fd);
+    }
+    // 只有所有阶段都启动了，最后一个阶段的退出码才是整条命令的退出码
+    let last = if pids.len() == stages.len() { pids.last().copied() } else { None };
+    Some(Job { id: 0, command: String::from(command), pids, last, exit_code: None })
+}
+
+/// 一条命令启动的所有进程
+struct Job {
+    /// 后台作业的编号，前台命令为 0
+    id: usize,
+    command: String,
+    /// 还没有回收的进程
+    pids: Vec<isize>,
+    /// 最后一个阶段的 pid
+    last: Option<isize>,
+    /// 最后一个阶段的退出码，回收后才有
+    exit_code: Option<i32>,
+}
+
+impl Job {
+    /// 记录进程 `pid` 已经以 `exit_code` 退出并被回收
+    fn reaped(&mut self, pid: isize, exit_code: i32) {
+        self.pids.retain(|&p| p != pid);
+        if self.last == Some(pid) {
+            self.exit_code = Some(exit_code);
+        }
+    }
+
+    /// 等待所有进程结束，返回最后一个阶段的 pid 和退出码
+    fn wait(&mut self) -> Option<(isize, i32)> {
+        while let Some(&pid) = self.pids.first() {
+            let mut exit_code: i32 = 0;
+            let exit_pid = waitpid(pid as usize, &mut exit_code);
+            assert_eq!(pid, exit_pid);
+            self.reaped(pid, exit_code);
+        }
+        self.last.zip(self.exit_code)
+    }
+
+    /// 不阻塞地回收已经退出的进程，所有进程都已回收时返回 true
+    fn try_reap(&mut self) -> bool {
+        for pid in self.pids.clone() {
+            let mut exit_code: i32 = 0;
+            match waitpid_nohang(pid as usize, &mut exit_code) {
+                0 => {}
+                // 出错说明这个进程已经不是子进程了，不再等它
+                n if n < 0 => self.reaped(pid, 0),
+                _ => self.reaped(pid, exit_code),
+            }
+        }
+        self.pids.is_empty()
+    }
+}
+
+/// 后台作业表
+#[derive(Default)]
+struct Jobs(Vec<Job>);
+
+impl Jobs {
+    /// 把作业放到后台，编号取当前最大编号加一
+    fn add(&mut self, mut job: Job) {
+        job.id = self.0.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        if let Some(pid) = job.pids.last() {
+            println!("[{}] {}", job.id, pid);
+        }
+        self.0.push(job);
+    }
+
+    /// 回收已经结束的后台作业并报告，在每次显示提示符或运行下一行命令之前调用
+    fn reap(&mut self) {
+        self.0.retain_mut(|job| {
+            if !job.try_reap() {
+                return true;
+            }
+            match job.exit_code {
+                Some(0) | None => println!("[{}] Done {}", job.id, job.command),
+                Some(code) => println!("[{}] Exit {} {}", job.id, code, job.command),
+            }
+            false
+        });
+    }
+
+    /// `jobs`：列出还在运行的后台作业
+    fn list(&mut self) {
+        self.reap();
+        for job in self.0.iter() {
+            println!("[{}] Running {}", job.id, job.command);
+        }
+    }
+
+    /// `fg [%n]`：等待编号为 n 的后台作业结束（不给编号时取最近的一个），返回最后一个阶段的 pid 和退出码
+    fn fg(&mut self, spec: Option<&str>) -> Option<(isize, i32)> {
+        let index = match spec {
+            None => self.0.len().checked_sub(1),
+            Some(spec) => {
+                let id = spec.strip_prefix('%').unwrap_or(spec).parse::<usize>().ok();
+                self.0.iter().position(|job| Some(job.id) == id)
+            }
+        };
+        let Some(index) = index else {
+            eprintln!("shell: fg: {}: no such job", spec.unwrap_or("current"));
+            return None;
+        };
+        let mut job = self.0.remove(index);
+        println!("{}", job.command);
+        job.wait()
+    }
+}
 
-use alloc::string::String;
-use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, getpwd, shutdown, waitpid};
 const SIZE: usize = 60;
+
+/// shell 进程自己的状态
+#[derive(Default)]
+struct Shell {
+    jobs: Jobs,
+    /// 传给子进程的环境变量，每一项形如 `NAME=value\0`
+    env: Vec<String>,
+}
+
+impl Shell {
+    /// 环境变量从 shell 自己收到的环境变量开始
+    fn new() -> Self {
+        let env = environ()
+            .map(|var| {
+                let mut var = String::from(var);
+                var.push('\0');
+                var
+            })
+            .collect();
+        Shell { jobs: Jobs::default(), env }
+    }
+
+    /// `export NAME=value`：设置环境变量，之后启动的命令都会收到它
+    fn export(&mut self, assignment: &str) {
+        let Some((name, _)) = assignment.split_once('=').filter(|(name, _)| !name.is_empty()) else {
+            eprintln!("shell: export: `{}': not a valid assignment", assignment);
+            return;
+        };
+        let mut var = String::from(assignment);
+        var.push('\0');
+        match self.env.iter().position(|old| old.split_once('=').map(|(old, _)| old) == Some(name)) {
+            Some(index) => self.env[index] = var,
+            None => self.env.push(var),
+        }
+    }
+}
+
+/// `cd [dir]`：改变 shell 自己的工作目录，之后启动的命令和相对路径都从新目录开始，不给目录时回到 `/`
+fn change_dir(dir: &str) {
+    let mut path = String::from(dir);
+    path.push('\0');
+    let ret = chdir(path.as_str());
+    if ret < 0 {
+        eprintln!("shell: cd: {}: cannot change directory ({})", dir, ret);
+    }
+}
+
+/// `pwd`：显示 shell 的工作目录
+fn print_dir() {
+    let mut cwd = String::new();
+    let ret = getpwd(&mut cwd, SIZE as u32);
+    if ret < 0 {
+        eprintln!("shell: pwd: cannot get the working directory ({})", ret);
+    } else {
+        println!("{}", cwd);
+    }
+}
+
+/// 内建命令在 shell 进程中执行，不创建子进程，也不支持管道和重定向。
+/// 是内建命令时返回 Some，其中是像 [`run_line`] 一样的结果
+fn run_builtin(words: &[&str], shell: &mut Shell) -> Option<Option<(isize, i32)>> {
+    match words {
+        ["cd"] => change_dir("/"),
+        ["cd", dir] => change_dir(dir),
+        ["pwd"] => print_dir(),
+        // 脚本模式下结束脚本，交互模式下的初始化进程不能退出，改为关机
+        ["exit"] => leave(0),
+        ["exit", code] => match code.parse::<i32>() {
+            Ok(code) => leave(code),
+            Err(_) => {
+                eprintln!("shell: exit: {}: numeric argument required", code);
+                leave(2);
+            }
+        },
+        ["export"] => {
+            for var in shell.env.iter() {
+                println!("export {}", var.trim_end_matches('\0'));
+            }
+        }
+        ["export", assignments @ ..] => {
+            for assignment in assignments {
+                shell.export(assignment);
+            }
+        }
+        ["jobs"] => shell.jobs.list(),
+        ["fg"] => return Some(shell.jobs.fg(None)),
+        ["fg", spec] => return Some(shell.jobs.fg(Some(spec))),
+        _ => return None,
+    }
+    Some(None)
+}
+
+/// 运行一行命令，返回最后一个阶段的 pid 和退出码；以 `&` 结尾的命令放到后台运行，不等待它结束。
+/// 内建命令、后台命令、语法错误或重定向的文件打不开时返回 None
+fn run_line(command: &str, shell: &mut Shell) -> Option<(isize, i32)> {
+    // 内建命令要在创建子进程之前处理，否则 cd 之类的修改只发生在子进程中
+    let words: Vec<&str> = command.split_whitespace().collect();
+    if let Some(result) = run_builtin(&words, shell) {
+        return result;
+    }
+    let (command, background) = match command.strip_suffix('&') {
+        Some(command) => (command.trim_end(), true),
+        None => (command, false),
+    };
+    let Some(stages) = parse_pipeline(command) else {
+        eprintln!("shell: syntax error: {}", command);
+        return None;
+    };
+    let mut job = spawn_pipeline(&stages, command, &shell.env)?;
+    if background {
+        shell.jobs.add(job);
+        None
+    } else {
+        job.wait()
+    }
+}
+
+/// 脚本模式：逐行运行脚本文件 `path` 中的命令，跳过空行和 `#` 开头的注释行。
+/// 一行失败不影响后面的行，返回最后一行命令的退出码（没有运行时为 -1）
+fn run_script(path: &str) -> i32 {
+    let mut name = String::from(path);
+    name.push('\0');
+    let fd = open(name.as_str(), OpenFlags::RDONLY);
+    if fd < 0 {
+        eprintln!("shell: {}: cannot open ({})", path, fd);
+        return -1;
+    }
+    let mut script = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        script.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    let mut shell = Shell::new();
+    let mut exit_code = -1;
+    for line in String::from_utf8_lossy(&script).lines() {
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        shell.jobs.reap();
+        exit_code = match run_line(command, &mut shell) {
+            Some((_, code)) => code,
+            None => -1,
+        };
+    }
+    exit_code
+}
+
 const APP:[&str; 33] = ["brk\0", "chdir\0", "clone\0", "close\0", "dup\0", "dup2\0", "execve\0", "exit\0",
                         "fork\0", "fstat\0", "getcwd\0", "getdents\0", "getpid\0", "getppid\0", "gettimeofday\0",
                         "mkdir_\0", "open\0", "openat\0", "pipe\0", "read\0", "sleep\0", "test_echo\0", "times\0", "uname\0",
                         "unlink\0", "wait\0", "waitpid\0", "write\0", "yield\0", "mount\0", "umount\0", "mmap\0", "munmap\0"];
 #[no_mangle]
-pub fn main() -> i32 {
-
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return run_script(argv[1]);
+    }
     println!("Rust user shell");
     let mut line: String = String::new();
     let mut buf:String = String::new();
+    let mut cooked = Termios::default();
+    tcgetattr(STDIN, &mut cooked);
     getpwd(&mut buf, SIZE as u32);
     flush();
     for app in APP.iter() {
@@ -37,52 +531,27 @@ pub fn main() -> i32 {
             let mut exit_code: i32 = 0;
             let exit_pid = waitpid(pid as usize, &mut exit_code);
             assert_eq!(pid, exit_pid);
-            println!("Shell: Process {} exited with code {}", pid, exit_code);
+            println!("Shell: Process {} ({}) exited with code {}", pid, app.trim_end_matches('\0'), exit_code);
         }
     }
+    let mut shell = Shell::new();
     print!("\nPS HXH:{}>$", buf);
     flush();
     loop {
-        let c = getchar();
-        match c {
-            LF | CR => {
-                print!("\n");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str(), &[0 as *const u8]) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
-                    }
-                    line.clear();
-                }
-                getpwd(&mut buf, SIZE as u32);
-                print!("PS HXH:{}>$", buf);
-                flush();
-            }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{}", BS as char);
-                    print!(" ");
-                    print!("{}", BS as char);
-                    flush();
-                    line.pop();
-                }
-            }
-            _ => {
-                print!("{}", c as char);
-                flush();
-                line.push(c as char);
+        if !read_line(&mut line, &cooked) {
+            // 文件结束或空行上的 Ctrl-D
+            print!("\n");
+            leave(0);
+        }
+        let command = line.trim();
+        if !command.is_empty() {
+            if let Some((pid, exit_code)) = run_line(command, &mut shell) {
+                println!("Shell: Process {} ({}) exited with code {}", pid, command, exit_code);
             }
         }
+        shell.jobs.reap();
+        getpwd(&mut buf, SIZE as u32);
+        print!("PS HXH:{}>$", buf);
+        flush();
     }
 }
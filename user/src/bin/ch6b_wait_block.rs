@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+//! 父进程等待一个睡眠 2 秒的子进程：等待期间父进程阻塞在 waitpid 中，
+//! 几乎不消耗 CPU 时间；WNOHANG 在子进程退出前立即返回 0。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, sleep_blocking, times, waitpid, waitpid_nohang};
+
+const SLEEP_MS: usize = 2000;
+/// 时钟频率，times 返回的时间以时钟周期为单位
+const CLOCK_FREQ: u64 = 12_500_000;
+
+fn cpu_time() -> u64 {
+    let mut t = [0u64; 4];
+    assert!(times(&mut t) >= 0, "times failed");
+    t[0] + t[1]
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        sleep_blocking(SLEEP_MS);
+        exit(7);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid_nohang(pid as usize, &mut exit_code), 0, "WNOHANG blocked or reaped early");
+    let (start, cpu_start) = (get_time(), cpu_time());
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    let (elapsed, cpu) = (get_time() - start, cpu_time() - cpu_start);
+    println!("waited {} ms, parent used {} cycles", elapsed, cpu);
+    assert_eq!(exit_code >> 8, 7);
+    assert!(elapsed >= SLEEP_MS as isize - 100, "waitpid returned too early");
+    // 等待期间消耗的 CPU 时间应远小于等待时长的 1%
+    assert!(cpu < CLOCK_FREQ * SLEEP_MS as u64 / 1000 / 100, "parent spun while waiting");
+    println!("wait_block passed!");
+    0
+}
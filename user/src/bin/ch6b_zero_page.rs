@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, sysinfo, SysInfo};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = 4 << 20;
+/// 读遍整个映射后允许消耗的页帧数（页表页等）
+const MAX_FRAMES_FOR_READ: usize = 64;
+
+fn free_frames() -> usize {
+    let mut info = SysInfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    info.freeram
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = mmap(0, LEN, 3);
+    assert!(start > 0, "mmap failed");
+    let start = start as usize;
+    let before = free_frames();
+    // 只读访问每一页：全部映射到共享的全零页
+    for offset in (0..LEN).step_by(PAGE_SIZE) {
+        let value = unsafe { ((start + offset) as *const u64).read_volatile() };
+        assert_eq!(value, 0);
+    }
+    let used = before - free_frames();
+    assert!(used < MAX_FRAMES_FOR_READ, "reading {} pages used {} frames", LEN / PAGE_SIZE, used);
+    // 写入一页后它变为私有页，其余页面仍然是零
+    let page = (start + PAGE_SIZE) as *mut u64;
+    unsafe { page.write_volatile(0xdead_beef) };
+    assert_eq!(unsafe { page.read_volatile() }, 0xdead_beef);
+    for offset in (0..LEN).step_by(PAGE_SIZE).filter(|&offset| offset != PAGE_SIZE) {
+        let value = unsafe { ((start + offset) as *const u64).read_volatile() };
+        assert_eq!(value, 0);
+    }
+    assert_eq!(munmap(start, LEN), 0);
+    println!("zero_page passed!");
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+//! 子进程退出后、父进程回收之前，僵尸进程不应再占用内核栈和用户内存：
+//! 200 个未回收的子进程只能留下很少的物理页帧（包括为内核栈新建的内核页表）。
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, sleep_blocking, sysinfo, wait, SysInfo};
+
+const CHILDREN: usize = 200;
+/// 每个子进程的内核栈就占两个页帧，僵尸进程平均留下的页帧必须少于一个
+const MAX_ZOMBIE_FRAMES: usize = CHILDREN;
+
+fn free_frames() -> usize {
+    let mut info = SysInfo::default();
+    assert_eq!(sysinfo(&mut info), 0);
+    info.freeram
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let before = free_frames();
+    for i in 0..CHILDREN {
+        let pid = fork();
+        assert!(pid >= 0, "fork {} failed", i);
+        if pid == 0 {
+            exit(0);
+        }
+    }
+    // 等所有子进程退出成为僵尸进程
+    sleep_blocking(500);
+    let zombies = free_frames();
+    let pinned = before.saturating_sub(zombies);
+    println!("{} zombies pin {} frames", CHILDREN, pinned);
+    assert!(pinned < MAX_ZOMBIE_FRAMES, "zombies pin {} frames", pinned);
+    for _ in 0..CHILDREN {
+        let mut exit_code: i32 = 0;
+        assert!(wait(&mut exit_code) > 0, "wait failed");
+        assert_eq!(exit_code, 0);
+    }
+    println!("zombie_reclaim passed!");
+    0
+}
@@ -5,6 +5,7 @@ use spin::mutex::Mutex;
 
 pub const STDIN: usize = 0;
 pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
 
 const CONSOLE_BUFFER_SIZE: usize = 256 * 10;
 
@@ -49,6 +50,39 @@ pub fn print(args: fmt::Arguments) {
     buf.write_fmt(args);
 }
 
+/// 标准错误不经过缓冲，每段格式化输出直接写入
+struct Stderr;
+
+impl Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if write(STDERR, s.as_bytes()) < 0 {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[allow(unused)]
+pub fn eprint(args: fmt::Arguments) {
+    // 先输出缓冲中的标准输出，保持两者的先后顺序
+    flush();
+    Stderr.write_fmt(args);
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::eprint(format_args!($fmt $(, $($arg)+)?));
+    }
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    ($fmt: literal $(, $($arg: tt)+)?) => {
+        $crate::console::eprint(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
+    }
+}
+
 #[macro_export]
 macro_rules! print {
     ($fmt: literal $(, $($arg: tt)+)?) => {
@@ -2,6 +2,7 @@
 #![feature(linkage)]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
+#![feature(naked_functions)]
 
 #[macro_use]
 pub mod console;
@@ -15,7 +16,7 @@ extern crate bitflags;
 
 use alloc::{string::String, vec::Vec, vec};
 use buddy_system_allocator::LockedHeap;
-pub use console::{flush, STDIN, STDOUT};
+pub use console::{flush, STDERR, STDIN, STDOUT};
 pub use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
@@ -44,11 +45,27 @@ fn clear_bss() {
     }
 }
 
+/// 程序入口：内核把 argc、argv、envp 和 auxv 依次放在初始栈上，sp 指向 argc
+#[naked]
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub unsafe extern "C" fn _start() -> ! {
+    core::arch::asm!("mv a0, sp", "tail {}", sym rust_start, options(noreturn))
+}
+
+extern "C" fn rust_start(sp: usize) -> ! {
+    let argc = unsafe { (sp as *const usize).read() };
+    let argv = sp + core::mem::size_of::<usize>();
+    // envp 紧跟在 argv 的空指针之后，auxv 紧跟在 envp 的空指针之后
+    let mut auxv = argv + (argc + 1) * core::mem::size_of::<usize>();
+    while unsafe { (auxv as *const usize).read() } != 0 {
+        auxv += core::mem::size_of::<usize>();
+    }
+    auxv += core::mem::size_of::<usize>();
     clear_bss();
     unsafe {
+        ENVP = argv + (argc + 1) * core::mem::size_of::<usize>();
+        AUXV = auxv;
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
@@ -69,6 +86,56 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     exit(main(argc, v.as_slice()));
 }
 
+/// auxv 表项的类型
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+
+/// 初始栈上 auxv 的起始地址
+static mut AUXV: usize = 0;
+
+/// 初始栈上 envp 的起始地址
+static mut ENVP: usize = 0;
+
+/// 内核传入的环境变量，每一项形如 `NAME=value`
+pub fn environ() -> impl Iterator<Item = &'static str> {
+    let mut entry = unsafe { ENVP } as *const usize;
+    core::iter::from_fn(move || {
+        let str_start = unsafe { entry.read() };
+        if str_start == 0 {
+            return None;
+        }
+        entry = entry.wrapping_add(1);
+        let len = (0usize..)
+            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
+            .unwrap();
+        core::str::from_utf8(unsafe { core::slice::from_raw_parts(str_start as *const u8, len) }).ok()
+    })
+}
+
+/// 环境变量 `name` 的值
+pub fn getenv(name: &str) -> Option<&'static str> {
+    environ().find_map(|env| env.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// 在内核传入的 auxv 中查找 `key` 对应的值
+pub fn getauxval(key: usize) -> Option<usize> {
+    let mut entry = unsafe { AUXV } as *const [usize; 2];
+    loop {
+        let [k, v] = unsafe { entry.read() };
+        if k == key {
+            return Some(v);
+        }
+        if k == AT_NULL {
+            return None;
+        }
+        entry = entry.wrapping_add(1);
+    }
+}
+
 #[linkage = "weak"]
 #[no_mangle]
 fn main(_argc: usize, _argv: &[&str]) -> i32 {
@@ -80,8 +147,11 @@ bitflags! {
         const RDONLY = 0;
         const WRONLY = 1 << 0;
         const RDWR = 1 << 1;
-        const CREATE = 1 << 9;
-        const TRUNC = 1 << 10;
+        const CREATE = 1 << 6;
+        const TRUNC = 1 << 9;
+        const APPEND = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        const O_DIRECTORY = 1 << 21;
     }
 }
 
@@ -98,6 +168,33 @@ impl TimeVal {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// wall-clock time since 1970-01-01 00:00:00 UTC
+pub const CLOCK_REALTIME: usize = 0;
+/// time since boot
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// 距 1970-01-01 `days` 天（可以为负）的公历日期 (年, 月, 日)
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // 从 0000-03-01 起算的天数换算成年月日
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     UnInit,
@@ -131,6 +228,51 @@ impl TaskInfo {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SysInfo {
+    pub uptime: isize,
+    pub loads: [usize; 3],
+    pub totalram: usize,
+    pub freeram: usize,
+    pub sharedram: usize,
+    pub bufferram: usize,
+    pub totalswap: usize,
+    pub freeswap: usize,
+    pub procs: u16,
+    pad: u16,
+    pub totalhigh: usize,
+    pub freehigh: usize,
+    pub mem_unit: u32,
+}
+
+pub const PS_READY: usize = 0;
+pub const PS_RUNNING: usize = 1;
+pub const PS_SLEEPING: usize = 2;
+pub const PS_ZOMBIE: usize = 3;
+
+/// sys_ps 返回的一条进程记录
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PsEntry {
+    pub pid: usize,
+    pub ppid: usize,
+    pub state: usize,
+    pub priority: isize,
+    pub utime: u64,
+    pub stime: u64,
+    pub rss: usize,
+    pub name: [u8; 16],
+}
+
+impl PsEntry {
+    /// 命令名，僵尸进程没有命令名
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Stat {
@@ -142,8 +284,23 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
-    /// unused pad
-    pad: [u64; 7],
+    /// user ID of owner
+    pub uid: u32,
+    /// group ID of owner
+    pub gid: u32,
+    /// device ID (if special file)
+    pub rdev: u64,
+    pad: u64,
+    /// total size, in bytes
+    pub size: i64,
+    /// block size for filesystem I/O
+    pub blksize: u32,
+    pad2: u32,
+    /// number of 512B blocks allocated
+    pub blocks: u64,
+    /// access, modification and status change times, each as seconds then nanoseconds
+    pub times: [i64; 6],
+    unused: [u32; 2],
 }
 
 impl Stat {
@@ -153,7 +310,16 @@ impl Stat {
             ino: 0,
             mode: StatMode::NULL,
             nlink: 0,
-            pad: [0; 7],
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            pad: 0,
+            size: 0,
+            blksize: 0,
+            pad2: 0,
+            blocks: 0,
+            times: [0; 6],
+            unused: [0; 2],
         }
     }
 }
@@ -167,15 +333,69 @@ impl Default for Stat {
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
+        /// named pipe
+        const FIFO  = 0o010000;
+        /// character device
+        const CHR   = 0o020000;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// owner has read permission
+        const OWNER_READ  = 0o400;
+        /// owner has write permission
+        const OWNER_WRITE = 0o200;
+        /// owner has execute permission
+        const OWNER_EXEC  = 0o100;
+        /// group has read permission
+        const GROUP_READ  = 0o040;
+        /// group has write permission
+        const GROUP_WRITE = 0o020;
+        /// group has execute permission
+        const GROUP_EXEC  = 0o010;
+        /// others have read permission
+        const OTHER_READ  = 0o004;
+        /// others have write permission
+        const OTHER_WRITE = 0o002;
+        /// others have execute permission
+        const OTHER_EXEC  = 0o001;
+    }
+}
+
+impl StatMode {
+    /// the file type bits, without the permissions
+    pub fn file_type(self) -> StatMode {
+        self & (StatMode::FIFO | StatMode::CHR | StatMode::DIR | StatMode::FILE)
+    }
+
+    /// the permission bits, e.g. 0o644
+    pub fn permissions(self) -> u32 {
+        self.bits & 0o777
     }
 }
 
 const AT_FDCWD: isize = -100;
 
+/// a file in the watched directory was written
+pub const IN_MODIFY: u32 = 0x2;
+/// a file was created in the watched directory
+pub const IN_CREATE: u32 = 0x100;
+/// a file was deleted from the watched directory
+pub const IN_DELETE: u32 = 0x200;
+/// the event queue overflowed and events were dropped
+pub const IN_Q_OVERFLOW: u32 = 0x4000;
+/// inotify_init1 flag: reads return 0 instead of blocking when there is no event
+pub const IN_NONBLOCK: u32 = 0o4000;
+
+/// shared lock for flock
+pub const LOCK_SH: u32 = 1;
+/// exclusive lock for flock
+pub const LOCK_EX: u32 = 2;
+/// don't block when locking
+pub const LOCK_NB: u32 = 4;
+/// unlock
+pub const LOCK_UN: u32 = 8;
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits)
 }
@@ -187,6 +407,28 @@ pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 
+pub fn fsync(fd: usize) -> isize {
+    sys_fsync(fd)
+}
+
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// 返回新的读写位置；目录的位置是 getdents64 下一次读取的目录项序号
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
+/// 将目录项以 linux_dirent64 格式读入 `buf`，返回读取的字节数，读完时返回 0
+pub fn getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -203,10 +445,88 @@ pub fn unlink(path: &str) -> isize {
     sys_unlinkat(AT_FDCWD as usize, path, 0)
 }
 
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD as usize, path)
+}
+
+/// 创建命名管道，路径已存在时返回 -EEXIST
+pub fn mkfifo(path: &str) -> isize {
+    sys_mknodat(AT_FDCWD as usize, path, StatMode::FIFO.bits())
+}
+
+/// 挂载文件系统，所有字符串都要以 \0 结尾；目前只支持 "tmpfs"，`data` 可以是 "size=<字节数>"
+pub fn mount(source: &str, target: &str, fstype: &str, data: &str) -> isize {
+    sys_mount(source, target, fstype, 0, data)
+}
+
+/// mount 的标志：只读挂载
+pub const MS_RDONLY: usize = 1;
+/// mount 的标志：只改变 `target` 上已经挂载的文件系统的读写方式
+pub const MS_REMOUNT: usize = 32;
+
+/// 带标志挂载文件系统，字符串都要以 \0 结尾
+pub fn mount_flags(source: &str, target: &str, fstype: &str, flags: usize, data: &str) -> isize {
+    sys_mount(source, target, fstype, flags, data)
+}
+
+pub fn umount(target: &str) -> isize {
+    sys_umount2(target, 0)
+}
+
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+
+pub fn chroot(path: &str) -> isize {
+    sys_chroot(path)
+}
+
+pub fn inotify_init1(flags: u32) -> isize {
+    sys_inotify_init1(flags)
+}
+
+pub fn inotify_add_watch(fd: usize, path: &str, mask: u32) -> isize {
+    sys_inotify_add_watch(fd, path, mask)
+}
+
+pub fn flock(fd: usize, operation: u32) -> isize {
+    sys_flock(fd, operation)
+}
+
+pub fn fchmod(fd: usize, mode: u32) -> isize {
+    sys_fchmod(fd, mode)
+}
+
+pub fn chmod(path: &str, mode: u32) -> isize {
+    sys_fchmodat(AT_FDCWD as usize, path, mode, 0)
+}
+
+/// access 的 `mode`：只检查是否存在
+pub const F_OK: u32 = 0;
+/// access 的 `mode`：可读
+pub const R_OK: u32 = 4;
+/// access 的 `mode`：可写
+pub const W_OK: u32 = 2;
+/// access 的 `mode`：可执行
+pub const X_OK: u32 = 1;
+
+pub fn access(path: &str, mode: u32) -> isize {
+    sys_faccessat(AT_FDCWD as usize, path, mode)
+}
+
 pub fn fstat(fd: usize, st: &mut Stat) -> isize {
     sys_fstat(fd, st)
 }
 
+/// 取得相对于目录 `dirfd` 的路径 `path` 的文件状态，`path` 是绝对路径时忽略 `dirfd`
+pub fn fstatat(dirfd: usize, path: &str, st: &mut Stat) -> isize {
+    sys_fstatat(dirfd, path, st, 0)
+}
+
+pub fn stat(path: &str, st: &mut Stat) -> isize {
+    sys_fstatat(AT_FDCWD as usize, path, st, 0)
+}
+
 pub fn mail_read(buf: &mut [u8]) -> isize {
     sys_mail_read(buf)
 }
@@ -232,9 +552,16 @@ pub fn get_time() -> isize {
     }
 }
 
+pub fn clock_gettime(clock_id: usize, time: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock_id, time)
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
+pub fn getppid() -> isize {
+    sys_getppid()
+}
 
 pub fn fork() -> isize {
     sys_fork()
@@ -244,20 +571,35 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
 
+/// 和 exec 相同，另外传入以空指针结尾的环境变量 `envs`，每一项形如 `NAME=value\0`
+pub fn execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    sys_execve(path, args, envs)
+}
+
 pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
 
+pub const WNOHANG: usize = 1;
+
 pub fn wait(exit_code: &mut i32) -> isize {
-    sys_waitpid(-1, exit_code as *mut _)
+    sys_waitpid(-1, exit_code as *mut _, 0)
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+/// 子进程尚未退出时立即返回 0
+pub fn waitpid_nohang(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, WNOHANG)
 }
 
 pub fn sleep_blocking(sleep_ms: usize) {
-    sys_sleep(sleep_ms);
+    sys_sleep(&TimeVal {
+        sec: sleep_ms / 1000,
+        usec: sleep_ms % 1000 * 1000,
+    });
 }
 
 pub fn sleep(period_ms: usize) {
@@ -266,14 +608,52 @@ pub fn sleep(period_ms: usize) {
         sys_yield();
     }
 }
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    sys_mmap(start, len, prot, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0)
+}
+
+pub fn mmap_shared(start: usize, len: usize, prot: usize) -> isize {
+    sys_mmap(start, len, prot, MAP_SHARED | MAP_ANONYMOUS, usize::MAX, 0)
+}
+
+pub fn mmap_file(start: usize, len: usize, prot: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(start, len, prot, MAP_PRIVATE, fd, offset)
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
     sys_munmap(start, len)
 }
 
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const IPC_RMID: usize = 0;
+pub const SHM_RDONLY: usize = 0o10000;
+
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    sys_shmget(key, size, flags)
+}
+
+pub fn shmat(id: usize, addr: usize, flags: usize) -> isize {
+    sys_shmat(id, addr, flags)
+}
+
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+
+pub fn shmctl(id: usize, cmd: usize) -> isize {
+    sys_shmctl(id, cmd)
+}
+
 pub fn sbrk(size: i32) -> isize {
     sys_sbrk(size)
 }
@@ -285,10 +665,213 @@ pub fn spawn(path: &str) -> isize {
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+/// 让 `new_fd` 指向 `old_fd` 打开的文件，`new_fd` 原来打开的文件被关闭
+pub fn dup3(old_fd: usize, new_fd: usize) -> isize {
+    if new_fd == STDOUT {
+        console::flush();
+    }
+    sys_dup3(old_fd, new_fd)
+}
+/// 内核按 C 的 `int[2]` 写回读端和写端
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    pipe2(pipe_fd, OpenFlags::empty())
+}
+/// 同 `pipe`，`flags` 中只有 O_NONBLOCK 起作用
+pub fn pipe2(pipe_fd: &mut [usize], flags: OpenFlags) -> isize {
+    let mut fds = [0u32; 2];
+    let ret = sys_pipe(&mut fds, flags.bits());
+    if ret == 0 {
+        pipe_fd[0] = fds[0] as usize;
+        pipe_fd[1] = fds[1] as usize;
+    }
+    ret
+}
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+/// c_iflag：将输入的回车转换为换行
+pub const ICRNL: u32 = 0o000400;
+/// c_lflag：Ctrl-C 等字符产生信号
+pub const ISIG: u32 = 0o000001;
+/// c_lflag：规范模式，内核按行编辑输入
+pub const ICANON: u32 = 0o000002;
+/// c_lflag：内核回显输入
+pub const ECHO: u32 = 0o000010;
+/// c_cc 下标：非规范模式 read 的超时，单位为 0.1 秒
+pub const VTIME: usize = 5;
+/// c_cc 下标：非规范模式 read 至少返回的字节数
+pub const VMIN: usize = 6;
+
+/// 终端属性，布局与 Linux 内核的 `struct termios` 相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; 19],
+}
+
+/// 终端窗口大小，布局与 Linux 的 `struct winsize` 相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+/// 只有控制台终端（fd 0–2 和 /dev/tty）支持，其他文件返回 -ENOTTY
+pub fn ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    sys_ioctl(fd, request, arg)
+}
+
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    ioctl(fd, TCGETS, termios as *mut Termios as usize)
+}
+
+/// 控制台的输出是同步的，TCSANOW 和 TCSADRAIN 效果相同
+pub fn tcsetattr(fd: usize, termios: &Termios) -> isize {
+    ioctl(fd, TCSETSW, termios as *const Termios as usize)
 }
 
+/// 关闭规范模式、回显、信号和回车转换，read 每收到一个字节就返回
+pub fn cfmakeraw(termios: &mut Termios) {
+    termios.iflag &= !ICRNL;
+    termios.lflag &= !(ICANON | ECHO | ISIG);
+    termios.cc[VMIN] = 1;
+    termios.cc[VTIME] = 0;
+}
+
+pub fn get_winsize(fd: usize, winsize: &mut WinSize) -> isize {
+    ioctl(fd, TIOCGWINSZ, winsize as *mut WinSize as usize)
+}
+
+/// IPv4 address family
+pub const AF_INET: usize = 2;
+/// datagram socket type
+pub const SOCK_DGRAM: usize = 2;
+/// socket type flag: recvfrom returns -EAGAIN instead of blocking
+pub const SOCK_NONBLOCK: usize = 0o4000;
+/// recvfrom flag: don't block when no datagram is queued
+pub const MSG_DONTWAIT: u32 = 0x40;
+
+/// the IPv4 socket address, the same layout as Linux's `struct sockaddr_in`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SockAddrIn {
+    pub family: u16,
+    /// port in network byte order
+    pub port: u16,
+    /// address in network byte order
+    pub addr: [u8; 4],
+    pub zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    pub fn new(addr: [u8; 4], port: u16) -> Self {
+        Self { family: AF_INET as u16, port: port.to_be(), addr, zero: [0; 8] }
+    }
+
+    /// the port in host byte order
+    pub fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+/// 只支持 AF_INET/SOCK_DGRAM（UDP）
+pub fn socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    sys_socket(domain, ty, protocol)
+}
+
+/// 端口为 0 时分配一个临时端口
+pub fn bind(fd: usize, addr: &SockAddrIn) -> isize {
+    sys_bind(fd, addr)
+}
+
+pub fn sendto(fd: usize, buf: &[u8], flags: u32, addr: &SockAddrIn) -> isize {
+    sys_sendto(fd, buf, flags, addr)
+}
+
+/// 返回收到的字节数，数据报的来源写入 `addr`
+pub fn recvfrom(fd: usize, buf: &mut [u8], flags: u32, addr: &mut SockAddrIn) -> isize {
+    let mut addrlen = core::mem::size_of::<SockAddrIn>() as u32;
+    sys_recvfrom(fd, buf, flags, addr, &mut addrlen)
+}
+
+pub const F_SETPIPE_SZ: usize = 1031;
+pub const F_GETPIPE_SZ: usize = 1032;
+
+/// 目前只支持 F_GETPIPE_SZ 和 F_SETPIPE_SZ，设置成功时返回新的管道容量
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+/// 依次返回 utime、stime、cutime、cstime（时钟周期）
+pub fn times(times: &mut [u64; 4]) -> isize {
+    sys_times(times)
+}
+
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+/// 与 Linux 的 struct rusage 布局相同，内核只填写时间、RSS 峰值、缺页和上下文切换次数
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    /// RSS 峰值（KiB）
+    pub ru_maxrss: isize,
+    pub ru_ixrss: isize,
+    pub ru_idrss: isize,
+    pub ru_isrss: isize,
+    pub ru_minflt: isize,
+    pub ru_majflt: isize,
+    pub ru_nswap: isize,
+    pub ru_inblock: isize,
+    pub ru_oublock: isize,
+    pub ru_msgsnd: isize,
+    pub ru_msgrcv: isize,
+    pub ru_nsignals: isize,
+    pub ru_nvcsw: isize,
+    pub ru_nivcsw: isize,
+}
+
+pub fn getrusage(who: isize, usage: &mut RUsage) -> isize {
+    sys_getrusage(who, usage)
+}
+pub const PR_SET_NAME: usize = 15;
+pub const PR_GET_NAME: usize = 16;
+/// 设置当前线程的命令名，超过 15 字节的部分被截断
+pub fn set_name(name: &str) -> isize {
+    let mut buf = [0u8; 16];
+    let len = name.len().min(buf.len() - 1);
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    sys_prctl(PR_SET_NAME, buf.as_ptr() as usize)
+}
+/// 读取当前线程的命令名，以 \0 结尾
+pub fn get_name(buf: &mut [u8; 16]) -> isize {
+    sys_prctl(PR_GET_NAME, buf.as_mut_ptr() as usize)
+}
+/// 填写最多 `buf.len()` 个进程的记录，返回进程总数
+pub fn ps(buf: &mut [PsEntry]) -> isize {
+    sys_ps(buf)
+}
+/// 让内核把调度统计打印到控制台
+pub fn perf_dump() -> isize {
+    sys_perf_dump()
+}
+pub fn sysinfo(info: &mut SysInfo) -> isize {
+    sys_sysinfo(info)
+}
 pub fn task_info(info: &mut TaskInfo) -> isize {
     sys_task_info(info)
 }
@@ -296,6 +879,45 @@ pub fn task_info(info: &mut TaskInfo) -> isize {
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
+/// 用 `clone(CLONE_VM | CLONE_THREAD | CLONE_SETTLS)` 在当前进程中创建线程：
+/// 新线程在以 `stack_top` 为栈顶的栈上、以 `tls` 为 tp 执行 `entry(arg)`，返回值作为线程的退出码。
+/// 返回新线程的线程号，失败时返回负的错误码
+pub fn clone_thread(entry: extern "C" fn(usize) -> i32, arg: usize, stack_top: usize, tls: usize) -> isize {
+    unsafe { clone_trampoline(entry, arg, stack_top, tls) }
+}
+
+/// 子线程不能回到调用者的栈帧，所以 `entry` 和 `arg` 先存到新栈上，子线程从那里取出后调用
+#[naked]
+unsafe extern "C" fn clone_trampoline(
+    _entry: extern "C" fn(usize) -> i32,
+    _arg: usize,
+    _stack_top: usize,
+    _tls: usize,
+) -> isize {
+    core::arch::asm!(
+        "addi a2, a2, -16",
+        "sd a0, 0(a2)",
+        "sd a1, 8(a2)",
+        // clone(flags, stack, ptid, tls, ctid)，a3 已经是 tls
+        "li a0, 0x90100",
+        "mv a1, a2",
+        "li a2, 0",
+        "li a4, 0",
+        "li a7, 220",
+        "ecall",
+        "bnez a0, 1f",
+        // 子线程：sp 指向保存的 entry 和 arg
+        "ld t0, 0(sp)",
+        "ld a0, 8(sp)",
+        "jalr t0",
+        // entry 的返回值已在 a0 中，作为退出码退出线程
+        "li a7, 93",
+        "ecall",
+        "1:",
+        "ret",
+        options(noreturn)
+    )
+}
 pub fn gettid() -> isize {
     sys_gettid()
 }
@@ -1,23 +1,55 @@
 use crate::{TaskInfo, SignalAction};
-use super::{Stat, TimeVal};
+use super::{PsEntry, RUsage, SockAddrIn, Stat, SysInfo, TimeSpec, TimeVal};
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_IOCTL: usize = 29;
+pub const SYSCALL_MKNODAT: usize = 33;
+pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
+pub const SYSCALL_INOTIFY_INIT1: usize = 26;
+pub const SYSCALL_INOTIFY_ADD_WATCH: usize = 27;
+pub const SYSCALL_FLOCK: usize = 32;
+pub const SYSCALL_UMOUNT2: usize = 39;
+pub const SYSCALL_MOUNT: usize = 40;
+pub const SYSCALL_FACCESSAT: usize = 48;
+pub const SYSCALL_CHDIR: usize = 49;
+pub const SYSCALL_CHROOT: usize = 51;
+pub const SYSCALL_FCHMOD: usize = 52;
+pub const SYSCALL_FCHMODAT: usize = 53;
+pub const SYSCALL_FSTATAT: usize = 79;
 pub const SYSCALL_FSTAT: usize = 80;
+pub const SYSCALL_SYNC: usize = 81;
+pub const SYSCALL_FSYNC: usize = 82;
+pub const SYSCALL_GETDENTS64: usize = 61;
+pub const SYSCALL_LSEEK: usize = 62;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_CLOCK_GETTIME: usize = 113;
 pub const SYSCALL_YIELD: usize = 124;
 pub const SYSCALL_KILL: usize = 129;
 pub const SYSCALL_SIGACTION: usize = 134;
 pub const SYSCALL_SIGPROCMASK: usize = 135;
 pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_GETRUSAGE: usize = 165;
+pub const SYSCALL_PRCTL: usize = 167;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
+pub const SYSCALL_GETPPID: usize = 173;
 pub const SYSCALL_GETTID: usize = 178;
+pub const SYSCALL_SYSINFO: usize = 179;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMCTL: usize = 195;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
+pub const SYSCALL_SOCKET: usize = 198;
+pub const SYSCALL_BIND: usize = 200;
+pub const SYSCALL_SENDTO: usize = 206;
+pub const SYSCALL_RECVFROM: usize = 207;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
@@ -25,12 +57,17 @@ pub const SYSCALL_SET_PRIORITY: usize = 140;
 pub const SYSCALL_SBRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
-pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_DUP: usize = 23;
+pub const SYSCALL_DUP3: usize = 24;
 pub const SYSCALL_PIPE: usize = 59;
+pub const SYSCALL_FCNTL: usize = 25;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_PS: usize = 411;
+pub const SYSCALL_PERF_DUMP: usize = 412;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -95,6 +132,26 @@ pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_fsync(fd: usize) -> isize {
+    syscall(SYSCALL_FSYNC, [fd, 0, 0])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETDENTS64, [fd, buf.as_mut_ptr() as usize, buf.len()])
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -126,10 +183,76 @@ pub fn sys_linkat(
     )
 }
 
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, request, arg])
+}
+
+pub fn sys_mknodat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_MKNODAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_mkdirat(dirfd: usize, path: &str) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd, path.as_ptr() as usize, 0])
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str, flags: usize, data: &str) -> isize {
+    syscall6(
+        SYSCALL_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            flags,
+            data.as_ptr() as usize,
+            0,
+        ],
+    )
+}
+
+pub fn sys_umount2(target: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UMOUNT2, [target.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_chroot(path: &str) -> isize {
+    syscall(SYSCALL_CHROOT, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_inotify_init1(flags: u32) -> isize {
+    syscall(SYSCALL_INOTIFY_INIT1, [flags as usize, 0, 0])
+}
+
+pub fn sys_inotify_add_watch(fd: usize, path: &str, mask: u32) -> isize {
+    syscall(SYSCALL_INOTIFY_ADD_WATCH, [fd, path.as_ptr() as usize, mask as usize])
+}
+
+pub fn sys_flock(fd: usize, operation: u32) -> isize {
+    syscall(SYSCALL_FLOCK, [fd, operation as usize, 0])
+}
+
+pub fn sys_faccessat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_FACCESSAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_fchmod(fd: usize, mode: u32) -> isize {
+    syscall(SYSCALL_FCHMOD, [fd, mode as usize, 0])
+}
+
+pub fn sys_fchmodat(dirfd: usize, path: &str, mode: u32, flags: usize) -> isize {
+    syscall6(SYSCALL_FCHMODAT, [dirfd, path.as_ptr() as usize, mode as usize, flags, 0, 0])
+}
+
 pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
 
+pub fn sys_fstatat(dirfd: usize, path: &str, st: &mut Stat, flags: usize) -> isize {
+    syscall6(SYSCALL_FSTATAT, [dirfd, path.as_ptr() as usize, st as *const _ as usize, flags, 0, 0])
+}
+
 pub fn sys_fstat(fd: usize, st: &mut Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
 }
@@ -153,8 +276,8 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
-pub fn sys_sleep(sleep_ms: usize) -> isize {
-    syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
+pub fn sys_sleep(time: &TimeVal) -> isize {
+    syscall(SYSCALL_SLEEP, [time as *const _ as usize, 0, 0])
 }
 
 pub fn sys_yield() -> isize {
@@ -165,10 +288,18 @@ pub fn sys_get_time(time: &mut TimeVal, tz: usize) -> isize {
     syscall(SYSCALL_GETTIMEOFDAY, [time as *const _ as usize, tz, 0])
 }
 
+pub fn sys_clock_gettime(clock_id: usize, time: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, time as *mut _ as usize, 0])
+}
+
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -180,8 +311,15 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+pub fn sys_execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [path.as_ptr() as usize, args.as_ptr() as usize, envs.as_ptr() as usize],
+    )
+}
+
+pub fn sys_waitpid(pid: isize, xstatus: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, options])
 }
 
 pub fn sys_set_priority(prio: isize) -> isize {
@@ -192,14 +330,34 @@ pub fn sys_sbrk(size: i32) -> isize {
     syscall(SYSCALL_SBRK, [size as usize, 0, 0])
 }
 
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
-    syscall(SYSCALL_MMAP, [start, len, prot])
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, fd, offset])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0])
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, addr, flags])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -208,8 +366,36 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
-pub fn sys_pipe(pipe: &mut [usize]) -> isize {
-    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
+pub fn sys_dup3(old_fd: usize, new_fd: usize) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, 0])
+}
+
+pub fn sys_pipe(pipe: &mut [u32; 2], flags: u32) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_times(times: &mut [u64; 4]) -> isize {
+    syscall(SYSCALL_TIMES, [times as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut RUsage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
+pub fn sys_prctl(option: usize, arg2: usize) -> isize {
+    syscall(SYSCALL_PRCTL, [option, arg2, 0])
+}
+
+pub fn sys_sysinfo(info: &mut SysInfo) -> isize {
+    syscall(SYSCALL_SYSINFO, [info as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_ps(buf: &mut [PsEntry]) -> isize {
+    syscall(SYSCALL_PS, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
+pub fn sys_perf_dump() -> isize {
+    syscall(SYSCALL_PERF_DUMP, [0, 0, 0])
 }
 
 pub fn sys_task_info(info: &mut TaskInfo) -> isize {
@@ -300,4 +486,42 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
 
 pub fn sys_getcwd(buf: *mut u8, size: u32) -> isize{
     syscall(SYSCALL_GETPWD, [buf as usize, size as usize, 0])
-}
\ No newline at end of file
+}
+pub fn sys_socket(domain: usize, ty: usize, protocol: usize) -> isize {
+    syscall(SYSCALL_SOCKET, [domain, ty, protocol])
+}
+
+pub fn sys_bind(fd: usize, addr: &SockAddrIn) -> isize {
+    syscall(
+        SYSCALL_BIND,
+        [fd, addr as *const SockAddrIn as usize, core::mem::size_of::<SockAddrIn>()],
+    )
+}
+
+pub fn sys_sendto(fd: usize, buf: &[u8], flags: u32, addr: &SockAddrIn) -> isize {
+    syscall6(
+        SYSCALL_SENDTO,
+        [
+            fd,
+            buf.as_ptr() as usize,
+            buf.len(),
+            flags as usize,
+            addr as *const SockAddrIn as usize,
+            core::mem::size_of::<SockAddrIn>(),
+        ],
+    )
+}
+
+pub fn sys_recvfrom(fd: usize, buf: &mut [u8], flags: u32, addr: &mut SockAddrIn, addrlen: &mut u32) -> isize {
+    syscall6(
+        SYSCALL_RECVFROM,
+        [
+            fd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            flags as usize,
+            addr as *mut SockAddrIn as usize,
+            addrlen as *mut u32 as usize,
+        ],
+    )
+}